@@ -1,22 +1,18 @@
 """
 Minimal Python control envelope creation.
 """
-from dataclasses import dataclass, asdict
 import json
-import time
-import os
 
+from alnp import build_control_envelope
 
-@dataclass
-class ControlHeader:
-    seq: int
-    nonce: bytes
-    timestamp_ms: int
+session_id = "00000000-0000-0000-0000-000000000000"  # from the completed handshake
 
+envelope = build_control_envelope(
+    session_id=session_id,
+    seq=1,
+    op="get_status",
+    payload={},
+    mac=b"",  # attach the ChaCha20-Poly1305 tag over the canonical payload here
+)
 
-header = ControlHeader(seq=1, nonce=b"\x01\x02\x03", timestamp_ms=int(time.time() * 1000))
-payload = {"type": "GetStatus"}
-
-envelope = {"header": asdict(header), "payload": payload, "signature": ""}
-
-print(json.dumps(envelope, indent=2))
+print(json.dumps({**envelope.to_map(), "mac": envelope.mac.hex()}, indent=2))
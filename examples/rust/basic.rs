@@ -1,42 +1,34 @@
-use alnp::{
-    control::ControlClient,
-    handshake::transport::{JsonUdpTransport, ReliableControlChannel, TimeoutTransport},
-    messages::{ControlPayload, SetMode},
-    session::{example_controller_session, LoopbackTransport},
-};
+use std::net::SocketAddr;
+
+use alpine::crypto::identity::NodeCredentials;
+use alpine::messages::{CapabilitySet, ChannelFormat, DeviceIdentity};
+use alpine::profile::StreamProfile;
+use alpine_protocol_sdk::AlpineClient;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let identity = alnp::messages::DeviceIdentity {
-        cid: uuid::Uuid::new_v4(),
-        manufacturer: "Demo".into(),
-        model: "RustClient".into(),
+    let identity = DeviceIdentity {
+        device_id: uuid::Uuid::new_v4().to_string(),
+        manufacturer_id: "Demo".into(),
+        model_id: "RustClient".into(),
+        hardware_rev: "rev1".into(),
         firmware_rev: "1.0.11".into(),
     };
+    let credentials = NodeCredentials::load("path/to/credentials")?;
 
-    // Replace LoopbackTransport with JsonUdpTransport::bind for real network.
     // Refer to docs/implementation_audit.md for the UDP handshake/control/streaming architecture when wiring this example into production.
-    let mut transport = LoopbackTransport::new();
-    let session = example_controller_session(identity.clone(), &mut transport).await?;
-
-    let signing = ed25519_dalek::SigningKey::from_bytes(&[1u8; 32]);
-    let control = ControlClient::new(
-        identity,
-        alnp::control::ControlCrypto::new(signing, None),
-    );
-
-    let udp = JsonUdpTransport::bind("0.0.0.0:9001".parse()?, "127.0.0.1:9000".parse()?, 1200).await?;
-    let mut reliable = ReliableControlChannel::new(TimeoutTransport::new(udp, std::time::Duration::from_millis(500)));
+    let local: SocketAddr = "0.0.0.0:0".parse()?;
+    let device: SocketAddr = "127.0.0.1:5555".parse()?;
+    let mut client =
+        AlpineClient::connect(local, device, identity, CapabilitySet::default(), credentials)
+            .await?;
 
-    let payload = ControlPayload::SetMode(SetMode {
-        mode: alnp::messages::OperatingMode::Normal,
-    });
-    let ack = control.send(&mut reliable, payload).await?;
+    // Control requests are a `ControlOp` plus a JSON payload; the helpers build both.
+    let ack = client.set_mode("normal").await?;
     println!("ACK: ok={} detail={:?}", ack.ok, ack.detail);
 
-    // Use session + streaming adapter to send universes once Ready/Streaming.
-    let sacn = alnp::stream::CSacnAdapter { source: 0, receiver: 0 };
-    let stream = alnp::stream::AlnpStream::new(session, sacn);
-    let _ = stream.send(1, &[0, 1, 2, 3]);
+    client.start_stream(StreamProfile::auto()).await?;
+    client.send_frame(ChannelFormat::U8, vec![0, 1, 2, 3], 100, None, None)?;
+    client.close().await;
     Ok(())
 }
@@ -1,25 +1,13 @@
-import {
-  ControlEnvelope,
-  ControlHeader,
-  ControlPayload,
-  SetMode,
-} from "../../protocol/ts/src";
+import { ControlOp, buildControlEnvelope } from "../../protocol/ts/src";
 
-const header: ControlHeader = {
-  seq: 1,
-  nonce: new Uint8Array([1, 2, 3]),
-  timestamp_ms: Date.now(),
-};
+const sessionId = "00000000-0000-0000-0000-000000000000"; // from the completed handshake
 
-const payload: ControlPayload = {
-  type: "SetMode",
-  body: { mode: "Normal" },
-};
-
-const envelope: ControlEnvelope = {
-  header,
-  payload,
-  signature: new Uint8Array([]), // attach Ed25519 signature here
-};
+const envelope = buildControlEnvelope(
+  sessionId,
+  1,
+  ControlOp.SetMode,
+  { mode: "normal" },
+  new Uint8Array([]), // attach the ChaCha20-Poly1305 tag over the canonical payload here
+);
 
 console.log("Example envelope", envelope);
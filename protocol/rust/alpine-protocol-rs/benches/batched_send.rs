@@ -0,0 +1,111 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+use alpine::stream::FrameTransport;
+use alpine::transport::{TransportConfig, UdpTransport, UringTransport};
+
+#[path = "common/config.rs"]
+mod config;
+
+use config::UDP_BUFFER_SIZE;
+
+/// Frames per tick, as for a controller sending this many universes.
+const TICK_SIZES: [usize; 2] = [64, 256];
+/// About the size of a sealed 512-channel `u8` frame.
+const FRAME_LEN: usize = 600;
+
+fn frames(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|index| vec![index as u8; FRAME_LEN])
+        .collect()
+}
+
+/// One `sendmmsg` call for the whole tick, the batching the io_uring path competes with.
+#[cfg(target_os = "linux")]
+fn send_mmsg(socket: &UdpSocket, frames: &[Vec<u8>]) -> usize {
+    use std::os::fd::AsRawFd;
+
+    let mut iovecs: Vec<libc::iovec> = frames
+        .iter()
+        .map(|frame| libc::iovec {
+            iov_base: frame.as_ptr() as *mut libc::c_void,
+            iov_len: frame.len(),
+        })
+        .collect();
+    let mut messages: Vec<libc::mmsghdr> = iovecs
+        .iter_mut()
+        .map(|iovec| {
+            // SAFETY: an all-zero mmsghdr is valid; the socket is connected, so no name.
+            let mut message: libc::mmsghdr = unsafe { std::mem::zeroed() };
+            message.msg_hdr.msg_iov = iovec;
+            message.msg_hdr.msg_iovlen = 1;
+            message
+        })
+        .collect();
+    let mut sent = 0;
+    while sent < messages.len() {
+        // SAFETY: every header points at an iovec and frame that outlive the call.
+        let result = unsafe {
+            libc::sendmmsg(
+                socket.as_raw_fd(),
+                messages[sent..].as_mut_ptr(),
+                (messages.len() - sent) as libc::c_uint,
+                0,
+            )
+        };
+        if result <= 0 {
+            break;
+        }
+        sent += result as usize;
+    }
+    sent
+}
+
+fn bench_batched_send(c: &mut Criterion) {
+    let rt = Runtime::new().expect("tokio runtime");
+    let _runtime = rt.enter();
+    // Frames land here and are never read; the kernel drops what does not fit.
+    let sink = UdpSocket::bind("127.0.0.1:0").expect("bind sink");
+    let sink_addr = sink.local_addr().unwrap();
+    let local: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let config = TransportConfig::default().with_buffer_sizes(1 << 20, UDP_BUFFER_SIZE);
+    let udp = UdpTransport::bind(local, sink_addr, &config).expect("bind transport");
+    let uring = UringTransport::new(UdpTransport::bind(local, sink_addr, &config).unwrap());
+    let mmsg = config.bind_udp(local).expect("bind sendmmsg socket");
+    mmsg.connect(sink_addr).unwrap();
+
+    let mut group = c.benchmark_group("batched_send");
+    for &count in TICK_SIZES.iter() {
+        let tick = frames(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("send_to", count), &tick, |b, tick| {
+            b.iter(|| {
+                tick.iter()
+                    .filter(|frame| udp.send_frame(frame).is_ok())
+                    .count()
+            })
+        });
+        #[cfg(target_os = "linux")]
+        group.bench_with_input(BenchmarkId::new("sendmmsg", count), &tick, |b, tick| {
+            b.iter(|| send_mmsg(&mmsg, tick))
+        });
+        // Without the feature, or without kernel support, this would only measure the
+        // fallback, which `send_to` already covers.
+        if uring.is_uring() {
+            group.bench_with_input(BenchmarkId::new("io_uring", count), &tick, |b, tick| {
+                b.iter(|| {
+                    for frame in tick {
+                        uring.send_frame(frame).expect("queue frame");
+                    }
+                    uring.flush().expect("flush")
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_batched_send);
+criterion_main!(benches);
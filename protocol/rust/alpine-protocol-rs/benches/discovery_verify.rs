@@ -0,0 +1,66 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ed25519_dalek::{Signature, SigningKey, Verifier, VerifyingKey};
+
+use alpine::discovery::{verify_replies, DiscoveryResponder};
+use alpine::messages::{CapabilitySet, DeviceIdentity, DiscoveryReply};
+
+/// Rig sizes a console discovers at load-in.
+const REPLY_COUNTS: [usize; 2] = [30, 300];
+const CLIENT_NONCE: [u8; 32] = [0x5a; 32];
+
+fn replies(count: usize) -> Vec<(DiscoveryReply, VerifyingKey)> {
+    (0..count)
+        .map(|index| {
+            let mut seed = [0u8; 32];
+            seed[..8].copy_from_slice(&(index as u64).to_le_bytes());
+            let responder = DiscoveryResponder {
+                identity: DeviceIdentity {
+                    device_id: format!("node-{index}"),
+                    manufacturer_id: "bench-manu".into(),
+                    model_id: "dimmer".into(),
+                    hardware_rev: "rev1".into(),
+                    firmware_rev: "1.0.0".into(),
+                },
+                mac_address: "AA:BB:CC:DD:EE:FF".into(),
+                capabilities: CapabilitySet::default(),
+                signer: SigningKey::from_bytes(&seed),
+            };
+            let key = responder.signer.verifying_key();
+            (responder.reply(seed.to_vec(), &CLIENT_NONCE), key)
+        })
+        .collect()
+}
+
+/// What verifying every reply as it arrives costs.
+fn verify_serially(replies: &[(&DiscoveryReply, &VerifyingKey)]) -> usize {
+    replies
+        .iter()
+        .filter(|(reply, key)| {
+            let mut data = reply.server_nonce.clone();
+            data.extend_from_slice(&CLIENT_NONCE);
+            Signature::from_slice(&reply.signature)
+                .is_ok_and(|signature| key.verify(&data, &signature).is_ok())
+        })
+        .count()
+}
+
+fn bench_discovery_verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("discovery_verify");
+    for &count in REPLY_COUNTS.iter() {
+        let owned = replies(count);
+        let pairs: Vec<_> = owned.iter().map(|(reply, key)| (reply, key)).collect();
+        group.bench_with_input(BenchmarkId::new("serial", count), &pairs, |b, pairs| {
+            b.iter(|| assert_eq!(verify_serially(black_box(pairs)), count))
+        });
+        group.bench_with_input(BenchmarkId::new("batch", count), &pairs, |b, pairs| {
+            b.iter(|| {
+                let results = verify_replies(black_box(pairs), &CLIENT_NONCE);
+                assert!(results.iter().all(|result| result.is_ok()));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_discovery_verify);
+criterion_main!(benches);
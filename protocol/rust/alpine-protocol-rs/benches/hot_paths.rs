@@ -0,0 +1,234 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use serde_json::json;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+use alpine::codec;
+use alpine::control::{ControlClient, ControlCrypto, ControlResponder};
+use alpine::crypto::ratchet::StreamRatchet;
+use alpine::crypto::{SessionKeys, SuiteId, X25519KeyExchange};
+use alpine::handshake::HandshakeContext;
+use alpine::messages::{
+    CapabilitySet, ChannelBuffer, ChannelFormat, Compression, ControlOp, DeviceIdentity,
+    FrameEnvelope, MessageType, SealedFrame,
+};
+use alpine::profile::StreamIntent;
+use alpine::session::{AlnpSession, Ed25519Authenticator};
+use alpine::stream::{decide_next_state, AdaptationState, NetworkConditions, RecoveryReason};
+use alpine::testing::{SimulatedDevice, SimulatorConfig};
+
+#[path = "common/config.rs"]
+mod config;
+
+use config::{CHANNEL_COUNTS, FRAME_PRIORITY};
+
+const SESSION: Uuid = Uuid::from_u128(0x5e55_1011);
+
+/// Wire encodings compared side by side, so compact-encoding work shows up as a change
+/// in one row rather than a shift in every number.
+const ENCODINGS: [&str; 4] = ["u16", "u8", "u8_packed", "rle"];
+
+fn keys() -> SessionKeys {
+    SessionKeys {
+        shared_secret: vec![0x11; 32],
+        control_key: [0x22; 32],
+        stream_key: [0x33; 32],
+        suite: SuiteId::default(),
+        channel_binding: [0x44; 32],
+    }
+}
+
+fn identity(name: &str) -> DeviceIdentity {
+    DeviceIdentity {
+        device_id: format!("{name}-1"),
+        manufacturer_id: "bench-manu".into(),
+        model_id: "model".into(),
+        hardware_rev: "rev1".into(),
+        firmware_rev: "1.0.0".into(),
+    }
+}
+
+/// A look with the runs of equal levels real rigs have: fixtures parked at zero, washes
+/// at full, a few moving heads in between.
+fn look(count: usize) -> ChannelBuffer {
+    (0..count)
+        .map(|index| match (index / 16) % 4 {
+            0 => 0,
+            1 => 255,
+            2 => (index % 256) as u16,
+            _ => 128,
+        })
+        .collect()
+}
+
+fn frame(count: usize) -> FrameEnvelope {
+    FrameEnvelope {
+        message_type: MessageType::AlpineFrame,
+        session_id: SESSION,
+        universe: 1,
+        timestamp_us: 1_700_000_000_000_000,
+        priority: FRAME_PRIORITY,
+        channel_format: ChannelFormat::U8,
+        channels: look(count),
+        packed_channels: None,
+        float_channels: None,
+        compression: None,
+        compressed_channels: None,
+        groups: None,
+        group_refs: None,
+        config_tag: None,
+        deadline_us: None,
+        metadata: None,
+        extensions: None,
+    }
+}
+
+/// Prepares `base` for the wire in `encoding` and encodes it, as the stream does per send.
+fn encode(base: &FrameEnvelope, encoding: &str) -> Vec<u8> {
+    let mut frame = base.clone();
+    match encoding {
+        "u16" => frame.convert(ChannelFormat::U16),
+        "u8_packed" => {
+            frame.channel_format = ChannelFormat::U8Packed;
+            frame.pack();
+        }
+        "rle" => {
+            frame.compress(Compression::Rle);
+        }
+        _ => {}
+    }
+    codec::to_vec(&frame).expect("encode frame")
+}
+
+fn bench_frame_encoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_encoding");
+    for &channels in CHANNEL_COUNTS.iter() {
+        let base = frame(channels);
+        group.throughput(Throughput::Elements(channels as u64));
+        for encoding in ENCODINGS {
+            group.bench_with_input(BenchmarkId::new(encoding, channels), &base, |b, base| {
+                b.iter(|| encode(black_box(base), encoding))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_frame_seal(c: &mut Criterion) {
+    let ratchet = StreamRatchet::sender(&keys()).expect("ratchet");
+    let mut group = c.benchmark_group("frame_seal");
+    for &channels in CHANNEL_COUNTS.iter() {
+        let base = frame(channels);
+        group.bench_with_input(BenchmarkId::new("seal", channels), &base, |b, base| {
+            let mut seq = 0u64;
+            b.iter(|| {
+                seq += 1;
+                let bytes = encode(black_box(base), "u8");
+                SealedFrame::seal(&ratchet, SESSION, seq, &bytes).expect("seal frame")
+            })
+        });
+        let bytes = encode(&base, "u8");
+        let sealed = SealedFrame::seal(&ratchet, SESSION, 1, &bytes).expect("seal frame");
+        group.bench_with_input(BenchmarkId::new("open", channels), &sealed, |b, sealed| {
+            let mut receiver = StreamRatchet::receiver(&keys()).expect("ratchet");
+            b.iter(|| black_box(sealed).open(&mut receiver).expect("open frame"))
+        });
+    }
+    group.finish();
+}
+
+fn bench_control_mac(c: &mut Criterion) {
+    let client = ControlClient::new(Uuid::nil(), SESSION, ControlCrypto::new(keys()));
+    let responder = ControlResponder::new(SESSION, ControlCrypto::new(keys()));
+    let payload = json!({ "mode": "show", "universe": 1 });
+    let envelope = client
+        .envelope(1, ControlOp::SetMode, payload.clone())
+        .expect("envelope");
+
+    let mut group = c.benchmark_group("control_mac");
+    group.bench_function("sign", |b| {
+        b.iter(|| {
+            client
+                .envelope(1, ControlOp::SetMode, black_box(payload.clone()))
+                .expect("envelope")
+        })
+    });
+    group.bench_function("verify", |b| {
+        b.iter(|| responder.verify(black_box(&envelope)).expect("verify"))
+    });
+    group.finish();
+}
+
+/// Conditions after a second of 40 Hz frames with every tenth one lost.
+fn lossy_conditions() -> NetworkConditions {
+    let mut conditions = NetworkConditions::new();
+    for seq in (0..40u64).filter(|seq| seq % 10 != 9) {
+        let arrival_us = seq * 25_000 + (seq % 3) * 1_500;
+        conditions.record_frame(seq, arrival_us, seq * 25_000 + 20_000);
+    }
+    conditions
+}
+
+fn bench_adaptation_step(c: &mut Criterion) {
+    let intent = StreamIntent::Auto;
+    let baseline = AdaptationState::baseline(intent);
+    let clean = NetworkConditions::new();
+    let lossy = lossy_conditions();
+
+    let mut group = c.benchmark_group("adaptation_step");
+    group.bench_function("clean", |b| {
+        b.iter(|| decide_next_state(black_box(&baseline), &clean, None, false, intent))
+    });
+    group.bench_function("recovering", |b| {
+        b.iter(|| {
+            decide_next_state(
+                black_box(&baseline),
+                &lossy,
+                Some(RecoveryReason::SustainedLoss),
+                true,
+                intent,
+            )
+        })
+    });
+    group.finish();
+}
+
+fn bench_handshake(c: &mut Criterion) {
+    let rt = Runtime::new().expect("tokio runtime");
+    let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+
+    let mut group = c.benchmark_group("handshake");
+    group.bench_function("simulated_device", |b| {
+        b.iter_batched(
+            || {
+                let _guard = rt.enter();
+                device.connect()
+            },
+            |mut link| {
+                rt.block_on(AlnpSession::connect(
+                    identity("controller"),
+                    CapabilitySet::default(),
+                    Ed25519Authenticator::new(device.credentials().clone()),
+                    X25519KeyExchange::new(),
+                    HandshakeContext::default(),
+                    &mut link,
+                ))
+                .expect("handshake")
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_frame_encoding,
+    bench_frame_seal,
+    bench_control_mac,
+    bench_adaptation_step,
+    bench_handshake
+);
+criterion_main!(benches);
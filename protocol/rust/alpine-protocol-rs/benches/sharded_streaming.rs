@@ -0,0 +1,119 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+use alpine::e2e_common::run_udp_handshake;
+use alpine::messages::ChannelFormat;
+use alpine::profile::StreamProfile;
+use alpine::session::AlnpSession;
+use alpine::stream::{AlnpStream, FrameOutcome, ShardConfig, ShardPool};
+use alpine::transport::{TransportConfig, UdpTransport};
+
+#[path = "common/config.rs"]
+mod config;
+
+use config::FRAME_PRIORITY;
+
+/// Sessions the controller streams to, each carrying an equal share of the universes.
+const SESSIONS: usize = 8;
+const UNIVERSES: usize = 256;
+const CHANNELS: usize = 512;
+const SHARD_COUNTS: [usize; 3] = [1, 2, 4];
+
+/// Sends every universe of one session once, with levels that change each tick so no
+/// frame is skipped as unchanged.
+fn send_tick(stream: &AlnpStream<UdpTransport>, first: usize, tick: &mut u16) -> u64 {
+    *tick = tick.wrapping_add(1);
+    let mut sent = 0;
+    for universe in first..first + UNIVERSES / SESSIONS {
+        let levels = [*tick % 256; CHANNELS];
+        let outcome = stream
+            .send_universe_outcome(
+                universe as u16,
+                ChannelFormat::U8,
+                &levels,
+                FRAME_PRIORITY,
+                None,
+                None,
+            )
+            .expect("stream send failed");
+        sent += u64::from(outcome == FrameOutcome::Sent);
+    }
+    sent
+}
+
+fn bench_sharded_streaming(c: &mut Criterion) {
+    let rt = Runtime::new().expect("tokio runtime");
+    let sessions: Vec<AlnpSession> = (0..SESSIONS)
+        .map(|_| {
+            rt.block_on(run_udp_handshake())
+                .expect("handshake failed")
+                .0
+        })
+        .collect();
+    // Frames land here and are never read; the kernel drops what does not fit.
+    let sink = UdpSocket::bind("127.0.0.1:0").expect("bind sink");
+    let sink_addr = sink.local_addr().unwrap();
+    let profile = StreamProfile::auto().compile().expect("profile");
+
+    let mut group = c.benchmark_group("sharded_streaming");
+    group.throughput(Throughput::Elements(UNIVERSES as u64));
+    group.sample_size(20);
+    for shards in SHARD_COUNTS {
+        let pool = {
+            let _runtime = rt.enter();
+            ShardPool::start(ShardConfig {
+                shards,
+                bind: SocketAddr::from(([127, 0, 0, 1], 0)),
+                transport: TransportConfig::default(),
+                ..ShardConfig::default()
+            })
+            .expect("start shards")
+        };
+        let streams: Vec<_> = sessions
+            .iter()
+            .enumerate()
+            .map(|(index, session)| {
+                let key = format!("node-{index}");
+                let transport = pool.transport(&key, sink_addr);
+                let stream = AlnpStream::new(session.clone(), transport, profile.clone());
+                (key, Arc::new(stream))
+            })
+            .collect();
+        let frames_sent =
+            |pool: &ShardPool| -> u64 { pool.stats().iter().map(|shard| shard.frames_sent).sum() };
+
+        group.bench_function(BenchmarkId::new("shards", shards), |b| {
+            b.iter_custom(|iters| {
+                let target = frames_sent(&pool) + iters * UNIVERSES as u64;
+                let started = Instant::now();
+                for (index, (key, stream)) in streams.iter().enumerate() {
+                    let stream = stream.clone();
+                    let first = index * UNIVERSES / SESSIONS;
+                    let mut tick = 0u16;
+                    pool.spawn(key, Duration::ZERO, move || {
+                        Ok(send_tick(&stream, first, &mut tick))
+                    });
+                }
+                while frames_sent(&pool) < target {
+                    std::thread::yield_now();
+                }
+                let elapsed = started.elapsed();
+                for (key, _) in &streams {
+                    pool.cancel(key);
+                }
+                while pool.stats().iter().any(|shard| shard.jobs > 0) {
+                    std::thread::yield_now();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_sharded_streaming);
+criterion_main!(benches);
@@ -0,0 +1,140 @@
+//! Writes valid encodings of every fuzzed message into `corpus/<target>/` as seeds.
+//!
+//! Run with `cargo run --bin build_corpus` from this directory before the first fuzz run.
+
+use std::fs;
+use std::path::Path;
+
+use alpine::codec;
+use alpine::handshake::HandshakeMessage;
+use alpine::messages::{
+    Acknowledge, CapabilitySet, ChannelFormat, ControlEnvelope, ControlOp, DeviceIdentity,
+    DiscoveryReply, ErrorCode, ErrorEnvelope, Extensible, ExtensionValue, FrameEnvelope, Keepalive,
+    MessageType, SessionComplete, SessionInit,
+};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::json;
+use uuid::Uuid;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let session_id = Uuid::from_bytes([0x42; 16]);
+    let identity = DeviceIdentity {
+        device_id: "node-0001".into(),
+        manufacturer_id: "corpus-manu".into(),
+        model_id: "corpus-model".into(),
+        hardware_rev: "rev1".into(),
+        firmware_rev: "1.0.0".into(),
+    };
+
+    let control = ControlEnvelope {
+        message_type: MessageType::AlpineControl,
+        session_id,
+        seq: 1,
+        op: ControlOp::SetMode,
+        payload: codec::to_value(&json!({
+            "mode": "show",
+            "levels": [1, 2.5, -3],
+            "nested": {"a": null},
+        }))?,
+        mac: vec![0xAA; 16],
+    };
+    let mut frame = FrameEnvelope {
+        message_type: MessageType::AlpineFrame,
+        session_id,
+        universe: 2,
+        timestamp_us: 1_000_000,
+        priority: 100,
+        channel_format: ChannelFormat::U16,
+        channels: vec![0, 128, 65_535].into(),
+        packed_channels: None,
+        float_channels: None,
+        compression: None,
+        compressed_channels: None,
+        groups: Some([("front".to_string(), vec![0, 1])].into()),
+        group_refs: None,
+        config_tag: None,
+        deadline_us: None,
+        metadata: Some([("keyframe".to_string(), json!(true))].into()),
+        extensions: None,
+    };
+    let plain_frame = frame.clone();
+    frame.set_extension("com.example", ExtensionValue::Bytes(vec![1, 2, 3]));
+
+    let signer = SigningKey::from_bytes(&[0x07; 32]);
+    let server_nonce = vec![0x01; 32];
+    let mut reply = DiscoveryReply::new(
+        &identity,
+        "AA:BB:CC:DD:EE:FF".into(),
+        server_nonce.clone(),
+        CapabilitySet::default(),
+        signer.sign(&server_nonce).to_vec(),
+    );
+    let plain_reply = reply.clone();
+    reply.set_extension("com.example", ExtensionValue::Text("fixture".into()));
+
+    let handshake = [
+        HandshakeMessage::SessionInit(SessionInit {
+            message_type: MessageType::SessionInit,
+            controller_nonce: vec![0x01; 32],
+            controller_pubkey: vec![0x02; 32],
+            requested: CapabilitySet::default(),
+            session_id,
+            suites: Vec::new(),
+            controller_firmware_rev: None,
+        }),
+        HandshakeMessage::SessionComplete(SessionComplete {
+            message_type: MessageType::SessionComplete,
+            session_id,
+            ok: false,
+            error: Some(ErrorCode::SessionMacMismatch),
+        }),
+        HandshakeMessage::Keepalive(Keepalive {
+            message_type: MessageType::Keepalive,
+            session_id,
+            tick_ms: 5_000,
+        }),
+        HandshakeMessage::Control(control.clone()),
+        HandshakeMessage::Ack(Acknowledge {
+            message_type: MessageType::AlpineControlAck,
+            session_id,
+            seq: 1,
+            ok: true,
+            detail: Some("done".into()),
+            mac: vec![0xBB; 16],
+        }),
+        HandshakeMessage::Error(ErrorEnvelope::for_control(
+            session_id,
+            1,
+            ErrorCode::ControlUnknownOp,
+            None,
+        )),
+    ];
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+    let write = |target: &str, seeds: Vec<Vec<u8>>| -> std::io::Result<()> {
+        let dir = root.join(target);
+        fs::create_dir_all(&dir)?;
+        for (i, seed) in seeds.iter().enumerate() {
+            fs::write(dir.join(format!("seed-{i}")), seed)?;
+        }
+        Ok(())
+    };
+    write(
+        "handshake_message",
+        handshake
+            .iter()
+            .map(codec::to_vec)
+            .collect::<Result<_, _>>()?,
+    )?;
+    write(
+        "frame_envelope",
+        vec![codec::to_vec(&plain_frame)?, codec::to_vec(&frame)?],
+    )?;
+    write("control_envelope", vec![codec::to_vec(&control)?])?;
+    write(
+        "discovery_reply",
+        vec![codec::to_vec(&plain_reply)?, codec::to_vec(&reply)?],
+    )?;
+    println!("seed corpus written to {}", root.display());
+    Ok(())
+}
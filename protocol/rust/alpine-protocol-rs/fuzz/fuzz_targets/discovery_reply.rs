@@ -0,0 +1,15 @@
+#![no_main]
+
+use alpine::codec;
+use alpine::messages::DiscoveryReply;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Anything accepted from the wire must survive a re-encode unchanged.
+    if let Ok(msg) = codec::decode_untrusted::<DiscoveryReply>(data) {
+        let bytes = codec::to_vec(&msg).expect("decoded message re-encodes");
+        let again: DiscoveryReply =
+            codec::decode_untrusted(&bytes).expect("re-encoded message decodes");
+        assert_eq!(again, msg);
+    }
+});
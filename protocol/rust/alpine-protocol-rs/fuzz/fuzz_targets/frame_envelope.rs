@@ -0,0 +1,15 @@
+#![no_main]
+
+use alpine::codec;
+use alpine::messages::FrameEnvelope;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Anything accepted from the wire must survive a re-encode unchanged.
+    if let Ok(msg) = codec::decode_untrusted::<FrameEnvelope>(data) {
+        let bytes = codec::to_vec(&msg).expect("decoded message re-encodes");
+        let again: FrameEnvelope =
+            codec::decode_untrusted(&bytes).expect("re-encoded message decodes");
+        assert_eq!(again, msg);
+    }
+});
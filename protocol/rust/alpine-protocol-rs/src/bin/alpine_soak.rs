@@ -0,0 +1,424 @@
+//! Long-running soak test for the session, control, and streaming paths.
+//!
+//! ```bash
+//! cargo run --release --features soak --bin alpine-soak -- --sessions 5000 --frames 5000000
+//! ```
+//!
+//! The run churns through many handshakes against an in-process node, drives the control
+//! sequence across its wrap point, then streams frames over an impaired loopback. It
+//! exits non-zero if resident memory keeps growing, keyframe cadence stalls, or frame
+//! latency percentiles drift between the first and last measurement windows.
+
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use uuid::Uuid;
+
+use alpine::codec;
+use alpine::control::{ControlClient, ControlCrypto};
+use alpine::crypto::X25519KeyExchange;
+use alpine::handshake::transport::ReliableControlChannel;
+use alpine::handshake::{HandshakeContext, HandshakeError};
+use alpine::messages::{CapabilitySet, ChannelFormat, ControlOp, DeviceIdentity, FrameEnvelope};
+use alpine::profile::StreamProfile;
+use alpine::session::{AlnpSession, Ed25519Authenticator};
+use alpine::stream::{AlnpStream, FrameTransport};
+use alpine::testing::{SimulatedDevice, SimulatedLink, SimulatorConfig};
+use alpine::UniverseId;
+
+/// Resident memory may grow by this much after the first window before it counts as a leak.
+const RSS_GROWTH_ALLOWANCE_KB: u64 = 16 * 1024;
+/// Last-window p99 latency may be at most this multiple of the first window's.
+const P99_DRIFT_FACTOR: u64 = 3;
+/// Absolute slack for the p99 comparison, so sub-microsecond noise is not flagged.
+const P99_SLACK_NS: u64 = 50_000;
+
+struct Options {
+    sessions: u32,
+    frames: u64,
+    universes: u16,
+    channels: usize,
+    loss: f64,
+    seed: u64,
+    windows: u64,
+}
+
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut opts = Self {
+            sessions: 2_000,
+            frames: 2_000_000,
+            universes: 8,
+            channels: 512,
+            loss: 0.02,
+            seed: 1,
+            windows: 20,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", flag))?;
+            let bad = || format!("invalid value for {}: {}", flag, value);
+            match flag.as_str() {
+                "--sessions" => opts.sessions = value.parse().map_err(|_| bad())?,
+                "--frames" => opts.frames = value.parse().map_err(|_| bad())?,
+                "--universes" => opts.universes = value.parse().map_err(|_| bad())?,
+                "--channels" => opts.channels = value.parse().map_err(|_| bad())?,
+                "--loss" => opts.loss = value.parse().map_err(|_| bad())?,
+                "--seed" => opts.seed = value.parse().map_err(|_| bad())?,
+                "--windows" => opts.windows = value.parse().map_err(|_| bad())?,
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        if opts.universes == 0 || opts.windows < 2 || opts.frames < opts.windows {
+            return Err("need at least one universe, two windows, and a frame per window".into());
+        }
+        if !(0.0..1.0).contains(&opts.loss) {
+            return Err("--loss must be in [0, 1)".into());
+        }
+        Ok(opts)
+    }
+}
+
+#[derive(Default)]
+struct Violations(Vec<String>);
+
+impl Violations {
+    fn check(&mut self, ok: bool, what: impl FnOnce() -> String) {
+        if !ok {
+            let what = what();
+            eprintln!("VIOLATION: {}", what);
+            self.0.push(what);
+        }
+    }
+}
+
+fn identity(name: &str) -> DeviceIdentity {
+    DeviceIdentity {
+        device_id: format!("{name}-soak"),
+        manufacturer_id: "alpine".into(),
+        model_id: "soak".into(),
+        hardware_rev: "rev1".into(),
+        firmware_rev: env!("CARGO_PKG_VERSION").into(),
+    }
+}
+
+async fn connect(device: &SimulatedDevice) -> Result<(AlnpSession, SimulatedLink), HandshakeError> {
+    let mut link = device.connect();
+    let session = AlnpSession::connect(
+        identity("controller"),
+        CapabilitySet::default(),
+        Ed25519Authenticator::new(device.credentials().clone()),
+        X25519KeyExchange::new(),
+        HandshakeContext::default(),
+        &mut link,
+    )
+    .await?;
+    Ok((session, link))
+}
+
+fn control_client(session: &AlnpSession) -> Option<ControlClient> {
+    let established = session.established()?;
+    Some(ControlClient::new(
+        Uuid::new_v4(),
+        established.session_id,
+        ControlCrypto::new(session.keys()?),
+    ))
+}
+
+/// Resident set size in KiB, where the platform exposes it.
+fn rss_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+fn check_memory(violations: &mut Violations, phase: &str, baseline: Option<u64>, now: Option<u64>) {
+    if let (Some(baseline), Some(now)) = (baseline, now) {
+        violations.check(now <= baseline + RSS_GROWTH_ALLOWANCE_KB, || {
+            format!(
+                "{}: resident memory grew from {} KiB to {} KiB",
+                phase, baseline, now
+            )
+        });
+    }
+}
+
+async fn soak_sessions(opts: &Options, violations: &mut Violations) {
+    let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+    let started = Instant::now();
+    let mut baseline = None;
+    let checkpoint = (opts.sessions / 10).max(1);
+    for i in 0..opts.sessions {
+        let outcome = async {
+            let (session, link) = connect(&device).await?;
+            let client = control_client(&session)
+                .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+            let mut channel = ReliableControlChannel::new(link);
+            client
+                .send(&mut channel, ControlOp::Identify, json!({}))
+                .await?;
+            session.close();
+            Ok::<_, HandshakeError>(())
+        }
+        .await;
+        if let Err(err) = outcome {
+            violations.check(false, || format!("session {} failed: {}", i, err));
+            return;
+        }
+        if i + 1 == checkpoint {
+            baseline = rss_kb();
+        }
+    }
+    let stats = device.stats();
+    violations.check(stats.handshakes == u64::from(opts.sessions), || {
+        format!(
+            "{} handshakes completed, expected {}",
+            stats.handshakes, opts.sessions
+        )
+    });
+    violations.check(device.identify_count() == opts.sessions, || {
+        format!(
+            "{} identify requests acted on, expected {}",
+            device.identify_count(),
+            opts.sessions
+        )
+    });
+    check_memory(violations, "sessions", baseline, rss_kb());
+    println!(
+        "sessions: {} in {:.1?}, rss {} KiB",
+        opts.sessions,
+        started.elapsed(),
+        rss_kb().map_or("n/a".into(), |kb| kb.to_string())
+    );
+}
+
+/// Drives control sequence numbers through `u64::MAX` and back to zero.
+async fn soak_sequence_wrap(violations: &mut Violations) {
+    const OPS: u64 = 16;
+    let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+    let outcome = async {
+        let (session, link) = connect(&device).await?;
+        let client = control_client(&session)
+            .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+        let mut channel = ReliableControlChannel::new(link);
+        let first = client.envelope(u64::MAX - OPS / 2, ControlOp::Identify, json!({}))?;
+        channel.send_reliable(first).await?;
+        for _ in 1..OPS {
+            client
+                .send(&mut channel, ControlOp::Identify, json!({}))
+                .await?;
+        }
+        Ok::<_, HandshakeError>(())
+    }
+    .await;
+    violations.check(outcome.is_ok(), || {
+        format!("control sequence wrap failed: {:?}", outcome)
+    });
+    violations.check(u64::from(device.identify_count()) == OPS, || {
+        format!(
+            "{} of {} control envelopes across the wrap were acted on",
+            device.identify_count(),
+            OPS
+        )
+    });
+    println!("sequence wrap: {} control envelopes across u64::MAX", OPS);
+}
+
+/// What the impaired loopback saw for the last frame sent.
+#[derive(Default)]
+struct Delivery {
+    delivered: bool,
+    universe: UniverseId,
+    keyframe: bool,
+    rejected: Option<String>,
+}
+
+/// Frame transport that loses frames at random and decodes the rest like a receiver.
+struct ImpairedLoopback {
+    loss: f64,
+    rng: Mutex<StdRng>,
+    last: Arc<Mutex<Delivery>>,
+}
+
+impl FrameTransport for ImpairedLoopback {
+    fn send_frame(&self, bytes: &[u8]) -> Result<(), String> {
+        let mut last = self.last.lock();
+        *last = Delivery::default();
+        if self.rng.lock().gen_bool(self.loss) {
+            return Ok(());
+        }
+        match codec::decode_untrusted::<FrameEnvelope>(bytes) {
+            Ok(frame) => {
+                last.delivered = true;
+                last.universe = frame.universe;
+                last.keyframe = frame
+                    .adaptation_info()
+                    .is_some_and(|adaptation| adaptation.force_keyframe);
+            }
+            Err(err) => last.rejected = Some(err.to_string()),
+        }
+        Ok(())
+    }
+}
+
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[(sorted.len() - 1) * pct / 100]
+}
+
+async fn soak_frames(opts: &Options, violations: &mut Violations) {
+    let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+    let session = match connect(&device).await {
+        Ok((session, _link)) => session,
+        Err(err) => {
+            violations.check(false, || format!("streaming handshake failed: {}", err));
+            return;
+        }
+    };
+    let profile = match StreamProfile::auto().compile() {
+        Ok(profile) => profile,
+        Err(err) => {
+            violations.check(false, || format!("profile: {}", err));
+            return;
+        }
+    };
+    if let Err(err) = session.set_stream_profile(profile.clone()) {
+        violations.check(false, || format!("profile: {}", err));
+        return;
+    }
+    session.mark_streaming();
+
+    let last = Arc::new(Mutex::new(Delivery::default()));
+    let transport = ImpairedLoopback {
+        loss: opts.loss,
+        rng: Mutex::new(StdRng::seed_from_u64(opts.seed)),
+        last: last.clone(),
+    };
+    let stream = AlnpStream::new(session, transport, profile);
+    let payload: Vec<u16> = (0..opts.channels).map(|i| (i % 256) as u16).collect();
+    let window = opts.frames / opts.windows;
+    let epoch = Instant::now();
+    let mut sequences = vec![0u64; usize::from(opts.universes)];
+    let mut latencies = Vec::with_capacity(window as usize);
+    let mut keyframes = vec![false; usize::from(opts.universes)];
+    let mut first_p99 = None;
+    let mut baseline_rss = None;
+
+    for frame in 0..window * opts.windows {
+        let universe = (frame % u64::from(opts.universes)) as UniverseId;
+        let sent = Instant::now();
+        if let Err(err) =
+            stream.send_universe(universe, ChannelFormat::U8, &payload, 100, None, None)
+        {
+            violations.check(false, || format!("frame {} send failed: {}", frame, err));
+            return;
+        }
+        let delivery = std::mem::take(&mut *last.lock());
+        let slot = usize::from(universe);
+        // Every frame consumes a sequence number; lost ones show up as gaps.
+        sequences[slot] += 1;
+        if let Some(err) = delivery.rejected {
+            violations.check(false, || format!("frame {} did not decode: {}", frame, err));
+            return;
+        }
+        if delivery.delivered {
+            latencies.push(sent.elapsed().as_nanos() as u64);
+            keyframes[slot] |= delivery.keyframe;
+            let arrival_us = epoch.elapsed().as_micros() as u64;
+            stream.record_universe_arrival(
+                delivery.universe,
+                sequences[slot],
+                arrival_us,
+                arrival_us + 1_000,
+            );
+        }
+
+        if (frame + 1) % window == 0 {
+            let index = (frame + 1) / window;
+            latencies.sort_unstable();
+            let (p50, p99) = (percentile(&latencies, 50), percentile(&latencies, 99));
+            let rss = rss_kb();
+            let health = stream.health();
+            println!(
+                "window {:>3}: p50 {:>7} ns  p99 {:>8} ns  worst loss {:.3}  degraded {}  rss {} KiB",
+                index,
+                p50,
+                p99,
+                health.worst_loss_ratio,
+                health.degraded_safe,
+                rss.map_or("n/a".into(), |kb| kb.to_string())
+            );
+            violations.check(
+                health.universes.len() == usize::from(opts.universes),
+                || {
+                    format!(
+                        "stream tracks {} universes, expected {}",
+                        health.universes.len(),
+                        opts.universes
+                    )
+                },
+            );
+            violations.check(keyframes.iter().all(|seen| *seen), || {
+                format!("window {}: a universe emitted no keyframe", index)
+            });
+            match first_p99 {
+                None => {
+                    first_p99 = Some(p99);
+                    baseline_rss = rss;
+                }
+                Some(first) if index == opts.windows => {
+                    violations.check(p99 <= first * P99_DRIFT_FACTOR + P99_SLACK_NS, || {
+                        format!("p99 latency drifted from {} ns to {} ns", first, p99)
+                    });
+                }
+                Some(_) => {}
+            }
+            latencies.clear();
+            keyframes.iter_mut().for_each(|seen| *seen = false);
+        }
+    }
+    check_memory(violations, "frames", baseline_rss, rss_kb());
+    println!(
+        "frames: {} across {} universes in {:.1?}",
+        window * opts.windows,
+        opts.universes,
+        epoch.elapsed()
+    );
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let opts = match Options::parse() {
+        Ok(opts) => opts,
+        Err(err) => {
+            eprintln!("alpine-soak: {}", err);
+            eprintln!(
+                "usage: alpine-soak [--sessions N] [--frames N] [--universes N] [--channels N] \
+                 [--loss P] [--seed N] [--windows N]"
+            );
+            return ExitCode::from(2);
+        }
+    };
+    let mut violations = Violations::default();
+    soak_sessions(&opts, &mut violations).await;
+    soak_sequence_wrap(&mut violations).await;
+    soak_frames(&opts, &mut violations).await;
+    if violations.0.is_empty() {
+        println!("soak passed");
+        ExitCode::SUCCESS
+    } else {
+        println!("soak failed with {} violation(s)", violations.0.len());
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,607 @@
+//! Fixed memory budget for the node receive path.
+//!
+//! A node with under a megabyte of RAM cannot let a burst of traffic decide how much it
+//! allocates. [`BudgetedReceiver`] holds everything the frame path needs in storage sized
+//! by const parameters: one receive buffer, a bounded queue of decoded frames, and the
+//! latest look per universe. Frames decode straight into that storage, so a received
+//! frame allocates nothing; malformed or refused frames only allocate their error.
+//!
+//! The budget is checked when the receiver is instantiated: a configuration whose
+//! storage exceeds [`MAX_NODE_BUFFERS`], or whose datagrams could outgrow the decoder's
+//! scratch space, fails to compile. [`EmbeddedReceiver`] is sized for RP2040/ESP32 class
+//! nodes.
+//!
+//! Only clear `u8`, `u16`, and `u8_packed` frames fit the budget. `f32` and compressed
+//! frames are refused, as are sealed streams, whose decryption allocates; a budgeted node
+//! advertises none of them. Fragmented datagrams are not reassembled.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::size_of;
+
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::codec::{self, CodecError};
+use crate::messages::{ChannelBuffer, ChannelFormat, MessageType, UniverseId, UNIVERSE_CHANNELS};
+
+/// Most bytes a [`BudgetedReceiver`] may occupy, leaving the rest of a 1 MB part to the
+/// network stack, crypto, and stacks.
+pub const MAX_NODE_BUFFERS: usize = 256 * 1024;
+
+/// Scratch space the CBOR decoder keeps on the stack. Byte and text strings up to this
+/// length decode without allocating, so no datagram within the budget may exceed it.
+pub const DECODE_SCRATCH: usize = 4096;
+
+/// Receiver for nodes with under 1 MB of RAM: 2 KB datagrams, four universes of 512
+/// channels, and four frames queued for output.
+pub type EmbeddedReceiver = BudgetedReceiver<2048, 512, 4, 4>;
+
+// Checked on every build rather than when a node first instantiates a receiver.
+const _: () = EmbeddedReceiver::WITHIN_BUDGET;
+const _: () = {
+    assert!(
+        size_of::<ChannelBuffer>() >= UNIVERSE_CHANNELS * size_of::<u16>(),
+        "a full universe no longer fits a ChannelBuffer inline"
+    );
+    assert!(
+        size_of::<FrameQueue<512, 4>>()
+            <= 4 * size_of::<Option<FixedFrame<512>>>() + 2 * size_of::<usize>() + size_of::<u64>(),
+        "frame queue storage grew beyond its slots"
+    );
+    assert!(
+        size_of::<FrameQueue<512, 4>>() <= MAX_NODE_BUFFERS / 4,
+        "the embedded frame queue takes more than a quarter of the budget"
+    );
+};
+
+/// Failure receiving a frame within the budget.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetError {
+    /// The datagram is longer than the receive buffer.
+    Oversized(usize),
+    /// The datagram is not a frame the budget can hold.
+    Decode(CodecError),
+    /// The frame belongs to another session.
+    WrongSession,
+    /// Every universe slot already holds another universe.
+    UniversesFull(UniverseId),
+}
+
+impl fmt::Display for BudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetError::Oversized(len) => {
+                write!(f, "datagram of {} bytes exceeds the receive buffer", len)
+            }
+            BudgetError::Decode(err) => write!(f, "{}", err),
+            BudgetError::WrongSession => write!(f, "frame for another session"),
+            BudgetError::UniversesFull(universe) => {
+                write!(f, "no universe slot left for universe {}", universe)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BudgetError {}
+
+/// A frame decoded into fixed storage for at most `CHANNELS` levels.
+///
+/// `u8_packed` frames keep their levels in `u16` slots like the other formats, so
+/// [`Self::levels`] reads the same whatever arrived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedFrame<const CHANNELS: usize> {
+    pub session_id: Uuid,
+    pub universe: UniverseId,
+    pub timestamp_us: u64,
+    pub priority: u8,
+    pub channel_format: ChannelFormat,
+    pub config_tag: Option<u32>,
+    pub deadline_us: Option<u64>,
+    levels: [u16; CHANNELS],
+    len: usize,
+}
+
+impl<const CHANNELS: usize> FixedFrame<CHANNELS> {
+    fn empty() -> Self {
+        Self {
+            session_id: Uuid::nil(),
+            universe: 0,
+            timestamp_us: 0,
+            priority: 0,
+            channel_format: ChannelFormat::U8,
+            config_tag: None,
+            deadline_us: None,
+            levels: [0; CHANNELS],
+            len: 0,
+        }
+    }
+
+    /// Levels carried by the frame.
+    pub fn levels(&self) -> &[u16] {
+        &self.levels[..self.len]
+    }
+
+    fn push_level<E: de::Error>(&mut self, level: u16) -> Result<(), E> {
+        let slot = self
+            .levels
+            .get_mut(self.len)
+            .ok_or_else(|| E::custom(format_args!("more than {} channels", CHANNELS)))?;
+        *slot = level;
+        self.len += 1;
+        Ok(())
+    }
+}
+
+enum Field {
+    Type,
+    SessionId,
+    Universe,
+    TimestampUs,
+    Priority,
+    ChannelFormat,
+    Channels,
+    PackedChannels,
+    FloatChannels,
+    Compression,
+    ConfigTag,
+    DeadlineUs,
+    Other,
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl Visitor<'_> for FieldVisitor {
+            type Value = Field;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a frame field name")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Field, E> {
+                Ok(match name {
+                    "type" => Field::Type,
+                    "session_id" => Field::SessionId,
+                    "universe" => Field::Universe,
+                    "timestamp_us" => Field::TimestampUs,
+                    "priority" => Field::Priority,
+                    "channel_format" => Field::ChannelFormat,
+                    "channels" => Field::Channels,
+                    "packed_channels" => Field::PackedChannels,
+                    "float_channels" => Field::FloatChannels,
+                    "compression" => Field::Compression,
+                    "compressed_channels" => Field::Compression,
+                    "config_tag" => Field::ConfigTag,
+                    "deadline_us" => Field::DeadlineUs,
+                    _ => Field::Other,
+                })
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+/// Writes a `channels` array into the frame's slots.
+struct Levels<'a, const CHANNELS: usize>(&'a mut FixedFrame<CHANNELS>);
+
+impl<'de, const CHANNELS: usize> de::DeserializeSeed<'de> for Levels<'_, CHANNELS> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, const CHANNELS: usize> Visitor<'de> for Levels<'_, CHANNELS> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at most {} channel levels", CHANNELS)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(level) = seq.next_element::<u16>()? {
+            self.0.push_level(level)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `packed_channels` bytes into the frame's slots; absent when null.
+struct PackedLevels<'a, const CHANNELS: usize>(&'a mut FixedFrame<CHANNELS>);
+
+impl<'de, const CHANNELS: usize> de::DeserializeSeed<'de> for PackedLevels<'_, CHANNELS> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, const CHANNELS: usize> Visitor<'de> for PackedLevels<'_, CHANNELS> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at most {} packed levels", CHANNELS)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<(), E> {
+        bytes
+            .iter()
+            .try_for_each(|&level| self.0.push_level(u16::from(level)))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+struct FrameVisitor<const CHANNELS: usize>(PhantomData<[u16; CHANNELS]>);
+
+impl<'de, const CHANNELS: usize> Visitor<'de> for FrameVisitor<CHANNELS> {
+    type Value = FixedFrame<CHANNELS>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an alpine_frame")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut frame = FixedFrame::empty();
+        let mut message_type = None;
+        while let Some(field) = map.next_key::<Field>()? {
+            match field {
+                Field::Type => message_type = Some(map.next_value::<MessageType>()?),
+                Field::SessionId => frame.session_id = map.next_value()?,
+                Field::Universe => frame.universe = map.next_value()?,
+                Field::TimestampUs => frame.timestamp_us = map.next_value()?,
+                Field::Priority => frame.priority = map.next_value()?,
+                Field::ChannelFormat => frame.channel_format = map.next_value()?,
+                Field::Channels => map.next_value_seed(Levels(&mut frame))?,
+                Field::PackedChannels => map.next_value_seed(PackedLevels(&mut frame))?,
+                Field::FloatChannels | Field::Compression => {
+                    if map.next_value::<Option<IgnoredAny>>()?.is_some() {
+                        return Err(de::Error::custom(
+                            "f32 and compressed frames do not fit the memory budget",
+                        ));
+                    }
+                }
+                Field::ConfigTag => frame.config_tag = map.next_value()?,
+                Field::DeadlineUs => frame.deadline_us = map.next_value()?,
+                Field::Other => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        if message_type != Some(MessageType::AlpineFrame) {
+            return Err(de::Error::custom("not an alpine_frame"));
+        }
+        if frame.channel_format == ChannelFormat::F32 {
+            return Err(de::Error::custom(
+                "f32 and compressed frames do not fit the memory budget",
+            ));
+        }
+        Ok(frame)
+    }
+}
+
+impl<'de, const CHANNELS: usize> Deserialize<'de> for FixedFrame<CHANNELS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(FrameVisitor(PhantomData))
+    }
+}
+
+/// Frames waiting for output, oldest first. When all `DEPTH` slots are taken the oldest
+/// frame is dropped, since a newer look supersedes it.
+#[derive(Debug)]
+pub struct FrameQueue<const CHANNELS: usize, const DEPTH: usize> {
+    slots: [Option<FixedFrame<CHANNELS>>; DEPTH],
+    head: usize,
+    len: usize,
+    dropped: u64,
+}
+
+impl<const CHANNELS: usize, const DEPTH: usize> FrameQueue<CHANNELS, DEPTH> {
+    pub fn new() -> Self {
+        Self {
+            slots: core::array::from_fn(|_| None),
+            head: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Frames dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn push(&mut self, frame: FixedFrame<CHANNELS>) {
+        if DEPTH == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.len == DEPTH {
+            self.head = (self.head + 1) % DEPTH;
+            self.len -= 1;
+            self.dropped += 1;
+        }
+        self.slots[(self.head + self.len) % DEPTH] = Some(frame);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<FixedFrame<CHANNELS>> {
+        if self.len == 0 {
+            return None;
+        }
+        let frame = self.slots[self.head].take();
+        self.head = (self.head + 1) % DEPTH;
+        self.len -= 1;
+        frame
+    }
+}
+
+impl<const CHANNELS: usize, const DEPTH: usize> Default for FrameQueue<CHANNELS, DEPTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Node receive path within a fixed budget: datagrams of up to `DATAGRAM` bytes, frames
+/// of up to `CHANNELS` levels on at most `UNIVERSES` universes, and `DEPTH` frames queued
+/// between the network and the output.
+///
+/// The host receives each datagram into [`Self::buffer`], hands its length to
+/// [`Self::receive`], and calls [`Self::apply`] from its output loop.
+#[derive(Debug)]
+pub struct BudgetedReceiver<
+    const DATAGRAM: usize,
+    const CHANNELS: usize,
+    const UNIVERSES: usize,
+    const DEPTH: usize,
+> {
+    session_id: Uuid,
+    buffer: [u8; DATAGRAM],
+    queue: FrameQueue<CHANNELS, DEPTH>,
+    looks: [Option<FixedFrame<CHANNELS>>; UNIVERSES],
+}
+
+impl<const DATAGRAM: usize, const CHANNELS: usize, const UNIVERSES: usize, const DEPTH: usize>
+    BudgetedReceiver<DATAGRAM, CHANNELS, UNIVERSES, DEPTH>
+{
+    /// Bytes the receiver occupies, wherever it is placed.
+    pub const BYTES: usize = size_of::<Self>();
+
+    const WITHIN_BUDGET: () = {
+        assert!(
+            DATAGRAM <= DECODE_SCRATCH,
+            "datagrams larger than DECODE_SCRATCH can make the decoder allocate"
+        );
+        assert!(
+            UNIVERSES > 0 && DEPTH > 0,
+            "a receiver needs a universe and a queue slot"
+        );
+        assert!(
+            Self::BYTES <= MAX_NODE_BUFFERS,
+            "receiver buffers exceed MAX_NODE_BUFFERS"
+        );
+    };
+
+    /// Receiver for the frames of `session_id`. Fails to compile when the parameters
+    /// exceed the budget.
+    pub fn new(session_id: Uuid) -> Self {
+        let () = Self::WITHIN_BUDGET;
+        Self {
+            session_id,
+            buffer: [0; DATAGRAM],
+            queue: FrameQueue::new(),
+            looks: core::array::from_fn(|_| None),
+        }
+    }
+
+    /// Buffer to receive the next datagram into.
+    pub fn buffer(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+
+    /// Decodes the first `len` bytes of [`Self::buffer`] and queues the frame, returning
+    /// its universe.
+    ///
+    /// Frames for a universe with no slot are refused once all `UNIVERSES` slots hold
+    /// looks; the slot is reserved when the frame is applied.
+    pub fn receive(&mut self, len: usize) -> Result<UniverseId, BudgetError> {
+        let bytes = self.buffer.get(..len).ok_or(BudgetError::Oversized(len))?;
+        let frame: FixedFrame<CHANNELS> =
+            codec::decode_untrusted_within(bytes, DATAGRAM).map_err(BudgetError::Decode)?;
+        if frame.session_id != self.session_id {
+            return Err(BudgetError::WrongSession);
+        }
+        let universe = frame.universe;
+        if self.slot(universe).is_none() {
+            return Err(BudgetError::UniversesFull(universe));
+        }
+        self.queue.push(frame);
+        Ok(universe)
+    }
+
+    /// Moves the oldest queued frame into its universe's look and returns the universe.
+    /// Frames older than the look already applied are skipped.
+    pub fn apply(&mut self) -> Option<UniverseId> {
+        while let Some(frame) = self.queue.pop() {
+            let Some(index) = self.slot(frame.universe) else {
+                continue;
+            };
+            let look = &mut self.looks[index];
+            if look
+                .as_ref()
+                .is_some_and(|current| current.timestamp_us > frame.timestamp_us)
+            {
+                continue;
+            }
+            let universe = frame.universe;
+            *look = Some(frame);
+            return Some(universe);
+        }
+        None
+    }
+
+    /// Latest applied frame of `universe`.
+    pub fn look(&self, universe: UniverseId) -> Option<&FixedFrame<CHANNELS>> {
+        self.looks
+            .iter()
+            .flatten()
+            .find(|look| look.universe == universe)
+    }
+
+    /// Frames dropped because the output fell `DEPTH` frames behind.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped()
+    }
+
+    /// Slot holding `universe`, or a free one.
+    fn slot(&self, universe: UniverseId) -> Option<usize> {
+        self.looks
+            .iter()
+            .position(|look| look.as_ref().is_some_and(|look| look.universe == universe))
+            .or_else(|| self.looks.iter().position(Option::is_none))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{FrameEnvelope, PackedChannels};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    const SESSION: Uuid = Uuid::from_u128(7);
+
+    fn frame(universe: UniverseId, timestamp_us: u64, channels: Vec<u16>) -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: SESSION,
+            universe,
+            timestamp_us,
+            priority: 100,
+            channel_format: ChannelFormat::U8,
+            channels: channels.into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: Some(timestamp_us + 20_000),
+            metadata: Some([("cue".into(), serde_json::json!({"n": [1, 2]}))].into()),
+            extensions: None,
+        }
+    }
+
+    fn receive<const D: usize, const C: usize, const U: usize, const Q: usize>(
+        receiver: &mut BudgetedReceiver<D, C, U, Q>,
+        frame: &FrameEnvelope,
+    ) -> Result<UniverseId, BudgetError> {
+        let bytes = codec::to_vec(frame).unwrap();
+        receiver.buffer()[..bytes.len()].copy_from_slice(&bytes);
+        receiver.receive(bytes.len())
+    }
+
+    #[test]
+    fn decodes_every_integer_format_into_fixed_storage() {
+        let mut receiver = BudgetedReceiver::<512, 8, 1, 2>::new(SESSION);
+        let plain = frame(1, 10, vec![0, 128, 255]);
+        assert_eq!(receive(&mut receiver, &plain), Ok(1));
+        assert_eq!(receiver.apply(), Some(1));
+        let look = receiver.look(1).unwrap();
+        assert_eq!(look.levels(), &[0, 128, 255]);
+        assert_eq!(look.deadline_us, Some(20_010));
+
+        let mut packed = frame(1, 20, vec![]);
+        packed.channel_format = ChannelFormat::U8Packed;
+        packed.packed_channels = Some(PackedChannels(vec![9, 8, 7, 6]));
+        receive(&mut receiver, &packed).unwrap();
+        receiver.apply();
+        assert_eq!(receiver.look(1).unwrap().levels(), &[9, 8, 7, 6]);
+    }
+
+    #[test]
+    fn refuses_frames_beyond_the_budget() {
+        let mut receiver = BudgetedReceiver::<512, 4, 1, 1>::new(SESSION);
+        assert!(matches!(
+            receive(&mut receiver, &frame(1, 1, vec![1; 5])),
+            Err(BudgetError::Decode(_))
+        ));
+
+        let mut float = frame(1, 1, vec![]);
+        float.channel_format = ChannelFormat::F32;
+        float.float_channels = Some(vec![0.5]);
+        assert!(matches!(
+            receive(&mut receiver, &float),
+            Err(BudgetError::Decode(_))
+        ));
+
+        let mut other = frame(1, 1, vec![1]);
+        other.session_id = Uuid::from_u128(8);
+        assert_eq!(
+            receive(&mut receiver, &other),
+            Err(BudgetError::WrongSession)
+        );
+        assert_eq!(receiver.receive(513), Err(BudgetError::Oversized(513)));
+
+        receive(&mut receiver, &frame(1, 1, vec![1])).unwrap();
+        receiver.apply();
+        assert_eq!(
+            receive(&mut receiver, &frame(2, 2, vec![1])),
+            Err(BudgetError::UniversesFull(2))
+        );
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest_frame() {
+        let mut receiver = BudgetedReceiver::<512, 4, 1, 2>::new(SESSION);
+        for timestamp in 1..=3 {
+            receive(&mut receiver, &frame(1, timestamp, vec![timestamp as u16])).unwrap();
+        }
+        assert_eq!(receiver.dropped(), 1);
+        assert_eq!(receiver.apply(), Some(1));
+        assert_eq!(receiver.look(1).unwrap().levels(), &[2]);
+        assert_eq!(receiver.apply(), Some(1));
+        assert_eq!(receiver.look(1).unwrap().levels(), &[3]);
+        assert_eq!(receiver.apply(), None);
+    }
+
+    #[test]
+    fn stale_frames_do_not_replace_the_look() {
+        let mut receiver = BudgetedReceiver::<512, 4, 2, 4>::new(SESSION);
+        receive(&mut receiver, &frame(1, 50, vec![5])).unwrap();
+        receive(&mut receiver, &frame(1, 40, vec![4])).unwrap();
+        receive(&mut receiver, &frame(2, 10, vec![1])).unwrap();
+        assert_eq!(receiver.apply(), Some(1));
+        assert_eq!(receiver.apply(), Some(2));
+        assert_eq!(receiver.look(1).unwrap().levels(), &[5]);
+    }
+
+    #[test]
+    fn embedded_receiver_fits_the_budget() {
+        const { assert!(EmbeddedReceiver::BYTES <= MAX_NODE_BUFFERS) };
+        let receiver = EmbeddedReceiver::new(SESSION);
+        assert_eq!(receiver.buffer.len(), 2048);
+    }
+}
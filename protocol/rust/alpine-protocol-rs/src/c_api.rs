@@ -2,6 +2,7 @@ use std::ffi::CStr;
 use std::os::raw::{c_char, c_int};
 use std::slice;
 
+use crate::codec;
 use crate::messages::DiscoveryRequest;
 
 #[repr(C)]
@@ -98,7 +99,7 @@ pub extern "C" fn alnp_build_discovery_request(
     };
 
     let discovery = DiscoveryRequest::new(requested, nonce);
-    let encoded = match serde_cbor::to_vec(&discovery) {
+    let encoded = match codec::to_vec(&discovery) {
         Ok(bytes) => bytes,
         Err(_) => return -1,
     };
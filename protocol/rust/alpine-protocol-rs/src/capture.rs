@@ -0,0 +1,371 @@
+//! Rolling capture of received frames for diagnostics.
+//!
+//! When an operator reports a flicker at 21:03, support needs to see exactly what the
+//! node received then. A [`FrameCapture`] keeps the decoded levels of the last few
+//! seconds of frames with their arrival time, source, and whether they were applied, and
+//! serves them over `get_capture`.
+//!
+//! Control acks travel in one datagram, so the capture is read in pages of at most
+//! [`CAPTURE_PAGE_BYTES`]: a page holds [`CapturedSlice`]s, consecutive channels of one
+//! frame, and a cursor to continue from. [`CapturedFrame::join`] puts the slices of all
+//! pages back together.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::control::ControlHandlers;
+use crate::messages::{ChannelFormat, ControlOp, UniverseId};
+
+/// How far back a capture reaches unless configured otherwise.
+pub const DEFAULT_CAPTURE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Frames kept regardless of the window, so a fast stream cannot exhaust memory.
+pub const MAX_CAPTURED_FRAMES: usize = 8_192;
+
+/// Most bytes of JSON one `get_capture` page holds.
+pub const CAPTURE_PAGE_BYTES: usize = 1_200;
+
+/// One received frame, with levels unpacked and decompressed but not smoothed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CapturedFrame {
+    /// Capture-wide counter, increasing with arrival.
+    pub seq: u64,
+    /// Node wall clock when the datagram was read, in microseconds since the epoch.
+    pub received_us: u64,
+    /// Address of the controller that sent the frame.
+    pub source: String,
+    pub universe: UniverseId,
+    pub priority: u8,
+    /// Sender wall clock from the frame.
+    pub timestamp_us: u64,
+    pub format: ChannelFormat,
+    /// Whether the frame reached the outputs; refused and late frames are kept too.
+    pub applied: bool,
+    pub levels: Vec<u16>,
+}
+
+impl CapturedFrame {
+    /// Joins slices from any number of pages into frames, ordered by `seq`. Frames with
+    /// a missing slice keep the levels that arrived.
+    pub fn join(slices: impl IntoIterator<Item = CapturedSlice>) -> Vec<CapturedFrame> {
+        let mut frames: Vec<CapturedFrame> = Vec::new();
+        for slice in slices {
+            match frames.last_mut() {
+                Some(frame) if frame.seq == slice.seq => frame.levels.extend(slice.levels),
+                _ => frames.push(CapturedFrame {
+                    seq: slice.seq,
+                    received_us: slice.received_us,
+                    source: slice.source,
+                    universe: slice.universe,
+                    priority: slice.priority,
+                    timestamp_us: slice.timestamp_us,
+                    format: slice.format,
+                    applied: slice.applied,
+                    levels: slice.levels,
+                }),
+            }
+        }
+        frames
+    }
+}
+
+/// Consecutive channels of one captured frame, starting at `first_channel`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CapturedSlice {
+    pub seq: u64,
+    pub received_us: u64,
+    pub source: String,
+    pub universe: UniverseId,
+    pub priority: u8,
+    pub timestamp_us: u64,
+    pub format: ChannelFormat,
+    pub applied: bool,
+    pub first_channel: u16,
+    pub levels: Vec<u16>,
+}
+
+/// Where the next page starts.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CaptureCursor {
+    pub seq: u64,
+    pub channel: u16,
+}
+
+/// Payload of `get_capture`; every field narrows the frames returned.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CaptureQuery {
+    /// Frames received at or after this node wall clock time, in microseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since_us: Option<u64>,
+    /// Frames received at or before this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until_us: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub universe: Option<UniverseId>,
+    /// `next` of the previous page; absent for the first one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<CaptureCursor>,
+}
+
+/// Ack detail of `get_capture`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CapturePage {
+    pub window_ms: u64,
+    pub slices: Vec<CapturedSlice>,
+    /// Cursor for the following page; `None` once the query is exhausted.
+    pub next: Option<CaptureCursor>,
+}
+
+/// Arrival details of a frame handed to [`FrameCapture::record`].
+#[derive(Debug, Clone)]
+pub struct FrameArrival {
+    pub received_us: u64,
+    pub source: String,
+    pub universe: UniverseId,
+    pub priority: u8,
+    pub timestamp_us: u64,
+    pub format: ChannelFormat,
+    pub applied: bool,
+}
+
+#[derive(Debug)]
+struct Frames {
+    next_seq: u64,
+    frames: VecDeque<CapturedFrame>,
+}
+
+/// Rolling window of received frames.
+#[derive(Debug)]
+pub struct FrameCapture {
+    window: Duration,
+    frames: Mutex<Frames>,
+}
+
+impl Default for FrameCapture {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPTURE_WINDOW)
+    }
+}
+
+impl FrameCapture {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            frames: Mutex::new(Frames {
+                next_seq: 0,
+                frames: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Adds a frame and forgets those older than the window before it.
+    pub fn record(&self, arrival: FrameArrival, levels: Vec<u16>) {
+        let mut frames = self.frames.lock();
+        let seq = frames.next_seq;
+        frames.next_seq += 1;
+        let oldest_us = arrival
+            .received_us
+            .saturating_sub(self.window.as_micros() as u64);
+        while frames
+            .frames
+            .front()
+            .is_some_and(|frame| frame.received_us < oldest_us)
+            || frames.frames.len() >= MAX_CAPTURED_FRAMES
+        {
+            frames.frames.pop_front();
+        }
+        frames.frames.push_back(CapturedFrame {
+            seq,
+            received_us: arrival.received_us,
+            source: arrival.source,
+            universe: arrival.universe,
+            priority: arrival.priority,
+            timestamp_us: arrival.timestamp_us,
+            format: arrival.format,
+            applied: arrival.applied,
+            levels,
+        });
+    }
+
+    /// Frames matching `query`, ignoring its cursor.
+    pub fn frames(&self, query: &CaptureQuery) -> Vec<CapturedFrame> {
+        let frames = self.frames.lock();
+        frames
+            .frames
+            .iter()
+            .filter(|frame| matches(query, frame))
+            .cloned()
+            .collect()
+    }
+
+    /// The page of `query` starting at its cursor.
+    pub fn page(&self, query: &CaptureQuery) -> CapturePage {
+        let frames = self.frames.lock();
+        let mut page = CapturePage {
+            window_ms: self.window.as_millis() as u64,
+            ..CapturePage::default()
+        };
+        let mut budget = CAPTURE_PAGE_BYTES.saturating_sub(json_len(&page));
+        let start = query.cursor.unwrap_or(CaptureCursor { seq: 0, channel: 0 });
+        let pending = frames
+            .frames
+            .iter()
+            .filter(|frame| frame.seq >= start.seq && matches(query, frame));
+        for frame in pending {
+            let first = if frame.seq == start.seq {
+                usize::from(start.channel).min(frame.levels.len())
+            } else {
+                0
+            };
+            let mut slice = CapturedSlice {
+                seq: frame.seq,
+                received_us: frame.received_us,
+                source: frame.source.clone(),
+                universe: frame.universe,
+                priority: frame.priority,
+                timestamp_us: frame.timestamp_us,
+                format: frame.format,
+                applied: frame.applied,
+                first_channel: first as u16,
+                levels: Vec::new(),
+            };
+            // A separating comma, plus a generous allowance for the cursor.
+            let overhead = json_len(&slice) + 1 + 48;
+            if overhead >= budget {
+                page.next = Some(CaptureCursor {
+                    seq: frame.seq,
+                    channel: first as u16,
+                });
+                break;
+            }
+            budget -= overhead;
+            let mut end = first;
+            for &level in &frame.levels[first..] {
+                let cost = decimal_len(level) + 1;
+                if cost > budget {
+                    break;
+                }
+                budget -= cost;
+                end += 1;
+            }
+            slice.levels = frame.levels[first..end].to_vec();
+            page.slices.push(slice);
+            if end < frame.levels.len() {
+                page.next = Some(CaptureCursor {
+                    seq: frame.seq,
+                    channel: end as u16,
+                });
+                break;
+            }
+        }
+        page
+    }
+
+    /// Registers the handler for `get_capture`, which acks with a [`CapturePage`] as JSON.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let capture = self.clone();
+        handlers.on(ControlOp::GetCapture, move |query: CaptureQuery| {
+            serde_json::to_string(&capture.page(&query))
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+fn matches(query: &CaptureQuery, frame: &CapturedFrame) -> bool {
+    query
+        .since_us
+        .is_none_or(|since| frame.received_us >= since)
+        && query
+            .until_us
+            .is_none_or(|until| frame.received_us <= until)
+        && query
+            .universe
+            .is_none_or(|universe| frame.universe == universe)
+}
+
+fn json_len<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
+
+fn decimal_len(value: u16) -> usize {
+    value
+        .checked_ilog10()
+        .map_or(1, |digits| digits as usize + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arrival(received_us: u64, universe: UniverseId) -> FrameArrival {
+        FrameArrival {
+            received_us,
+            source: "10.0.0.2:6454".into(),
+            universe,
+            priority: 100,
+            timestamp_us: received_us.saturating_sub(1_000),
+            format: ChannelFormat::U16,
+            applied: true,
+        }
+    }
+
+    #[test]
+    fn keeps_only_the_window() {
+        let capture = FrameCapture::new(Duration::from_secs(1));
+        for i in 0..5u64 {
+            capture.record(arrival(i * 400_000, 1), vec![i as u16; 4]);
+        }
+        let frames = capture.frames(&CaptureQuery::default());
+        assert_eq!(
+            frames.iter().map(|f| f.seq).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        let narrowed = capture.frames(&CaptureQuery {
+            since_us: Some(1_000_000),
+            universe: Some(1),
+            ..CaptureQuery::default()
+        });
+        assert_eq!(narrowed.len(), 2);
+        assert!(capture
+            .frames(&CaptureQuery {
+                universe: Some(2),
+                ..CaptureQuery::default()
+            })
+            .is_empty());
+    }
+
+    #[test]
+    fn pages_split_frames_and_join_back() {
+        let capture = FrameCapture::default();
+        let levels: Vec<u16> = (0..512).map(|i| (i * 127) as u16).collect();
+        for i in 0..3u64 {
+            capture.record(arrival(1_000_000 + i, 0), levels.clone());
+        }
+        let mut query = CaptureQuery::default();
+        let mut slices = Vec::new();
+        let mut pages = 0;
+        loop {
+            let page = capture.page(&query);
+            assert!(serde_json::to_vec(&page).unwrap().len() <= CAPTURE_PAGE_BYTES);
+            pages += 1;
+            slices.extend(page.slices);
+            match page.next {
+                Some(next) => query.cursor = Some(next),
+                None => break,
+            }
+        }
+        assert!(pages > 3);
+        let frames = CapturedFrame::join(slices);
+        assert_eq!(frames, capture.frames(&CaptureQuery::default()));
+        assert!(frames.iter().all(|frame| frame.levels == levels));
+    }
+}
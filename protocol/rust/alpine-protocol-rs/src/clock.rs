@@ -0,0 +1,226 @@
+//! The node's wall clock and where it gets its time from.
+//!
+//! Schedules fire on the node's clock, and log entries, captured frames, and latency
+//! samples are stamped with it, so comparing them across a rig needs every node to agree
+//! on the time. Nodes without NTP or a battery-backed clock boot at the epoch or
+//! wherever their RTC drifted to. A [`NodeClock`] lets a controller set the time with
+//! `time_sync` and read it back, together with its source and offset, with `get_time`.
+//!
+//! Setting the clock does not touch the operating system clock, which usually needs
+//! privileges the node process lacks; the node keeps an offset from it instead. Nodes
+//! whose time comes from NTP, PTP, or GPS refuse `time_sync`, since a controller's clock
+//! is no better than theirs.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::control::ControlHandlers;
+use crate::messages::ControlOp;
+
+/// Where the node's time comes from.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSource {
+    /// The operating system clock, with no known discipline.
+    #[default]
+    System,
+    /// A battery-backed real-time clock.
+    Rtc,
+    Ntp,
+    Ptp,
+    Gps,
+    /// Set by a controller with `time_sync`.
+    Controller,
+}
+
+impl TimeSource {
+    /// Whether the source keeps better time than a controller could set, so `time_sync`
+    /// is refused.
+    pub fn is_disciplined(&self) -> bool {
+        matches!(self, TimeSource::Ntp | TimeSource::Ptp | TimeSource::Gps)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeSource::System => "system",
+            TimeSource::Rtc => "rtc",
+            TimeSource::Ntp => "ntp",
+            TimeSource::Ptp => "ptp",
+            TimeSource::Gps => "gps",
+            TimeSource::Controller => "controller",
+        }
+    }
+}
+
+/// Payload of `time_sync`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimeSet {
+    /// Controller wall clock, in microseconds since the epoch, when the node reads the
+    /// request; senders add their estimate of the one-way delay.
+    pub unix_us: u64,
+}
+
+/// Ack detail of `get_time` and `time_sync`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimeStatus {
+    /// Node wall clock, in microseconds since the epoch.
+    pub unix_us: u64,
+    pub source: TimeSource,
+    /// How far the node clock is ahead of the operating system clock.
+    pub offset_us: i64,
+    /// Node wall clock when a controller last set the time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub set_at_us: Option<u64>,
+    /// Session that last set the time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub set_by: Option<Uuid>,
+}
+
+#[derive(Debug, Default)]
+struct ClockState {
+    source: TimeSource,
+    offset_us: i64,
+    set_at_us: Option<u64>,
+    set_by: Option<Uuid>,
+}
+
+/// Wall clock of a node: the operating system clock plus an offset a controller set.
+#[derive(Debug, Default)]
+pub struct NodeClock {
+    state: Mutex<ClockState>,
+}
+
+impl NodeClock {
+    /// A clock that reports `source` until a controller sets it.
+    pub fn new(source: TimeSource) -> Self {
+        Self {
+            state: Mutex::new(ClockState {
+                source,
+                ..ClockState::default()
+            }),
+        }
+    }
+
+    /// Reports `source` from now on, e.g. once NTP has synchronised after boot. A
+    /// disciplined source drops any offset a controller set, since the operating system
+    /// clock is now right.
+    pub fn set_source(&self, source: TimeSource) {
+        let mut state = self.state.lock();
+        if source.is_disciplined() {
+            *state = ClockState::default();
+        }
+        state.source = source;
+    }
+
+    /// Microseconds since the epoch on the node clock.
+    pub fn now_us(&self) -> u64 {
+        self.at(system_us())
+    }
+
+    /// Milliseconds since the epoch on the node clock.
+    pub fn now_ms(&self) -> u64 {
+        self.now_us() / 1_000
+    }
+
+    /// The node clock reading for an operating system clock reading of `system_us`.
+    pub fn at(&self, system_us: u64) -> u64 {
+        system_us.saturating_add_signed(self.state.lock().offset_us)
+    }
+
+    pub fn status(&self) -> TimeStatus {
+        let system = system_us();
+        let state = self.state.lock();
+        TimeStatus {
+            unix_us: system.saturating_add_signed(state.offset_us),
+            source: state.source,
+            offset_us: state.offset_us,
+            set_at_us: state.set_at_us,
+            set_by: state.set_by,
+        }
+    }
+
+    /// Steps the clock to `unix_us`, as read when the operating system clock showed
+    /// `system_us`; refused when the clock is disciplined.
+    pub fn set(&self, unix_us: u64, system_us: u64, set_by: Option<Uuid>) -> Result<(), String> {
+        let mut state = self.state.lock();
+        if state.source.is_disciplined() {
+            return Err(format!("clock is disciplined by {}", state.source.as_str()));
+        }
+        let offset = i128::from(unix_us) - i128::from(system_us);
+        state.offset_us = offset.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64;
+        state.source = TimeSource::Controller;
+        state.set_at_us = Some(unix_us);
+        state.set_by = set_by;
+        Ok(())
+    }
+
+    /// Registers the handlers for `time_sync`, which sets the clock, and `get_time`; both
+    /// ack with a [`TimeStatus`] as JSON.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let clock = self.clone();
+        handlers.on_envelope(ControlOp::TimeSync, move |env| {
+            let request: TimeSet = env.payload_as().map_err(|e| e.to_string())?;
+            clock.set(request.unix_us, system_us(), Some(env.session_id))?;
+            serde_json::to_string(&clock.status())
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+        let clock = self.clone();
+        handlers.on(ControlOp::GetTime, move |_: serde_json::Value| {
+            serde_json::to_string(&clock.status())
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+fn system_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_the_clock_keeps_an_offset_from_the_system_clock() {
+        let clock = NodeClock::default();
+        assert_eq!(clock.at(5_000_000), 5_000_000);
+        assert_eq!(clock.status().source, TimeSource::System);
+
+        let session = Uuid::new_v4();
+        clock.set(1_000_000, 5_000_000, Some(session)).unwrap();
+        assert_eq!(clock.at(6_000_000), 2_000_000);
+        let status = clock.status();
+        assert_eq!(status.source, TimeSource::Controller);
+        assert_eq!(status.offset_us, -4_000_000);
+        assert_eq!(status.set_by, Some(session));
+
+        clock.set(9_000_000, 5_000_000, None).unwrap();
+        assert_eq!(clock.at(5_000_000), 9_000_000);
+    }
+
+    #[test]
+    fn disciplined_clocks_refuse_to_be_set() {
+        let clock = NodeClock::new(TimeSource::Ntp);
+        assert_eq!(
+            clock.set(1, 2, None),
+            Err("clock is disciplined by ntp".into())
+        );
+        assert_eq!(clock.status().offset_us, 0);
+        assert_eq!(clock.status().source, TimeSource::Ntp);
+
+        let clock = NodeClock::default();
+        clock.set(1_000_000, 5_000_000, None).unwrap();
+        clock.set_source(TimeSource::Ptp);
+        assert_eq!(clock.at(5_000_000), 5_000_000);
+        assert_eq!(clock.status().set_at_us, None);
+    }
+}
@@ -0,0 +1,427 @@
+//! CBOR encoding shared by every ALPINE message.
+//!
+//! All wire encoding goes through these helpers so the codec can evolve in one place and
+//! stays available on `no_std` targets. The backend is `ciborium`; its output is plain
+//! RFC 8949 CBOR and stays wire-compatible with peers that still use `serde_cbor`.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use ciborium::value::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+mod strict;
+pub use strict::{CriticalField, DecodePolicy};
+
+/// Error produced while encoding or decoding a CBOR payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    Encode(String),
+    Decode(String),
+    /// A critical field holds a value this implementation does not know; `offset` is the
+    /// byte offset of the value in the message. Only [`DecodePolicy::Strict`] reports it.
+    UnknownValue {
+        field: CriticalField,
+        value: String,
+        offset: usize,
+    },
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Encode(err) => write!(f, "encode: {}", err),
+            CodecError::Decode(err) => write!(f, "decode: {}", err),
+            CodecError::UnknownValue {
+                field,
+                value,
+                offset,
+            } => write!(
+                f,
+                "decode: unknown {} `{}` at byte {}",
+                field, value, offset
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CodecError {}
+
+/// Largest message accepted from the network (the maximum UDP payload over IPv4).
+pub const MAX_MESSAGE_SIZE: usize = 65_507;
+
+/// Deepest nesting accepted from the network; ALPINE messages nest only a few levels.
+pub const MAX_NESTING: usize = 32;
+
+/// Encodes `value` as CBOR.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, CodecError> {
+    let mut out = Vec::new();
+    ciborium::ser::into_writer(value, &mut out)
+        .map_err(|e| CodecError::Encode(format!("{:?}", e)))?;
+    Ok(out)
+}
+
+/// Encodes `value` using the deterministic encoding of RFC 8949 §4.2.1.
+///
+/// Integers, lengths, and floats take their shortest form, and map entries are sorted by
+/// the bytewise order of their encoded keys, so peers that build the same value produce
+/// the same bytes regardless of map iteration order. Every MAC and signature input that
+/// covers structured data MUST be encoded with this function.
+pub fn to_canonical_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, CodecError> {
+    to_vec(&canonicalize(to_value(value)?)?)
+}
+
+/// Converts `value` into a CBOR value, such as a control payload built from a typed
+/// struct or from the `serde_json::Value` an older integration still passes.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, CodecError> {
+    Value::serialized(value).map_err(|e| CodecError::Encode(format!("{:?}", e)))
+}
+
+/// Decodes a CBOR value into `T`.
+pub fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T, CodecError> {
+    value
+        .deserialized()
+        .map_err(|e| CodecError::Decode(format!("{:?}", e)))
+}
+
+fn canonicalize(value: Value) -> Result<Value, CodecError> {
+    Ok(match value {
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(canonicalize)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Tag(tag, inner) => Value::Tag(tag, Box::new(canonicalize(*inner)?)),
+        Value::Map(entries) => {
+            let mut keyed = Vec::with_capacity(entries.len());
+            for (key, entry) in entries {
+                let key = canonicalize(key)?;
+                keyed.push((to_vec(&key)?, key, canonicalize(entry)?));
+            }
+            keyed.sort_by(|a, b| a.0.cmp(&b.0));
+            if keyed.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(CodecError::Encode("duplicate map key".into()));
+            }
+            Value::Map(keyed.into_iter().map(|(_, k, v)| (k, v)).collect())
+        }
+        other => other,
+    })
+}
+
+/// Decodes a CBOR payload into `T`.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+    ciborium::de::from_reader(bytes).map_err(|e| CodecError::Decode(format!("{:?}", e)))
+}
+
+/// Decodes a payload received from an untrusted peer.
+///
+/// Oversized input and excessive nesting are rejected up front, and malformed bytes of
+/// any shape surface as [`CodecError::Decode`] rather than a panic. Every network receive
+/// path must decode through this function.
+pub fn decode_untrusted<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+    decode_untrusted_within(bytes, MAX_MESSAGE_SIZE)
+}
+
+/// Like [`decode_untrusted`] for payloads reassembled from several messages, such as
+/// uploaded shows, with `max_size` in place of the datagram limit.
+pub fn decode_untrusted_within<T: DeserializeOwned>(
+    bytes: &[u8],
+    max_size: usize,
+) -> Result<T, CodecError> {
+    if bytes.len() > max_size {
+        return Err(CodecError::Decode(format!(
+            "message of {} bytes exceeds {}",
+            bytes.len(),
+            max_size
+        )));
+    }
+    ciborium::de::from_reader_with_recursion_limit(bytes, MAX_NESTING)
+        .map_err(|e| CodecError::Decode(format!("{:?}", e)))
+}
+
+/// Decodes an untrusted payload of any shape into JSON, for inspecting captured packets.
+///
+/// CBOR byte strings become lowercase hex, tags are dropped, and non-text map keys are
+/// written in CBOR diagnostic form.
+pub fn to_diagnostic_json(bytes: &[u8]) -> Result<serde_json::Value, CodecError> {
+    decode_untrusted::<Value>(bytes).map(|value| diagnostic(&value))
+}
+
+fn diagnostic(value: &Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        Value::Integer(int) => {
+            let int = i128::from(*int);
+            i64::try_from(int)
+                .map(Json::from)
+                .or_else(|_| u64::try_from(int).map(Json::from))
+                .unwrap_or_else(|_| Json::String(int.to_string()))
+        }
+        Value::Bytes(bytes) => Json::String(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        Value::Float(float) => Json::from(*float),
+        Value::Text(text) => Json::String(text.clone()),
+        Value::Bool(flag) => Json::Bool(*flag),
+        Value::Null => Json::Null,
+        Value::Tag(_, inner) => diagnostic(inner),
+        Value::Array(items) => Json::Array(items.iter().map(diagnostic).collect()),
+        Value::Map(entries) => Json::Object(
+            entries
+                .iter()
+                .map(|(key, entry)| {
+                    let key = match key {
+                        Value::Text(text) => text.clone(),
+                        other => diagnostic(other).to_string(),
+                    };
+                    (key, diagnostic(entry))
+                })
+                .collect(),
+        ),
+        _ => Json::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handshake::HandshakeMessage;
+    use crate::messages::{
+        CapabilitySet, ChannelFormat, ControlEnvelope, ControlOp, DiscoveryRequest, Extensible,
+        ExtensionValue, FrameEnvelope, Keepalive, KeepaliveStats, MessageType,
+    };
+    use ciborium::cbor;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn frame() -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::new_v4(),
+            universe: 3,
+            timestamp_us: 1_234_567,
+            priority: 100,
+            channel_format: ChannelFormat::U16,
+            channels: vec![0, 255, 65_535].into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: Some([("k".to_string(), json!({"x": 1.5, "y": [1, 2]}))].into()),
+            extensions: None,
+        }
+    }
+
+    fn control() -> HandshakeMessage {
+        HandshakeMessage::Control(ControlEnvelope {
+            message_type: MessageType::AlpineControl,
+            session_id: Uuid::new_v4(),
+            seq: u64::MAX,
+            op: ControlOp::SetMode,
+            payload: cbor!({"mode" => "show", "level" => -3}).unwrap(),
+            mac: vec![0xAA; 16],
+        })
+    }
+
+    #[test]
+    fn serde_cbor_peers_decode_our_output() {
+        let frame = frame();
+        let decoded: FrameEnvelope = serde_cbor::from_slice(&to_vec(&frame).unwrap()).unwrap();
+        assert_eq!(decoded, frame);
+
+        let msg = control();
+        let decoded: HandshakeMessage = serde_cbor::from_slice(&to_vec(&msg).unwrap()).unwrap();
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn we_decode_serde_cbor_output() {
+        let frame = frame();
+        let decoded: FrameEnvelope = from_slice(&serde_cbor::to_vec(&frame).unwrap()).unwrap();
+        assert_eq!(decoded, frame);
+
+        let keepalive = HandshakeMessage::Keepalive(Keepalive {
+            message_type: MessageType::Keepalive,
+            session_id: Uuid::new_v4(),
+            tick_ms: 5_000,
+            stats: Some(KeepaliveStats {
+                frames_sent: 440,
+                last_frame_us: Some(1_000_000),
+                ..KeepaliveStats::default()
+            }),
+        });
+        let decoded: HandshakeMessage =
+            from_slice(&serde_cbor::to_vec(&keepalive).unwrap()).unwrap();
+        assert_eq!(decoded, keepalive);
+
+        let request = DiscoveryRequest::new(vec!["alpine-control".into()], vec![7; 32]);
+        let decoded: DiscoveryRequest = from_slice(&serde_cbor::to_vec(&request).unwrap()).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    #[test]
+    fn encodings_match_byte_for_byte() {
+        let caps = CapabilitySet::default();
+        assert_eq!(to_vec(&caps).unwrap(), serde_cbor::to_vec(&caps).unwrap());
+        let msg = control();
+        assert_eq!(to_vec(&msg).unwrap(), serde_cbor::to_vec(&msg).unwrap());
+    }
+
+    #[test]
+    fn diagnostic_json_shows_any_message() {
+        let bytes = to_vec(&frame()).unwrap();
+        let json = to_diagnostic_json(&bytes).unwrap();
+        assert_eq!(json["type"], "alpine_frame");
+        assert_eq!(json["channels"], json!([0, 255, 65_535]));
+        let bytes = to_vec(&Value::Map(vec![(
+            Value::Integer(1.into()),
+            Value::Bytes(vec![0xab, 0x01]),
+        )]))
+        .unwrap();
+        assert_eq!(to_diagnostic_json(&bytes).unwrap(), json!({"1": "ab01"}));
+        assert!(to_diagnostic_json(&bytes[..2]).is_err());
+    }
+
+    #[test]
+    fn truncated_input_is_a_decode_error() {
+        let bytes = to_vec(&frame()).unwrap();
+        assert!(matches!(
+            from_slice::<FrameEnvelope>(&bytes[..bytes.len() - 1]),
+            Err(CodecError::Decode(_))
+        ));
+    }
+
+    /// Vectors published in docs/control_plane.md for cross-implementation checks.
+    #[test]
+    fn canonical_vectors() {
+        let payload = json!({"mode": "show", "level": -3, "a": [1, 2.5]});
+        assert_eq!(
+            hex(&to_canonical_vec(&payload).unwrap()),
+            "a3 6161 82 01 f94100 646d6f6465 6473686f77 656c6576656c 22".replace(' ', "")
+        );
+
+        let nested = json!({"z": {"c": 1000, "bb": 1}, "yy": null});
+        assert_eq!(
+            hex(&to_canonical_vec(&nested).unwrap()),
+            "a2 617a a2 6163 1903e8 626262 01 627979 f6".replace(' ', "")
+        );
+    }
+
+    #[test]
+    fn canonical_encoding_ignores_map_insertion_order() {
+        let keys = ["mode", "a", "intensity", "zz", "b", "level"];
+        let forward: std::collections::HashMap<_, _> =
+            keys.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+        let mut reversed = std::collections::HashMap::new();
+        for (i, k) in keys.iter().enumerate().rev() {
+            reversed.insert(*k, i);
+        }
+        assert_eq!(
+            to_canonical_vec(&forward).unwrap(),
+            to_canonical_vec(&reversed).unwrap()
+        );
+        let decoded: std::collections::HashMap<String, usize> =
+            from_slice(&to_canonical_vec(&forward).unwrap()).unwrap();
+        assert_eq!(decoded.len(), keys.len());
+    }
+
+    #[test]
+    fn vendor_extensions_stay_optional_on_the_wire() {
+        let plain = to_vec(&frame()).unwrap();
+        let Value::Map(entries) = from_slice::<Value>(&plain).unwrap() else {
+            panic!("frame must encode as a map");
+        };
+        assert!(!entries
+            .iter()
+            .any(|(k, _)| k.as_text() == Some("extensions")));
+
+        let mut frame = frame();
+        frame.set_extension("com.example.fixture", ExtensionValue::Bytes(vec![1, 2, 3]));
+        let bytes = to_vec(&frame).unwrap();
+        let decoded: FrameEnvelope = from_slice(&bytes).unwrap();
+        assert_eq!(
+            decoded.extension("com.example.fixture"),
+            Some(&ExtensionValue::Bytes(vec![1, 2, 3]))
+        );
+        assert_eq!(decoded.extension("org.unknown"), None);
+
+        // A peer built before extensions existed still decodes the frame.
+        #[derive(serde::Deserialize)]
+        struct LegacyFrame {
+            universe: u16,
+            channels: Vec<u16>,
+        }
+        let legacy: LegacyFrame = serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(legacy.universe, frame.universe);
+        assert_eq!(legacy.channels[..], frame.channels[..]);
+    }
+
+    /// In-tree smoke run of the fuzz targets: mutated and random input never panics.
+    #[test]
+    fn untrusted_decoding_survives_mutated_input() {
+        let mut discovery = crate::messages::DiscoveryReply::new(
+            &crate::messages::DeviceIdentity {
+                device_id: "node-1".into(),
+                manufacturer_id: "manu".into(),
+                model_id: "model".into(),
+                hardware_rev: "rev1".into(),
+                firmware_rev: "1.0.0".into(),
+            },
+            "AA:BB:CC:DD:EE:FF".into(),
+            vec![1; 32],
+            CapabilitySet::default(),
+            vec![2; 64],
+        );
+        discovery.set_extension("com.example", ExtensionValue::Integer(7.into()));
+        let seeds = [
+            to_vec(&frame()).unwrap(),
+            to_vec(&control()).unwrap(),
+            to_vec(&discovery).unwrap(),
+        ];
+
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for round in 0..2_000 {
+            let mut bytes = seeds[round % seeds.len()].clone();
+            for _ in 0..=(next() % 4) {
+                let at = next() as usize % bytes.len();
+                match next() % 3 {
+                    0 => bytes[at] = next() as u8,
+                    1 => bytes.truncate(at.max(1)),
+                    _ => bytes.insert(at, next() as u8),
+                }
+            }
+            let _ = decode_untrusted::<HandshakeMessage>(&bytes);
+            let _ = decode_untrusted::<FrameEnvelope>(&bytes);
+            let _ = decode_untrusted::<ControlEnvelope>(&bytes);
+            let _ = decode_untrusted::<crate::messages::DiscoveryReply>(&bytes);
+        }
+    }
+
+    #[test]
+    fn untrusted_decoding_rejects_deep_nesting_and_oversize() {
+        let mut nested = vec![0x81; MAX_NESTING * 4];
+        nested.push(0x00);
+        assert!(decode_untrusted::<serde_json::Value>(&nested).is_err());
+
+        let oversized = vec![0u8; MAX_MESSAGE_SIZE + 1];
+        assert!(decode_untrusted::<FrameEnvelope>(&oversized).is_err());
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
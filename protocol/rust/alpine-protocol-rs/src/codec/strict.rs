@@ -0,0 +1,351 @@
+//! Decode policies for messages from peers that may run a newer protocol revision.
+//!
+//! Serde ignores map keys a type does not declare, which is what lets older receivers
+//! read newer messages. The same leniency is a hazard for the few fields that decide how
+//! the rest of a message is read: a receiver that skipped an unknown `channel_format`
+//! would read the levels in the wrong layout. Under [`DecodePolicy::Strict`] a pre-pass
+//! walks the raw CBOR and stops at the first such value with its byte offset, before the
+//! typed decode runs.
+
+use alloc::string::String;
+use core::fmt;
+
+use ciborium::value::Value;
+use serde::de::DeserializeOwned;
+
+use super::{decode_untrusted_within, CodecError, MAX_MESSAGE_SIZE, MAX_NESTING};
+use crate::messages::{ChannelFormat, ControlOp, MessageType};
+
+/// Map keys holding vendor or application data, which strict decoding does not inspect.
+const EXTENSION_AREAS: [&str; 5] = [
+    "extensions",
+    "vendor_extensions",
+    "metadata",
+    "payload",
+    "groups",
+];
+
+/// Field whose value decides how the rest of a message is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CriticalField {
+    MessageType,
+    ChannelFormat,
+    Op,
+}
+
+impl CriticalField {
+    /// Map key the field is carried under.
+    pub fn key(self) -> &'static str {
+        match self {
+            CriticalField::MessageType => "type",
+            CriticalField::ChannelFormat => "channel_format",
+            CriticalField::Op => "op",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        [
+            CriticalField::MessageType,
+            CriticalField::ChannelFormat,
+            CriticalField::Op,
+        ]
+        .into_iter()
+        .find(|field| field.key() == key)
+    }
+
+    fn knows(self, value: &str) -> bool {
+        let value = Value::Text(value.into());
+        match self {
+            CriticalField::MessageType => value.deserialized::<MessageType>().is_ok(),
+            CriticalField::ChannelFormat => value.deserialized::<ChannelFormat>().is_ok(),
+            CriticalField::Op => value.deserialized::<ControlOp>().is_ok(),
+        }
+    }
+}
+
+impl fmt::Display for CriticalField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+/// How a receiver treats values it does not recognise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodePolicy {
+    /// Decodes like [`super::decode_untrusted`]: unknown fields are ignored, and an
+    /// unknown value fails as [`CodecError::Decode`] only where the target type reads it.
+    #[default]
+    Lenient,
+    /// Also fails with [`CodecError::UnknownValue`] on a critical field holding a value
+    /// this implementation does not know, in any map of the message and whether or not
+    /// the target type reads it. Unknown fields are still ignored, and extension areas
+    /// such as `extensions`, `metadata`, and control payloads are not inspected at all.
+    Strict,
+}
+
+impl DecodePolicy {
+    /// Decodes a payload received from an untrusted peer under this policy.
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, CodecError> {
+        self.decode_within(bytes, MAX_MESSAGE_SIZE)
+    }
+
+    /// Like [`DecodePolicy::decode`] with `max_size` in place of the datagram limit.
+    pub fn decode_within<T: DeserializeOwned>(
+        self,
+        bytes: &[u8],
+        max_size: usize,
+    ) -> Result<T, CodecError> {
+        if self == DecodePolicy::Strict && bytes.len() <= max_size {
+            check(bytes)?;
+        }
+        decode_untrusted_within(bytes, max_size)
+    }
+}
+
+/// Fails on the first unknown critical value; malformed input is left to the typed
+/// decode, which reports it.
+fn check(bytes: &[u8]) -> Result<(), CodecError> {
+    match (Scanner { bytes, at: 0 }).item(0, true) {
+        Err(Stop::Unknown(err)) => Err(err),
+        _ => Ok(()),
+    }
+}
+
+enum Stop {
+    Unknown(CodecError),
+    Malformed,
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn take(&mut self, len: u64) -> Result<&'a [u8], Stop> {
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| self.at.checked_add(len))
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(Stop::Malformed)?;
+        let taken = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(taken)
+    }
+
+    /// Reads an item's major type and argument; the argument is `None` for indefinite
+    /// lengths and breaks.
+    fn head(&mut self) -> Result<(u8, Option<u64>), Stop> {
+        let initial = self.take(1)?[0];
+        let info = initial & 0x1f;
+        let argument = match info {
+            0..=23 => Some(u64::from(info)),
+            24..=27 => Some(
+                self.take(1 << (info - 24))?
+                    .iter()
+                    .fold(0, |acc, byte| acc << 8 | u64::from(*byte)),
+            ),
+            31 => None,
+            _ => return Err(Stop::Malformed),
+        };
+        Ok((initial >> 5, argument))
+    }
+
+    /// Walks one item, checking critical fields of the maps in it unless `checked` is
+    /// false.
+    fn item(&mut self, depth: usize, checked: bool) -> Result<(), Stop> {
+        if depth > MAX_NESTING {
+            return Err(Stop::Malformed);
+        }
+        match self.head()? {
+            (0 | 1, Some(_)) | (7, Some(_)) => Ok(()),
+            (2 | 3, Some(len)) => self.take(len).map(drop),
+            (2 | 3, None) => self.until_break(|scanner| scanner.item(depth + 1, false)),
+            (4, Some(len)) => (0..len).try_for_each(|_| self.item(depth + 1, checked)),
+            (4, None) => self.until_break(|scanner| scanner.item(depth + 1, checked)),
+            (5, Some(len)) => (0..len).try_for_each(|_| self.entry(depth + 1, checked)),
+            (5, None) => self.until_break(|scanner| scanner.entry(depth + 1, checked)),
+            (6, Some(_)) => self.item(depth + 1, checked),
+            _ => Err(Stop::Malformed),
+        }
+    }
+
+    fn until_break(
+        &mut self,
+        mut each: impl FnMut(&mut Self) -> Result<(), Stop>,
+    ) -> Result<(), Stop> {
+        loop {
+            match self.bytes.get(self.at) {
+                Some(0xff) => {
+                    self.at += 1;
+                    return Ok(());
+                }
+                Some(_) => each(self)?,
+                None => return Err(Stop::Malformed),
+            }
+        }
+    }
+
+    /// Reads a definite-length text string, or rewinds and returns `None` for any other
+    /// item.
+    fn text(&mut self) -> Result<Option<&'a str>, Stop> {
+        let start = self.at;
+        if let (3, Some(len)) = self.head()? {
+            let text = self.take(len)?;
+            return core::str::from_utf8(text)
+                .map(Some)
+                .map_err(|_| Stop::Malformed);
+        }
+        self.at = start;
+        Ok(None)
+    }
+
+    fn entry(&mut self, depth: usize, checked: bool) -> Result<(), Stop> {
+        let Some(key) = self.text()? else {
+            self.item(depth, false)?;
+            return self.item(depth, checked);
+        };
+        if EXTENSION_AREAS.contains(&key) {
+            return self.item(depth, false);
+        }
+        let Some(field) = CriticalField::from_key(key).filter(|_| checked) else {
+            return self.item(depth, checked);
+        };
+        let offset = self.at;
+        match self.text()? {
+            Some(value) if !field.knows(value) => Err(Stop::Unknown(CodecError::UnknownValue {
+                field,
+                value: String::from(value),
+                offset,
+            })),
+            Some(_) => Ok(()),
+            None => self.item(depth, checked),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::to_vec;
+    use crate::handshake::HandshakeMessage;
+    use crate::messages::{ControlEnvelope, FrameEnvelope};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use ciborium::cbor;
+    use uuid::Uuid;
+
+    fn frame() -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::nil(),
+            universe: 1,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format: ChannelFormat::U8,
+            channels: vec![0, 128, 255].into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        }
+    }
+
+    /// Sets `key` of `map` to `value`, appending the entry when absent.
+    fn with_entry(map: &mut Vec<(Value, Value)>, key: &str, value: Value) {
+        match map.iter_mut().find(|(k, _)| k.as_text() == Some(key)) {
+            Some(entry) => entry.1 = value,
+            None => map.push((Value::from(key), value)),
+        }
+    }
+
+    fn encoded_frame(edit: impl FnOnce(&mut Vec<(Value, Value)>)) -> Vec<u8> {
+        let mut value = Value::serialized(&frame()).unwrap();
+        edit(value.as_map_mut().unwrap());
+        to_vec(&value).unwrap()
+    }
+
+    #[test]
+    fn strict_reports_unknown_channel_format_with_offset() {
+        let bytes = encoded_frame(|map| with_entry(map, "channel_format", "u12".into()));
+        match DecodePolicy::Strict.decode::<FrameEnvelope>(&bytes) {
+            Err(CodecError::UnknownValue {
+                field,
+                value,
+                offset,
+            }) => {
+                assert_eq!(
+                    (field, value.as_str()),
+                    (CriticalField::ChannelFormat, "u12")
+                );
+                assert_eq!(&bytes[offset..offset + 4], b"\x63u12");
+            }
+            other => panic!("expected unknown channel_format, got {:?}", other),
+        }
+        assert!(matches!(
+            DecodePolicy::Lenient.decode::<FrameEnvelope>(&bytes),
+            Err(CodecError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn strict_checks_fields_the_target_does_not_read() {
+        let control = HandshakeMessage::Control(ControlEnvelope {
+            message_type: MessageType::AlpineControl,
+            session_id: Uuid::nil(),
+            seq: 1,
+            op: ControlOp::Identify,
+            payload: cbor!({}).unwrap(),
+            mac: vec![0; 16],
+        });
+        let mut value = Value::serialized(&control).unwrap();
+        let inner = &mut value.as_map_mut().unwrap()[0].1;
+        with_entry(inner.as_map_mut().unwrap(), "op", "self_destruct".into());
+        let bytes = to_vec(&value).unwrap();
+
+        // A receiver that only peeks at the envelope would accept it leniently.
+        assert!(DecodePolicy::Lenient.decode::<Value>(&bytes).is_ok());
+        match DecodePolicy::Strict.decode::<Value>(&bytes) {
+            Err(CodecError::UnknownValue { field, offset, .. }) => {
+                assert_eq!(field, CriticalField::Op);
+                assert_eq!(&bytes[offset + 1..offset + 14], b"self_destruct");
+            }
+            other => panic!("expected unknown op, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn strict_ignores_unknown_fields_and_extension_areas() {
+        let bytes = encoded_frame(|map| {
+            with_entry(map, "future_field", cbor!({"level" => 3}).unwrap());
+            with_entry(
+                map,
+                "extensions",
+                cbor!({"org.example" => {"type" => "vendor_thing"}}).unwrap(),
+            );
+            with_entry(
+                map,
+                "metadata",
+                cbor!({"cue" => {"channel_format" => "u12"}}).unwrap(),
+            );
+        });
+        let decoded: FrameEnvelope = DecodePolicy::Strict.decode(&bytes).unwrap();
+        assert_eq!(decoded.channels[..], [0, 128, 255]);
+        assert!(decoded.extensions.is_some());
+    }
+
+    #[test]
+    fn strict_leaves_malformed_input_to_the_decoder() {
+        let bytes = encoded_frame(|_| {});
+        assert!(matches!(
+            DecodePolicy::Strict.decode::<FrameEnvelope>(&bytes[..bytes.len() - 1]),
+            Err(CodecError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+//! Persistent device configuration.
+//!
+//! Settings a node must keep across power cycles, changed over the control plane and saved
+//! through a [`ConfigStore`]. Fields default when absent, so configs written by older
+//! firmware still load. Subsystems share one [`SharedConfig`] so their updates never
+//! overwrite each other.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::codec;
+use crate::groups::ChannelGroup;
+use crate::merge::MergePolicy;
+use crate::messages::{GroupId, PortId, UniverseId};
+
+/// Device settings persisted across restarts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct DeviceConfig {
+    /// Merge policy per universe; unlisted universes use [`MergePolicy::default`].
+    #[serde(default)]
+    pub merge: BTreeMap<UniverseId, MergePolicy>,
+    /// Channel groups frames may reference by id.
+    #[serde(default)]
+    pub groups: BTreeMap<GroupId, ChannelGroup>,
+    /// Output port per universe, where it differs from the default; see [`crate::ports`].
+    #[serde(default)]
+    pub ports: BTreeMap<UniverseId, PortId>,
+}
+
+/// Failures reading or writing the device config.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("config storage failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("config encoding failed: {0}")]
+    Codec(String),
+}
+
+/// Persistence for the device config.
+pub trait ConfigStore: Send + Sync + std::fmt::Debug {
+    fn save(&self, config: &DeviceConfig) -> Result<(), ConfigError>;
+    fn load(&self) -> Result<DeviceConfig, ConfigError>;
+}
+
+/// Device config loaded from a store, shared by the subsystems that change it.
+#[derive(Debug, Clone)]
+pub struct SharedConfig {
+    store: Arc<dyn ConfigStore>,
+    config: Arc<Mutex<DeviceConfig>>,
+}
+
+impl SharedConfig {
+    /// Loads the config from `store`.
+    pub fn open(store: Arc<dyn ConfigStore>) -> Result<Self, ConfigError> {
+        let config = store.load()?;
+        Ok(Self {
+            store,
+            config: Arc::new(Mutex::new(config)),
+        })
+    }
+
+    /// Empty config kept in memory only.
+    pub fn memory() -> Self {
+        Self {
+            store: Arc::new(MemoryConfigStore::new()),
+            config: Arc::new(Mutex::new(DeviceConfig::default())),
+        }
+    }
+
+    /// Reads from the current config.
+    pub fn read<R>(&self, f: impl FnOnce(&DeviceConfig) -> R) -> R {
+        f(&self.config.lock())
+    }
+
+    /// Applies `f` and persists the result; the change is discarded if saving fails.
+    pub fn update(&self, f: impl FnOnce(&mut DeviceConfig)) -> Result<(), ConfigError> {
+        let mut config = self.config.lock();
+        let mut next = config.clone();
+        f(&mut next);
+        self.store.save(&next)?;
+        *config = next;
+        Ok(())
+    }
+}
+
+/// Process-local store; the config does not survive a restart.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryConfigStore {
+    config: Arc<Mutex<DeviceConfig>>,
+}
+
+impl MemoryConfigStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConfigStore for MemoryConfigStore {
+    fn save(&self, config: &DeviceConfig) -> Result<(), ConfigError> {
+        self.config.lock().clone_from(config);
+        Ok(())
+    }
+
+    fn load(&self) -> Result<DeviceConfig, ConfigError> {
+        Ok(self.config.lock().clone())
+    }
+}
+
+/// Stores the config as one CBOR file.
+#[derive(Debug, Clone)]
+pub struct FileConfigStore {
+    path: PathBuf,
+}
+
+impl FileConfigStore {
+    /// Uses the file at `path`, creating its directory on first save.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ConfigStore for FileConfigStore {
+    fn save(&self, config: &DeviceConfig) -> Result<(), ConfigError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let bytes = codec::to_vec(config).map_err(|e| ConfigError::Codec(e.to_string()))?;
+        // Write then rename so a power cut mid-save keeps the previous config.
+        let staging = self.path.with_extension("tmp");
+        fs::write(&staging, bytes)?;
+        fs::rename(staging, &self.path)?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<DeviceConfig, ConfigError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => {
+                codec::decode_untrusted(&bytes).map_err(|e| ConfigError::Codec(e.to_string()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(DeviceConfig::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
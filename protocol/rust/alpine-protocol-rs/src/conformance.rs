@@ -0,0 +1,565 @@
+//! Scripted conformance battery for node implementations.
+//!
+//! Vendors point [`ConformanceSuite`] at their node through a [`ConformanceTarget`] that
+//! opens fresh controller-side connections, and get back a [`ConformanceReport`] listing
+//! every check. The battery acts as a controller: it completes a normal handshake, then
+//! probes timeouts, replayed and out-of-order messages, capability mismatches, and (when
+//! a [`FrameTransport`] is supplied) frame delivery.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::json;
+use tokio::time;
+use uuid::Uuid;
+
+use crate::crypto::{KeyExchange, X25519KeyExchange};
+use crate::handshake::{
+    new_nonce, ChallengeAuthenticator, HandshakeContext, HandshakeError, HandshakeMessage,
+    HandshakeTransport,
+};
+use crate::messages::{
+    CapabilitySet, ChannelFormat, DeviceIdentity, ErrorCode, ErrorEnvelope, MessageType,
+    SessionInit, SessionReady, SessionRejected,
+};
+use crate::profile::StreamProfile;
+use crate::session::AlnpSession;
+use crate::stream::{AlnpStream, FrameTransport};
+
+/// Node under test, reached through the vendor's own transports.
+#[async_trait]
+pub trait ConformanceTarget: Send {
+    type Transport: HandshakeTransport + Send;
+
+    /// Opens a fresh controller-side connection to the node.
+    async fn connect(&mut self) -> Result<Self::Transport, HandshakeError>;
+
+    /// Frame transport towards the node; the frame checks are skipped without one.
+    fn frame_transport(&mut self) -> Option<Box<dyn FrameTransport>> {
+        None
+    }
+}
+
+/// Controller parameters used while probing the node.
+#[derive(Debug, Clone)]
+pub struct ConformanceConfig {
+    pub identity: DeviceIdentity,
+    /// Capabilities requested by the well-behaved checks; must be supported by the node.
+    pub capabilities: CapabilitySet,
+    /// Longest the node may take to answer a single handshake message.
+    pub response_timeout: Duration,
+}
+
+impl ConformanceConfig {
+    pub fn new(identity: DeviceIdentity) -> Self {
+        Self {
+            identity,
+            capabilities: CapabilitySet::default(),
+            response_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Result of one conformance check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Pass,
+    Fail(String),
+    Skipped(String),
+}
+
+/// Named outcome recorded in a report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub outcome: CheckOutcome,
+}
+
+/// Outcome of a full conformance run.
+#[derive(Debug, Clone, Default)]
+pub struct ConformanceReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    /// True when no check failed; skipped checks do not count against the node.
+    pub fn passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|check| matches!(check.outcome, CheckOutcome::Fail(_)))
+    }
+
+    /// Returns the outcome of the named check, if it ran.
+    pub fn outcome(&self, name: &str) -> Option<&CheckOutcome> {
+        self.checks
+            .iter()
+            .find(|check| check.name == name)
+            .map(|check| &check.outcome)
+    }
+
+    fn record(&mut self, name: &'static str, outcome: CheckOutcome) {
+        self.checks.push(CheckResult { name, outcome });
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.outcome {
+                CheckOutcome::Pass => writeln!(f, "PASS {}", check.name)?,
+                CheckOutcome::Fail(why) => writeln!(f, "FAIL {}: {}", check.name, why)?,
+                CheckOutcome::Skipped(why) => writeln!(f, "SKIP {}: {}", check.name, why)?,
+            }
+        }
+        write!(
+            f,
+            "{}",
+            if self.passed() {
+                "node conforms"
+            } else {
+                "node does not conform"
+            }
+        )
+    }
+}
+
+/// Runs the conformance battery against a node.
+pub struct ConformanceSuite<A> {
+    config: ConformanceConfig,
+    authenticator: Box<dyn Fn() -> A + Send + Sync>,
+}
+
+impl<A> ConformanceSuite<A>
+where
+    A: ChallengeAuthenticator + Send + Sync,
+{
+    /// `authenticator` builds the controller-side verifier for the node's signatures.
+    pub fn new(
+        config: ConformanceConfig,
+        authenticator: impl Fn() -> A + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            config,
+            authenticator: Box::new(authenticator),
+        }
+    }
+
+    /// Runs every check in order and returns the report.
+    pub async fn run<G: ConformanceTarget>(&self, target: &mut G) -> ConformanceReport {
+        let mut report = ConformanceReport::default();
+        let session = self.handshake_completes(target, &mut report).await;
+        let outcome = self.recovers_from_abandoned_handshake(target).await;
+        report.record("recovers_from_abandoned_handshake", outcome);
+        let outcome = self.rejects_out_of_order_messages(target).await;
+        report.record("rejects_out_of_order_messages", outcome);
+        let outcome = self.rejects_replayed_session_ready(target).await;
+        report.record("rejects_replayed_session_ready", outcome);
+        let outcome = self.rejects_capability_mismatch(target).await;
+        report.record("rejects_capability_mismatch", outcome);
+        let outcome = Self::streams_frames(target, session);
+        report.record("streams_frames", outcome);
+        report
+    }
+
+    /// Runs a controller handshake; four messages cross the wire, each within the
+    /// response deadline.
+    async fn connect_session<T: HandshakeTransport + Send>(
+        &self,
+        transport: &mut T,
+    ) -> Result<AlnpSession, HandshakeError> {
+        let deadline = self.config.response_timeout * 4;
+        let connect = AlnpSession::connect(
+            self.config.identity.clone(),
+            self.config.capabilities.clone(),
+            (self.authenticator)(),
+            X25519KeyExchange::new(),
+            HandshakeContext::default(),
+            transport,
+        );
+        time::timeout(deadline, connect)
+            .await
+            .map_err(|_| HandshakeError::Transport(format!("no session within {:?}", deadline)))?
+    }
+
+    /// Waits for the node's next message, or `None` if it stays silent or hangs up.
+    async fn reply<T: HandshakeTransport + Send>(
+        &self,
+        transport: &mut T,
+    ) -> Option<HandshakeMessage> {
+        match time::timeout(self.config.response_timeout, transport.recv()).await {
+            Ok(Ok(msg)) => Some(msg),
+            _ => None,
+        }
+    }
+
+    fn session_init(&self, requested: CapabilitySet) -> SessionInit {
+        SessionInit {
+            message_type: MessageType::SessionInit,
+            controller_nonce: new_nonce().to_vec(),
+            controller_pubkey: X25519KeyExchange::new().public_key(),
+            requested,
+            session_id: Uuid::new_v4(),
+            suites: Vec::new(),
+            controller_firmware_rev: None,
+            controller_priority: None,
+            role: None,
+        }
+    }
+
+    async fn handshake_completes<G: ConformanceTarget>(
+        &self,
+        target: &mut G,
+        report: &mut ConformanceReport,
+    ) -> Option<AlnpSession> {
+        let outcome = match target.connect().await {
+            Ok(mut transport) => match self.connect_session(&mut transport).await {
+                Ok(session) => {
+                    report.record("handshake_completes", CheckOutcome::Pass);
+                    return Some(session);
+                }
+                Err(err) => CheckOutcome::Fail(err.to_string()),
+            },
+            Err(err) => CheckOutcome::Fail(format!("connect: {}", err)),
+        };
+        report.record("handshake_completes", outcome);
+        None
+    }
+
+    /// A controller that vanishes mid-handshake must not wedge the node.
+    async fn recovers_from_abandoned_handshake<G: ConformanceTarget>(
+        &self,
+        target: &mut G,
+    ) -> CheckOutcome {
+        let mut abandoned = match target.connect().await {
+            Ok(transport) => transport,
+            Err(err) => return CheckOutcome::Fail(format!("connect: {}", err)),
+        };
+        let init = self.session_init(self.config.capabilities.clone());
+        if let Err(err) = abandoned.send(HandshakeMessage::SessionInit(init)).await {
+            return CheckOutcome::Fail(format!("send session_init: {}", err));
+        }
+        let _ = self.reply(&mut abandoned).await;
+        drop(abandoned);
+
+        let mut transport = match target.connect().await {
+            Ok(transport) => transport,
+            Err(err) => return CheckOutcome::Fail(format!("reconnect: {}", err)),
+        };
+        match self.connect_session(&mut transport).await {
+            Ok(_) => CheckOutcome::Pass,
+            Err(err) => CheckOutcome::Fail(format!("handshake after abandon: {}", err)),
+        }
+    }
+
+    /// A `session_ready` without a preceding `session_init` must be refused.
+    async fn rejects_out_of_order_messages<G: ConformanceTarget>(
+        &self,
+        target: &mut G,
+    ) -> CheckOutcome {
+        let mut transport = match target.connect().await {
+            Ok(transport) => transport,
+            Err(err) => return CheckOutcome::Fail(format!("connect: {}", err)),
+        };
+        let ready = SessionReady {
+            message_type: MessageType::SessionReady,
+            session_id: Uuid::new_v4(),
+            mac: vec![0; 16],
+        };
+        if let Err(err) = transport.send(HandshakeMessage::SessionReady(ready)).await {
+            return CheckOutcome::Fail(format!("send session_ready: {}", err));
+        }
+        match self.reply(&mut transport).await {
+            None | Some(HandshakeMessage::Error(_)) => CheckOutcome::Pass,
+            Some(other) => CheckOutcome::Fail(format!("node answered with {:?}", other)),
+        }
+    }
+
+    /// Replaying a recorded `session_init`/`session_ready` pair must not yield a session.
+    async fn rejects_replayed_session_ready<G: ConformanceTarget>(
+        &self,
+        target: &mut G,
+    ) -> CheckOutcome {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut recorder = match target.connect().await {
+            Ok(inner) => Recorder {
+                inner,
+                sent: sent.clone(),
+            },
+            Err(err) => return CheckOutcome::Fail(format!("connect: {}", err)),
+        };
+        if let Err(err) = self.connect_session(&mut recorder).await {
+            return CheckOutcome::Skipped(format!("no session to record: {}", err));
+        }
+        let recorded = sent.lock().map(|sent| sent.clone()).unwrap_or_default();
+        let (Some(init), Some(ready)) = (
+            recorded
+                .iter()
+                .find(|msg| matches!(msg, HandshakeMessage::SessionInit(_))),
+            recorded
+                .iter()
+                .find(|msg| matches!(msg, HandshakeMessage::SessionReady(_))),
+        ) else {
+            return CheckOutcome::Skipped("handshake was not recorded".into());
+        };
+
+        let mut transport = match target.connect().await {
+            Ok(transport) => transport,
+            Err(err) => return CheckOutcome::Fail(format!("connect: {}", err)),
+        };
+        if let Err(err) = transport.send(init.clone()).await {
+            return CheckOutcome::Fail(format!("replay session_init: {}", err));
+        }
+        match self.reply(&mut transport).await {
+            Some(HandshakeMessage::SessionAck(_)) => {}
+            None
+            | Some(HandshakeMessage::Error(_))
+            | Some(HandshakeMessage::SessionRejected(_)) => return CheckOutcome::Pass,
+            Some(other) => return CheckOutcome::Fail(format!("node answered with {:?}", other)),
+        }
+        if let Err(err) = transport.send(ready.clone()).await {
+            return CheckOutcome::Fail(format!("replay session_ready: {}", err));
+        }
+        match self.reply(&mut transport).await {
+            Some(HandshakeMessage::SessionComplete(complete)) if complete.ok => {
+                CheckOutcome::Fail("node accepted a replayed session_ready".into())
+            }
+            _ => CheckOutcome::Pass,
+        }
+    }
+
+    /// Requests the node cannot honour must be refused with `HANDSHAKE_CAPABILITY_MISMATCH`.
+    async fn rejects_capability_mismatch<G: ConformanceTarget>(
+        &self,
+        target: &mut G,
+    ) -> CheckOutcome {
+        let mut transport = match target.connect().await {
+            Ok(transport) => transport,
+            Err(err) => return CheckOutcome::Fail(format!("connect: {}", err)),
+        };
+        let requested = CapabilitySet {
+            channel_formats: vec![ChannelFormat::U8, ChannelFormat::U16],
+            max_channels: u32::MAX,
+            grouping_supported: true,
+            streaming_supported: true,
+            encryption_supported: true,
+            vendor_extensions: None,
+            compression: Vec::new(),
+            max_frame_rate: None,
+            min_frame_interval_us: None,
+            jitter_strategies: Vec::new(),
+            ports: None,
+        };
+        let init = self.session_init(requested);
+        if let Err(err) = transport.send(HandshakeMessage::SessionInit(init)).await {
+            return CheckOutcome::Fail(format!("send session_init: {}", err));
+        }
+        match self.reply(&mut transport).await {
+            Some(HandshakeMessage::Error(ErrorEnvelope { code, .. }))
+            | Some(HandshakeMessage::SessionRejected(SessionRejected { code, .. }))
+                if code == ErrorCode::HandshakeCapabilityMismatch =>
+            {
+                CheckOutcome::Pass
+            }
+            Some(other) => CheckOutcome::Fail(format!("node answered with {:?}", other)),
+            None => CheckOutcome::Fail("node sent no capability error".into()),
+        }
+    }
+
+    fn streams_frames<G: ConformanceTarget>(
+        target: &mut G,
+        session: Option<AlnpSession>,
+    ) -> CheckOutcome {
+        let Some(frames) = target.frame_transport() else {
+            return CheckOutcome::Skipped("no frame transport supplied".into());
+        };
+        let Some(session) = session else {
+            return CheckOutcome::Skipped("no established session".into());
+        };
+        let profile = match StreamProfile::auto().compile() {
+            Ok(profile) => profile,
+            Err(err) => return CheckOutcome::Fail(err.to_string()),
+        };
+        if let Err(err) = session.set_stream_profile(profile.clone()) {
+            return CheckOutcome::Fail(err.to_string());
+        }
+        session.mark_streaming();
+        let stream = AlnpStream::new(session, BoxedFrames(frames), profile);
+        for (universe, format, channels) in [
+            (0, ChannelFormat::U8, vec![0, 127, 255]),
+            (1, ChannelFormat::U16, vec![0, 32_768, 65_535]),
+        ] {
+            let metadata = Some([("conformance".to_string(), json!(true))].into());
+            if let Err(err) = stream.send_universe(universe, format, &channels, 100, None, metadata)
+            {
+                return CheckOutcome::Fail(format!("universe {}: {}", universe, err));
+            }
+        }
+        CheckOutcome::Pass
+    }
+}
+
+/// Handshake transport that remembers every message it sends.
+struct Recorder<T> {
+    inner: T,
+    sent: Arc<Mutex<Vec<HandshakeMessage>>>,
+}
+
+#[async_trait]
+impl<T: HandshakeTransport + Send> HandshakeTransport for Recorder<T> {
+    async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.push(msg.clone());
+        }
+        self.inner.send(msg).await
+    }
+
+    async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+        self.inner.recv().await
+    }
+}
+
+struct BoxedFrames(Box<dyn FrameTransport>);
+
+impl FrameTransport for BoxedFrames {
+    fn send_frame(&self, bytes: &[u8]) -> Result<(), String> {
+        self.0.send_frame(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handshake::server::ServerHandshake;
+    use crate::handshake::HandshakeParticipant;
+    use crate::session::StaticKeyAuthenticator;
+    use tokio::sync::mpsc;
+
+    struct Pipe {
+        tx: mpsc::Sender<HandshakeMessage>,
+        rx: mpsc::Receiver<HandshakeMessage>,
+    }
+
+    #[async_trait]
+    impl HandshakeTransport for Pipe {
+        async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+            self.tx
+                .send(msg)
+                .await
+                .map_err(|e| HandshakeError::Transport(e.to_string()))
+        }
+
+        async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+            self.rx
+                .recv()
+                .await
+                .ok_or_else(|| HandshakeError::Transport("closed".into()))
+        }
+    }
+
+    fn identity(name: &str) -> DeviceIdentity {
+        DeviceIdentity {
+            device_id: format!("{name}-1"),
+            manufacturer_id: "manu".into(),
+            model_id: "model".into(),
+            hardware_rev: "rev1".into(),
+            firmware_rev: "1.0.0".into(),
+        }
+    }
+
+    /// Spawns a reference node per connection, as a real listener would.
+    struct ReferenceNode;
+
+    #[async_trait]
+    impl ConformanceTarget for ReferenceNode {
+        type Transport = Pipe;
+
+        async fn connect(&mut self) -> Result<Pipe, HandshakeError> {
+            let (to_node, node_rx) = mpsc::channel(8);
+            let (node_tx, from_node) = mpsc::channel(8);
+            tokio::spawn(async move {
+                let server = ServerHandshake {
+                    identity: identity("node"),
+                    capabilities: CapabilitySet::default(),
+                    authenticator: StaticKeyAuthenticator::default(),
+                    key_exchange: X25519KeyExchange::new(),
+                    context: HandshakeContext::default(),
+                };
+                let mut pipe = Pipe {
+                    tx: node_tx,
+                    rx: node_rx,
+                };
+                let _ = server.run(&mut pipe).await;
+            });
+            Ok(Pipe {
+                tx: to_node,
+                rx: from_node,
+            })
+        }
+
+        fn frame_transport(&mut self) -> Option<Box<dyn FrameTransport>> {
+            struct Accepting;
+            impl FrameTransport for Accepting {
+                fn send_frame(&self, _bytes: &[u8]) -> Result<(), String> {
+                    Ok(())
+                }
+            }
+            Some(Box::new(Accepting))
+        }
+    }
+
+    fn suite() -> ConformanceSuite<StaticKeyAuthenticator> {
+        let mut config = ConformanceConfig::new(identity("controller"));
+        config.response_timeout = Duration::from_millis(200);
+        ConformanceSuite::new(config, StaticKeyAuthenticator::default)
+    }
+
+    #[tokio::test]
+    async fn reference_node_conforms() {
+        let report = suite().run(&mut ReferenceNode).await;
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.checks.len(), 6);
+        assert_eq!(report.outcome("streams_frames"), Some(&CheckOutcome::Pass));
+    }
+
+    /// Node that never answers: the handshake fails but silence is a valid rejection.
+    #[derive(Default)]
+    struct SilentNode {
+        held: Vec<Pipe>,
+    }
+
+    #[async_trait]
+    impl ConformanceTarget for SilentNode {
+        type Transport = Pipe;
+
+        async fn connect(&mut self) -> Result<Pipe, HandshakeError> {
+            let (tx, node_rx) = mpsc::channel(8);
+            let (node_tx, rx) = mpsc::channel(8);
+            self.held.push(Pipe {
+                tx: node_tx,
+                rx: node_rx,
+            });
+            Ok(Pipe { tx, rx })
+        }
+    }
+
+    #[tokio::test]
+    async fn silent_node_fails_handshake_checks() {
+        let report = suite().run(&mut SilentNode::default()).await;
+        assert!(!report.passed());
+        assert!(matches!(
+            report.outcome("handshake_completes"),
+            Some(CheckOutcome::Fail(_))
+        ));
+        assert_eq!(
+            report.outcome("rejects_out_of_order_messages"),
+            Some(&CheckOutcome::Pass)
+        );
+        assert!(matches!(
+            report.outcome("streams_frames"),
+            Some(CheckOutcome::Skipped(_))
+        ));
+    }
+}
@@ -1,14 +1,44 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::crypto::{compute_mac, verify_mac, SessionKeys};
+use crate::codec;
+use crate::crypto::nonce::{self, NonceLane, NonceSequence};
+use crate::crypto::{compute_mac_with_nonce, verify_mac_with_nonce, SessionKeys};
 use crate::handshake::HandshakeError;
-use crate::messages::{Acknowledge, ControlEnvelope, ControlOp, MessageType};
-use crate::{handshake::transport::ReliableControlChannel, handshake::HandshakeTransport};
+use crate::messages::{
+    Acknowledge, CapabilitySet, CapabilityUpdate, ControlEnvelope, ControlOp, ControlProgress,
+    ErrorCode, ErrorEnvelope, MessageType, MirroredOutput, SessionClose, SessionPreempted,
+};
+use crate::mirror::MirrorSlice;
+use crate::session::migration::{MigrationTicket, SessionSnapshot};
+use crate::session::AlnpSession;
+use crate::{
+    handshake::transport::{
+        ControlDedup, Delivery, PendingControl, ReliableControlChannel, RetryPolicy,
+    },
+    handshake::HandshakeMessage,
+    handshake::HandshakeTransport,
+};
+use parking_lot::{Mutex, RwLock};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::json;
+use thiserror::Error;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
+/// First sequence a device uses for [`CapabilityUpdate`]s and [`SessionPreempted`]
+/// notices, which count up together.
+///
+/// Updates share the control key and nonce lane with acks, so they number from the top
+/// half of the sequence space, which controller envelopes never reach.
+pub const CAPABILITY_UPDATE_SEQ_BASE: u64 = 1 << 63;
+
 /// Signs and verifies control envelopes using the derived session keys.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ControlCrypto {
     keys: SessionKeys,
 }
@@ -18,28 +48,35 @@ impl ControlCrypto {
         Self { keys }
     }
 
-    pub fn mac_for_payload(
+    /// MAC of `payload` with nonce `seq` in `lane`; see [`crate::crypto::nonce`].
+    ///
+    /// The MAC covers the canonical CBOR encoding of `payload`.
+    pub fn mac_for_payload<P: Serialize + ?Sized>(
         &self,
+        lane: NonceLane,
         seq: u64,
         session_id: &Uuid,
-        payload: &serde_json::Value,
+        payload: &P,
     ) -> Result<Vec<u8>, HandshakeError> {
-        let bytes = serde_cbor::to_vec(payload)
-            .map_err(|e| HandshakeError::Protocol(format!("payload encode: {}", e)))?;
-        compute_mac(&self.keys, seq, &bytes, session_id.as_bytes())
+        let bytes = codec::to_canonical_vec(payload)
+            .map_err(|e| HandshakeError::Protocol(format!("payload {}", e)))?;
+        let nonce = nonce::nonce(nonce::nonce_salt(session_id.as_bytes(), lane), seq);
+        compute_mac_with_nonce(&self.keys, &nonce, &bytes, session_id.as_bytes())
             .map_err(|e| HandshakeError::Authentication(e.to_string()))
     }
 
-    pub fn verify_mac(
+    pub fn verify_mac<P: Serialize + ?Sized>(
         &self,
+        lane: NonceLane,
         seq: u64,
         session_id: &Uuid,
-        payload: &serde_json::Value,
+        payload: &P,
         mac: &[u8],
     ) -> Result<(), HandshakeError> {
-        let bytes = serde_cbor::to_vec(payload)
-            .map_err(|e| HandshakeError::Protocol(format!("payload encode: {}", e)))?;
-        if verify_mac(&self.keys, seq, &bytes, session_id.as_bytes(), mac) {
+        let bytes = codec::to_canonical_vec(payload)
+            .map_err(|e| HandshakeError::Protocol(format!("payload {}", e)))?;
+        let nonce = nonce::nonce(nonce::nonce_salt(session_id.as_bytes(), lane), seq);
+        if verify_mac_with_nonce(&self.keys, &nonce, &bytes, session_id.as_bytes(), mac) {
             Ok(())
         } else {
             Err(HandshakeError::Authentication(
@@ -49,12 +86,104 @@ impl ControlCrypto {
     }
 }
 
+/// Rejected [`RateLimit`] values.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RateLimitError {
+    #[error("{0} must be greater than zero")]
+    Zero(&'static str),
+}
+
+/// Token-bucket limit on control envelopes: `burst` envelopes at once, refilled at
+/// `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    per_second: u32,
+    burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(per_second: u32, burst: u32) -> Result<Self, RateLimitError> {
+        if per_second == 0 {
+            return Err(RateLimitError::Zero("rate"));
+        }
+        if burst == 0 {
+            return Err(RateLimitError::Zero("burst"));
+        }
+        Ok(Self { per_second, burst })
+    }
+
+    pub fn per_second(&self) -> u32 {
+        self.per_second
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+}
+
+/// Running state of a [`RateLimit`].
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled: now,
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    pub(crate) fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        let rate = f64::from(self.limit.per_second);
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.limit.burst));
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// Client-side pacing: an optional self-imposed limit plus any hold requested by the
+/// responder.
+#[derive(Debug, Default)]
+struct Pacing {
+    bucket: Option<TokenBucket>,
+    hold_until: Option<Instant>,
+}
+
+impl Pacing {
+    fn wait(&mut self, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.hold_until {
+            if until > now {
+                return Some(until - now);
+            }
+            self.hold_until = None;
+        }
+        self.bucket.as_mut()?.try_take(now).err()
+    }
+}
+
 /// Control-plane client helper to build authenticated envelopes and handle acks.
 #[derive(Debug)]
 pub struct ControlClient {
     pub device_id: Uuid,
     pub crypto: ControlCrypto,
     pub session_id: Uuid,
+    pacing: Mutex<Pacing>,
+    /// Sequence of the last capability update or preemption notice accepted.
+    capability_seq: Mutex<Option<u64>>,
+    /// Sequence of the last mirrored output accepted.
+    mirror_seq: Mutex<Option<u64>>,
 }
 
 impl ControlClient {
@@ -63,18 +192,60 @@ impl ControlClient {
             device_id,
             crypto,
             session_id,
+            pacing: Mutex::new(Pacing::default()),
+            capability_seq: Mutex::new(None),
+            mirror_seq: Mutex::new(None),
         }
     }
 
-    pub fn envelope(
+    /// Paces sends to `limit`, so the controller stays within what the node accepts.
+    ///
+    /// Without a limit the client still honours `CONTROL_RATE_LIMITED` replies by holding
+    /// further sends for the requested `retry_after_ms`.
+    pub fn with_rate_limit(self, limit: RateLimit) -> Self {
+        self.pacing.lock().bucket = Some(TokenBucket::new(limit, Instant::now()));
+        self
+    }
+
+    /// Waits until pacing allows another envelope.
+    async fn pace(&self) {
+        loop {
+            let wait = self.pacing.lock().wait(Instant::now());
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+
+    /// Holds further sends when the responder asked the controller to slow down.
+    fn observe<A>(&self, result: &Result<A, HandshakeError>) {
+        if let Err(HandshakeError::Remote(error)) = result {
+            if error.code == ErrorCode::ControlRateLimited {
+                let hold = Duration::from_millis(error.retry_after_ms.unwrap_or(100));
+                self.pacing.lock().hold_until = Some(Instant::now() + hold);
+            }
+        }
+    }
+
+    /// Builds an authenticated envelope for `op`.
+    ///
+    /// `payload` may be a typed struct, a [`ControlValue`](crate::messages::ControlValue), or a `serde_json::Value`; all
+    /// are carried and authenticated as CBOR.
+    pub fn envelope<P: Serialize>(
         &self,
         seq: u64,
         op: ControlOp,
-        payload: serde_json::Value,
+        payload: P,
     ) -> Result<ControlEnvelope, HandshakeError> {
-        let mac = self
-            .crypto
-            .mac_for_payload(seq, &self.session_id, &payload)?;
+        let payload = codec::to_value(&payload)
+            .map_err(|e| HandshakeError::Protocol(format!("payload {}", e)))?;
+        let mac = self.crypto.mac_for_payload(
+            NonceLane::ControlRequest,
+            seq,
+            &self.session_id,
+            &payload,
+        )?;
         Ok(ControlEnvelope {
             message_type: MessageType::AlpineControl,
             session_id: self.session_id,
@@ -85,15 +256,199 @@ impl ControlClient {
         })
     }
 
-    pub async fn send<T: HandshakeTransport + Send>(
+    /// Builds a `Migrate` envelope carrying a ticket issued by the outgoing controller.
+    pub fn migration_envelope(
+        &self,
+        seq: u64,
+        ticket: &MigrationTicket,
+    ) -> Result<ControlEnvelope, HandshakeError> {
+        self.envelope(seq, ControlOp::Migrate, ticket)
+    }
+
+    pub async fn send<T: HandshakeTransport + Send, P: Serialize + Send>(
+        &self,
+        channel: &mut ReliableControlChannel<T>,
+        op: ControlOp,
+        payload: P,
+    ) -> Result<Acknowledge, HandshakeError> {
+        self.pace().await;
+        let seq = channel.next_seq()?;
+        let env = self.envelope(seq, op, payload)?;
+        let result = channel.send_reliable(env).await;
+        self.observe(&result);
+        result
+    }
+
+    /// Like [`ControlClient::send`], retrying under `policy` instead of the channel's
+    /// policy for `op`.
+    pub async fn send_with<T: HandshakeTransport + Send, P: Serialize + Send>(
         &self,
         channel: &mut ReliableControlChannel<T>,
         op: ControlOp,
-        payload: serde_json::Value,
+        payload: P,
+        policy: RetryPolicy,
     ) -> Result<Acknowledge, HandshakeError> {
-        let seq = channel.next_seq();
+        self.pace().await;
+        let seq = channel.next_seq()?;
+        let env = self.envelope(seq, op, payload)?;
+        let result = channel.send_reliable_with(env, policy).await;
+        self.observe(&result);
+        result
+    }
+
+    /// Sends a long-running operation and returns the in-flight call, which yields the
+    /// device's progress updates before the final ack.
+    pub async fn call<'a, T: HandshakeTransport + Send, P: Serialize + Send>(
+        &self,
+        channel: &'a mut ReliableControlChannel<T>,
+        op: ControlOp,
+        payload: P,
+    ) -> Result<PendingControl<'a, T>, HandshakeError> {
+        self.pace().await;
+        let seq = channel.next_seq()?;
         let env = self.envelope(seq, op, payload)?;
-        channel.send_reliable(env).await
+        Ok(channel.start(env))
+    }
+
+    /// Builds an authenticated [`SessionClose`]; `seq` is reserved like an envelope's.
+    pub fn close_notice(
+        &self,
+        seq: u64,
+        reason: Option<String>,
+    ) -> Result<SessionClose, HandshakeError> {
+        let payload = json!({"reason": reason});
+        let mac = self.crypto.mac_for_payload(
+            NonceLane::ControlRequest,
+            seq,
+            &self.session_id,
+            &payload,
+        )?;
+        Ok(SessionClose {
+            message_type: MessageType::AlpineSessionClose,
+            session_id: self.session_id,
+            seq,
+            reason,
+            mac,
+        })
+    }
+
+    /// Tells the device the session is over. Sent once; the device does not answer.
+    pub async fn close<T: HandshakeTransport + Send>(
+        &self,
+        channel: &mut ReliableControlChannel<T>,
+        reason: Option<String>,
+    ) -> Result<(), HandshakeError> {
+        let seq = channel.next_seq()?;
+        let notice = self.close_notice(seq, reason)?;
+        channel.notify(HandshakeMessage::SessionClose(notice)).await
+    }
+
+    /// Authenticates a capability update pushed by the device and applies it to `session`,
+    /// returning the capabilities it replaced.
+    ///
+    /// Updates for another session, with a bad MAC, or not newer than the last one
+    /// accepted are refused, so a replayed update cannot roll limits back.
+    pub fn accept_capability_update(
+        &self,
+        update: &CapabilityUpdate,
+        session: &AlnpSession,
+    ) -> Result<CapabilitySet, HandshakeError> {
+        if update.session_id != self.session_id {
+            return Err(HandshakeError::Authentication(
+                "capability update for another session".into(),
+            ));
+        }
+        let mut last = self.capability_seq.lock();
+        if update.seq < CAPABILITY_UPDATE_SEQ_BASE || last.is_some_and(|last| update.seq <= last) {
+            return Err(HandshakeError::Protocol(format!(
+                "stale capability update {}",
+                update.seq
+            )));
+        }
+        let payload = json!({"capabilities": update.capabilities, "reason": update.reason});
+        self.crypto.verify_mac(
+            NonceLane::ControlReply,
+            update.seq,
+            &update.session_id,
+            &payload,
+            &update.mac,
+        )?;
+        let previous = session.update_capabilities(update.capabilities.clone())?;
+        *last = Some(update.seq);
+        Ok(previous)
+    }
+
+    /// Authenticates a notice that a higher-priority controller is taking the session
+    /// over, returning how long the session keeps streaming.
+    ///
+    /// Notices are refused like capability updates: for another session, with a bad MAC,
+    /// or not newer than the last update or notice accepted.
+    pub fn accept_preemption(&self, notice: &SessionPreempted) -> Result<Duration, HandshakeError> {
+        if notice.session_id != self.session_id {
+            return Err(HandshakeError::Authentication(
+                "preemption notice for another session".into(),
+            ));
+        }
+        let mut last = self.capability_seq.lock();
+        if notice.seq < CAPABILITY_UPDATE_SEQ_BASE || last.is_some_and(|last| notice.seq <= last) {
+            return Err(HandshakeError::Protocol(format!(
+                "stale preemption notice {}",
+                notice.seq
+            )));
+        }
+        let payload = json!({"priority": notice.priority, "grace_ms": notice.grace_ms});
+        self.crypto.verify_mac(
+            NonceLane::ControlReply,
+            notice.seq,
+            &notice.session_id,
+            &payload,
+            &notice.mac,
+        )?;
+        *last = Some(notice.seq);
+        Ok(Duration::from_millis(notice.grace_ms))
+    }
+
+    /// Authenticates output the device mirrored to this observer session.
+    ///
+    /// Mirrored output has a sequence of its own; output for another session, with a bad
+    /// MAC, or not newer than the last accepted is refused, so a late datagram never
+    /// rolls a visualizer back.
+    pub fn accept_mirrored_output(
+        &self,
+        output: &MirroredOutput,
+    ) -> Result<MirrorSlice, HandshakeError> {
+        if output.session_id != self.session_id {
+            return Err(HandshakeError::Authentication(
+                "mirrored output for another session".into(),
+            ));
+        }
+        let mut last = self.mirror_seq.lock();
+        if last.is_some_and(|last| output.seq <= last) {
+            return Err(HandshakeError::Protocol(format!(
+                "stale mirrored output {}",
+                output.seq
+            )));
+        }
+        let payload = json!({
+            "universe": output.universe,
+            "applied_us": output.applied_us,
+            "start": output.start,
+            "levels": output.levels,
+        });
+        self.crypto.verify_mac(
+            NonceLane::OutputMirror,
+            output.seq,
+            &output.session_id,
+            &payload,
+            &output.mac,
+        )?;
+        *last = Some(output.seq);
+        Ok(MirrorSlice {
+            universe: output.universe,
+            applied_us: output.applied_us,
+            start: output.start,
+            levels: output.levels.clone(),
+        })
     }
 
     pub fn now_ms() -> u64 {
@@ -108,16 +463,91 @@ impl ControlClient {
 pub struct ControlResponder {
     pub crypto: ControlCrypto,
     pub session_id: Uuid,
+    /// Numbers progress updates across all envelopes, since one envelope may get many.
+    progress: Mutex<NonceSequence>,
+    /// Numbers mirrored output, which is sent far more often than acks.
+    mirror: Mutex<NonceSequence>,
 }
 
 impl ControlResponder {
     pub fn new(session_id: Uuid, crypto: ControlCrypto) -> Self {
-        Self { crypto, session_id }
+        Self {
+            crypto,
+            session_id,
+            progress: Mutex::new(NonceSequence::new(
+                session_id.as_bytes(),
+                NonceLane::ControlProgress,
+            )),
+            mirror: Mutex::new(NonceSequence::new(
+                session_id.as_bytes(),
+                NonceLane::OutputMirror,
+            )),
+        }
     }
 
     pub fn verify(&self, env: &ControlEnvelope) -> Result<(), HandshakeError> {
-        self.crypto
-            .verify_mac(env.seq, &env.session_id, &env.payload, &env.mac)
+        self.crypto.verify_mac(
+            NonceLane::ControlRequest,
+            env.seq,
+            &env.session_id,
+            &env.payload,
+            &env.mac,
+        )
+    }
+
+    /// Authenticates a controller's [`SessionClose`] for this session.
+    pub fn verify_close(&self, notice: &SessionClose) -> Result<(), HandshakeError> {
+        if notice.session_id != self.session_id {
+            return Err(HandshakeError::Authentication(
+                "session close for another session".into(),
+            ));
+        }
+        self.crypto.verify_mac(
+            NonceLane::ControlRequest,
+            notice.seq,
+            &notice.session_id,
+            &json!({"reason": notice.reason}),
+            &notice.mac,
+        )
+    }
+
+    /// Validates a `Migrate` envelope and takes over the state of `previous`.
+    ///
+    /// The ticket must open with the previous session's keys. On success the snapshot is
+    /// applied to `next`, carrying over its profile, control sequence, and frame
+    /// priorities, the previous session is closed, and the snapshot is returned.
+    pub fn accept_migration(
+        &self,
+        env: &ControlEnvelope,
+        previous: &AlnpSession,
+        next: &AlnpSession,
+    ) -> Result<SessionSnapshot, HandshakeError> {
+        self.verify(env)?;
+        if env.op != ControlOp::Migrate {
+            return Err(HandshakeError::Protocol(format!(
+                "expected migrate op, got {:?}",
+                env.op
+            )));
+        }
+        let ticket: MigrationTicket = env
+            .payload_as()
+            .map_err(|e| HandshakeError::Protocol(format!("ticket decode: {}", e)))?;
+        let previous_id = previous
+            .established()
+            .map(|established| established.session_id)
+            .ok_or_else(|| HandshakeError::Protocol("previous session not established".into()))?;
+        if ticket.from_session != previous_id {
+            return Err(HandshakeError::Authentication(
+                "migration ticket issued for another session".into(),
+            ));
+        }
+        let keys = previous
+            .keys()
+            .ok_or_else(|| HandshakeError::Protocol("previous session keys missing".into()))?;
+        let snapshot = ticket.open(&keys, ControlClient::now_ms())?;
+        snapshot.apply(next)?;
+        previous.close();
+        Ok(snapshot)
     }
 
     pub fn ack(
@@ -127,9 +557,12 @@ impl ControlResponder {
         detail: Option<String>,
     ) -> Result<Acknowledge, HandshakeError> {
         let payload = json!({"ok": ok, "detail": detail});
-        let mac = self
-            .crypto
-            .mac_for_payload(seq, &self.session_id, &payload)?;
+        let mac = self.crypto.mac_for_payload(
+            NonceLane::ControlReply,
+            seq,
+            &self.session_id,
+            &payload,
+        )?;
         Ok(Acknowledge {
             message_type: MessageType::AlpineControlAck,
             session_id: self.session_id,
@@ -139,4 +572,720 @@ impl ControlResponder {
             mac,
         })
     }
+
+    /// Builds an authenticated progress update for control envelope `seq`.
+    ///
+    /// `percent` is clamped to 100. Fails once the session's progress nonces run out.
+    pub fn progress(
+        &self,
+        seq: u64,
+        percent: Option<u8>,
+        detail: Option<String>,
+    ) -> Result<ControlProgress, HandshakeError> {
+        let percent = percent.map(|p| p.min(100));
+        let payload = json!({"percent": percent, "detail": detail});
+        let (update, _) = self
+            .progress
+            .lock()
+            .next_nonce()
+            .map_err(|e| HandshakeError::Protocol(e.to_string()))?;
+        let mac = self.crypto.mac_for_payload(
+            NonceLane::ControlProgress,
+            update,
+            &self.session_id,
+            &payload,
+        )?;
+        Ok(ControlProgress {
+            message_type: MessageType::AlpineControlProgress,
+            session_id: self.session_id,
+            seq,
+            update,
+            percent,
+            detail,
+            mac,
+        })
+    }
+
+    /// Builds an authenticated capability update; `seq` counts up from
+    /// [`CAPABILITY_UPDATE_SEQ_BASE`] for each update sent on the session.
+    pub fn capability_update(
+        &self,
+        seq: u64,
+        capabilities: CapabilitySet,
+        reason: Option<String>,
+    ) -> Result<CapabilityUpdate, HandshakeError> {
+        let payload = json!({"capabilities": capabilities, "reason": reason});
+        let mac = self.crypto.mac_for_payload(
+            NonceLane::ControlReply,
+            seq,
+            &self.session_id,
+            &payload,
+        )?;
+        Ok(CapabilityUpdate {
+            message_type: MessageType::AlpineCapabilityUpdate,
+            session_id: self.session_id,
+            seq,
+            capabilities,
+            reason,
+            mac,
+        })
+    }
+
+    /// Builds an authenticated notice that a controller of `priority` is taking the
+    /// session over in `grace`; `seq` comes from the same counter as capability updates.
+    pub fn preemption_notice(
+        &self,
+        seq: u64,
+        priority: u8,
+        grace: Duration,
+    ) -> Result<SessionPreempted, HandshakeError> {
+        let grace_ms = grace.as_millis() as u64;
+        let payload = json!({"priority": priority, "grace_ms": grace_ms});
+        let mac = self.crypto.mac_for_payload(
+            NonceLane::ControlReply,
+            seq,
+            &self.session_id,
+            &payload,
+        )?;
+        Ok(SessionPreempted {
+            message_type: MessageType::AlpineSessionPreempted,
+            session_id: self.session_id,
+            seq,
+            priority,
+            grace_ms,
+            mac,
+        })
+    }
+
+    /// Builds authenticated mirrored output for an observer session, numbered in the
+    /// session's output mirror lane. Fails once that lane's nonces run out.
+    pub fn mirrored_output(&self, slice: &MirrorSlice) -> Result<MirroredOutput, HandshakeError> {
+        let payload = json!({
+            "universe": slice.universe,
+            "applied_us": slice.applied_us,
+            "start": slice.start,
+            "levels": slice.levels,
+        });
+        let (seq, _) = self
+            .mirror
+            .lock()
+            .next_nonce()
+            .map_err(|e| HandshakeError::Protocol(e.to_string()))?;
+        let mac = self.crypto.mac_for_payload(
+            NonceLane::OutputMirror,
+            seq,
+            &self.session_id,
+            &payload,
+        )?;
+        Ok(MirroredOutput {
+            message_type: MessageType::AlpineOutputMirror,
+            session_id: self.session_id,
+            seq,
+            universe: slice.universe,
+            applied_us: slice.applied_us,
+            start: slice.start,
+            levels: slice.levels.clone(),
+            mac,
+        })
+    }
+
+    /// Builds the error envelope reporting that control envelope `seq` was refused.
+    ///
+    /// Used instead of an ack when the envelope cannot be acted on at all, such as a MAC
+    /// failure or an operation the node does not implement.
+    pub fn error(&self, seq: u64, code: ErrorCode, detail: Option<String>) -> ErrorEnvelope {
+        ErrorEnvelope::for_control(self.session_id, seq, code, detail)
+    }
+}
+
+/// Outcome of a control handler.
+///
+/// `Ok(detail)` acknowledges the envelope; `Err(detail)` sends a negative ack carrying the
+/// detail back to the controller.
+pub type HandlerResult = Result<Option<String>, String>;
+
+type HandlerFuture = Pin<Box<dyn Future<Output = HandlerResult> + Send>>;
+
+/// Type-erased handler; fails with the decode error when the payload does not match.
+type BoxedHandler =
+    Arc<dyn Fn(&ControlEnvelope, ProgressReporter) -> Result<HandlerFuture, String> + Send + Sync>;
+
+/// Sends interim progress for the envelope a handler is working on.
+///
+/// Reports are best-effort: they are dropped when the dispatcher has no progress sink or
+/// the peer is gone.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    responder: Arc<ControlResponder>,
+    seq: u64,
+    sink: Option<mpsc::UnboundedSender<HandshakeMessage>>,
+}
+
+impl ProgressReporter {
+    /// Sequence of the envelope being worked on.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn report(&self, percent: Option<u8>, detail: Option<String>) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        if let Ok(progress) = self.responder.progress(self.seq, percent, detail) {
+            let _ = sink.send(HandshakeMessage::Progress(progress));
+        }
+    }
+}
+
+/// Shared table of control handlers keyed by operation.
+///
+/// Cloning shares the table, so handlers registered after a [`ControlDispatcher`] was
+/// built still receive its envelopes.
+#[derive(Clone, Default)]
+pub struct ControlHandlers {
+    handlers: Arc<RwLock<HashMap<ControlOp, BoxedHandler>>>,
+}
+
+impl ControlHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a synchronous handler for `op` whose payload decodes as `P`, replacing
+    /// any previous handler.
+    ///
+    /// Use [`ControlValue`](crate::messages::ControlValue) for `P` to receive the payload untouched; handlers written
+    /// against JSON payloads can keep taking a `serde_json::Value`.
+    pub fn on<P, F>(&self, op: ControlOp, handler: F)
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: Fn(P) -> HandlerResult + Send + Sync + 'static,
+    {
+        self.on_progress(op, move |payload: P, _| {
+            std::future::ready(handler(payload))
+        });
+    }
+
+    /// Registers an asynchronous handler for `op` whose payload decodes as `P`, replacing
+    /// any previous handler.
+    pub fn on_async<P, F, Fut>(&self, op: ControlOp, handler: F)
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: Fn(P) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.on_progress(op, move |payload: P, _| handler(payload));
+    }
+
+    /// Registers an asynchronous handler for a long-running `op` that reports interim
+    /// progress through the given [`ProgressReporter`] before it returns.
+    pub fn on_progress<P, F, Fut>(&self, op: ControlOp, handler: F)
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: Fn(P, ProgressReporter) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        let handler: BoxedHandler = Arc::new(move |env: &ControlEnvelope, progress| {
+            env.payload_as::<P>()
+                .map(|payload| Box::pin(handler(payload, progress)) as HandlerFuture)
+                .map_err(|e| e.to_string())
+        });
+        self.handlers.write().insert(op, handler);
+    }
+
+    /// Registers a handler that receives the whole verified envelope.
+    pub fn on_envelope<F>(&self, op: ControlOp, handler: F)
+    where
+        F: Fn(&ControlEnvelope) -> HandlerResult + Send + Sync + 'static,
+    {
+        let handler: BoxedHandler = Arc::new(move |env: &ControlEnvelope, _| {
+            let result = handler(env);
+            Ok(Box::pin(std::future::ready(result)) as HandlerFuture)
+        });
+        self.handlers.write().insert(op, handler);
+    }
+
+    /// Removes the handler for `op`; later envelopes are answered with
+    /// `CONTROL_UNKNOWN_OP`.
+    pub fn remove(&self, op: &ControlOp) {
+        self.handlers.write().remove(op);
+    }
+
+    pub fn contains(&self, op: &ControlOp) -> bool {
+        self.handlers.read().contains_key(op)
+    }
+
+    fn get(&self, op: &ControlOp) -> Option<BoxedHandler> {
+        self.handlers.read().get(op).cloned()
+    }
+}
+
+impl std::fmt::Debug for ControlHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.handlers.read().keys()).finish()
+    }
+}
+
+/// Routes verified control envelopes to registered handlers and builds the reply.
+///
+/// Each envelope is checked against the session MAC, then against a [`ControlDedup`]
+/// window: a retransmission of a recent envelope gets the cached reply again without
+/// re-running its handler, and older sequences are dropped as replays. Operations without a handler
+/// are answered with `CONTROL_UNKNOWN_OP`, payloads that do not decode with
+/// `CONTROL_PAYLOAD_INVALID`, and everything else with an authenticated ack carrying the
+/// handler's detail. With a [`RateLimit`] set, new envelopes beyond it are refused with
+/// `CONTROL_RATE_LIMITED` before their handler runs. With a permission check set,
+/// operations it refuses are answered with `CONTROL_UNAUTHORIZED`.
+pub struct ControlDispatcher {
+    responder: Arc<ControlResponder>,
+    handlers: ControlHandlers,
+    dedup: ControlDedup,
+    progress: Option<mpsc::UnboundedSender<HandshakeMessage>>,
+    rate: Option<TokenBucket>,
+    permits: Option<PermissionCheck>,
+}
+
+/// Decides whether the session may run an operation; consulted for every envelope.
+pub type PermissionCheck = Arc<dyn Fn(&ControlOp) -> bool + Send + Sync>;
+
+impl ControlDispatcher {
+    pub fn new(responder: ControlResponder, handlers: ControlHandlers) -> Self {
+        Self {
+            responder: Arc::new(responder),
+            handlers,
+            dedup: ControlDedup::default(),
+            progress: None,
+            rate: None,
+            permits: None,
+        }
+    }
+
+    /// Refuses operations `permits` rejects, such as writes from a monitoring session.
+    pub fn set_permissions(&mut self, permits: PermissionCheck) {
+        self.permits = Some(permits);
+    }
+
+    /// Limits how many new envelopes this session may have handled, protecting the node
+    /// from a controller that floods the control plane.
+    pub fn set_rate_limit(&mut self, limit: RateLimit) {
+        self.rate = Some(TokenBucket::new(limit, Instant::now()));
+    }
+
+    /// Delivers progress updates from running handlers to `sink`, which the caller
+    /// forwards to the controller while [`ControlDispatcher::dispatch`] is pending.
+    pub fn set_progress_sink(&mut self, sink: mpsc::UnboundedSender<HandshakeMessage>) {
+        self.progress = Some(sink);
+    }
+
+    /// Drops envelopes below `next` as replays; seed it with
+    /// [`AlnpSession::next_control_seq`] after [`ControlResponder::accept_migration`].
+    pub fn seed_sequence(&mut self, next: u64) {
+        self.dedup.seed(self.responder.session_id, next);
+    }
+
+    pub fn responder(&self) -> &ControlResponder {
+        &self.responder
+    }
+
+    pub fn handlers(&self) -> &ControlHandlers {
+        &self.handlers
+    }
+
+    /// Handles one inbound envelope and returns the message to send back, or `None` for a
+    /// replayed envelope that should be ignored.
+    pub async fn dispatch(&mut self, env: &ControlEnvelope) -> Option<HandshakeMessage> {
+        if env.session_id != self.responder.session_id || self.responder.verify(env).is_err() {
+            // Forged envelopes never touch the sequence window.
+            return Some(HandshakeMessage::Error(self.responder.error(
+                env.seq,
+                ErrorCode::SessionMacMismatch,
+                Some("control MAC validation failed".to_string()),
+            )));
+        }
+        if self
+            .permits
+            .as_ref()
+            .is_some_and(|permits| !permits(&env.op))
+        {
+            return Some(HandshakeMessage::Error(self.responder.error(
+                env.seq,
+                ErrorCode::ControlUnauthorized,
+                Some(format!("{:?} not permitted for this session", env.op)),
+            )));
+        }
+        match self.dedup.accept(env) {
+            Delivery::New => {}
+            Delivery::Replay(reply) => return Some(*reply),
+            Delivery::InProgress | Delivery::Stale => return None,
+        }
+        if let Some(Err(wait)) = self.rate.as_mut().map(|rate| rate.try_take(Instant::now())) {
+            // Not handled, so a retransmission after the wait is treated as new.
+            self.dedup.release(env);
+            let mut error = self.responder.error(
+                env.seq,
+                ErrorCode::ControlRateLimited,
+                Some("control rate limit exceeded".to_string()),
+            );
+            error.retry_after_ms = Some(wait.as_millis().max(1) as u64);
+            return Some(HandshakeMessage::Error(error));
+        }
+
+        let reply = self.route(env).await;
+        self.dedup.complete(env, &reply);
+        Some(reply)
+    }
+
+    async fn route(&self, env: &ControlEnvelope) -> HandshakeMessage {
+        let progress = ProgressReporter {
+            responder: self.responder.clone(),
+            seq: env.seq,
+            sink: self.progress.clone(),
+        };
+        let pending = match self
+            .handlers
+            .get(&env.op)
+            .map(|handler| handler(env, progress))
+        {
+            Some(Ok(pending)) => pending,
+            Some(Err(detail)) => {
+                return HandshakeMessage::Error(self.responder.error(
+                    env.seq,
+                    ErrorCode::ControlPayloadInvalid,
+                    Some(detail),
+                ))
+            }
+            None => {
+                return HandshakeMessage::Error(self.responder.error(
+                    env.seq,
+                    ErrorCode::ControlUnknownOp,
+                    Some(format!("unsupported op {:?}", env.op)),
+                ))
+            }
+        };
+        let (ok, detail) = match pending.await {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, Some(detail)),
+        };
+        match self.responder.ack(env.seq, ok, detail) {
+            Ok(ack) => HandshakeMessage::Ack(ack),
+            Err(err) => HandshakeMessage::Error(self.responder.error(
+                env.seq,
+                ErrorCode::ControlPayloadInvalid,
+                Some(err.to_string()),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SuiteId;
+    use crate::handshake::transport::ControlUpdate;
+    use crate::messages::ControlValue;
+
+    fn crypto() -> ControlCrypto {
+        ControlCrypto::new(SessionKeys {
+            shared_secret: vec![0x11; 32],
+            control_key: [0x11; 32],
+            stream_key: [0x22; 32],
+            suite: SuiteId::default(),
+            channel_binding: [0x33; 32],
+        })
+    }
+
+    /// Published alongside the canonical CBOR vectors in docs/control_plane.md.
+    #[test]
+    fn control_mac_vector() {
+        let payload = json!({"mode": "show", "level": -3, "a": [1, 2.5]});
+        let mac = crypto()
+            .mac_for_payload(NonceLane::ControlRequest, 1, &Uuid::nil(), &payload)
+            .unwrap();
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "1635900df503c0cbf407db44ba39e123");
+    }
+
+    #[test]
+    fn json_and_typed_payloads_authenticate_alike() {
+        #[derive(Serialize)]
+        struct Fade {
+            level: f64,
+            fade_ms: u32,
+        }
+        let client = ControlClient::new(Uuid::nil(), Uuid::nil(), crypto());
+        let typed = client
+            .envelope(
+                1,
+                ControlOp::SetMode,
+                Fade {
+                    level: 1.0,
+                    fade_ms: 500,
+                },
+            )
+            .unwrap();
+        let json = client
+            .envelope(1, ControlOp::SetMode, json!({"level": 1.0, "fade_ms": 500}))
+            .unwrap();
+        assert_eq!(typed.mac, json.mac);
+
+        // The level stays a float and the fade an integer across the wire, so the MAC
+        // the device recomputes matches.
+        let decoded: ControlEnvelope = codec::from_slice(&codec::to_vec(&typed).unwrap()).unwrap();
+        let entries = decoded.payload.as_map().unwrap();
+        assert!(entries.contains(&(ControlValue::from("level"), ControlValue::Float(1.0))));
+        assert!(entries.contains(&(ControlValue::from("fade_ms"), ControlValue::from(500))));
+        ControlResponder::new(Uuid::nil(), crypto())
+            .verify(&decoded)
+            .unwrap();
+        assert_eq!(
+            decoded.payload_as::<serde_json::Value>().unwrap(),
+            json!({"level": 1.0, "fade_ms": 500})
+        );
+    }
+
+    #[derive(serde::Deserialize)]
+    struct SetMode {
+        mode: String,
+    }
+
+    fn dispatcher(handlers: &ControlHandlers) -> (ControlClient, ControlDispatcher) {
+        let session_id = Uuid::new_v4();
+        let client = ControlClient::new(Uuid::nil(), session_id, crypto());
+        let responder = ControlResponder::new(session_id, crypto());
+        (client, ControlDispatcher::new(responder, handlers.clone()))
+    }
+
+    fn ack(reply: Option<HandshakeMessage>) -> Acknowledge {
+        match reply {
+            Some(HandshakeMessage::Ack(ack)) => ack,
+            other => panic!("expected ack, got {:?}", other),
+        }
+    }
+
+    fn error_code(reply: Option<HandshakeMessage>) -> ErrorCode {
+        match reply {
+            Some(HandshakeMessage::Error(error)) => error.code,
+            other => panic!("expected error envelope, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch_routes_typed_handlers_and_suppresses_replays() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let handlers = ControlHandlers::new();
+        let counter = calls.clone();
+        handlers.on(ControlOp::SetMode, move |req: SetMode| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            match req.mode.as_str() {
+                "show" => Ok(Some("mode set".into())),
+                other => Err(format!("unknown mode {}", other)),
+            }
+        });
+        handlers.on_async(ControlOp::GetStatus, |_: serde_json::Value| async {
+            Ok(Some("idle".into()))
+        });
+        let (client, mut dispatcher) = dispatcher(&handlers);
+
+        let env = client
+            .envelope(1, ControlOp::SetMode, json!({"mode": "show"}))
+            .unwrap();
+        let first = ack(dispatcher.dispatch(&env).await);
+        assert!(first.ok);
+        assert_eq!(first.detail.as_deref(), Some("mode set"));
+        // A retransmission gets the same ack without running the handler again.
+        assert_eq!(ack(dispatcher.dispatch(&env).await), first);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let env = client
+            .envelope(2, ControlOp::SetMode, json!({"mode": "party"}))
+            .unwrap();
+        let refused = ack(dispatcher.dispatch(&env).await);
+        assert!(!refused.ok);
+        assert_eq!(refused.detail.as_deref(), Some("unknown mode party"));
+
+        let env = client.envelope(3, ControlOp::GetStatus, json!({})).unwrap();
+        assert_eq!(
+            ack(dispatcher.dispatch(&env).await).detail.as_deref(),
+            Some("idle")
+        );
+
+        // A late retransmission of an earlier envelope is answered from cache, not
+        // applied again.
+        let late = client
+            .envelope(1, ControlOp::SetMode, json!({"mode": "show"}))
+            .unwrap();
+        assert_eq!(ack(dispatcher.dispatch(&late).await), first);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn dispatch_reports_mac_payload_and_unknown_op_errors() {
+        let handlers = ControlHandlers::new();
+        handlers.on(ControlOp::SetMode, |_: SetMode| Ok(None));
+        let (client, mut dispatcher) = dispatcher(&handlers);
+
+        let mut forged = client
+            .envelope(5, ControlOp::SetMode, json!({"mode": "show"}))
+            .unwrap();
+        forged.payload = codec::to_value(&json!({"mode": "blackout"})).unwrap();
+        assert_eq!(
+            error_code(dispatcher.dispatch(&forged).await),
+            ErrorCode::SessionMacMismatch
+        );
+
+        let env = client
+            .envelope(1, ControlOp::SetMode, json!({"level": 3}))
+            .unwrap();
+        assert_eq!(
+            error_code(dispatcher.dispatch(&env).await),
+            ErrorCode::ControlPayloadInvalid
+        );
+
+        let env = client.envelope(2, ControlOp::Identify, json!({})).unwrap();
+        assert_eq!(
+            error_code(dispatcher.dispatch(&env).await),
+            ErrorCode::ControlUnknownOp
+        );
+
+        // Handlers registered through a clone of the table apply immediately.
+        handlers.on_envelope(ControlOp::Identify, |env| {
+            Ok(Some(format!("seq {}", env.seq)))
+        });
+        let env = client.envelope(3, ControlOp::Identify, json!({})).unwrap();
+        assert_eq!(
+            ack(dispatcher.dispatch(&env).await).detail.as_deref(),
+            Some("seq 3")
+        );
+    }
+
+    /// Replays device replies in order and records what the controller sent.
+    #[derive(Default)]
+    struct Scripted {
+        replies: std::collections::VecDeque<HandshakeMessage>,
+        sent: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl HandshakeTransport for Scripted {
+        async fn send(&mut self, _msg: HandshakeMessage) -> Result<(), HandshakeError> {
+            self.sent.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+            self.replies
+                .pop_front()
+                .ok_or_else(|| HandshakeError::Transport("script exhausted".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn long_running_call_reports_progress_before_ack() {
+        let handlers = ControlHandlers::new();
+        handlers.on_progress(
+            ControlOp::Restart,
+            |_: serde_json::Value, progress: ProgressReporter| async move {
+                progress.report(Some(40), Some("flashing".into()));
+                progress.report(Some(250), None);
+                Ok(Some("restarted".into()))
+            },
+        );
+        let (client, mut dispatcher) = dispatcher(&handlers);
+        let (sink, mut updates) = mpsc::unbounded_channel();
+        dispatcher.set_progress_sink(sink);
+
+        // The device side produces two progress updates and the final ack for seq 1.
+        let env = client.envelope(1, ControlOp::Restart, json!({})).unwrap();
+        let mut script = Scripted::default();
+        let final_reply = dispatcher.dispatch(&env).await.unwrap();
+        while let Ok(update) = updates.try_recv() {
+            script.replies.push_back(update);
+        }
+        script.replies.push_back(final_reply);
+
+        let sent = script.sent.clone();
+        let mut channel = ReliableControlChannel::new(script);
+        let mut call = client
+            .call(&mut channel, ControlOp::Restart, json!({}))
+            .await
+            .unwrap();
+        let mut seen = Vec::new();
+        let mut acked = None;
+        while let Some(update) = call.next().await {
+            match update.unwrap() {
+                ControlUpdate::Progress(progress) => {
+                    assert_eq!(progress.seq, 1);
+                    seen.push(progress.percent);
+                }
+                ControlUpdate::Acked(ack) => acked = Some(ack),
+            }
+        }
+        assert_eq!(seen, vec![Some(40), Some(100)]);
+        assert_eq!(acked.unwrap().detail.as_deref(), Some("restarted"));
+        // Progress holds off retransmission, so the envelope went out once.
+        assert_eq!(sent.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn dispatcher_sheds_envelopes_beyond_rate_limit() {
+        let handlers = ControlHandlers::new();
+        handlers.on(ControlOp::Identify, |_: serde_json::Value| Ok(None));
+        let (client, mut dispatcher) = dispatcher(&handlers);
+        dispatcher.set_rate_limit(RateLimit::new(1, 2).unwrap());
+
+        for seq in 1..=2 {
+            let env = client
+                .envelope(seq, ControlOp::Identify, json!({}))
+                .unwrap();
+            assert!(ack(dispatcher.dispatch(&env).await).ok);
+        }
+        let env = client.envelope(3, ControlOp::Identify, json!({})).unwrap();
+        let Some(HandshakeMessage::Error(error)) = dispatcher.dispatch(&env).await else {
+            panic!("expected rate limit error");
+        };
+        assert_eq!(error.code, ErrorCode::ControlRateLimited);
+        assert!(error.retryable);
+        assert!(error.retry_after_ms.is_some_and(|ms| ms > 0 && ms <= 1000));
+
+        // Retransmissions of handled envelopes are still answered from cache.
+        let env = client.envelope(2, ControlOp::Identify, json!({})).unwrap();
+        assert!(ack(dispatcher.dispatch(&env).await).ok);
+    }
+
+    #[tokio::test]
+    async fn dispatcher_refuses_operations_the_session_may_not_run() {
+        let handlers = ControlHandlers::new();
+        handlers.on(ControlOp::GetStatus, |_: serde_json::Value| Ok(None));
+        handlers.on(ControlOp::SetMode, |_: serde_json::Value| Ok(None));
+        let (client, mut dispatcher) = dispatcher(&handlers);
+        dispatcher.set_permissions(Arc::new(|op: &ControlOp| op.is_read_only()));
+
+        let env = client.envelope(1, ControlOp::GetStatus, json!({})).unwrap();
+        assert!(ack(dispatcher.dispatch(&env).await).ok);
+        let env = client.envelope(2, ControlOp::SetMode, json!({})).unwrap();
+        let Some(HandshakeMessage::Error(error)) = dispatcher.dispatch(&env).await else {
+            panic!("expected unauthorized error");
+        };
+        assert_eq!(error.code, ErrorCode::ControlUnauthorized);
+    }
+
+    #[test]
+    fn pacing_spaces_sends_and_honours_holds() {
+        let start = Instant::now();
+        let mut pacing = Pacing {
+            bucket: Some(TokenBucket::new(RateLimit::new(10, 1).unwrap(), start)),
+            hold_until: None,
+        };
+        assert_eq!(pacing.wait(start), None);
+        let wait = pacing.wait(start).unwrap();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(100));
+        assert_eq!(pacing.wait(start + Duration::from_millis(100)), None);
+
+        pacing.hold_until = Some(start + Duration::from_secs(1));
+        assert_eq!(
+            pacing.wait(start + Duration::from_millis(400)),
+            Some(Duration::from_millis(600))
+        );
+        assert_eq!(pacing.wait(start + Duration::from_secs(2)), None);
+    }
 }
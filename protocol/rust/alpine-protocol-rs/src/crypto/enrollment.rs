@@ -0,0 +1,327 @@
+//! Device enrollment: binding a node's key to its identity under a trusted authority.
+//!
+//! A freshly provisioned node generates credentials with [`super::identity::generate`]
+//! and signs an [`IdentityClaim`] naming its [`DeviceIdentity`] and public key, much like
+//! a certificate signing request. A provisioning controller holding a manufacturer or
+//! venue [`Authority`] checks the claim's self-signature and counter-signs it into an
+//! [`EnrolledIdentity`]. Controllers that trust the authority's key accept the enrolled
+//! identity without having seen the node before. Both artifacts are plain serde types, so
+//! they can be stored as JSON or CBOR next to the node's keys.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::identity::NodeCredentials;
+use crate::codec;
+use crate::messages::DeviceIdentity;
+
+/// Signature purpose of an [`IdentityClaim`], so claim signatures cannot be replayed as
+/// any other signed ALPINE structure.
+const CLAIM_PURPOSE: &str = "alpine-identity-claim";
+/// Signature purpose of an [`EnrolledIdentity`].
+const ENROLLMENT_PURPOSE: &str = "alpine-enrollment";
+
+/// Who vouches for an enrolled identity.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthorityKind {
+    /// The device maker, enrolling at the factory.
+    Manufacturer,
+    /// The venue or rental house, enrolling when the device joins its fleet.
+    Venue,
+}
+
+/// Reasons an enrollment artifact is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnrollmentError {
+    Encode(String),
+    /// A public key or signature field is not valid Ed25519 material.
+    BadKey,
+    /// The node's self-signature does not match the claim.
+    BadClaimSignature,
+    /// The authority's counter-signature does not match the enrolled identity.
+    BadSignature,
+    /// The enrolling authority's key is not among the trusted ones.
+    UntrustedAuthority,
+    NotYetValid,
+    Expired,
+}
+
+impl fmt::Display for EnrollmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnrollmentError::Encode(err) => write!(f, "encode: {}", err),
+            EnrollmentError::BadKey => write!(f, "invalid key or signature encoding"),
+            EnrollmentError::BadClaimSignature => write!(f, "identity claim signature mismatch"),
+            EnrollmentError::BadSignature => write!(f, "enrollment signature mismatch"),
+            EnrollmentError::UntrustedAuthority => write!(f, "enrolling authority not trusted"),
+            EnrollmentError::NotYetValid => write!(f, "enrollment not yet valid"),
+            EnrollmentError::Expired => write!(f, "enrollment expired"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EnrollmentError {}
+
+/// A node's signed statement that `public_key` belongs to `identity`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdentityClaim {
+    pub identity: DeviceIdentity,
+    /// Ed25519 public key of the node.
+    pub public_key: Vec<u8>,
+    pub created_at_ms: u64,
+    /// Signature by `public_key` over the other fields.
+    pub signature: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct ClaimBody<'a> {
+    purpose: &'static str,
+    identity: &'a DeviceIdentity,
+    public_key: &'a [u8],
+    created_at_ms: u64,
+}
+
+impl IdentityClaim {
+    /// Claim for `identity`, signed with the node's own credentials.
+    pub fn new(
+        identity: DeviceIdentity,
+        credentials: &NodeCredentials,
+        now_ms: u64,
+    ) -> Result<Self, EnrollmentError> {
+        let public_key = credentials.verifying.to_bytes().to_vec();
+        let body = signed_bytes(&ClaimBody {
+            purpose: CLAIM_PURPOSE,
+            identity: &identity,
+            public_key: &public_key,
+            created_at_ms: now_ms,
+        })?;
+        Ok(Self {
+            identity,
+            public_key,
+            created_at_ms: now_ms,
+            signature: credentials.sign(&body).to_bytes().to_vec(),
+        })
+    }
+
+    /// The node key the claim names.
+    pub fn verifying_key(&self) -> Result<VerifyingKey, EnrollmentError> {
+        verifying_key(&self.public_key)
+    }
+
+    /// Checks that the claim was signed by the key it names, proving the claimant holds
+    /// the private key.
+    pub fn verify(&self) -> Result<(), EnrollmentError> {
+        let body = signed_bytes(&ClaimBody {
+            purpose: CLAIM_PURPOSE,
+            identity: &self.identity,
+            public_key: &self.public_key,
+            created_at_ms: self.created_at_ms,
+        })?;
+        self.verifying_key()?
+            .verify(&body, &signature(&self.signature)?)
+            .map_err(|_| EnrollmentError::BadClaimSignature)
+    }
+}
+
+/// Signing side of a manufacturer or venue certificate authority.
+#[derive(Clone)]
+pub struct Authority {
+    pub name: String,
+    pub kind: AuthorityKind,
+    pub credentials: NodeCredentials,
+}
+
+impl Authority {
+    pub fn new(name: impl Into<String>, kind: AuthorityKind, credentials: NodeCredentials) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            credentials,
+        }
+    }
+
+    /// Verifies `claim` and counter-signs it, valid from `now_ms` for `valid_for_ms`, or
+    /// indefinitely when `None`.
+    pub fn enroll(
+        &self,
+        claim: &IdentityClaim,
+        now_ms: u64,
+        valid_for_ms: Option<u64>,
+    ) -> Result<EnrolledIdentity, EnrollmentError> {
+        claim.verify()?;
+        let mut enrolled = EnrolledIdentity {
+            claim: claim.clone(),
+            authority: self.name.clone(),
+            authority_kind: self.kind,
+            authority_key: self.credentials.verifying.to_bytes().to_vec(),
+            issued_at_ms: now_ms,
+            expires_at_ms: valid_for_ms.map(|valid| now_ms.saturating_add(valid)),
+            signature: Vec::new(),
+        };
+        let body = enrolled.signed_bytes()?;
+        enrolled.signature = self.credentials.sign(&body).to_bytes().to_vec();
+        Ok(enrolled)
+    }
+}
+
+/// An [`IdentityClaim`] counter-signed by an [`Authority`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EnrolledIdentity {
+    pub claim: IdentityClaim,
+    /// Name of the enrolling authority, for operators.
+    pub authority: String,
+    pub authority_kind: AuthorityKind,
+    /// Ed25519 public key of the enrolling authority.
+    pub authority_key: Vec<u8>,
+    pub issued_at_ms: u64,
+    pub expires_at_ms: Option<u64>,
+    /// Signature by `authority_key` over the other fields.
+    pub signature: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct EnrollmentBody<'a> {
+    purpose: &'static str,
+    claim: &'a IdentityClaim,
+    authority: &'a str,
+    authority_kind: AuthorityKind,
+    authority_key: &'a [u8],
+    issued_at_ms: u64,
+    expires_at_ms: Option<u64>,
+}
+
+impl EnrolledIdentity {
+    fn signed_bytes(&self) -> Result<Vec<u8>, EnrollmentError> {
+        signed_bytes(&EnrollmentBody {
+            purpose: ENROLLMENT_PURPOSE,
+            claim: &self.claim,
+            authority: &self.authority,
+            authority_kind: self.authority_kind,
+            authority_key: &self.authority_key,
+            issued_at_ms: self.issued_at_ms,
+            expires_at_ms: self.expires_at_ms,
+        })
+    }
+
+    /// Checks that a `trusted` authority enrolled this identity, that it is valid at
+    /// `now_ms`, and that the node's own claim is intact.
+    pub fn verify(&self, trusted: &[VerifyingKey], now_ms: u64) -> Result<(), EnrollmentError> {
+        let authority = verifying_key(&self.authority_key)?;
+        if !trusted.contains(&authority) {
+            return Err(EnrollmentError::UntrustedAuthority);
+        }
+        authority
+            .verify(&self.signed_bytes()?, &signature(&self.signature)?)
+            .map_err(|_| EnrollmentError::BadSignature)?;
+        if now_ms < self.issued_at_ms {
+            return Err(EnrollmentError::NotYetValid);
+        }
+        if self.expires_at_ms.is_some_and(|expires| now_ms >= expires) {
+            return Err(EnrollmentError::Expired);
+        }
+        self.claim.verify()
+    }
+
+    /// The enrolled node's identity.
+    pub fn identity(&self) -> &DeviceIdentity {
+        &self.claim.identity
+    }
+
+    /// The enrolled node's key, to check its handshake signatures against.
+    pub fn verifying_key(&self) -> Result<VerifyingKey, EnrollmentError> {
+        self.claim.verifying_key()
+    }
+}
+
+fn signed_bytes<T: Serialize>(body: &T) -> Result<Vec<u8>, EnrollmentError> {
+    codec::to_canonical_vec(body).map_err(|e| EnrollmentError::Encode(format!("{}", e)))
+}
+
+fn verifying_key(bytes: &[u8]) -> Result<VerifyingKey, EnrollmentError> {
+    let bytes: &[u8; 32] = bytes.try_into().map_err(|_| EnrollmentError::BadKey)?;
+    VerifyingKey::from_bytes(bytes).map_err(|_| EnrollmentError::BadKey)
+}
+
+fn signature(bytes: &[u8]) -> Result<Signature, EnrollmentError> {
+    Signature::from_slice(bytes).map_err(|_| EnrollmentError::BadKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::identity::generate;
+
+    fn node_identity() -> DeviceIdentity {
+        DeviceIdentity {
+            device_id: "node-1".into(),
+            manufacturer_id: "ALPINE".into(),
+            model_id: "dimmer".into(),
+            hardware_rev: "1".into(),
+            firmware_rev: "1.0".into(),
+        }
+    }
+
+    fn enrolled(valid_for_ms: Option<u64>) -> (EnrolledIdentity, VerifyingKey) {
+        let node = generate();
+        let claim = IdentityClaim::new(node_identity(), &node, 1_000).unwrap();
+        let authority = Authority::new("Venue CA", AuthorityKind::Venue, generate());
+        let enrolled = authority.enroll(&claim, 2_000, valid_for_ms).unwrap();
+        (enrolled, authority.credentials.verifying)
+    }
+
+    #[test]
+    fn enrolled_identity_round_trips_and_verifies() {
+        let (enrolled, authority) = enrolled(Some(10_000));
+        let json = serde_json::to_string(&enrolled).unwrap();
+        let from_json: EnrolledIdentity = serde_json::from_str(&json).unwrap();
+        let from_cbor: EnrolledIdentity =
+            codec::from_slice(&codec::to_vec(&enrolled).unwrap()).unwrap();
+        assert_eq!(from_json, enrolled);
+        assert_eq!(from_cbor, enrolled);
+        assert_eq!(from_json.verify(&[authority], 5_000), Ok(()));
+        assert_eq!(from_json.identity().device_id, "node-1");
+    }
+
+    #[test]
+    fn tampered_claims_and_enrollments_are_rejected() {
+        let node = generate();
+        let mut claim = IdentityClaim::new(node_identity(), &node, 1_000).unwrap();
+        claim.identity.device_id = "node-2".into();
+        let authority = Authority::new("ACME", AuthorityKind::Manufacturer, generate());
+        assert_eq!(
+            authority.enroll(&claim, 2_000, None).unwrap_err(),
+            EnrollmentError::BadClaimSignature
+        );
+
+        let (mut enrolled, authority) = enrolled(None);
+        enrolled.authority_kind = AuthorityKind::Manufacturer;
+        assert_eq!(
+            enrolled.verify(&[authority], 5_000),
+            Err(EnrollmentError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn enrollment_needs_a_trusted_authority_and_validity() {
+        let (enrolled, authority) = enrolled(Some(10_000));
+        assert_eq!(
+            enrolled.verify(&[generate().verifying], 5_000),
+            Err(EnrollmentError::UntrustedAuthority)
+        );
+        assert_eq!(
+            enrolled.verify(&[authority], 1_999),
+            Err(EnrollmentError::NotYetValid)
+        );
+        assert_eq!(
+            enrolled.verify(&[authority], 12_000),
+            Err(EnrollmentError::Expired)
+        );
+    }
+}
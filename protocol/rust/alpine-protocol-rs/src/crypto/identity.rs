@@ -1,10 +1,18 @@
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io::BufReader;
 
-use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+#[cfg(feature = "std")]
+use ed25519_dalek::pkcs8::spki::der::pem::{self, LineEnding};
+#[cfg(feature = "std")]
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
 use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
 use ed25519_dalek::{Signer, Verifier};
-use thiserror::Error;
+#[cfg(feature = "std")]
+use rand_core::{OsRng, RngCore};
 
 /// Ed25519 credentials loaded from PEM files.
 #[derive(Clone)]
@@ -13,15 +21,56 @@ pub struct NodeCredentials {
     pub verifying: VerifyingKey,
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug)]
 pub enum IdentityError {
-    #[error("failed to parse PEM: {0}")]
     Pem(String),
-    #[error("missing key material in PEM")]
     MissingKey,
 }
 
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentityError::Pem(err) => write!(f, "failed to parse PEM: {}", err),
+            IdentityError::MissingKey => write!(f, "missing key material in PEM"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IdentityError {}
+
+/// Fresh credentials from the OS random number generator.
+#[cfg(feature = "std")]
+pub fn generate() -> NodeCredentials {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let signing = SigningKey::from_bytes(&secret);
+    NodeCredentials {
+        verifying: signing.verifying_key(),
+        signing,
+    }
+}
+
+/// PEM loading needs a filesystem, so it is only available with `std`.
+#[cfg(feature = "std")]
 impl NodeCredentials {
+    /// Encodes the keys as the private and public PEM files that
+    /// [`NodeCredentials::load_signing_pem`] and [`NodeCredentials::load_verifying_pem`]
+    /// read.
+    pub fn to_pem(&self) -> Result<(String, String), IdentityError> {
+        let signing = self
+            .signing
+            .to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| IdentityError::Pem(e.to_string()))?;
+        let public = self
+            .verifying
+            .to_public_key_der()
+            .map_err(|e| IdentityError::Pem(e.to_string()))?;
+        let verifying = pem::encode_string("CERTIFICATE", LineEnding::LF, public.as_bytes())
+            .map_err(|e| IdentityError::Pem(e.to_string()))?;
+        Ok((signing.to_string(), verifying))
+    }
+
     pub fn load_signing_pem(path: &str) -> Result<SigningKey, IdentityError> {
         let file = File::open(path).map_err(|e| IdentityError::Pem(e.to_string()))?;
         let mut reader = BufReader::new(file);
@@ -43,7 +92,9 @@ impl NodeCredentials {
         VerifyingKey::from_public_key_der(cert.as_ref())
             .map_err(|e| IdentityError::Pem(e.to_string()))
     }
+}
 
+impl NodeCredentials {
     pub fn sign(&self, data: &[u8]) -> Signature {
         self.signing.sign(data)
     }
@@ -52,3 +103,26 @@ impl NodeCredentials {
         self.verifying.verify(data, sig).is_ok()
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_credentials_round_trip_through_pem() {
+        let credentials = generate();
+        let (signing, verifying) = credentials.to_pem().unwrap();
+        let dir = std::env::temp_dir().join(format!("alpine-identity-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (signing_path, verifying_path) = (dir.join("signing.pem"), dir.join("verifying.pem"));
+        std::fs::write(&signing_path, signing).unwrap();
+        std::fs::write(&verifying_path, verifying).unwrap();
+
+        let signing = NodeCredentials::load_signing_pem(signing_path.to_str().unwrap()).unwrap();
+        let verifying =
+            NodeCredentials::load_verifying_pem(verifying_path.to_str().unwrap()).unwrap();
+        assert_eq!(signing.to_bytes(), credentials.signing.to_bytes());
+        assert_eq!(verifying, credentials.verifying);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
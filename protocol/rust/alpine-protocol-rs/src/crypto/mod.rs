@@ -1,13 +1,30 @@
-use rand::rngs::OsRng;
-use thiserror::Error;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use rand_core::OsRng;
 use x25519_dalek::{PublicKey as X25519PublicKey, SharedSecret, StaticSecret as X25519Secret};
 
-use chacha20poly1305::aead::{AeadInPlace, KeyInit};
-use chacha20poly1305::{ChaCha20Poly1305, Key};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::aead::{Aead, AeadInPlace, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
 use hkdf::Hkdf;
-use sha2::Sha256;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
+pub mod enrollment;
 pub mod identity;
+pub mod nonce;
+pub mod ratchet;
+pub mod revocation;
+pub mod selftest;
+
+pub use selftest::{
+    self_test, CipherSuite, SelfTestReport, AES_256_GCM_SUITE, ALPINE_CIPHER_SUITE,
+};
 
 /// Algorithms supported for the initial key exchange.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -17,12 +34,47 @@ pub enum KeyExchangeAlgorithm {
     None,
 }
 
+/// Cipher suite identifier negotiated in the handshake.
+///
+/// Every suite shares X25519, Ed25519 and HKDF-SHA256 and differs in the AEAD that tags
+/// control and stream traffic. New suites are appended so existing wire names never change.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SuiteId {
+    /// The suite of every peer that predates negotiation.
+    #[default]
+    #[serde(rename = "chacha20_poly1305")]
+    ChaCha20Poly1305 = 1,
+    /// For nodes whose hardware accelerates AES.
+    #[serde(rename = "aes_256_gcm")]
+    Aes256Gcm = 2,
+}
+
+/// Suites this build implements, in the default order of preference.
+pub const SUPPORTED_SUITES: [SuiteId; 2] = [SuiteId::ChaCha20Poly1305, SuiteId::Aes256Gcm];
+
+impl SuiteId {
+    /// The algorithms making up the suite.
+    pub fn describe(self) -> CipherSuite {
+        match self {
+            SuiteId::ChaCha20Poly1305 => ALPINE_CIPHER_SUITE,
+            SuiteId::Aes256Gcm => AES_256_GCM_SUITE,
+        }
+    }
+}
+
 /// Derived session key material.
 #[derive(Debug, Clone)]
 pub struct SessionKeys {
     pub shared_secret: Vec<u8>,
     pub control_key: [u8; 32],
     pub stream_key: [u8; 32],
+    /// AEAD the keys are used with; key exchanges derive the default suite and the
+    /// handshake sets the negotiated one.
+    pub suite: SuiteId,
+    /// Digest of the handshake that produced the keys; see [`channel_binding`]. Control,
+    /// heartbeat, echo, and sealed-frame MACs all cover it. Zero until the handshake sets
+    /// it.
+    pub channel_binding: [u8; 32],
 }
 
 /// Behavior required to complete the handshake key agreement.
@@ -39,8 +91,16 @@ pub struct X25519KeyExchange {
 }
 
 impl X25519KeyExchange {
+    /// Fresh key pair from the OS random number generator. Without `std`, generate the
+    /// secret with the target's RNG and use [`X25519KeyExchange::from_secret`].
+    #[cfg(feature = "std")]
     pub fn new() -> Self {
-        let private_key = X25519Secret::random_from_rng(OsRng);
+        Self::from_secret(X25519Secret::random_from_rng(OsRng).to_bytes())
+    }
+
+    /// Uses a fixed private key; intended for reproducible test transcripts.
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let private_key = X25519Secret::from(secret);
         let public_key = X25519PublicKey::from(&private_key);
         Self {
             public_key,
@@ -49,6 +109,7 @@ impl X25519KeyExchange {
     }
 }
 
+#[cfg(feature = "std")]
 impl Default for X25519KeyExchange {
     fn default() -> Self {
         Self::new()
@@ -84,6 +145,8 @@ impl KeyExchange for X25519KeyExchange {
             shared_secret: shared_secret_bytes,
             control_key,
             stream_key,
+            suite: SuiteId::default(),
+            channel_binding: [0; 32],
         })
     }
 }
@@ -95,16 +158,37 @@ pub trait TlsWrapper {
 }
 
 /// Cryptographic helper errors.
-#[derive(Debug, Error)]
+#[derive(Debug)]
 pub enum CryptoError {
-    #[error("invalid peer public key")]
     InvalidPeerKey,
-    #[error("hkdf expand error: {0}")]
     Hkdf(String),
-    #[error("aead error: {0}")]
     Aead(String),
+    /// Exporter labels must start with [`EXPORTER_LABEL_PREFIX`].
+    ExporterLabel(String),
+    /// A sealed stream ran through every epoch; the session must be re-keyed.
+    EpochsExhausted,
+    /// A [`nonce::NonceSequence`] reached its limit; the key must not be used again.
+    NoncesExhausted,
 }
 
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::InvalidPeerKey => write!(f, "invalid peer public key"),
+            CryptoError::Hkdf(err) => write!(f, "hkdf expand error: {}", err),
+            CryptoError::Aead(err) => write!(f, "aead error: {}", err),
+            CryptoError::ExporterLabel(label) => {
+                write!(f, "exporter label {:?} lacks the EXPORTER- prefix", label)
+            }
+            CryptoError::EpochsExhausted => write!(f, "stream epochs exhausted"),
+            CryptoError::NoncesExhausted => write!(f, "nonce sequence exhausted"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CryptoError {}
+
 /// Compute an authentication tag for a control payload using the derived control key.
 pub fn compute_mac(
     keys: &SessionKeys,
@@ -112,15 +196,175 @@ pub fn compute_mac(
     payload: &[u8],
     aad: &[u8],
 ) -> Result<Vec<u8>, CryptoError> {
-    let key = Key::from_slice(&keys.control_key);
-    let cipher = ChaCha20Poly1305::new(key);
+    tag_with(
+        keys.suite,
+        &keys.control_key,
+        &counter_nonce(seq),
+        payload,
+        aad,
+    )
+}
+
+/// Compute an authentication tag for a control message under the control key with an
+/// explicit nonce, normally one from [`nonce`]. The tag also covers the channel binding.
+pub fn compute_mac_with_nonce(
+    keys: &SessionKeys,
+    nonce: &[u8; 12],
+    payload: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    tag_with(
+        keys.suite,
+        &keys.control_key,
+        nonce,
+        payload,
+        &bound(keys, aad),
+    )
+}
+
+/// Validate an authentication tag produced by [`compute_mac_with_nonce`].
+pub fn verify_mac_with_nonce(
+    keys: &SessionKeys,
+    nonce: &[u8; 12],
+    payload: &[u8],
+    aad: &[u8],
+    mac: &[u8],
+) -> bool {
+    const CHACHA_TAG_SIZE: usize = 16;
+    if mac.len() != CHACHA_TAG_SIZE {
+        return false;
+    }
+    match compute_mac_with_nonce(keys, nonce, payload, aad) {
+        Ok(expected) => expected == mac,
+        Err(_) => false,
+    }
+}
+
+/// Compute an authentication tag for a streaming-path message using the derived stream
+/// key, so its nonces never collide with control sequence numbers. The tag also covers
+/// the channel binding.
+pub fn compute_stream_mac(
+    keys: &SessionKeys,
+    seq: u64,
+    payload: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    tag_with(
+        keys.suite,
+        &keys.stream_key,
+        &counter_nonce(seq),
+        payload,
+        &bound(keys, aad),
+    )
+}
+
+/// Validate an authentication tag produced by [`compute_stream_mac`].
+pub fn verify_stream_mac(
+    keys: &SessionKeys,
+    seq: u64,
+    payload: &[u8],
+    aad: &[u8],
+    mac: &[u8],
+) -> bool {
+    const CHACHA_TAG_SIZE: usize = 16;
+    if mac.len() != CHACHA_TAG_SIZE {
+        return false;
+    }
+    match compute_stream_mac(keys, seq, payload, aad) {
+        Ok(expected) => expected == mac,
+        Err(_) => false,
+    }
+}
+
+/// Digest of a handshake transcript, kept as [`SessionKeys::channel_binding`].
+///
+/// Parts are length-prefixed, so no two transcripts hash alike. Since every session MAC
+/// covers the digest, a message captured from one session never verifies under another,
+/// even between the same peers.
+pub fn channel_binding(transcript: &[&[u8]]) -> [u8; 32] {
+    let mut hash = Sha256::new();
+    hash.update(b"alpine-channel-binding");
+    for part in transcript {
+        hash.update((part.len() as u32).to_be_bytes());
+        hash.update(part);
+    }
+    hash.finalize().into()
+}
+
+/// `aad` followed by the channel binding of `keys`.
+fn bound(keys: &SessionKeys, aad: &[u8]) -> Vec<u8> {
+    let mut bound = Vec::with_capacity(aad.len() + 32);
+    bound.extend_from_slice(aad);
+    bound.extend_from_slice(&keys.channel_binding);
+    bound
+}
+
+/// `seq` big-endian, zero-padded to 12 bytes; salted nonces never take this form.
+fn counter_nonce(seq: u64) -> [u8; 12] {
     let mut nonce = [0u8; 12];
     nonce[..8].copy_from_slice(&seq.to_be_bytes());
+    nonce
+}
+
+fn tag_with(
+    suite: SuiteId,
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    payload: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
     let mut buffer = payload.to_vec();
-    let tag = cipher
-        .encrypt_in_place_detached(&nonce.into(), aad, &mut buffer)
-        .map_err(|e| CryptoError::Aead(e.to_string()))?;
-    Ok(tag.to_vec())
+    let tag = match suite {
+        SuiteId::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into())
+            .encrypt_in_place_detached(nonce.into(), aad, &mut buffer)
+            .map(|tag| tag.to_vec()),
+        SuiteId::Aes256Gcm => Aes256Gcm::new(key.into())
+            .encrypt_in_place_detached(nonce.into(), aad, &mut buffer)
+            .map(|tag| tag.to_vec()),
+    };
+    tag.map_err(|e| CryptoError::Aead(e.to_string()))
+}
+
+/// Encrypts `plaintext` with the AEAD of `suite`, appending the tag.
+pub fn seal(
+    suite: SuiteId,
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+    let sealed = match suite {
+        SuiteId::ChaCha20Poly1305 => {
+            ChaCha20Poly1305::new(key.into()).encrypt(nonce.into(), payload)
+        }
+        SuiteId::Aes256Gcm => Aes256Gcm::new(key.into()).encrypt(nonce.into(), payload),
+    };
+    sealed.map_err(|e| CryptoError::Aead(e.to_string()))
+}
+
+/// Decrypts and authenticates a [`seal`] output.
+pub fn open(
+    suite: SuiteId,
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    let opened = match suite {
+        SuiteId::ChaCha20Poly1305 => {
+            ChaCha20Poly1305::new(key.into()).decrypt(nonce.into(), payload)
+        }
+        SuiteId::Aes256Gcm => Aes256Gcm::new(key.into()).decrypt(nonce.into(), payload),
+    };
+    opened.map_err(|e| CryptoError::Aead(e.to_string()))
 }
 
 /// Validate an authentication tag for a control payload.
@@ -134,3 +378,93 @@ pub fn verify_mac(keys: &SessionKeys, seq: u64, payload: &[u8], aad: &[u8], mac:
         Err(_) => false,
     }
 }
+
+/// Derives the secret both peers keep so a later handshake can resume this session.
+pub fn resumption_secret(keys: &SessionKeys) -> Result<[u8; 32], CryptoError> {
+    let mut ikm = Vec::with_capacity(64);
+    ikm.extend_from_slice(&keys.control_key);
+    ikm.extend_from_slice(&keys.stream_key);
+    let mut secret = [0u8; 32];
+    Hkdf::<Sha256>::new(None, &ikm)
+        .expand(b"alpine-resume", &mut secret)
+        .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+    Ok(secret)
+}
+
+/// Prefix every keying-material exporter label must carry, keeping exported values apart
+/// from the protocol's own derivations.
+pub const EXPORTER_LABEL_PREFIX: &str = "EXPORTER-";
+
+/// Exports `len` bytes of keying material bound to `label` and the session, in the manner
+/// of the TLS exporter (RFC 5705) that DTLS-SRTP keys media with.
+///
+/// Both peers of a session derive the same value, while the value reveals nothing about
+/// the session keys, so it can be handed to a monitoring appliance or another consumer
+/// that must never hold the keys themselves. At most 8160 bytes can be exported per label.
+pub fn export_keying_material(
+    keys: &SessionKeys,
+    session_id: &[u8],
+    label: &str,
+    len: usize,
+) -> Result<Vec<u8>, CryptoError> {
+    if !label.starts_with(EXPORTER_LABEL_PREFIX) {
+        return Err(CryptoError::ExporterLabel(label.to_string()));
+    }
+    let mut ikm = Vec::with_capacity(64);
+    ikm.extend_from_slice(&keys.control_key);
+    ikm.extend_from_slice(&keys.stream_key);
+    let mut info = Vec::with_capacity(label.len() + 1 + session_id.len());
+    info.extend_from_slice(label.as_bytes());
+    // Separates the label from the session id so no two labels share an info string.
+    info.push(0);
+    info.extend_from_slice(session_id);
+    let mut material = vec![0u8; len];
+    Hkdf::<Sha256>::new(Some(b"alpine-exporter"), &ikm)
+        .expand(&info, &mut material)
+        .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+    Ok(material)
+}
+
+/// Proves possession of a resumption secret, bound to `label`, the new session, and `salt`.
+pub fn resumption_binder(
+    secret: &[u8; 32],
+    label: &str,
+    session_id: &[u8],
+    salt: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let mut info = Vec::with_capacity(label.len() + session_id.len());
+    info.extend_from_slice(label.as_bytes());
+    info.extend_from_slice(session_id);
+    let mut binder = vec![0u8; 32];
+    Hkdf::<Sha256>::new(Some(salt), secret)
+        .expand(&info, &mut binder)
+        .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+    Ok(binder)
+}
+
+/// Mixes a resumption secret into freshly exchanged keys.
+///
+/// Resumed sessions still run an ephemeral key exchange, so recovering the stored secret
+/// alone does not reveal their traffic keys.
+pub fn derive_resumed_keys(
+    exchanged: SessionKeys,
+    secret: &[u8; 32],
+    salt: &[u8],
+) -> Result<SessionKeys, CryptoError> {
+    let mut ikm = exchanged.shared_secret.clone();
+    ikm.extend_from_slice(secret);
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut control_key = [0u8; 32];
+    let mut stream_key = [0u8; 32];
+    hkdf.expand(b"alpine-control", &mut control_key)
+        .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+    hkdf.expand(b"alpine-stream", &mut stream_key)
+        .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+    Ok(SessionKeys {
+        shared_secret: exchanged.shared_secret,
+        control_key,
+        stream_key,
+        suite: exchanged.suite,
+        channel_binding: exchanged.channel_binding,
+    })
+}
@@ -0,0 +1,153 @@
+//! Deterministic AEAD nonces.
+//!
+//! A nonce is a 4-byte salt followed by a big-endian 64-bit sequence number. The salt
+//! starts with the lane, the kind of message the nonce belongs to, and ties the remaining
+//! bytes to the session:
+//!
+//! ```text
+//! salt  = lane || SHA-256("alpine-nonce-salt" || lane || session_id)[..3]
+//! nonce = salt || seq
+//! ```
+//!
+//! Lanes sharing a key therefore never share a nonce, and since no lane is zero none
+//! collides with the zero-padded nonces of [`super::compute_mac`] either. Within a lane a
+//! [`NonceSequence`] hands out each sequence number once and fails with
+//! [`CryptoError::NoncesExhausted`] instead of wrapping around.
+
+use sha2::{Digest, Sha256};
+
+use super::CryptoError;
+
+/// Kind of message a nonce is used for; the first byte of every nonce salt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum NonceLane {
+    /// Control envelopes sent by the controller.
+    ControlRequest = 1,
+    /// Acks and capability updates sent by the device.
+    ControlReply = 2,
+    /// Progress updates, numbered apart from the envelopes they report on.
+    ControlProgress = 3,
+    /// Sealed stream datagrams, numbered afresh in every epoch.
+    SealedFrame = 4,
+    /// Applied output mirrored to observer sessions, numbered apart from acks.
+    OutputMirror = 5,
+}
+
+/// Salt of `lane` for the session `session_id`.
+pub fn nonce_salt(session_id: &[u8], lane: NonceLane) -> [u8; 4] {
+    let digest = Sha256::new()
+        .chain_update(b"alpine-nonce-salt")
+        .chain_update([lane as u8])
+        .chain_update(session_id)
+        .finalize();
+    [lane as u8, digest[0], digest[1], digest[2]]
+}
+
+/// The nonce for sequence number `seq` under `salt`.
+pub fn nonce(salt: [u8; 4], seq: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&salt);
+    nonce[4..].copy_from_slice(&seq.to_be_bytes());
+    nonce
+}
+
+/// Sequence numbers of one lane of a session, each handed out once.
+#[derive(Debug, Clone)]
+pub struct NonceSequence {
+    salt: [u8; 4],
+    next: u64,
+    /// First sequence number never handed out.
+    limit: u64,
+}
+
+impl NonceSequence {
+    /// Sequence starting at 0 that runs up to `u64::MAX`.
+    pub fn new(session_id: &[u8], lane: NonceLane) -> Self {
+        Self {
+            salt: nonce_salt(session_id, lane),
+            next: 0,
+            limit: u64::MAX,
+        }
+    }
+
+    /// Stops before `limit`, e.g. to move to a fresh key long before the AEAD's safety
+    /// margin runs out.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Continues from `seq`, e.g. after a counter restored from a snapshot.
+    pub fn starting_at(mut self, seq: u64) -> Self {
+        self.next = seq;
+        self
+    }
+
+    pub fn salt(&self) -> [u8; 4] {
+        self.salt
+    }
+
+    /// Sequence numbers left before the sequence fails.
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.next)
+    }
+
+    /// The next sequence number and its nonce; fails once the limit is reached rather
+    /// than use a nonce twice.
+    pub fn next_nonce(&mut self) -> Result<(u64, [u8; 12]), CryptoError> {
+        if self.next >= self.limit {
+            return Err(CryptoError::NoncesExhausted);
+        }
+        let seq = self.next;
+        self.next += 1;
+        Ok((seq, nonce(self.salt, seq)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn nonces_are_distinct_per_lane_and_session() {
+        let session = [7u8; 16];
+        let request = nonce_salt(&session, NonceLane::ControlRequest);
+        let reply = nonce_salt(&session, NonceLane::ControlReply);
+        assert_ne!(nonce(request, 1), nonce(reply, 1));
+        assert_ne!(request, nonce_salt(&[8u8; 16], NonceLane::ControlRequest));
+        assert_eq!(request[0], 1);
+
+        let n = nonce(reply, 0x0102);
+        assert_eq!(&n[..4], &reply);
+        assert_eq!(&n[4..], &0x0102u64.to_be_bytes());
+    }
+
+    #[test]
+    fn sequences_fail_before_reusing_a_nonce() {
+        let mut seq = NonceSequence::new(&[1u8; 16], NonceLane::SealedFrame).with_limit(3);
+        let nonces: Vec<_> = (0..3).map(|_| seq.next_nonce().unwrap()).collect();
+        assert_eq!(
+            nonces.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(seq.remaining(), 0);
+        assert!(matches!(
+            seq.next_nonce(),
+            Err(CryptoError::NoncesExhausted)
+        ));
+        assert!(matches!(
+            seq.next_nonce(),
+            Err(CryptoError::NoncesExhausted)
+        ));
+
+        let mut top =
+            NonceSequence::new(&[1u8; 16], NonceLane::ControlReply).starting_at(u64::MAX - 1);
+        assert_eq!(top.next_nonce().unwrap().0, u64::MAX - 1);
+        assert!(matches!(
+            top.next_nonce(),
+            Err(CryptoError::NoncesExhausted)
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+//! Per-epoch subkeys for encrypted streams.
+//!
+//! Sealed frames are not encrypted under the session's stream key itself but under a
+//! subkey for the current epoch. The sender starts a new epoch at every keyframe interval
+//! rollover, a few hundred milliseconds apart, by stepping a one-way hash chain:
+//!
+//! ```text
+//! chain_0     = HKDF(salt "alpine-stream-ratchet", stream_key).expand("alpine-epoch-chain")
+//! chain_{n+1} = HKDF(chain_n).expand("alpine-epoch-chain")
+//! key_n       = HKDF(chain_n).expand("alpine-epoch-key")
+//! ```
+//!
+//! Earlier chain values are discarded, so a captured epoch key decrypts only the frames
+//! of its epoch, and a captured chain value none from before it. Receivers keep the keys
+//! of the last few epochs for frames that arrive late.
+
+use alloc::collections::VecDeque;
+use alloc::format;
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use super::{CryptoError, SessionKeys, SuiteId};
+
+/// Previous epochs whose keys a receiver keeps for reordered frames.
+pub const EPOCH_WINDOW: usize = 4;
+
+/// Furthest a receiver steps its chain ahead for one frame; frames naming a later epoch
+/// are dropped rather than costing thousands of derivations.
+pub const MAX_EPOCH_SKIP: u32 = 256;
+
+/// Most datagrams a sender seals in one epoch; it moves to the next epoch by itself
+/// rather than repeat a nonce.
+pub const EPOCH_NONCE_LIMIT: u64 = 1 << 32;
+
+/// Key of one epoch of a sealed stream.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EpochKey {
+    pub epoch: u32,
+    pub key: [u8; 32],
+}
+
+impl core::fmt::Debug for EpochKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EpochKey")
+            .field("epoch", &self.epoch)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Hash chain deriving the epoch keys of one session's stream.
+pub struct StreamRatchet {
+    suite: SuiteId,
+    channel_binding: [u8; 32],
+    chain: [u8; 32],
+    current: EpochKey,
+    /// Keys of earlier epochs, newest last; always empty on senders.
+    previous: VecDeque<EpochKey>,
+    window: usize,
+}
+
+impl core::fmt::Debug for StreamRatchet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StreamRatchet")
+            .field("suite", &self.suite)
+            .field("epoch", &self.current.epoch)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StreamRatchet {
+    /// Ratchet for sealing frames, at epoch 0. Senders keep no earlier keys.
+    pub fn sender(keys: &SessionKeys) -> Result<Self, CryptoError> {
+        Self::with_window(keys, 0)
+    }
+
+    /// Ratchet for opening frames, at epoch 0, keeping [`EPOCH_WINDOW`] earlier keys.
+    pub fn receiver(keys: &SessionKeys) -> Result<Self, CryptoError> {
+        Self::with_window(keys, EPOCH_WINDOW)
+    }
+
+    fn with_window(keys: &SessionKeys, window: usize) -> Result<Self, CryptoError> {
+        let mut chain = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(b"alpine-stream-ratchet"), &keys.stream_key)
+            .expand(b"alpine-epoch-chain", &mut chain)
+            .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+        Ok(Self {
+            suite: keys.suite,
+            channel_binding: keys.channel_binding,
+            current: EpochKey {
+                epoch: 0,
+                key: epoch_key(&chain)?,
+            },
+            chain,
+            previous: VecDeque::new(),
+            window,
+        })
+    }
+
+    /// AEAD the epoch keys are used with.
+    pub fn suite(&self) -> SuiteId {
+        self.suite
+    }
+
+    /// Channel binding of the session whose stream key seeded the chain; sealed frames
+    /// cover it.
+    pub fn channel_binding(&self) -> &[u8; 32] {
+        &self.channel_binding
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.current.epoch
+    }
+
+    /// Key of the current epoch.
+    pub fn current(&self) -> &EpochKey {
+        &self.current
+    }
+
+    /// Starts the next epoch and returns its number, forgetting the chain value that led
+    /// to it.
+    pub fn advance(&mut self) -> Result<u32, CryptoError> {
+        let epoch = self
+            .current
+            .epoch
+            .checked_add(1)
+            .ok_or(CryptoError::EpochsExhausted)?;
+        let mut chain = [0u8; 32];
+        Hkdf::<Sha256>::from_prk(&self.chain)
+            .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?
+            .expand(b"alpine-epoch-chain", &mut chain)
+            .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+        let next = EpochKey {
+            epoch,
+            key: epoch_key(&chain)?,
+        };
+        self.chain = chain;
+        let retired = core::mem::replace(&mut self.current, next);
+        if self.window > 0 {
+            if self.previous.len() == self.window {
+                self.previous.pop_front();
+            }
+            self.previous.push_back(retired);
+        }
+        Ok(epoch)
+    }
+
+    /// Key for a received frame of `epoch`; `None` when the epoch has been forgotten or
+    /// lies too far ahead.
+    ///
+    /// Keys of later epochs are derived on a copy of the chain. The epoch travels in the
+    /// clear, so the ratchet only moves with [`StreamRatchet::catch_up`] once a frame of
+    /// that epoch has authenticated; a forged epoch number cannot discard the real keys.
+    pub fn key_for(&self, epoch: u32) -> Option<EpochKey> {
+        if epoch <= self.current.epoch {
+            return core::iter::once(&self.current)
+                .chain(&self.previous)
+                .find(|key| key.epoch == epoch)
+                .cloned();
+        }
+        if epoch - self.current.epoch > MAX_EPOCH_SKIP {
+            return None;
+        }
+        let mut ahead = Self {
+            suite: self.suite,
+            channel_binding: self.channel_binding,
+            chain: self.chain,
+            current: self.current.clone(),
+            previous: VecDeque::new(),
+            window: 0,
+        };
+        while ahead.current.epoch < epoch {
+            ahead.advance().ok()?;
+        }
+        Some(ahead.current)
+    }
+
+    /// Steps the chain forward to `epoch` after a frame of it has authenticated; does
+    /// nothing for the current epoch or earlier ones.
+    pub fn catch_up(&mut self, epoch: u32) -> Result<(), CryptoError> {
+        if epoch.saturating_sub(self.current.epoch) > MAX_EPOCH_SKIP {
+            return Err(CryptoError::Aead(format!("epoch {} too far ahead", epoch)));
+        }
+        while self.current.epoch < epoch {
+            self.advance()?;
+        }
+        Ok(())
+    }
+}
+
+fn epoch_key(chain: &[u8; 32]) -> Result<[u8; 32], CryptoError> {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::from_prk(chain)
+        .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?
+        .expand(b"alpine-epoch-key", &mut key)
+        .map_err(|e| CryptoError::Hkdf(format!("{:?}", e)))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn keys(byte: u8) -> SessionKeys {
+        SessionKeys {
+            shared_secret: Vec::new(),
+            control_key: [byte; 32],
+            stream_key: [byte.wrapping_add(1); 32],
+            suite: SuiteId::default(),
+            channel_binding: [0; 32],
+        }
+    }
+
+    #[test]
+    fn receivers_follow_the_sender_and_keep_a_window() {
+        let mut sender = StreamRatchet::sender(&keys(1)).unwrap();
+        let mut receiver = StreamRatchet::receiver(&keys(1)).unwrap();
+        let mut sent = Vec::new();
+        for _ in 0..8 {
+            sent.push(sender.current().clone());
+            sender.advance().unwrap();
+        }
+        assert_eq!(sender.epoch(), 8);
+        // Every epoch gets its own key.
+        assert!(sent.windows(2).all(|pair| pair[0].key != pair[1].key));
+
+        assert_eq!(receiver.key_for(6).as_ref(), Some(&sent[6]));
+        // Looking ahead does not move the chain.
+        assert_eq!(receiver.epoch(), 0);
+        receiver.catch_up(6).unwrap();
+        assert_eq!(receiver.key_for(3).as_ref(), Some(&sent[3]));
+        assert_eq!(receiver.key_for(2).as_ref(), Some(&sent[2]));
+        // Only EPOCH_WINDOW earlier epochs are kept.
+        assert_eq!(receiver.key_for(1), None);
+        assert_eq!(receiver.key_for(7).as_ref(), Some(&sent[7]));
+        receiver.catch_up(7).unwrap();
+        assert_eq!(receiver.key_for(2), None);
+    }
+
+    #[test]
+    fn epochs_are_bound_to_the_session_and_bounded_ahead() {
+        let a = StreamRatchet::sender(&keys(1)).unwrap();
+        let b = StreamRatchet::sender(&keys(2)).unwrap();
+        assert_ne!(a.current().key, b.current().key);
+        assert_ne!(a.current().key, keys(1).stream_key);
+
+        let mut receiver = StreamRatchet::receiver(&keys(1)).unwrap();
+        assert!(receiver.key_for(MAX_EPOCH_SKIP + 1).is_none());
+        assert!(receiver.catch_up(MAX_EPOCH_SKIP + 1).is_err());
+        assert_eq!(receiver.epoch(), 0);
+        assert!(receiver.key_for(MAX_EPOCH_SKIP).is_some());
+        receiver.catch_up(MAX_EPOCH_SKIP).unwrap();
+        assert_eq!(receiver.epoch(), MAX_EPOCH_SKIP);
+    }
+
+    #[test]
+    fn the_last_epoch_cannot_be_left() {
+        let mut sender = StreamRatchet::sender(&keys(1)).unwrap();
+        sender.current.epoch = u32::MAX - 1;
+        assert_eq!(sender.advance().unwrap(), u32::MAX);
+        let key = sender.current().clone();
+        assert!(matches!(
+            sender.advance(),
+            Err(CryptoError::EpochsExhausted)
+        ));
+        assert_eq!(sender.current(), &key);
+    }
+}
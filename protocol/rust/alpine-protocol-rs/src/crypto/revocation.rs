@@ -0,0 +1,335 @@
+//! Revocation of compromised node and controller keys.
+//!
+//! A trust root (the same [`Authority`] that enrolls devices) issues a signed
+//! [`RevocationList`] naming Ed25519 keys and device ids that must no longer be accepted.
+//! Lists carry a serial number; a store only ever moves to a newer list, so a captured old
+//! list cannot be replayed to un-revoke a key. Handshakes consult the list through
+//! [`RevocationCheck`] on their `HandshakeContext`.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::enrollment::{Authority, EnrolledIdentity, EnrollmentError};
+use crate::codec;
+use crate::handshake::RevocationCheck;
+
+/// Signature purpose of a [`RevocationList`].
+const REVOCATION_PURPOSE: &str = "alpine-revocation-list";
+
+/// Reasons a revocation list is not installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevocationError {
+    /// The list is malformed, unsigned by a trusted root, or tampered with.
+    Invalid(EnrollmentError),
+    /// The list is not newer than the installed one.
+    Stale {
+        installed: u64,
+        offered: u64,
+    },
+    Io(String),
+}
+
+impl fmt::Display for RevocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevocationError::Invalid(err) => write!(f, "invalid revocation list: {}", err),
+            RevocationError::Stale { installed, offered } => write!(
+                f,
+                "revocation list serial {} is not newer than installed {}",
+                offered, installed
+            ),
+            RevocationError::Io(err) => write!(f, "revocation list io: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RevocationError {}
+
+impl From<EnrollmentError> for RevocationError {
+    fn from(err: EnrollmentError) -> Self {
+        RevocationError::Invalid(err)
+    }
+}
+
+/// Keys and devices cut off by a trust root.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RevocationList {
+    /// Name of the issuing trust root, for operators.
+    pub issuer: String,
+    /// Ed25519 public key of the issuing trust root.
+    pub issuer_key: Vec<u8>,
+    /// Increases with every list the root issues.
+    pub serial: u64,
+    pub issued_at_ms: u64,
+    /// Revoked Ed25519 public keys, of nodes or controllers.
+    pub revoked_keys: Vec<Vec<u8>>,
+    /// Revoked device ids.
+    pub revoked_devices: Vec<String>,
+    /// Signature by `issuer_key` over the other fields.
+    pub signature: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct ListBody<'a> {
+    purpose: &'static str,
+    issuer: &'a str,
+    issuer_key: &'a [u8],
+    serial: u64,
+    issued_at_ms: u64,
+    revoked_keys: &'a [Vec<u8>],
+    revoked_devices: &'a [String],
+}
+
+impl RevocationList {
+    /// Issues list `serial`, signed by `root`.
+    pub fn issue(
+        root: &Authority,
+        serial: u64,
+        now_ms: u64,
+        revoked_keys: Vec<Vec<u8>>,
+        revoked_devices: Vec<String>,
+    ) -> Result<Self, EnrollmentError> {
+        let mut list = Self {
+            issuer: root.name.clone(),
+            issuer_key: root.credentials.verifying.to_bytes().to_vec(),
+            serial,
+            issued_at_ms: now_ms,
+            revoked_keys,
+            revoked_devices,
+            signature: Vec::new(),
+        };
+        let body = list.signed_bytes()?;
+        list.signature = root.credentials.sign(&body).to_bytes().to_vec();
+        Ok(list)
+    }
+
+    fn signed_bytes(&self) -> Result<Vec<u8>, EnrollmentError> {
+        codec::to_canonical_vec(&ListBody {
+            purpose: REVOCATION_PURPOSE,
+            issuer: &self.issuer,
+            issuer_key: &self.issuer_key,
+            serial: self.serial,
+            issued_at_ms: self.issued_at_ms,
+            revoked_keys: &self.revoked_keys,
+            revoked_devices: &self.revoked_devices,
+        })
+        .map_err(|e| EnrollmentError::Encode(format!("{}", e)))
+    }
+
+    /// Checks that a `trusted` root issued the list and that it is intact.
+    pub fn verify(&self, trusted: &[VerifyingKey]) -> Result<(), EnrollmentError> {
+        let issuer_key: &[u8; 32] = self
+            .issuer_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| EnrollmentError::BadKey)?;
+        let issuer = VerifyingKey::from_bytes(issuer_key).map_err(|_| EnrollmentError::BadKey)?;
+        if !trusted.contains(&issuer) {
+            return Err(EnrollmentError::UntrustedAuthority);
+        }
+        let signature =
+            Signature::from_slice(&self.signature).map_err(|_| EnrollmentError::BadKey)?;
+        issuer
+            .verify(&self.signed_bytes()?, &signature)
+            .map_err(|_| EnrollmentError::BadSignature)
+    }
+
+    /// Whether `enrolled` names a revoked key or device, or was enrolled by a revoked
+    /// authority.
+    pub fn revokes(&self, enrolled: &EnrolledIdentity) -> bool {
+        self.is_revoked_key(&enrolled.claim.public_key)
+            || self.is_revoked_key(&enrolled.authority_key)
+            || self.is_revoked_device(&enrolled.identity().device_id)
+    }
+}
+
+impl RevocationCheck for RevocationList {
+    fn is_revoked_key(&self, key: &[u8]) -> bool {
+        self.revoked_keys.iter().any(|revoked| revoked == key)
+    }
+
+    fn is_revoked_device(&self, device_id: &str) -> bool {
+        self.revoked_devices
+            .iter()
+            .any(|revoked| revoked == device_id)
+    }
+}
+
+#[cfg(feature = "std")]
+pub use store::RevocationStore;
+
+#[cfg(feature = "std")]
+mod store {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use ed25519_dalek::VerifyingKey;
+    use parking_lot::RwLock;
+
+    use super::{RevocationError, RevocationList};
+    use crate::control::ControlHandlers;
+    use crate::handshake::RevocationCheck;
+    use crate::messages::ControlOp;
+
+    /// The newest verified revocation list, shared by every handshake of a node or
+    /// controller.
+    #[derive(Debug)]
+    pub struct RevocationStore {
+        trusted: Vec<VerifyingKey>,
+        current: RwLock<Option<RevocationList>>,
+    }
+
+    impl RevocationStore {
+        /// Empty store accepting lists issued by any of the `trusted` roots.
+        pub fn new(trusted: Vec<VerifyingKey>) -> Self {
+            Self {
+                trusted,
+                current: RwLock::new(None),
+            }
+        }
+
+        /// Verifies `list` and installs it if it is newer than the current one.
+        pub fn install(&self, list: RevocationList) -> Result<(), RevocationError> {
+            list.verify(&self.trusted)?;
+            let mut current = self.current.write();
+            if let Some(installed) = current.as_ref() {
+                if list.serial <= installed.serial {
+                    return Err(RevocationError::Stale {
+                        installed: installed.serial,
+                        offered: list.serial,
+                    });
+                }
+            }
+            *current = Some(list);
+            Ok(())
+        }
+
+        /// Installs a list stored as JSON at `path`, e.g. one distributed with a
+        /// configuration bundle.
+        pub fn load_file(&self, path: impl AsRef<Path>) -> Result<(), RevocationError> {
+            let bytes = std::fs::read(path).map_err(|e| RevocationError::Io(e.to_string()))?;
+            let list =
+                serde_json::from_slice(&bytes).map_err(|e| RevocationError::Io(e.to_string()))?;
+            self.install(list)
+        }
+
+        /// The installed list, if any.
+        pub fn current(&self) -> Option<RevocationList> {
+            self.current.read().clone()
+        }
+
+        /// Handles `update_revocations`, so a controller can push a newer list to the
+        /// node.
+        pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+            let store = self.clone();
+            handlers.on(ControlOp::UpdateRevocations, move |list: RevocationList| {
+                let serial = list.serial;
+                store
+                    .install(list)
+                    .map(|_| Some(serial.to_string()))
+                    .map_err(|e| e.to_string())
+            });
+        }
+    }
+
+    impl RevocationCheck for RevocationStore {
+        fn is_revoked_key(&self, key: &[u8]) -> bool {
+            self.current
+                .read()
+                .as_ref()
+                .is_some_and(|list| list.is_revoked_key(key))
+        }
+
+        fn is_revoked_device(&self, device_id: &str) -> bool {
+            self.current
+                .read()
+                .as_ref()
+                .is_some_and(|list| list.is_revoked_device(device_id))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::enrollment::{AuthorityKind, IdentityClaim};
+    use crate::crypto::identity::generate;
+    use crate::messages::DeviceIdentity;
+
+    fn root() -> Authority {
+        Authority::new("Venue root", AuthorityKind::Venue, generate())
+    }
+
+    #[test]
+    fn signed_list_round_trips_and_names_revoked_enrollments() {
+        let root = root();
+        let node = generate();
+        let identity = DeviceIdentity {
+            device_id: "node-7".into(),
+            manufacturer_id: "ALPINE".into(),
+            model_id: "dimmer".into(),
+            hardware_rev: "1".into(),
+            firmware_rev: "1.0".into(),
+        };
+        let claim = IdentityClaim::new(identity, &node, 0).unwrap();
+        let enrolled = root.enroll(&claim, 0, None).unwrap();
+
+        let list = RevocationList::issue(
+            &root,
+            1,
+            10,
+            vec![node.verifying.to_bytes().to_vec()],
+            Vec::new(),
+        )
+        .unwrap();
+        let decoded: RevocationList = codec::from_slice(&codec::to_vec(&list).unwrap()).unwrap();
+        assert_eq!(decoded.verify(&[root.credentials.verifying]), Ok(()));
+        assert!(decoded.revokes(&enrolled));
+        assert!(
+            !RevocationList::issue(&root, 2, 20, Vec::new(), vec!["node-8".into()])
+                .unwrap()
+                .revokes(&enrolled)
+        );
+    }
+
+    #[test]
+    fn tampered_or_foreign_lists_are_rejected() {
+        let root = root();
+        let mut list = RevocationList::issue(&root, 1, 10, Vec::new(), vec!["a".into()]).unwrap();
+        assert_eq!(
+            list.verify(&[generate().verifying]),
+            Err(EnrollmentError::UntrustedAuthority)
+        );
+        list.revoked_devices.clear();
+        assert_eq!(
+            list.verify(&[root.credentials.verifying]),
+            Err(EnrollmentError::BadSignature)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn store_only_moves_to_newer_lists() {
+        let root = root();
+        let store = RevocationStore::new(vec![root.credentials.verifying]);
+        let second = RevocationList::issue(&root, 2, 20, Vec::new(), vec!["b".into()]).unwrap();
+        let first = RevocationList::issue(&root, 1, 10, Vec::new(), vec!["a".into()]).unwrap();
+        assert!(!store.is_revoked_device("b"));
+        store.install(second).unwrap();
+        assert!(store.is_revoked_device("b"));
+        assert_eq!(
+            store.install(first),
+            Err(RevocationError::Stale {
+                installed: 2,
+                offered: 1
+            })
+        );
+        assert!(!store.is_revoked_device("a"));
+    }
+}
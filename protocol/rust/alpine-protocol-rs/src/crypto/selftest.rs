@@ -0,0 +1,254 @@
+//! Runtime known-answer tests for the primitives ALPINE is built on.
+//!
+//! [`self_test`] runs each primitive against a published test vector, so a node or
+//! controller can refuse to start when its build links a miscompiled or substituted crypto
+//! backend. Safety-critical venues run it at power-on and log the [`SelfTestReport`]
+//! together with the [`CipherSuite`] in use.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key};
+use ed25519_dalek::{Signer, SigningKey, Verifier};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret as X25519Secret};
+
+use super::{KeyExchangeAlgorithm, SuiteId};
+
+/// The algorithms protecting an ALPINE session.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CipherSuite {
+    pub id: SuiteId,
+    pub key_exchange: KeyExchangeAlgorithm,
+    pub signature: &'static str,
+    pub kdf: &'static str,
+    pub aead: &'static str,
+}
+
+/// The default suite, used by every peer that predates negotiation.
+pub const ALPINE_CIPHER_SUITE: CipherSuite = CipherSuite {
+    id: SuiteId::ChaCha20Poly1305,
+    key_exchange: KeyExchangeAlgorithm::X25519,
+    signature: "ed25519",
+    kdf: "hkdf-sha256",
+    aead: "chacha20-poly1305",
+};
+
+/// [`ALPINE_CIPHER_SUITE`] with AES-256-GCM, for nodes with AES hardware.
+pub const AES_256_GCM_SUITE: CipherSuite = CipherSuite {
+    id: SuiteId::Aes256Gcm,
+    aead: "aes-256-gcm",
+    ..ALPINE_CIPHER_SUITE
+};
+
+impl fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}/{}/{}/{}",
+            self.key_exchange, self.signature, self.kdf, self.aead
+        )
+    }
+}
+
+/// Outcome of one known-answer test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownAnswer {
+    pub algorithm: &'static str,
+    /// Where the test vector is published.
+    pub vector: &'static str,
+    pub passed: bool,
+}
+
+/// Outcome of [`self_test`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestReport {
+    /// Suites whose primitives were tested.
+    pub suites: Vec<CipherSuite>,
+    pub results: Vec<KnownAnswer>,
+}
+
+impl SelfTestReport {
+    /// Whether every known-answer test passed.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.passed)
+    }
+
+    /// Algorithms whose known-answer test failed.
+    pub fn failures(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.results
+            .iter()
+            .filter(|result| !result.passed)
+            .map(|result| result.algorithm)
+    }
+}
+
+/// Runs a known-answer test for each primitive of every suite this build supports.
+pub fn self_test() -> SelfTestReport {
+    SelfTestReport {
+        suites: vec![ALPINE_CIPHER_SUITE, AES_256_GCM_SUITE],
+        results: vec![
+            KnownAnswer {
+                algorithm: "ed25519",
+                vector: "RFC 8032 section 7.1, test 1",
+                passed: ed25519_kat(),
+            },
+            KnownAnswer {
+                algorithm: "x25519",
+                vector: "RFC 7748 section 6.1",
+                passed: x25519_kat(),
+            },
+            KnownAnswer {
+                algorithm: "hkdf-sha256",
+                vector: "RFC 5869 appendix A.1",
+                passed: hkdf_kat(),
+            },
+            KnownAnswer {
+                algorithm: "chacha20-poly1305",
+                vector: "RFC 8439 section 2.8.2",
+                passed: aead_kat(),
+            },
+            KnownAnswer {
+                algorithm: "aes-256-gcm",
+                vector: "NIST GCM specification, test case 14",
+                passed: aes_gcm_kat(),
+            },
+        ],
+    }
+}
+
+fn ed25519_kat() -> bool {
+    let secret: [u8; 32] = hex("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+        .try_into()
+        .unwrap_or([0; 32]);
+    let key = SigningKey::from_bytes(&secret);
+    let signature = key.sign(b"");
+    key.verifying_key().to_bytes()[..]
+        == hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")[..]
+        && signature.to_bytes()[..]
+            == hex(concat!(
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155",
+                "5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+            ))[..]
+        && key.verifying_key().verify(b"", &signature).is_ok()
+        && key.verifying_key().verify(b"x", &signature).is_err()
+}
+
+fn x25519_kat() -> bool {
+    let secret = |hex_str: &str| -> X25519Secret {
+        let bytes: [u8; 32] = hex(hex_str).try_into().unwrap_or([0; 32]);
+        X25519Secret::from(bytes)
+    };
+    let alice = secret("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+    let bob = secret("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+    let alice_public = X25519PublicKey::from(&alice);
+    let bob_public = X25519PublicKey::from(&bob);
+    let shared = hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    alice_public.as_bytes()[..]
+        == hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")[..]
+        && bob_public.as_bytes()[..]
+            == hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")[..]
+        && alice.diffie_hellman(&bob_public).as_bytes()[..] == shared[..]
+        && bob.diffie_hellman(&alice_public).as_bytes()[..] == shared[..]
+}
+
+fn hkdf_kat() -> bool {
+    let mut okm = [0u8; 42];
+    let expanded = Hkdf::<Sha256>::new(
+        Some(&hex("000102030405060708090a0b0c")),
+        &hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+    )
+    .expand(&hex("f0f1f2f3f4f5f6f7f8f9"), &mut okm);
+    expanded.is_ok()
+        && okm[..]
+            == hex(concat!(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf",
+                "34007208d5b887185865"
+            ))[..]
+}
+
+fn aead_kat() -> bool {
+    let key = hex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+    let nonce = hex("070000004041424344454647");
+    let aad = hex("50515253c0c1c2c3c4c5c6c7");
+    let plaintext: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+        only one tip for the future, sunscreen would be it.";
+    let expected = hex(concat!(
+        "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6",
+        "3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36",
+        "92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc",
+        "3ff4def08e4b7a9de576d26586cec64b6116"
+    ));
+    let tag = hex("1ae10b594f09e26a7e902ecbd0600691");
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let nonce = chacha20poly1305::Nonce::from_slice(&nonce);
+    let mut buffer = plaintext.to_vec();
+    let sealed = match cipher.encrypt_in_place_detached(nonce, &aad, &mut buffer) {
+        Ok(sealed) => sealed,
+        Err(_) => return false,
+    };
+    if buffer != expected || sealed[..] != tag[..] {
+        return false;
+    }
+    cipher
+        .decrypt_in_place_detached(nonce, &aad, &mut buffer, &sealed)
+        .is_ok()
+        && buffer == plaintext
+}
+
+fn aes_gcm_kat() -> bool {
+    let cipher = Aes256Gcm::new(&[0u8; 32].into());
+    let nonce = [0u8; 12].into();
+    let mut buffer = [0u8; 16];
+    match cipher.encrypt_in_place_detached(&nonce, b"", &mut buffer) {
+        Ok(tag) => {
+            buffer[..] == hex("cea7403d4d606b6e074ec5d3baf39d18")[..]
+                && tag[..] == hex("d0d1c8a799996bf0265b98b5d48ab919")[..]
+                && cipher
+                    .decrypt_in_place_detached(&nonce, b"", &mut buffer, &tag)
+                    .is_ok()
+                && buffer == [0u8; 16]
+        }
+        Err(_) => false,
+    }
+}
+
+/// Decodes the lowercase hex of a test vector.
+fn hex(digits: &str) -> Vec<u8> {
+    let nibble = |digit: u8| match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => 0,
+    };
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| (nibble(pair[0]) << 4) | nibble(pair[1]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_known_answer_test_passes() {
+        let report = self_test();
+        assert_eq!(report.failures().collect::<Vec<_>>(), Vec::<&str>::new());
+        assert!(report.passed());
+        assert_eq!(report.results.len(), 5);
+        assert_eq!(
+            report.suites[0].to_string(),
+            "X25519/ed25519/hkdf-sha256/chacha20-poly1305"
+        );
+        assert_eq!(
+            report.suites[1].to_string(),
+            "X25519/ed25519/hkdf-sha256/aes-256-gcm"
+        );
+    }
+}
@@ -1,15 +1,106 @@
-use crate::crypto::{identity::NodeCredentials, X25519KeyExchange};
-use crate::discovery::DiscoveryResponder;
-use crate::handshake::{HandshakeContext, HandshakeError, HandshakeTransport};
-use crate::messages::{CapabilitySet, DeviceIdentity};
-use crate::session::{AlnpSession, Ed25519Authenticator};
+use crate::codec;
+use crate::control::TokenBucket;
+use crate::crypto::{identity::NodeCredentials, SuiteId, X25519KeyExchange};
+use crate::discovery::{DiscoveryConfig, DiscoveryError, DiscoveryResponder};
+use crate::handshake::server::ServerHandshake;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::{Mutex, RwLock};
+use rand::{rngs::OsRng, RngCore};
+use tokio::net::UdpSocket;
+use tokio::task::JoinSet;
+
+use crate::handshake::{
+    reject, report_failure, FirmwarePolicy, HandshakeContext, HandshakeError, HandshakeMessage,
+    HandshakeTransport, RevocationCheck,
+};
+use crate::messages::{
+    CapabilitySet, DeviceIdentity, DiscoveryReply, DiscoveryRequest, MessageType, SessionRole,
+    DEFAULT_CONTROLLER_PRIORITY,
+};
+use crate::mirror::OutputMirror;
+use crate::session::limits::SessionLimits;
+use crate::session::quota::SessionQuotas;
+use crate::session::registry::{SessionAccess, SessionRegistry};
+use crate::session::resume::ResumptionCache;
+use crate::session::{AlnpRole, AlnpSession, Ed25519Authenticator, TimingConfig};
+use crate::stream::FrameValidator;
+
+/// A device setting that can be replaced while the node runs.
+///
+/// Clones share the same value, so every holder sees a replacement at once.
+#[derive(Debug, Clone, Default)]
+pub struct Shared<T>(Arc<RwLock<T>>);
+
+impl<T: Clone> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(RwLock::new(value)))
+    }
+
+    /// A copy of the value in use now.
+    pub fn current(&self) -> T {
+        self.0.read().clone()
+    }
+
+    /// Swaps in `value` and returns the previous one.
+    pub fn replace(&self, value: T) -> T {
+        std::mem::replace(&mut *self.0.write(), value)
+    }
+}
+
+impl<T: Clone> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+/// Node credentials, replaced by [`DeviceServer::rotate_credentials`].
+pub type SharedCredentials = Shared<NodeCredentials>;
+
+/// Advertised capabilities, replaced by [`DeviceServer::update_capabilities`].
+pub type SharedCapabilities = Shared<CapabilitySet>;
 
 /// Minimal device-side server skeleton that wires discovery + handshake together.
 pub struct DeviceServer {
     pub identity: DeviceIdentity,
     pub mac_address: String,
-    pub capabilities: CapabilitySet,
-    pub credentials: NodeCredentials,
+    /// Capabilities advertised in discovery and offered in handshakes; replace them with
+    /// [`DeviceServer::update_capabilities`].
+    pub capabilities: SharedCapabilities,
+    /// Identity key signing discovery replies and handshakes; replace it with
+    /// [`DeviceServer::rotate_credentials`].
+    pub credentials: SharedCredentials,
+    /// Timing applied to every accepted session.
+    pub timing: TimingConfig,
+    /// Age and frames-per-key limits applied to every accepted session.
+    pub limits: SessionLimits,
+    /// Sessions a restarted controller may resume; every accepted session is added.
+    pub resumption: ResumptionCache,
+    /// Keys refused during the handshake, usually a shared `RevocationStore`.
+    pub revocations: Option<Arc<dyn RevocationCheck>>,
+    /// Cipher suites the node accepts, most preferred first; put `Aes256Gcm` first on
+    /// hardware with AES acceleration.
+    pub cipher_suites: Vec<SuiteId>,
+    /// Oldest controller firmware the node accepts; see
+    /// [`HandshakeContext::required_firmware_rev`].
+    pub required_firmware_rev: Option<String>,
+    /// Decides whether controllers below `required_firmware_rev` may connect anyway.
+    pub firmware_policy: Option<Arc<dyn FirmwarePolicy>>,
+    /// Sessions open at once, each with its own keys, sequence window, and access; every
+    /// accepted session is added with [`SessionAccess::Full`], or
+    /// [`SessionAccess::Monitor`] for observers.
+    pub sessions: SessionRegistry,
+    /// Frame, control, and metadata budgets each session gets on its own.
+    pub quotas: SessionQuotas,
+    /// Lets a controller stating a higher priority take a full node over from the
+    /// lowest-priority session, which keeps streaming this long; `None` refuses it like
+    /// any other controller. See [`SessionRegistry::admit`].
+    pub preemption_grace: Option<Duration>,
+    /// Latest applied look per universe, mirrored to observer sessions that subscribe
+    /// with `subscribe_output`; its rate caps how often each observer is sent a look.
+    pub mirror: OutputMirror,
 }
 
 impl DeviceServer {
@@ -18,26 +109,207 @@ impl DeviceServer {
         DiscoveryResponder {
             identity: self.identity.clone(),
             mac_address: self.mac_address.clone(),
-            capabilities: self.capabilities.clone(),
-            signer: self.credentials.signing.clone(),
+            capabilities: self.capabilities.current(),
+            signer: self.credentials.current().signing,
+        }
+    }
+
+    /// Signed reply to `request` with the current capabilities and credentials, marked
+    /// busy while [`DeviceServer::sessions`] is full.
+    pub fn discovery_reply(&self, request: &DiscoveryRequest) -> DiscoveryReply {
+        let mut server_nonce = vec![0u8; 32];
+        OsRng.fill_bytes(&mut server_nonce);
+        let mut reply = self
+            .discovery_responder()
+            .reply(server_nonce, &request.client_nonce);
+        reply.busy = Some(self.sessions.is_full());
+        reply
+    }
+
+    /// Answers discovery on every address in `config` until one of its sockets fails.
+    ///
+    /// Handshakes are accepted separately with [`DeviceServer::accept`]. Requests beyond
+    /// the configured reply rate are dropped; controllers repeat discovery on their own
+    /// timeout. Returns at once if `config` lists no address.
+    pub async fn run(self: Arc<Self>, config: DiscoveryConfig) -> Result<(), DiscoveryError> {
+        let budget = config
+            .reply_rate
+            .map(|limit| Arc::new(Mutex::new(TokenBucket::new(limit, Instant::now()))));
+        let mut listeners = JoinSet::new();
+        for addr in &config.listen {
+            let socket = UdpSocket::bind(addr)
+                .await
+                .map_err(|e| DiscoveryError::Io(format!("bind {}: {}", addr, e)))?;
+            listeners.spawn(self.clone().answer_discovery(socket, budget.clone()));
         }
+        match listeners.join_next().await {
+            Some(Ok(result)) => result,
+            Some(Err(err)) => Err(DiscoveryError::Io(err.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    async fn answer_discovery(
+        self: Arc<Self>,
+        socket: UdpSocket,
+        budget: Option<Arc<Mutex<TokenBucket>>>,
+    ) -> Result<(), DiscoveryError> {
+        let mut buf = vec![0u8; 2048];
+        loop {
+            let (len, from) = socket
+                .recv_from(&mut buf)
+                .await
+                .map_err(|e| DiscoveryError::Io(e.to_string()))?;
+            let Ok(request) = codec::decode_untrusted::<DiscoveryRequest>(&buf[..len]) else {
+                continue;
+            };
+            if request.message_type != MessageType::AlpineDiscover
+                || budget
+                    .as_ref()
+                    .is_some_and(|budget| budget.lock().try_take(Instant::now()).is_err())
+            {
+                continue;
+            }
+            if let Ok(bytes) = codec::to_vec(&self.discovery_reply(&request)) {
+                // Best-effort: the controller retries discovery on its own timeout.
+                let _ = socket.send_to(&bytes, from).await;
+            }
+        }
+    }
+
+    /// Replaces the advertised capabilities and returns the previous ones.
+    ///
+    /// Discovery replies, new handshakes, and the validators of sessions accepted from
+    /// then on use them at once; open sessions keep what they negotiated.
+    pub fn update_capabilities(&self, capabilities: CapabilitySet) -> CapabilitySet {
+        self.capabilities.replace(capabilities)
+    }
+
+    /// Installs a new identity key and returns the one it replaces.
+    ///
+    /// Discovery replies and handshakes, including resumptions, are signed with the new
+    /// key from the moment this returns; a handshake already past its signature finishes
+    /// under the old one. Open sessions keep their session keys and are served until they
+    /// re-handshake. Build responders with [`DeviceServer::discovery_responder`] per reply,
+    /// or rebuild them after rotating, so they do not keep signing with the old key.
+    pub fn rotate_credentials(&self, credentials: NodeCredentials) -> NodeCredentials {
+        self.credentials.replace(credentials)
+    }
+
+    /// Build the validator for one session's frames: the advertised capabilities plus
+    /// [`DeviceServer::quotas`].
+    pub fn frame_validator(&self) -> FrameValidator {
+        FrameValidator::new(&self.capabilities.current()).with_quotas(&self.quotas)
     }
 
     /// Accept an inbound session using the provided transport.
+    ///
+    /// Controllers holding a ticket from [`DeviceServer::resumption`] may resume instead
+    /// of running the full handshake. Once [`DeviceServer::sessions`] is full the next
+    /// controller is told the node is busy instead of getting a handshake, unless
+    /// [`DeviceServer::preemption_grace`] lets it take over a lower-priority session; the
+    /// takeover is then recorded in the registry. Sessions asking for
+    /// [`SessionRole::Observer`] are admitted with [`SessionAccess::Monitor`] and never
+    /// take over another. A controller arriving while the
+    /// registry's handshake limit is taken is told the same, whatever its priority; both
+    /// refusals carry the registry's retry-after hint and fail with
+    /// [`HandshakeError::Busy`].
     pub async fn accept<T: HandshakeTransport + Send>(
         &self,
         transport: &mut T,
     ) -> Result<AlnpSession, HandshakeError> {
-        let authenticator = Ed25519Authenticator::new(self.credentials.clone());
-        let key_exchange = X25519KeyExchange::new();
-        AlnpSession::accept(
-            self.identity.clone(),
-            self.capabilities.clone(),
-            authenticator,
-            key_exchange,
-            HandshakeContext::default(),
+        let mut transport = Replay {
+            first: None,
             transport,
-        )
-        .await
+        };
+        let slot = self.sessions.begin_handshake();
+        if slot.is_none() || self.sessions.is_full() {
+            let first = transport.recv().await?;
+            let (session_id, priority, role, nonce) = match &first {
+                HandshakeMessage::SessionInit(init) => (
+                    Some(init.session_id),
+                    init.controller_priority,
+                    init.role,
+                    Some(init.controller_nonce.clone()),
+                ),
+                HandshakeMessage::SessionResume(resume) => (
+                    Some(resume.session_id),
+                    resume.controller_priority,
+                    resume.role,
+                    None,
+                ),
+                _ => (None, None, None, None),
+            };
+            let priority = priority.unwrap_or(DEFAULT_CONTROLLER_PRIORITY);
+            let admitted = slot.is_some()
+                && (!self.sessions.is_full()
+                    || self.preemption_grace.is_some()
+                        && session_id.is_some()
+                        && role != Some(SessionRole::Observer)
+                        && self.sessions.preemptible(priority).is_some());
+            if !admitted {
+                let err = HandshakeError::Busy(self.sessions.retry_after());
+                // A full handshake gets a signed refusal; resumption has no identity check
+                // to verify one against.
+                return Err(match (session_id, nonce) {
+                    (Some(session_id), Some(nonce)) => {
+                        let authenticator = Ed25519Authenticator::new(self.credentials.current());
+                        reject(&mut transport, &authenticator, &nonce, session_id, err).await
+                    }
+                    _ => report_failure(&mut transport, session_id, err).await,
+                });
+            }
+            transport.first = Some(first);
+        }
+        let transport = &mut transport;
+        let session = AlnpSession::start_handshake(AlnpRole::Node, self.timing)?;
+        let driver = ServerHandshake {
+            identity: self.identity.clone(),
+            capabilities: self.capabilities.current(),
+            authenticator: Ed25519Authenticator::new(self.credentials.current()),
+            key_exchange: X25519KeyExchange::new(),
+            context: HandshakeContext {
+                revocations: self.revocations.clone(),
+                cipher_suites: self.cipher_suites.clone(),
+                required_firmware_rev: self.required_firmware_rev.clone(),
+                firmware_policy: self.firmware_policy.clone(),
+                ..HandshakeContext::default()
+            },
+        };
+        let outcome = driver
+            .run_with_resumption(transport, &self.resumption)
+            .await?;
+        drop(slot);
+        let role = outcome.established.role();
+        session.finish_handshake(outcome)?;
+        session.set_limits(self.limits);
+        // Observers are held to monitoring and never take a controller's place.
+        let (access, grace) = match role {
+            SessionRole::Controller => (SessionAccess::Full, self.preemption_grace),
+            SessionRole::Observer => (SessionAccess::Monitor, None),
+        };
+        self.sessions.admit(&session, access, grace)?;
+        self.resumption.insert(&session)?;
+        Ok(session)
+    }
+}
+
+/// Hands the handshake driver a message the node already read, then reads on.
+struct Replay<'a, T> {
+    first: Option<HandshakeMessage>,
+    transport: &'a mut T,
+}
+
+#[async_trait]
+impl<T: HandshakeTransport + Send> HandshakeTransport for Replay<'_, T> {
+    async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        self.transport.send(msg).await
+    }
+
+    async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+        match self.first.take() {
+            Some(msg) => Ok(msg),
+            None => self.transport.recv().await,
+        }
     }
 }
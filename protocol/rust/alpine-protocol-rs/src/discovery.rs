@@ -5,6 +5,8 @@ use rand::{rngs::OsRng, RngCore};
 use thiserror::Error;
 use tokio::net::UdpSocket;
 
+use crate::codec;
+use crate::control::RateLimit;
 use crate::messages::{CapabilitySet, DiscoveryReply, DiscoveryRequest, MessageType};
 
 #[derive(Debug, Error)]
@@ -33,8 +35,7 @@ impl DiscoveryClient {
         let mut nonce = vec![0u8; 32];
         OsRng.fill_bytes(&mut nonce);
         let request = DiscoveryRequest::new(requested, nonce.clone());
-        let bytes =
-            serde_cbor::to_vec(&request).map_err(|e| DiscoveryError::Decode(e.to_string()))?;
+        let bytes = codec::to_vec(&request).map_err(|e| DiscoveryError::Decode(e.to_string()))?;
         socket
             .send_to(&bytes, broadcast)
             .await
@@ -52,13 +53,38 @@ impl DiscoveryClient {
             .recv_from(&mut buf)
             .await
             .map_err(|e| DiscoveryError::Io(e.to_string()))?;
-        let reply: DiscoveryReply = serde_cbor::from_slice(&buf[..len])
+        let reply: DiscoveryReply = codec::decode_untrusted(&buf[..len])
             .map_err(|e| DiscoveryError::Decode(e.to_string()))?;
         verify_reply(&reply, expected_nonce, verifier)?;
         Ok(reply)
     }
 }
 
+/// Where and how fast [`crate::device::DeviceServer::run`] answers discovery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryConfig {
+    /// Addresses to listen on. Bind `0.0.0.0:port` to hear broadcasts on every
+    /// interface, or one address per interface to answer only on those.
+    pub listen: Vec<SocketAddr>,
+    /// Replies sent per second across all listeners; requests beyond it are dropped, so
+    /// a flood of requests cannot keep the node signing replies.
+    pub reply_rate: Option<RateLimit>,
+}
+
+impl DiscoveryConfig {
+    pub fn new(listen: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self {
+            listen: listen.into_iter().collect(),
+            reply_rate: None,
+        }
+    }
+
+    pub fn with_reply_rate(mut self, limit: RateLimit) -> Self {
+        self.reply_rate = Some(limit);
+        self
+    }
+}
+
 /// Device-side responder skeleton.
 pub struct DiscoveryResponder {
     pub identity: crate::messages::DeviceIdentity,
@@ -82,11 +108,62 @@ impl DiscoveryResponder {
     }
 }
 
+/// Verifies replies to the request that carried `client_nonce`, such as the hundreds a
+/// console receives when a rig is discovered at load-in.
+///
+/// Each reply is paired with the key of the device it claims to be. The signatures are
+/// checked in one Ed25519 batch, which costs far less than checking them one by one; if
+/// the batch fails, every reply is checked on its own so one forged reply does not reject
+/// the rest. Results are in the order of `replies`.
+pub fn verify_replies(
+    replies: &[(&DiscoveryReply, &VerifyingKey)],
+    client_nonce: &[u8],
+) -> Vec<Result<(), DiscoveryError>> {
+    let mut results = Vec::with_capacity(replies.len());
+    let mut pending = Vec::new();
+    for (index, (reply, key)) in replies.iter().enumerate() {
+        match parse_reply(reply, client_nonce) {
+            Ok((data, signature)) => {
+                pending.push((index, data, signature, **key));
+                results.push(Ok(()));
+            }
+            Err(err) => results.push(Err(err)),
+        }
+    }
+
+    let messages: Vec<&[u8]> = pending
+        .iter()
+        .map(|(_, data, _, _)| data.as_slice())
+        .collect();
+    let signatures: Vec<Signature> = pending.iter().map(|(_, _, sig, _)| *sig).collect();
+    let keys: Vec<VerifyingKey> = pending.iter().map(|(_, _, _, key)| *key).collect();
+    if ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_err() {
+        for (index, data, signature, key) in &pending {
+            if key.verify(data, signature).is_err() {
+                results[*index] = Err(DiscoveryError::InvalidSignature);
+            }
+        }
+    }
+    results
+}
+
 fn verify_reply(
     reply: &DiscoveryReply,
     expected_client_nonce: &[u8],
     verifier: &VerifyingKey,
 ) -> Result<(), DiscoveryError> {
+    let (data, sig) = parse_reply(reply, expected_client_nonce)?;
+    verifier
+        .verify(&data, &sig)
+        .map_err(|_| DiscoveryError::InvalidSignature)?;
+    Ok(())
+}
+
+/// Checks the reply's version and returns the signed bytes with the signature.
+fn parse_reply(
+    reply: &DiscoveryReply,
+    expected_client_nonce: &[u8],
+) -> Result<(Vec<u8>, Signature), DiscoveryError> {
     if reply.message_type != MessageType::AlpineDiscoverReply {
         return Err(DiscoveryError::UnsupportedVersion);
     }
@@ -99,8 +176,51 @@ fn verify_reply(
     data.extend_from_slice(expected_client_nonce);
     let sig =
         Signature::from_slice(&reply.signature).map_err(|_| DiscoveryError::InvalidSignature)?;
-    verifier
-        .verify(&data, &sig)
-        .map_err(|_| DiscoveryError::InvalidSignature)?;
-    Ok(())
+    Ok((data, sig))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::identity::generate;
+    use crate::messages::DeviceIdentity;
+
+    fn responder(index: usize) -> DiscoveryResponder {
+        DiscoveryResponder {
+            identity: DeviceIdentity {
+                device_id: format!("node-{}", index),
+                manufacturer_id: "ALPINE".into(),
+                model_id: "dimmer".into(),
+                hardware_rev: "1".into(),
+                firmware_rev: "1.0".into(),
+            },
+            mac_address: "AA:BB:CC:DD:EE:FF".into(),
+            capabilities: CapabilitySet::default(),
+            signer: generate().signing,
+        }
+    }
+
+    #[test]
+    fn batch_isolates_forged_replies() {
+        let client_nonce = [7u8; 32];
+        let responders: Vec<_> = (0..8).map(responder).collect();
+        let keys: Vec<_> = responders
+            .iter()
+            .map(|r| r.signer.verifying_key())
+            .collect();
+        let mut replies: Vec<_> = responders
+            .iter()
+            .map(|r| r.reply(vec![1; 32], &client_nonce))
+            .collect();
+        let pairs: Vec<_> = replies.iter().zip(&keys).collect();
+        assert!(verify_replies(&pairs, &client_nonce)
+            .iter()
+            .all(|result| result.is_ok()));
+
+        replies[3].server_nonce[0] ^= 1;
+        let pairs: Vec<_> = replies.iter().zip(&keys).collect();
+        let results = verify_replies(&pairs, &client_nonce);
+        assert!(matches!(results[3], Err(DiscoveryError::InvalidSignature)));
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 7);
+    }
 }
@@ -0,0 +1,191 @@
+//! Channel group registry.
+//!
+//! Instead of re-sending a `groups` map in every frame, controllers register named groups
+//! on the node once with `define_group` and reference them by id in a frame's
+//! `group_refs`. The registry lives in the device config, so every controller addressing
+//! the node sees the same "stage-left truss".
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::{ConfigError, SharedConfig};
+use crate::control::ControlHandlers;
+use crate::messages::{ControlOp, FrameEnvelope, GroupId, Map};
+
+/// Named set of channel indices; payload of `define_group`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChannelGroup {
+    pub id: GroupId,
+    pub name: String,
+    pub channels: Vec<u16>,
+}
+
+/// Payload of `remove_group`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoveGroup {
+    pub id: GroupId,
+}
+
+/// Failures changing the registry or resolving group references.
+#[derive(Debug, Error)]
+pub enum GroupError {
+    #[error("group name must not be empty")]
+    EmptyName,
+    #[error("group name {0:?} is already used by another id")]
+    DuplicateName(String),
+    #[error("unknown group {0}")]
+    Unknown(GroupId),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+/// Node-side registry of channel groups, persisted in the device config.
+#[derive(Debug, Clone)]
+pub struct GroupRegistry {
+    config: SharedConfig,
+}
+
+impl GroupRegistry {
+    pub fn new(config: SharedConfig) -> Self {
+        Self { config }
+    }
+
+    /// Registers `group`, replacing any group with the same id.
+    pub fn define(&self, group: ChannelGroup) -> Result<(), GroupError> {
+        if group.name.is_empty() {
+            return Err(GroupError::EmptyName);
+        }
+        let taken = self.config.read(|config| {
+            config
+                .groups
+                .values()
+                .any(|other| other.id != group.id && other.name == group.name)
+        });
+        if taken {
+            return Err(GroupError::DuplicateName(group.name));
+        }
+        self.config.update(|config| {
+            config.groups.insert(group.id, group);
+        })?;
+        Ok(())
+    }
+
+    pub fn remove(&self, id: GroupId) -> Result<(), GroupError> {
+        self.config.update(|config| {
+            config.groups.remove(&id);
+        })?;
+        Ok(())
+    }
+
+    /// Registered groups, ordered by id.
+    pub fn list(&self) -> Vec<ChannelGroup> {
+        self.config
+            .read(|config| config.groups.values().cloned().collect())
+    }
+
+    /// Expands `frame.group_refs` into entries of `frame.groups`, so receivers only deal
+    /// with inline groups.
+    ///
+    /// Fails without changing the frame if any reference is unknown.
+    pub fn resolve(&self, frame: &mut FrameEnvelope) -> Result<(), GroupError> {
+        let Some(refs) = frame.group_refs.as_ref() else {
+            return Ok(());
+        };
+        let resolved = self.config.read(|config| {
+            refs.iter()
+                .map(|id| {
+                    config
+                        .groups
+                        .get(id)
+                        .map(|group| (group.name.clone(), group.channels.clone()))
+                        .ok_or(GroupError::Unknown(*id))
+                })
+                .collect::<Result<BTreeMap<_, _>, _>>()
+        })?;
+        frame.groups.get_or_insert_with(Map::new).extend(resolved);
+        frame.group_refs = None;
+        Ok(())
+    }
+
+    /// Registers handlers for `define_group`, `remove_group`, and `list_groups`.
+    ///
+    /// `list_groups` answers with a JSON array of [`ChannelGroup`] in the ack detail.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let registry = self.clone();
+        handlers.on(ControlOp::DefineGroup, move |group: ChannelGroup| {
+            registry
+                .define(group)
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+        let registry = self.clone();
+        handlers.on(ControlOp::RemoveGroup, move |req: RemoveGroup| {
+            registry
+                .remove(req.id)
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+        let registry = self.clone();
+        handlers.on(ControlOp::ListGroups, move |_: serde_json::Value| {
+            serde_json::to_string(&registry.list())
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ChannelFormat, MessageType};
+    use uuid::Uuid;
+
+    #[test]
+    fn frames_reference_registered_groups_by_id() {
+        let registry = GroupRegistry::new(SharedConfig::memory());
+        let truss = |id, name: &str| ChannelGroup {
+            id,
+            name: name.into(),
+            channels: vec![0, 1, 2],
+        };
+        registry.define(truss(7, "stage-left truss")).unwrap();
+        assert!(matches!(
+            registry.define(truss(8, "stage-left truss")),
+            Err(GroupError::DuplicateName(_))
+        ));
+
+        let mut frame = FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::new_v4(),
+            universe: 0,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format: ChannelFormat::U8,
+            channels: vec![255; 4].into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: Some(vec![7, 9]),
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        };
+        assert!(matches!(
+            registry.resolve(&mut frame),
+            Err(GroupError::Unknown(9))
+        ));
+        frame.group_refs = Some(vec![7]);
+        registry.resolve(&mut frame).unwrap();
+        assert_eq!(frame.group_refs, None);
+        assert_eq!(
+            frame.groups.unwrap().get("stage-left truss"),
+            Some(&vec![0, 1, 2])
+        );
+    }
+}
@@ -1,14 +1,19 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::ToString;
+
 use async_trait::async_trait;
 use uuid::Uuid;
 
 use super::{
-    HandshakeContext, HandshakeError, HandshakeMessage, HandshakeOutcome, HandshakeParticipant,
-    HandshakeTransport,
+    bind_channel, bind_suites, check_firmware, report_failure, select_suite, unexpected,
+    verify_rejection, HandshakeContext, HandshakeError, HandshakeMessage, HandshakeOutcome,
+    HandshakeParticipant, HandshakeTransport,
 };
 use crate::crypto::{compute_mac, KeyExchange};
 use crate::messages::{
-    CapabilitySet, DeviceIdentity, MessageType, SessionAck, SessionEstablished, SessionInit,
-    SessionReady,
+    CapabilitySet, DeviceIdentity, ErrorEnvelope, MessageType, SessionAck, SessionEstablished,
+    SessionInit, SessionReady, SessionRejected,
 };
 
 /// Controller-side handshake driver implementing the ALPINE 1.0 flow.
@@ -34,8 +39,25 @@ where
         &self,
         transport: &mut T,
     ) -> Result<HandshakeOutcome, HandshakeError> {
-        let controller_nonce = super::new_nonce().to_vec();
-        let session_id = Uuid::new_v4();
+        let session_id = self.context.entropy.session_id();
+        match self.exchange(transport, session_id).await {
+            Ok(outcome) => Ok(outcome),
+            Err(err) => Err(report_failure(transport, Some(session_id), err).await),
+        }
+    }
+}
+
+impl<A, K> ClientHandshake<A, K>
+where
+    A: super::ChallengeAuthenticator + Send + Sync,
+    K: KeyExchange + Send + Sync,
+{
+    async fn exchange<T: HandshakeTransport + Send>(
+        &self,
+        transport: &mut T,
+        session_id: Uuid,
+    ) -> Result<HandshakeOutcome, HandshakeError> {
+        let controller_nonce = self.context.entropy.nonce("controller-nonce").to_vec();
 
         // 1) Controller -> device: session_init
         let init = SessionInit {
@@ -44,20 +66,33 @@ where
             controller_pubkey: self.key_exchange.public_key(),
             requested: self.capabilities.clone(),
             session_id,
+            suites: self.context.cipher_suites.clone(),
+            controller_firmware_rev: Some(self.identity.firmware_rev.clone()),
+            controller_priority: self.context.controller_priority,
+            role: self.context.role,
         };
         transport.send(HandshakeMessage::SessionInit(init)).await?;
 
         // 2) Device -> controller: session_ack
         let ack = match transport.recv().await? {
             HandshakeMessage::SessionAck(ack) => ack,
-            other => {
-                return Err(HandshakeError::Protocol(format!(
-                    "expected SessionAck, got {:?}",
-                    other
-                )))
+            HandshakeMessage::SessionRejected(rejection) => {
+                return Err(self.rejected(&rejection, session_id, &controller_nonce))
             }
+            other => return Err(unexpected("SessionAck", other)),
         };
         validate_ack(&ack, session_id, &controller_nonce, &self.context)?;
+        if let Some(revocations) = &self.context.revocations {
+            let key_revoked = self
+                .authenticator
+                .verifying_key()
+                .is_some_and(|key| revocations.is_revoked_key(&key));
+            if key_revoked || revocations.is_revoked_device(&ack.device_identity.device_id) {
+                return Err(HandshakeError::Authentication(
+                    "device credentials revoked".into(),
+                ));
+            }
+        }
 
         // 3) Verify device signature over the controller nonce.
         let sig_valid = self
@@ -68,17 +103,35 @@ where
                 "device signature validation failed".into(),
             ));
         }
+        check_firmware(
+            &self.context,
+            Some(ack.device_identity.firmware_rev.as_str()),
+        )?;
 
         // 4) Derive shared keys (HKDF over concatenated nonces).
         let mut salt = controller_nonce.clone();
         salt.extend_from_slice(&ack.device_nonce);
-        let keys = self
+        let mut keys = self
             .key_exchange
             .derive_keys(&ack.device_pubkey, &salt)
             .map_err(|e| HandshakeError::Authentication(format!("{}", e)))?;
+        keys.suite = ack.suite;
+        bind_channel(
+            &mut keys,
+            &session_id,
+            &salt,
+            &self.key_exchange.public_key(),
+            &ack.device_pubkey,
+            &self.context.cipher_suites,
+            self.context.controller_priority,
+            self.context.role,
+        );
 
-        // 5) Controller -> device: session_ready (MAC proves key possession).
-        let mac = compute_mac(&keys, 0, session_id.as_bytes(), ack.device_nonce.as_slice())
+        // 5) Controller -> device: session_ready (MAC proves key possession and covers the
+        // suite negotiation).
+        let mut aad = ack.device_nonce.clone();
+        bind_suites(&mut aad, &self.context.cipher_suites, ack.suite);
+        let mac = compute_mac(&keys, 0, session_id.as_bytes(), &aad)
             .map_err(|e| HandshakeError::Authentication(e.to_string()))?;
         let ready = SessionReady {
             message_type: MessageType::SessionReady,
@@ -92,17 +145,20 @@ where
         // 6) Device -> controller: session_complete
         let complete = match transport.recv().await? {
             HandshakeMessage::SessionComplete(c) => c,
-            other => {
-                return Err(HandshakeError::Protocol(format!(
-                    "expected SessionComplete, got {:?}",
-                    other
-                )))
+            HandshakeMessage::SessionRejected(rejection) => {
+                return Err(self.rejected(&rejection, session_id, &controller_nonce))
             }
+            other => return Err(unexpected("SessionComplete", other)),
         };
         if !complete.ok {
-            return Err(HandshakeError::Authentication(
-                "device rejected session_ready".into(),
-            ));
+            return Err(match complete.error {
+                Some(code) => HandshakeError::Remote(ErrorEnvelope::new(
+                    Some(session_id),
+                    code,
+                    Some("device rejected session_ready".into()),
+                )),
+                None => HandshakeError::Authentication("device rejected session_ready".into()),
+            });
         }
 
         let established = SessionEstablished {
@@ -111,10 +167,24 @@ where
             device_nonce: ack.device_nonce,
             capabilities: ack.capabilities,
             device_identity: ack.device_identity,
+            extensions: None,
+            controller_priority: self.context.controller_priority,
+            role: self.context.role,
         };
 
         Ok(HandshakeOutcome { established, keys })
     }
+
+    /// Error for a node's rejection of this handshake; one the node's key did not sign is
+    /// an authentication failure.
+    fn rejected(
+        &self,
+        rejection: &SessionRejected,
+        session_id: Uuid,
+        controller_nonce: &[u8],
+    ) -> HandshakeError {
+        verify_rejection(&self.authenticator, rejection, session_id, controller_nonce)
+    }
 }
 
 fn validate_ack(
@@ -135,6 +205,13 @@ fn validate_ack(
         ));
     }
 
+    if select_suite(&[ack.suite], &context.cipher_suites).is_err() {
+        return Err(HandshakeError::Capability(format!(
+            "device chose cipher suite {:?}, which was not offered",
+            ack.suite
+        )));
+    }
+
     if let Some(expected) = &context.expected_controller {
         if expected != &session_id.to_string() {
             return Err(HandshakeError::Authentication(
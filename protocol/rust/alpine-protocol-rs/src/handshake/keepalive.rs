@@ -1,11 +1,13 @@
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 use tokio::time;
 
 use super::{HandshakeMessage, HandshakeTransport};
 use crate::messages::{Keepalive, MessageType};
+use crate::session::AlnpSession;
 
 /// Spawns a keepalive task that periodically pushes Keepalive frames on the control channel.
 pub async fn spawn_keepalive<T>(
@@ -15,18 +17,61 @@ pub async fn spawn_keepalive<T>(
 ) where
     T: HandshakeTransport + Send + 'static,
 {
-    tokio::spawn(async move {
+    tokio::spawn(run(
+        transport,
+        interval,
+        session_id,
+        None,
+        std::future::pending(),
+    ));
+}
+
+/// Sends keepalives carrying the session's stream counters (see
+/// [`crate::messages::KeepaliveStats`]) until `shutdown` turns true or its sender is
+/// dropped.
+///
+/// Runs on the caller's task, so awaiting the task joins the loop itself. Shutdown is
+/// only observed between keepalives: one already being sent finishes first and leaves
+/// the transport free for a closing message.
+pub async fn keepalive_until<T>(
+    transport: Arc<Mutex<T>>,
+    interval: Duration,
+    session_id: uuid::Uuid,
+    session: AlnpSession,
+    mut shutdown: watch::Receiver<bool>,
+) where
+    T: HandshakeTransport + Send + 'static,
+{
+    let stop = async move {
+        let _ = shutdown.wait_for(|stop| *stop).await;
+    };
+    run(transport, interval, session_id, Some(session), stop).await;
+}
+
+async fn run<T>(
+    transport: Arc<Mutex<T>>,
+    interval: Duration,
+    session_id: uuid::Uuid,
+    session: Option<AlnpSession>,
+    stop: impl Future<Output = ()>,
+) where
+    T: HandshakeTransport + Send + 'static,
+{
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            _ = &mut stop => return,
+            _ = time::sleep(interval) => {}
+        }
         let payload = HandshakeMessage::Keepalive(Keepalive {
             message_type: MessageType::Keepalive,
             session_id,
             tick_ms: interval.as_millis() as u64,
+            stats: session.as_ref().map(AlnpSession::keepalive_stats),
         });
-        loop {
-            time::sleep(interval).await;
-            let mut guard = transport.lock().await;
-            if let Err(_e) = guard.send(payload.clone()).await {
-                // Best-effort; log or trace hook could be added here.
-            }
+        let mut guard = transport.lock().await;
+        if let Err(_e) = guard.send(payload).await {
+            // Best-effort; log or trace hook could be added here.
         }
-    });
+    }
 }
@@ -1,17 +1,36 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::time::Duration;
+
 use async_trait::async_trait;
-use rand::{rngs::OsRng, RngCore};
+use hkdf::Hkdf;
+#[cfg(feature = "std")]
+use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
-use thiserror::Error;
+use sha2::Sha256;
+use uuid::Uuid;
 
-use crate::crypto::{KeyExchangeAlgorithm, SessionKeys};
+use crate::crypto::{
+    channel_binding, KeyExchangeAlgorithm, SessionKeys, SuiteId, SUPPORTED_SUITES,
+};
 use crate::messages::{
-    Acknowledge, ControlEnvelope, Keepalive, SessionAck, SessionComplete, SessionEstablished,
-    SessionInit, SessionReady,
+    Acknowledge, CapabilitySet, CapabilityUpdate, ControlEnvelope, ControlProgress, ErrorCode,
+    ErrorEnvelope, Keepalive, MessageType, MirroredOutput, SessionAck, SessionClose,
+    SessionComplete, SessionEstablished, SessionInit, SessionPreempted, SessionReady,
+    SessionRejected, SessionResume, SessionResumeAck, SessionRole,
 };
 
 pub mod client;
+#[cfg(feature = "std")]
 pub mod keepalive;
+pub mod resume;
 pub mod server;
+#[cfg(feature = "std")]
 pub mod transport;
 
 /// Transport abstraction used during the ALNP handshake.
@@ -32,6 +51,15 @@ pub enum HandshakeMessage {
     Keepalive(Keepalive),
     Control(ControlEnvelope),
     Ack(Acknowledge),
+    Error(ErrorEnvelope),
+    SessionResume(SessionResume),
+    SessionResumeAck(SessionResumeAck),
+    Progress(ControlProgress),
+    CapabilityUpdate(CapabilityUpdate),
+    SessionPreempted(SessionPreempted),
+    SessionClose(SessionClose),
+    SessionRejected(SessionRejected),
+    MirroredOutput(MirroredOutput),
 }
 
 /// Context shared between handshake participants.
@@ -39,32 +67,465 @@ pub enum HandshakeMessage {
 pub struct HandshakeContext {
     pub key_algorithm: KeyExchangeAlgorithm,
     pub expected_controller: Option<String>,
+    /// Oldest firmware the peer may run, compared with [`compare_firmware`]: the node's
+    /// `firmware_rev` for controllers, the controller's for nodes.
     pub required_firmware_rev: Option<String>,
+    /// Consulted when the peer runs older firmware; without one the handshake fails with
+    /// [`HandshakeError::Firmware`].
+    pub firmware_policy: Option<Arc<dyn FirmwarePolicy>>,
+    pub entropy: HandshakeEntropy,
+    /// Revoked keys and devices to refuse, usually a shared `RevocationStore`.
+    pub revocations: Option<Arc<dyn RevocationCheck>>,
+    /// Cipher suites this participant accepts, most preferred first. Controllers offer them
+    /// in this order; nodes choose the first one the controller offered.
+    pub cipher_suites: Vec<SuiteId>,
+    /// Priority controllers state in `session_init` and `session_resume`; unused on nodes.
+    pub controller_priority: Option<u8>,
+    /// Role controllers ask for in `session_init` and `session_resume`; unused on nodes.
+    pub role: Option<SessionRole>,
 }
 
-impl Default for HandshakeContext {
-    fn default() -> Self {
+impl HandshakeContext {
+    /// Default context drawing nonces and session ids from `entropy`; the only way to
+    /// build one without `std`, where there is no OS randomness to default to.
+    pub fn with_entropy(entropy: HandshakeEntropy) -> Self {
         Self {
             key_algorithm: KeyExchangeAlgorithm::X25519,
             expected_controller: None,
             required_firmware_rev: None,
+            firmware_policy: None,
+            entropy,
+            revocations: None,
+            cipher_suites: SUPPORTED_SUITES.to_vec(),
+            controller_priority: None,
+            role: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for HandshakeContext {
+    fn default() -> Self {
+        Self::with_entropy(HandshakeEntropy::Os)
+    }
+}
+
+/// Node-side suite choice: the first of `accepted` that the controller `offered`. An empty
+/// offer comes from a controller that predates negotiation and only speaks
+/// ChaCha20-Poly1305.
+pub(crate) fn select_suite(
+    accepted: &[SuiteId],
+    offered: &[SuiteId],
+) -> Result<SuiteId, HandshakeError> {
+    let offered: &[SuiteId] = if offered.is_empty() {
+        &[SuiteId::ChaCha20Poly1305]
+    } else {
+        offered
+    };
+    accepted
+        .iter()
+        .copied()
+        .find(|suite| offered.contains(suite))
+        .ok_or_else(|| HandshakeError::Capability("no common cipher suite".into()))
+}
+
+/// Appends the offered suites and the choice to the data a handshake MAC covers, so a
+/// tampered offer or choice fails key confirmation. Adds nothing for an empty offer, which
+/// keeps the MAC of pre-negotiation peers unchanged.
+pub(crate) fn bind_suites(data: &mut Vec<u8>, offered: &[SuiteId], chosen: SuiteId) {
+    if offered.is_empty() {
+        return;
+    }
+    data.extend(offered.iter().map(|suite| *suite as u8));
+    data.push(chosen as u8);
+}
+
+/// Sets the channel binding of freshly derived `keys` from the handshake transcript: the
+/// session id, both nonces (`salt`), both key-exchange public keys, the suite offer, the
+/// negotiated suite already set on `keys`, the controller's stated priority, if any, and
+/// the observer role, if asked for.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn bind_channel(
+    keys: &mut SessionKeys,
+    session_id: &Uuid,
+    salt: &[u8],
+    controller_pubkey: &[u8],
+    device_pubkey: &[u8],
+    offered: &[SuiteId],
+    controller_priority: Option<u8>,
+    role: Option<SessionRole>,
+) {
+    let mut suites: Vec<u8> = offered.iter().map(|suite| *suite as u8).collect();
+    suites.push(keys.suite as u8);
+    let priority = controller_priority.map(|priority| [priority]);
+    let mut transcript: Vec<&[u8]> = alloc::vec![
+        session_id.as_bytes(),
+        salt,
+        controller_pubkey,
+        device_pubkey,
+        &suites,
+    ];
+    // Absent for controllers that state none, keeping their binding unchanged.
+    if let Some(priority) = &priority {
+        transcript.push(priority);
+    }
+    // A label rather than one byte, so it never reads as a priority.
+    if role == Some(SessionRole::Observer) {
+        transcript.push(b"observer");
+    }
+    keys.channel_binding = channel_binding(&transcript);
+}
+
+/// Revocation state consulted during handshakes.
+pub trait RevocationCheck: fmt::Debug + Send + Sync {
+    /// Whether the public key `key` has been revoked.
+    fn is_revoked_key(&self, key: &[u8]) -> bool;
+    /// Whether the device `device_id` has been revoked.
+    fn is_revoked_device(&self, device_id: &str) -> bool;
+}
+
+/// A peer running older firmware than the local side requires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareMismatch {
+    pub required: String,
+    /// Firmware the peer reported; empty when it did not report any.
+    pub actual: String,
+}
+
+impl fmt::Display for FirmwareMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let actual = if self.actual.is_empty() {
+            "unreported"
+        } else {
+            &self.actual
+        };
+        write!(
+            f,
+            "peer firmware {} is older than the required {}",
+            actual, self.required
+        )
+    }
+}
+
+/// Decides whether a session may go ahead with a peer below the required firmware.
+pub trait FirmwarePolicy: fmt::Debug + Send + Sync {
+    /// `true` accepts the session for degraded operation; `false` fails the handshake.
+    fn allow_degraded(&self, mismatch: &FirmwareMismatch) -> bool;
+}
+
+/// Orders firmware revisions by their dotted numeric components, so `1.0.11` is newer
+/// than `1.0.2`. A leading `v` is ignored, each component counts up to its first
+/// non-digit, and missing components count as zero.
+pub fn compare_firmware(a: &str, b: &str) -> Ordering {
+    fn components(rev: &str) -> impl Iterator<Item = u64> + '_ {
+        rev.trim_start_matches('v').split('.').map(|part| {
+            part.bytes()
+                .take_while(u8::is_ascii_digit)
+                .fold(0u64, |n, digit| {
+                    n.saturating_mul(10).saturating_add(u64::from(digit - b'0'))
+                })
+        })
+    }
+    let (mut a, mut b) = (components(a), components(b));
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (x, y) => match x.unwrap_or(0).cmp(&y.unwrap_or(0)) {
+                Ordering::Equal => continue,
+                other => return other,
+            },
+        }
+    }
+}
+
+/// Checks the firmware the peer reported against `context`, consulting its policy on a
+/// mismatch.
+pub(crate) fn check_firmware(
+    context: &HandshakeContext,
+    actual: Option<&str>,
+) -> Result<(), HandshakeError> {
+    let Some(required) = &context.required_firmware_rev else {
+        return Ok(());
+    };
+    let actual = actual.unwrap_or_default();
+    if !actual.is_empty() && compare_firmware(actual, required) != Ordering::Less {
+        return Ok(());
+    }
+    let mismatch = FirmwareMismatch {
+        required: required.clone(),
+        actual: actual.into(),
+    };
+    match &context.firmware_policy {
+        Some(policy) if policy.allow_degraded(&mismatch) => Ok(()),
+        _ => Err(HandshakeError::Firmware(mismatch)),
+    }
+}
+
+/// Source of the nonces and session id a handshake participant generates.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "std", derive(Default))]
+pub enum HandshakeEntropy {
+    /// Operating-system randomness; the choice for real deployments on hosted targets.
+    #[cfg(feature = "std")]
+    #[default]
+    Os,
+    /// Fills the buffer from the target's hardware RNG, for `no_std` nodes without OS
+    /// randomness.
+    Host(fn(&mut [u8])),
+    /// Derives every value from a fixed seed so transcripts are reproducible.
+    ///
+    /// Each value is `HKDF-SHA256(ikm = seed, info = "alpine-test-" + label)`, so other
+    /// implementations can regenerate golden transcripts byte for byte. Never use this
+    /// outside tests.
+    Deterministic([u8; 32]),
+}
+
+impl HandshakeEntropy {
+    /// Fills `out` with the value used for `label` (e.g. `controller-nonce`).
+    pub fn fill(&self, label: &str, out: &mut [u8]) {
+        match self {
+            #[cfg(feature = "std")]
+            HandshakeEntropy::Os => OsRng.fill_bytes(out),
+            HandshakeEntropy::Host(fill) => fill(out),
+            HandshakeEntropy::Deterministic(seed) => {
+                let info = format!("alpine-test-{}", label);
+                Hkdf::<Sha256>::new(None, seed)
+                    .expand(info.as_bytes(), out)
+                    .expect("handshake values are far below the HKDF output limit");
+            }
         }
     }
+
+    /// Generates a 32-byte challenge nonce.
+    pub fn nonce(&self, label: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        self.fill(label, &mut bytes);
+        bytes
+    }
+
+    /// Generates a version 4 session id.
+    pub fn session_id(&self) -> Uuid {
+        let mut bytes = [0u8; 16];
+        self.fill("session-id", &mut bytes);
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    }
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug)]
 pub enum HandshakeError {
-    #[error("transport error: {0}")]
     Transport(String),
-    #[error("protocol violation: {0}")]
+    /// The peer did not answer within the receive timeout.
+    Timeout(String),
     Protocol(String),
-    #[error("authentication failed: {0}")]
     Authentication(String),
-    #[error("unsupported capability: {0}")]
     Capability(String),
+    /// The peer runs older firmware than required and the firmware policy refused it.
+    Firmware(FirmwareMismatch),
+    /// The peer reported a failure with an error envelope.
+    Remote(ErrorEnvelope),
+    /// This side refused the peer on policy, e.g. not allowlisted or at capacity.
+    Denied(ErrorCode, String),
+    /// The node refused the handshake with a [`SessionRejected`] its identity key signed.
+    Rejected(ErrorCode),
+    /// The node is at its session or handshake limit and asks for the next attempt to
+    /// wait this long; raised on both sides.
+    Busy(Duration),
+}
+
+impl HandshakeError {
+    /// Wire code describing this error, or `None` for local transport failures.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            HandshakeError::Transport(_) | HandshakeError::Timeout(_) => None,
+            HandshakeError::Protocol(_) => Some(ErrorCode::HandshakeProtocolViolation),
+            HandshakeError::Authentication(_) => Some(ErrorCode::HandshakeSignatureInvalid),
+            HandshakeError::Capability(_) => Some(ErrorCode::HandshakeCapabilityMismatch),
+            HandshakeError::Firmware(_) => Some(ErrorCode::HandshakeFirmwareIncompatible),
+            HandshakeError::Remote(env) => Some(env.code.clone()),
+            HandshakeError::Denied(code, _) | HandshakeError::Rejected(code) => Some(code.clone()),
+            HandshakeError::Busy(_) => Some(ErrorCode::HandshakeCapacity),
+        }
+    }
+
+    /// How long the peer asked to wait before retrying, if it said.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            HandshakeError::Busy(wait) => Some(*wait),
+            HandshakeError::Remote(env) => env.retry_after_ms.map(Duration::from_millis),
+            _ => None,
+        }
+    }
+
+    /// Whether retrying the operation may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            HandshakeError::Transport(_) | HandshakeError::Timeout(_) => true,
+            HandshakeError::Remote(env) => env.retryable,
+            other => other.code().is_some_and(|code| code.is_retryable()),
+        }
+    }
+
+    /// Error envelope to send the peer for a locally detected failure.
+    ///
+    /// Returns `None` for transport failures and for errors the peer itself reported.
+    pub fn to_envelope(&self, session_id: Option<Uuid>) -> Option<ErrorEnvelope> {
+        let detail = match self {
+            HandshakeError::Protocol(d)
+            | HandshakeError::Authentication(d)
+            | HandshakeError::Capability(d) => d.clone(),
+            HandshakeError::Firmware(mismatch) => mismatch.to_string(),
+            HandshakeError::Denied(_, d) => d.clone(),
+            HandshakeError::Busy(wait) => {
+                let mut envelope = ErrorEnvelope::new(
+                    session_id,
+                    ErrorCode::HandshakeCapacity,
+                    Some("node busy".into()),
+                );
+                envelope.retry_after_ms = Some(wait.as_millis().max(1) as u64);
+                return Some(envelope);
+            }
+            HandshakeError::Transport(_)
+            | HandshakeError::Timeout(_)
+            | HandshakeError::Remote(_)
+            | HandshakeError::Rejected(_) => return None,
+        };
+        Some(ErrorEnvelope::new(session_id, self.code()?, Some(detail)))
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Transport(err) => write!(f, "transport error: {}", err),
+            HandshakeError::Timeout(err) => write!(f, "timed out: {}", err),
+            HandshakeError::Protocol(err) => write!(f, "protocol violation: {}", err),
+            HandshakeError::Authentication(err) => write!(f, "authentication failed: {}", err),
+            HandshakeError::Capability(err) => write!(f, "unsupported capability: {}", err),
+            HandshakeError::Firmware(mismatch) => write!(f, "incompatible firmware: {}", mismatch),
+            HandshakeError::Remote(env) => match &env.detail {
+                Some(detail) => write!(f, "peer reported {:?}: {}", env.code, detail),
+                None => write!(f, "peer reported {:?}", env.code),
+            },
+            HandshakeError::Denied(code, detail) => write!(f, "refused {:?}: {}", code, detail),
+            HandshakeError::Rejected(code) => write!(f, "node rejected the handshake: {:?}", code),
+            HandshakeError::Busy(wait) => write!(f, "node busy, retry after {:?}", wait),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HandshakeError {}
+
+/// Sends the peer an error envelope describing `err`, best-effort, and hands `err` back.
+pub(crate) async fn report_failure<T: HandshakeTransport + Send>(
+    transport: &mut T,
+    session_id: Option<Uuid>,
+    err: HandshakeError,
+) -> HandshakeError {
+    if let Some(envelope) = err.to_envelope(session_id) {
+        // The peer may already be gone; the local error is what the caller needs.
+        let _ = transport.send(HandshakeMessage::Error(envelope)).await;
+    }
+    err
+}
+
+/// Label prefixed to the data a node signs in a [`SessionRejected`].
+const REJECTION_LABEL: &str = "alpine-session-rejected";
+
+/// Data a node signs in `rejection`. Covering the controller's nonce keeps a captured
+/// rejection from being replayed against a later handshake.
+fn rejection_challenge(
+    controller_nonce: &[u8],
+    rejection: &SessionRejected,
+) -> Result<Vec<u8>, HandshakeError> {
+    crate::codec::to_vec(&(
+        REJECTION_LABEL,
+        controller_nonce,
+        &rejection.session_id,
+        &rejection.code,
+        &rejection.detail,
+        rejection.retry_after_ms,
+    ))
+    .map_err(|e| HandshakeError::Protocol(e.to_string()))
+}
+
+/// Refuses the handshake that sent `controller_nonce` with a signed [`SessionRejected`]
+/// describing `err`, best-effort, and hands `err` back.
+pub(crate) async fn reject<T, A>(
+    transport: &mut T,
+    authenticator: &A,
+    controller_nonce: &[u8],
+    session_id: Uuid,
+    err: HandshakeError,
+) -> HandshakeError
+where
+    T: HandshakeTransport + Send,
+    A: ChallengeAuthenticator + Sync,
+{
+    let Some(envelope) = err.to_envelope(Some(session_id)) else {
+        return err;
+    };
+    let mut rejection = SessionRejected {
+        message_type: MessageType::SessionRejected,
+        session_id,
+        code: envelope.code,
+        detail: envelope.detail,
+        retry_after_ms: envelope.retry_after_ms,
+        signature: Vec::new(),
+    };
+    let Ok(challenge) = rejection_challenge(controller_nonce, &rejection) else {
+        return report_failure(transport, Some(session_id), err).await;
+    };
+    rejection.signature = authenticator.sign_challenge(&challenge);
+    // The peer may already be gone; the local error is what the caller needs.
+    let _ = transport
+        .send(HandshakeMessage::SessionRejected(rejection))
+        .await;
+    err
+}
+
+/// Checks a node's [`SessionRejected`] answering the handshake that sent
+/// `controller_nonce`, and turns it into [`HandshakeError::Rejected`], or
+/// [`HandshakeError::Busy`] when a busy node says how long to wait.
+pub(crate) fn verify_rejection<A: ChallengeAuthenticator>(
+    authenticator: &A,
+    rejection: &SessionRejected,
+    session_id: Uuid,
+    controller_nonce: &[u8],
+) -> HandshakeError {
+    let signed = rejection.session_id == session_id
+        && rejection_challenge(controller_nonce, rejection).is_ok_and(|challenge| {
+            authenticator.verify_challenge(&challenge, &rejection.signature)
+        });
+    if !signed {
+        return HandshakeError::Authentication("session rejection signature invalid".into());
+    }
+    match (&rejection.code, rejection.retry_after_ms) {
+        (ErrorCode::HandshakeCapacity, Some(wait)) => {
+            HandshakeError::Busy(Duration::from_millis(wait))
+        }
+        (code, _) => HandshakeError::Rejected(code.clone()),
+    }
+}
+
+/// Error for a message that arrived out of order; a peer error envelope is surfaced as-is.
+///
+/// A [`SessionRejected`] the caller cannot verify is surfaced like an error envelope.
+pub(crate) fn unexpected(expected: &str, other: HandshakeMessage) -> HandshakeError {
+    match other {
+        HandshakeMessage::Error(envelope) => HandshakeError::Remote(envelope),
+        HandshakeMessage::SessionRejected(rejection) => {
+            let mut envelope =
+                ErrorEnvelope::new(Some(rejection.session_id), rejection.code, rejection.detail);
+            envelope.retry_after_ms = rejection.retry_after_ms;
+            HandshakeError::Remote(envelope)
+        }
+        other => HandshakeError::Protocol(format!("expected {}, got {:?}", expected, other)),
+    }
 }
 
 /// Generates a cryptographic nonce for challenge/response.
+#[cfg(feature = "std")]
 pub fn new_nonce() -> [u8; 32] {
     let mut bytes = [0u8; 32];
     OsRng.fill_bytes(&mut bytes);
@@ -84,6 +545,25 @@ pub trait HandshakeParticipant {
 pub trait ChallengeAuthenticator {
     fn sign_challenge(&self, nonce: &[u8]) -> Vec<u8>;
     fn verify_challenge(&self, nonce: &[u8], signature: &[u8]) -> bool;
+
+    /// Ed25519 key peer signatures are checked against, so handshakes can refuse it once
+    /// revoked; `None` for authenticators without one.
+    fn verifying_key(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Secret and negotiated capabilities retained from a session that may be resumed.
+#[derive(Debug, Clone)]
+pub struct ResumableSession {
+    pub secret: [u8; 32],
+    pub capabilities: CapabilitySet,
+}
+
+/// Node-side lookup of sessions eligible for resumption.
+pub trait ResumptionLookup {
+    /// Removes and returns the entry for `session_id`; each entry resumes at most once.
+    fn take(&self, session_id: &Uuid) -> Option<ResumableSession>;
 }
 
 /// Output returned by handshake drivers.
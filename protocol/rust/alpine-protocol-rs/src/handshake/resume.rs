@@ -0,0 +1,275 @@
+//! Abbreviated handshake that resumes an earlier session.
+//!
+//! After a full handshake both peers derive a resumption secret from the session keys. A
+//! controller that restarts can present that secret's binder in `session_resume` instead of
+//! repeating discovery and the signed handshake; the device answers with
+//! `session_resume_ack`. Both sides still run a fresh ephemeral key exchange and mix the
+//! secret into it, so the resumed session gets new keys and a new session id.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::{
+    bind_channel, bind_suites, report_failure, select_suite, unexpected, HandshakeContext,
+    HandshakeError, HandshakeMessage, HandshakeOutcome, HandshakeParticipant, HandshakeTransport,
+    ResumptionLookup,
+};
+use crate::crypto::{derive_resumed_keys, resumption_binder, KeyExchange, SuiteId};
+use crate::messages::{
+    CapabilitySet, DeviceIdentity, ErrorCode, ErrorEnvelope, MessageType, SessionEstablished,
+    SessionResume, SessionResumeAck,
+};
+
+/// Binder label for the controller's `session_resume`.
+const CONTROLLER_BINDER: &str = "alpine-resume-controller";
+/// Binder label for the device's `session_resume_ack`.
+const DEVICE_BINDER: &str = "alpine-resume-device";
+
+/// Controller-side driver resuming a session from retained material.
+pub struct ClientResumption<K>
+where
+    K: KeyExchange + Send + Sync,
+{
+    pub resumed_session: Uuid,
+    pub secret: [u8; 32],
+    /// Device identity learned in the original handshake.
+    pub device_identity: DeviceIdentity,
+    /// Capabilities negotiated in the original handshake.
+    pub capabilities: CapabilitySet,
+    pub key_exchange: K,
+    pub context: HandshakeContext,
+}
+
+#[async_trait]
+impl<K> HandshakeParticipant for ClientResumption<K>
+where
+    K: KeyExchange + Send + Sync,
+{
+    async fn run<T: HandshakeTransport + Send>(
+        &self,
+        transport: &mut T,
+    ) -> Result<HandshakeOutcome, HandshakeError> {
+        let session_id = self.context.entropy.session_id();
+        match self.exchange(transport, session_id).await {
+            Ok(outcome) => Ok(outcome),
+            Err(err) => Err(report_failure(transport, Some(session_id), err).await),
+        }
+    }
+}
+
+impl<K> ClientResumption<K>
+where
+    K: KeyExchange + Send + Sync,
+{
+    async fn exchange<T: HandshakeTransport + Send>(
+        &self,
+        transport: &mut T,
+        session_id: Uuid,
+    ) -> Result<HandshakeOutcome, HandshakeError> {
+        if let Some(revocations) = &self.context.revocations {
+            if revocations.is_revoked_device(&self.device_identity.device_id) {
+                return Err(HandshakeError::Authentication(
+                    "device credentials revoked".into(),
+                ));
+            }
+        }
+        let controller_nonce = self.context.entropy.nonce("controller-nonce").to_vec();
+        let mac = resumption_binder(
+            &self.secret,
+            CONTROLLER_BINDER,
+            session_id.as_bytes(),
+            &controller_nonce,
+        )
+        .map_err(|e| HandshakeError::Authentication(e.to_string()))?;
+
+        // 1) Controller -> device: session_resume
+        let resume = SessionResume {
+            message_type: MessageType::SessionResume,
+            session_id,
+            resumed_session: self.resumed_session,
+            controller_nonce: controller_nonce.clone(),
+            controller_pubkey: self.key_exchange.public_key(),
+            mac,
+            suites: self.context.cipher_suites.clone(),
+            controller_priority: self.context.controller_priority,
+            role: self.context.role,
+        };
+        transport
+            .send(HandshakeMessage::SessionResume(resume))
+            .await?;
+
+        // 2) Device -> controller: session_resume_ack
+        let ack = match transport.recv().await? {
+            HandshakeMessage::SessionResumeAck(ack) => ack,
+            other => return Err(unexpected("SessionResumeAck", other)),
+        };
+        if ack.session_id != session_id {
+            return Err(HandshakeError::Protocol(
+                "session_id mismatch between resume and ack".into(),
+            ));
+        }
+        if !ack.ok {
+            return Err(HandshakeError::Remote(ErrorEnvelope::new(
+                Some(session_id),
+                ack.error.unwrap_or(ErrorCode::SessionExpired),
+                Some("device refused resumption".into()),
+            )));
+        }
+
+        if select_suite(&[ack.suite], &self.context.cipher_suites).is_err() {
+            return Err(HandshakeError::Capability(format!(
+                "device chose cipher suite {:?}, which was not offered",
+                ack.suite
+            )));
+        }
+
+        // 3) Verify the device holds the same secret and saw the same suite offer, then mix
+        // the secret into fresh keys.
+        let mut salt = controller_nonce.clone();
+        salt.extend_from_slice(&ack.device_nonce);
+        let mut bound = salt.clone();
+        bind_suites(&mut bound, &self.context.cipher_suites, ack.suite);
+        let expected =
+            resumption_binder(&self.secret, DEVICE_BINDER, session_id.as_bytes(), &bound)
+                .map_err(|e| HandshakeError::Authentication(e.to_string()))?;
+        if expected != ack.mac {
+            return Err(HandshakeError::Authentication(
+                "session_resume_ack binder invalid".into(),
+            ));
+        }
+        let exchanged = self
+            .key_exchange
+            .derive_keys(&ack.device_pubkey, &salt)
+            .map_err(|e| HandshakeError::Authentication(format!("{}", e)))?;
+        let mut keys = derive_resumed_keys(exchanged, &self.secret, &salt)
+            .map_err(|e| HandshakeError::Authentication(format!("{}", e)))?;
+        keys.suite = ack.suite;
+        bind_channel(
+            &mut keys,
+            &session_id,
+            &salt,
+            &self.key_exchange.public_key(),
+            &ack.device_pubkey,
+            &self.context.cipher_suites,
+            self.context.controller_priority,
+            self.context.role,
+        );
+
+        let established = SessionEstablished {
+            session_id,
+            controller_nonce,
+            device_nonce: ack.device_nonce,
+            capabilities: self.capabilities.clone(),
+            device_identity: self.device_identity.clone(),
+            extensions: None,
+            controller_priority: self.context.controller_priority,
+            role: self.context.role,
+        };
+        Ok(HandshakeOutcome { established, keys })
+    }
+}
+
+/// Device side of `session_resume`.
+///
+/// Returns `Ok(None)` after refusing a session that is unknown, already resumed, or whose
+/// binder does not verify; the controller then falls back to a full handshake.
+pub(crate) async fn respond<T, K, R>(
+    identity: &DeviceIdentity,
+    key_exchange: &K,
+    context: &HandshakeContext,
+    lookup: &R,
+    transport: &mut T,
+    resume: SessionResume,
+) -> Result<Option<HandshakeOutcome>, HandshakeError>
+where
+    T: HandshakeTransport + Send,
+    K: KeyExchange + Send + Sync,
+    R: ResumptionLookup + Sync,
+{
+    let session_id = resume.session_id;
+    let grant = lookup.take(&resume.resumed_session).filter(|grant| {
+        resumption_binder(
+            &grant.secret,
+            CONTROLLER_BINDER,
+            session_id.as_bytes(),
+            &resume.controller_nonce,
+        )
+        .map(|expected| expected == resume.mac)
+        .unwrap_or(false)
+    });
+    let grant = match grant {
+        Some(grant) => grant,
+        None => {
+            let refusal = SessionResumeAck {
+                message_type: MessageType::SessionResumeAck,
+                session_id,
+                ok: false,
+                error: Some(ErrorCode::SessionExpired),
+                device_nonce: Vec::new(),
+                device_pubkey: Vec::new(),
+                mac: Vec::new(),
+                suite: SuiteId::default(),
+            };
+            transport
+                .send(HandshakeMessage::SessionResumeAck(refusal))
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    let suite = select_suite(&context.cipher_suites, &resume.suites)?;
+    let device_nonce = context.entropy.nonce("device-nonce").to_vec();
+    let mut salt = resume.controller_nonce.clone();
+    salt.extend_from_slice(&device_nonce);
+    let exchanged = key_exchange
+        .derive_keys(&resume.controller_pubkey, &salt)
+        .map_err(|e| HandshakeError::Authentication(format!("{}", e)))?;
+    let mut keys = derive_resumed_keys(exchanged, &grant.secret, &salt)
+        .map_err(|e| HandshakeError::Authentication(format!("{}", e)))?;
+    keys.suite = suite;
+    bind_channel(
+        &mut keys,
+        &session_id,
+        &salt,
+        &resume.controller_pubkey,
+        &key_exchange.public_key(),
+        &resume.suites,
+        resume.controller_priority,
+        resume.role,
+    );
+    let mut bound = salt.clone();
+    bind_suites(&mut bound, &resume.suites, suite);
+    let mac = resumption_binder(&grant.secret, DEVICE_BINDER, session_id.as_bytes(), &bound)
+        .map_err(|e| HandshakeError::Authentication(e.to_string()))?;
+
+    let ack = SessionResumeAck {
+        message_type: MessageType::SessionResumeAck,
+        session_id,
+        ok: true,
+        error: None,
+        device_nonce: device_nonce.clone(),
+        device_pubkey: key_exchange.public_key(),
+        mac,
+        suite,
+    };
+    transport
+        .send(HandshakeMessage::SessionResumeAck(ack))
+        .await?;
+
+    let established = SessionEstablished {
+        session_id,
+        controller_nonce: resume.controller_nonce,
+        device_nonce,
+        capabilities: grant.capabilities,
+        device_identity: identity.clone(),
+        extensions: None,
+        controller_priority: resume.controller_priority,
+        role: resume.role,
+    };
+    Ok(Some(HandshakeOutcome { established, keys }))
+}
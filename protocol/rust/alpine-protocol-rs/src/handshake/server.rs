@@ -1,12 +1,18 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::ToString;
+
 use async_trait::async_trait;
 
 use super::{
-    new_nonce, ChallengeAuthenticator, HandshakeContext, HandshakeError, HandshakeMessage,
-    HandshakeOutcome, HandshakeParticipant, HandshakeTransport,
+    bind_channel, bind_suites, check_firmware, reject, report_failure, resume, select_suite,
+    unexpected, ChallengeAuthenticator, HandshakeContext, HandshakeError, HandshakeMessage,
+    HandshakeOutcome, HandshakeParticipant, HandshakeTransport, ResumptionLookup,
 };
 use crate::crypto::{compute_mac, KeyExchange};
 use crate::messages::{
-    CapabilitySet, DeviceIdentity, MessageType, SessionAck, SessionComplete, SessionEstablished,
+    CapabilitySet, DeviceIdentity, ErrorCode, MessageType, SessionAck, SessionComplete,
+    SessionEstablished, SessionInit,
 };
 
 /// Node-side handshake driver that validates the controller and proves identity.
@@ -36,23 +42,114 @@ where
         let init = match transport.recv().await? {
             HandshakeMessage::SessionInit(msg) => msg,
             other => {
-                return Err(HandshakeError::Protocol(format!(
-                    "expected SessionInit, got {:?}",
-                    other
-                )))
+                return Err(report_failure(transport, None, unexpected("SessionInit", other)).await)
             }
         };
+        let (session_id, nonce) = (init.session_id, init.controller_nonce.clone());
+        match self.respond(transport, init).await {
+            Ok(outcome) => Ok(outcome),
+            Err(err) => Err(reject(transport, &self.authenticator, &nonce, session_id, err).await),
+        }
+    }
+}
+
+impl<A, K> ServerHandshake<A, K>
+where
+    A: ChallengeAuthenticator + Send + Sync,
+    K: KeyExchange + Send + Sync,
+{
+    /// Like [`HandshakeParticipant::run`], but also resumes sessions held in `lookup`.
+    ///
+    /// A refused `session_resume` is answered with a negative ack, after which the
+    /// controller's fallback `session_init` is accepted on the same exchange.
+    pub async fn run_with_resumption<T, R>(
+        &self,
+        transport: &mut T,
+        lookup: &R,
+    ) -> Result<HandshakeOutcome, HandshakeError>
+    where
+        T: HandshakeTransport + Send,
+        R: ResumptionLookup + Sync,
+    {
+        let mut refused = false;
+        loop {
+            match transport.recv().await? {
+                HandshakeMessage::SessionInit(init) => {
+                    let (session_id, nonce) = (init.session_id, init.controller_nonce.clone());
+                    return match self.respond(transport, init).await {
+                        Ok(outcome) => Ok(outcome),
+                        Err(err) => {
+                            Err(
+                                reject(transport, &self.authenticator, &nonce, session_id, err)
+                                    .await,
+                            )
+                        }
+                    };
+                }
+                // Only one resumption attempt per exchange; a second must be a full handshake.
+                HandshakeMessage::SessionResume(msg) if !refused => {
+                    let session_id = msg.session_id;
+                    match resume::respond(
+                        &self.identity,
+                        &self.key_exchange,
+                        &self.context,
+                        lookup,
+                        transport,
+                        msg,
+                    )
+                    .await
+                    {
+                        Ok(Some(outcome)) => return Ok(outcome),
+                        Ok(None) => refused = true,
+                        Err(err) => {
+                            return Err(report_failure(transport, Some(session_id), err).await)
+                        }
+                    }
+                }
+                other => {
+                    return Err(
+                        report_failure(transport, None, unexpected("SessionInit", other)).await,
+                    )
+                }
+            }
+        }
+    }
 
+    async fn respond<T: HandshakeTransport + Send>(
+        &self,
+        transport: &mut T,
+        init: SessionInit,
+    ) -> Result<HandshakeOutcome, HandshakeError> {
         if let Some(expected) = &self.context.expected_controller {
             if expected != &init.session_id.to_string() {
-                return Err(HandshakeError::Authentication(
+                return Err(HandshakeError::Denied(
+                    ErrorCode::HandshakeNotAuthorized,
                     "controller identity not authorized".into(),
                 ));
             }
         }
+        // Controllers do not present a signing identity yet; a static key-exchange key is
+        // the only thing a node can match against the revocation list.
+        if let Some(revocations) = &self.context.revocations {
+            if revocations.is_revoked_key(&init.controller_pubkey) {
+                return Err(HandshakeError::Denied(
+                    ErrorCode::HandshakeNotAuthorized,
+                    "controller key revoked".into(),
+                ));
+            }
+        }
+        check_firmware(&self.context, init.controller_firmware_rev.as_deref())?;
+        if let Some(missing) = self.capabilities.unsupported(&init.requested) {
+            return Err(HandshakeError::Capability(format!(
+                "controller requested unsupported {}",
+                missing
+            )));
+        }
+
+        let suite = select_suite(&self.context.cipher_suites, &init.suites)?;
 
         // 2) Device -> controller: session_ack
-        let device_nonce = new_nonce().to_vec();
+        let device_nonce = self.context.entropy.nonce("device-nonce").to_vec();
         let signature = self.authenticator.sign_challenge(&init.controller_nonce);
         let ack = SessionAck {
             message_type: MessageType::SessionAck,
@@ -62,6 +159,7 @@ where
             capabilities: self.capabilities.clone(),
             signature,
             session_id: init.session_id,
+            suite,
         };
         transport
             .send(HandshakeMessage::SessionAck(ack.clone()))
@@ -70,12 +168,7 @@ where
         // 3) Controller -> device: session_ready (validate MAC)
         let ready = match transport.recv().await? {
             HandshakeMessage::SessionReady(r) => r,
-            other => {
-                return Err(HandshakeError::Protocol(format!(
-                    "expected SessionReady, got {:?}",
-                    other
-                )))
-            }
+            other => return Err(unexpected("SessionReady", other)),
         };
 
         if ready.session_id != init.session_id {
@@ -86,18 +179,26 @@ where
 
         let mut salt = init.controller_nonce.clone();
         salt.extend_from_slice(&device_nonce);
-        let keys = self
+        let mut keys = self
             .key_exchange
             .derive_keys(&init.controller_pubkey, &salt)
             .map_err(|e| HandshakeError::Authentication(format!("{}", e)))?;
-        let mac_valid = compute_mac(
-            &keys,
-            0,
-            init.session_id.as_bytes(),
-            device_nonce.as_slice(),
-        )
-        .map(|expected| expected == ready.mac)
-        .unwrap_or(false);
+        keys.suite = suite;
+        bind_channel(
+            &mut keys,
+            &init.session_id,
+            &salt,
+            &init.controller_pubkey,
+            &ack.device_pubkey,
+            &init.suites,
+            init.controller_priority,
+            init.role,
+        );
+        let mut aad = device_nonce.clone();
+        bind_suites(&mut aad, &init.suites, suite);
+        let mac_valid = compute_mac(&keys, 0, init.session_id.as_bytes(), &aad)
+            .map(|expected| expected == ready.mac)
+            .unwrap_or(false);
         if !mac_valid {
             return Err(HandshakeError::Authentication(
                 "session_ready MAC invalid".into(),
@@ -121,6 +222,9 @@ where
             device_nonce,
             capabilities: init.requested,
             device_identity: self.identity.clone(),
+            extensions: None,
+            controller_priority: init.controller_priority,
+            role: init.role,
         };
 
         Ok(HandshakeOutcome { established, keys })
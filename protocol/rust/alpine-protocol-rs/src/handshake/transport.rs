@@ -1,63 +1,80 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use thiserror::Error;
 use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
 use tokio::time;
+use uuid::Uuid;
 
 use super::{HandshakeError, HandshakeMessage, HandshakeTransport};
-use crate::messages::{Acknowledge, ControlEnvelope};
+use crate::control::CAPABILITY_UPDATE_SEQ_BASE;
+use crate::messages::{
+    Acknowledge, CapabilityUpdate, ControlEnvelope, ControlOp, ControlProgress, KeepaliveStats,
+    MirroredOutput, SessionPreempted,
+};
+use crate::session::metrics::RttStats;
+use crate::transport::{TransportConfig, UdpTransport};
 
 /// CBOR-over-UDP transport for handshake and control-plane exchange.
+///
+/// Kept for one release as a wrapper over [`UdpTransport`], which also carries stream
+/// frames and counts traffic; use it directly.
+#[deprecated(note = "use `alpine::transport::UdpTransport`")]
 #[derive(Debug)]
 pub struct CborUdpTransport {
-    socket: UdpSocket,
-    peer: SocketAddr,
-    max_size: usize,
+    inner: UdpTransport,
 }
 
+#[allow(deprecated)]
 impl CborUdpTransport {
     pub async fn bind(
         local: SocketAddr,
         peer: SocketAddr,
         max_size: usize,
     ) -> Result<Self, HandshakeError> {
-        let socket = UdpSocket::bind(local)
-            .await
-            .map_err(|e| HandshakeError::Transport(e.to_string()))?;
-        socket
-            .connect(peer)
-            .await
+        Self::bind_with_config(local, peer, max_size, &TransportConfig::default()).await
+    }
+
+    /// Binds with interface, TTL, DSCP, and buffer options from `config`.
+    pub async fn bind_with_config(
+        local: SocketAddr,
+        peer: SocketAddr,
+        max_size: usize,
+        config: &TransportConfig,
+    ) -> Result<Self, HandshakeError> {
+        let inner = UdpTransport::bind(local, peer, config)
             .map_err(|e| HandshakeError::Transport(e.to_string()))?;
         Ok(Self {
-            socket,
-            peer,
-            max_size,
+            inner: inner.with_max_message(max_size),
         })
     }
+
+    /// Wraps a socket the caller already bound, and usually connected, to `peer`.
+    pub fn from_socket(socket: UdpSocket, peer: SocketAddr, max_size: usize) -> Self {
+        Self {
+            inner: UdpTransport::from_socket(socket, peer).with_max_message(max_size),
+        }
+    }
+
+    /// The transport this one wraps.
+    pub fn into_inner(self) -> UdpTransport {
+        self.inner
+    }
 }
 
+#[allow(deprecated)]
 #[async_trait]
 impl HandshakeTransport for CborUdpTransport {
     async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
-        let bytes = serde_cbor::to_vec(&msg)
-            .map_err(|e| HandshakeError::Transport(format!("encode: {}", e)))?;
-        self.socket
-            .send_to(&bytes, self.peer)
-            .await
-            .map_err(|e| HandshakeError::Transport(e.to_string()))?;
-        Ok(())
+        self.inner.send(msg).await
     }
 
     async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
-        let mut buf = vec![0u8; self.max_size];
-        let (len, _) = self
-            .socket
-            .recv_from(&mut buf)
-            .await
-            .map_err(|e| HandshakeError::Transport(e.to_string()))?;
-        serde_cbor::from_slice(&buf[..len])
-            .map_err(|e| HandshakeError::Transport(format!("decode: {}", e)))
+        self.inner.recv().await
     }
 }
 
@@ -89,18 +106,153 @@ where
     async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
         match time::timeout(self.recv_timeout, self.inner.recv()).await {
             Ok(res) => res,
-            Err(_) => Err(HandshakeError::Transport("recv timeout".into())),
+            Err(_) => Err(HandshakeError::Timeout("recv timeout".into())),
+        }
+    }
+}
+
+/// Lets several tasks (e.g. keepalive and control) share one transport.
+#[async_trait]
+impl<T> HandshakeTransport for Arc<Mutex<T>>
+where
+    T: HandshakeTransport + Send,
+{
+    async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        self.lock().await.send(msg).await
+    }
+
+    async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+        self.lock().await.recv().await
+    }
+}
+
+/// Rejected [`RetryPolicy`] values.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RetryPolicyError {
+    #[error("{0} must be greater than zero")]
+    Zero(&'static str),
+    #[error("max timeout {max:?} is below the base timeout {base:?}")]
+    MaxBelowBase { base: Duration, max: Duration },
+}
+
+/// Retransmission schedule for a control envelope.
+///
+/// Attempt `n` waits `base_timeout * 2^(n-1)` for a reply, capped at `max_timeout`, and the
+/// send fails after `max_attempts` unanswered attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u8,
+    base_timeout: Duration,
+    max_timeout: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(
+        max_attempts: u8,
+        base_timeout: Duration,
+        max_timeout: Duration,
+    ) -> Result<Self, RetryPolicyError> {
+        if max_attempts == 0 {
+            return Err(RetryPolicyError::Zero("max attempts"));
+        }
+        if base_timeout.is_zero() {
+            return Err(RetryPolicyError::Zero("base timeout"));
+        }
+        if max_timeout < base_timeout {
+            return Err(RetryPolicyError::MaxBelowBase {
+                base: base_timeout,
+                max: max_timeout,
+            });
+        }
+        Ok(Self {
+            max_attempts,
+            base_timeout,
+            max_timeout,
+        })
+    }
+
+    pub fn max_attempts(&self) -> u8 {
+        self.max_attempts
+    }
+
+    pub fn base_timeout(&self) -> Duration {
+        self.base_timeout
+    }
+
+    pub fn max_timeout(&self) -> Duration {
+        self.max_timeout
+    }
+
+    /// Reply wait for the 1-based `attempt`.
+    pub fn timeout_for(&self, attempt: u8) -> Duration {
+        self.base_timeout
+            .checked_mul(2u32.saturating_pow(u32::from(attempt.saturating_sub(1))))
+            .map_or(self.max_timeout, |timeout| timeout.min(self.max_timeout))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// 5 attempts starting at 200 ms, capped at 5 s.
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_timeout: Duration::from_millis(200),
+            max_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Counters for a [`ReliableControlChannel`], from [`ReliableControlChannel::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControlStats {
+    /// Envelopes that completed with an ack.
+    pub acked: u64,
+    /// Envelopes that failed: retransmit limit, transport error, or error envelope.
+    pub failed: u64,
+    /// Sends beyond the first for each envelope.
+    pub retransmissions: u64,
+    /// Times the envelope was sent for the most recently completed call.
+    pub last_attempts: u32,
+    /// Time from first send to ack.
+    pub latency: RttStats,
+}
+
+/// Message a device sends a controller unasked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceNotice {
+    Capabilities(CapabilityUpdate),
+    Preempted(SessionPreempted),
+    /// Applied output mirrored to an observer session.
+    Output(MirroredOutput),
+    /// Stream counters from the device's answer to a keepalive that carried the
+    /// controller's own.
+    Stats(KeepaliveStats),
+}
+
+impl DeviceNotice {
+    fn from_message(msg: HandshakeMessage) -> Option<Self> {
+        match msg {
+            HandshakeMessage::CapabilityUpdate(update) => Some(Self::Capabilities(update)),
+            HandshakeMessage::SessionPreempted(notice) => Some(Self::Preempted(notice)),
+            HandshakeMessage::MirroredOutput(output) => Some(Self::Output(output)),
+            HandshakeMessage::Keepalive(keepalive) => keepalive.stats.map(Self::Stats),
+            _ => None,
         }
     }
 }
 
 /// Minimal reliability layer for control envelopes with retransmissions and replay protection.
+#[derive(Debug)]
 pub struct ReliableControlChannel<T> {
     transport: T,
     seq: u64,
-    max_attempts: u8,
-    base_timeout: Duration,
-    drop_threshold: u8,
+    policy: RetryPolicy,
+    op_policies: HashMap<ControlOp, RetryPolicy>,
+    progress_timeout: Duration,
+    stats: ControlStats,
+    latency_total: Duration,
+    /// Device notices that arrived while waiting for acks, oldest first.
+    notices: VecDeque<DeviceNotice>,
 }
 
 impl<T> ReliableControlChannel<T> {
@@ -108,48 +260,188 @@ impl<T> ReliableControlChannel<T> {
         Self {
             transport,
             seq: 0,
-            max_attempts: 5,
-            base_timeout: Duration::from_millis(200),
-            drop_threshold: 5,
+            policy: RetryPolicy::default(),
+            op_policies: HashMap::new(),
+            progress_timeout: Duration::from_secs(30),
+            stats: ControlStats::default(),
+            latency_total: Duration::ZERO,
+            notices: VecDeque::new(),
+        }
+    }
+
+    /// How long to wait after a progress update before retransmitting again.
+    pub fn with_progress_timeout(mut self, timeout: Duration) -> Self {
+        self.progress_timeout = timeout;
+        self
+    }
+
+    /// Policy for operations without their own.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Uses `policy` for every envelope carrying `op`.
+    pub fn set_op_policy(&mut self, op: ControlOp, policy: RetryPolicy) {
+        self.op_policies.insert(op, policy);
+    }
+
+    /// Policy applied to `op` unless a send overrides it.
+    pub fn policy_for(&self, op: &ControlOp) -> RetryPolicy {
+        self.op_policies.get(op).copied().unwrap_or(self.policy)
+    }
+
+    pub fn stats(&self) -> ControlStats {
+        self.stats
+    }
+
+    fn record(&mut self, sends: u32, latency: Option<Duration>) {
+        self.stats.retransmissions += u64::from(sends.saturating_sub(1));
+        self.stats.last_attempts = sends;
+        match latency {
+            Some(latency) => {
+                self.stats.acked += 1;
+                self.latency_total += latency;
+                self.stats.latency.record(latency, self.latency_total);
+            }
+            None => self.stats.failed += 1,
         }
     }
 }
 
-impl<T> ReliableControlChannel<T>
+/// Update yielded by [`PendingControl::next`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlUpdate {
+    /// The device is still working on the envelope.
+    Progress(ControlProgress),
+    /// The final positive ack; no further updates follow.
+    Acked(Acknowledge),
+}
+
+/// Control envelope in flight, yielding interim progress before its final ack.
+pub struct PendingControl<'a, T> {
+    channel: &'a mut ReliableControlChannel<T>,
+    envelope: ControlEnvelope,
+    policy: RetryPolicy,
+    started: time::Instant,
+    attempt: u8,
+    sends: u32,
+    /// Set after a progress update: wait quietly until then instead of retransmitting.
+    quiet_until: Option<time::Instant>,
+    done: bool,
+}
+
+impl<T> PendingControl<'_, T>
 where
     T: HandshakeTransport + Send,
 {
-    pub async fn send_reliable(
-        &mut self,
-        mut envelope: ControlEnvelope,
-    ) -> Result<Acknowledge, HandshakeError> {
-        self.seq = self.seq.wrapping_add(1);
-        envelope.seq = self.seq;
+    pub fn seq(&self) -> u64 {
+        self.envelope.seq
+    }
 
-        let mut attempt: u8 = 0;
-        loop {
-            attempt += 1;
-            self.transport
-                .send(HandshakeMessage::Control(envelope.clone()))
-                .await?;
+    /// Waits for the next update, retransmitting with backoff while the device is silent.
+    ///
+    /// Returns `None` once the ack or an error has been returned. Progress resets the
+    /// retransmit budget, so operations may run as long as they keep reporting within
+    /// the channel's progress timeout.
+    pub async fn next(&mut self) -> Option<Result<ControlUpdate, HandshakeError>> {
+        if self.done {
+            return None;
+        }
+        let update = self.poll().await;
+        match &update {
+            Ok(ControlUpdate::Progress(_)) => {}
+            Ok(ControlUpdate::Acked(_)) => {
+                self.done = true;
+                let latency = self.started.elapsed();
+                self.channel.record(self.sends, Some(latency));
+            }
+            Err(_) => {
+                self.done = true;
+                self.channel.record(self.sends, None);
+            }
+        }
+        Some(update)
+    }
 
-            let timeout = self
-                .base_timeout
-                .checked_mul(2u32.saturating_pow((attempt - 1) as u32))
-                .unwrap_or(self.base_timeout * 4);
+    /// Discards progress and waits for the final ack.
+    pub async fn finish(mut self) -> Result<Acknowledge, HandshakeError> {
+        while let Some(update) = self.next().await {
+            if let ControlUpdate::Acked(ack) = update? {
+                return Ok(ack);
+            }
+        }
+        Err(HandshakeError::Protocol(
+            "control call already finished".into(),
+        ))
+    }
 
-            match time::timeout(timeout, self.transport.recv()).await {
+    async fn poll(&mut self) -> Result<ControlUpdate, HandshakeError> {
+        let channel = &mut *self.channel;
+        let envelope = &self.envelope;
+        loop {
+            let timeout = if let Some(until) = self.quiet_until {
+                until.saturating_duration_since(time::Instant::now())
+            } else {
+                self.attempt += 1;
+                self.sends += 1;
+                channel
+                    .transport
+                    .send(HandshakeMessage::Control(envelope.clone()))
+                    .await?;
+                self.policy.timeout_for(self.attempt)
+            };
+
+            match time::timeout(timeout, channel.transport.recv()).await {
                 Ok(Ok(HandshakeMessage::Ack(ack))) => {
                     if ack.seq == envelope.seq && ack.ok {
-                        return Ok(ack);
+                        return Ok(ControlUpdate::Acked(ack));
                     }
                 }
-                Ok(Ok(HandshakeMessage::Keepalive(_))) => {
+                Ok(Ok(HandshakeMessage::Progress(progress)))
+                    if progress.session_id == envelope.session_id
+                        && progress.seq == envelope.seq =>
+                {
+                    self.attempt = 0;
+                    self.quiet_until = Some(time::Instant::now() + channel.progress_timeout);
+                    return Ok(ControlUpdate::Progress(progress));
+                }
+                Ok(Ok(HandshakeMessage::Keepalive(keepalive))) => {
                     // keepalive resets attempt counter
-                    attempt = 0;
+                    self.attempt = 0;
+                    if let Some(stats) = keepalive.stats {
+                        channel.notices.push_back(DeviceNotice::Stats(stats));
+                    }
+                }
+                Ok(Ok(HandshakeMessage::CapabilityUpdate(update))) => {
+                    channel
+                        .notices
+                        .push_back(DeviceNotice::Capabilities(update));
+                }
+                Ok(Ok(HandshakeMessage::SessionPreempted(notice))) => {
+                    channel.notices.push_back(DeviceNotice::Preempted(notice));
+                }
+                Ok(Ok(HandshakeMessage::MirroredOutput(output))) => {
+                    channel.notices.push_back(DeviceNotice::Output(output));
+                }
+                Ok(Ok(HandshakeMessage::Error(error)))
+                    if error.session_id == Some(envelope.session_id)
+                        && error.seq == Some(envelope.seq) =>
+                {
+                    return Err(HandshakeError::Remote(error));
+                }
+                _ if self.quiet_until.is_some() => {
+                    // Once the device stays quiet past the progress timeout, fall back to
+                    // retransmitting.
+                    if self
+                        .quiet_until
+                        .is_some_and(|until| time::Instant::now() >= until)
+                    {
+                        self.quiet_until = None;
+                    }
                 }
                 _ => {
-                    if attempt >= self.max_attempts || attempt >= self.drop_threshold {
+                    if self.attempt >= self.policy.max_attempts {
                         return Err(HandshakeError::Transport(
                             "control channel retransmit limit exceeded".into(),
                         ));
@@ -158,9 +450,432 @@ where
             }
         }
     }
+}
+
+impl<T> ReliableControlChannel<T>
+where
+    T: HandshakeTransport + Send,
+{
+    /// Sends `envelope` until a matching ack arrives or the retransmit limit is hit.
+    ///
+    /// The envelope is sent exactly as given: its MAC covers `seq`, so callers reserve
+    /// the sequence with [`ReliableControlChannel::next_seq`] before signing.
+    pub async fn send_reliable(
+        &mut self,
+        envelope: ControlEnvelope,
+    ) -> Result<Acknowledge, HandshakeError> {
+        self.start(envelope).finish().await
+    }
+
+    /// Like [`ReliableControlChannel::send_reliable`], retrying under `policy` instead of
+    /// the channel's policy for the envelope's op.
+    pub async fn send_reliable_with(
+        &mut self,
+        envelope: ControlEnvelope,
+        policy: RetryPolicy,
+    ) -> Result<Acknowledge, HandshakeError> {
+        self.start_with(envelope, policy).finish().await
+    }
+
+    /// Starts sending `envelope` and returns a handle that yields progress updates before
+    /// the final ack.
+    pub fn start(&mut self, envelope: ControlEnvelope) -> PendingControl<'_, T> {
+        let policy = self.policy_for(&envelope.op);
+        self.start_with(envelope, policy)
+    }
+
+    /// Like [`ReliableControlChannel::start`] with an explicit retry policy.
+    pub fn start_with(
+        &mut self,
+        envelope: ControlEnvelope,
+        policy: RetryPolicy,
+    ) -> PendingControl<'_, T> {
+        if envelope.seq > self.seq {
+            self.seq = envelope.seq;
+        }
+        PendingControl {
+            channel: self,
+            envelope,
+            policy,
+            started: time::Instant::now(),
+            attempt: 0,
+            sends: 0,
+            quiet_until: None,
+            done: false,
+        }
+    }
+
+    /// Sends `msg` once without waiting for a reply, for fire-and-forget messages such
+    /// as [`HandshakeMessage::SessionClose`].
+    pub async fn notify(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        self.transport.send(msg).await
+    }
+
+    /// Sequence for the next envelope.
+    ///
+    /// Sequences number the envelope MAC nonces and never wrap: once they would reach
+    /// [`CAPABILITY_UPDATE_SEQ_BASE`] this fails and the session must be replaced.
+    pub fn next_seq(&mut self) -> Result<u64, HandshakeError> {
+        let seq = self.seq.saturating_add(1);
+        if seq >= CAPABILITY_UPDATE_SEQ_BASE {
+            return Err(HandshakeError::Protocol(
+                "control sequence exhausted; re-handshake required".into(),
+            ));
+        }
+        self.seq = seq;
+        Ok(seq)
+    }
+
+    /// Returns the oldest capability update the device pushed, waiting up to `wait` for
+    /// one when none arrived during earlier calls.
+    ///
+    /// Other messages received meanwhile, other notices included, are discarded;
+    /// callers authenticate the update with
+    /// [`crate::control::ControlClient::accept_capability_update`].
+    pub async fn next_capability_update(
+        &mut self,
+        wait: Duration,
+    ) -> Result<Option<CapabilityUpdate>, HandshakeError> {
+        let deadline = time::Instant::now() + wait;
+        loop {
+            let wait = deadline.saturating_duration_since(time::Instant::now());
+            match self.next_notice(wait).await? {
+                Some(DeviceNotice::Capabilities(update)) => return Ok(Some(update)),
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Returns the oldest notice the device pushed, waiting up to `wait` for one when
+    /// none arrived during earlier calls.
+    ///
+    /// Other messages received meanwhile are discarded; callers authenticate the notice
+    /// with [`crate::control::ControlClient::accept_capability_update`],
+    /// [`crate::control::ControlClient::accept_preemption`], or
+    /// [`crate::control::ControlClient::accept_mirrored_output`]. Keepalive stats carry no
+    /// MAC.
+    pub async fn next_notice(
+        &mut self,
+        wait: Duration,
+    ) -> Result<Option<DeviceNotice>, HandshakeError> {
+        if let Some(notice) = self.notices.pop_front() {
+            return Ok(Some(notice));
+        }
+        let deadline = time::Instant::now() + wait;
+        loop {
+            match time::timeout_at(deadline, self.transport.recv()).await {
+                Ok(Ok(msg)) => {
+                    if let Some(notice) = DeviceNotice::from_message(msg) {
+                        return Ok(Some(notice));
+                    }
+                }
+                Ok(Err(err)) => return Err(err),
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+}
+
+/// How a responder should treat an inbound control envelope, from [`ControlDedup::accept`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delivery {
+    /// First sight of this sequence: act on it, then [`ControlDedup::complete`] it.
+    New,
+    /// Retransmission of an answered envelope: send this cached reply again.
+    Replay(Box<HandshakeMessage>),
+    /// Retransmission of an envelope still being handled: ignore it.
+    InProgress,
+    /// Behind the session's window, or already handled with its reply since forgotten:
+    /// drop it as a replay.
+    Stale,
+}
+
+/// Receive-side half of the control reliability layer.
+///
+/// [`ReliableControlChannel`] retransmits until it sees an ack, so a slow or lost ack makes
+/// the responder see the same envelope again. `ControlDedup` remembers the replies to the
+/// most recent envelopes per `(session, seq)` so retransmissions are answered from cache
+/// instead of being applied twice. Feed it only envelopes whose MAC has been verified,
+/// otherwise forged sequences could shadow real ones.
+///
+/// Each session also has a window of the last `capacity` sequences up to the highest one
+/// seen. Envelopes overtaken by later ones are still new as long as they fall inside it,
+/// so pipelined or reordered requests are not lost; only sequences behind it are stale.
+#[derive(Debug)]
+pub struct ControlDedup {
+    capacity: usize,
+    replies: HashMap<(Uuid, u64), Option<HandshakeMessage>>,
+    order: VecDeque<(Uuid, u64)>,
+    windows: HashMap<Uuid, SeqWindow>,
+}
+
+/// Sequences of one session at or past `floor` that have been accepted.
+#[derive(Debug, Default)]
+struct SeqWindow {
+    floor: u64,
+    seen: BTreeSet<u64>,
+}
+
+impl SeqWindow {
+    fn raise_floor(&mut self, floor: u64) {
+        self.floor = self.floor.max(floor);
+        self.seen = self.seen.split_off(&self.floor);
+    }
+}
+
+impl ControlDedup {
+    /// Replies remembered unless configured otherwise.
+    pub const DEFAULT_CAPACITY: usize = 64;
+
+    /// Remembers replies to the last `capacity` envelopes (at least one).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            replies: HashMap::new(),
+            order: VecDeque::new(),
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Treats sequences of `session_id` below `next` as replays, e.g. those a previous
+    /// controller used before a migration.
+    pub fn seed(&mut self, session_id: Uuid, next: u64) {
+        self.windows
+            .entry(session_id)
+            .or_default()
+            .raise_floor(next);
+    }
+
+    /// Classifies `env` and, when it is new, marks it in progress.
+    pub fn accept(&mut self, env: &ControlEnvelope) -> Delivery {
+        let key = (env.session_id, env.seq);
+        match self.replies.get(&key) {
+            Some(Some(reply)) => return Delivery::Replay(Box::new(reply.clone())),
+            Some(None) => return Delivery::InProgress,
+            None => {}
+        }
+        let window = self.windows.entry(env.session_id).or_default();
+        if env.seq < window.floor || !window.seen.insert(env.seq) {
+            return Delivery::Stale;
+        }
+        if let Some(&highest) = window.seen.last() {
+            let capacity = u64::try_from(self.capacity).unwrap_or(u64::MAX);
+            window.raise_floor(highest.saturating_add(1).saturating_sub(capacity));
+        }
+        self.replies.insert(key, None);
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.replies.remove(&evicted);
+            }
+        }
+        Delivery::New
+    }
+
+    /// Stores the reply sent for `env` so retransmissions get the same answer.
+    pub fn complete(&mut self, env: &ControlEnvelope, reply: &HandshakeMessage) {
+        if let Some(slot) = self.replies.get_mut(&(env.session_id, env.seq)) {
+            *slot = Some(reply.clone());
+        }
+    }
+
+    /// Drops the in-progress mark for `env` without a reply, so a retransmission is
+    /// treated as new; used when the envelope was refused before being handled.
+    pub fn release(&mut self, env: &ControlEnvelope) {
+        let key = (env.session_id, env.seq);
+        if let Some(None) = self.replies.get(&key) {
+            self.replies.remove(&key);
+            self.order.retain(|entry| *entry != key);
+            if let Some(window) = self.windows.get_mut(&env.session_id) {
+                window.seen.remove(&env.seq);
+            }
+        }
+    }
+
+    /// Forgets every sequence of `session_id`, e.g. once that session closes.
+    pub fn forget(&mut self, session_id: &Uuid) {
+        self.windows.remove(session_id);
+        self.order.retain(|(session, _)| session != session_id);
+        self.replies.retain(|(session, _), _| session != session_id);
+    }
+}
+
+impl Default for ControlDedup {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ControlOp, ControlValue, MessageType};
+
+    fn envelope(session_id: Uuid, seq: u64) -> ControlEnvelope {
+        ControlEnvelope {
+            message_type: MessageType::AlpineControl,
+            session_id,
+            seq,
+            op: ControlOp::Identify,
+            payload: ControlValue::Null,
+            mac: Vec::new(),
+        }
+    }
+
+    fn reply(seq: u64) -> HandshakeMessage {
+        HandshakeMessage::Ack(Acknowledge {
+            message_type: MessageType::AlpineControlAck,
+            session_id: Uuid::nil(),
+            seq,
+            ok: true,
+            detail: None,
+            mac: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn dedup_replays_cached_replies_per_session() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut dedup = ControlDedup::new(2);
+
+        assert_eq!(dedup.accept(&envelope(a, 1)), Delivery::New);
+        assert_eq!(dedup.accept(&envelope(a, 1)), Delivery::InProgress);
+        dedup.complete(&envelope(a, 1), &reply(1));
+        assert_eq!(
+            dedup.accept(&envelope(a, 1)),
+            Delivery::Replay(Box::new(reply(1)))
+        );
+
+        // Another session's sequence space is independent.
+        assert_eq!(dedup.accept(&envelope(b, 1)), Delivery::New);
+        assert_eq!(dedup.accept(&envelope(a, 2)), Delivery::New);
+
+        // The reply to seq 1 of `a` was evicted, but it was handled already.
+        assert_eq!(dedup.accept(&envelope(a, 1)), Delivery::Stale);
+
+        dedup.forget(&a);
+        assert_eq!(dedup.accept(&envelope(a, 1)), Delivery::New);
+    }
+
+    #[test]
+    fn dedup_accepts_reordered_sequences_inside_the_window() {
+        let a = Uuid::new_v4();
+        let mut dedup = ControlDedup::new(4);
+
+        assert_eq!(dedup.accept(&envelope(a, 5)), Delivery::New);
+        // Overtaken by seq 5 but never seen.
+        assert_eq!(dedup.accept(&envelope(a, 3)), Delivery::New);
+        assert_eq!(dedup.accept(&envelope(a, 3)), Delivery::InProgress);
+        assert_eq!(dedup.accept(&envelope(a, 2)), Delivery::New);
+        // Behind the four sequences up to 5.
+        assert_eq!(dedup.accept(&envelope(a, 1)), Delivery::Stale);
+
+        dedup.release(&envelope(a, 2));
+        assert_eq!(dedup.accept(&envelope(a, 2)), Delivery::New);
+        assert_eq!(dedup.accept(&envelope(a, 8)), Delivery::New);
+        assert_eq!(dedup.accept(&envelope(a, 4)), Delivery::Stale);
+        assert_eq!(dedup.accept(&envelope(a, 6)), Delivery::New);
+
+        dedup.seed(a, 10);
+        assert_eq!(dedup.accept(&envelope(a, 7)), Delivery::Stale);
+        assert_eq!(dedup.accept(&envelope(a, 10)), Delivery::New);
+    }
+
+    /// Answers each send after `silent` unanswered sends with an ack.
+    struct SlowAcker {
+        silent: u32,
+        sends: u32,
+        pending: Option<u64>,
+    }
+
+    #[async_trait]
+    impl HandshakeTransport for SlowAcker {
+        async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+            if let HandshakeMessage::Control(env) = msg {
+                self.sends += 1;
+                self.pending = (self.sends > self.silent).then_some(env.seq);
+            }
+            Ok(())
+        }
+
+        async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+            match self.pending.take() {
+                Some(seq) => Ok(reply(seq)),
+                None => std::future::pending().await,
+            }
+        }
+    }
+
+    #[test]
+    fn retry_policy_validates_and_caps_backoff() {
+        assert_eq!(
+            RetryPolicy::new(0, Duration::from_millis(10), Duration::from_secs(1)),
+            Err(RetryPolicyError::Zero("max attempts"))
+        );
+        assert!(matches!(
+            RetryPolicy::new(3, Duration::from_secs(2), Duration::from_secs(1)),
+            Err(RetryPolicyError::MaxBelowBase { .. })
+        ));
+        let policy =
+            RetryPolicy::new(10, Duration::from_millis(100), Duration::from_millis(500)).unwrap();
+        assert_eq!(policy.timeout_for(1), Duration::from_millis(100));
+        assert_eq!(policy.timeout_for(3), Duration::from_millis(400));
+        assert_eq!(policy.timeout_for(4), Duration::from_millis(500));
+        assert_eq!(policy.timeout_for(u8::MAX), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn envelope_sequences_stop_short_of_capability_updates() {
+        let mut channel = ReliableControlChannel::new(SlowAcker {
+            silent: 0,
+            sends: 0,
+            pending: None,
+        });
+        assert_eq!(channel.next_seq().unwrap(), 1);
+        channel.seq = CAPABILITY_UPDATE_SEQ_BASE - 2;
+        assert_eq!(channel.next_seq().unwrap(), CAPABILITY_UPDATE_SEQ_BASE - 1);
+        assert!(matches!(
+            channel.next_seq(),
+            Err(HandshakeError::Protocol(_))
+        ));
+        // Failing leaves the counter where it was, so it keeps failing.
+        assert!(channel.next_seq().is_err());
+        assert_eq!(channel.seq, CAPABILITY_UPDATE_SEQ_BASE - 1);
+    }
+
+    #[tokio::test]
+    async fn per_op_policy_governs_retries_and_stats() {
+        let quick =
+            RetryPolicy::new(2, Duration::from_millis(10), Duration::from_millis(10)).unwrap();
+        let mut channel = ReliableControlChannel::new(SlowAcker {
+            silent: 2,
+            sends: 0,
+            pending: None,
+        });
+        channel.set_op_policy(ControlOp::Identify, quick);
+        assert_eq!(channel.policy_for(&ControlOp::Identify), quick);
+
+        // Two silent attempts exhaust the Identify policy.
+        let seq = channel.next_seq().unwrap();
+        let err = channel
+            .send_reliable(envelope(Uuid::nil(), seq))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, HandshakeError::Transport(_)));
+
+        // The default policy allows enough attempts for the third send to be acked.
+        let seq = channel.next_seq().unwrap();
+        let ack = channel
+            .send_reliable_with(envelope(Uuid::nil(), seq), RetryPolicy::default())
+            .await
+            .unwrap();
+        assert_eq!(ack.seq, seq);
 
-    pub fn next_seq(&mut self) -> u64 {
-        self.seq = self.seq.wrapping_add(1);
-        self.seq
+        let stats = channel.stats();
+        assert_eq!((stats.acked, stats.failed), (1, 1));
+        assert_eq!(stats.retransmissions, 1);
+        assert_eq!(stats.last_attempts, 1);
+        assert_eq!(stats.latency.samples, 1);
     }
 }
@@ -0,0 +1,377 @@
+//! Device inventories for rig checks.
+//!
+//! Before a show the crew wants to know that every node on the plot answers, runs the
+//! expected firmware, and carries the right universes. An [`Inventory`] is a snapshot
+//! of devices, either observed (from discovery replies and live sessions) or expected
+//! (written by hand or saved from a previous rig and imported as JSON).
+//! [`Inventory::check`] diffs the expected inventory against the observed one.
+//!
+//! Expected records may leave fields empty; only the fields they fill in are checked.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::messages::{CapabilitySet, DeviceIdentity, DiscoveryReply, PortId, UniverseId};
+use crate::stream::HealthLevel;
+
+/// Version written to inventory files.
+pub const INVENTORY_VERSION: u32 = 1;
+
+/// A universe a device carries, and the port it leaves on for multi-port nodes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PatchEntry {
+    pub universe: UniverseId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<PortId>,
+}
+
+/// Composite health of the device's connection when it was recorded.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeviceHealth {
+    pub score: u8,
+    pub level: HealthLevel,
+}
+
+/// What is known about one device.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DeviceRecord {
+    pub device_id: String,
+    /// Free-form name from the rig plan, e.g. "FOH truss left".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub manufacturer_id: String,
+    #[serde(default)]
+    pub model_id: String,
+    #[serde(default)]
+    pub hardware_rev: String,
+    #[serde(default)]
+    pub firmware_rev: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<SocketAddr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<CapabilitySet>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patch: Vec<PatchEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<DeviceHealth>,
+}
+
+impl DeviceRecord {
+    pub fn from_identity(identity: &DeviceIdentity) -> Self {
+        Self {
+            device_id: identity.device_id.clone(),
+            manufacturer_id: identity.manufacturer_id.clone(),
+            model_id: identity.model_id.clone(),
+            hardware_rev: identity.hardware_rev.clone(),
+            firmware_rev: identity.firmware_rev.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Record of a discovery reply received from `peer`.
+    pub fn from_discovery(reply: &DiscoveryReply, peer: SocketAddr) -> Self {
+        Self {
+            device_id: reply.device_id.clone(),
+            manufacturer_id: reply.manufacturer_id.clone(),
+            model_id: reply.model_id.clone(),
+            hardware_rev: reply.hardware_rev.clone(),
+            firmware_rev: reply.firmware_rev.clone(),
+            address: Some(peer),
+            capabilities: Some(reply.capabilities.clone()),
+            ..Self::default()
+        }
+    }
+
+    /// Takes every field `newer` fills in, keeping the others.
+    pub fn merge(&mut self, newer: DeviceRecord) {
+        fn take(field: &mut String, newer: String) {
+            if !newer.is_empty() {
+                *field = newer;
+            }
+        }
+        take(&mut self.manufacturer_id, newer.manufacturer_id);
+        take(&mut self.model_id, newer.model_id);
+        take(&mut self.hardware_rev, newer.hardware_rev);
+        take(&mut self.firmware_rev, newer.firmware_rev);
+        self.label = newer.label.or(self.label.take());
+        self.address = newer.address.or(self.address);
+        self.capabilities = newer.capabilities.or(self.capabilities.take());
+        if !newer.patch.is_empty() {
+            self.patch = newer.patch;
+        }
+        self.health = newer.health.or(self.health);
+    }
+}
+
+/// Failures reading or writing inventory files.
+#[derive(Debug, Error)]
+pub enum InventoryError {
+    #[error("inventory file access failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("inventory is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("inventory version {0} is not supported")]
+    Version(u32),
+}
+
+#[derive(Serialize, Deserialize)]
+struct InventoryFile {
+    version: u32,
+    devices: Vec<DeviceRecord>,
+}
+
+/// Devices keyed by device id.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inventory {
+    devices: BTreeMap<String, DeviceRecord>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn get(&self, device_id: &str) -> Option<&DeviceRecord> {
+        self.devices.get(device_id)
+    }
+
+    /// Records in device id order.
+    pub fn devices(&self) -> impl Iterator<Item = &DeviceRecord> {
+        self.devices.values()
+    }
+
+    /// Adds `record`, or merges it into the record of the same device.
+    pub fn observe(&mut self, record: DeviceRecord) {
+        match self.devices.get_mut(&record.device_id) {
+            Some(known) => known.merge(record),
+            None => {
+                self.devices.insert(record.device_id.clone(), record);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, device_id: &str) -> Option<DeviceRecord> {
+        self.devices.remove(device_id)
+    }
+
+    pub fn to_json(&self) -> Result<String, InventoryError> {
+        let file = InventoryFile {
+            version: INVENTORY_VERSION,
+            devices: self.devices.values().cloned().collect(),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, InventoryError> {
+        let file: InventoryFile = serde_json::from_str(json)?;
+        if file.version != INVENTORY_VERSION {
+            return Err(InventoryError::Version(file.version));
+        }
+        Ok(file.devices.into_iter().collect())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), InventoryError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Imports an inventory file, e.g. the expected devices of a rig.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, InventoryError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Diffs this inventory, taken as expected, against `found`.
+    pub fn check(&self, found: &Inventory) -> RigCheck {
+        let mut check = RigCheck::default();
+        for (device_id, expected) in &self.devices {
+            match found.get(device_id) {
+                Some(record) => check.mismatches.extend(mismatches(expected, record)),
+                None => check.missing.push(device_id.clone()),
+            }
+        }
+        check.unexpected = found
+            .devices
+            .keys()
+            .filter(|device_id| !self.devices.contains_key(*device_id))
+            .cloned()
+            .collect();
+        check
+    }
+}
+
+impl FromIterator<DeviceRecord> for Inventory {
+    fn from_iter<I: IntoIterator<Item = DeviceRecord>>(records: I) -> Self {
+        let mut inventory = Self::new();
+        for record in records {
+            inventory.observe(record);
+        }
+        inventory
+    }
+}
+
+/// A field of a found device that differs from the expected record.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Mismatch {
+    pub device_id: String,
+    pub field: String,
+    pub expected: String,
+    pub found: String,
+}
+
+/// Outcome of [`Inventory::check`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RigCheck {
+    /// Expected devices that were not found.
+    pub missing: Vec<String>,
+    /// Found devices the expected inventory does not list.
+    pub unexpected: Vec<String>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl RigCheck {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.mismatches.is_empty()
+    }
+}
+
+fn mismatches(expected: &DeviceRecord, found: &DeviceRecord) -> Vec<Mismatch> {
+    let mut out = Vec::new();
+    let mut compare = |field: &str, want: String, got: String| {
+        if want != got {
+            out.push(Mismatch {
+                device_id: expected.device_id.clone(),
+                field: field.into(),
+                expected: want,
+                found: got,
+            });
+        }
+    };
+    let strings = [
+        (
+            "manufacturer_id",
+            &expected.manufacturer_id,
+            &found.manufacturer_id,
+        ),
+        ("model_id", &expected.model_id, &found.model_id),
+        ("hardware_rev", &expected.hardware_rev, &found.hardware_rev),
+        ("firmware_rev", &expected.firmware_rev, &found.firmware_rev),
+    ];
+    for (field, want, got) in strings {
+        if !want.is_empty() {
+            compare(field, want.clone(), got.clone());
+        }
+    }
+    // Nodes answer from an ephemeral port, so only the IP address is pinned.
+    if let Some(want) = expected.address {
+        let got = found.address.map(|addr| addr.ip().to_string());
+        compare("address", want.ip().to_string(), got.unwrap_or_default());
+    }
+    let want_ports = expected.capabilities.as_ref().and_then(|caps| caps.ports);
+    if let Some(want) = want_ports {
+        let got = found.capabilities.as_ref().and_then(|caps| caps.ports);
+        compare(
+            "ports",
+            want.to_string(),
+            got.map(|p| p.to_string()).unwrap_or_default(),
+        );
+    }
+    if !expected.patch.is_empty() {
+        compare(
+            "patch",
+            patch_text(&expected.patch),
+            patch_text(&found.patch),
+        );
+    }
+    out
+}
+
+fn patch_text(patch: &[PatchEntry]) -> String {
+    let mut patch = patch.to_vec();
+    patch.sort();
+    patch
+        .iter()
+        .map(|entry| match entry.port {
+            Some(port) => format!("{}@{}", entry.universe, port),
+            None => entry.universe.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(device_id: &str, firmware_rev: &str) -> DeviceRecord {
+        DeviceRecord {
+            device_id: device_id.into(),
+            manufacturer_id: "acme".into(),
+            model_id: "node-4".into(),
+            firmware_rev: firmware_rev.into(),
+            ..DeviceRecord::default()
+        }
+    }
+
+    #[test]
+    fn check_diffs_expected_against_found() {
+        let mut planned = record("a", "2.1.0");
+        planned.label = Some("FOH left".into());
+        planned.address = Some("10.0.0.5:0".parse().unwrap());
+        planned.patch = vec![PatchEntry {
+            universe: 1,
+            port: Some(2),
+        }];
+        let expected: Inventory = [planned, record("b", ""), record("c", "")]
+            .into_iter()
+            .collect();
+        let expected = Inventory::from_json(&expected.to_json().unwrap()).unwrap();
+        assert_eq!(
+            expected.get("a").unwrap().label.as_deref(),
+            Some("FOH left")
+        );
+
+        let mut found = Inventory::new();
+        let mut seen = record("a", "2.0.3");
+        seen.address = Some("10.0.0.5:40123".parse().unwrap());
+        found.observe(seen);
+        found.observe(DeviceRecord {
+            device_id: "a".into(),
+            health: Some(DeviceHealth {
+                score: 90,
+                level: HealthLevel::Good,
+            }),
+            ..DeviceRecord::default()
+        });
+        found.observe(record("b", "1.0.0"));
+        found.observe(record("d", "1.0.0"));
+        assert_eq!(found.get("a").unwrap().firmware_rev, "2.0.3");
+        assert!(found.get("a").unwrap().health.is_some());
+
+        let check = expected.check(&found);
+        assert!(!check.is_clean());
+        assert_eq!(check.missing, vec!["c".to_string()]);
+        assert_eq!(check.unexpected, vec!["d".to_string()]);
+        let fields: Vec<_> = check.mismatches.iter().map(|m| m.field.as_str()).collect();
+        assert_eq!(fields, vec!["firmware_rev", "patch"]);
+        assert_eq!(check.mismatches[1].expected, "1@2");
+        assert!(matches!(
+            Inventory::from_json(r#"{"version":9,"devices":[]}"#),
+            Err(InventoryError::Version(9))
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+//! End-to-end latency accounting.
+//!
+//! Each frame's path is split into stages: the controller measures how long it spent
+//! encoding the frame and handing it to the socket; the node measures the network leg
+//! (from the frame's `timestamp_us` to the datagram being read, which needs synchronised
+//! clocks and also covers the controller's two stages), how long the datagram waited
+//! before processing, and how long applying it took until the look reached the
+//! application. Percentiles per stage show whether the network or the node is eating an
+//! installation's latency budget.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::control::ControlHandlers;
+use crate::messages::ControlOp;
+
+/// Budget installers commonly hold a lighting path to.
+pub const DEFAULT_LATENCY_BUDGET: Duration = Duration::from_millis(25);
+
+/// Samples kept for percentiles; older samples are discarded first.
+pub const LATENCY_WINDOW: usize = 1024;
+
+/// Segment of a frame's path from controller to applied look.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyStage {
+    /// Controller: building and serialising the frame.
+    Encode,
+    /// Controller: handing the datagram to the socket.
+    Socket,
+    /// Frame timestamp to the node reading the datagram.
+    Network,
+    /// Node: datagram read to the start of processing, decoding included.
+    Queue,
+    /// Node: processing until the look is handed to the application.
+    Apply,
+}
+
+/// Stage timings of one frame; each side fills in the stages it can see.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySample {
+    pub encode: Option<Duration>,
+    pub socket: Option<Duration>,
+    pub network: Option<Duration>,
+    pub queue: Option<Duration>,
+    pub apply: Option<Duration>,
+}
+
+impl LatencySample {
+    fn stages(&self) -> [(LatencyStage, Option<Duration>); 5] {
+        [
+            (LatencyStage::Encode, self.encode),
+            (LatencyStage::Socket, self.socket),
+            (LatencyStage::Network, self.network),
+            (LatencyStage::Queue, self.queue),
+            (LatencyStage::Apply, self.apply),
+        ]
+    }
+
+    /// Sum of the recorded stages.
+    pub fn total(&self) -> Duration {
+        self.stages().iter().filter_map(|(_, d)| *d).sum()
+    }
+}
+
+/// Distribution of one stage, in microseconds.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Percentiles {
+    pub samples: usize,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `values`, in whatever unit they share.
+    pub fn of(mut values: Vec<u64>) -> Self {
+        values.sort_unstable();
+        // Nearest rank, so every percentile is an observed value.
+        let rank = |p: usize| values[(values.len() * p).div_ceil(100).max(1) - 1];
+        match values.last() {
+            None => Self::default(),
+            Some(&max_us) => Self {
+                samples: values.len(),
+                p50_us: rank(50),
+                p95_us: rank(95),
+                p99_us: rank(99),
+                max_us,
+            },
+        }
+    }
+}
+
+/// Latency summary answered to `get_latency`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LatencyReport {
+    pub budget_us: u64,
+    /// Stages that have samples.
+    pub stages: BTreeMap<LatencyStage, Percentiles>,
+    /// Per-frame sum of the stages this side recorded.
+    pub total: Percentiles,
+    /// Fraction of frames whose total exceeded the budget.
+    pub over_budget: f64,
+    /// Stage with the highest p95, the likeliest cause of a missed budget.
+    pub dominant: Option<LatencyStage>,
+}
+
+/// Rolling window of latency samples.
+#[derive(Debug)]
+pub struct LatencyRecorder {
+    budget: Duration,
+    samples: Mutex<VecDeque<LatencySample>>,
+}
+
+impl LatencyRecorder {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            samples: Mutex::new(VecDeque::with_capacity(LATENCY_WINDOW)),
+        }
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    pub fn record(&self, sample: LatencySample) {
+        let mut samples = self.samples.lock();
+        if samples.len() == LATENCY_WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        let samples = self.samples.lock();
+        let micros = |d: Duration| d.as_micros() as u64;
+        let mut by_stage: BTreeMap<LatencyStage, Vec<u64>> = BTreeMap::new();
+        for sample in samples.iter() {
+            for (stage, duration) in sample.stages() {
+                if let Some(duration) = duration {
+                    by_stage.entry(stage).or_default().push(micros(duration));
+                }
+            }
+        }
+        let totals: Vec<u64> = samples.iter().map(|s| micros(s.total())).collect();
+        let over = totals
+            .iter()
+            .filter(|&&total| total > micros(self.budget))
+            .count();
+        let stages: BTreeMap<LatencyStage, Percentiles> = by_stage
+            .into_iter()
+            .map(|(stage, values)| (stage, Percentiles::of(values)))
+            .collect();
+        LatencyReport {
+            budget_us: micros(self.budget),
+            dominant: stages
+                .iter()
+                .max_by_key(|(_, p)| p.p95_us)
+                .map(|(stage, _)| *stage),
+            stages,
+            over_budget: if totals.is_empty() {
+                0.0
+            } else {
+                over as f64 / totals.len() as f64
+            },
+            total: Percentiles::of(totals),
+        }
+    }
+
+    /// Registers the handler for `get_latency`, which acks with the report as JSON.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let recorder = self.clone();
+        handlers.on(ControlOp::GetLatency, move |_: serde_json::Value| {
+            serde_json::to_string(&recorder.report())
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self::new(DEFAULT_LATENCY_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_names_the_stage_eating_the_budget() {
+        let recorder = LatencyRecorder::new(Duration::from_millis(10));
+        let ms = Duration::from_millis;
+        for i in 0..100u64 {
+            recorder.record(LatencySample {
+                network: Some(ms(2)),
+                queue: Some(Duration::from_micros(100)),
+                // Every tenth frame waits on a slow application.
+                apply: Some(if i % 10 == 0 { ms(20) } else { ms(1) }),
+                ..LatencySample::default()
+            });
+        }
+        let report = recorder.report();
+        assert_eq!(report.budget_us, 10_000);
+        assert_eq!(report.stages.len(), 3);
+        assert_eq!(report.stages[&LatencyStage::Network].p99_us, 2_000);
+        let apply = report.stages[&LatencyStage::Apply];
+        assert_eq!(
+            (apply.p50_us, apply.p95_us, apply.max_us),
+            (1_000, 20_000, 20_000)
+        );
+        assert_eq!(report.dominant, Some(LatencyStage::Apply));
+        assert_eq!(report.total.p50_us, 3_100);
+        assert!((report.over_budget - 0.1).abs() < 1e-9);
+
+        for _ in 0..LATENCY_WINDOW {
+            recorder.record(LatencySample {
+                encode: Some(ms(1)),
+                ..LatencySample::default()
+            });
+        }
+        let report = recorder.report();
+        assert_eq!(report.stages.len(), 1);
+        assert_eq!(report.total.samples, LATENCY_WINDOW);
+        assert_eq!(report.over_budget, 0.0);
+    }
+}
@@ -3,26 +3,121 @@
 //! Implements discovery, handshake, control, and streaming layers as defined in the
 //! specification documents. All messages are encoded using CBOR and cryptographically
 //! authenticated with Ed25519 + X25519 + HKDF + ChaCha20-Poly1305.
+//!
+//! With the default `std` feature disabled the crate is `no_std` + `alloc` and keeps only
+//! the portable core: `messages`, `codec`, `crypto`, `budget`, and the handshake state
+//! machines. Hosts supply their own [`handshake::HandshakeTransport`] implementation.
 
-pub mod control;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod budget;
+pub mod codec;
 pub mod crypto;
+pub mod handshake;
+pub mod messages;
+
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod control;
+#[cfg(feature = "std")]
 pub mod device;
+#[cfg(feature = "std")]
 pub mod discovery;
+#[cfg(feature = "std")]
 pub mod e2e_common;
-pub mod handshake;
-pub mod messages;
+#[cfg(feature = "std")]
+pub mod groups;
+#[cfg(feature = "std")]
+pub mod inventory;
+#[cfg(feature = "std")]
+pub mod latency;
+#[cfg(feature = "std")]
+pub mod logs;
+#[cfg(feature = "std")]
+pub mod maintenance;
+#[cfg(feature = "std")]
+pub mod merge;
+#[cfg(feature = "std")]
+pub mod mirror;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
+pub mod planning;
+#[cfg(feature = "std")]
+pub mod ports;
+#[cfg(feature = "std")]
 pub mod profile;
+#[cfg(feature = "std")]
+pub mod rdm;
+#[cfg(feature = "std")]
+pub mod scene;
+#[cfg(feature = "std")]
+pub mod schedule;
+#[cfg(feature = "std")]
 pub mod session;
+#[cfg(feature = "std")]
+pub mod show;
+#[cfg(feature = "std")]
+pub mod sim;
+#[cfg(feature = "std")]
+pub mod source;
+#[cfg(feature = "std")]
 pub mod stream;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod transport;
 
-pub use control::{ControlClient, ControlCrypto, ControlResponder};
+#[cfg(feature = "std")]
+pub use config::{ConfigStore, DeviceConfig, SharedConfig};
+#[cfg(feature = "std")]
+pub use control::{
+    ControlClient, ControlCrypto, ControlDispatcher, ControlHandlers, ControlResponder,
+};
+#[cfg(feature = "std")]
 pub use device::DeviceServer;
+#[cfg(feature = "std")]
+pub use groups::{ChannelGroup, GroupRegistry};
+#[cfg(feature = "std")]
+pub use merge::{MergeEngine, MergePolicy};
 pub use messages::{
-    Acknowledge, CapabilitySet, ChannelFormat, ControlEnvelope, ControlOp, DeviceIdentity,
-    DiscoveryReply, DiscoveryRequest, FrameEnvelope, MessageType, SessionEstablished,
+    Acknowledge, CapabilitySet, CapabilityUpdate, ChannelFormat, ControlEnvelope, ControlOp,
+    ControlProgress, DeviceIdentity, DiscoveryReply, DiscoveryRequest, FrameEnvelope, MessageType,
+    SessionEstablished, UniverseId,
 };
+#[cfg(feature = "std")]
+pub use output::{OutputDriver, OutputError};
+#[cfg(feature = "std")]
 pub use profile::{CompiledStreamProfile, StreamProfile};
-pub use session::{AlnpRole, AlnpSession, JitterStrategy};
+#[cfg(feature = "std")]
+pub use scene::{SceneEngine, SceneStore};
+#[cfg(feature = "std")]
+pub use schedule::{Schedule, ScheduleStore, Scheduler};
+#[cfg(feature = "std")]
+pub use session::limits::SessionLimits;
+#[cfg(feature = "std")]
+pub use session::metrics::SessionMetrics;
+#[cfg(feature = "std")]
+pub use session::resume::{ResumptionTicket, SessionStore};
+#[cfg(feature = "std")]
+pub use session::{AlnpRole, AlnpSession, JitterStrategy, TimingConfig};
+#[cfg(feature = "std")]
+pub use show::{FallbackPlayer, Show, ShowStore};
+#[cfg(feature = "std")]
+pub use source::{FrameSource, SourceFrame};
+#[cfg(feature = "std")]
 pub use stream::{AlnpStream, FrameTransport};
+#[cfg(feature = "std")]
+pub use transport::{interfaces, InterfaceAddr, TransportConfig};
 
+#[cfg(feature = "std")]
 mod c_api;
@@ -0,0 +1,340 @@
+//! Ring-buffer log of protocol events on a node.
+//!
+//! A node bolted to a truss has no console a technician can attach to. A [`NodeLog`]
+//! keeps the most recent handshakes, session changes, refused control operations, and
+//! refused frames in memory, and serves them over `get_logs` so a controller can read
+//! them from the ground.
+//!
+//! Control acks travel in one datagram, so the log is read in pages of at most
+//! [`LOG_PAGE_BYTES`]; each page names the sequence number to continue from. Polling
+//! with that cursor follows the log as it grows.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::NodeClock;
+use crate::control::ControlHandlers;
+use crate::messages::ControlOp;
+
+/// Entries kept unless configured otherwise.
+pub const DEFAULT_LOG_CAPACITY: usize = 1_024;
+
+/// Most bytes of JSON one `get_logs` page holds.
+pub const LOG_PAGE_BYTES: usize = 1_200;
+
+/// Longest message kept, in bytes; longer ones are cut at a character boundary.
+pub const MAX_LOG_MESSAGE: usize = 256;
+
+/// How serious a logged event is; queries filter on a minimum.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSeverity {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogSeverity::Debug => "debug",
+            LogSeverity::Info => "info",
+            LogSeverity::Warn => "warn",
+            LogSeverity::Error => "error",
+        }
+    }
+}
+
+/// One logged event.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogEntry {
+    /// Log-wide counter, increasing with every entry.
+    pub seq: u64,
+    /// Node wall clock when the event was logged, in microseconds since the epoch.
+    pub time_us: u64,
+    pub severity: LogSeverity,
+    /// Subsystem that logged the event, e.g. `handshake`, `session`, `control`, `stream`.
+    pub target: String,
+    pub message: String,
+}
+
+/// Payload of `get_logs`; every field is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogQuery {
+    /// Leaves out entries below this severity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_severity: Option<LogSeverity>,
+    /// Only the last `tail` matching entries; ignored once a cursor is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<usize>,
+    /// `next` of the previous page: the first sequence number not yet read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<u64>,
+}
+
+/// Ack detail of `get_logs`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogPage {
+    /// Oldest sequence number still held; a cursor below it has missed entries.
+    pub oldest_seq: u64,
+    pub entries: Vec<LogEntry>,
+    /// Cursor to read on from. Equal to the query's cursor when nothing new arrived.
+    pub next: u64,
+    /// Whether matching entries remain past this page.
+    pub more: bool,
+}
+
+#[derive(Debug)]
+struct Entries {
+    next_seq: u64,
+    entries: VecDeque<LogEntry>,
+}
+
+/// Fixed-size log of the node's recent protocol events; the oldest entries make room for
+/// new ones.
+#[derive(Debug)]
+pub struct NodeLog {
+    capacity: usize,
+    /// Stamps entries; the system clock when absent.
+    clock: Option<Arc<NodeClock>>,
+    entries: Mutex<Entries>,
+}
+
+impl Default for NodeLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_LOG_CAPACITY)
+    }
+}
+
+impl NodeLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            clock: None,
+            entries: Mutex::new(Entries {
+                next_seq: 0,
+                entries: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Stamps entries with `clock`, so they line up with other nodes whose time a
+    /// controller set.
+    pub fn with_clock(mut self, clock: Arc<NodeClock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Logs an event at the current wall clock time.
+    pub fn record(&self, severity: LogSeverity, target: &str, message: impl Into<String>) {
+        let time_us = match &self.clock {
+            Some(clock) => clock.now_us(),
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as u64,
+        };
+        self.record_at(time_us, severity, target, message);
+    }
+
+    /// Logs an event at `time_us`.
+    pub fn record_at(
+        &self,
+        time_us: u64,
+        severity: LogSeverity,
+        target: &str,
+        message: impl Into<String>,
+    ) {
+        let mut message = message.into();
+        if message.len() > MAX_LOG_MESSAGE {
+            let mut end = MAX_LOG_MESSAGE;
+            while !message.is_char_boundary(end) {
+                end -= 1;
+            }
+            message.truncate(end);
+        }
+        let mut entries = self.entries.lock();
+        let seq = entries.next_seq;
+        entries.next_seq += 1;
+        if entries.entries.len() >= self.capacity {
+            entries.entries.pop_front();
+        }
+        entries.entries.push_back(LogEntry {
+            seq,
+            time_us,
+            severity,
+            target: target.into(),
+            message,
+        });
+    }
+
+    pub fn info(&self, target: &str, message: impl Into<String>) {
+        self.record(LogSeverity::Info, target, message);
+    }
+
+    pub fn warn(&self, target: &str, message: impl Into<String>) {
+        self.record(LogSeverity::Warn, target, message);
+    }
+
+    /// Entries matching `query`, starting at its cursor.
+    pub fn entries(&self, query: &LogQuery) -> Vec<LogEntry> {
+        let entries = self.entries.lock();
+        pending(&entries.entries, query).cloned().collect()
+    }
+
+    /// The page of `query` starting at its cursor.
+    pub fn page(&self, query: &LogQuery) -> LogPage {
+        let entries = self.entries.lock();
+        let mut page = LogPage {
+            oldest_seq: entries
+                .entries
+                .front()
+                .map_or(entries.next_seq, |entry| entry.seq),
+            next: query.cursor.unwrap_or(0),
+            ..LogPage::default()
+        };
+        // Allow for the widest `next` and `more` the page could end up with.
+        let mut budget = LOG_PAGE_BYTES.saturating_sub(json_len(&page) + 24);
+        for entry in pending(&entries.entries, query) {
+            // A separating comma.
+            let cost = json_len(entry) + 1;
+            if cost > budget {
+                page.more = true;
+                break;
+            }
+            budget -= cost;
+            page.next = entry.seq + 1;
+            page.entries.push(entry.clone());
+        }
+        if !page.more {
+            // Entries the filter left out need not be read again.
+            page.next = page.next.max(entries.next_seq);
+        }
+        page
+    }
+
+    /// Registers the handler for `get_logs`, which acks with a [`LogPage`] as JSON.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let log = self.clone();
+        handlers.on(ControlOp::GetLogs, move |query: LogQuery| {
+            serde_json::to_string(&log.page(&query))
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+/// Entries of `query` in order, from its cursor or the start of its tail.
+fn pending<'a>(
+    entries: &'a VecDeque<LogEntry>,
+    query: &'a LogQuery,
+) -> impl Iterator<Item = &'a LogEntry> + 'a {
+    let matching =
+        move |entry: &&LogEntry| query.min_severity.is_none_or(|min| entry.severity >= min);
+    let start = match (query.cursor, query.tail) {
+        (Some(cursor), _) => cursor,
+        (None, Some(tail)) => entries
+            .iter()
+            .rev()
+            .filter(matching)
+            .take(tail)
+            .last()
+            .map_or(u64::MAX, |entry| entry.seq),
+        (None, None) => 0,
+    };
+    entries
+        .iter()
+        .filter(move |entry| entry.seq >= start)
+        .filter(matching)
+}
+
+fn json_len<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_entries_and_filters_by_severity() {
+        let log = NodeLog::new(4);
+        for i in 0..6u64 {
+            let severity = if i % 2 == 0 {
+                LogSeverity::Info
+            } else {
+                LogSeverity::Warn
+            };
+            log.record_at(i, severity, "session", format!("event {i}"));
+        }
+        let all = log.entries(&LogQuery::default());
+        assert_eq!(
+            all.iter().map(|e| e.seq).collect::<Vec<_>>(),
+            vec![2, 3, 4, 5]
+        );
+        let warnings = log.entries(&LogQuery {
+            min_severity: Some(LogSeverity::Warn),
+            ..LogQuery::default()
+        });
+        assert_eq!(
+            warnings.iter().map(|e| e.seq).collect::<Vec<_>>(),
+            vec![3, 5]
+        );
+        let tail = log.entries(&LogQuery {
+            tail: Some(1),
+            min_severity: Some(LogSeverity::Info),
+            ..LogQuery::default()
+        });
+        assert_eq!(tail.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(log.page(&LogQuery::default()).oldest_seq, 2);
+
+        log.record_at(6, LogSeverity::Error, "control", "x".repeat(1_000));
+        assert_eq!(
+            log.entries(&LogQuery::default())[3].message.len(),
+            MAX_LOG_MESSAGE
+        );
+    }
+
+    #[test]
+    fn pages_fit_a_datagram_and_follow_the_log() {
+        let log = NodeLog::default();
+        for i in 0..100u64 {
+            log.record_at(i, LogSeverity::Info, "stream", format!("frame refused {i}"));
+        }
+        let mut query = LogQuery::default();
+        let mut read = Vec::new();
+        let mut pages = 0;
+        loop {
+            let page = log.page(&query);
+            assert!(serde_json::to_vec(&page).unwrap().len() <= LOG_PAGE_BYTES);
+            pages += 1;
+            read.extend(page.entries);
+            query.cursor = Some(page.next);
+            if !page.more {
+                break;
+            }
+        }
+        assert!(pages > 1);
+        assert_eq!(read, log.entries(&LogQuery::default()));
+
+        // Nothing new: the cursor stays put.
+        let idle = log.page(&query);
+        assert!(idle.entries.is_empty() && !idle.more);
+        assert_eq!(idle.next, 100);
+
+        log.warn("handshake", "signature invalid");
+        let followed = log.page(&query);
+        assert_eq!(followed.entries.len(), 1);
+        assert_eq!(followed.entries[0].seq, 100);
+        assert_eq!(followed.next, 101);
+    }
+}
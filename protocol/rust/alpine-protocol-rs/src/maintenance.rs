@@ -0,0 +1,370 @@
+//! Remote reboot and factory reset.
+//!
+//! `restart` reboots the node and `factory_reset` wipes its settings back to the
+//! factory state. Both are ordinary control operations, so they are authenticated by the
+//! session MAC and refused to monitoring sessions like any other write. A factory reset
+//! also takes two steps: the first request only returns a single-use confirmation nonce,
+//! and the reset runs when the same session sends it back within
+//! [`CONFIRMATION_WINDOW`]. A script that fires the op by mistake, or a stray
+//! retransmission, cannot wipe a node on its own.
+//!
+//! The device decides when the action may actually run through its
+//! [`MaintenanceHook`]: it may veto a reboot in the middle of a show, or delay it until
+//! the current cue has finished. The node acks before it acts, so the controller hears
+//! back before the node goes away.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::control::ControlHandlers;
+use crate::messages::ControlOp;
+
+/// How long a factory reset confirmation nonce stays valid.
+pub const CONFIRMATION_WINDOW: Duration = Duration::from_secs(30);
+
+/// Time given to the ack to leave the node before the hook performs the action.
+pub const ACK_GRACE: Duration = Duration::from_millis(200);
+
+/// Longest a hook may delay an action; longer delays are capped.
+pub const MAX_MAINTENANCE_DELAY: Duration = Duration::from_secs(3_600);
+
+/// What the controller asked the node to do.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceAction {
+    Reboot,
+    FactoryReset,
+}
+
+impl MaintenanceAction {
+    pub fn op(&self) -> ControlOp {
+        match self {
+            MaintenanceAction::Reboot => ControlOp::Restart,
+            MaintenanceAction::FactoryReset => ControlOp::FactoryReset,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceAction::Reboot => "reboot",
+            MaintenanceAction::FactoryReset => "factory_reset",
+        }
+    }
+}
+
+/// A device's answer to a maintenance request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaintenanceDecision {
+    /// Perform the action straight after the ack.
+    Proceed,
+    /// Ask again after this long, e.g. once the running cue ends.
+    Delay(Duration),
+    /// Refuse; the reason goes back to the controller in a negative ack.
+    Veto(String),
+}
+
+/// Device-side hooks for maintenance requests.
+pub trait MaintenanceHook: Send + Sync {
+    /// Whether `action` may run now. Called when the request arrives and again just
+    /// before the action runs; a veto at that point drops the action silently.
+    fn check(&self, action: MaintenanceAction) -> MaintenanceDecision {
+        let _ = action;
+        MaintenanceDecision::Proceed
+    }
+
+    /// Reboots or resets the device. Called once, after the ack was sent.
+    fn perform(&self, action: MaintenanceAction);
+}
+
+/// Payload of `restart` and `factory_reset`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MaintenanceRequest {
+    /// Nonce from the first `factory_reset` ack; ignored by `restart`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm: Option<String>,
+    /// Why the operator asked, for the node's logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Ack detail of `restart` and `factory_reset`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum MaintenanceReply {
+    /// Send the request again with `confirm` within `expires_ms` to go ahead.
+    ConfirmRequired { confirm: String, expires_ms: u64 },
+    /// The action runs in `after_ms`, unless the device vetoes it by then.
+    Scheduled {
+        action: MaintenanceAction,
+        after_ms: u64,
+    },
+}
+
+#[derive(Debug)]
+struct Pending {
+    session_id: Uuid,
+    expires: Instant,
+}
+
+/// Serves `restart` and `factory_reset` through a [`MaintenanceHook`].
+pub struct Maintenance {
+    hook: Arc<dyn MaintenanceHook>,
+    /// Outstanding factory reset nonces.
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl std::fmt::Debug for Maintenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Maintenance")
+            .field("pending", &self.pending.lock().len())
+            .finish()
+    }
+}
+
+impl Maintenance {
+    pub fn new(hook: Arc<dyn MaintenanceHook>) -> Self {
+        Self {
+            hook,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Answers a request from `session_id`; `Err` carries the reason for a negative ack.
+    ///
+    /// A [`MaintenanceReply::Scheduled`] reply leaves performing the action to the caller,
+    /// as [`Maintenance::perform_after`] does.
+    pub fn request(
+        &self,
+        session_id: Uuid,
+        action: MaintenanceAction,
+        request: &MaintenanceRequest,
+        now: Instant,
+    ) -> Result<MaintenanceReply, String> {
+        if action == MaintenanceAction::FactoryReset {
+            let mut pending = self.pending.lock();
+            pending.retain(|_, nonce| nonce.expires > now);
+            let Some(confirm) = &request.confirm else {
+                let confirm = new_nonce();
+                pending.insert(
+                    confirm.clone(),
+                    Pending {
+                        session_id,
+                        expires: now + CONFIRMATION_WINDOW,
+                    },
+                );
+                return Ok(MaintenanceReply::ConfirmRequired {
+                    confirm,
+                    expires_ms: CONFIRMATION_WINDOW.as_millis() as u64,
+                });
+            };
+            // A nonce is spent by any attempt to use it, so it cannot be guessed at.
+            match pending.remove(confirm) {
+                Some(nonce) if nonce.session_id == session_id => {}
+                _ => return Err("confirmation unknown or expired".into()),
+            }
+        }
+        let after = match self.hook.check(action) {
+            MaintenanceDecision::Proceed => ACK_GRACE,
+            MaintenanceDecision::Delay(delay) => delay.clamp(ACK_GRACE, MAX_MAINTENANCE_DELAY),
+            MaintenanceDecision::Veto(reason) => {
+                return Err(format!("{} vetoed: {}", action.as_str(), reason))
+            }
+        };
+        Ok(MaintenanceReply::Scheduled {
+            action,
+            after_ms: after.as_millis() as u64,
+        })
+    }
+
+    /// Waits `after`, then performs `action` once the hook agrees, asking again after
+    /// every delay it requests.
+    pub async fn perform_after(self: Arc<Self>, action: MaintenanceAction, after: Duration) {
+        let mut after = after;
+        loop {
+            tokio::time::sleep(after).await;
+            match self.hook.check(action) {
+                MaintenanceDecision::Proceed => break,
+                MaintenanceDecision::Delay(delay) => {
+                    after = delay.clamp(ACK_GRACE, MAX_MAINTENANCE_DELAY);
+                }
+                MaintenanceDecision::Veto(_) => return,
+            }
+        }
+        self.hook.perform(action);
+    }
+
+    /// Registers the handlers for `restart` and `factory_reset`, which ack with a
+    /// [`MaintenanceReply`] as JSON and perform scheduled actions in the background.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        for action in [MaintenanceAction::Reboot, MaintenanceAction::FactoryReset] {
+            let maintenance = self.clone();
+            handlers.on_envelope(action.op(), move |env| {
+                let request: MaintenanceRequest = env.payload_as().map_err(|e| e.to_string())?;
+                let reply =
+                    maintenance.request(env.session_id, action, &request, Instant::now())?;
+                if let MaintenanceReply::Scheduled { after_ms, .. } = reply {
+                    tokio::spawn(
+                        maintenance
+                            .clone()
+                            .perform_after(action, Duration::from_millis(after_ms)),
+                    );
+                }
+                serde_json::to_string(&reply)
+                    .map(Some)
+                    .map_err(|e| e.to_string())
+            });
+        }
+    }
+}
+
+fn new_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ShowRunning(Mutex<Vec<MaintenanceAction>>);
+
+    impl MaintenanceHook for ShowRunning {
+        fn check(&self, action: MaintenanceAction) -> MaintenanceDecision {
+            match action {
+                MaintenanceAction::Reboot => MaintenanceDecision::Delay(Duration::from_secs(5)),
+                MaintenanceAction::FactoryReset => MaintenanceDecision::Veto("show running".into()),
+            }
+        }
+
+        fn perform(&self, action: MaintenanceAction) {
+            self.0.lock().push(action);
+        }
+    }
+
+    struct Idle(Mutex<Vec<MaintenanceAction>>);
+
+    impl MaintenanceHook for Idle {
+        fn perform(&self, action: MaintenanceAction) {
+            self.0.lock().push(action);
+        }
+    }
+
+    #[test]
+    fn factory_reset_needs_the_nonce_from_the_same_session() {
+        let maintenance = Maintenance::new(Arc::new(Idle(Mutex::new(Vec::new()))));
+        let (session, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Instant::now();
+        let reset = MaintenanceAction::FactoryReset;
+
+        let MaintenanceReply::ConfirmRequired { confirm, .. } = maintenance
+            .request(session, reset, &MaintenanceRequest::default(), now)
+            .unwrap()
+        else {
+            panic!("expected a confirmation nonce");
+        };
+        let confirmed = MaintenanceRequest {
+            confirm: Some(confirm),
+            reason: None,
+        };
+        // Another session cannot use the nonce, and the attempt spends it.
+        assert!(maintenance.request(other, reset, &confirmed, now).is_err());
+        assert!(maintenance
+            .request(session, reset, &confirmed, now)
+            .is_err());
+
+        let MaintenanceReply::ConfirmRequired { confirm, .. } = maintenance
+            .request(session, reset, &MaintenanceRequest::default(), now)
+            .unwrap()
+        else {
+            panic!("expected a confirmation nonce");
+        };
+        let confirmed = MaintenanceRequest {
+            confirm: Some(confirm),
+            reason: None,
+        };
+        let late = now + CONFIRMATION_WINDOW + Duration::from_secs(1);
+        assert!(maintenance
+            .request(session, reset, &confirmed, late)
+            .is_err());
+
+        let MaintenanceReply::ConfirmRequired { confirm, .. } = maintenance
+            .request(session, reset, &MaintenanceRequest::default(), now)
+            .unwrap()
+        else {
+            panic!("expected a confirmation nonce");
+        };
+        let confirmed = MaintenanceRequest {
+            confirm: Some(confirm),
+            reason: None,
+        };
+        assert_eq!(
+            maintenance.request(session, reset, &confirmed, now),
+            Ok(MaintenanceReply::Scheduled {
+                action: reset,
+                after_ms: ACK_GRACE.as_millis() as u64,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn hooks_veto_or_delay_actions() {
+        let hook = Arc::new(ShowRunning(Mutex::new(Vec::new())));
+        let maintenance = Arc::new(Maintenance::new(hook.clone()));
+        let session = Uuid::new_v4();
+        let now = Instant::now();
+
+        assert_eq!(
+            maintenance.request(
+                session,
+                MaintenanceAction::Reboot,
+                &MaintenanceRequest::default(),
+                now
+            ),
+            Ok(MaintenanceReply::Scheduled {
+                action: MaintenanceAction::Reboot,
+                after_ms: 5_000,
+            })
+        );
+        let MaintenanceReply::ConfirmRequired { confirm, .. } = maintenance
+            .request(
+                session,
+                MaintenanceAction::FactoryReset,
+                &MaintenanceRequest::default(),
+                now,
+            )
+            .unwrap()
+        else {
+            panic!("expected a confirmation nonce");
+        };
+        let vetoed = maintenance.request(
+            session,
+            MaintenanceAction::FactoryReset,
+            &MaintenanceRequest {
+                confirm: Some(confirm),
+                reason: Some("new venue".into()),
+            },
+            now,
+        );
+        assert_eq!(vetoed, Err("factory_reset vetoed: show running".into()));
+
+        // A veto once the delay ends drops the action.
+        maintenance
+            .clone()
+            .perform_after(MaintenanceAction::FactoryReset, Duration::ZERO)
+            .await;
+        assert!(hook.0.lock().is_empty());
+
+        let idle = Arc::new(Idle(Mutex::new(Vec::new())));
+        Arc::new(Maintenance::new(idle.clone()))
+            .perform_after(MaintenanceAction::Reboot, Duration::ZERO)
+            .await;
+        assert_eq!(*idle.0.lock(), vec![MaintenanceAction::Reboot]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Merging frames from several sources per universe.
+//!
+//! When more than one controller streams the same universe, the node combines them
+//! according to the universe's [`MergePolicy`]. Policies are set with the
+//! `set_merge_policy` control op, persisted in the device config, and reported by
+//! [`MergeEngine::status`] for status queries.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::{ConfigError, SharedConfig};
+use crate::control::ControlHandlers;
+use crate::messages::{ChannelBuffer, ControlOp, UniverseId};
+
+/// Silence after which a source stops contributing, matching common DMX-over-IP practice.
+pub const DEFAULT_SOURCE_TIMEOUT: Duration = Duration::from_millis(2500);
+
+/// How a universe combines frames from several sources.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePolicy {
+    /// Highest value per channel across live sources.
+    #[default]
+    Htp,
+    /// The most recently received frame wins.
+    Ltp,
+    /// The highest-priority live source wins outright; ties go to the most recent.
+    PriorityOnly,
+    /// The first live source holds the universe until it falls silent.
+    SingleSourceLock,
+}
+
+/// Payload of `set_merge_policy`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MergeSetting {
+    pub universe: UniverseId,
+    pub policy: MergePolicy,
+}
+
+/// Merge state of one universe, as reported in status queries.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UniverseMergeStatus {
+    pub universe: UniverseId,
+    pub policy: MergePolicy,
+    /// Sources heard within the source timeout.
+    pub sources: usize,
+    /// Source holding a [`MergePolicy::SingleSourceLock`] universe.
+    pub owner: Option<Uuid>,
+}
+
+#[derive(Debug)]
+struct Source {
+    channels: ChannelBuffer,
+    priority: u8,
+    seen: Instant,
+}
+
+#[derive(Debug, Default)]
+struct UniverseSources {
+    sources: HashMap<Uuid, Source>,
+    owner: Option<Uuid>,
+}
+
+impl UniverseSources {
+    fn expire(&mut self, now: Instant, timeout: Duration) {
+        self.sources
+            .retain(|_, source| now.saturating_duration_since(source.seen) < timeout);
+        if self
+            .owner
+            .is_some_and(|owner| !self.sources.contains_key(&owner))
+        {
+            self.owner = None;
+        }
+    }
+
+    fn merged(&mut self, policy: MergePolicy, latest: Uuid) -> ChannelBuffer {
+        match policy {
+            MergePolicy::Htp => {
+                let len = self.sources.values().map(|s| s.channels.len()).max();
+                let mut out = ChannelBuffer::from_elem(0, len.unwrap_or(0));
+                for source in self.sources.values() {
+                    for (value, &channel) in out.iter_mut().zip(&source.channels) {
+                        *value = (*value).max(channel);
+                    }
+                }
+                out
+            }
+            MergePolicy::Ltp => self.channels_of(latest),
+            MergePolicy::PriorityOnly => self
+                .sources
+                .values()
+                .max_by_key(|source| (source.priority, source.seen))
+                .map(|source| source.channels.clone())
+                .unwrap_or_default(),
+            MergePolicy::SingleSourceLock => {
+                let owner = *self.owner.get_or_insert(latest);
+                self.channels_of(owner)
+            }
+        }
+    }
+
+    fn channels_of(&self, source: Uuid) -> ChannelBuffer {
+        self.sources
+            .get(&source)
+            .map(|s| s.channels.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Node-side merge of frames from several sessions, keyed by session id.
+#[derive(Debug)]
+pub struct MergeEngine {
+    config: SharedConfig,
+    source_timeout: Duration,
+    universes: Mutex<HashMap<UniverseId, UniverseSources>>,
+}
+
+impl MergeEngine {
+    /// Uses the merge policies in `config`.
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            source_timeout: DEFAULT_SOURCE_TIMEOUT,
+            universes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_source_timeout(mut self, timeout: Duration) -> Self {
+        self.source_timeout = timeout;
+        self
+    }
+
+    pub fn policy(&self, universe: UniverseId) -> MergePolicy {
+        self.config
+            .read(|config| config.merge.get(&universe).copied())
+            .unwrap_or_default()
+    }
+
+    /// Changes the policy for `universe` and persists it.
+    pub fn set_policy(&self, universe: UniverseId, policy: MergePolicy) -> Result<(), ConfigError> {
+        self.config.update(|config| {
+            config.merge.insert(universe, policy);
+        })
+    }
+
+    /// Takes a frame from `source` and returns the universe's merged channels.
+    pub fn submit(
+        &self,
+        source: Uuid,
+        universe: UniverseId,
+        priority: u8,
+        channels: &[u16],
+        now: Instant,
+    ) -> ChannelBuffer {
+        let policy = self.policy(universe);
+        let mut universes = self.universes.lock();
+        let state = universes.entry(universe).or_default();
+        state.expire(now, self.source_timeout);
+        state.sources.insert(
+            source,
+            Source {
+                channels: ChannelBuffer::from_slice(channels),
+                priority,
+                seen: now,
+            },
+        );
+        state.merged(policy, source)
+    }
+
+    /// Drops `source` from every universe, e.g. when its session closes.
+    pub fn forget(&self, source: Uuid) {
+        for state in self.universes.lock().values_mut() {
+            state.sources.remove(&source);
+            if state.owner == Some(source) {
+                state.owner = None;
+            }
+        }
+    }
+
+    /// Policy and live sources for every configured or active universe.
+    pub fn status(&self, now: Instant) -> Vec<UniverseMergeStatus> {
+        let policies = self.config.read(|config| config.merge.clone());
+        let mut universes = self.universes.lock();
+        let mut ids: Vec<UniverseId> = policies.keys().chain(universes.keys()).copied().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.into_iter()
+            .map(|universe| {
+                let state = universes.entry(universe).or_default();
+                state.expire(now, self.source_timeout);
+                UniverseMergeStatus {
+                    universe,
+                    policy: policies.get(&universe).copied().unwrap_or_default(),
+                    sources: state.sources.len(),
+                    owner: state.owner,
+                }
+            })
+            .collect()
+    }
+
+    /// Registers the handler for `set_merge_policy`.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let engine = self.clone();
+        handlers.on(ControlOp::SetMergePolicy, move |setting: MergeSetting| {
+            engine
+                .set_policy(setting.universe, setting.policy)
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FileConfigStore;
+
+    #[test]
+    fn policies_combine_sources_and_persist() {
+        let path = std::env::temp_dir()
+            .join(format!("alpine-config-{}", Uuid::new_v4()))
+            .join("device.cfg");
+        let engine =
+            MergeEngine::new(SharedConfig::open(Arc::new(FileConfigStore::new(&path))).unwrap());
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let start = Instant::now();
+        let later = |ms| start + Duration::from_millis(ms);
+
+        engine.submit(a, 1, 100, &[10, 200], start);
+        assert_eq!(
+            engine.submit(b, 1, 50, &[30, 20, 5], later(1))[..],
+            [30, 200, 5]
+        );
+
+        engine.set_policy(1, MergePolicy::Ltp).unwrap();
+        assert_eq!(engine.submit(b, 1, 50, &[1, 2], later(2))[..], [1, 2]);
+
+        engine.set_policy(1, MergePolicy::PriorityOnly).unwrap();
+        assert_eq!(engine.submit(b, 1, 50, &[3, 4], later(3))[..], [10, 200]);
+
+        engine.set_policy(1, MergePolicy::SingleSourceLock).unwrap();
+        assert_eq!(engine.submit(b, 1, 50, &[5, 6], later(4))[..], [5, 6]);
+        assert_eq!(engine.submit(a, 1, 100, &[7, 8], later(5))[..], [5, 6]);
+        // The holder falls silent and the other source takes over.
+        assert_eq!(engine.submit(a, 1, 100, &[7, 8], later(3000))[..], [7, 8]);
+        assert_eq!(
+            engine.status(later(3000)),
+            vec![UniverseMergeStatus {
+                universe: 1,
+                policy: MergePolicy::SingleSourceLock,
+                sources: 1,
+                owner: Some(a),
+            }]
+        );
+
+        let reopened =
+            MergeEngine::new(SharedConfig::open(Arc::new(FileConfigStore::new(&path))).unwrap());
+        assert_eq!(reopened.policy(1), MergePolicy::SingleSourceLock);
+        assert_eq!(reopened.policy(2), MergePolicy::Htp);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
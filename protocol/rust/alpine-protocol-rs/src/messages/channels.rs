@@ -0,0 +1,346 @@
+//! Channel value encodings and conversions between them.
+//!
+//! `u8` and `u16` frames carry integer levels in `channels`. `f32` frames carry normalized
+//! 0.0–1.0 values in `float_channels`, and `u8_packed` frames carry 8-bit levels as one
+//! CBOR byte string in `packed_channels` — one byte per channel instead of up to two for
+//! an integer array. [`FrameEnvelope::normalized`] reads any of them as floats, and
+//! [`FrameEnvelope::unpack`] turns packed and float frames back into integer levels for
+//! code that works on `channels`.
+//!
+//! Senders whose format the device did not advertise convert frames with
+//! [`CapabilitySet::closest_format`] and [`FrameEnvelope::convert`] instead of sending
+//! values the device would misread.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use smallvec::SmallVec;
+
+use super::{CapabilitySet, ChannelFormat, FrameEnvelope};
+
+/// Channels of one DMX512 universe, the most a [`ChannelBuffer`] holds inline.
+pub const UNIVERSE_CHANNELS: usize = 512;
+
+/// Integer levels of a frame.
+///
+/// Holds a full universe inline, so decoding, cloning, and smoothing a frame of up to
+/// [`UNIVERSE_CHANNELS`] channels never allocates; longer frames spill to the heap. It
+/// encodes as the same integer array as a `Vec<u16>`.
+pub type ChannelBuffer = SmallVec<[u16; UNIVERSE_CHANNELS]>;
+
+impl ChannelFormat {
+    /// Integer level that represents full output; float frames quantize to 16 bits.
+    pub fn full_scale(&self) -> u16 {
+        match self {
+            ChannelFormat::U8 | ChannelFormat::U8Packed => u16::from(u8::MAX),
+            ChannelFormat::U16 | ChannelFormat::F32 => u16::MAX,
+        }
+    }
+
+    /// Whether every value in this format survives conversion to `to`.
+    pub fn converts_losslessly_to(&self, to: &ChannelFormat) -> bool {
+        self == to || (*self != ChannelFormat::F32 && self.resolution() <= to.resolution())
+    }
+
+    fn resolution(&self) -> u8 {
+        match self {
+            ChannelFormat::U8 | ChannelFormat::U8Packed => 8,
+            ChannelFormat::U16 => 16,
+            ChannelFormat::F32 => 24,
+        }
+    }
+}
+
+impl CapabilitySet {
+    /// Advertised format best suited to carry `requested` values: `requested` itself, else
+    /// a lossless target, else the highest-resolution one. `None` if no format is listed.
+    pub fn closest_format(&self, requested: ChannelFormat) -> Option<ChannelFormat> {
+        if self.channel_formats.contains(&requested) {
+            return Some(requested);
+        }
+        // Reversed so ties go to the format the device listed first.
+        self.channel_formats
+            .iter()
+            .rev()
+            .max_by_key(|format| {
+                (
+                    requested.converts_losslessly_to(format),
+                    format.resolution(),
+                )
+            })
+            .copied()
+    }
+
+    /// Whether frames in `format` reach the device as sent. Devices listing no formats
+    /// predate negotiation; u8 and u16 always worked on them.
+    pub fn supports_format(&self, format: ChannelFormat) -> bool {
+        if self.channel_formats.is_empty() {
+            matches!(format, ChannelFormat::U8 | ChannelFormat::U16)
+        } else {
+            self.channel_formats.contains(&format)
+        }
+    }
+}
+
+/// Converts `level` out of `full_scale` to 0.0–1.0.
+pub fn normalize(level: u16, full_scale: u16) -> f32 {
+    if full_scale == 0 {
+        return 0.0;
+    }
+    (f32::from(level) / f32::from(full_scale)).min(1.0)
+}
+
+/// Converts a 0.0–1.0 value to the nearest level out of `full_scale`, clamping values
+/// outside the range; NaN maps to zero.
+pub fn quantize(value: f32, full_scale: u16) -> u16 {
+    // Adding one half before truncating rounds to nearest without `f32::round`, which
+    // needs std.
+    (value.clamp(0.0, 1.0) * f32::from(full_scale) + 0.5) as u16
+}
+
+/// Packs levels into bytes, saturating anything above 255.
+pub fn pack_u8(levels: &[u16]) -> Vec<u8> {
+    levels
+        .iter()
+        .map(|&level| u8::try_from(level).unwrap_or(u8::MAX))
+        .collect()
+}
+
+/// Widens packed bytes back into levels.
+pub fn unpack_u8(bytes: &[u8]) -> ChannelBuffer {
+    bytes.iter().map(|&byte| u16::from(byte)).collect()
+}
+
+/// 8-bit channel levels encoded as a single byte string.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PackedChannels(pub Vec<u8>);
+
+impl Serialize for PackedChannels {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for PackedChannels {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PackedVisitor;
+
+        impl<'de> Visitor<'de> for PackedVisitor {
+            type Value = PackedChannels;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte string of channel levels")
+            }
+
+            fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                Ok(PackedChannels(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(PackedChannels(bytes))
+            }
+
+            // Text encodings such as JSON have no byte strings and send an array.
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(PackedChannels(bytes))
+            }
+        }
+
+        deserializer.deserialize_bytes(PackedVisitor)
+    }
+}
+
+impl FrameEnvelope {
+    /// Channel values as 0.0–1.0, whatever the frame's format.
+    pub fn normalized(&self) -> Vec<f32> {
+        match (&self.float_channels, &self.packed_channels) {
+            (Some(values), _) => values.clone(),
+            (None, Some(packed)) => packed
+                .0
+                .iter()
+                .map(|&byte| normalize(u16::from(byte), u16::from(u8::MAX)))
+                .collect(),
+            (None, None) => {
+                let full_scale = self.channel_format.full_scale();
+                self.channels
+                    .iter()
+                    .map(|&level| normalize(level, full_scale))
+                    .collect()
+            }
+        }
+    }
+
+    /// Moves `u8_packed` levels from `channels` into `packed_channels` for sending.
+    pub fn pack(&mut self) {
+        if self.channel_format == ChannelFormat::U8Packed && self.packed_channels.is_none() {
+            self.packed_channels = Some(PackedChannels(pack_u8(&self.channels)));
+            self.channels.clear();
+        }
+    }
+
+    /// Re-encodes the frame's values in `to`, scaling integer levels and quantizing
+    /// floats. A `u8_packed` result keeps its levels in `channels` until [`Self::pack`].
+    pub fn convert(&mut self, to: ChannelFormat) {
+        if self.channel_format == to {
+            return;
+        }
+        let values = self.normalized();
+        self.packed_channels = None;
+        if to == ChannelFormat::F32 {
+            self.channels.clear();
+            self.float_channels = Some(values);
+        } else {
+            let full_scale = to.full_scale();
+            self.float_channels = None;
+            self.channels = values
+                .iter()
+                .map(|&value| quantize(value, full_scale))
+                .collect();
+        }
+        self.channel_format = to;
+    }
+
+    /// Replaces packed and float values with integer `channels`.
+    ///
+    /// Packed frames become [`ChannelFormat::U8`] and float frames [`ChannelFormat::U16`];
+    /// integer frames are unchanged.
+    pub fn unpack(&mut self) {
+        if let Some(packed) = self.packed_channels.take() {
+            self.channels = unpack_u8(&packed.0);
+            self.channel_format = ChannelFormat::U8;
+        }
+        if let Some(values) = self.float_channels.take() {
+            self.channels = values
+                .iter()
+                .map(|&value| quantize(value, u16::MAX))
+                .collect();
+            self.channel_format = ChannelFormat::U16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::messages::MessageType;
+    use uuid::Uuid;
+
+    fn frame(channel_format: ChannelFormat) -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::new_v4(),
+            universe: 0,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format,
+            channels: ChannelBuffer::new(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        }
+    }
+
+    #[test]
+    fn packed_and_float_frames_round_trip() {
+        let mut packed = frame(ChannelFormat::U8Packed);
+        packed.channels = alloc::vec![0, 128, 255, 300].into();
+        packed.pack();
+        let bytes = codec::to_vec(&packed).unwrap();
+        let mut decoded: FrameEnvelope = codec::from_slice(&bytes).unwrap();
+        assert_eq!(
+            decoded.packed_channels,
+            Some(PackedChannels(alloc::vec![0, 128, 255, 255]))
+        );
+        assert_eq!(decoded.normalized()[2], 1.0);
+        decoded.unpack();
+        assert_eq!(decoded.channel_format, ChannelFormat::U8);
+        assert_eq!(decoded.channels[..], [0, 128, 255, 255]);
+
+        let mut float = frame(ChannelFormat::F32);
+        float.float_channels = Some(alloc::vec![0.0, 0.5, 1.0, 2.0, f32::NAN]);
+        let bytes = codec::to_vec(&float).unwrap();
+        let mut decoded: FrameEnvelope = codec::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.normalized()[1], 0.5);
+        decoded.unpack();
+        assert_eq!(decoded.channel_format, ChannelFormat::U16);
+        assert_eq!(decoded.channels[..], [0, 32768, 65535, 65535, 0]);
+    }
+
+    #[test]
+    fn converts_to_the_closest_advertised_format() {
+        let mut caps = CapabilitySet {
+            channel_formats: alloc::vec![ChannelFormat::U8, ChannelFormat::F32],
+            ..CapabilitySet::default()
+        };
+        assert_eq!(
+            caps.closest_format(ChannelFormat::U8),
+            Some(ChannelFormat::U8)
+        );
+        assert_eq!(
+            caps.closest_format(ChannelFormat::U16),
+            Some(ChannelFormat::F32)
+        );
+        caps.channel_formats = alloc::vec![ChannelFormat::U8Packed, ChannelFormat::U8];
+        assert_eq!(
+            caps.closest_format(ChannelFormat::F32),
+            Some(ChannelFormat::U8Packed)
+        );
+        assert!(!ChannelFormat::U16.converts_losslessly_to(&ChannelFormat::U8));
+        assert!(ChannelFormat::U8.converts_losslessly_to(&ChannelFormat::U16));
+
+        let mut wide = frame(ChannelFormat::U8);
+        wide.channels = alloc::vec![0, 1, 255].into();
+        wide.convert(ChannelFormat::U16);
+        assert_eq!(wide.channels[..], [0, 257, 65535]);
+        wide.convert(ChannelFormat::F32);
+        assert_eq!(
+            wide.float_channels,
+            Some(alloc::vec![0.0, 257.0 / 65535.0, 1.0])
+        );
+        wide.convert(ChannelFormat::U8Packed);
+        wide.pack();
+        assert_eq!(
+            wide.packed_channels,
+            Some(PackedChannels(alloc::vec![0, 1, 255]))
+        );
+        assert!(wide.channels.is_empty());
+    }
+
+    #[test]
+    fn devices_listing_no_formats_take_u8_and_u16() {
+        let mut caps = CapabilitySet {
+            channel_formats: alloc::vec![],
+            ..CapabilitySet::default()
+        };
+        assert!(caps.supports_format(ChannelFormat::U8));
+        assert!(caps.supports_format(ChannelFormat::U16));
+        assert!(!caps.supports_format(ChannelFormat::F32));
+        caps.channel_formats = alloc::vec![ChannelFormat::U8Packed];
+        assert!(caps.supports_format(ChannelFormat::U8Packed));
+        assert!(!caps.supports_format(ChannelFormat::U8));
+    }
+
+    #[test]
+    fn full_universe_stays_inline_through_decode_and_clone() {
+        let mut full = frame(ChannelFormat::U16);
+        full.channels = (0..UNIVERSE_CHANNELS as u16).collect();
+        let decoded: FrameEnvelope = codec::from_slice(&codec::to_vec(&full).unwrap()).unwrap();
+        assert_eq!(decoded.channels, full.channels);
+        assert!(!decoded.channels.spilled());
+        assert!(!decoded.clone().channels.spilled());
+    }
+}
@@ -0,0 +1,284 @@
+//! Optional per-frame compression of channel values.
+//!
+//! Pixel universes are dominated by long runs of identical values (blackouts, solid
+//! colours, unlit segments), so a run-length code shrinks them cheaply. A compressed frame
+//! sets `compression` and carries its values in `compressed_channels`, leaving `channels`,
+//! `packed_channels`, and `float_channels` empty. The code works on whole values in the
+//! frame's format: one byte for `u8` and `u8_packed`, two big-endian bytes for `u16`, and
+//! the four big-endian bytes of an `f32`.
+//!
+//! [`Compression::Rle`] is PackBits over values: a header below 128 is followed by
+//! header + 1 literal values; a header of 128 or more is followed by one value repeated
+//! header − 126 times.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::channels::pack_u8;
+use super::{ChannelFormat, FrameEnvelope, PackedChannels};
+
+/// Frames whose values take fewer bytes than this are not worth compressing.
+pub const MIN_COMPRESSIBLE_BYTES: usize = 64;
+
+/// Largest payload a compressed frame may expand to, so a few bytes on the wire cannot
+/// demand unbounded memory.
+pub const MAX_DECOMPRESSED_BYTES: usize = 256 * 1024;
+
+/// Frame compression schemes, advertised in [`super::CapabilitySet::compression`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Rle,
+}
+
+/// Failure restoring a compressed frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressionError {
+    /// `compression` is set but `compressed_channels` is absent.
+    MissingPayload,
+    /// The payload ends inside a run.
+    Malformed,
+    /// The payload expands past [`MAX_DECOMPRESSED_BYTES`].
+    TooLarge,
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionError::MissingPayload => write!(f, "compressed frame has no payload"),
+            CompressionError::Malformed => write!(f, "malformed compressed payload"),
+            CompressionError::TooLarge => write!(f, "compressed payload expands too far"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CompressionError {}
+
+fn value_width(format: ChannelFormat) -> usize {
+    match format {
+        ChannelFormat::U8 | ChannelFormat::U8Packed => 1,
+        ChannelFormat::U16 => 2,
+        ChannelFormat::F32 => 4,
+    }
+}
+
+fn rle_encode(raw: &[u8], width: usize) -> Vec<u8> {
+    let values: Vec<&[u8]> = raw.chunks(width).collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let mut run = 1;
+        while i + run < values.len() && run < 129 && values[i + run] == values[i] {
+            run += 1;
+        }
+        if run > 1 {
+            out.push((run + 126) as u8);
+            out.extend_from_slice(values[i]);
+            i += run;
+            continue;
+        }
+        // Literal values up to the next repeat.
+        let start = i;
+        while i < values.len()
+            && i - start < 128
+            && values.get(i + 1).is_none_or(|next| *next != values[i])
+        {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        for value in &values[start..i] {
+            out.extend_from_slice(value);
+        }
+    }
+    out
+}
+
+fn rle_decode(data: &[u8], width: usize) -> Result<Vec<u8>, CompressionError> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while let Some(&header) = data.get(pos) {
+        pos += 1;
+        let (count, stored) = if header < 128 {
+            let count = usize::from(header) + 1;
+            (count, count)
+        } else {
+            (usize::from(header) - 126, 1)
+        };
+        let bytes = data
+            .get(pos..pos + stored * width)
+            .ok_or(CompressionError::Malformed)?;
+        pos += bytes.len();
+        if out.len() + count * width > MAX_DECOMPRESSED_BYTES {
+            return Err(CompressionError::TooLarge);
+        }
+        if stored == count {
+            out.extend_from_slice(bytes);
+        } else {
+            for _ in 0..count {
+                out.extend_from_slice(bytes);
+            }
+        }
+    }
+    Ok(out)
+}
+
+impl FrameEnvelope {
+    fn value_bytes(&self) -> Vec<u8> {
+        match self.channel_format {
+            ChannelFormat::U8Packed if self.packed_channels.is_some() => self
+                .packed_channels
+                .as_ref()
+                .map(|packed| packed.0.clone())
+                .unwrap_or_default(),
+            ChannelFormat::U8 | ChannelFormat::U8Packed => pack_u8(&self.channels),
+            ChannelFormat::U16 => self
+                .channels
+                .iter()
+                .flat_map(|level| level.to_be_bytes())
+                .collect(),
+            ChannelFormat::F32 => self
+                .float_channels
+                .iter()
+                .flatten()
+                .flat_map(|value| value.to_bits().to_be_bytes())
+                .collect(),
+        }
+    }
+
+    /// Compresses the frame's values with `scheme` if that makes them smaller.
+    ///
+    /// Frames under [`MIN_COMPRESSIBLE_BYTES`] and frames the scheme would shrink by less
+    /// than an eighth are left alone. Returns whether the frame was compressed.
+    pub fn compress(&mut self, scheme: Compression) -> bool {
+        if self.compression.is_some() {
+            return false;
+        }
+        let raw = self.value_bytes();
+        if raw.len() < MIN_COMPRESSIBLE_BYTES {
+            return false;
+        }
+        let encoded = match scheme {
+            Compression::Rle => rle_encode(&raw, value_width(self.channel_format)),
+        };
+        if encoded.len() > raw.len() - raw.len() / 8 {
+            return false;
+        }
+        self.channels.clear();
+        self.packed_channels = None;
+        self.float_channels = None;
+        self.compression = Some(scheme);
+        self.compressed_channels = Some(PackedChannels(encoded));
+        true
+    }
+
+    /// Restores the values of a compressed frame; uncompressed frames are unchanged.
+    pub fn decompress(&mut self) -> Result<(), CompressionError> {
+        let Some(scheme) = self.compression else {
+            return Ok(());
+        };
+        let payload = self
+            .compressed_channels
+            .as_ref()
+            .ok_or(CompressionError::MissingPayload)?;
+        let width = value_width(self.channel_format);
+        let raw = match scheme {
+            Compression::Rle => rle_decode(&payload.0, width)?,
+        };
+        match self.channel_format {
+            ChannelFormat::U8 => self.channels = raw.iter().map(|&b| u16::from(b)).collect(),
+            ChannelFormat::U8Packed => self.packed_channels = Some(PackedChannels(raw)),
+            ChannelFormat::U16 => {
+                self.channels = raw
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect();
+            }
+            ChannelFormat::F32 => {
+                self.float_channels = Some(
+                    raw.chunks_exact(4)
+                        .map(|b| f32::from_bits(u32::from_be_bytes([b[0], b[1], b[2], b[3]])))
+                        .collect(),
+                );
+            }
+        }
+        self.compression = None;
+        self.compressed_channels = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::messages::MessageType;
+    use alloc::vec;
+    use uuid::Uuid;
+
+    fn frame(channel_format: ChannelFormat, channels: Vec<u16>) -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::new_v4(),
+            universe: 0,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format,
+            channels: channels.into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        }
+    }
+
+    #[test]
+    fn pixel_frames_compress_and_round_trip() {
+        // 170 RGB pixels: a lit segment, then black.
+        let mut channels: Vec<u16> = (0..60).map(|i| (i * 4) as u16).collect();
+        channels.resize(510, 0);
+        for format in [ChannelFormat::U8, ChannelFormat::U16] {
+            let original = frame(format, channels.clone());
+            let mut compressed = original.clone();
+            assert!(compressed.compress(Compression::Rle));
+            let bytes = codec::to_vec(&compressed).unwrap();
+            assert!(bytes.len() < codec::to_vec(&original).unwrap().len() / 2);
+            let mut decoded: FrameEnvelope = codec::from_slice(&bytes).unwrap();
+            decoded.decompress().unwrap();
+            assert_eq!(decoded, original);
+        }
+
+        let mut float = frame(ChannelFormat::F32, Vec::new());
+        float.float_channels = Some(vec![0.25; 100]);
+        let original = float.clone();
+        assert!(float.compress(Compression::Rle));
+        float.decompress().unwrap();
+        assert_eq!(float, original);
+    }
+
+    #[test]
+    fn incompressible_and_hostile_frames_are_refused() {
+        let mut small = frame(ChannelFormat::U8, vec![0; 16]);
+        assert!(!small.compress(Compression::Rle));
+        let mut noisy = frame(ChannelFormat::U8, (0..512).map(|i| i % 256).collect());
+        assert!(!noisy.compress(Compression::Rle));
+
+        let mut truncated = frame(ChannelFormat::U16, Vec::new());
+        truncated.compression = Some(Compression::Rle);
+        truncated.compressed_channels = Some(PackedChannels(vec![3, 0, 1]));
+        assert_eq!(truncated.decompress(), Err(CompressionError::Malformed));
+
+        let mut bomb = frame(ChannelFormat::U8, Vec::new());
+        bomb.compression = Some(Compression::Rle);
+        bomb.compressed_channels = Some(PackedChannels([255, 0].repeat(2048)));
+        assert_eq!(bomb.decompress(), Err(CompressionError::TooLarge));
+    }
+}
@@ -0,0 +1,91 @@
+//! Pre-1.0 control payloads, kept only so older integrations can migrate.
+//!
+//! Early releases modelled control requests as a tagged `ControlPayload` enum. ALPINE 1.0
+//! sends a [`ControlOp`] plus a JSON payload instead; [`ControlPayload::into_op`] performs
+//! that translation so the resulting envelope is indistinguishable from a native one.
+//! Legacy device identities need no conversion: [`super::DeviceIdentity`] accepts their
+//! field names directly.
+
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::ControlOp;
+
+/// Body of the legacy `SetMode` request.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SetMode {
+    pub mode: String,
+}
+
+/// Legacy control request, encoded as `{"type": <variant>, "body": <payload>}`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "body")]
+pub enum ControlPayload {
+    GetInfo,
+    GetCaps,
+    Identify,
+    Restart,
+    GetStatus,
+    SetConfig(Value),
+    SetMode(SetMode),
+    TimeSync(Value),
+    Vendor(Value),
+}
+
+impl ControlPayload {
+    /// Converts the request into the 1.0 operation and payload.
+    pub fn into_op(self) -> (ControlOp, Value) {
+        match self {
+            ControlPayload::GetInfo => (ControlOp::GetInfo, json!({})),
+            ControlPayload::GetCaps => (ControlOp::GetCaps, json!({})),
+            ControlPayload::Identify => (ControlOp::Identify, json!({})),
+            ControlPayload::Restart => (ControlOp::Restart, json!({})),
+            ControlPayload::GetStatus => (ControlOp::GetStatus, json!({})),
+            ControlPayload::SetConfig(body) => (ControlOp::SetConfig, body),
+            ControlPayload::SetMode(body) => (ControlOp::SetMode, json!({ "mode": body.mode })),
+            ControlPayload::TimeSync(body) => (ControlOp::TimeSync, body),
+            ControlPayload::Vendor(body) => (ControlOp::Vendor, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::DeviceIdentity;
+
+    #[test]
+    fn legacy_set_mode_maps_to_native_op() {
+        let payload: ControlPayload =
+            serde_json::from_str(r#"{"type": "SetMode", "body": {"mode": "Normal"}}"#).unwrap();
+        assert_eq!(
+            payload.into_op(),
+            (ControlOp::SetMode, json!({ "mode": "Normal" }))
+        );
+
+        let payload: ControlPayload = serde_json::from_str(r#"{"type": "GetStatus"}"#).unwrap();
+        assert_eq!(payload.into_op(), (ControlOp::GetStatus, json!({})));
+    }
+
+    #[test]
+    fn legacy_identity_decodes_into_native_identity() {
+        let identity: DeviceIdentity = serde_json::from_str(
+            r#"{
+                "cid": "0b1e6f0c-6c8e-4d0e-9a53-8c1f1c1d2e3f",
+                "manufacturer": "Demo",
+                "model": "RustClient",
+                "firmware_rev": "1.0.11"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(identity.device_id, "0b1e6f0c-6c8e-4d0e-9a53-8c1f1c1d2e3f");
+        assert_eq!(identity.manufacturer_id, "Demo");
+        assert_eq!(identity.model_id, "RustClient");
+        assert_eq!(identity.hardware_rev, "");
+
+        let native = serde_json::to_value(&identity).unwrap();
+        assert!(native.get("device_id").is_some());
+        assert!(native.get("cid").is_none());
+    }
+}
@@ -0,0 +1,222 @@
+//! Typed view of a frame's `metadata` map.
+//!
+//! Keys starting with [`RESERVED_PREFIX`] belong to the protocol: recovery and adaptation
+//! state annotated by the sender, and a vendor area keyed by vendor name. Every other key
+//! is the application's. Reserved entries carry a `version` so receivers can tell what
+//! they are reading; entries from senders that predate it decode as version 1.
+
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{FrameEnvelope, Map};
+
+/// Prefix of the metadata keys reserved for the protocol.
+pub const RESERVED_PREFIX: &str = "alpine_";
+/// Key of the sender's [`RecoveryInfo`].
+pub const RECOVERY_KEY: &str = "alpine_recovery";
+/// Key of the sender's [`AdaptationInfo`].
+pub const ADAPTATION_KEY: &str = "alpine_adaptation";
+/// Key of the vendor area, a map from vendor name to value.
+pub const VENDOR_KEY: &str = "alpine_vendor";
+/// Version of the reserved entries this crate writes.
+pub const METADATA_VERSION: u8 = 1;
+
+fn first_version() -> u8 {
+    1
+}
+
+/// Recovery the sender is running on the frame's universe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryInfo {
+    #[serde(default = "first_version")]
+    pub version: u8,
+    pub phase: String,
+    /// Why recovery started, such as `sustained_loss` or `burst_loss`.
+    pub reason: String,
+}
+
+/// The sender's adaptation state for the frame's universe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdaptationInfo {
+    #[serde(default = "first_version")]
+    pub version: u8,
+    pub keyframe_interval: u8,
+    pub delta_depth: u8,
+    pub deadline_offset_ms: i16,
+    pub degraded_safe: bool,
+    pub frames_since_keyframe: u8,
+    /// Whether this frame is a forced keyframe.
+    pub force_keyframe: bool,
+    /// Last adaptation event, or `steady`.
+    pub event: String,
+}
+
+/// Refused metadata write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataError {
+    /// The key starts with [`RESERVED_PREFIX`]; use the typed setters instead.
+    Reserved(String),
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::Reserved(key) => write!(f, "metadata key {key} is reserved"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MetadataError {}
+
+/// Whether `key` is reserved for the protocol.
+pub fn is_reserved_key(key: &str) -> bool {
+    key.starts_with(RESERVED_PREFIX)
+}
+
+impl FrameEnvelope {
+    /// Recovery state annotated by the sender; `None` when absent or unreadable.
+    pub fn recovery_info(&self) -> Option<RecoveryInfo> {
+        self.reserved(RECOVERY_KEY)
+    }
+
+    pub fn set_recovery_info(&mut self, info: Option<RecoveryInfo>) {
+        self.set_reserved(RECOVERY_KEY, info);
+    }
+
+    /// Adaptation state annotated by the sender; `None` when absent or unreadable.
+    pub fn adaptation_info(&self) -> Option<AdaptationInfo> {
+        self.reserved(ADAPTATION_KEY)
+    }
+
+    pub fn set_adaptation_info(&mut self, info: Option<AdaptationInfo>) {
+        self.set_reserved(ADAPTATION_KEY, info);
+    }
+
+    /// Value `vendor` placed in the vendor area.
+    pub fn vendor_metadata(&self, vendor: &str) -> Option<&Value> {
+        self.metadata.as_ref()?.get(VENDOR_KEY)?.get(vendor)
+    }
+
+    /// Places `value` in the vendor area under `vendor`, replacing any earlier value.
+    pub fn set_vendor_metadata(&mut self, vendor: &str, value: Value) {
+        let area = self
+            .metadata
+            .get_or_insert_with(Map::new)
+            .entry(VENDOR_KEY.to_string())
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+        if !area.is_object() {
+            *area = Value::Object(serde_json::Map::new());
+        }
+        if let Value::Object(area) = area {
+            area.insert(vendor.to_string(), value);
+        }
+    }
+
+    /// Application entries, leaving out the reserved ones.
+    pub fn app_metadata(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.metadata
+            .iter()
+            .flatten()
+            .filter(|(key, _)| !is_reserved_key(key))
+            .map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// Sets an application entry; reserved keys are refused.
+    pub fn set_app_metadata(&mut self, key: &str, value: Value) -> Result<(), MetadataError> {
+        if is_reserved_key(key) {
+            return Err(MetadataError::Reserved(key.to_string()));
+        }
+        self.metadata
+            .get_or_insert_with(Map::new)
+            .insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn reserved<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.metadata.as_ref()?.get(key)?;
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    fn set_reserved<T: Serialize>(&mut self, key: &str, info: Option<T>) {
+        match info.and_then(|info| serde_json::to_value(info).ok()) {
+            Some(value) => {
+                self.metadata
+                    .get_or_insert_with(Map::new)
+                    .insert(key.to_string(), value);
+            }
+            None => {
+                if let Some(metadata) = &mut self.metadata {
+                    metadata.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ChannelFormat, MessageType};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn reserved_entries_are_typed_and_kept_apart() {
+        let mut frame = FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::nil(),
+            universe: 0,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format: ChannelFormat::U8,
+            channels: vec![1].into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            // As written by senders that predate versioned entries.
+            metadata: Some(
+                [
+                    (
+                        RECOVERY_KEY.to_string(),
+                        json!({"phase": "recovery", "reason": "burst_loss"}),
+                    ),
+                    ("scene".to_string(), json!("intro")),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+            extensions: None,
+        };
+        let recovery = frame.recovery_info().unwrap();
+        assert_eq!(
+            (recovery.version, recovery.reason.as_str()),
+            (1, "burst_loss")
+        );
+        assert_eq!(frame.adaptation_info(), None);
+
+        assert_eq!(
+            frame.set_app_metadata("alpine_recovery", json!(1)),
+            Err(MetadataError::Reserved("alpine_recovery".into()))
+        );
+        frame.set_app_metadata("cue", json!(4)).unwrap();
+        frame.set_vendor_metadata("acme", json!({"gobo": 2}));
+        frame.set_vendor_metadata("zed", json!(true));
+        assert_eq!(frame.vendor_metadata("acme"), Some(&json!({"gobo": 2})));
+        let mut app: alloc::vec::Vec<_> = frame.app_metadata().map(|(key, _)| key).collect();
+        app.sort_unstable();
+        assert_eq!(app, ["cue", "scene"]);
+
+        frame.set_recovery_info(None);
+        assert_eq!(frame.recovery_info(), None);
+    }
+}
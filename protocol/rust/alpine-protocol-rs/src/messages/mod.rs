@@ -1,9 +1,86 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use uuid::Uuid;
 
+use crate::codec::{self, CodecError};
+use crate::crypto::SuiteId;
+
+pub mod channels;
+pub mod compression;
+pub mod legacy;
+pub mod metadata;
+
+pub use channels::{ChannelBuffer, PackedChannels, UNIVERSE_CHANNELS};
+pub use compression::Compression;
+pub use metadata::{AdaptationInfo, MetadataError, RecoveryInfo};
+
+/// Map type used for open-ended message fields (groups, metadata, vendor extensions).
+///
+/// `HashMap` with `std`; `BTreeMap` on `no_std` targets. Both encode as CBOR maps.
+#[cfg(feature = "std")]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
 pub const ALPINE_VERSION: &str = "1.0";
 
+/// Priority of controllers that do not state one; see [`SessionInit::controller_priority`].
+pub const DEFAULT_CONTROLLER_PRIORITY: u8 = 100;
+
+/// What a session may do, as the controller asks for it in `session_init`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionRole {
+    /// Streams frames and sends any control operation the node allows.
+    #[default]
+    Controller,
+    /// Monitors only: reads status and stats, never streams or changes node state. The
+    /// node holds the session to this whatever its own access policy would grant.
+    Observer,
+}
+
+/// Vendor identifier keying an extension entry (reverse-DNS name or ESTA manufacturer id).
+pub type VendorId = String;
+
+/// Arbitrary CBOR value carried in a vendor extension.
+pub type ExtensionValue = ciborium::value::Value;
+
+/// CBOR payload of a [`ControlEnvelope`].
+///
+/// Payloads stay CBOR end to end, so integers and floats keep their major type through
+/// the MAC and nothing is re-encoded through JSON. Build one from any `Serialize` value
+/// with [`codec::to_value`] and read it back with [`ControlEnvelope::payload_as`].
+pub type ControlValue = ciborium::value::Value;
+
+/// Vendor extension map carried on discovery replies, established sessions, and frames.
+///
+/// Receivers ignore vendors they do not recognise. Senders omit the field when they have
+/// nothing to carry, so peers that predate extensions see an unchanged message.
+pub type Extensions = Map<VendorId, ExtensionValue>;
+
+/// Access to the vendor extension map of a message.
+pub trait Extensible {
+    fn extensions(&self) -> Option<&Extensions>;
+    fn extensions_mut(&mut self) -> &mut Option<Extensions>;
+
+    /// Returns the value `vendor` attached, if any.
+    fn extension(&self, vendor: &str) -> Option<&ExtensionValue> {
+        self.extensions().and_then(|ext| ext.get(vendor))
+    }
+
+    /// Attaches `value` under `vendor`, replacing any previous value.
+    fn set_extension(&mut self, vendor: impl Into<VendorId>, value: ExtensionValue) {
+        self.extensions_mut()
+            .get_or_insert_with(Extensions::new)
+            .insert(vendor.into(), value);
+    }
+}
+
 /// Common envelope type identifiers used across CBOR payloads.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -18,6 +95,21 @@ pub enum MessageType {
     AlpineControlAck,
     AlpineFrame,
     Keepalive,
+    AlpineError,
+    SessionResume,
+    SessionResumeAck,
+    AlpineControlProgress,
+    AlpineEcho,
+    AlpineEchoReply,
+    AlpineParity,
+    AlpineStreamHeartbeat,
+    AlpineCapabilityUpdate,
+    AlpineFrameFragment,
+    AlpineSealedFrame,
+    AlpineSessionPreempted,
+    AlpineSessionClose,
+    SessionRejected,
+    AlpineOutputMirror,
 }
 
 /// Discovery request broadcast by controllers.
@@ -56,6 +148,23 @@ pub struct DiscoveryReply {
     pub server_nonce: Vec<u8>,
     pub capabilities: CapabilitySet,
     pub signature: Vec<u8>,
+    /// Whether the device holds its maximum sessions and will refuse new handshakes;
+    /// absent from devices that do not report it. Not covered by the signature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busy: Option<bool>,
+    /// Vendor extensions; see [`Extensions`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Extensions>,
+}
+
+impl Extensible for DiscoveryReply {
+    fn extensions(&self) -> Option<&Extensions> {
+        self.extensions.as_ref()
+    }
+
+    fn extensions_mut(&mut self) -> &mut Option<Extensions> {
+        &mut self.extensions
+    }
 }
 
 impl DiscoveryReply {
@@ -78,16 +187,25 @@ impl DiscoveryReply {
             server_nonce,
             capabilities,
             signature,
+            busy: None,
+            extensions: None,
         }
     }
 }
 
 /// Device identity tuple exchanged during discovery and handshake.
+///
+/// The aliases accept identities written by pre-1.0 tooling (`cid`, `manufacturer`,
+/// `model`, no hardware revision); they are always re-encoded with the 1.0 names.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct DeviceIdentity {
+    #[serde(alias = "cid")]
     pub device_id: String,
+    #[serde(alias = "manufacturer")]
     pub manufacturer_id: String,
+    #[serde(alias = "model")]
     pub model_id: String,
+    #[serde(default)]
     pub hardware_rev: String,
     pub firmware_rev: String,
 }
@@ -100,7 +218,81 @@ pub struct CapabilitySet {
     pub grouping_supported: bool,
     pub streaming_supported: bool,
     pub encryption_supported: bool,
-    pub vendor_extensions: Option<HashMap<String, serde_json::Value>>,
+    pub vendor_extensions: Option<Map<String, serde_json::Value>>,
+    /// Frame compression schemes the device can decode; controllers may use any of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<Compression>,
+    /// Highest frame rate per universe the device can apply, when it is limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_frame_rate: Option<u32>,
+    /// Shortest time between two frames for the same universe, in microseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_frame_interval_us: Option<u32>,
+    /// Jitter strategies the device can apply; empty accepts every strategy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jitter_strategies: Vec<JitterStrategy>,
+    /// Physical output ports of multi-port nodes, numbered from 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<PortId>,
+}
+
+impl CapabilitySet {
+    /// Shortest interval between frames for one universe allowed by `max_frame_rate` and
+    /// `min_frame_interval_us` together; `None` when the device sets neither.
+    pub fn min_frame_interval(&self) -> Option<Duration> {
+        let by_rate = self
+            .max_frame_rate
+            .filter(|&rate| rate > 0)
+            .map(|rate| Duration::from_micros(1_000_000 / u64::from(rate)));
+        let by_interval = self
+            .min_frame_interval_us
+            .map(|us| Duration::from_micros(u64::from(us)));
+        by_rate.max(by_interval)
+    }
+
+    /// Whether the device applies `strategy`.
+    pub fn supports_jitter(&self, strategy: JitterStrategy) -> bool {
+        self.jitter_strategies.is_empty() || self.jitter_strategies.contains(&strategy)
+    }
+
+    /// Describes the first requested capability this set cannot provide, if any.
+    pub fn unsupported(&self, requested: &CapabilitySet) -> Option<String> {
+        if let Some(format) = requested
+            .channel_formats
+            .iter()
+            .find(|format| !self.channel_formats.contains(format))
+        {
+            return Some(format!("channel format {:?}", format));
+        }
+        if requested.max_channels > self.max_channels {
+            return Some(format!(
+                "{} channels requested, {} supported",
+                requested.max_channels, self.max_channels
+            ));
+        }
+        if let (Some(requested), Some(max)) = (requested.max_frame_rate, self.max_frame_rate) {
+            if requested > max {
+                return Some(format!("{} fps requested, {} supported", requested, max));
+            }
+        }
+        if let Some(strategy) = requested
+            .jitter_strategies
+            .iter()
+            .find(|&&strategy| !self.supports_jitter(strategy))
+        {
+            return Some(format!("jitter strategy {:?}", strategy));
+        }
+        if requested.grouping_supported && !self.grouping_supported {
+            return Some("grouping".into());
+        }
+        if requested.streaming_supported && !self.streaming_supported {
+            return Some("streaming".into());
+        }
+        if requested.encryption_supported && !self.encryption_supported {
+            return Some("encryption".into());
+        }
+        None
+    }
 }
 
 impl Default for CapabilitySet {
@@ -112,16 +304,35 @@ impl Default for CapabilitySet {
             streaming_supported: true,
             encryption_supported: true,
             vendor_extensions: None,
+            compression: Vec::new(),
+            max_frame_rate: None,
+            min_frame_interval_us: None,
+            jitter_strategies: Vec::new(),
+            ports: None,
         }
     }
 }
 
-/// Supported channel encodings for frames.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// How a receiver fills gaps and smooths changes between frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JitterStrategy {
+    HoldLast,
+    Drop,
+    Lerp,
+}
+
+/// Supported channel encodings for frames; see [`channels`] for conversions.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum ChannelFormat {
     U8,
     U16,
+    /// Normalized 0.0–1.0 values in `float_channels`.
+    F32,
+    /// 8-bit levels as one byte string in `packed_channels`.
+    #[serde(rename = "u8_packed")]
+    U8Packed,
 }
 
 /// Handshake session_init payload.
@@ -133,6 +344,21 @@ pub struct SessionInit {
     pub controller_pubkey: Vec<u8>,
     pub requested: CapabilitySet,
     pub session_id: Uuid,
+    /// Cipher suites the controller accepts, most preferred first; empty from controllers
+    /// that predate negotiation, which only speak ChaCha20-Poly1305.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suites: Vec<SuiteId>,
+    /// `firmware_rev` of the controller, for nodes that require a minimum; absent from
+    /// older controllers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller_firmware_rev: Option<String>,
+    /// How much the controller outranks others when the node is at its session limit;
+    /// [`DEFAULT_CONTROLLER_PRIORITY`] when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller_priority: Option<u8>,
+    /// Role the controller asks for; [`SessionRole::Controller`] when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<SessionRole>,
 }
 
 /// Handshake session_ack payload.
@@ -146,6 +372,9 @@ pub struct SessionAck {
     pub capabilities: CapabilitySet,
     pub signature: Vec<u8>,
     pub session_id: Uuid,
+    /// Cipher suite the device chose from the controller's offer.
+    #[serde(default)]
+    pub suite: SuiteId,
 }
 
 /// Controller readiness marker after keys are derived.
@@ -167,6 +396,46 @@ pub struct SessionComplete {
     pub error: Option<ErrorCode>,
 }
 
+/// Abbreviated handshake asking the device to resume an earlier session.
+///
+/// `mac` is a resumption binder over the new session id, proving the controller holds the
+/// secret retained from `resumed_session`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionResume {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub resumed_session: Uuid,
+    pub controller_nonce: Vec<u8>,
+    pub controller_pubkey: Vec<u8>,
+    pub mac: Vec<u8>,
+    /// Cipher suites the controller accepts, as in [`SessionInit::suites`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suites: Vec<SuiteId>,
+    /// As in [`SessionInit::controller_priority`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller_priority: Option<u8>,
+    /// As in [`SessionInit::role`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<SessionRole>,
+}
+
+/// Device answer to [`SessionResume`]; on refusal the controller falls back to `session_init`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionResumeAck {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub ok: bool,
+    pub error: Option<ErrorCode>,
+    pub device_nonce: Vec<u8>,
+    pub device_pubkey: Vec<u8>,
+    pub mac: Vec<u8>,
+    /// Cipher suite the device chose for the resumed session.
+    #[serde(default)]
+    pub suite: SuiteId,
+}
+
 /// Internal representation of an established session derived from the handshake.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SessionEstablished {
@@ -175,6 +444,38 @@ pub struct SessionEstablished {
     pub device_nonce: Vec<u8>,
     pub capabilities: CapabilitySet,
     pub device_identity: DeviceIdentity,
+    /// Vendor extensions; see [`Extensions`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Extensions>,
+    /// Priority the controller stated in its handshake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller_priority: Option<u8>,
+    /// Role the controller asked for in its handshake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<SessionRole>,
+}
+
+impl SessionEstablished {
+    /// The role the controller asked for, or [`SessionRole::Controller`].
+    pub fn role(&self) -> SessionRole {
+        self.role.unwrap_or_default()
+    }
+
+    /// The controller's stated priority, or [`DEFAULT_CONTROLLER_PRIORITY`].
+    pub fn priority(&self) -> u8 {
+        self.controller_priority
+            .unwrap_or(DEFAULT_CONTROLLER_PRIORITY)
+    }
+}
+
+impl Extensible for SessionEstablished {
+    fn extensions(&self) -> Option<&Extensions> {
+        self.extensions.as_ref()
+    }
+
+    fn extensions_mut(&mut self) -> &mut Option<Extensions> {
+        &mut self.extensions
+    }
 }
 
 /// Control-plane envelope with authenticated payload.
@@ -185,10 +486,18 @@ pub struct ControlEnvelope {
     pub session_id: Uuid,
     pub seq: u64,
     pub op: ControlOp,
-    pub payload: serde_json::Value,
+    pub payload: ControlValue,
     pub mac: Vec<u8>,
 }
 
+impl ControlEnvelope {
+    /// Decodes the payload as `P`; a `serde_json::Value` works for callers that still
+    /// handle payloads as JSON.
+    pub fn payload_as<P: DeserializeOwned>(&self) -> Result<P, CodecError> {
+        codec::from_value(&self.payload)
+    }
+}
+
 /// Ack for control-plane operations.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Acknowledge {
@@ -201,8 +510,117 @@ pub struct Acknowledge {
     pub mac: Vec<u8>,
 }
 
+/// Interim progress for a control operation that is still running.
+///
+/// Sent by the device between the envelope and its final ack; `seq` is the sequence of the
+/// envelope being worked on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ControlProgress {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub seq: u64,
+    /// The device's count of progress updates sent in the session, which numbers the
+    /// MAC nonce; `seq` may repeat across updates, this never does.
+    #[serde(default)]
+    pub update: u64,
+    /// Completion from 0 to 100, when the operation can estimate it.
+    pub percent: Option<u8>,
+    pub detail: Option<String>,
+    pub mac: Vec<u8>,
+}
+
+/// Capabilities a device pushes mid-session after its situation changed, such as a failed
+/// output port or thermal throttling.
+///
+/// `seq` is the device's own counter for the session and only ever grows; the controller
+/// replaces the capabilities it negotiated with `capabilities`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CapabilityUpdate {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub seq: u64,
+    pub capabilities: CapabilitySet,
+    /// Why the capabilities changed, for operator logs.
+    pub reason: Option<String>,
+    pub mac: Vec<u8>,
+}
+
+/// Sent by a device to the controller whose session a higher-priority controller is
+/// taking over.
+///
+/// The session keeps streaming for `grace_ms`, then the device closes it and the new
+/// controller's frames take effect. `seq` shares the device's counter with
+/// [`CapabilityUpdate`]s.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionPreempted {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub seq: u64,
+    /// Priority of the controller taking over.
+    pub priority: u8,
+    pub grace_ms: u64,
+    pub mac: Vec<u8>,
+}
+
+/// Levels of consecutive channels of one universe as the node applied them, pushed to
+/// an observer session that subscribed with `subscribe_output`.
+///
+/// `seq` numbers the session's mirrored output in its own nonce lane, counting up from 0.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MirroredOutput {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub seq: u64,
+    pub universe: UniverseId,
+    /// Node clock when the look was applied, in microseconds since the epoch.
+    pub applied_us: u64,
+    /// Index of the first channel in `levels`.
+    pub start: u16,
+    pub levels: Vec<u16>,
+    pub mac: Vec<u8>,
+}
+
+/// Sent by a controller that is done with a session, so the device frees it at once
+/// instead of waiting out the keepalive timeout.
+///
+/// Fire-and-forget: the device does not answer. `seq` comes from the controller's
+/// control envelope counter.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionClose {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub seq: u64,
+    /// Why the controller closed the session, for operator logs.
+    pub reason: Option<String>,
+    pub mac: Vec<u8>,
+}
+
+/// Sent by a node that refuses a handshake, in place of the message the controller is
+/// waiting for.
+///
+/// Unlike an [`ErrorEnvelope`] it is signed with the node's identity key over the
+/// controller's nonce, so a controller can trust the `code` and stop retrying a node that
+/// will never accept it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionRejected {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub code: ErrorCode,
+    pub detail: Option<String>,
+    /// How long a busy node asks the controller to wait before its next attempt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+    pub signature: Vec<u8>,
+}
+
 /// Control operations enumerated by the spec.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ControlOp {
     GetInfo,
@@ -214,20 +632,172 @@ pub enum ControlOp {
     SetMode,
     TimeSync,
     Vendor,
+    /// Hands a running session over to a new controller (payload: `MigrationTicket`).
+    Migrate,
+    /// Saves the node's current look into a scene slot (payload: `{slot, name?}`).
+    StoreScene,
+    /// Lists stored scenes; the ack detail is a JSON array of summaries.
+    ListScenes,
+    /// Crossfades to a stored scene (payload: `{slot, fade_ms?}`).
+    RecallScene,
+    /// Uploads one chunk of a show for standalone playback (payload: `ShowChunk`).
+    UploadShow,
+    /// Arms or disarms fallback playback of the uploaded show (payload: `{idle_ms}`).
+    SetFallback,
+    /// Replaces the node's time-of-day schedule (payload: `Schedule`).
+    SetSchedule,
+    /// Reads the schedule back; the ack detail is the schedule as JSON.
+    GetSchedule,
+    /// Sets how a universe merges several sources (payload: `{universe, policy}`).
+    SetMergePolicy,
+    /// Registers or replaces a channel group (payload: `{id, name, channels}`).
+    DefineGroup,
+    /// Removes a channel group (payload: `{id}`).
+    RemoveGroup,
+    /// Lists channel groups; the ack detail is a JSON array of groups.
+    ListGroups,
+    /// Reads latency percentiles per stage; the ack detail is the report as JSON.
+    GetLatency,
+    /// Announces that the controller is deliberately stopping frames; the node holds
+    /// the last look (payload: `{}`).
+    PauseStream,
+    /// Announces that frames are about to resume after `pause_stream` (payload: `{}`).
+    ResumeStream,
+    /// Switches a running stream to a new profile (payload: `{intent, latency_weight,
+    /// resilience_weight, config_id}`); the ack detail is the adopted `config_id`.
+    RestartStream,
+    /// Announces the profile a stream starts under (payload as `restart_stream`); the
+    /// ack detail is the bound `config_id`.
+    StartStream,
+    /// Tunnels an RDM GET or SET to a responder at or behind the node (payload:
+    /// `{dest, sub_device?, command_class, pid, data?}`); the ack detail is the reply.
+    RdmCommand,
+    /// Installs a newer signed revocation list (payload: `RevocationList`); the ack
+    /// detail is its serial.
+    UpdateRevocations,
+    /// Reads one page of the node's capture of received frames (payload: `{since_us?,
+    /// until_us?, universe?, cursor?}`); the ack detail is the page as JSON.
+    GetCapture,
+    /// Assigns a universe to an output port, or back to its default one (payload:
+    /// `{universe, port}`).
+    SetPortMap,
+    /// Drives one universe of the node's outputs with a test pattern, or stops it
+    /// (payload: `{universe, pattern?, channels?, duration_ms?}`).
+    TestPattern,
+    /// Reads one page of the node's event log (payload: `{min_severity?, tail?,
+    /// cursor?}`); the ack detail is the page as JSON.
+    GetLogs,
+    /// Wipes the node's settings back to the factory state (payload: `{confirm?,
+    /// reason?}`); the first request only returns the nonce to confirm it with.
+    FactoryReset,
+    /// Reads the node's clock, time source, and offset; the ack detail is a `TimeStatus`
+    /// as JSON.
+    GetTime,
+    /// Mirrors the node's applied output to an observer session (payload: `{universes?,
+    /// rate_hz?}`); the ack detail is the granted rate, 0 once unsubscribed.
+    SubscribeOutput,
+}
+
+impl ControlOp {
+    /// Whether the operation only reads node state, so a monitoring session may send it.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            ControlOp::GetInfo
+                | ControlOp::GetCaps
+                | ControlOp::GetStatus
+                | ControlOp::ListScenes
+                | ControlOp::GetSchedule
+                | ControlOp::ListGroups
+                | ControlOp::GetLatency
+                | ControlOp::GetCapture
+                | ControlOp::GetLogs
+                | ControlOp::GetTime
+                | ControlOp::SubscribeOutput
+        )
+    }
 }
 
+/// Identifier of a logical universe within a stream.
+pub type UniverseId = u16;
+
+/// Physical output port of a node, numbered from 1.
+pub type PortId = u8;
+
+/// Payload of `identify`; an empty payload identifies the whole node.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdentifyRequest {
+    /// Identify only the fixtures on this port.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<PortId>,
+}
+
+/// Identifier of a channel group registered on the node.
+pub type GroupId = u16;
+
+/// Universe used by senders that do not address universes explicitly.
+pub const DEFAULT_UNIVERSE: UniverseId = 0;
+
 /// Real-time frame envelope.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FrameEnvelope {
     #[serde(rename = "type")]
     pub message_type: MessageType,
     pub session_id: Uuid,
+    /// Absent on frames from pre-universe senders, which decode as `DEFAULT_UNIVERSE`.
+    #[serde(default)]
+    pub universe: UniverseId,
     pub timestamp_us: u64,
     pub priority: u8,
     pub channel_format: ChannelFormat,
-    pub channels: Vec<u16>,
-    pub groups: Option<HashMap<String, Vec<u16>>>,
-    pub metadata: Option<HashMap<String, serde_json::Value>>,
+    pub channels: ChannelBuffer,
+    /// Levels of a `u8_packed` frame, which leaves `channels` empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packed_channels: Option<PackedChannels>,
+    /// Values of an `f32` frame, which leaves `channels` empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub float_channels: Option<Vec<f32>>,
+    /// Scheme compressing the frame's values into `compressed_channels`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_channels: Option<PackedChannels>,
+    pub groups: Option<Map<String, Vec<u16>>>,
+    /// Groups from the node's registry, referenced by id instead of listing channels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_refs: Option<Vec<GroupId>>,
+    /// Leading 32 bits of the stream profile's `config_id`, so the node can refuse frames
+    /// sent under a profile it was never told about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_tag: Option<u32>,
+    /// Sender wall clock, in microseconds, after which the frame has missed its deadline;
+    /// see [`crate::profile::WireBehavior::frame_deadline`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_us: Option<u64>,
+    /// Keys prefixed `alpine_` are reserved; see [`metadata`] for typed access.
+    pub metadata: Option<Map<String, serde_json::Value>>,
+    /// Vendor extensions; see [`Extensions`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Extensions>,
+}
+
+impl FrameEnvelope {
+    /// Whether a frame received at `arrival_us` missed its deadline; frames from senders
+    /// that set none never do.
+    pub fn missed_deadline(&self, arrival_us: u64) -> bool {
+        self.deadline_us
+            .is_some_and(|deadline_us| arrival_us > deadline_us)
+    }
+}
+
+impl Extensible for FrameEnvelope {
+    fn extensions(&self) -> Option<&Extensions> {
+        self.extensions.as_ref()
+    }
+
+    fn extensions_mut(&mut self) -> &mut Option<Extensions> {
+        &mut self.extensions
+    }
 }
 
 /// Control-plane keepalive frame to detect dead sessions.
@@ -237,6 +807,105 @@ pub struct Keepalive {
     pub message_type: MessageType,
     pub session_id: Uuid,
     pub tick_ms: u64,
+    /// Stream counters of the sender; absent from peers that send none. A node answers a
+    /// keepalive carrying them with a keepalive carrying its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<KeepaliveStats>,
+}
+
+/// Compact stream counters piggybacked on a [`Keepalive`], giving both peers basic
+/// delivery figures without receiver reports.
+///
+/// Keepalives carry no MAC, so the figures are for display and diagnosis only.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeepaliveStats {
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    /// `timestamp_us` of the last frame sent, which orders frames like a sequence number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_frame_us: Option<u64>,
+    /// Frames the other peer reported sending that never arrived here.
+    #[serde(default)]
+    pub frames_lost: u64,
+}
+
+/// XOR parity over a group of encoded frames of one universe, from which a receiver
+/// rebuilds any single lost frame of the group.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ParityFrame {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub universe: UniverseId,
+    /// `timestamp_us` of each covered frame, in send order.
+    pub covers: Vec<u64>,
+    /// Encoded length of each covered frame, matching `covers`.
+    pub lengths: Vec<u32>,
+    /// XOR of the covered frames' encodings, each zero-padded to the longest.
+    pub parity: PackedChannels,
+}
+
+/// Authenticated probe on the streaming path, reflected by the receiver as soon as it
+/// arrives to measure round-trip and one-way delay.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EchoFrame {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    pub seq: u64,
+    /// Sender wall clock when the probe left, in microseconds.
+    pub sent_us: u64,
+    /// Receiver wall clock when it reflected the probe; set on replies only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reflected_us: Option<u64>,
+    /// Filler that sizes the probe when it measures the path MTU; reflected unchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub padding: Option<PackedChannels>,
+    pub mac: Vec<u8>,
+}
+
+/// One piece of a stream datagram too large for the path MTU.
+///
+/// The sender cuts the encoded datagram into `count` pieces of equal size, the last one
+/// shorter; the receiver concatenates them by `index` once all have arrived and handles
+/// the result like the datagram itself.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FrameFragment {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    /// Sender's counter for fragmented datagrams; shared by every piece of one.
+    pub datagram_id: u64,
+    pub index: u16,
+    pub count: u16,
+    pub data: PackedChannels,
+}
+
+/// A frame or parity datagram encrypted under the key of one stream epoch; see
+/// [`crate::crypto::ratchet`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SealedFrame {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    /// Epoch whose key sealed the datagram.
+    pub epoch: u32,
+    /// Sender's counter for the datagrams of `epoch`, restarting at zero in each epoch.
+    pub seq: u64,
+    /// The datagram's encoding, encrypted, followed by the AEAD tag.
+    pub ciphertext: PackedChannels,
+}
+
+/// Authenticated heartbeat on the streaming path, sent while no frames flow so receivers
+/// can tell an idle or paused sender from a dead one.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StreamHeartbeat {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub session_id: Uuid,
+    /// Strictly increasing per sender; receivers ignore replays.
+    pub seq: u64,
+    pub mac: Vec<u8>,
 }
 
 /// Standard error codes from docs/errors.md.
@@ -256,7 +925,85 @@ pub enum ErrorCode {
     ControlUnknownOp,
     ControlPayloadInvalid,
     ControlUnauthorized,
+    HandshakeProtocolViolation,
+    HandshakeCapabilityMismatch,
     StreamBadFormat,
     StreamTooLarge,
     StreamUnsupportedChannelMode,
+    /// The responder is shedding control traffic; retry after `retry_after_ms`.
+    ControlRateLimited,
+    /// The session is streaming faster than its frame quota; frames are refused for
+    /// `retry_after_ms`.
+    StreamRateLimited,
+    /// The peer runs older firmware than required; `detail` names both revisions.
+    HandshakeFirmwareIncompatible,
+    /// The node does not accept this controller: it is not on the allowlist or its key is
+    /// revoked.
+    HandshakeNotAuthorized,
+    /// The node already serves as many sessions, or runs as many handshakes, as it can;
+    /// retry after `retry_after_ms`.
+    HandshakeCapacity,
+}
+
+impl ErrorCode {
+    /// Whether repeating the same request may succeed without operator intervention.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::HandshakeTimeout
+                | ErrorCode::HandshakeReplay
+                | ErrorCode::SessionExpired
+                | ErrorCode::ControlRateLimited
+                | ErrorCode::StreamRateLimited
+                | ErrorCode::HandshakeCapacity
+        )
+    }
+}
+
+/// Structured error report sent when a handshake or control exchange fails.
+///
+/// Error envelopes are not authenticated, so receivers treat them as advisory and only act
+/// on ones that match the session (and control sequence) they are waiting on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ErrorEnvelope {
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    /// Absent when the failure happened before a session id was agreed.
+    pub session_id: Option<Uuid>,
+    /// Control sequence the error answers, if it answers one.
+    pub seq: Option<u64>,
+    pub code: ErrorCode,
+    pub retryable: bool,
+    pub detail: Option<String>,
+    /// How long the sender should wait before retrying, for `CONTROL_RATE_LIMITED`,
+    /// `STREAM_RATE_LIMITED`, and `HANDSHAKE_CAPACITY`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl ErrorEnvelope {
+    pub fn new(session_id: Option<Uuid>, code: ErrorCode, detail: Option<String>) -> Self {
+        Self {
+            message_type: MessageType::AlpineError,
+            session_id,
+            seq: None,
+            retryable: code.is_retryable(),
+            code,
+            detail,
+            retry_after_ms: None,
+        }
+    }
+
+    /// Builds the error answering control envelope `seq` on `session_id`.
+    pub fn for_control(
+        session_id: Uuid,
+        seq: u64,
+        code: ErrorCode,
+        detail: Option<String>,
+    ) -> Self {
+        Self {
+            seq: Some(seq),
+            ..Self::new(Some(session_id), code, detail)
+        }
+    }
 }
@@ -0,0 +1,317 @@
+//! Mirroring the node's applied output to observer sessions.
+//!
+//! A remote visualizer should show what the fixtures are actually receiving, after
+//! merging or arbitration, rather than what one controller thinks it sent. An
+//! [`OutputMirror`] keeps the latest look applied to each universe; an observer session
+//! subscribes with `subscribe_output`, and its serving task then pushes the universes
+//! that changed as authenticated [`crate::messages::MirroredOutput`] notices, at no more
+//! than the subscribed rate and never faster than the node's cap.
+//!
+//! Each notice travels in one datagram, so a universe goes out in [`MirrorSlice`]s of at
+//! most [`MIRROR_SLICE_CHANNELS`] channels.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::control::ControlHandlers;
+use crate::messages::{ControlOp, SessionRole, UniverseId};
+use crate::session::registry::SessionRegistry;
+
+/// Looks per second an observer gets at most unless the node is configured otherwise.
+pub const DEFAULT_MIRROR_RATE_HZ: u32 = 10;
+
+/// Most channels one mirrored notice carries.
+pub const MIRROR_SLICE_CHANNELS: usize = 256;
+
+/// Payload of `subscribe_output`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutputSubscription {
+    /// Universes to mirror; every universe when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub universes: Option<Vec<UniverseId>>,
+    /// Looks per second per universe, capped by the node; the cap when absent, and 0
+    /// ends the subscription.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_hz: Option<u32>,
+}
+
+/// Consecutive channels of one universe as the node applied them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorSlice {
+    pub universe: UniverseId,
+    /// Node clock when the look was applied, in microseconds since the epoch.
+    pub applied_us: u64,
+    /// Index of the first channel in `levels`.
+    pub start: u16,
+    pub levels: Vec<u16>,
+}
+
+#[derive(Debug)]
+struct Look {
+    levels: Vec<u16>,
+    applied_us: u64,
+    version: u64,
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    universes: Option<HashSet<UniverseId>>,
+    interval: Duration,
+    next: Instant,
+    /// Version of the look last sent per universe.
+    sent: HashMap<UniverseId, u64>,
+}
+
+#[derive(Debug, Default)]
+struct MirrorState {
+    looks: HashMap<UniverseId, Look>,
+    /// Counts looks that changed a universe, so subscribers can tell which they sent.
+    version: u64,
+    subscribers: HashMap<Uuid, Subscriber>,
+}
+
+/// Latest applied look per universe and the observer sessions subscribed to it.
+///
+/// Cloning shares the state, so the node's serving tasks record into and read from the
+/// same mirror.
+#[derive(Debug, Clone)]
+pub struct OutputMirror {
+    max_rate_hz: u32,
+    state: Arc<Mutex<MirrorState>>,
+}
+
+impl Default for OutputMirror {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIRROR_RATE_HZ)
+    }
+}
+
+impl OutputMirror {
+    /// Mirror that sends each observer at most `max_rate_hz` looks per second and
+    /// universe; at least one.
+    pub fn new(max_rate_hz: u32) -> Self {
+        Self {
+            max_rate_hz: max_rate_hz.max(1),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn max_rate_hz(&self) -> u32 {
+        self.max_rate_hz
+    }
+
+    /// Notes the levels just applied to `universe`; an unchanged look is not sent again.
+    pub fn record(&self, universe: UniverseId, levels: &[u16], applied_us: u64) {
+        let mut state = self.state.lock();
+        if state
+            .looks
+            .get(&universe)
+            .is_some_and(|look| look.levels == levels)
+        {
+            return;
+        }
+        state.version += 1;
+        let version = state.version;
+        state.looks.insert(
+            universe,
+            Look {
+                levels: levels.to_vec(),
+                applied_us,
+                version,
+            },
+        );
+    }
+
+    /// Subscribes `session_id`, replacing any earlier subscription, and returns the rate
+    /// granted; 0 unsubscribes. The current look of every mirrored universe is due at
+    /// once, so a visualizer starts from what the fixtures show now.
+    pub fn subscribe(
+        &self,
+        session_id: Uuid,
+        subscription: &OutputSubscription,
+        now: Instant,
+    ) -> u32 {
+        let rate = subscription
+            .rate_hz
+            .unwrap_or(self.max_rate_hz)
+            .min(self.max_rate_hz);
+        if rate == 0 {
+            self.unsubscribe(session_id);
+            return 0;
+        }
+        let subscriber = Subscriber {
+            universes: subscription
+                .universes
+                .as_ref()
+                .map(|universes| universes.iter().copied().collect()),
+            interval: Duration::from_secs(1) / rate,
+            next: now,
+            sent: HashMap::new(),
+        };
+        self.state.lock().subscribers.insert(session_id, subscriber);
+        rate
+    }
+
+    /// Ends the subscription of `session_id`; `false` if it had none.
+    pub fn unsubscribe(&self, session_id: Uuid) -> bool {
+        self.state.lock().subscribers.remove(&session_id).is_some()
+    }
+
+    /// When `session_id` is next due a look, if it is subscribed.
+    pub fn next_due(&self, session_id: Uuid) -> Option<Instant> {
+        self.state
+            .lock()
+            .subscribers
+            .get(&session_id)
+            .map(|subscriber| subscriber.next)
+    }
+
+    /// Slices of the looks `session_id` has not been sent yet, once it is due; the next
+    /// batch is then due one interval later.
+    pub fn take_due(&self, session_id: Uuid, now: Instant) -> Vec<MirrorSlice> {
+        let mut state = self.state.lock();
+        let MirrorState {
+            looks, subscribers, ..
+        } = &mut *state;
+        let Some(subscriber) = subscribers.get_mut(&session_id) else {
+            return Vec::new();
+        };
+        if now < subscriber.next {
+            return Vec::new();
+        }
+        subscriber.next = now + subscriber.interval;
+        let mut universes: Vec<_> = looks
+            .iter()
+            .filter(|(universe, look)| {
+                subscriber
+                    .universes
+                    .as_ref()
+                    .is_none_or(|wanted| wanted.contains(universe))
+                    && subscriber.sent.get(universe) != Some(&look.version)
+            })
+            .collect();
+        universes.sort_by_key(|(universe, _)| **universe);
+        let mut slices = Vec::new();
+        for (&universe, look) in universes {
+            subscriber.sent.insert(universe, look.version);
+            for (index, levels) in look.levels.chunks(MIRROR_SLICE_CHANNELS).enumerate() {
+                slices.push(MirrorSlice {
+                    universe,
+                    applied_us: look.applied_us,
+                    start: (index * MIRROR_SLICE_CHANNELS) as u16,
+                    levels: levels.to_vec(),
+                });
+            }
+        }
+        slices
+    }
+
+    /// Registers the handler for `subscribe_output`, which only observer sessions in
+    /// `sessions` may use; the ack detail is the rate granted.
+    pub fn register(&self, handlers: &ControlHandlers, sessions: &SessionRegistry) {
+        let (mirror, sessions) = (self.clone(), sessions.clone());
+        handlers.on_envelope(ControlOp::SubscribeOutput, move |env| {
+            if sessions.role(env.session_id) != Some(SessionRole::Observer) {
+                return Err("output mirroring is for observer sessions".into());
+            }
+            let subscription: OutputSubscription = env.payload_as().map_err(|e| e.to_string())?;
+            let rate = mirror.subscribe(env.session_id, &subscription, Instant::now());
+            Ok(Some(rate.to_string()))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscribed(mirror: &OutputMirror, rate_hz: Option<u32>, now: Instant) -> Uuid {
+        let session_id = Uuid::new_v4();
+        let subscription = OutputSubscription {
+            universes: None,
+            rate_hz,
+        };
+        mirror.subscribe(session_id, &subscription, now);
+        session_id
+    }
+
+    #[test]
+    fn sends_changed_looks_at_the_subscribed_rate() {
+        let mirror = OutputMirror::new(20);
+        let now = Instant::now();
+        let observer = subscribed(&mirror, Some(5), now);
+        mirror.record(1, &[10, 20], 1_000);
+        mirror.record(1, &[30, 40], 2_000);
+        let slices = mirror.take_due(observer, now);
+        assert_eq!(
+            slices,
+            vec![MirrorSlice {
+                universe: 1,
+                applied_us: 2_000,
+                start: 0,
+                levels: vec![30, 40],
+            }]
+        );
+
+        mirror.record(1, &[50, 60], 3_000);
+        assert!(mirror
+            .take_due(observer, now + Duration::from_millis(100))
+            .is_empty());
+        assert_eq!(
+            mirror.next_due(observer),
+            Some(now + Duration::from_millis(200))
+        );
+        let later = now + Duration::from_millis(200);
+        assert_eq!(mirror.take_due(observer, later)[0].levels, vec![50, 60]);
+        // Nothing changed since, so nothing is sent again.
+        mirror.record(1, &[50, 60], 4_000);
+        assert!(mirror
+            .take_due(observer, later + Duration::from_secs(1))
+            .is_empty());
+    }
+
+    #[test]
+    fn rates_are_capped_and_zero_unsubscribes() {
+        let mirror = OutputMirror::new(10);
+        let now = Instant::now();
+        let observer = Uuid::new_v4();
+        let asked = OutputSubscription {
+            universes: Some(vec![2]),
+            rate_hz: Some(60),
+        };
+        assert_eq!(mirror.subscribe(observer, &asked, now), 10);
+        mirror.record(1, &[1], 0);
+        mirror.record(2, &[2], 0);
+        let slices = mirror.take_due(observer, now);
+        assert_eq!(slices.len(), 1);
+        assert_eq!(slices[0].universe, 2);
+
+        let stop = OutputSubscription {
+            universes: None,
+            rate_hz: Some(0),
+        };
+        assert_eq!(mirror.subscribe(observer, &stop, now), 0);
+        assert_eq!(mirror.next_due(observer), None);
+    }
+
+    #[test]
+    fn large_universes_are_sliced() {
+        let mirror = OutputMirror::default();
+        let now = Instant::now();
+        let observer = subscribed(&mirror, None, now);
+        mirror.record(7, &[9; 512], 0);
+        let slices = mirror.take_due(observer, now);
+        assert_eq!(
+            slices
+                .iter()
+                .map(|slice| (slice.start, slice.levels.len()))
+                .collect::<Vec<_>>(),
+            vec![(0, 256), (256, 256)]
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//! Physical output of received looks.
+//!
+//! An [`OutputDriver`] takes each look a node publishes, after smoothing, merge, and
+//! arbitration, and puts it on the wire to the fixtures. With the `dmx-serial` feature the
+//! crate ships `dmx_serial::DmxSerialDriver`, which drives a DMX512 line from a UART;
+//! with `enttec` it ships `enttec::EnttecPro` for the Enttec DMX USB Pro widget.
+//! [`pattern::PatternGenerator`] renders commissioning test patterns onto the drivers.
+
+use thiserror::Error;
+
+use crate::messages::channels::quantize;
+use crate::messages::FrameEnvelope;
+
+#[cfg(feature = "dmx-serial")]
+pub mod dmx_serial;
+#[cfg(feature = "enttec")]
+pub mod enttec;
+pub mod pattern;
+#[cfg(all(target_os = "linux", any(feature = "dmx-serial", feature = "enttec")))]
+mod tty;
+
+/// Errors raised by output drivers.
+#[derive(Debug, Error)]
+pub enum OutputError {
+    #[error("output port failed: {0}")]
+    Port(String),
+    #[error("output driver is closed")]
+    Closed,
+}
+
+/// Sink for the looks a node outputs.
+///
+/// `output` is called on the node's receive path and must not block on the hardware;
+/// drivers that refresh continuously keep the latest look and transmit it from their own
+/// thread.
+pub trait OutputDriver: Send + Sync {
+    fn output(&self, look: &FrameEnvelope) -> Result<(), OutputError>;
+}
+
+/// The look's values as 8-bit DMX levels, whatever its channel format.
+pub fn dmx_levels(look: &FrameEnvelope) -> Vec<u8> {
+    look.normalized()
+        .iter()
+        .map(|&value| quantize(value, u16::from(u8::MAX)) as u8)
+        .collect()
+}
@@ -0,0 +1,303 @@
+//! DMX512 output from a UART driving an RS-485 transceiver.
+//!
+//! DMX512 runs at 250 kbaud, 8 data bits, no parity, 2 stop bits. Each packet starts with a
+//! break of at least 92 µs and a mark-after-break of at least 12 µs, then the start code 0
+//! and up to 512 slots. Fixtures hold or blank their output when packets stop, so the
+//! driver retransmits the latest levels continuously from its own thread.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use super::{dmx_levels, OutputDriver, OutputError};
+use crate::messages::{FrameEnvelope, UniverseId, DEFAULT_UNIVERSE};
+
+/// DMX512 line rate in baud.
+pub const DMX_BAUD: u32 = 250_000;
+
+/// Largest number of slots in a DMX512 packet.
+pub const DMX_SLOTS: usize = 512;
+
+const START_CODE: u8 = 0;
+
+/// Universe and line timing of one serial DMX port.
+#[derive(Debug, Clone, Copy)]
+pub struct DmxSerialConfig {
+    /// Universe whose looks the port outputs; looks for other universes are ignored.
+    pub universe: UniverseId,
+    /// Slots sent per packet, at most [`DMX_SLOTS`]; fewer raise the refresh rate.
+    pub slots: usize,
+    pub break_time: Duration,
+    pub mark_after_break: Duration,
+}
+
+impl Default for DmxSerialConfig {
+    fn default() -> Self {
+        Self {
+            universe: DEFAULT_UNIVERSE,
+            slots: DMX_SLOTS,
+            break_time: Duration::from_micros(176),
+            mark_after_break: Duration::from_micros(16),
+        }
+    }
+}
+
+/// Serial line a [`DmxSerialDriver`] transmits on, already set up for 250 kbaud 8N2.
+pub trait DmxLine: Send + 'static {
+    /// Holds the line low (break) or releases it.
+    fn set_break(&mut self, on: bool) -> io::Result<()>;
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()>;
+    /// Waits until every written byte has left the UART.
+    fn drain(&mut self) -> io::Result<()>;
+}
+
+/// A Linux serial device such as `/dev/ttyAMA0` or `/dev/ttyUSB0`.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct TtyLine(std::fs::File);
+
+#[cfg(target_os = "linux")]
+impl TtyLine {
+    /// Opens `path` and switches it to raw 250 kbaud 8N2.
+    pub fn open(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        super::tty::open_raw(path.as_ref(), DMX_BAUD, true).map(Self)
+    }
+
+    fn check(result: libc::c_int) -> io::Result<()> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DmxLine for TtyLine {
+    fn set_break(&mut self, on: bool) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        let request = if on { libc::TIOCSBRK } else { libc::TIOCCBRK };
+        // SAFETY: the descriptor is open, and the break requests take no argument.
+        Self::check(unsafe { libc::ioctl(self.0.as_raw_fd(), request) })
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        io::Write::write_all(&mut self.0, bytes)
+    }
+
+    fn drain(&mut self) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        // SAFETY: the descriptor is open.
+        Self::check(unsafe { libc::tcdrain(self.0.as_raw_fd()) })
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    slots: Mutex<Vec<u8>>,
+    running: AtomicBool,
+    failure: Mutex<Option<String>>,
+}
+
+/// [`OutputDriver`] that outputs one universe as DMX512 on a serial line.
+#[derive(Debug)]
+pub struct DmxSerialDriver {
+    universe: UniverseId,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DmxSerialDriver {
+    /// Opens the serial device at `path` and starts transmitting.
+    #[cfg(target_os = "linux")]
+    pub fn open(
+        path: impl AsRef<std::path::Path>,
+        config: DmxSerialConfig,
+    ) -> Result<Self, OutputError> {
+        let line = TtyLine::open(path).map_err(|e| OutputError::Port(e.to_string()))?;
+        Self::new(line, config)
+    }
+
+    /// Starts transmitting on `line`, with every slot at zero until the first look.
+    pub fn new<L: DmxLine>(line: L, config: DmxSerialConfig) -> Result<Self, OutputError> {
+        let shared = Arc::new(Shared {
+            slots: Mutex::new(vec![0; config.slots.min(DMX_SLOTS)]),
+            running: AtomicBool::new(true),
+            failure: Mutex::new(None),
+        });
+        let thread = thread::Builder::new()
+            .name("alpine-dmx-serial".into())
+            .spawn({
+                let shared = shared.clone();
+                move || refresh(line, &shared, &config)
+            })
+            .map_err(|e| OutputError::Port(e.to_string()))?;
+        Ok(Self {
+            universe: config.universe,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn universe(&self) -> UniverseId {
+        self.universe
+    }
+}
+
+impl OutputDriver for DmxSerialDriver {
+    fn output(&self, look: &FrameEnvelope) -> Result<(), OutputError> {
+        if let Some(failure) = self.shared.failure.lock().clone() {
+            return Err(OutputError::Port(failure));
+        }
+        if !self.shared.running.load(Ordering::Relaxed) {
+            return Err(OutputError::Closed);
+        }
+        if look.universe != self.universe {
+            return Ok(());
+        }
+        let levels = dmx_levels(look);
+        let mut slots = self.shared.slots.lock();
+        let used = levels.len().min(slots.len());
+        slots[..used].copy_from_slice(&levels[..used]);
+        slots[used..].fill(0);
+        Ok(())
+    }
+}
+
+impl Drop for DmxSerialDriver {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Transmits the latest slots until the driver is dropped or the line fails.
+fn refresh<L: DmxLine>(mut line: L, shared: &Shared, config: &DmxSerialConfig) {
+    let mut packet = Vec::with_capacity(DMX_SLOTS + 1);
+    while shared.running.load(Ordering::Relaxed) {
+        packet.clear();
+        packet.push(START_CODE);
+        packet.extend_from_slice(&shared.slots.lock());
+        if let Err(err) = transmit(&mut line, &packet, config) {
+            *shared.failure.lock() = Some(err.to_string());
+            return;
+        }
+    }
+}
+
+fn transmit<L: DmxLine>(line: &mut L, packet: &[u8], config: &DmxSerialConfig) -> io::Result<()> {
+    // The previous packet must be on the wire before the break cuts it off.
+    line.drain()?;
+    line.set_break(true)?;
+    thread::sleep(config.break_time);
+    line.set_break(false)?;
+    thread::sleep(config.mark_after_break);
+    line.write_all(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ChannelFormat, MessageType};
+    use std::time::Instant;
+    use uuid::Uuid;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Event {
+        Break(bool),
+        Write(Vec<u8>),
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeLine {
+        events: Arc<Mutex<Vec<Event>>>,
+        fail: Arc<AtomicBool>,
+    }
+
+    impl DmxLine for FakeLine {
+        fn set_break(&mut self, on: bool) -> io::Result<()> {
+            if self.fail.load(Ordering::Relaxed) {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "unplugged"));
+            }
+            self.events.lock().push(Event::Break(on));
+            Ok(())
+        }
+
+        fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+            self.events.lock().push(Event::Write(bytes.to_vec()));
+            thread::sleep(Duration::from_millis(1));
+            Ok(())
+        }
+
+        fn drain(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn look(universe: UniverseId, channels: Vec<u16>) -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::nil(),
+            universe,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format: ChannelFormat::U16,
+            channels: channels.into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        }
+    }
+
+    fn wait_for(what: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !what() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn refreshes_break_then_latest_levels_and_reports_failures() {
+        let line = FakeLine::default();
+        let driver = DmxSerialDriver::new(
+            line.clone(),
+            DmxSerialConfig {
+                universe: 2,
+                slots: 4,
+                ..DmxSerialConfig::default()
+            },
+        )
+        .unwrap();
+        driver.output(&look(1, vec![u16::MAX; 4])).unwrap();
+        driver.output(&look(2, vec![u16::MAX, 0x8000])).unwrap();
+        let expected = Event::Write(vec![START_CODE, 255, 128, 0, 0]);
+        wait_for(|| line.events.lock().contains(&expected));
+        let events = line.events.lock().clone();
+        let at = events.iter().position(|event| *event == expected).unwrap();
+        assert_eq!(
+            events[at - 2..at],
+            [Event::Break(true), Event::Break(false)]
+        );
+
+        line.fail.store(true, Ordering::Relaxed);
+        wait_for(|| driver.output(&look(2, Vec::new())).is_err());
+        assert!(matches!(
+            driver.output(&look(2, Vec::new())),
+            Err(OutputError::Port(_))
+        ));
+    }
+}
@@ -0,0 +1,382 @@
+//! Enttec DMX USB Pro widget.
+//!
+//! The widget appears as a USB serial port and speaks framed messages: `0x7E`, a label,
+//! the data length as a little-endian u16, the data, and `0xE7`. Label 6 sends a DMX
+//! packet, which the widget keeps refreshing on its output until the next one; label 5
+//! carries a packet received on its input, and label 8 selects whether it reports every
+//! received packet or only changes.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use super::{dmx_levels, OutputDriver, OutputError};
+use crate::messages::{ChannelFormat, FrameEnvelope, UniverseId, DEFAULT_UNIVERSE};
+use crate::source::{FrameSource, SourceError, SourceFrame, DEFAULT_SOURCE_PRIORITY};
+
+const START_OF_MESSAGE: u8 = 0x7E;
+const END_OF_MESSAGE: u8 = 0xE7;
+const LABEL_RECEIVED_DMX: u8 = 5;
+const LABEL_SEND_DMX: u8 = 6;
+const LABEL_RECEIVE_ON_CHANGE: u8 = 8;
+
+/// Largest data length the widget sends or accepts.
+const MAX_DATA: usize = 600;
+/// Fewest slots the widget accepts in an output packet.
+const MIN_SLOTS: usize = 24;
+const MAX_SLOTS: usize = 512;
+const START_CODE: u8 = 0;
+/// Pause between reads that returned nothing.
+const IDLE_POLL: Duration = Duration::from_millis(10);
+
+/// One widget message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnttecMessage {
+    pub label: u8,
+    pub data: Vec<u8>,
+}
+
+impl EnttecMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.data.len() + 5);
+        bytes.push(START_OF_MESSAGE);
+        bytes.push(self.label);
+        bytes.extend_from_slice(&(self.data.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes.push(END_OF_MESSAGE);
+        bytes
+    }
+}
+
+/// Splits the widget's byte stream into messages, skipping bytes that do not frame one.
+#[derive(Debug, Default)]
+pub struct EnttecDecoder {
+    buffer: Vec<u8>,
+}
+
+impl EnttecDecoder {
+    /// Adds `bytes` and returns the messages they complete.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<EnttecMessage> {
+        self.buffer.extend_from_slice(bytes);
+        let mut messages = Vec::new();
+        loop {
+            let Some(start) = self.buffer.iter().position(|&b| b == START_OF_MESSAGE) else {
+                self.buffer.clear();
+                break;
+            };
+            self.buffer.drain(..start);
+            if self.buffer.len() < 4 {
+                break;
+            }
+            let len = usize::from(u16::from_le_bytes([self.buffer[2], self.buffer[3]]));
+            if len > MAX_DATA {
+                self.buffer.drain(..1);
+                continue;
+            }
+            if self.buffer.len() < len + 5 {
+                break;
+            }
+            if self.buffer[len + 4] != END_OF_MESSAGE {
+                self.buffer.drain(..1);
+                continue;
+            }
+            messages.push(EnttecMessage {
+                label: self.buffer[1],
+                data: self.buffer[4..len + 4].to_vec(),
+            });
+            self.buffer.drain(..len + 5);
+        }
+        messages
+    }
+}
+
+/// Universes a widget outputs and, optionally, reports its input as.
+#[derive(Debug, Clone, Copy)]
+pub struct EnttecProConfig {
+    /// Universe whose looks go out of the widget; looks for other universes are ignored.
+    pub universe: UniverseId,
+    /// Universe to report DMX received on the widget's input as; `None` leaves input off.
+    pub input_universe: Option<UniverseId>,
+}
+
+impl Default for EnttecProConfig {
+    fn default() -> Self {
+        Self {
+            universe: DEFAULT_UNIVERSE,
+            input_universe: None,
+        }
+    }
+}
+
+/// A DMX packet received on the widget's input.
+///
+/// Controllers bridging a desk into ALPINE send it on with
+/// `stream.send_universe(input.universe, ChannelFormat::U8, input.levels, ..)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmxInput {
+    pub universe: UniverseId,
+    pub levels: Vec<u16>,
+    /// The widget dropped data before this packet.
+    pub overrun: bool,
+}
+
+impl DmxInput {
+    /// Reads a received-DMX message; `None` for other labels and non-zero start codes.
+    fn from_message(universe: UniverseId, message: &EnttecMessage) -> Option<Self> {
+        if message.label != LABEL_RECEIVED_DMX {
+            return None;
+        }
+        let (&status, rest) = message.data.split_first()?;
+        let (&start_code, slots) = rest.split_first()?;
+        (start_code == START_CODE).then(|| Self {
+            universe,
+            levels: slots.iter().map(|&level| u16::from(level)).collect(),
+            overrun: status & 0b11 != 0,
+        })
+    }
+}
+
+/// [`OutputDriver`] for an Enttec DMX USB Pro, with the widget's input as a frame source.
+pub struct EnttecPro {
+    universe: UniverseId,
+    writer: Mutex<Box<dyn Write + Send>>,
+    inputs: Option<Mutex<mpsc::Receiver<DmxInput>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl EnttecPro {
+    /// Opens the widget's serial device, such as `/dev/ttyUSB0`.
+    #[cfg(target_os = "linux")]
+    pub fn open(
+        path: impl AsRef<std::path::Path>,
+        config: EnttecProConfig,
+    ) -> Result<Self, OutputError> {
+        let port = || -> io::Result<_> {
+            // The widget is USB; the line rate only matters to the serial driver.
+            let port = super::tty::open_raw(path.as_ref(), 57_600, false)?;
+            let reader = match config.input_universe {
+                Some(_) => Some(port.try_clone()?),
+                None => None,
+            };
+            Ok((port, reader))
+        };
+        let (port, reader) = port().map_err(|e| OutputError::Port(e.to_string()))?;
+        Self::new(port, reader, config)
+    }
+
+    /// Drives a widget through `writer`, reading its input from `reader` when
+    /// [`EnttecProConfig::input_universe`] is set.
+    pub fn new<W, R>(
+        writer: W,
+        reader: Option<R>,
+        config: EnttecProConfig,
+    ) -> Result<Self, OutputError>
+    where
+        W: Write + Send + 'static,
+        R: Read + Send + 'static,
+    {
+        let mut driver = Self {
+            universe: config.universe,
+            writer: Mutex::new(Box::new(writer)),
+            inputs: None,
+            running: Arc::new(AtomicBool::new(true)),
+        };
+        let (Some(universe), Some(reader)) = (config.input_universe, reader) else {
+            return Ok(driver);
+        };
+        driver.send(LABEL_RECEIVE_ON_CHANGE, vec![0])?;
+        let (inputs, received) = mpsc::channel();
+        let running = driver.running.clone();
+        // Detached: the reader wakes at least every read timeout and then sees the flag.
+        thread::Builder::new()
+            .name("alpine-enttec-input".into())
+            .spawn(move || receive(reader, universe, &running, &inputs))
+            .map_err(|e| OutputError::Port(e.to_string()))?;
+        driver.inputs = Some(Mutex::new(received));
+        Ok(driver)
+    }
+
+    /// Waits up to `timeout` for the next packet received on the widget's input.
+    pub fn next_input(&self, timeout: Duration) -> Option<DmxInput> {
+        self.inputs.as_ref()?.lock().recv_timeout(timeout).ok()
+    }
+
+    fn send(&self, label: u8, data: Vec<u8>) -> Result<(), OutputError> {
+        let bytes = EnttecMessage { label, data }.encode();
+        let mut writer = self.writer.lock();
+        writer
+            .write_all(&bytes)
+            .and_then(|()| writer.flush())
+            .map_err(|e| OutputError::Port(e.to_string()))
+    }
+}
+
+impl OutputDriver for EnttecPro {
+    fn output(&self, look: &FrameEnvelope) -> Result<(), OutputError> {
+        if look.universe != self.universe {
+            return Ok(());
+        }
+        let mut data = vec![START_CODE];
+        data.extend(dmx_levels(look).into_iter().take(MAX_SLOTS));
+        data.resize(data.len().max(MIN_SLOTS + 1), 0);
+        self.send(LABEL_SEND_DMX, data)
+    }
+}
+
+impl FrameSource for EnttecPro {
+    fn next_frame(&mut self, timeout: Duration) -> Result<Option<SourceFrame>, SourceError> {
+        let inputs = self.inputs.as_ref().ok_or(SourceError::Closed)?;
+        let received = inputs.lock().recv_timeout(timeout);
+        match received {
+            Ok(input) => Ok(Some(SourceFrame {
+                universe: input.universe,
+                channel_format: ChannelFormat::U8,
+                channels: input.levels,
+                priority: DEFAULT_SOURCE_PRIORITY,
+            })),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(SourceError::Closed),
+        }
+    }
+}
+
+impl Drop for EnttecPro {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Forwards packets received on the widget until it is dropped or the port fails.
+fn receive<R: Read>(
+    mut reader: R,
+    universe: UniverseId,
+    running: &AtomicBool,
+    inputs: &mpsc::Sender<DmxInput>,
+) {
+    let mut decoder = EnttecDecoder::default();
+    let mut buffer = [0; 1024];
+    while running.load(Ordering::Relaxed) {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => {
+                thread::sleep(IDLE_POLL);
+                continue;
+            }
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return,
+        };
+        for message in decoder.push(&buffer[..read]) {
+            if let Some(input) = DmxInput::from_message(universe, &message) {
+                if inputs.send(input).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MessageType;
+    use uuid::Uuid;
+
+    #[derive(Clone, Default)]
+    struct Wire(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Wire {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn decoder_resyncs_past_noise_and_split_messages() {
+        let message = EnttecMessage {
+            label: LABEL_RECEIVED_DMX,
+            data: vec![0, 0, 9],
+        };
+        let bytes = message.encode();
+        let mut decoder = EnttecDecoder::default();
+        let mut noisy = vec![0x12, START_OF_MESSAGE, 3, 0xFF, 0xFF];
+        noisy.extend_from_slice(&bytes[..4]);
+        assert!(decoder.push(&noisy).is_empty());
+        assert_eq!(decoder.push(&bytes[4..]), vec![message]);
+    }
+
+    #[test]
+    fn sends_looks_and_reports_input() {
+        let wire = Wire::default();
+        let received = [
+            EnttecMessage {
+                label: LABEL_RECEIVED_DMX,
+                data: vec![0, 0, 10, 20],
+            }
+            .encode(),
+            // Non-zero start codes (RDM, text) are not levels.
+            EnttecMessage {
+                label: LABEL_RECEIVED_DMX,
+                data: vec![0, 0xCC, 1],
+            }
+            .encode(),
+        ]
+        .concat();
+        let mut widget = EnttecPro::new(
+            wire.clone(),
+            Some(io::Cursor::new(received)),
+            EnttecProConfig {
+                universe: 3,
+                input_universe: Some(7),
+            },
+        )
+        .unwrap();
+        let input = widget.next_input(Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            input,
+            DmxInput {
+                universe: 7,
+                levels: vec![10, 20],
+                overrun: false
+            }
+        );
+        assert_eq!(widget.next_frame(Duration::from_millis(50)).unwrap(), None);
+
+        let look = |universe| FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::nil(),
+            universe,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format: ChannelFormat::U16,
+            channels: vec![u16::MAX, 0x8000].into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        };
+        widget.output(&look(1)).unwrap();
+        widget.output(&look(3)).unwrap();
+        let messages = EnttecDecoder::default().push(&wire.0.lock());
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].label, LABEL_RECEIVE_ON_CHANGE);
+        assert_eq!(messages[1].label, LABEL_SEND_DMX);
+        assert_eq!(messages[1].data.len(), MIN_SLOTS + 1);
+        assert_eq!(messages[1].data[..4], [START_CODE, 255, 128, 0]);
+    }
+}
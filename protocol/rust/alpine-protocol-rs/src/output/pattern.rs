@@ -0,0 +1,289 @@
+//! Test patterns a node renders on its own outputs.
+//!
+//! During commissioning an installer wants to see that each port reaches the right
+//! fixtures before a console is on site. `test_pattern` has the node drive one universe
+//! of its outputs with a [`TestPattern`] for a while, in place of streamed looks; other
+//! universes keep following the stream. A new request replaces the running pattern of
+//! its universe, one without a pattern stops it, and every pattern stops by itself after
+//! its duration. A stopped pattern leaves its channels at zero until the next streamed
+//! look.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::OutputDriver;
+use crate::control::ControlHandlers;
+use crate::messages::{
+    ChannelBuffer, ChannelFormat, ControlOp, FrameEnvelope, MessageType, UniverseId,
+};
+
+/// How long a pattern runs when the request names no duration.
+pub const DEFAULT_PATTERN_DURATION: Duration = Duration::from_secs(60);
+
+/// Longest a pattern may run, so a forgotten test cannot hold a rig.
+pub const MAX_PATTERN_DURATION: Duration = Duration::from_secs(3_600);
+
+/// Channels a pattern covers when the request names no count.
+pub const DEFAULT_PATTERN_CHANNELS: u16 = 512;
+
+/// What a pattern puts on a universe; levels are 8-bit.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TestPattern {
+    /// Every channel at full.
+    FullOn,
+    /// One channel at full at a time, moving on every `step_ms`.
+    Chase { step_ms: u32 },
+    /// Each channel in turn fades from zero to full over `period_ms`, the others at zero.
+    Ramp { period_ms: u32 },
+    /// Only `channel`, counted from zero, at `level`.
+    Poke { channel: u16, level: u8 },
+}
+
+/// Payload of `test_pattern`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PatternRequest {
+    pub universe: UniverseId,
+    /// `None` stops the universe's pattern.
+    #[serde(default)]
+    pub pattern: Option<TestPattern>,
+    /// Channels to cover; [`DEFAULT_PATTERN_CHANNELS`] when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u16>,
+    /// [`DEFAULT_PATTERN_DURATION`] when absent, at most [`MAX_PATTERN_DURATION`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Running {
+    pattern: TestPattern,
+    channels: u16,
+    started: Instant,
+    until: Instant,
+}
+
+impl Running {
+    fn levels(&self, now: Instant) -> ChannelBuffer {
+        let count = usize::from(self.channels);
+        let mut levels = ChannelBuffer::from_elem(0, count);
+        if count == 0 {
+            return levels;
+        }
+        let elapsed = now.saturating_duration_since(self.started).as_millis();
+        match self.pattern {
+            TestPattern::FullOn => levels.fill(255),
+            TestPattern::Chase { step_ms } => {
+                let step = elapsed / u128::from(step_ms.max(1));
+                levels[(step % count as u128) as usize] = 255;
+            }
+            TestPattern::Ramp { period_ms } => {
+                let period = u128::from(period_ms.max(1));
+                let channel = (elapsed / period % count as u128) as usize;
+                levels[channel] = (elapsed % period * 255 / period) as u16;
+            }
+            TestPattern::Poke { channel, level } => {
+                if let Some(slot) = levels.get_mut(usize::from(channel)) {
+                    *slot = u16::from(level);
+                }
+            }
+        }
+        levels
+    }
+}
+
+#[derive(Debug, Default)]
+struct Patterns {
+    running: BTreeMap<UniverseId, Running>,
+    /// Universes whose pattern stopped and the channels it covered, to be cleared.
+    stopped: Vec<(UniverseId, u16)>,
+}
+
+impl Patterns {
+    fn stop(&mut self, universe: UniverseId) {
+        if let Some(running) = self.running.remove(&universe) {
+            self.stopped.push((universe, running.channels));
+        }
+    }
+}
+
+/// Running test patterns, one per universe at most.
+#[derive(Debug, Default)]
+pub struct PatternGenerator {
+    patterns: Mutex<Patterns>,
+}
+
+impl PatternGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts, replaces, or stops the pattern of `request.universe`.
+    pub fn apply(&self, request: &PatternRequest, now: Instant) {
+        let mut patterns = self.patterns.lock();
+        let Some(pattern) = request.pattern else {
+            patterns.stop(request.universe);
+            return;
+        };
+        let duration = request
+            .duration_ms
+            .map_or(DEFAULT_PATTERN_DURATION, Duration::from_millis)
+            .min(MAX_PATTERN_DURATION);
+        patterns.running.insert(
+            request.universe,
+            Running {
+                pattern,
+                channels: request.channels.unwrap_or(DEFAULT_PATTERN_CHANNELS),
+                started: now,
+                until: now + duration,
+            },
+        );
+    }
+
+    /// Stops every pattern.
+    pub fn stop_all(&self) {
+        let mut patterns = self.patterns.lock();
+        let universes: Vec<_> = patterns.running.keys().copied().collect();
+        for universe in universes {
+            patterns.stop(universe);
+        }
+    }
+
+    /// Whether a pattern holds `universe`, so streamed looks must not reach the outputs.
+    pub fn is_active(&self, universe: UniverseId, now: Instant) -> bool {
+        self.patterns
+            .lock()
+            .running
+            .get(&universe)
+            .is_some_and(|running| now < running.until)
+    }
+
+    /// Looks of every running pattern at `now`, plus a blank look once for each pattern
+    /// that stopped; patterns past their duration stop.
+    pub fn render(&self, now: Instant) -> Vec<FrameEnvelope> {
+        let mut patterns = self.patterns.lock();
+        let expired: Vec<_> = patterns
+            .running
+            .iter()
+            .filter(|(_, running)| now >= running.until)
+            .map(|(&universe, _)| universe)
+            .collect();
+        for universe in expired {
+            patterns.stop(universe);
+        }
+        let blanks =
+            std::mem::take(&mut patterns.stopped)
+                .into_iter()
+                .map(|(universe, channels)| {
+                    look(universe, ChannelBuffer::from_elem(0, usize::from(channels)))
+                });
+        patterns
+            .running
+            .iter()
+            .map(|(&universe, running)| look(universe, running.levels(now)))
+            .chain(blanks)
+            .collect()
+    }
+
+    /// Renders running patterns to `outputs` every `period` until the task is dropped.
+    pub async fn run(self: Arc<Self>, outputs: Vec<Arc<dyn OutputDriver>>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            for look in self.render(Instant::now()) {
+                for output in &outputs {
+                    // A failed port must not stop the pattern on the others.
+                    let _ = output.output(&look);
+                }
+            }
+        }
+    }
+
+    /// Registers the handler for `test_pattern`.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let generator = self.clone();
+        handlers.on(ControlOp::TestPattern, move |request: PatternRequest| {
+            generator.apply(&request, Instant::now());
+            Ok(None)
+        });
+    }
+}
+
+fn look(universe: UniverseId, channels: ChannelBuffer) -> FrameEnvelope {
+    FrameEnvelope {
+        message_type: MessageType::AlpineFrame,
+        session_id: Uuid::nil(),
+        universe,
+        timestamp_us: 0,
+        priority: 0,
+        channel_format: ChannelFormat::U8,
+        channels,
+        packed_channels: None,
+        float_channels: None,
+        compression: None,
+        compressed_channels: None,
+        groups: None,
+        group_refs: None,
+        config_tag: None,
+        deadline_us: None,
+        metadata: None,
+        extensions: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(pattern: Option<TestPattern>) -> PatternRequest {
+        PatternRequest {
+            universe: 2,
+            pattern,
+            channels: Some(4),
+            duration_ms: Some(1_000),
+        }
+    }
+
+    #[test]
+    fn patterns_render_and_expire() {
+        let generator = PatternGenerator::new();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        generator.apply(&request(Some(TestPattern::Chase { step_ms: 100 })), start);
+        assert_eq!(generator.render(at(250))[0].channels[..], [0, 0, 255, 0]);
+        assert_eq!(generator.render(at(450))[0].channels[..], [255, 0, 0, 0]);
+
+        generator.apply(&request(Some(TestPattern::Ramp { period_ms: 200 })), start);
+        assert_eq!(generator.render(at(300))[0].channels[..], [0, 127, 0, 0]);
+
+        generator.apply(
+            &request(Some(TestPattern::Poke {
+                channel: 3,
+                level: 40,
+            })),
+            start,
+        );
+        let looks = generator.render(at(10));
+        assert_eq!(
+            (looks[0].universe, &looks[0].channels[..]),
+            (2, &[0, 0, 0, 40][..])
+        );
+        assert!(generator.is_active(2, at(999)));
+        assert!(!generator.is_active(1, at(10)));
+        // An ended pattern blanks its channels once, then the stream has the universe.
+        assert_eq!(generator.render(at(1_000))[0].channels[..], [0; 4]);
+        assert!(generator.render(at(1_010)).is_empty());
+        assert!(!generator.is_active(2, at(10)));
+
+        generator.apply(&request(Some(TestPattern::FullOn)), start);
+        assert_eq!(generator.render(at(10))[0].channels[..], [255; 4]);
+        generator.apply(&request(None), start);
+        assert_eq!(generator.render(at(20))[0].channels[..], [0; 4]);
+        assert!(generator.render(at(30)).is_empty());
+    }
+}
@@ -0,0 +1,42 @@
+//! Raw serial devices for the output drivers.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Opens `path` as a raw 8-bit line at `baud` with two stop bits when `two_stop_bits`.
+///
+/// Reads return after 100 ms without data, so reader threads can notice shutdown.
+pub(crate) fn open_raw(path: &Path, baud: u32, two_stop_bits: bool) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)?;
+    let fd = file.as_raw_fd();
+    // Rates such as 250 kbaud are not standard termios speeds, so set them through termios2.
+    // SAFETY: `fd` stays open for the lifetime of `file`, and `tio` is a valid termios2.
+    unsafe {
+        let mut tio: libc::termios2 = std::mem::zeroed();
+        if libc::ioctl(fd, libc::TCGETS2, &mut tio) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        tio.c_iflag = 0;
+        tio.c_oflag = 0;
+        tio.c_lflag = 0;
+        tio.c_cflag = libc::BOTHER | libc::CS8 | libc::CLOCAL | libc::CREAD;
+        if two_stop_bits {
+            tio.c_cflag |= libc::CSTOPB;
+        }
+        tio.c_ispeed = baud;
+        tio.c_ospeed = baud;
+        tio.c_cc[libc::VMIN] = 0;
+        tio.c_cc[libc::VTIME] = 1;
+        if libc::ioctl(fd, libc::TCSETS2, &tio) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(file)
+}
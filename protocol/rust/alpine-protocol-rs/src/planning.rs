@@ -0,0 +1,425 @@
+//! Bandwidth planning before deployment.
+//!
+//! A [`Patch`] lists the universes a show will stream, which node each goes to, the
+//! network link it crosses, and its format, channel count, and profile. [`Patch::plan`]
+//! encodes a worst-case frame for each universe exactly as the stream would, multiplies
+//! by its frame rate, and sums the result per node and per link. Universes that ask for
+//! more than a node advertised, nodes over their frame quota, and links over their
+//! capacity are flagged, so a patch can be fixed on paper rather than at load-in.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::codec;
+use crate::messages::metadata::METADATA_VERSION;
+use crate::messages::{
+    AdaptationInfo, CapabilitySet, ChannelBuffer, ChannelFormat, FrameEnvelope, MessageType,
+    UniverseId,
+};
+use crate::profile::{StreamIntent, StreamProfile, WireBehavior};
+use crate::stream::FRAGMENT_OVERHEAD;
+
+/// Bytes IPv4 and UDP headers add to every datagram.
+pub const DATAGRAM_OVERHEAD: usize = 28;
+
+/// One universe of the patch.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlannedUniverse {
+    pub universe: UniverseId,
+    /// Device id of the node the universe is streamed to.
+    pub node: String,
+    /// Network link the frames cross, e.g. a switch uplink; the node's own link if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    pub format: ChannelFormat,
+    pub channels: u32,
+    pub intent: StreamIntent,
+    /// Frames per second; the profile's rate if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<u32>,
+}
+
+/// Limits a node was configured or negotiated with; unknown limits are not checked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PlannedNode {
+    /// Capabilities the node advertises, e.g. from a saved inventory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<CapabilitySet>,
+    /// Frames per second the node accepts from one session across all universes; see
+    /// [`crate::session::quota::SessionQuotas::with_frame_rate`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_quota: Option<u32>,
+}
+
+/// A show's universes and the limits they must fit.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Patch {
+    pub universes: Vec<PlannedUniverse>,
+    #[serde(default)]
+    pub nodes: BTreeMap<String, PlannedNode>,
+    /// Capacity of each link in bytes per second.
+    #[serde(default)]
+    pub links: BTreeMap<String, u64>,
+    /// Datagram limit frames are fragmented to, if any; see
+    /// [`crate::stream::AlnpStream::with_max_datagram`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_datagram: Option<usize>,
+}
+
+/// Expected output for one universe.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UniverseLoad {
+    pub universe: UniverseId,
+    pub node: String,
+    pub link: String,
+    /// Format the frames go out in after negotiation with the node.
+    pub wire_format: ChannelFormat,
+    pub frame_rate: u32,
+    /// Bytes on the wire per frame, fragments and IP and UDP headers included.
+    pub frame_bytes: usize,
+    /// Frame and parity bytes per second.
+    pub bytes_per_second: u64,
+}
+
+/// A limit the patch exceeds.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlanIssue {
+    /// The universe asks for a faster frame rate than the node applies.
+    FrameRate {
+        node: String,
+        universe: UniverseId,
+        frame_rate: u32,
+        limit: u32,
+    },
+    /// Channels past the node's `max_channels` would be dropped.
+    Channels {
+        node: String,
+        universe: UniverseId,
+        channels: u32,
+        max_channels: u32,
+    },
+    /// The node lists no formats, so only u8 and u16 are known to work.
+    Format {
+        node: String,
+        universe: UniverseId,
+        format: ChannelFormat,
+    },
+    /// The node's universes together exceed its per-session frame quota.
+    FrameQuota {
+        node: String,
+        frames_per_second: u32,
+        quota: u32,
+    },
+    /// The link's universes together exceed its capacity.
+    LinkCapacity {
+        link: String,
+        bytes_per_second: u64,
+        capacity: u64,
+    },
+}
+
+/// Outcome of [`Patch::plan`].
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct PlanReport {
+    /// Per-universe loads in patch order.
+    pub universes: Vec<UniverseLoad>,
+    /// Bytes per second to each node.
+    pub nodes: BTreeMap<String, u64>,
+    /// Bytes per second across each link.
+    pub links: BTreeMap<String, u64>,
+    pub issues: Vec<PlanIssue>,
+}
+
+impl PlanReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Patch {
+    /// Computes the expected bandwidth of every universe, node, and link, and the
+    /// limits they exceed.
+    ///
+    /// Frames are sized for the worst case: every level at full scale, uncompressed, and
+    /// sent every frame with no static-universe thinning.
+    pub fn plan(&self) -> PlanReport {
+        let mut report = PlanReport::default();
+        let mut node_frames: BTreeMap<&str, u32> = BTreeMap::new();
+        for planned in &self.universes {
+            let node = self.nodes.get(&planned.node);
+            let capabilities = node.and_then(|node| node.capabilities.as_ref());
+            let wire = wire_behavior(planned.intent);
+            let frame_rate = planned
+                .frame_rate
+                .unwrap_or_else(|| per_second(wire.frame_interval));
+            let mut wire_format = planned.format;
+            if let Some(capabilities) = capabilities {
+                self.check_universe(planned, capabilities, frame_rate, &mut report.issues);
+                wire_format = capabilities
+                    .closest_format(planned.format)
+                    .unwrap_or(planned.format);
+            }
+            let channels = capabilities.map_or(planned.channels, |capabilities| {
+                planned.channels.min(capabilities.max_channels)
+            });
+            let frame_bytes = self.datagram_bytes(frame_bytes(wire_format, channels));
+            let frames = f64::from(frame_rate);
+            let parity = wire
+                .fec_group
+                .map_or(0.0, |group| frames / group.max(1) as f64);
+            let bytes_per_second = ((frames + parity) * frame_bytes as f64).ceil() as u64;
+            let link = planned.link.clone().unwrap_or_else(|| planned.node.clone());
+            *report.nodes.entry(planned.node.clone()).or_default() += bytes_per_second;
+            *report.links.entry(link.clone()).or_default() += bytes_per_second;
+            *node_frames.entry(&planned.node).or_default() += frame_rate;
+            report.universes.push(UniverseLoad {
+                universe: planned.universe,
+                node: planned.node.clone(),
+                link,
+                wire_format,
+                frame_rate,
+                frame_bytes,
+                bytes_per_second,
+            });
+        }
+        for (node, frames_per_second) in node_frames {
+            let quota = self.nodes.get(node).and_then(|node| node.frame_quota);
+            if let Some(quota) = quota.filter(|&quota| frames_per_second > quota) {
+                report.issues.push(PlanIssue::FrameQuota {
+                    node: node.to_string(),
+                    frames_per_second,
+                    quota,
+                });
+            }
+        }
+        for (link, &bytes_per_second) in &report.links {
+            let capacity = self.links.get(link).copied();
+            if let Some(capacity) = capacity.filter(|&capacity| bytes_per_second > capacity) {
+                report.issues.push(PlanIssue::LinkCapacity {
+                    link: link.clone(),
+                    bytes_per_second,
+                    capacity,
+                });
+            }
+        }
+        report
+    }
+
+    fn check_universe(
+        &self,
+        planned: &PlannedUniverse,
+        capabilities: &CapabilitySet,
+        frame_rate: u32,
+        issues: &mut Vec<PlanIssue>,
+    ) {
+        let node = || planned.node.clone();
+        if let Some(limit) = capabilities.min_frame_interval().map(per_second) {
+            if frame_rate > limit {
+                issues.push(PlanIssue::FrameRate {
+                    node: node(),
+                    universe: planned.universe,
+                    frame_rate,
+                    limit,
+                });
+            }
+        }
+        if planned.channels > capabilities.max_channels {
+            issues.push(PlanIssue::Channels {
+                node: node(),
+                universe: planned.universe,
+                channels: planned.channels,
+                max_channels: capabilities.max_channels,
+            });
+        }
+        if capabilities.closest_format(planned.format).is_none()
+            && !capabilities.supports_format(planned.format)
+        {
+            issues.push(PlanIssue::Format {
+                node: node(),
+                universe: planned.universe,
+                format: planned.format,
+            });
+        }
+    }
+
+    /// Bytes an encoded frame takes on the wire, split into fragments past the datagram
+    /// limit.
+    fn datagram_bytes(&self, encoded: usize) -> usize {
+        match self.max_datagram {
+            Some(max) if encoded > max && max > FRAGMENT_OVERHEAD => {
+                let fragments = encoded.div_ceil(max - FRAGMENT_OVERHEAD);
+                encoded + fragments * (FRAGMENT_OVERHEAD + DATAGRAM_OVERHEAD)
+            }
+            _ => encoded + DATAGRAM_OVERHEAD,
+        }
+    }
+}
+
+/// Transport settings of the preset profile for `intent`.
+fn wire_behavior(intent: StreamIntent) -> WireBehavior {
+    let profile = match intent {
+        StreamIntent::Auto => StreamProfile::auto(),
+        StreamIntent::Realtime => StreamProfile::realtime(),
+        StreamIntent::Install => StreamProfile::install(),
+    };
+    profile
+        .compile()
+        .expect("preset profiles have valid weights")
+        .wire_behavior()
+}
+
+/// Whole frames per second at one frame per `interval`.
+fn per_second(interval: Duration) -> u32 {
+    u32::try_from(Duration::from_secs(1).as_micros() / interval.as_micros().max(1))
+        .unwrap_or(u32::MAX)
+}
+
+/// Encoded size of a frame of `channels` full-scale levels in `format`, with the
+/// annotations the stream adds to every frame.
+fn frame_bytes(format: ChannelFormat, channels: u32) -> usize {
+    let count = channels as usize;
+    let (levels, float_channels) = match format {
+        // A third is the longest float to encode: it does not fit a half float.
+        ChannelFormat::F32 => (ChannelBuffer::new(), Some(vec![1.0 / 3.0; count])),
+        ChannelFormat::U16 => (ChannelBuffer::from_elem(u16::MAX, count), None),
+        _ => (ChannelBuffer::from_elem(u16::from(u8::MAX), count), None),
+    };
+    let mut envelope = FrameEnvelope {
+        message_type: MessageType::AlpineFrame,
+        session_id: Uuid::nil(),
+        universe: 0,
+        timestamp_us: u64::MAX,
+        priority: u8::MAX,
+        channel_format: format,
+        channels: levels,
+        packed_channels: None,
+        float_channels,
+        compression: None,
+        compressed_channels: None,
+        groups: None,
+        group_refs: None,
+        config_tag: Some(u32::MAX),
+        deadline_us: Some(u64::MAX),
+        metadata: None,
+        extensions: None,
+    };
+    envelope.set_adaptation_info(Some(AdaptationInfo {
+        version: METADATA_VERSION,
+        keyframe_interval: u8::MAX,
+        delta_depth: u8::MAX,
+        deadline_offset_ms: i16::MIN,
+        degraded_safe: false,
+        frames_since_keyframe: u8::MAX,
+        force_keyframe: false,
+        event: "keyframe_interval_increased".to_string(),
+    }));
+    envelope.pack();
+    codec::to_vec(&envelope).map_or(0, |bytes| bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universe(universe: UniverseId, node: &str, intent: StreamIntent) -> PlannedUniverse {
+        PlannedUniverse {
+            universe,
+            node: node.to_string(),
+            link: Some("uplink".to_string()),
+            format: ChannelFormat::U8,
+            channels: 512,
+            intent,
+            frame_rate: None,
+        }
+    }
+
+    #[test]
+    fn plan_sums_links_and_flags_exceeded_limits() {
+        let capabilities = CapabilitySet {
+            max_frame_rate: Some(30),
+            ..CapabilitySet::default()
+        };
+        let mut patch = Patch {
+            universes: vec![
+                universe(1, "left", StreamIntent::Realtime),
+                universe(2, "left", StreamIntent::Install),
+                PlannedUniverse {
+                    format: ChannelFormat::F32,
+                    channels: 600,
+                    ..universe(3, "right", StreamIntent::Install)
+                },
+            ],
+            ..Patch::default()
+        };
+        patch.nodes.insert(
+            "left".into(),
+            PlannedNode {
+                capabilities: Some(capabilities.clone()),
+                frame_quota: Some(60),
+            },
+        );
+        patch.nodes.insert(
+            "right".into(),
+            PlannedNode {
+                capabilities: Some(capabilities),
+                frame_quota: None,
+            },
+        );
+
+        let report = patch.plan();
+        let left: u64 = report.universes[..2]
+            .iter()
+            .map(|u| u.bytes_per_second)
+            .sum();
+        assert_eq!(report.nodes["left"], left);
+        assert_eq!(
+            report.links["uplink"],
+            left + report.universes[2].bytes_per_second
+        );
+        // Install sends parity for every fourth frame.
+        let install = &report.universes[1];
+        assert_eq!(install.frame_rate, 30);
+        assert_eq!(
+            install.bytes_per_second,
+            (37.5 * install.frame_bytes as f64).ceil() as u64
+        );
+        assert!(report.universes[0].frame_bytes > 512);
+        // Floats go out as the node's 8-bit levels.
+        assert_eq!(report.universes[2].wire_format, ChannelFormat::U8);
+        assert_eq!(
+            report.issues,
+            vec![
+                PlanIssue::FrameRate {
+                    node: "left".into(),
+                    universe: 1,
+                    frame_rate: 44,
+                    limit: 30,
+                },
+                PlanIssue::Channels {
+                    node: "right".into(),
+                    universe: 3,
+                    channels: 600,
+                    max_channels: 512,
+                },
+                PlanIssue::FrameQuota {
+                    node: "left".into(),
+                    frames_per_second: 74,
+                    quota: 60,
+                },
+            ]
+        );
+
+        patch.links.insert("uplink".into(), 10_000);
+        assert!(matches!(
+            patch.plan().issues.last(),
+            Some(PlanIssue::LinkCapacity {
+                capacity: 10_000,
+                ..
+            })
+        ));
+    }
+}
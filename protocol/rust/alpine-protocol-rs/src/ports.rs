@@ -0,0 +1,230 @@
+//! Physical output ports of multi-port nodes.
+//!
+//! Many nodes drive two to eight DMX lines. A node advertises how many in
+//! [`CapabilitySet::ports`](crate::messages::CapabilitySet::ports); ports are numbered
+//! from 1, as printed on the enclosure. Each port carries one universe: by default port
+//! `n` carries universe `n - 1`, and `set_port_map` moves a universe to another port,
+//! persisted in the device config. [`PortMap`] also tracks each port's output health for
+//! status queries.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::{ConfigError, SharedConfig};
+use crate::control::ControlHandlers;
+use crate::messages::{ControlOp, PortId, UniverseId};
+
+/// Payload of `set_port_map`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PortMapping {
+    pub universe: UniverseId,
+    /// `None` returns the universe to its default port.
+    #[serde(default)]
+    pub port: Option<PortId>,
+}
+
+/// State of one port, as reported in status queries.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PortStatus {
+    pub port: PortId,
+    /// Universe the port carries, if any.
+    pub universe: Option<UniverseId>,
+    /// Whether the last look written to the port succeeded; `true` before any output.
+    pub healthy: bool,
+    pub outputs: u64,
+    pub failures: u64,
+    pub last_error: Option<String>,
+    /// Time since the last look was written, in milliseconds.
+    pub idle_ms: Option<u64>,
+}
+
+/// Failures changing the port map.
+#[derive(Debug, Error)]
+pub enum PortError {
+    #[error("port {port} does not exist; the node has {count}")]
+    UnknownPort { port: PortId, count: PortId },
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+#[derive(Debug, Default)]
+struct PortHealth {
+    outputs: u64,
+    failures: u64,
+    last_output: Option<Instant>,
+    last_error: Option<String>,
+}
+
+/// Universe-to-port assignment and per-port health of a node.
+#[derive(Debug)]
+pub struct PortMap {
+    count: PortId,
+    config: SharedConfig,
+    health: Mutex<BTreeMap<PortId, PortHealth>>,
+}
+
+impl PortMap {
+    /// Ports `1..=count`, with assignments kept in `config`.
+    pub fn new(count: PortId, config: SharedConfig) -> Self {
+        Self {
+            count,
+            config,
+            health: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn count(&self) -> PortId {
+        self.count
+    }
+
+    pub fn contains(&self, port: PortId) -> bool {
+        (1..=self.count).contains(&port)
+    }
+
+    /// Moves `universe` to `port`, or back to its default port when `None`. A universe
+    /// another universe's default port was carrying takes that port over.
+    pub fn set_port(&self, universe: UniverseId, port: Option<PortId>) -> Result<(), PortError> {
+        if let Some(port) = port.filter(|&port| !self.contains(port)) {
+            return Err(PortError::UnknownPort {
+                port,
+                count: self.count,
+            });
+        }
+        self.config.update(|config| {
+            match port {
+                Some(port) => {
+                    // One universe per port: the previous occupant loses its assignment.
+                    config.ports.retain(|_, assigned| *assigned != port);
+                    config.ports.insert(universe, port)
+                }
+                None => config.ports.remove(&universe),
+            };
+        })?;
+        Ok(())
+    }
+
+    /// Port carrying `universe`, if any.
+    pub fn port_of(&self, universe: UniverseId) -> Option<PortId> {
+        self.config.read(|config| {
+            if let Some(&port) = config.ports.get(&universe) {
+                return Some(port);
+            }
+            let default = PortId::try_from(universe.checked_add(1)?).ok()?;
+            let taken = config.ports.values().any(|&port| port == default);
+            (self.contains(default) && !taken).then_some(default)
+        })
+    }
+
+    /// Universe `port` carries, if any.
+    pub fn universe_of(&self, port: PortId) -> Option<UniverseId> {
+        if !self.contains(port) {
+            return None;
+        }
+        self.config.read(|config| {
+            if let Some((&universe, _)) = config.ports.iter().find(|(_, &p)| p == port) {
+                return Some(universe);
+            }
+            let default = UniverseId::from(port - 1);
+            (!config.ports.contains_key(&default)).then_some(default)
+        })
+    }
+
+    /// Records the outcome of writing a look to `port`.
+    pub fn record_output<E: ToString>(&self, port: PortId, result: Result<(), E>, now: Instant) {
+        let mut health = self.health.lock();
+        let health = health.entry(port).or_default();
+        health.outputs += 1;
+        health.last_output = Some(now);
+        match result {
+            Ok(()) => health.last_error = None,
+            Err(err) => {
+                health.failures += 1;
+                health.last_error = Some(err.to_string());
+            }
+        }
+    }
+
+    pub fn status(&self, now: Instant) -> Vec<PortStatus> {
+        let health = self.health.lock();
+        (1..=self.count)
+            .map(|port| {
+                let state = health.get(&port);
+                PortStatus {
+                    port,
+                    universe: self.universe_of(port),
+                    healthy: state.is_none_or(|state| state.last_error.is_none()),
+                    outputs: state.map_or(0, |state| state.outputs),
+                    failures: state.map_or(0, |state| state.failures),
+                    last_error: state.and_then(|state| state.last_error.clone()),
+                    idle_ms: state
+                        .and_then(|state| state.last_output)
+                        .map(|at| now.saturating_duration_since(at).as_millis() as u64),
+                }
+            })
+            .collect()
+    }
+
+    /// Registers the handler for `set_port_map`.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let ports = self.clone();
+        handlers.on(ControlOp::SetPortMap, move |mapping: PortMapping| {
+            ports
+                .set_port(mapping.universe, mapping.port)
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FileConfigStore;
+    use uuid::Uuid;
+
+    #[test]
+    fn universes_move_between_ports_and_persist() {
+        let path = std::env::temp_dir()
+            .join(format!("alpine-config-{}", Uuid::new_v4()))
+            .join("device.cfg");
+        let open = || SharedConfig::open(Arc::new(FileConfigStore::new(&path))).unwrap();
+        let ports = PortMap::new(4, open());
+        assert_eq!(ports.port_of(0), Some(1));
+        assert_eq!(ports.port_of(3), Some(4));
+        assert_eq!(ports.port_of(4), None);
+
+        ports.set_port(10, Some(2)).unwrap();
+        assert_eq!(ports.port_of(10), Some(2));
+        assert_eq!(ports.port_of(1), None);
+        assert_eq!(ports.universe_of(2), Some(10));
+        assert!(matches!(
+            ports.set_port(0, Some(5)),
+            Err(PortError::UnknownPort { port: 5, count: 4 })
+        ));
+
+        let reopened = PortMap::new(4, open());
+        assert_eq!(reopened.port_of(10), Some(2));
+        // Moving another universe onto the port unassigns the previous one.
+        reopened.set_port(20, Some(2)).unwrap();
+        assert_eq!(reopened.port_of(10), None);
+        reopened.set_port(20, None).unwrap();
+        assert_eq!(reopened.universe_of(2), Some(1));
+
+        let now = Instant::now();
+        reopened.record_output(3, Err("uart gone"), now);
+        let status = reopened.status(now);
+        assert_eq!(status.len(), 4);
+        assert!(status[0].healthy);
+        assert_eq!(status[0].idle_ms, None);
+        assert!(!status[2].healthy);
+        assert_eq!(status[2].last_error.as_deref(), Some("uart gone"));
+        reopened.record_output::<&str>(3, Ok(()), now);
+        assert!(reopened.status(now)[2].healthy);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
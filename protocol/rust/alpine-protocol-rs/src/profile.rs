@@ -1,10 +1,17 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+use crate::messages::CapabilitySet;
+use crate::session::JitterStrategy;
+
 /// Declares intent for streaming behavior.
 ///
 /// The value is emitted into the config ID calculation so runtime decisions stay deterministic.
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StreamIntent {
     /// Safe default balancing latency and resilience.
     Auto,
@@ -23,6 +30,12 @@ pub enum ProfileError {
     ResilienceWeightOutOfRange,
     #[error("latency and resilience weights cannot both be zero")]
     ZeroTotalWeight,
+    #[error("profile compiles to config_id {actual}, not the announced {announced}")]
+    ConfigIdMismatch { announced: String, actual: String },
+    #[error("profile sends every {interval:?}, faster than the node's minimum frame interval of {min:?}")]
+    RateExceeded { interval: Duration, min: Duration },
+    #[error("node does not support the profile's {0:?} jitter strategy")]
+    UnsupportedJitter(JitterStrategy),
 }
 
 /// High-level description of stream behavior selected by callers.
@@ -89,8 +102,8 @@ impl StreamProfile {
         }
 
         let mut hasher = Sha256::new();
-        hasher.update(&[self.latency_weight, self.resilience_weight]);
-        hasher.update(&[self.intent as u8]);
+        hasher.update([self.latency_weight, self.resilience_weight]);
+        hasher.update([self.intent as u8]);
         let digest = hasher.finalize();
         let config_id = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
 
@@ -125,6 +138,11 @@ impl CompiledStreamProfile {
         &self.config_id
     }
 
+    /// Leading 32 bits of the `config_id`, carried in every frame as `config_tag`.
+    pub fn config_tag(&self) -> u32 {
+        u32::from_str_radix(&self.config_id[..8], 16).unwrap_or_default()
+    }
+
     /// Latency weight applied by the runtime.
     pub fn latency_weight(&self) -> u8 {
         self.latency_weight
@@ -139,6 +157,131 @@ impl CompiledStreamProfile {
     pub fn intent(&self) -> StreamIntent {
         self.intent
     }
+
+    /// Refuses the profile when it would send faster than `capabilities` allow or smooth
+    /// frames with a jitter strategy the device lacks.
+    pub fn check_capabilities(&self, capabilities: &CapabilitySet) -> Result<(), ProfileError> {
+        let wire = self.wire_behavior();
+        if let Some(min) = capabilities.min_frame_interval() {
+            if wire.frame_interval < min {
+                return Err(ProfileError::RateExceeded {
+                    interval: wire.frame_interval,
+                    min,
+                });
+            }
+        }
+        if !capabilities.supports_jitter(wire.jitter_strategy) {
+            return Err(ProfileError::UnsupportedJitter(wire.jitter_strategy));
+        }
+        Ok(())
+    }
+
+    /// Transport behavior both ends derive from the profile.
+    ///
+    /// `Install` trades latency for smoothness: parity frames, a deep receive buffer, and
+    /// unchanged universes refreshed only once a second. `Realtime` never interpolates
+    /// and applies frames as they arrive. Interpolation otherwise follows the weights.
+    pub fn wire_behavior(&self) -> WireBehavior {
+        let interpolate = self.resilience_weight > self.latency_weight;
+        let jitter_strategy = if interpolate && self.intent != StreamIntent::Realtime {
+            JitterStrategy::Lerp
+        } else {
+            JitterStrategy::HoldLast
+        };
+        match self.intent {
+            StreamIntent::Auto => WireBehavior {
+                fec_group: None,
+                jitter_buffer: Duration::from_millis(5),
+                static_refresh: None,
+                jitter_strategy,
+                frame_interval: Duration::from_millis(25),
+            },
+            StreamIntent::Realtime => WireBehavior {
+                fec_group: None,
+                jitter_buffer: Duration::ZERO,
+                static_refresh: None,
+                jitter_strategy,
+                // DMX512's fastest full-universe refresh.
+                frame_interval: Duration::from_micros(1_000_000 / 44),
+            },
+            StreamIntent::Install => WireBehavior {
+                fec_group: Some(4),
+                jitter_buffer: Duration::from_millis(40),
+                static_refresh: Some(Duration::from_secs(1)),
+                jitter_strategy,
+                frame_interval: Duration::from_micros(1_000_000 / 30),
+            },
+        }
+    }
+}
+
+/// Per-intent transport settings, see [`CompiledStreamProfile::wire_behavior`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireBehavior {
+    /// Frames per universe covered by one XOR parity frame; `None` sends no parity.
+    pub fec_group: Option<usize>,
+    /// How long the receiver holds frames to reorder them and rebuild lost ones before
+    /// applying them.
+    pub jitter_buffer: Duration,
+    /// Interval at which a universe whose values have not changed is re-sent; `None`
+    /// sends every frame.
+    pub static_refresh: Option<Duration>,
+    /// How gaps and changes between frames are smoothed.
+    pub jitter_strategy: JitterStrategy,
+    /// Interval at which controllers send each universe: 44 Hz for `Realtime`, 40 Hz for
+    /// `Auto`, and 30 Hz for `Install`.
+    pub frame_interval: Duration,
+}
+
+impl WireBehavior {
+    /// How long after its timestamp a frame may arrive and still be on time: one frame
+    /// interval plus the receive buffer's hold, moved by the adaptation engine's
+    /// `deadline_offset_ms`.
+    pub fn frame_deadline(&self, deadline_offset_ms: i16) -> Duration {
+        let base = self.frame_interval + self.jitter_buffer;
+        let offset = Duration::from_millis(u64::from(deadline_offset_ms.unsigned_abs()));
+        if deadline_offset_ms < 0 {
+            base.saturating_sub(offset)
+        } else {
+            base + offset
+        }
+    }
+}
+
+/// Payload of `start_stream` and `restart_stream`: the profile's parameters and the
+/// `config_id` the controller compiled them to, which the node must reproduce.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProfileAnnouncement {
+    pub intent: StreamIntent,
+    pub latency_weight: u8,
+    pub resilience_weight: u8,
+    pub config_id: String,
+}
+
+impl ProfileAnnouncement {
+    pub fn new(profile: &CompiledStreamProfile) -> Self {
+        Self {
+            intent: profile.intent(),
+            latency_weight: profile.latency_weight(),
+            resilience_weight: profile.resilience_weight(),
+            config_id: profile.config_id().to_string(),
+        }
+    }
+
+    /// Recompiles the announced profile, refusing it unless it yields the announced
+    /// `config_id`.
+    pub fn compile(&self) -> Result<CompiledStreamProfile, ProfileError> {
+        let compiled =
+            StreamProfile::with_weights(self.intent, self.latency_weight, self.resilience_weight)
+                .compile()?;
+        if compiled.config_id() != self.config_id {
+            return Err(ProfileError::ConfigIdMismatch {
+                announced: self.config_id.clone(),
+                actual: compiled.config_id().to_string(),
+            });
+        }
+        Ok(compiled)
+    }
 }
 
 impl Default for StreamProfile {
@@ -197,4 +340,82 @@ mod tests {
             Err(ProfileError::LatencyWeightOutOfRange)
         ));
     }
+
+    #[test]
+    fn reject_profiles_beyond_node_limits() {
+        let realtime = StreamProfile::realtime().compile().unwrap();
+        let install = StreamProfile::install().compile().unwrap();
+        let limited = CapabilitySet {
+            max_frame_rate: Some(30),
+            jitter_strategies: vec![JitterStrategy::HoldLast],
+            ..CapabilitySet::default()
+        };
+        assert!(matches!(
+            realtime.check_capabilities(&limited),
+            Err(ProfileError::RateExceeded { .. })
+        ));
+        assert!(matches!(
+            install.check_capabilities(&limited),
+            Err(ProfileError::UnsupportedJitter(JitterStrategy::Lerp))
+        ));
+        let slow = CapabilitySet {
+            min_frame_interval_us: Some(40_000),
+            ..CapabilitySet::default()
+        };
+        assert!(install.check_capabilities(&slow).is_err());
+        assert!(install
+            .check_capabilities(&CapabilitySet::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn intents_shape_wire_behavior() {
+        let install = StreamProfile::install().compile().unwrap().wire_behavior();
+        assert_eq!(install.fec_group, Some(4));
+        assert_eq!(install.jitter_strategy, JitterStrategy::Lerp);
+        assert!(install.static_refresh.is_some());
+
+        let realtime = StreamProfile::realtime().compile().unwrap().wire_behavior();
+        assert_eq!(realtime.fec_group, None);
+        assert_eq!(realtime.jitter_buffer, Duration::ZERO);
+        assert!(realtime.jitter_buffer < install.jitter_buffer);
+        // Realtime never interpolates, even when its weights lean towards resilience.
+        let cautious = StreamProfile::with_weights(StreamIntent::Realtime, 10, 90)
+            .compile()
+            .unwrap();
+        assert_eq!(
+            cautious.wire_behavior().jitter_strategy,
+            JitterStrategy::HoldLast
+        );
+        let auto = StreamProfile::auto().compile().unwrap().wire_behavior();
+        assert_eq!(auto.jitter_strategy, JitterStrategy::HoldLast);
+        assert!(auto.jitter_buffer > realtime.jitter_buffer);
+        assert!(realtime.frame_interval < auto.frame_interval);
+        assert!(auto.frame_interval < install.frame_interval);
+    }
+
+    #[test]
+    fn deadlines_follow_the_adaptation_offset() {
+        let auto = StreamProfile::auto().compile().unwrap().wire_behavior();
+        assert_eq!(auto.frame_deadline(0), Duration::from_millis(30));
+        assert_eq!(auto.frame_deadline(15), Duration::from_millis(45));
+        assert_eq!(auto.frame_deadline(-10), Duration::from_millis(20));
+        assert_eq!(auto.frame_deadline(i16::MIN), Duration::ZERO);
+    }
+
+    #[test]
+    fn renegotiation_must_reproduce_config_id() {
+        let install = StreamProfile::install().compile().unwrap();
+        let mut offer = ProfileAnnouncement::new(&install);
+        assert_eq!(offer.compile().unwrap().config_id(), install.config_id());
+        assert_eq!(
+            format!("{:08x}", install.config_tag()),
+            install.config_id()[..8]
+        );
+        offer.latency_weight = 30;
+        assert!(matches!(
+            offer.compile(),
+            Err(ProfileError::ConfigIdMismatch { .. })
+        ));
+    }
 }
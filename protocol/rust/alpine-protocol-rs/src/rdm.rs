@@ -0,0 +1,440 @@
+//! RDM (ANSI E1.20) interop for RDMnet-managed rigs (ANSI E1.33).
+//!
+//! ALPINE devices get RDM UIDs derived from their identity so RDMnet controllers can see
+//! them as responders. [`llrp`] answers RDMnet's LLRP discovery and RDM commands on the
+//! local network. ALPINE controllers reach the same responders with `rdm_command` control
+//! envelopes, which take the place of RPT's broker hop: the command is authenticated by
+//! the session instead of travelling through a broker.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::control::ControlHandlers;
+use crate::messages::{ControlOp, DeviceIdentity};
+
+pub mod llrp;
+
+const RDM_START_CODE: u8 = 0xCC;
+const RDM_SUB_START_CODE: u8 = 0x01;
+/// Bytes of an RDM message before the parameter data, start code included.
+const RDM_HEADER_LEN: usize = 24;
+const RDM_MAX_DATA: usize = 231;
+
+/// NACK reason for a parameter the responder does not support.
+pub const NR_UNKNOWN_PID: u16 = 0x0000;
+/// NACK reason for a command class the parameter does not support.
+pub const NR_UNSUPPORTED_COMMAND_CLASS: u16 = 0x0005;
+
+/// Failures decoding RDM messages or unique ids.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RdmError {
+    #[error("rdm message is truncated")]
+    Truncated,
+    #[error("rdm message has the wrong start code")]
+    StartCode,
+    #[error("rdm checksum mismatch")]
+    Checksum,
+    #[error("unknown rdm command class {0:#04x}")]
+    CommandClass(u8),
+    #[error("rdm parameter data exceeds 231 bytes")]
+    DataTooLong,
+    #[error("invalid rdm uid {0:?}")]
+    Uid(String),
+}
+
+/// RDM unique id: an ESTA manufacturer id and a device id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RdmUid {
+    pub manufacturer: u16,
+    pub device: u32,
+}
+
+impl RdmUid {
+    /// Addresses every responder.
+    pub const BROADCAST: Self = Self {
+        manufacturer: 0xFFFF,
+        device: 0xFFFF_FFFF,
+    };
+
+    /// Stable UID for an ALPINE device under the ESTA `manufacturer` id, derived from its
+    /// `device_id`.
+    pub fn for_device(manufacturer: u16, identity: &DeviceIdentity) -> Self {
+        let digest = Sha256::digest(identity.device_id.as_bytes());
+        let device = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+        Self {
+            manufacturer,
+            // All-ones device ids are manufacturer broadcasts.
+            device: device.min(0xFFFF_FFFE),
+        }
+    }
+
+    /// Whether a message to `self` reaches the responder `uid`.
+    pub fn addresses(&self, uid: RdmUid) -> bool {
+        *self == uid
+            || (self.device == 0xFFFF_FFFF
+                && (self.manufacturer == 0xFFFF || self.manufacturer == uid.manufacturer))
+    }
+
+    pub fn to_bytes(self) -> [u8; 6] {
+        let mut bytes = [0; 6];
+        bytes[..2].copy_from_slice(&self.manufacturer.to_be_bytes());
+        bytes[2..].copy_from_slice(&self.device.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 6]) -> Self {
+        Self {
+            manufacturer: u16::from_be_bytes([bytes[0], bytes[1]]),
+            device: u32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
+        }
+    }
+}
+
+impl fmt::Display for RdmUid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}:{:08X}", self.manufacturer, self.device)
+    }
+}
+
+impl FromStr for RdmUid {
+    type Err = RdmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RdmError::Uid(s.to_string());
+        let (manufacturer, device) = s.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            manufacturer: u16::from_str_radix(manufacturer, 16).map_err(|_| invalid())?,
+            device: u32::from_str_radix(device, 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for RdmUid {
+    type Error = RdmError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<RdmUid> for String {
+    fn from(uid: RdmUid) -> Self {
+        uid.to_string()
+    }
+}
+
+/// RDM command class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandClass {
+    Get,
+    GetResponse,
+    Set,
+    SetResponse,
+}
+
+impl CommandClass {
+    fn to_byte(self) -> u8 {
+        match self {
+            CommandClass::Get => 0x20,
+            CommandClass::GetResponse => 0x21,
+            CommandClass::Set => 0x30,
+            CommandClass::SetResponse => 0x31,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, RdmError> {
+        match byte {
+            0x20 => Ok(CommandClass::Get),
+            0x21 => Ok(CommandClass::GetResponse),
+            0x30 => Ok(CommandClass::Set),
+            0x31 => Ok(CommandClass::SetResponse),
+            other => Err(RdmError::CommandClass(other)),
+        }
+    }
+
+    /// The class a responder answers this command with.
+    pub fn response(self) -> Self {
+        match self {
+            CommandClass::Get | CommandClass::GetResponse => CommandClass::GetResponse,
+            CommandClass::Set | CommandClass::SetResponse => CommandClass::SetResponse,
+        }
+    }
+}
+
+/// How a responder answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseType {
+    Ack,
+    /// `data` is the delay, in tenths of a second, before the result can be collected.
+    AckTimer,
+    /// `data` is the NACK reason code.
+    NackReason,
+    AckOverflow,
+}
+
+impl ResponseType {
+    fn to_byte(self) -> u8 {
+        match self {
+            ResponseType::Ack => 0x00,
+            ResponseType::AckTimer => 0x01,
+            ResponseType::NackReason => 0x02,
+            ResponseType::AckOverflow => 0x03,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0x01 => ResponseType::AckTimer,
+            0x02 => ResponseType::NackReason,
+            0x03 => ResponseType::AckOverflow,
+            _ => ResponseType::Ack,
+        }
+    }
+}
+
+/// An RDM message as sent on the wire, start code through checksum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RdmMessage {
+    pub dest: RdmUid,
+    pub source: RdmUid,
+    pub transaction: u8,
+    /// Port id in commands, response type in responses.
+    pub port_or_response: u8,
+    pub message_count: u8,
+    pub sub_device: u16,
+    pub command_class: CommandClass,
+    pub pid: u16,
+    pub data: Vec<u8>,
+}
+
+impl RdmMessage {
+    pub fn encode(&self) -> Result<Vec<u8>, RdmError> {
+        if self.data.len() > RDM_MAX_DATA {
+            return Err(RdmError::DataTooLong);
+        }
+        let mut bytes = Vec::with_capacity(RDM_HEADER_LEN + self.data.len() + 2);
+        bytes.push(RDM_START_CODE);
+        bytes.push(RDM_SUB_START_CODE);
+        bytes.push((RDM_HEADER_LEN + self.data.len()) as u8);
+        bytes.extend_from_slice(&self.dest.to_bytes());
+        bytes.extend_from_slice(&self.source.to_bytes());
+        bytes.push(self.transaction);
+        bytes.push(self.port_or_response);
+        bytes.push(self.message_count);
+        bytes.extend_from_slice(&self.sub_device.to_be_bytes());
+        bytes.push(self.command_class.to_byte());
+        bytes.extend_from_slice(&self.pid.to_be_bytes());
+        bytes.push(self.data.len() as u8);
+        bytes.extend_from_slice(&self.data);
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, RdmError> {
+        if bytes.len() < RDM_HEADER_LEN + 2 {
+            return Err(RdmError::Truncated);
+        }
+        if bytes[0] != RDM_START_CODE || bytes[1] != RDM_SUB_START_CODE {
+            return Err(RdmError::StartCode);
+        }
+        let len = usize::from(bytes[2]);
+        let data_len = usize::from(bytes[23]);
+        if len != RDM_HEADER_LEN + data_len || bytes.len() < len + 2 {
+            return Err(RdmError::Truncated);
+        }
+        if checksum(&bytes[..len]) != u16::from_be_bytes([bytes[len], bytes[len + 1]]) {
+            return Err(RdmError::Checksum);
+        }
+        let uid = |at: usize| {
+            let mut raw = [0; 6];
+            raw.copy_from_slice(&bytes[at..at + 6]);
+            RdmUid::from_bytes(raw)
+        };
+        Ok(Self {
+            dest: uid(3),
+            source: uid(9),
+            transaction: bytes[15],
+            port_or_response: bytes[16],
+            message_count: bytes[17],
+            sub_device: u16::from_be_bytes([bytes[18], bytes[19]]),
+            command_class: CommandClass::from_byte(bytes[20])?,
+            pid: u16::from_be_bytes([bytes[21], bytes[22]]),
+            data: bytes[RDM_HEADER_LEN..len].to_vec(),
+        })
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(u16::from(byte)))
+}
+
+/// Payload of `rdm_command`: a GET or SET for a responder at or behind the node.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RdmRequest {
+    pub dest: RdmUid,
+    #[serde(default)]
+    pub sub_device: u16,
+    pub command_class: CommandClass,
+    pub pid: u16,
+    #[serde(default)]
+    pub data: Vec<u8>,
+}
+
+/// A responder's answer; carried as JSON in the `rdm_command` ack detail.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RdmReply {
+    pub response_type: ResponseType,
+    #[serde(default)]
+    pub data: Vec<u8>,
+}
+
+impl RdmReply {
+    pub fn ack(data: Vec<u8>) -> Self {
+        Self {
+            response_type: ResponseType::Ack,
+            data,
+        }
+    }
+
+    pub fn nack(reason: u16) -> Self {
+        Self {
+            response_type: ResponseType::NackReason,
+            data: reason.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// Reads a wire-format response.
+    pub fn from_message(message: &RdmMessage) -> Self {
+        Self {
+            response_type: ResponseType::from_byte(message.port_or_response),
+            data: message.data.clone(),
+        }
+    }
+}
+
+/// Answers RDM GET and SET commands for the node and any gear it fronts.
+pub trait RdmResponder: Send + Sync {
+    fn respond(&self, request: &RdmRequest) -> RdmReply;
+}
+
+/// Node-side RDM endpoint: the node's UID and the responder that answers for it.
+pub struct RdmGateway {
+    uid: RdmUid,
+    responder: Arc<dyn RdmResponder>,
+}
+
+impl RdmGateway {
+    pub fn new(uid: RdmUid, responder: Arc<dyn RdmResponder>) -> Self {
+        Self { uid, responder }
+    }
+
+    pub fn uid(&self) -> RdmUid {
+        self.uid
+    }
+
+    pub fn respond(&self, request: &RdmRequest) -> RdmReply {
+        self.responder.respond(request)
+    }
+
+    /// Answers a wire-format RDM command addressed to the node; `None` for responses,
+    /// commands for other UIDs, and broadcasts, which get no reply.
+    pub fn respond_message(&self, message: &RdmMessage) -> Option<RdmMessage> {
+        let command_class = match message.command_class {
+            CommandClass::Get | CommandClass::Set => message.command_class,
+            _ => return None,
+        };
+        if !message.dest.addresses(self.uid) {
+            return None;
+        }
+        let reply = self.respond(&RdmRequest {
+            dest: self.uid,
+            sub_device: message.sub_device,
+            command_class,
+            pid: message.pid,
+            data: message.data.clone(),
+        });
+        (message.dest == self.uid).then(|| RdmMessage {
+            dest: message.source,
+            source: self.uid,
+            transaction: message.transaction,
+            port_or_response: reply.response_type.to_byte(),
+            message_count: 0,
+            sub_device: message.sub_device,
+            command_class: command_class.response(),
+            pid: message.pid,
+            data: reply.data,
+        })
+    }
+
+    /// Registers the `rdm_command` handler; the ack detail is the [`RdmReply`] as JSON.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let gateway = self.clone();
+        handlers.on(ControlOp::RdmCommand, move |request: RdmRequest| {
+            if !matches!(request.command_class, CommandClass::Get | CommandClass::Set) {
+                return Err("rdm_command carries only get and set".to_string());
+            }
+            serde_json::to_string(&gateway.respond(&request))
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+impl fmt::Debug for RdmGateway {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RdmGateway")
+            .field("uid", &self.uid)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip_and_uids_follow_identity() {
+        let identity = DeviceIdentity {
+            device_id: "fixture-7".into(),
+            manufacturer_id: "acme".into(),
+            model_id: "wash".into(),
+            hardware_rev: String::new(),
+            firmware_rev: "1.0".into(),
+        };
+        let uid = RdmUid::for_device(0x7A70, &identity);
+        assert_eq!(uid, RdmUid::for_device(0x7A70, &identity));
+        assert_eq!(uid.to_string().parse::<RdmUid>(), Ok(uid));
+        assert_eq!(
+            serde_json::to_value(uid).unwrap(),
+            serde_json::json!(uid.to_string())
+        );
+        assert!(RdmUid::BROADCAST.addresses(uid));
+
+        let message = RdmMessage {
+            dest: uid,
+            source: RdmUid::from_bytes([1, 2, 3, 4, 5, 6]),
+            transaction: 9,
+            port_or_response: 1,
+            message_count: 0,
+            sub_device: 0,
+            command_class: CommandClass::Set,
+            pid: 0x00F0,
+            data: vec![0, 42],
+        };
+        let mut bytes = message.encode().unwrap();
+        assert_eq!(RdmMessage::decode(&bytes), Ok(message));
+        bytes[25] ^= 1;
+        assert_eq!(RdmMessage::decode(&bytes), Err(RdmError::Checksum));
+    }
+}
@@ -0,0 +1,395 @@
+//! LLRP (ANSI E1.33 section 5): RDMnet's low-level discovery and recovery.
+//!
+//! Managers multicast probe requests for a UID range to `239.255.250.133:5569`; targets in
+//! range that the manager does not already know reply to `239.255.250.134:5569` after a
+//! random backoff. Managers then address a target by CID with RDM commands, which is how
+//! RDMnet gear finds and configures devices before any broker exists. Packets are ACN
+//! root-layer PDUs with three-byte flags and length fields.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use uuid::Uuid;
+
+use super::{checksum, RdmError, RdmGateway, RdmMessage, RdmUid};
+
+/// UDP port LLRP uses for requests and replies.
+pub const LLRP_PORT: u16 = 5569;
+/// Group managers send requests to.
+pub const LLRP_REQUEST_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 250, 133);
+/// Group targets send replies to.
+pub const LLRP_RESPONSE_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 250, 134);
+/// Destination CID of probe requests, which every target reads.
+pub const LLRP_BROADCAST_CID: Uuid = Uuid::from_u128(0xFBAD822C_BD0C_4D4C_BDC8_7EABEBC85AFF);
+/// Longest a target waits before answering a probe, so replies do not collide.
+pub const LLRP_MAX_BACKOFF: Duration = Duration::from_millis(1500);
+
+/// Component type of an RPT device, which ALPINE nodes present as.
+pub const COMPONENT_RPT_DEVICE: u8 = 0x00;
+/// Probe filter bit: only brokers should reply.
+pub const FILTER_BROKERS_ONLY: u16 = 0x0002;
+
+const ACN_PREAMBLE: [u8; 16] = *b"\x00\x10\x00\x00ASC-E1.17\x00\x00\x00";
+const VECTOR_ROOT_LLRP: u32 = 0x0000_000A;
+const VECTOR_LLRP_PROBE_REQUEST: u32 = 0x0000_0001;
+const VECTOR_LLRP_PROBE_REPLY: u32 = 0x0000_0002;
+const VECTOR_LLRP_RDM_CMD: u32 = 0x0000_0003;
+const VECTOR_PROBE_REQUEST_DATA: u8 = 0x01;
+const VECTOR_PROBE_REPLY_DATA: u8 = 0x01;
+const VECTOR_RDM_CMD_RDM_DATA: u8 = 0xCC;
+const FLAGS: u8 = 0xF0;
+
+/// Body of an LLRP packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LlrpMessage {
+    ProbeRequest {
+        lower: RdmUid,
+        upper: RdmUid,
+        filter: u16,
+        /// Targets the manager already found, which stay quiet.
+        known: Vec<RdmUid>,
+    },
+    ProbeReply {
+        uid: RdmUid,
+        hardware_address: [u8; 6],
+        component_type: u8,
+    },
+    Rdm(RdmMessage),
+}
+
+/// An LLRP packet: sender and destination CIDs, a transaction number, and the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlrpPacket {
+    pub sender: Uuid,
+    pub dest: Uuid,
+    pub transaction: u32,
+    pub message: LlrpMessage,
+}
+
+impl LlrpPacket {
+    pub fn encode(&self) -> Result<Vec<u8>, RdmError> {
+        let (vector, body) = match &self.message {
+            LlrpMessage::ProbeRequest {
+                lower,
+                upper,
+                filter,
+                known,
+            } => {
+                let mut data = Vec::with_capacity(14 + known.len() * 6);
+                data.extend_from_slice(&lower.to_bytes());
+                data.extend_from_slice(&upper.to_bytes());
+                data.extend_from_slice(&filter.to_be_bytes());
+                for uid in known {
+                    data.extend_from_slice(&uid.to_bytes());
+                }
+                (
+                    VECTOR_LLRP_PROBE_REQUEST,
+                    pdu(&[VECTOR_PROBE_REQUEST_DATA], &data),
+                )
+            }
+            LlrpMessage::ProbeReply {
+                uid,
+                hardware_address,
+                component_type,
+            } => {
+                let mut data = Vec::with_capacity(13);
+                data.extend_from_slice(&uid.to_bytes());
+                data.extend_from_slice(hardware_address);
+                data.push(*component_type);
+                (
+                    VECTOR_LLRP_PROBE_REPLY,
+                    pdu(&[VECTOR_PROBE_REPLY_DATA], &data),
+                )
+            }
+            LlrpMessage::Rdm(message) => {
+                // Carried without the start code, which is the vector, and the checksum.
+                let bytes = message.encode()?;
+                (
+                    VECTOR_LLRP_RDM_CMD,
+                    pdu(&[VECTOR_RDM_CMD_RDM_DATA], &bytes[1..bytes.len() - 2]),
+                )
+            }
+        };
+        let mut llrp_header = Vec::with_capacity(24);
+        llrp_header.extend_from_slice(&vector.to_be_bytes());
+        llrp_header.extend_from_slice(self.dest.as_bytes());
+        llrp_header.extend_from_slice(&self.transaction.to_be_bytes());
+        let llrp = pdu(&llrp_header, &body);
+
+        let mut root_header = Vec::with_capacity(20);
+        root_header.extend_from_slice(&VECTOR_ROOT_LLRP.to_be_bytes());
+        root_header.extend_from_slice(self.sender.as_bytes());
+        let mut packet = ACN_PREAMBLE.to_vec();
+        packet.extend_from_slice(&pdu(&root_header, &llrp));
+        Ok(packet)
+    }
+
+    /// Parses an LLRP packet; `None` for anything else or a malformed packet.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let root = bytes.strip_prefix(&ACN_PREAMBLE)?;
+        let root = pdu_body(root)?;
+        if u32_at(root, 0)? != VECTOR_ROOT_LLRP {
+            return None;
+        }
+        let sender = Uuid::from_slice(root.get(4..20)?).ok()?;
+        let llrp = pdu_body(root.get(20..)?)?;
+        let vector = u32_at(llrp, 0)?;
+        let dest = Uuid::from_slice(llrp.get(4..20)?).ok()?;
+        let transaction = u32_at(llrp, 20)?;
+        let inner = pdu_body(llrp.get(24..)?)?;
+        let (&inner_vector, data) = inner.split_first()?;
+        let uid = |at: usize| Some(RdmUid::from_bytes(data.get(at..at + 6)?.try_into().ok()?));
+        let message = match (vector, inner_vector) {
+            (VECTOR_LLRP_PROBE_REQUEST, VECTOR_PROBE_REQUEST_DATA) => {
+                let known = data.get(14..)?;
+                if known.len() % 6 != 0 {
+                    return None;
+                }
+                LlrpMessage::ProbeRequest {
+                    lower: uid(0)?,
+                    upper: uid(6)?,
+                    filter: u16::from_be_bytes(data.get(12..14)?.try_into().ok()?),
+                    known: known
+                        .chunks_exact(6)
+                        .map(|raw| RdmUid::from_bytes(raw.try_into().unwrap_or_default()))
+                        .collect(),
+                }
+            }
+            (VECTOR_LLRP_PROBE_REPLY, VECTOR_PROBE_REPLY_DATA) => LlrpMessage::ProbeReply {
+                uid: uid(0)?,
+                hardware_address: data.get(6..12)?.try_into().ok()?,
+                component_type: *data.get(12)?,
+            },
+            (VECTOR_LLRP_RDM_CMD, VECTOR_RDM_CMD_RDM_DATA) => {
+                let mut raw = Vec::with_capacity(data.len() + 3);
+                raw.push(VECTOR_RDM_CMD_RDM_DATA);
+                raw.extend_from_slice(data);
+                raw.extend_from_slice(&checksum(&raw).to_be_bytes());
+                LlrpMessage::Rdm(RdmMessage::decode(&raw).ok()?)
+            }
+            _ => return None,
+        };
+        Some(Self {
+            sender,
+            dest,
+            transaction,
+            message,
+        })
+    }
+}
+
+/// Wraps `header` and `data` in a PDU with a three-byte flags and length field.
+fn pdu(header: &[u8], data: &[u8]) -> Vec<u8> {
+    let len = 3 + header.len() + data.len();
+    let mut bytes = Vec::with_capacity(len);
+    bytes.push(FLAGS | ((len >> 16) as u8 & 0x0F));
+    bytes.push((len >> 8) as u8);
+    bytes.push(len as u8);
+    bytes.extend_from_slice(header);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// The bytes of the PDU at the start of `bytes` after its flags and length.
+fn pdu_body(bytes: &[u8]) -> Option<&[u8]> {
+    let [flags, mid, low] = *bytes.get(..3)? else {
+        return None;
+    };
+    let len = usize::from(flags & 0x0F) << 16 | usize::from(mid) << 8 | usize::from(low);
+    bytes.get(3..len)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Answers LLRP probes and RDM commands for a node, as an RDMnet RPT device.
+#[derive(Debug, Clone)]
+pub struct LlrpTarget {
+    cid: Uuid,
+    hardware_address: [u8; 6],
+    gateway: Arc<RdmGateway>,
+}
+
+impl LlrpTarget {
+    /// Target with component id `cid` answering RDM through `gateway`.
+    pub fn new(cid: Uuid, gateway: Arc<RdmGateway>) -> Self {
+        Self {
+            cid,
+            hardware_address: [0; 6],
+            gateway,
+        }
+    }
+
+    /// MAC address reported in probe replies.
+    pub fn with_hardware_address(mut self, hardware_address: [u8; 6]) -> Self {
+        self.hardware_address = hardware_address;
+        self
+    }
+
+    /// The reply to `packet`, if it calls for one.
+    pub fn handle(&self, packet: &LlrpPacket) -> Option<LlrpPacket> {
+        let uid = self.gateway.uid();
+        let message = match &packet.message {
+            LlrpMessage::ProbeRequest {
+                lower,
+                upper,
+                filter,
+                known,
+            } if packet.dest == LLRP_BROADCAST_CID => {
+                if filter & FILTER_BROKERS_ONLY != 0
+                    || !(*lower..=*upper).contains(&uid)
+                    || known.contains(&uid)
+                {
+                    return None;
+                }
+                LlrpMessage::ProbeReply {
+                    uid,
+                    hardware_address: self.hardware_address,
+                    component_type: COMPONENT_RPT_DEVICE,
+                }
+            }
+            LlrpMessage::Rdm(command) if packet.dest == self.cid => {
+                LlrpMessage::Rdm(self.gateway.respond_message(command)?)
+            }
+            _ => return None,
+        };
+        Some(LlrpPacket {
+            sender: self.cid,
+            dest: packet.sender,
+            transaction: packet.transaction,
+            message,
+        })
+    }
+
+    /// Binds the LLRP port on every IPv4 interface and joins the request group.
+    pub fn bind() -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, LLRP_PORT)).into())?;
+        socket.join_multicast_v4(&LLRP_REQUEST_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        UdpSocket::from_std(socket.into())
+    }
+
+    /// Serves LLRP on `socket` until it fails, replying to the response group.
+    pub async fn run(self, socket: UdpSocket) -> io::Result<()> {
+        let socket = Arc::new(socket);
+        let replies = SocketAddrV4::new(LLRP_RESPONSE_GROUP, LLRP_PORT);
+        let mut buffer = [0; 1500];
+        loop {
+            let (len, _) = socket.recv_from(&mut buffer).await?;
+            let Some(reply) = LlrpPacket::decode(&buffer[..len]).and_then(|p| self.handle(&p))
+            else {
+                continue;
+            };
+            let Ok(bytes) = reply.encode() else {
+                continue;
+            };
+            let backoff = match reply.message {
+                LlrpMessage::ProbeReply { .. } => {
+                    rand::thread_rng().gen_range(Duration::ZERO..=LLRP_MAX_BACKOFF)
+                }
+                _ => Duration::ZERO,
+            };
+            let socket = socket.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(backoff).await;
+                let _ = socket.send_to(&bytes, replies).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdm::{CommandClass, RdmReply, RdmRequest, RdmResponder, ResponseType};
+
+    struct Label;
+
+    impl RdmResponder for Label {
+        fn respond(&self, request: &RdmRequest) -> RdmReply {
+            match (request.command_class, request.pid) {
+                (CommandClass::Get, 0x0082) => RdmReply::ack(b"wash 7".to_vec()),
+                _ => RdmReply::nack(crate::rdm::NR_UNKNOWN_PID),
+            }
+        }
+    }
+
+    #[test]
+    fn probes_in_range_get_a_reply_and_rdm_is_answered() {
+        let uid = RdmUid::from_bytes([0x7A, 0x70, 0, 0, 0, 7]);
+        let target = LlrpTarget::new(
+            Uuid::from_u128(7),
+            Arc::new(RdmGateway::new(uid, Arc::new(Label))),
+        )
+        .with_hardware_address([2, 0, 0, 0, 0, 7]);
+        let manager = Uuid::from_u128(1);
+        let probe = |known: Vec<RdmUid>| LlrpPacket {
+            sender: manager,
+            dest: LLRP_BROADCAST_CID,
+            transaction: 5,
+            message: LlrpMessage::ProbeRequest {
+                lower: RdmUid::from_bytes([0; 6]),
+                upper: RdmUid::BROADCAST,
+                filter: 0,
+                known,
+            },
+        };
+        let request = LlrpPacket::decode(&probe(Vec::new()).encode().unwrap()).unwrap();
+        assert_eq!(request, probe(Vec::new()));
+        let reply = target.handle(&request).unwrap();
+        assert_eq!((reply.dest, reply.transaction), (manager, 5));
+        assert_eq!(
+            LlrpPacket::decode(&reply.encode().unwrap())
+                .unwrap()
+                .message,
+            LlrpMessage::ProbeReply {
+                uid,
+                hardware_address: [2, 0, 0, 0, 0, 7],
+                component_type: COMPONENT_RPT_DEVICE,
+            }
+        );
+        assert_eq!(target.handle(&probe(vec![uid])), None);
+
+        let get = LlrpPacket {
+            sender: manager,
+            dest: Uuid::from_u128(7),
+            transaction: 6,
+            message: LlrpMessage::Rdm(RdmMessage {
+                dest: uid,
+                source: RdmUid::from_bytes([0x7A, 0x70, 0, 0, 0, 1]),
+                transaction: 3,
+                port_or_response: 1,
+                message_count: 0,
+                sub_device: 0,
+                command_class: CommandClass::Get,
+                pid: 0x0082,
+                data: Vec::new(),
+            }),
+        };
+        let get = LlrpPacket::decode(&get.encode().unwrap()).unwrap();
+        let reply = target.handle(&get).unwrap();
+        let LlrpMessage::Rdm(response) = LlrpPacket::decode(&reply.encode().unwrap())
+            .unwrap()
+            .message
+        else {
+            panic!("expected an rdm response");
+        };
+        assert_eq!(response.command_class, CommandClass::GetResponse);
+        assert_eq!(response.transaction, 3);
+        assert_eq!(
+            RdmReply::from_message(&response),
+            RdmReply {
+                response_type: ResponseType::Ack,
+                data: b"wash 7".to_vec(),
+            }
+        );
+    }
+}
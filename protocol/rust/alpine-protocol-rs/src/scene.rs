@@ -0,0 +1,470 @@
+//! Scene storage and recall on nodes.
+//!
+//! A scene is a snapshot of the node's current look saved in a numbered slot. Controllers
+//! manage scenes with the `store_scene`, `list_scenes`, and `recall_scene` control ops;
+//! recalling crossfades from the live look to the stored one over the requested time.
+//! Because scenes live on the node, local inputs such as a wall switch can recall them
+//! with no controller present.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::codec;
+use crate::control::{ControlClient, ControlHandlers};
+use crate::messages::{ControlOp, UniverseId};
+
+/// Channel values per universe.
+pub type Look = BTreeMap<UniverseId, Vec<u16>>;
+
+/// Look saved in a scene slot.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Scene {
+    pub slot: u16,
+    pub name: Option<String>,
+    pub look: Look,
+    pub captured_at_ms: u64,
+}
+
+/// Entry returned by `list_scenes`, without the channel data.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SceneSummary {
+    pub slot: u16,
+    pub name: Option<String>,
+    pub universes: Vec<UniverseId>,
+    pub captured_at_ms: u64,
+}
+
+impl From<&Scene> for SceneSummary {
+    fn from(scene: &Scene) -> Self {
+        Self {
+            slot: scene.slot,
+            name: scene.name.clone(),
+            universes: scene.look.keys().copied().collect(),
+            captured_at_ms: scene.captured_at_ms,
+        }
+    }
+}
+
+/// Payload of `store_scene`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoreScene {
+    pub slot: u16,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Payload of `recall_scene`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecallScene {
+    pub slot: u16,
+    /// Crossfade time; zero or absent snaps to the scene.
+    #[serde(default)]
+    pub fade_ms: u64,
+}
+
+/// Failures storing or recalling scenes.
+#[derive(Debug, Error)]
+pub enum SceneError {
+    #[error("scene slot {0} is empty")]
+    EmptySlot(u16),
+    #[error("no look to capture yet")]
+    NoLook,
+    #[error("scene storage failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("scene encoding failed: {0}")]
+    Codec(String),
+}
+
+/// Persistence for scenes, keyed by slot.
+pub trait SceneStore: Send + Sync + std::fmt::Debug {
+    fn save(&self, scene: &Scene) -> Result<(), SceneError>;
+    fn load(&self, slot: u16) -> Result<Option<Scene>, SceneError>;
+    /// All stored scenes, ordered by slot.
+    fn list(&self) -> Result<Vec<Scene>, SceneError>;
+    fn remove(&self, slot: u16) -> Result<(), SceneError>;
+}
+
+/// Process-local store; scenes do not survive a restart.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySceneStore {
+    scenes: Arc<Mutex<BTreeMap<u16, Scene>>>,
+}
+
+impl MemorySceneStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SceneStore for MemorySceneStore {
+    fn save(&self, scene: &Scene) -> Result<(), SceneError> {
+        self.scenes.lock().insert(scene.slot, scene.clone());
+        Ok(())
+    }
+
+    fn load(&self, slot: u16) -> Result<Option<Scene>, SceneError> {
+        Ok(self.scenes.lock().get(&slot).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<Scene>, SceneError> {
+        Ok(self.scenes.lock().values().cloned().collect())
+    }
+
+    fn remove(&self, slot: u16) -> Result<(), SceneError> {
+        self.scenes.lock().remove(&slot);
+        Ok(())
+    }
+}
+
+/// Stores one CBOR-encoded file per slot under a directory, so scenes survive power loss.
+#[derive(Debug, Clone)]
+pub struct FileSceneStore {
+    dir: PathBuf,
+}
+
+impl FileSceneStore {
+    /// Uses `dir`, creating it on first save.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, slot: u16) -> PathBuf {
+        self.dir.join(format!("{}.scene", slot))
+    }
+}
+
+impl SceneStore for FileSceneStore {
+    fn save(&self, scene: &Scene) -> Result<(), SceneError> {
+        fs::create_dir_all(&self.dir)?;
+        let bytes = codec::to_vec(scene).map_err(|e| SceneError::Codec(e.to_string()))?;
+        // Write then rename so a power cut mid-save never leaves a truncated scene.
+        let path = self.path(scene.slot);
+        let staging = path.with_extension("tmp");
+        fs::write(&staging, bytes)?;
+        fs::rename(staging, path)?;
+        Ok(())
+    }
+
+    fn load(&self, slot: u16) -> Result<Option<Scene>, SceneError> {
+        match fs::read(self.path(slot)) {
+            Ok(bytes) => codec::decode_untrusted(&bytes)
+                .map(Some)
+                .map_err(|e| SceneError::Codec(e.to_string())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<Scene>, SceneError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut slots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "scene") {
+                if let Some(slot) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<u16>().ok())
+                {
+                    slots.push(slot);
+                }
+            }
+        }
+        slots.sort_unstable();
+        let mut scenes = Vec::with_capacity(slots.len());
+        for slot in slots {
+            if let Some(scene) = self.load(slot)? {
+                scenes.push(scene);
+            }
+        }
+        Ok(scenes)
+    }
+
+    fn remove(&self, slot: u16) -> Result<(), SceneError> {
+        match fs::remove_file(self.path(slot)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Linear crossfade between two looks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fade {
+    from: Look,
+    to: Look,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Fade {
+    pub fn new(from: Look, to: Look, started: Instant, duration: Duration) -> Self {
+        Self {
+            from,
+            to,
+            started,
+            duration,
+        }
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) >= self.duration
+    }
+
+    /// Fade time left at `now`.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.duration
+            .saturating_sub(now.saturating_duration_since(self.started))
+    }
+
+    /// Look at `now`; channels absent from the starting look fade up from zero.
+    pub fn look_at(&self, now: Instant) -> Look {
+        if self.is_done(now) {
+            return self.to.clone();
+        }
+        let progress =
+            now.saturating_duration_since(self.started).as_secs_f64() / self.duration.as_secs_f64();
+        self.to
+            .iter()
+            .map(|(universe, target)| {
+                let start = self.from.get(universe);
+                let channels = target
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &to)| {
+                        let from = start.and_then(|s| s.get(i)).copied().unwrap_or(0);
+                        let value = f64::from(from) + (f64::from(to) - f64::from(from)) * progress;
+                        value.round() as u16
+                    })
+                    .collect();
+                (*universe, channels)
+            })
+            .collect()
+    }
+}
+
+/// Node-side scene engine: tracks the live look, stores and recalls scenes, and serves
+/// the scene control ops.
+#[derive(Debug)]
+pub struct SceneEngine {
+    store: Arc<dyn SceneStore>,
+    live: Mutex<Look>,
+    fade: Mutex<Option<Fade>>,
+    /// Target look and fade time left of a halted recall.
+    halted: Mutex<Option<(Look, Duration)>>,
+}
+
+impl SceneEngine {
+    pub fn new(store: Arc<dyn SceneStore>) -> Self {
+        Self {
+            store,
+            live: Mutex::new(Look::new()),
+            fade: Mutex::new(None),
+            halted: Mutex::new(None),
+        }
+    }
+
+    /// Records channels received for `universe`; streamed frames cancel a running recall.
+    pub fn observe(&self, universe: UniverseId, channels: &[u16]) {
+        self.fade.lock().take();
+        self.halted.lock().take();
+        self.live.lock().insert(universe, channels.to_vec());
+    }
+
+    /// Saves the live look into `slot`, replacing any scene stored there.
+    pub fn capture(&self, slot: u16, name: Option<String>) -> Result<Scene, SceneError> {
+        let look = self.output(Instant::now());
+        if look.is_empty() {
+            return Err(SceneError::NoLook);
+        }
+        let scene = Scene {
+            slot,
+            name,
+            look,
+            captured_at_ms: ControlClient::now_ms(),
+        };
+        self.store.save(&scene)?;
+        Ok(scene)
+    }
+
+    pub fn list(&self) -> Result<Vec<SceneSummary>, SceneError> {
+        Ok(self.store.list()?.iter().map(SceneSummary::from).collect())
+    }
+
+    /// Starts a crossfade from the current output to the scene in `slot`.
+    pub fn recall(&self, slot: u16, fade: Duration) -> Result<(), SceneError> {
+        let scene = self.store.load(slot)?.ok_or(SceneError::EmptySlot(slot))?;
+        let now = Instant::now();
+        let from = self.output(now);
+        self.halted.lock().take();
+        *self.fade.lock() = Some(Fade::new(from, scene.look, now, fade));
+        Ok(())
+    }
+
+    /// Freezes a running recall at its look at `now`; returns whether one was running.
+    pub fn halt(&self, now: Instant) -> bool {
+        let Some(fade) = self.fade.lock().take() else {
+            return false;
+        };
+        if fade.is_done(now) {
+            *self.live.lock() = fade.to;
+            return false;
+        }
+        *self.live.lock() = fade.look_at(now);
+        let remaining = fade.remaining(now);
+        *self.halted.lock() = Some((fade.to, remaining));
+        true
+    }
+
+    /// Continues a halted recall for the fade time it had left; returns whether one was
+    /// halted.
+    pub fn resume(&self, now: Instant) -> bool {
+        let Some((to, remaining)) = self.halted.lock().take() else {
+            return false;
+        };
+        let from = self.output(now);
+        *self.fade.lock() = Some(Fade::new(from, to, now, remaining));
+        true
+    }
+
+    /// Look the node should output at `now`: the recall in progress, or the live look.
+    ///
+    /// A finished recall becomes the live look.
+    pub fn output(&self, now: Instant) -> Look {
+        let mut fade = self.fade.lock();
+        let Some(active) = fade.as_ref() else {
+            return self.live.lock().clone();
+        };
+        let look = active.look_at(now);
+        if active.is_done(now) {
+            fade.take();
+            self.live.lock().clone_from(&look);
+        }
+        look
+    }
+
+    /// Whether a recall crossfade is still running.
+    pub fn is_fading(&self, now: Instant) -> bool {
+        self.fade
+            .lock()
+            .as_ref()
+            .is_some_and(|fade| !fade.is_done(now))
+    }
+
+    /// Registers handlers for `store_scene`, `list_scenes`, and `recall_scene`.
+    ///
+    /// `list_scenes` answers with a JSON array of [`SceneSummary`] in the ack detail.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let engine = self.clone();
+        handlers.on(ControlOp::StoreScene, move |req: StoreScene| {
+            engine
+                .capture(req.slot, req.name)
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+        let engine = self.clone();
+        handlers.on(ControlOp::ListScenes, move |_: serde_json::Value| {
+            let scenes = engine.list().map_err(|e| e.to_string())?;
+            serde_json::to_string(&scenes)
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+        let engine = self.clone();
+        handlers.on(ControlOp::RecallScene, move |req: RecallScene| {
+            engine
+                .recall(req.slot, Duration::from_millis(req.fade_ms))
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+impl Default for SceneEngine {
+    fn default() -> Self {
+        Self::new(Arc::new(MemorySceneStore::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn fade_interpolates_towards_scene() {
+        let start = Instant::now();
+        let from = Look::from([(1, vec![0, 1000])]);
+        let to = Look::from([(1, vec![100, 0]), (2, vec![50])]);
+        let fade = Fade::new(from, to.clone(), start, Duration::from_secs(2));
+        assert_eq!(
+            fade.look_at(start + Duration::from_secs(1)),
+            Look::from([(1, vec![50, 500]), (2, vec![25])])
+        );
+        assert_eq!(fade.look_at(start + Duration::from_secs(3)), to);
+    }
+
+    #[test]
+    fn engine_captures_lists_and_recalls_from_file_store() {
+        let dir = std::env::temp_dir().join(format!("alpine-scenes-{}", Uuid::new_v4()));
+        let engine = SceneEngine::new(Arc::new(FileSceneStore::new(&dir)));
+        assert!(matches!(engine.capture(1, None), Err(SceneError::NoLook)));
+
+        engine.observe(0, &[255, 0, 0]);
+        engine.capture(1, Some("red".into())).unwrap();
+        engine.observe(0, &[0, 0, 255]);
+        engine.capture(7, None).unwrap();
+
+        let slots: Vec<u16> = engine.list().unwrap().iter().map(|s| s.slot).collect();
+        assert_eq!(slots, vec![1, 7]);
+        assert!(matches!(
+            engine.recall(3, Duration::ZERO),
+            Err(SceneError::EmptySlot(3))
+        ));
+
+        // A fresh engine on the same directory still has the scenes, as after a reboot.
+        let engine = SceneEngine::new(Arc::new(FileSceneStore::new(&dir)));
+        engine.recall(1, Duration::ZERO).unwrap();
+        assert_eq!(
+            engine.output(Instant::now()),
+            Look::from([(0, vec![255, 0, 0])])
+        );
+        assert!(!engine.is_fading(Instant::now()));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn halted_recall_holds_and_resumes_with_time_left() {
+        let engine = SceneEngine::default();
+        engine.observe(0, &[200]);
+        engine.capture(1, None).unwrap();
+        engine.observe(0, &[0]);
+        engine.recall(1, Duration::from_secs(60)).unwrap();
+
+        let start = Instant::now();
+        assert!(engine.halt(start + Duration::from_secs(30)));
+        let held = engine.output(start + Duration::from_secs(45));
+        assert!(!engine.is_fading(start + Duration::from_secs(45)));
+        assert!((95..=105).contains(&held[&0][0]), "{held:?}");
+
+        let resumed = start + Duration::from_secs(50);
+        assert!(engine.resume(resumed));
+        assert!(engine.is_fading(resumed + Duration::from_secs(29)));
+        assert_eq!(
+            engine.output(resumed + Duration::from_secs(31)),
+            Look::from([(0, vec![200])])
+        );
+        assert!(!engine.resume(resumed));
+    }
+}
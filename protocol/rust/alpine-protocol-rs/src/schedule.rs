@@ -0,0 +1,526 @@
+//! Time-of-day scheduling on nodes.
+//!
+//! A [`Schedule`] maps cron-like triggers (a wall-clock time, or sunrise and sunset at the
+//! installation's location) on selected weekdays to scene recalls or intensity master
+//! fades. Controllers replace it with `set_schedule` and read it back with
+//! `get_schedule`; the node then runs it on its own clock, so architectural installs keep
+//! their daily behaviour without a permanently connected controller.
+
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::clock::NodeClock;
+use crate::codec;
+use crate::control::ControlHandlers;
+use crate::messages::ControlOp;
+use crate::scene::{Look, SceneEngine};
+
+const MS_PER_MINUTE: i64 = 60_000;
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// Weekday bitmask, bit 0 = Monday through bit 6 = Sunday.
+pub const EVERY_DAY: u8 = 0x7f;
+
+/// When an entry fires, in the schedule's local time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "at", rename_all = "snake_case")]
+pub enum Trigger {
+    Time {
+        hour: u8,
+        minute: u8,
+    },
+    /// Minutes relative to sunrise; needs a [`Location`].
+    Sunrise {
+        #[serde(default)]
+        offset_min: i16,
+    },
+    /// Minutes relative to sunset; needs a [`Location`].
+    Sunset {
+        #[serde(default)]
+        offset_min: i16,
+    },
+}
+
+/// What an entry does when it fires.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    RecallScene {
+        slot: u16,
+        #[serde(default)]
+        fade_ms: u64,
+    },
+    /// Fades the intensity master to `percent` (0-100).
+    Master {
+        percent: u8,
+        #[serde(default)]
+        fade_ms: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScheduleEntry {
+    pub trigger: Trigger,
+    /// Weekdays the entry fires on; see [`EVERY_DAY`].
+    #[serde(default = "every_day")]
+    pub days: u8,
+    pub action: Action,
+}
+
+fn every_day() -> u8 {
+    EVERY_DAY
+}
+
+/// Installation position used for sunrise and sunset, in degrees (east and north positive).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Payload of `set_schedule` and the `get_schedule` ack detail.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct Schedule {
+    /// Offset of local time from UTC; nodes have no time zone database.
+    #[serde(default)]
+    pub utc_offset_min: i16,
+    #[serde(default)]
+    pub location: Option<Location>,
+    #[serde(default)]
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl Schedule {
+    /// Unix time in ms at which `entry` fires on the local day `day` (days since the
+    /// epoch), or `None` when it does not fire that day.
+    fn fires_at(&self, entry: &ScheduleEntry, day: i64) -> Option<i64> {
+        // 1970-01-01 was a Thursday.
+        let weekday = (day + 3).rem_euclid(7);
+        if entry.days & (1 << weekday) == 0 {
+            return None;
+        }
+        let midnight = day * MS_PER_DAY - i64::from(self.utc_offset_min) * MS_PER_MINUTE;
+        match entry.trigger {
+            Trigger::Time { hour, minute } => {
+                Some(midnight + (i64::from(hour) * 60 + i64::from(minute)) * MS_PER_MINUTE)
+            }
+            Trigger::Sunrise { offset_min } => {
+                let (rise, _) = sun_times(self.location?, day)?;
+                Some(rise + i64::from(offset_min) * MS_PER_MINUTE)
+            }
+            Trigger::Sunset { offset_min } => {
+                let (_, set) = sun_times(self.location?, day)?;
+                Some(set + i64::from(offset_min) * MS_PER_MINUTE)
+            }
+        }
+    }
+
+    fn local_day(&self, unix_ms: i64) -> i64 {
+        (unix_ms + i64::from(self.utc_offset_min) * MS_PER_MINUTE).div_euclid(MS_PER_DAY)
+    }
+}
+
+/// Sunrise and sunset as Unix ms for `day` (days since the epoch), or `None` during polar
+/// day or night.
+///
+/// Uses the standard sunrise equation, accurate to a minute or two at inhabited latitudes.
+pub fn sun_times(location: Location, day: i64) -> Option<(i64, i64)> {
+    let rad = PI / 180.0;
+    // Days since 2000-01-01 12:00 (J2000), at local solar noon.
+    let n = (day - 10_957) as f64;
+    let mean_noon = n - location.longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * mean_noon).rem_euclid(360.0);
+    let center = 1.9148 * (anomaly * rad).sin()
+        + 0.02 * (2.0 * anomaly * rad).sin()
+        + 0.0003 * (3.0 * anomaly * rad).sin();
+    let ecliptic = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let transit =
+        mean_noon + 0.0053 * (anomaly * rad).sin() - 0.0069 * (2.0 * ecliptic * rad).sin();
+    let declination = ((ecliptic * rad).sin() * (23.4397 * rad).sin()).asin();
+    let latitude = location.latitude * rad;
+    let cos_hour = ((-0.833 * rad).sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour) {
+        return None;
+    }
+    let half_day = cos_hour.acos() / rad / 360.0;
+    // J2000 noon is 10957.5 days after the Unix epoch.
+    let to_unix = |days: f64| ((days + 10_957.5) * MS_PER_DAY as f64).round() as i64;
+    Some((to_unix(transit - half_day), to_unix(transit + half_day)))
+}
+
+/// Failures applying or storing schedules.
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("invalid schedule entry {index}: {reason}")]
+    Invalid { index: usize, reason: &'static str },
+    #[error("schedule storage failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("schedule encoding failed: {0}")]
+    Codec(String),
+}
+
+/// Persistence for the node's schedule.
+pub trait ScheduleStore: Send + Sync + std::fmt::Debug {
+    fn save(&self, schedule: &Schedule) -> Result<(), ScheduleError>;
+    fn load(&self) -> Result<Schedule, ScheduleError>;
+}
+
+/// Process-local store; the schedule does not survive a restart.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryScheduleStore {
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl MemoryScheduleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ScheduleStore for MemoryScheduleStore {
+    fn save(&self, schedule: &Schedule) -> Result<(), ScheduleError> {
+        self.schedule.lock().clone_from(schedule);
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Schedule, ScheduleError> {
+        Ok(self.schedule.lock().clone())
+    }
+}
+
+/// Stores the schedule as one CBOR file, so it survives power loss.
+#[derive(Debug, Clone)]
+pub struct FileScheduleStore {
+    path: PathBuf,
+}
+
+impl FileScheduleStore {
+    /// Uses the file at `path`, creating its directory on first save.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ScheduleStore for FileScheduleStore {
+    fn save(&self, schedule: &Schedule) -> Result<(), ScheduleError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let bytes = codec::to_vec(schedule).map_err(|e| ScheduleError::Codec(e.to_string()))?;
+        // Write then rename so a power cut mid-save keeps the previous schedule.
+        let staging = self.path.with_extension("tmp");
+        fs::write(&staging, bytes)?;
+        fs::rename(staging, &self.path)?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Schedule, ScheduleError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => {
+                codec::decode_untrusted(&bytes).map_err(|e| ScheduleError::Codec(e.to_string()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Schedule::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Intensity master fade.
+#[derive(Debug, Clone, Copy)]
+struct MasterFade {
+    from: u8,
+    to: u8,
+    started: Instant,
+    duration: Duration,
+}
+
+impl MasterFade {
+    fn level_at(&self, now: Instant) -> u8 {
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed >= self.duration {
+            return self.to;
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        let level = f64::from(self.from) + (f64::from(self.to) - f64::from(self.from)) * progress;
+        level.round() as u8
+    }
+}
+
+/// Node-side scheduler: fires schedule entries as the wall clock passes them and tracks
+/// the intensity master.
+#[derive(Debug)]
+pub struct Scheduler {
+    store: Arc<dyn ScheduleStore>,
+    scenes: Option<Arc<SceneEngine>>,
+    schedule: Mutex<Schedule>,
+    master: Mutex<MasterFade>,
+    last_tick_ms: Mutex<Option<i64>>,
+}
+
+impl Scheduler {
+    /// Loads the persisted schedule. Without `scenes`, schedules that recall scenes are
+    /// refused.
+    pub fn open(
+        store: Arc<dyn ScheduleStore>,
+        scenes: Option<Arc<SceneEngine>>,
+    ) -> Result<Self, ScheduleError> {
+        let schedule = store.load()?;
+        Ok(Self {
+            store,
+            scenes,
+            schedule: Mutex::new(schedule),
+            master: Mutex::new(MasterFade {
+                from: 100,
+                to: 100,
+                started: Instant::now(),
+                duration: Duration::ZERO,
+            }),
+            last_tick_ms: Mutex::new(None),
+        })
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        self.schedule.lock().clone()
+    }
+
+    /// Validates and stores `schedule`, replacing the current one.
+    pub fn set(&self, schedule: Schedule) -> Result<(), ScheduleError> {
+        for (index, entry) in schedule.entries.iter().enumerate() {
+            let reason = match (entry.trigger, entry.action) {
+                (Trigger::Time { hour, minute }, _) if hour > 23 || minute > 59 => {
+                    "time out of range"
+                }
+                (Trigger::Sunrise { .. } | Trigger::Sunset { .. }, _)
+                    if schedule.location.is_none() =>
+                {
+                    "sun trigger without a location"
+                }
+                (_, Action::Master { percent, .. }) if percent > 100 => "master above 100%",
+                (_, Action::RecallScene { .. }) if self.scenes.is_none() => {
+                    "node does not store scenes"
+                }
+                _ if entry.days & EVERY_DAY == 0 => "no weekdays selected",
+                _ => continue,
+            };
+            return Err(ScheduleError::Invalid { index, reason });
+        }
+        self.store.save(&schedule)?;
+        *self.schedule.lock() = schedule;
+        Ok(())
+    }
+
+    /// Fires entries whose time fell after the previous tick and up to `unix_ms`, and
+    /// returns them.
+    ///
+    /// The first tick only sets the reference point, as does a clock jump of more than a
+    /// day, so a reboot or clock correction never replays a day's worth of cues.
+    pub fn tick(&self, unix_ms: u64, now: Instant) -> Vec<Action> {
+        let unix_ms = unix_ms as i64;
+        let previous = self.last_tick_ms.lock().replace(unix_ms);
+        let Some(previous) = previous else {
+            return Vec::new();
+        };
+        if unix_ms <= previous || unix_ms - previous > MS_PER_DAY {
+            return Vec::new();
+        }
+        let schedule = self.schedule.lock().clone();
+        let mut due: Vec<(i64, Action)> = Vec::new();
+        for day in schedule.local_day(previous) - 1..=schedule.local_day(unix_ms) + 1 {
+            for entry in &schedule.entries {
+                if let Some(at) = schedule.fires_at(entry, day) {
+                    if at > previous && at <= unix_ms {
+                        due.push((at, entry.action));
+                    }
+                }
+            }
+        }
+        due.sort_by_key(|(at, _)| *at);
+        due.into_iter()
+            .map(|(_, action)| {
+                self.apply(action, now);
+                action
+            })
+            .collect()
+    }
+
+    fn apply(&self, action: Action, now: Instant) {
+        match action {
+            Action::RecallScene { slot, fade_ms } => {
+                if let Some(scenes) = &self.scenes {
+                    // An emptied slot skips the cue; the rest of the schedule carries on.
+                    let _ = scenes.recall(slot, Duration::from_millis(fade_ms));
+                }
+            }
+            Action::Master { percent, fade_ms } => {
+                let mut master = self.master.lock();
+                *master = MasterFade {
+                    from: master.level_at(now),
+                    to: percent.min(100),
+                    started: now,
+                    duration: Duration::from_millis(fade_ms),
+                };
+            }
+        }
+    }
+
+    /// Intensity master at `now`, in percent.
+    pub fn master(&self, now: Instant) -> u8 {
+        self.master.lock().level_at(now)
+    }
+
+    /// Scales every channel in `look` by the intensity master at `now`.
+    pub fn apply_master(&self, look: &mut Look, now: Instant) {
+        let master = u32::from(self.master(now));
+        for channels in look.values_mut() {
+            for value in channels.iter_mut() {
+                *value = (u32::from(*value) * master / 100) as u16;
+            }
+        }
+    }
+
+    /// Ticks on the system clock every `period` until the task is dropped.
+    pub async fn run(self: Arc<Self>, period: Duration) {
+        self.run_on(Arc::new(NodeClock::default()), period).await
+    }
+
+    /// Ticks on `clock` every `period` until the task is dropped, so entries follow the
+    /// time a controller set with `time_sync`.
+    pub async fn run_on(self: Arc<Self>, clock: Arc<NodeClock>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            self.tick(clock.now_ms(), Instant::now());
+        }
+    }
+
+    /// Registers handlers for `set_schedule` and `get_schedule`.
+    ///
+    /// `get_schedule` answers with the schedule as JSON in the ack detail.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let scheduler = self.clone();
+        handlers.on(ControlOp::SetSchedule, move |schedule: Schedule| {
+            scheduler
+                .set(schedule)
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+        let scheduler = self.clone();
+        handlers.on(ControlOp::GetSchedule, move |_: serde_json::Value| {
+            serde_json::to_string(&scheduler.schedule())
+                .map(Some)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::MemorySceneStore;
+
+    // 2024-06-20 (a Thursday) 00:00 UTC, as days and ms since the epoch.
+    const DAY: i64 = 19_894;
+    const MIDNIGHT: u64 = DAY as u64 * MS_PER_DAY as u64;
+
+    fn at(hour: u64, minute: u64) -> u64 {
+        MIDNIGHT + (hour * 60 + minute) * MS_PER_MINUTE as u64
+    }
+
+    #[test]
+    fn sunrise_and_sunset_match_reference_times() {
+        // London around the June solstice: sunrise ~03:43 UTC, sunset ~20:21 UTC.
+        let london = Location {
+            latitude: 51.5074,
+            longitude: -0.1278,
+        };
+        let (rise, set) = sun_times(london, DAY).unwrap();
+        assert!((rise - at(3, 43) as i64).abs() < 3 * MS_PER_MINUTE);
+        assert!((set - at(20, 21) as i64).abs() < 3 * MS_PER_MINUTE);
+        let tromso = Location {
+            latitude: 69.65,
+            longitude: 18.96,
+        };
+        assert_eq!(sun_times(tromso, DAY), None);
+    }
+
+    #[test]
+    fn scheduler_fires_entries_as_the_clock_passes_them() {
+        let scenes = Arc::new(SceneEngine::new(Arc::new(MemorySceneStore::new())));
+        scenes.observe(0, &[200]);
+        scenes.capture(1, None).unwrap();
+        let scheduler =
+            Scheduler::open(Arc::new(MemoryScheduleStore::new()), Some(scenes)).unwrap();
+        let evening = ScheduleEntry {
+            trigger: Trigger::Time {
+                hour: 19,
+                minute: 30,
+            },
+            days: EVERY_DAY,
+            action: Action::RecallScene {
+                slot: 1,
+                fade_ms: 0,
+            },
+        };
+        let weekend_dim = ScheduleEntry {
+            trigger: Trigger::Time {
+                hour: 20,
+                minute: 0,
+            },
+            // Saturday and Sunday only.
+            days: 0b110_0000,
+            action: Action::Master {
+                percent: 50,
+                fade_ms: 0,
+            },
+        };
+        assert!(matches!(
+            scheduler.set(Schedule {
+                entries: vec![ScheduleEntry {
+                    trigger: Trigger::Sunset { offset_min: 0 },
+                    ..evening.clone()
+                }],
+                ..Schedule::default()
+            }),
+            Err(ScheduleError::Invalid { index: 0, .. })
+        ));
+        scheduler
+            .set(Schedule {
+                // UTC+1, so 19:30 local is 18:30 UTC.
+                utc_offset_min: 60,
+                location: None,
+                entries: vec![evening, weekend_dim],
+            })
+            .unwrap();
+
+        let now = Instant::now();
+        assert!(scheduler.tick(at(18, 0), now).is_empty());
+        assert!(scheduler.tick(at(18, 29), now).is_empty());
+        assert_eq!(
+            scheduler.tick(at(18, 31), now),
+            vec![Action::RecallScene {
+                slot: 1,
+                fade_ms: 0
+            }]
+        );
+        // Thursday, so the weekend entry stays quiet.
+        assert!(scheduler.tick(at(19, 30), now).is_empty());
+        assert_eq!(scheduler.master(now), 100);
+
+        // Saturday evening.
+        let saturday = at(19, 1) + 2 * MS_PER_DAY as u64;
+        assert!(scheduler.tick(saturday - 120_000, now).is_empty());
+        assert_eq!(scheduler.tick(saturday, now).len(), 1);
+        let mut look = Look::from([(0, vec![200, 101])]);
+        scheduler.apply_master(&mut look, now);
+        assert_eq!(look, Look::from([(0, vec![100, 50])]));
+    }
+}
@@ -0,0 +1,273 @@
+//! Session lifetime limits.
+//!
+//! A session may cap its age and the number of frames carried under one set of keys.
+//! Crossing the warning threshold of either limit raises a single [`LimitWarning`] so the
+//! controller can re-handshake during a quiet moment; once a limit is reached the session
+//! refuses further frames until it is replaced. State can be carried to the replacement
+//! session with a [`super::migration::MigrationTicket`].
+
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+/// Rejected [`SessionLimits`] values.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LimitsError {
+    #[error("{0} must be greater than zero")]
+    Zero(&'static str),
+    #[error("warning threshold {0}% must be between 1 and 99")]
+    WarnPercent(u8),
+}
+
+/// The lifetime limit a warning or refusal refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionLimit {
+    /// Time since the session keys were installed.
+    Age,
+    /// Frames sent or received under the current keys.
+    Frames,
+}
+
+impl SessionLimit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionLimit::Age => "age",
+            SessionLimit::Frames => "frames",
+        }
+    }
+}
+
+/// One-time notice that a session crossed the warning threshold of a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitWarning {
+    Age { remaining: Duration },
+    Frames { remaining: u64 },
+}
+
+impl LimitWarning {
+    pub fn limit(&self) -> SessionLimit {
+        match self {
+            LimitWarning::Age { .. } => SessionLimit::Age,
+            LimitWarning::Frames { .. } => SessionLimit::Frames,
+        }
+    }
+}
+
+/// Maximum session age and frames per key; both unlimited by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionLimits {
+    max_age: Option<Duration>,
+    max_frames_per_key: Option<u64>,
+    warn_percent: u8,
+}
+
+impl SessionLimits {
+    /// Share of a limit consumed before [`LimitWarning`] fires, unless overridden.
+    pub const DEFAULT_WARN_PERCENT: u8 = 80;
+
+    /// Builds limits that warn at `warn_percent` of either bound.
+    ///
+    /// `None` leaves that bound unlimited; zero bounds are rejected.
+    pub fn new(
+        max_age: Option<Duration>,
+        max_frames_per_key: Option<u64>,
+        warn_percent: u8,
+    ) -> Result<Self, LimitsError> {
+        if max_age.is_some_and(|age| age.is_zero()) {
+            return Err(LimitsError::Zero("max age"));
+        }
+        if max_frames_per_key == Some(0) {
+            return Err(LimitsError::Zero("max frames per key"));
+        }
+        if !(1..=99).contains(&warn_percent) {
+            return Err(LimitsError::WarnPercent(warn_percent));
+        }
+        Ok(Self {
+            max_age,
+            max_frames_per_key,
+            warn_percent,
+        })
+    }
+
+    /// No age or frame limit.
+    pub fn unlimited() -> Self {
+        Self {
+            max_age: None,
+            max_frames_per_key: None,
+            warn_percent: Self::DEFAULT_WARN_PERCENT,
+        }
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    pub fn max_frames_per_key(&self) -> Option<u64> {
+        self.max_frames_per_key
+    }
+
+    pub fn warn_percent(&self) -> u8 {
+        self.warn_percent
+    }
+
+    fn age_threshold(&self) -> Option<Duration> {
+        self.max_age
+            .map(|age| age.mul_f64(f64::from(self.warn_percent) / 100.0))
+    }
+
+    fn frame_threshold(&self) -> Option<u64> {
+        self.max_frames_per_key
+            .map(|max| (u128::from(max) * u128::from(self.warn_percent) / 100) as u64)
+    }
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+/// Use of the current session keys, reset whenever new keys are installed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KeyUsage {
+    keyed_at: Instant,
+    frames: u64,
+    age_warned: bool,
+    frames_warned: bool,
+}
+
+impl KeyUsage {
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            keyed_at: now,
+            frames: 0,
+            age_warned: false,
+            frames_warned: false,
+        }
+    }
+
+    pub(crate) fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.keyed_at)
+    }
+
+    pub(crate) fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the first limit already reached, if any.
+    pub(crate) fn exceeded(&self, limits: &SessionLimits, now: Instant) -> Option<SessionLimit> {
+        if limits.max_age.is_some_and(|max| self.age(now) >= max) {
+            return Some(SessionLimit::Age);
+        }
+        if limits
+            .max_frames_per_key
+            .is_some_and(|max| self.frames >= max)
+        {
+            return Some(SessionLimit::Frames);
+        }
+        None
+    }
+
+    /// Counts one frame, or refuses it once a limit is reached.
+    ///
+    /// Each limit's warning is returned at most once per key.
+    pub(crate) fn record_frame(
+        &mut self,
+        limits: &SessionLimits,
+        now: Instant,
+    ) -> Result<Option<LimitWarning>, SessionLimit> {
+        if let Some(limit) = self.exceeded(limits, now) {
+            return Err(limit);
+        }
+        self.frames += 1;
+
+        if let (Some(max), Some(threshold)) = (limits.max_frames_per_key, limits.frame_threshold())
+        {
+            if !self.frames_warned && self.frames >= threshold {
+                self.frames_warned = true;
+                return Ok(Some(LimitWarning::Frames {
+                    remaining: max - self.frames,
+                }));
+            }
+        }
+        if let (Some(max), Some(threshold)) = (limits.max_age, limits.age_threshold()) {
+            let age = self.age(now);
+            if !self.age_warned && age >= threshold {
+                self.age_warned = true;
+                return Ok(Some(LimitWarning::Age {
+                    remaining: max.saturating_sub(age),
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_zero_bounds_and_bad_threshold() {
+        assert_eq!(
+            SessionLimits::new(Some(Duration::ZERO), None, 80),
+            Err(LimitsError::Zero("max age"))
+        );
+        assert_eq!(
+            SessionLimits::new(None, Some(0), 80),
+            Err(LimitsError::Zero("max frames per key"))
+        );
+        assert_eq!(
+            SessionLimits::new(None, Some(10), 100),
+            Err(LimitsError::WarnPercent(100))
+        );
+    }
+
+    #[test]
+    fn frame_limit_warns_once_then_refuses() {
+        let limits = SessionLimits::new(None, Some(10), 80).unwrap();
+        let now = Instant::now();
+        let mut usage = KeyUsage::new(now);
+        let mut warnings = Vec::new();
+        for _ in 0..10 {
+            if let Some(warning) = usage.record_frame(&limits, now).unwrap() {
+                warnings.push(warning);
+            }
+        }
+        assert_eq!(warnings, vec![LimitWarning::Frames { remaining: 2 }]);
+        assert_eq!(usage.record_frame(&limits, now), Err(SessionLimit::Frames));
+        assert_eq!(usage.frames(), 10);
+    }
+
+    #[test]
+    fn age_limit_warns_before_expiry() {
+        let limits = SessionLimits::new(Some(Duration::from_secs(100)), None, 90).unwrap();
+        let start = Instant::now();
+        let mut usage = KeyUsage::new(start);
+        assert_eq!(usage.record_frame(&limits, start), Ok(None));
+        assert_eq!(
+            usage.record_frame(&limits, start + Duration::from_secs(95)),
+            Ok(Some(LimitWarning::Age {
+                remaining: Duration::from_secs(5)
+            }))
+        );
+        assert_eq!(
+            usage.record_frame(&limits, start + Duration::from_secs(96)),
+            Ok(None)
+        );
+        assert_eq!(
+            usage.record_frame(&limits, start + Duration::from_secs(100)),
+            Err(SessionLimit::Age)
+        );
+    }
+
+    #[test]
+    fn unlimited_never_refuses() {
+        let limits = SessionLimits::unlimited();
+        let start = Instant::now();
+        let mut usage = KeyUsage::new(start);
+        let later = start + Duration::from_secs(86_400 * 365);
+        assert_eq!(usage.record_frame(&limits, later), Ok(None));
+        assert_eq!(usage.exceeded(&limits, later), None);
+    }
+}
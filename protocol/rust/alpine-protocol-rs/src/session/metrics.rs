@@ -0,0 +1,275 @@
+//! Per-session counters and timings for monitoring.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::state::SessionState;
+use crate::messages::KeepaliveStats;
+
+/// Round-trip time samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RttStats {
+    pub samples: u64,
+    pub last: Option<Duration>,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    pub mean: Option<Duration>,
+}
+
+impl RttStats {
+    pub(crate) fn record(&mut self, rtt: Duration, total: Duration) {
+        self.samples += 1;
+        self.last = Some(rtt);
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+        self.mean = Some(total / u32::try_from(self.samples).unwrap_or(u32::MAX));
+    }
+}
+
+/// Point-in-time view of a session, returned by [`super::AlnpSession::metrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionMetrics {
+    /// Current state name; see [`SessionState::name`].
+    pub state: &'static str,
+    /// Time the session has been ready, up to when it failed or closed.
+    pub uptime: Duration,
+    /// Cumulative time spent in each state, including the current one.
+    pub time_in_state: BTreeMap<&'static str, Duration>,
+    /// Time from the first handshake message to a ready session.
+    pub handshake_duration: Option<Duration>,
+    /// Round trips of liveness traffic on the control channel.
+    pub keepalive_rtt: RttStats,
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    /// Received frames that arrived after their `deadline_us`.
+    pub late_frames: u64,
+    /// Received frames refused by the session's frame-rate quota.
+    pub throttled_frames: u64,
+    /// Control envelopes refused by the session's control quota.
+    pub throttled_control: u64,
+    /// Fragmented datagrams dropped because a piece did not arrive in time.
+    pub reassembly_timeouts: u64,
+    /// Frames the peer's last keepalive stats say it sent that never arrived, counting
+    /// those still in flight then.
+    pub frames_lost: u64,
+    /// Stream counters the peer last piggybacked on a keepalive.
+    pub peer_stats: Option<KeepaliveStats>,
+    /// Reason the session last failed, kept after it is closed.
+    pub last_error: Option<String>,
+}
+
+/// Accumulates the values behind [`SessionMetrics`].
+#[derive(Debug, Clone)]
+pub(crate) struct MetricsRecorder {
+    state: &'static str,
+    entered: Instant,
+    time_in_state: BTreeMap<&'static str, Duration>,
+    handshake_started: Option<Instant>,
+    handshake_duration: Option<Duration>,
+    ready_at: Option<Instant>,
+    ended_at: Option<Instant>,
+    keepalive_rtt: RttStats,
+    rtt_total: Duration,
+    frames_sent: u64,
+    frames_received: u64,
+    late_frames: u64,
+    throttled_frames: u64,
+    throttled_control: u64,
+    reassembly_timeouts: u64,
+    last_frame_us: Option<u64>,
+    frames_lost: u64,
+    peer_stats: Option<KeepaliveStats>,
+    last_error: Option<String>,
+}
+
+impl MetricsRecorder {
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            state: SessionState::Init.name(),
+            entered: now,
+            time_in_state: BTreeMap::new(),
+            handshake_started: None,
+            handshake_duration: None,
+            ready_at: None,
+            ended_at: None,
+            keepalive_rtt: RttStats::default(),
+            rtt_total: Duration::ZERO,
+            frames_sent: 0,
+            frames_received: 0,
+            late_frames: 0,
+            throttled_frames: 0,
+            throttled_control: 0,
+            reassembly_timeouts: 0,
+            last_frame_us: None,
+            frames_lost: 0,
+            peer_stats: None,
+            last_error: None,
+        }
+    }
+
+    /// Records a move into `state`.
+    pub(crate) fn enter(&mut self, state: &SessionState, now: Instant) {
+        *self.time_in_state.entry(self.state).or_default() +=
+            now.saturating_duration_since(self.entered);
+        self.state = state.name();
+        self.entered = now;
+        match state {
+            SessionState::Handshake => self.handshake_started = Some(now),
+            SessionState::Ready { .. } => {
+                self.ready_at = Some(now);
+                self.handshake_duration = self
+                    .handshake_started
+                    .map(|started| now.saturating_duration_since(started));
+            }
+            SessionState::Failed(reason) => {
+                self.last_error = Some(reason.clone());
+                self.ended_at.get_or_insert(now);
+            }
+            SessionState::Closed => {
+                self.ended_at.get_or_insert(now);
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn keepalive_rtt(&mut self, rtt: Duration) {
+        self.rtt_total = self.rtt_total.saturating_add(rtt);
+        self.keepalive_rtt.record(rtt, self.rtt_total);
+    }
+
+    pub(crate) fn frame_sent(&mut self, timestamp_us: Option<u64>) {
+        self.frames_sent = self.frames_sent.saturating_add(1);
+        if timestamp_us.is_some() {
+            self.last_frame_us = timestamp_us;
+        }
+    }
+
+    pub(crate) fn frame_received(&mut self) {
+        self.frames_received = self.frames_received.saturating_add(1);
+    }
+
+    pub(crate) fn frame_late(&mut self) {
+        self.late_frames = self.late_frames.saturating_add(1);
+    }
+
+    pub(crate) fn frame_throttled(&mut self) {
+        self.throttled_frames = self.throttled_frames.saturating_add(1);
+    }
+
+    pub(crate) fn control_throttled(&mut self) {
+        self.throttled_control = self.throttled_control.saturating_add(1);
+    }
+
+    pub(crate) fn reassembly_timed_out(&mut self, datagrams: u64) {
+        self.reassembly_timeouts = self.reassembly_timeouts.saturating_add(datagrams);
+    }
+
+    /// Records the peer's keepalive stats; frames it sent beyond those received here are
+    /// counted as lost.
+    pub(crate) fn peer_stats(&mut self, stats: KeepaliveStats) {
+        self.frames_lost = stats.frames_sent.saturating_sub(self.frames_received);
+        self.peer_stats = Some(stats);
+    }
+
+    pub(crate) fn keepalive_stats(&self) -> KeepaliveStats {
+        KeepaliveStats {
+            frames_sent: self.frames_sent,
+            frames_received: self.frames_received,
+            last_frame_us: self.last_frame_us,
+            frames_lost: self.frames_lost,
+        }
+    }
+
+    pub(crate) fn snapshot(&self, now: Instant) -> SessionMetrics {
+        let mut time_in_state = self.time_in_state.clone();
+        *time_in_state.entry(self.state).or_default() +=
+            now.saturating_duration_since(self.entered);
+        SessionMetrics {
+            state: self.state,
+            uptime: self
+                .ready_at
+                .map(|ready| {
+                    self.ended_at
+                        .unwrap_or(now)
+                        .saturating_duration_since(ready)
+                })
+                .unwrap_or_default(),
+            time_in_state,
+            handshake_duration: self.handshake_duration,
+            keepalive_rtt: self.keepalive_rtt,
+            frames_sent: self.frames_sent,
+            frames_received: self.frames_received,
+            late_frames: self.late_frames,
+            throttled_frames: self.throttled_frames,
+            throttled_control: self.throttled_control,
+            reassembly_timeouts: self.reassembly_timeouts,
+            frames_lost: self.frames_lost,
+            peer_stats: self.peer_stats,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_state_time_and_handshake_duration() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut recorder = MetricsRecorder::new(start);
+        recorder.enter(&SessionState::Handshake, at(10));
+        recorder.enter(&SessionState::Authenticated { since: at(40) }, at(40));
+        recorder.enter(&SessionState::Ready { since: at(50) }, at(50));
+        recorder.enter(&SessionState::Failed("session timeout".into()), at(250));
+        recorder.enter(&SessionState::Closed, at(300));
+
+        let metrics = recorder.snapshot(at(400));
+        assert_eq!(metrics.state, "closed");
+        assert_eq!(metrics.handshake_duration, Some(Duration::from_millis(40)));
+        assert_eq!(metrics.uptime, Duration::from_millis(200));
+        assert_eq!(metrics.time_in_state["ready"], Duration::from_millis(200));
+        assert_eq!(metrics.time_in_state["closed"], Duration::from_millis(100));
+        assert_eq!(metrics.last_error.as_deref(), Some("session timeout"));
+    }
+
+    #[test]
+    fn rtt_stats_track_min_max_and_mean() {
+        let mut recorder = MetricsRecorder::new(Instant::now());
+        for ms in [30, 10, 20] {
+            recorder.keepalive_rtt(Duration::from_millis(ms));
+        }
+        let rtt = recorder.snapshot(Instant::now()).keepalive_rtt;
+        assert_eq!(rtt.samples, 3);
+        assert_eq!(rtt.last, Some(Duration::from_millis(20)));
+        assert_eq!(rtt.min, Some(Duration::from_millis(10)));
+        assert_eq!(rtt.max, Some(Duration::from_millis(30)));
+        assert_eq!(rtt.mean, Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn peer_keepalive_stats_reveal_lost_frames() {
+        let mut node = MetricsRecorder::new(Instant::now());
+        for _ in 0..7 {
+            node.frame_received();
+        }
+        let mut controller = MetricsRecorder::new(Instant::now());
+        for timestamp_us in 1..=10 {
+            controller.frame_sent(Some(timestamp_us));
+        }
+        controller.frame_sent(None);
+        let sent = controller.keepalive_stats();
+        assert_eq!((sent.frames_sent, sent.last_frame_us), (11, Some(10)));
+
+        node.peer_stats(sent);
+        let metrics = node.snapshot(Instant::now());
+        assert_eq!(metrics.frames_lost, 4);
+        assert_eq!(metrics.peer_stats, Some(sent));
+
+        // The node's answer tells the controller what it lost.
+        controller.peer_stats(node.keepalive_stats());
+        let peer = controller.snapshot(Instant::now()).peer_stats.unwrap();
+        assert_eq!((peer.frames_received, peer.frames_lost), (7, 4));
+    }
+}
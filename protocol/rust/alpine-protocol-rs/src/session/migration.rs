@@ -0,0 +1,264 @@
+//! Controller handoff: moves a running show from one controller session to another.
+//!
+//! The outgoing controller captures a [`SessionSnapshot`] and seals it into a
+//! [`MigrationTicket`] using a key derived from its own session. The incoming controller
+//! forwards the ticket to the node inside a `ControlOp::Migrate` envelope on its freshly
+//! established session. Only the node, which still holds the outgoing session keys, can
+//! open the ticket, so a successful open proves the previous controller authorized the
+//! handoff. The node then applies the snapshot to the new session and keeps streaming.
+
+use std::collections::BTreeMap;
+
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::{AlnpSession, FramePriorities, JitterStrategy};
+use crate::codec;
+use crate::control::ControlClient;
+use crate::crypto::{self, SessionKeys};
+use crate::handshake::HandshakeError;
+use crate::messages::UniverseId;
+use crate::profile::{StreamIntent, StreamProfile};
+
+/// Tickets older than this are rejected to limit replay of a captured handoff.
+pub const MIGRATION_TICKET_TTL_MS: u64 = 30_000;
+
+/// Session state carried across a controller handoff.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionSnapshot {
+    pub from_session: Uuid,
+    pub intent: StreamIntent,
+    pub latency_weight: u8,
+    pub resilience_weight: u8,
+    pub config_id: String,
+    pub jitter: JitterStrategy,
+    pub streaming: bool,
+    /// Next control sequence the outgoing controller would have used.
+    pub control_seq: u64,
+    /// Default frame priority.
+    pub priority: u8,
+    /// Per-universe priority overrides.
+    pub universe_priorities: BTreeMap<UniverseId, u8>,
+    pub issued_at_ms: u64,
+}
+
+impl SessionSnapshot {
+    /// Captures the profile, jitter, and streaming state of an established session.
+    pub fn capture(
+        session: &AlnpSession,
+        control_seq: u64,
+        priority: u8,
+        universe_priorities: BTreeMap<UniverseId, u8>,
+    ) -> Result<Self, HandshakeError> {
+        let established = session
+            .established()
+            .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+        let profile = session
+            .compiled_profile()
+            .ok_or_else(|| HandshakeError::Protocol("no stream profile to migrate".into()))?;
+        Ok(Self {
+            from_session: established.session_id,
+            intent: profile.intent(),
+            latency_weight: profile.latency_weight(),
+            resilience_weight: profile.resilience_weight(),
+            config_id: profile.config_id().to_string(),
+            jitter: session.jitter_strategy(),
+            streaming: session.profile_locked(),
+            control_seq,
+            priority,
+            universe_priorities,
+            issued_at_ms: ControlClient::now_ms(),
+        })
+    }
+
+    /// Applies the snapshot to the session that replaces the outgoing one.
+    ///
+    /// The profile is recompiled and must reproduce the original `config_id`, so both
+    /// sides keep identical runtime behavior across the handoff. The session expects
+    /// control sequences from `control_seq` on and takes over the frame priorities.
+    pub fn apply(&self, session: &AlnpSession) -> Result<(), HandshakeError> {
+        let compiled =
+            StreamProfile::with_weights(self.intent, self.latency_weight, self.resilience_weight)
+                .compile()
+                .map_err(|e| HandshakeError::Protocol(e.to_string()))?;
+        if compiled.config_id() != self.config_id {
+            return Err(HandshakeError::Protocol(
+                "migrated profile config_id mismatch".into(),
+            ));
+        }
+        session.set_stream_profile(compiled)?;
+        session.set_jitter_strategy(self.jitter);
+        session.seed_control_seq(self.control_seq);
+        session.set_frame_priorities(FramePriorities {
+            default: Some(self.priority),
+            universes: self.universe_priorities.clone(),
+        });
+        if self.streaming {
+            session.mark_streaming();
+        }
+        Ok(())
+    }
+}
+
+/// Encrypted session-state blob handed from the outgoing to the incoming controller.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MigrationTicket {
+    pub from_session: Uuid,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl MigrationTicket {
+    /// Seals `snapshot` with a key derived from the outgoing session's keys.
+    pub fn seal(snapshot: &SessionSnapshot, keys: &SessionKeys) -> Result<Self, HandshakeError> {
+        let plaintext = codec::to_vec(snapshot)
+            .map_err(|e| HandshakeError::Protocol(format!("snapshot {}", e)))?;
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = crypto::seal(
+            keys.suite,
+            &migration_key(keys)?,
+            &nonce,
+            snapshot.from_session.as_bytes(),
+            &plaintext,
+        )
+        .map_err(|e| HandshakeError::Authentication(format!("ticket seal: {}", e)))?;
+        Ok(Self {
+            from_session: snapshot.from_session,
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    /// Opens the ticket with the outgoing session's keys and checks freshness.
+    pub fn open(&self, keys: &SessionKeys, now_ms: u64) -> Result<SessionSnapshot, HandshakeError> {
+        let nonce: &[u8; 12] = self
+            .nonce
+            .as_slice()
+            .try_into()
+            .map_err(|_| HandshakeError::Protocol("ticket nonce must be 12 bytes".into()))?;
+        let plaintext = crypto::open(
+            keys.suite,
+            &migration_key(keys)?,
+            nonce,
+            self.from_session.as_bytes(),
+            &self.ciphertext,
+        )
+        .map_err(|_| HandshakeError::Authentication("migration ticket rejected".into()))?;
+        let snapshot: SessionSnapshot = codec::from_slice(&plaintext)
+            .map_err(|e| HandshakeError::Protocol(format!("snapshot {}", e)))?;
+        if snapshot.from_session != self.from_session {
+            return Err(HandshakeError::Authentication(
+                "migration ticket session mismatch".into(),
+            ));
+        }
+        if now_ms.saturating_sub(snapshot.issued_at_ms) > MIGRATION_TICKET_TTL_MS {
+            return Err(HandshakeError::Authentication(
+                "migration ticket expired".into(),
+            ));
+        }
+        Ok(snapshot)
+    }
+}
+
+fn migration_key(keys: &SessionKeys) -> Result<[u8; 32], HandshakeError> {
+    let hkdf = Hkdf::<Sha256>::new(None, &keys.control_key);
+    let mut key = [0u8; 32];
+    hkdf.expand(b"alpine-migration", &mut key)
+        .map_err(|e| HandshakeError::Protocol(format!("hkdf expand error: {:?}", e)))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SuiteId;
+    use crate::session::{AlnpRole, TimingConfig};
+
+    fn keys(byte: u8) -> SessionKeys {
+        SessionKeys {
+            shared_secret: vec![byte; 32],
+            control_key: [byte; 32],
+            stream_key: [byte; 32],
+            suite: SuiteId::default(),
+            channel_binding: [0; 32],
+        }
+    }
+
+    fn snapshot() -> SessionSnapshot {
+        let profile = StreamProfile::realtime().compile().unwrap();
+        SessionSnapshot {
+            from_session: Uuid::new_v4(),
+            intent: profile.intent(),
+            latency_weight: profile.latency_weight(),
+            resilience_weight: profile.resilience_weight(),
+            config_id: profile.config_id().to_string(),
+            jitter: JitterStrategy::Lerp,
+            streaming: true,
+            control_seq: 42,
+            priority: 100,
+            universe_priorities: BTreeMap::from([(1, 120)]),
+            issued_at_ms: 1_000,
+        }
+    }
+
+    #[test]
+    fn ticket_round_trips_with_outgoing_keys() {
+        let snapshot = snapshot();
+        let ticket = MigrationTicket::seal(&snapshot, &keys(7)).unwrap();
+        assert_eq!(ticket.open(&keys(7), 2_000).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn ticket_follows_the_session_suite() {
+        let mut aes = keys(1);
+        aes.suite = SuiteId::Aes256Gcm;
+        let ticket = MigrationTicket::seal(&snapshot(), &aes).unwrap();
+        assert!(ticket.open(&aes, 2_000).is_ok());
+        assert!(ticket.open(&keys(1), 2_000).is_err());
+    }
+
+    #[test]
+    fn ticket_rejected_with_other_keys() {
+        let ticket = MigrationTicket::seal(&snapshot(), &keys(7)).unwrap();
+        assert!(matches!(
+            ticket.open(&keys(8), 2_000),
+            Err(HandshakeError::Authentication(_))
+        ));
+    }
+
+    #[test]
+    fn stale_ticket_rejected() {
+        let ticket = MigrationTicket::seal(&snapshot(), &keys(7)).unwrap();
+        assert!(ticket
+            .open(&keys(7), 1_000 + MIGRATION_TICKET_TTL_MS + 1)
+            .is_err());
+    }
+
+    #[test]
+    fn apply_restores_profile_and_streaming() {
+        let snapshot = snapshot();
+        let session = AlnpSession::new(AlnpRole::Node, TimingConfig::default());
+        snapshot.apply(&session).unwrap();
+        assert_eq!(
+            session.profile_config_id().as_deref(),
+            Some(snapshot.config_id.as_str())
+        );
+        assert_eq!(session.jitter_strategy(), JitterStrategy::Lerp);
+        assert!(session.profile_locked());
+    }
+
+    #[test]
+    fn apply_restores_control_sequence_and_priorities() {
+        let session = AlnpSession::new(AlnpRole::Node, TimingConfig::default());
+        snapshot().apply(&session).unwrap();
+        assert_eq!(session.next_control_seq(), 42);
+        let priorities = session.frame_priorities();
+        assert_eq!(priorities.for_universe(1), Some(120));
+        assert_eq!(priorities.for_universe(2), Some(100));
+    }
+}
@@ -1,18 +1,36 @@
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use ed25519_dalek::Signature;
+use parking_lot::Mutex;
 
-use crate::crypto::{identity::NodeCredentials, KeyExchange, SessionKeys, X25519KeyExchange};
+use crate::crypto::{
+    export_keying_material, identity::NodeCredentials, CipherSuite, KeyExchange, SessionKeys,
+    X25519KeyExchange,
+};
 use crate::handshake::{
     client::ClientHandshake, server::ServerHandshake, ChallengeAuthenticator, HandshakeContext,
     HandshakeError, HandshakeOutcome, HandshakeParticipant, HandshakeTransport,
 };
-use crate::messages::{CapabilitySet, DeviceIdentity, SessionEstablished};
-use crate::profile::{CompiledStreamProfile, StreamProfile};
-
+use crate::messages::{
+    CapabilitySet, DeviceIdentity, KeepaliveStats, SealedFrame, SessionEstablished, UniverseId,
+};
+use crate::profile::CompiledStreamProfile;
+use crate::stream::sealing::Sealer;
+use crate::stream::{SmoothingConfig, StreamError};
+
+pub mod limits;
+pub mod metrics;
+pub mod migration;
+pub mod quota;
+pub mod registry;
+pub mod resume;
 pub mod state;
+use limits::{KeyUsage, LimitWarning, SessionLimit, SessionLimits};
+use metrics::{MetricsRecorder, SessionMetrics};
 use state::{SessionState, SessionStateError};
 
 impl From<SessionStateError> for HandshakeError {
@@ -27,11 +45,114 @@ pub enum AlnpRole {
     Node,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum JitterStrategy {
-    HoldLast,
-    Drop,
-    Lerp,
+pub use crate::messages::JitterStrategy;
+
+/// Frame priority of a session and its per-universe overrides.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FramePriorities {
+    /// Priority of universes without an override; `None` until one is set.
+    pub default: Option<u8>,
+    pub universes: BTreeMap<UniverseId, u8>,
+}
+
+impl FramePriorities {
+    pub fn for_universe(&self, universe: UniverseId) -> Option<u8> {
+        self.universes.get(&universe).copied().or(self.default)
+    }
+}
+
+/// Rejected [`TimingConfig`] values.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TimingError {
+    #[error("{0} must be greater than zero")]
+    Zero(&'static str),
+    #[error("keepalive interval {keepalive:?} must be shorter than session timeout {timeout:?}")]
+    KeepaliveNotBelowTimeout {
+        keepalive: Duration,
+        timeout: Duration,
+    },
+}
+
+impl From<TimingError> for HandshakeError {
+    fn from(err: TimingError) -> Self {
+        HandshakeError::Protocol(err.to_string())
+    }
+}
+
+/// Cryptographic algorithms and negotiated options of an established session, for the
+/// audit log of a venue that must show what protected each show.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSecurityReport {
+    pub session_id: uuid::Uuid,
+    pub suite: CipherSuite,
+    /// Capabilities agreed during the handshake, including encryption and compression.
+    pub capabilities: CapabilitySet,
+}
+
+/// Handshake and liveness timing shared by controllers and nodes.
+///
+/// Built with [`TimingConfig::new`], which rejects zero durations and a keepalive interval
+/// that would let the session time out between keepalives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingConfig {
+    recv_timeout: Duration,
+    session_timeout: Duration,
+    keepalive_interval: Duration,
+}
+
+impl TimingConfig {
+    pub fn new(
+        recv_timeout: Duration,
+        session_timeout: Duration,
+        keepalive_interval: Duration,
+    ) -> Result<Self, TimingError> {
+        for (name, value) in [
+            ("recv timeout", recv_timeout),
+            ("session timeout", session_timeout),
+            ("keepalive interval", keepalive_interval),
+        ] {
+            if value.is_zero() {
+                return Err(TimingError::Zero(name));
+            }
+        }
+        if keepalive_interval >= session_timeout {
+            return Err(TimingError::KeepaliveNotBelowTimeout {
+                keepalive: keepalive_interval,
+                timeout: session_timeout,
+            });
+        }
+        Ok(Self {
+            recv_timeout,
+            session_timeout,
+            keepalive_interval,
+        })
+    }
+
+    /// Longest wait for each handshake or control reply.
+    pub fn recv_timeout(&self) -> Duration {
+        self.recv_timeout
+    }
+
+    /// Idle time after which an established session is failed.
+    pub fn session_timeout(&self) -> Duration {
+        self.session_timeout
+    }
+
+    /// Interval between keepalives on the control channel.
+    pub fn keepalive_interval(&self) -> Duration {
+        self.keepalive_interval
+    }
+}
+
+impl Default for TimingConfig {
+    /// 3 s receive timeout, 10 s session timeout, 5 s keepalive interval.
+    fn default() -> Self {
+        Self {
+            recv_timeout: Duration::from_secs(3),
+            session_timeout: Duration::from_secs(10),
+            keepalive_interval: Duration::from_secs(5),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -39,44 +160,143 @@ pub struct AlnpSession {
     pub role: AlnpRole,
     state: Arc<Mutex<SessionState>>,
     last_keepalive: Arc<Mutex<Instant>>,
+    /// Last keepalive, frame, or authenticated message from the peer since the handshake.
+    peer_activity: Arc<Mutex<Option<Instant>>>,
     jitter: Arc<Mutex<JitterStrategy>>,
+    smoothing: Arc<Mutex<SmoothingConfig>>,
     streaming_enabled: Arc<Mutex<bool>>,
-    timeout: Duration,
+    timing: TimingConfig,
     session_established: Arc<Mutex<Option<SessionEstablished>>>,
     session_keys: Arc<Mutex<Option<SessionKeys>>>,
     compiled_profile: Arc<Mutex<Option<CompiledStreamProfile>>>,
     profile_locked: Arc<Mutex<bool>>,
+    limits: Arc<Mutex<SessionLimits>>,
+    key_usage: Arc<Mutex<KeyUsage>>,
+    metrics: Arc<Mutex<MetricsRecorder>>,
+    /// Set by the first sealed datagram; kept for the session so its epochs and nonces
+    /// carry over to later streams.
+    stream_sealer: Arc<Mutex<Option<Sealer>>>,
+    /// Lowest control sequence the session still expects; a migration raises it past
+    /// the sequences the outgoing controller used.
+    next_control_seq: Arc<Mutex<u64>>,
+    priorities: Arc<Mutex<FramePriorities>>,
 }
 
 impl AlnpSession {
-    pub fn new(role: AlnpRole) -> Self {
+    pub fn new(role: AlnpRole, timing: TimingConfig) -> Self {
         Self {
             role,
             state: Arc::new(Mutex::new(SessionState::Init)),
             last_keepalive: Arc::new(Mutex::new(Instant::now())),
+            peer_activity: Arc::new(Mutex::new(None)),
             jitter: Arc::new(Mutex::new(JitterStrategy::HoldLast)),
+            smoothing: Arc::new(Mutex::new(SmoothingConfig::default())),
             streaming_enabled: Arc::new(Mutex::new(true)),
-            timeout: Duration::from_secs(10),
+            timing,
             session_established: Arc::new(Mutex::new(None)),
             session_keys: Arc::new(Mutex::new(None)),
             compiled_profile: Arc::new(Mutex::new(None)),
             profile_locked: Arc::new(Mutex::new(false)),
+            limits: Arc::new(Mutex::new(SessionLimits::unlimited())),
+            key_usage: Arc::new(Mutex::new(KeyUsage::new(Instant::now()))),
+            metrics: Arc::new(Mutex::new(MetricsRecorder::new(Instant::now()))),
+            stream_sealer: Arc::new(Mutex::new(None)),
+            next_control_seq: Arc::new(Mutex::new(0)),
+            priorities: Arc::new(Mutex::new(FramePriorities::default())),
         }
     }
 
     pub fn established(&self) -> Option<SessionEstablished> {
-        self.session_established.lock().ok().and_then(|s| s.clone())
+        self.session_established.lock().clone()
+    }
+
+    pub fn timing(&self) -> TimingConfig {
+        self.timing
+    }
+
+    /// Replaces the negotiated capabilities after the peer pushed an update and returns
+    /// the previous ones; streams pick the new limits up with their next frame.
+    pub fn update_capabilities(
+        &self,
+        capabilities: CapabilitySet,
+    ) -> Result<CapabilitySet, HandshakeError> {
+        let mut established = self.session_established.lock();
+        let established = established
+            .as_mut()
+            .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+        Ok(std::mem::replace(
+            &mut established.capabilities,
+            capabilities,
+        ))
     }
 
     pub fn keys(&self) -> Option<SessionKeys> {
-        self.session_keys.lock().ok().and_then(|k| k.clone())
+        self.session_keys.lock().clone()
+    }
+
+    /// Seals an encoded stream datagram, in a new epoch when `rekey` is set.
+    pub(crate) fn seal_stream(
+        &self,
+        bytes: &[u8],
+        rekey: bool,
+    ) -> Result<SealedFrame, StreamError> {
+        let mut sealer = self.stream_sealer.lock();
+        let sealer = match sealer.as_mut() {
+            Some(sealer) => sealer,
+            None => {
+                let session_id = self
+                    .established()
+                    .ok_or(StreamError::NotAuthenticated)?
+                    .session_id;
+                let keys = self.keys().ok_or(StreamError::MissingSession)?;
+                let created = Sealer::new(&keys, session_id)
+                    .map_err(|e| StreamError::Transport(e.to_string()))?;
+                sealer.insert(created)
+            }
+        };
+        sealer
+            .seal(bytes, rekey)
+            .map_err(|e| StreamError::Transport(e.to_string()))
+    }
+
+    /// Key epoch of the session's sealed stream, once it has sealed a datagram.
+    pub(crate) fn stream_epoch(&self) -> Option<u32> {
+        self.stream_sealer.lock().as_ref().map(Sealer::epoch)
+    }
+
+    /// Exports `len` bytes of keying material for `label`, which must start with
+    /// `EXPORTER-`; see [`crate::crypto::export_keying_material`].
+    ///
+    /// Use it to provision an approved monitoring appliance for this session without
+    /// handing over the session keys.
+    pub fn export_keying_material(
+        &self,
+        label: &str,
+        len: usize,
+    ) -> Result<Vec<u8>, HandshakeError> {
+        let established = self
+            .established()
+            .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+        let keys = self
+            .keys()
+            .ok_or_else(|| HandshakeError::Protocol("session keys missing".into()))?;
+        export_keying_material(&keys, established.session_id.as_bytes(), label, len)
+            .map_err(|e| HandshakeError::Protocol(e.to_string()))
+    }
+
+    /// Reports the cipher suite and negotiated options of the established session.
+    pub fn security_report(&self) -> Option<SessionSecurityReport> {
+        let established = self.established()?;
+        let keys = self.keys()?;
+        Some(SessionSecurityReport {
+            session_id: established.session_id,
+            suite: keys.suite.describe(),
+            capabilities: established.capabilities,
+        })
     }
 
     pub fn state(&self) -> SessionState {
-        self.state
-            .lock()
-            .map(|g| g.clone())
-            .unwrap_or(SessionState::Failed("state poisoned".to_string()))
+        self.state.lock().clone()
     }
 
     pub fn ensure_streaming_ready(&self) -> Result<SessionEstablished, HandshakeError> {
@@ -97,52 +317,115 @@ impl AlnpSession {
     }
 
     pub fn update_keepalive(&self) {
-        if let Ok(mut k) = self.last_keepalive.lock() {
-            *k = Instant::now();
-        }
+        let now = Instant::now();
+        *self.last_keepalive.lock() = now;
+        *self.peer_activity.lock() = Some(now);
+    }
+
+    /// Notes that the peer was heard from, e.g. an authenticated control envelope.
+    /// Keepalives and received frames count on their own.
+    pub fn record_peer_activity(&self) {
+        *self.peer_activity.lock() = Some(Instant::now());
+    }
+
+    /// When the peer was last heard from after the handshake; `None` while it has sent
+    /// nothing, which on a node means the session may be half-open.
+    pub fn last_peer_activity(&self) -> Option<Instant> {
+        *self.peer_activity.lock()
     }
 
+    /// Fails the session after an idle timeout or once its maximum age is reached.
     pub fn check_timeouts(&self) -> Result<(), HandshakeError> {
         let now = Instant::now();
-        if let Ok(state) = self.state.lock() {
-            if state.check_timeout(self.timeout, now) {
-                self.fail("session timeout".into());
-                return Err(HandshakeError::Transport("session timeout".into()));
-            }
+        let timed_out = self
+            .state
+            .lock()
+            .check_timeout(self.timing.session_timeout(), now);
+        if timed_out {
+            self.fail("session timeout".into());
+            return Err(HandshakeError::Timeout("session timeout".into()));
+        }
+        let limits = self.limits();
+        let aged_out = self.key_usage.lock().exceeded(&limits, now) == Some(SessionLimit::Age);
+        if aged_out {
+            self.fail("session lifetime exceeded".into());
+            return Err(HandshakeError::Transport(
+                "session lifetime exceeded".into(),
+            ));
         }
         Ok(())
     }
 
+    /// Sets the age and per-key frame limits enforced by [`AlnpSession::record_frame`].
+    pub fn set_limits(&self, limits: SessionLimits) {
+        *self.limits.lock() = limits;
+    }
+
+    pub fn limits(&self) -> SessionLimits {
+        *self.limits.lock()
+    }
+
+    /// Time since the current session keys were installed.
+    pub fn key_age(&self) -> Duration {
+        self.key_usage.lock().age(Instant::now())
+    }
+
+    /// Frames counted against the current session keys.
+    pub fn frames_on_key(&self) -> u64 {
+        self.key_usage.lock().frames()
+    }
+
+    /// Counts one frame against the session limits.
+    ///
+    /// Returns a warning the first time a limit's threshold is crossed, and the reached
+    /// limit once the session must be replaced by a fresh handshake.
+    pub fn record_frame(&self) -> Result<Option<LimitWarning>, SessionLimit> {
+        let limits = self.limits();
+        self.key_usage.lock().record_frame(&limits, Instant::now())
+    }
+
     /// Sets the stream profile that determines runtime behavior.
     ///
     /// This method locks the profile until streaming begins to enforce immutability.
     pub fn set_stream_profile(&self, profile: CompiledStreamProfile) -> Result<(), HandshakeError> {
-        let locked = self
-            .profile_locked
-            .lock()
-            .map_err(|_| HandshakeError::Protocol("profile lock poisoned".into()))?;
+        let locked = self.profile_locked.lock();
         if *locked {
             return Err(HandshakeError::Protocol(
                 "stream profile cannot be changed after streaming starts".into(),
             ));
         }
-        let mut compiled = self
-            .compiled_profile
-            .lock()
-            .map_err(|_| HandshakeError::Protocol("compiled profile lock poisoned".into()))?;
-        *compiled = Some(profile);
+        *self.compiled_profile.lock() = Some(profile);
+        Ok(())
+    }
+
+    /// Replaces the profile of a session that has already started streaming.
+    ///
+    /// This is the one sanctioned way past the profile lock: the caller discards runtime
+    /// state built on the old profile and agrees on the new `config_id` with the peer
+    /// (`restart_stream`) before sending under it. The profile stays locked.
+    pub fn restart_stream_profile(
+        &self,
+        profile: CompiledStreamProfile,
+    ) -> Result<(), HandshakeError> {
+        if !self.profile_locked() {
+            return Err(HandshakeError::Protocol(
+                "stream has not started; set the profile instead".into(),
+            ));
+        }
+        *self.compiled_profile.lock() = Some(profile);
         Ok(())
     }
 
     /// Returns the bound profile's config ID, if set.
     ///
-    /// The `config_id` is computed from the normalized profile and never changes.
+    /// The `config_id` is computed from the normalized profile and only changes through
+    /// [`Self::restart_stream_profile`].
     #[must_use]
     pub fn profile_config_id(&self) -> Option<String> {
         self.compiled_profile
             .lock()
-            .ok()
-            .and_then(|guard| guard.clone().map(|profile| profile.config_id().to_string()))
+            .as_ref()
+            .map(|profile| profile.config_id().to_string())
     }
 
     /// Retrieves the compiled profile, if configured.
@@ -150,84 +433,200 @@ impl AlnpSession {
     /// Once streaming starts this returns the same object that controls runtime behavior.
     #[must_use]
     pub fn compiled_profile(&self) -> Option<CompiledStreamProfile> {
-        self.compiled_profile
-            .lock()
-            .ok()
-            .and_then(|guard| guard.clone())
+        self.compiled_profile.lock().clone()
     }
 
-    #[cfg(test)]
-    pub(crate) fn set_locked_profile_for_testing(&self, profile: CompiledStreamProfile) {
-        let mut compiled = self.compiled_profile.lock().unwrap();
-        *compiled = Some(profile);
-        *self.profile_locked.lock().unwrap() = true;
+    /// Returns true once streaming has started and the profile can no longer change.
+    pub fn profile_locked(&self) -> bool {
+        *self.profile_locked.lock()
     }
 
     pub fn set_jitter_strategy(&self, strat: JitterStrategy) {
-        if let Ok(mut j) = self.jitter.lock() {
-            *j = strat;
-        }
+        *self.jitter.lock() = strat;
     }
 
     pub fn jitter_strategy(&self) -> JitterStrategy {
-        self.jitter
-            .lock()
-            .map(|j| *j)
-            .unwrap_or(JitterStrategy::Drop)
+        *self.jitter.lock()
+    }
+
+    /// Replaces the ramp, slew, and per-group smoothing; takes effect with the next frame.
+    pub fn set_smoothing(&self, config: SmoothingConfig) {
+        *self.smoothing.lock() = config;
+    }
+
+    pub fn smoothing(&self) -> SmoothingConfig {
+        self.smoothing.lock().clone()
+    }
+
+    /// Raises the lowest control sequence the session expects to `next`; sequences are
+    /// never moved back.
+    pub fn seed_control_seq(&self, next: u64) {
+        let mut current = self.next_control_seq.lock();
+        *current = (*current).max(next);
+    }
+
+    pub fn next_control_seq(&self) -> u64 {
+        *self.next_control_seq.lock()
+    }
+
+    pub fn set_frame_priorities(&self, priorities: FramePriorities) {
+        *self.priorities.lock() = priorities;
+    }
+
+    pub fn frame_priorities(&self) -> FramePriorities {
+        self.priorities.lock().clone()
     }
 
     pub fn close(&self) {
-        if let Ok(mut state) = self.state.lock() {
-            *state = SessionState::Closed;
-        }
+        self.set_state(SessionState::Closed);
     }
 
     pub fn fail(&self, reason: String) {
-        if let Ok(mut state) = self.state.lock() {
-            *state = SessionState::Failed(reason);
-        }
+        self.set_state(SessionState::Failed(reason));
+    }
+
+    /// Runs `task`, failing the session if it panics.
+    ///
+    /// Session locks never poison, so a panicking task cannot wedge the session for its
+    /// other holders; wrapping work that drives the session here also moves it to
+    /// [`SessionState::Failed`], which every clone sees and which blocks streaming.
+    pub fn guard<R>(&self, task: impl FnOnce() -> R) -> Result<R, HandshakeError> {
+        panic::catch_unwind(AssertUnwindSafe(task)).map_err(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".into());
+            let reason = format!("task panicked: {}", message);
+            self.fail(reason.clone());
+            HandshakeError::Transport(reason)
+        })
+    }
+
+    fn set_state(&self, next: SessionState) {
+        let mut state = self.state.lock();
+        *state = next;
+        self.record_state(&state);
     }
 
     fn transition(&self, next: SessionState) -> Result<(), SessionStateError> {
-        let mut state = self.state.lock().unwrap();
-        let current = state.clone();
-        *state = current.transition(next)?;
+        let mut state = self.state.lock();
+        *state = state.clone().transition(next)?;
+        self.record_state(&state);
         Ok(())
     }
 
+    fn record_state(&self, state: &SessionState) {
+        self.metrics.lock().enter(state, Instant::now());
+    }
+
+    /// Returns uptime, per-state timings, round trips, frame counts, and the last error.
+    pub fn metrics(&self) -> SessionMetrics {
+        self.metrics.lock().snapshot(Instant::now())
+    }
+
+    /// Adds a measured control-channel round trip to [`SessionMetrics::keepalive_rtt`].
+    pub fn record_keepalive_rtt(&self, rtt: Duration) {
+        self.metrics.lock().keepalive_rtt(rtt);
+    }
+
+    /// Counts a frame handed to the transport.
+    pub fn record_frame_sent(&self) {
+        self.metrics.lock().frame_sent(None);
+    }
+
+    /// Counts a stream frame handed to the transport, remembering its `timestamp_us` for
+    /// [`KeepaliveStats::last_frame_us`].
+    pub(crate) fn record_stream_frame_sent(&self, timestamp_us: u64) {
+        self.metrics.lock().frame_sent(Some(timestamp_us));
+    }
+
+    /// Stream counters to piggyback on a keepalive.
+    pub fn keepalive_stats(&self) -> KeepaliveStats {
+        self.metrics.lock().keepalive_stats()
+    }
+
+    /// Records the counters the peer piggybacked on a keepalive, updating
+    /// [`SessionMetrics::frames_lost`] and [`SessionMetrics::peer_stats`].
+    pub fn record_peer_stats(&self, stats: KeepaliveStats) {
+        self.metrics.lock().peer_stats(stats);
+    }
+
+    /// Counts a frame accepted from the peer.
+    pub fn record_frame_received(&self) {
+        self.record_peer_activity();
+        self.metrics.lock().frame_received();
+    }
+
+    /// Counts a received frame that missed its deadline.
+    pub fn record_late_frame(&self) {
+        self.metrics.lock().frame_late();
+    }
+
+    /// Counts a received frame refused for exceeding the session's frame-rate quota.
+    pub fn record_throttled_frame(&self) {
+        self.metrics.lock().frame_throttled();
+    }
+
+    /// Counts a control envelope refused for exceeding the session's control quota.
+    pub fn record_throttled_control(&self) {
+        self.metrics.lock().control_throttled();
+    }
+
+    /// Counts fragmented datagrams dropped after their reassembly timeout.
+    pub fn record_reassembly_timeouts(&self, datagrams: u64) {
+        self.metrics.lock().reassembly_timed_out(datagrams);
+    }
+
     pub fn set_streaming_enabled(&self, enabled: bool) {
-        if let Ok(mut flag) = self.streaming_enabled.lock() {
-            *flag = enabled;
-        }
+        *self.streaming_enabled.lock() = enabled;
     }
 
     pub fn mark_streaming(&self) {
-        if let Ok(mut state) = self.state.lock() {
-            let current = state.clone();
-            if let SessionState::Ready { .. } = current {
-                let _ = current
-                    .transition(SessionState::Streaming {
-                        since: Instant::now(),
-                    })
-                    .map(|next| *state = next);
+        {
+            let mut state = self.state.lock();
+            if let SessionState::Ready { .. } = *state {
+                if let Ok(next) = state.clone().transition(SessionState::Streaming {
+                    since: Instant::now(),
+                }) {
+                    *state = next;
+                    self.record_state(&state);
+                }
             }
         }
-        if let Ok(mut locked) = self.profile_locked.lock() {
-            *locked = true;
-        }
+        *self.profile_locked.lock() = true;
     }
 
     pub fn streaming_enabled(&self) -> bool {
-        self.streaming_enabled.lock().map(|f| *f).unwrap_or(false)
+        *self.streaming_enabled.lock()
     }
 
     fn apply_outcome(&self, outcome: HandshakeOutcome) {
-        if let Ok(mut guard) = self.session_established.lock() {
-            *guard = Some(outcome.established);
-        }
-        if let Ok(mut guard) = self.session_keys.lock() {
-            *guard = Some(outcome.keys);
-        }
+        *self.session_established.lock() = Some(outcome.established);
+        *self.session_keys.lock() = Some(outcome.keys);
+        *self.key_usage.lock() = KeyUsage::new(Instant::now());
+    }
+
+    /// Creates a session that is about to run a handshake or resumption.
+    pub(crate) fn start_handshake(
+        role: AlnpRole,
+        timing: TimingConfig,
+    ) -> Result<Self, HandshakeError> {
+        let session = Self::new(role, timing);
+        session.transition(SessionState::Handshake)?;
+        Ok(session)
+    }
+
+    /// Installs the handshake outcome and marks the session ready.
+    pub(crate) fn finish_handshake(&self, outcome: HandshakeOutcome) -> Result<(), HandshakeError> {
+        self.transition(SessionState::Authenticated {
+            since: Instant::now(),
+        })?;
+        self.transition(SessionState::Ready {
+            since: Instant::now(),
+        })?;
+        self.apply_outcome(outcome);
+        Ok(())
     }
 
     pub async fn connect<T, A, K>(
@@ -243,8 +642,34 @@ impl AlnpSession {
         A: ChallengeAuthenticator + Send + Sync,
         K: KeyExchange + Send + Sync,
     {
-        let session = Self::new(AlnpRole::Controller);
-        session.transition(SessionState::Handshake)?;
+        Self::connect_with_timing(
+            identity,
+            capabilities,
+            authenticator,
+            key_exchange,
+            context,
+            TimingConfig::default(),
+            transport,
+        )
+        .await
+    }
+
+    /// Like [`AlnpSession::connect`], with non-default session timing.
+    pub async fn connect_with_timing<T, A, K>(
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        authenticator: A,
+        key_exchange: K,
+        context: HandshakeContext,
+        timing: TimingConfig,
+        transport: &mut T,
+    ) -> Result<Self, HandshakeError>
+    where
+        T: HandshakeTransport + Send,
+        A: ChallengeAuthenticator + Send + Sync,
+        K: KeyExchange + Send + Sync,
+    {
+        let session = Self::start_handshake(AlnpRole::Controller, timing)?;
         let driver = ClientHandshake {
             identity,
             capabilities,
@@ -254,13 +679,7 @@ impl AlnpSession {
         };
 
         let outcome = driver.run(transport).await?;
-        session.transition(SessionState::Authenticated {
-            since: Instant::now(),
-        })?;
-        session.transition(SessionState::Ready {
-            since: Instant::now(),
-        })?;
-        session.apply_outcome(outcome);
+        session.finish_handshake(outcome)?;
         Ok(session)
     }
 
@@ -277,8 +696,34 @@ impl AlnpSession {
         A: ChallengeAuthenticator + Send + Sync,
         K: KeyExchange + Send + Sync,
     {
-        let session = Self::new(AlnpRole::Node);
-        session.transition(SessionState::Handshake)?;
+        Self::accept_with_timing(
+            identity,
+            capabilities,
+            authenticator,
+            key_exchange,
+            context,
+            TimingConfig::default(),
+            transport,
+        )
+        .await
+    }
+
+    /// Like [`AlnpSession::accept`], with non-default session timing.
+    pub async fn accept_with_timing<T, A, K>(
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        authenticator: A,
+        key_exchange: K,
+        context: HandshakeContext,
+        timing: TimingConfig,
+        transport: &mut T,
+    ) -> Result<Self, HandshakeError>
+    where
+        T: HandshakeTransport + Send,
+        A: ChallengeAuthenticator + Send + Sync,
+        K: KeyExchange + Send + Sync,
+    {
+        let session = Self::start_handshake(AlnpRole::Node, timing)?;
         let driver = ServerHandshake {
             identity,
             capabilities,
@@ -288,13 +733,7 @@ impl AlnpSession {
         };
 
         let outcome = driver.run(transport).await?;
-        session.transition(SessionState::Authenticated {
-            since: Instant::now(),
-        })?;
-        session.transition(SessionState::Ready {
-            since: Instant::now(),
-        })?;
-        session.apply_outcome(outcome);
+        session.finish_handshake(outcome)?;
         Ok(session)
     }
 }
@@ -352,6 +791,10 @@ impl ChallengeAuthenticator for Ed25519Authenticator {
             false
         }
     }
+
+    fn verifying_key(&self) -> Option<Vec<u8>> {
+        Some(self.creds.verifying.to_bytes().to_vec())
+    }
 }
 
 /// Simplified in-memory transport useful for unit tests and examples.
@@ -365,38 +808,9 @@ impl LoopbackTransport {
     }
 }
 
-#[cfg(test)]
-mod session_tests {
-    use super::*;
-
-    #[test]
-    fn profile_lock_prevents_profile_swaps() {
-        let session = AlnpSession::new(AlnpRole::Controller);
-        let compiled = StreamProfile::auto().compile().unwrap();
-        session.set_stream_profile(compiled.clone()).unwrap();
-        session.mark_streaming();
-        assert!(session.set_stream_profile(compiled).is_err());
-    }
-
-    #[test]
-    fn config_id_matches_profile() {
-        let session = AlnpSession::new(AlnpRole::Controller);
-        let compiled = StreamProfile::realtime().compile().unwrap();
-        session.set_stream_profile(compiled.clone()).unwrap();
-        assert_eq!(session.profile_config_id().unwrap(), compiled.config_id());
-    }
-
-    #[test]
-    fn config_id_stays_locked_after_streaming() {
-        let session = AlnpSession::new(AlnpRole::Controller);
-        let compiled = StreamProfile::install().compile().unwrap();
-        session.set_stream_profile(compiled.clone()).unwrap();
-        let before_config = session.profile_config_id().unwrap();
-        session.mark_streaming();
-        assert_eq!(session.profile_config_id().unwrap(), before_config);
-        assert!(session
-            .set_stream_profile(StreamProfile::default().compile().unwrap())
-            .is_err());
+impl Default for LoopbackTransport {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -449,3 +863,117 @@ pub async fn example_node_session<T: HandshakeTransport + Send>(
     )
     .await
 }
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+    use crate::profile::StreamProfile;
+
+    #[test]
+    fn profile_lock_prevents_profile_swaps() {
+        let session = AlnpSession::new(AlnpRole::Controller, TimingConfig::default());
+        let compiled = StreamProfile::auto().compile().unwrap();
+        session.set_stream_profile(compiled.clone()).unwrap();
+        session.mark_streaming();
+        assert!(session.set_stream_profile(compiled).is_err());
+    }
+
+    #[test]
+    fn config_id_matches_profile() {
+        let session = AlnpSession::new(AlnpRole::Controller, TimingConfig::default());
+        let compiled = StreamProfile::realtime().compile().unwrap();
+        session.set_stream_profile(compiled.clone()).unwrap();
+        assert_eq!(session.profile_config_id().unwrap(), compiled.config_id());
+    }
+
+    #[test]
+    fn config_id_stays_locked_after_streaming() {
+        let session = AlnpSession::new(AlnpRole::Controller, TimingConfig::default());
+        let compiled = StreamProfile::install().compile().unwrap();
+        session.set_stream_profile(compiled.clone()).unwrap();
+        let before_config = session.profile_config_id().unwrap();
+        session.mark_streaming();
+        assert_eq!(session.profile_config_id().unwrap(), before_config);
+        assert!(session
+            .set_stream_profile(StreamProfile::default().compile().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn restart_replaces_a_locked_profile_only() {
+        let session = AlnpSession::new(AlnpRole::Controller, TimingConfig::default());
+        let realtime = StreamProfile::realtime().compile().unwrap();
+        assert!(session.restart_stream_profile(realtime.clone()).is_err());
+        session
+            .set_stream_profile(StreamProfile::install().compile().unwrap())
+            .unwrap();
+        session.mark_streaming();
+        session.restart_stream_profile(realtime.clone()).unwrap();
+        assert_eq!(session.profile_config_id().unwrap(), realtime.config_id());
+        assert!(session.profile_locked());
+    }
+
+    #[test]
+    fn a_panicking_task_fails_the_session_for_every_clone() {
+        let session = AlnpSession::new(AlnpRole::Controller, TimingConfig::default());
+        let worker = session.clone();
+        let err = std::thread::spawn(move || {
+            worker.guard(|| {
+                worker.record_frame_sent();
+                panic!("output driver crashed");
+            })
+        })
+        .join()
+        .unwrap()
+        .unwrap_err();
+        assert!(err.to_string().contains("output driver crashed"));
+        assert_eq!(
+            session.state(),
+            SessionState::Failed("task panicked: output driver crashed".into())
+        );
+        assert!(session.ensure_streaming_ready().is_err());
+        let metrics = session.metrics();
+        assert_eq!(metrics.frames_sent, 1);
+        assert_eq!(
+            metrics.last_error.as_deref(),
+            Some("task panicked: output driver crashed")
+        );
+        assert_eq!(session.guard(|| 7).unwrap(), 7);
+    }
+
+    #[test]
+    fn an_unguarded_panic_leaves_the_session_usable() {
+        let session = AlnpSession::new(AlnpRole::Controller, TimingConfig::default());
+        let worker = session.clone();
+        let joined = std::thread::spawn(move || {
+            worker.set_streaming_enabled(false);
+            worker.record_frame_received();
+            panic!("listener crashed");
+        })
+        .join();
+        assert!(joined.is_err());
+        assert!(!session.streaming_enabled());
+        session.set_streaming_enabled(true);
+        session.record_frame_received();
+        assert_eq!(session.metrics().frames_received, 2);
+        session.close();
+        assert_eq!(session.state(), SessionState::Closed);
+    }
+
+    #[test]
+    fn timing_rejects_keepalive_at_or_above_timeout() {
+        let secs = Duration::from_secs;
+        assert!(TimingConfig::new(secs(1), secs(4), secs(2)).is_ok());
+        assert_eq!(
+            TimingConfig::new(secs(1), secs(4), secs(4)),
+            Err(TimingError::KeepaliveNotBelowTimeout {
+                keepalive: secs(4),
+                timeout: secs(4),
+            })
+        );
+        assert_eq!(
+            TimingConfig::new(Duration::ZERO, secs(4), secs(2)),
+            Err(TimingError::Zero("recv timeout"))
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Per-session resource quotas.
+//!
+//! A device serving several controllers gives each session its own budget of frames and
+//! control envelopes per second and of metadata bytes per frame, so one misbehaving
+//! controller cannot starve the others. Frames beyond the frame-rate quota are refused
+//! and answered with a rate-limited [`crate::messages::ErrorEnvelope`]; control envelopes
+//! beyond the control quota get `CONTROL_RATE_LIMITED` as before.
+
+use crate::control::RateLimit;
+
+/// Budgets applied to every session a device accepts; unlimited by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionQuotas {
+    frame_rate: Option<RateLimit>,
+    control_rate: Option<RateLimit>,
+    max_metadata_bytes: Option<usize>,
+}
+
+impl SessionQuotas {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Limits the frames each session may stream, across all universes.
+    pub fn with_frame_rate(mut self, limit: RateLimit) -> Self {
+        self.frame_rate = Some(limit);
+        self
+    }
+
+    /// Limits the new control envelopes each session may have handled.
+    pub fn with_control_rate(mut self, limit: RateLimit) -> Self {
+        self.control_rate = Some(limit);
+        self
+    }
+
+    /// Refuses frames whose metadata encodes to more than `bytes` bytes of JSON.
+    pub fn with_max_metadata_bytes(mut self, bytes: usize) -> Self {
+        self.max_metadata_bytes = Some(bytes);
+        self
+    }
+
+    pub fn frame_rate(&self) -> Option<RateLimit> {
+        self.frame_rate
+    }
+
+    pub fn control_rate(&self) -> Option<RateLimit> {
+        self.control_rate
+    }
+
+    pub fn max_metadata_bytes(&self) -> Option<usize> {
+        self.max_metadata_bytes
+    }
+}
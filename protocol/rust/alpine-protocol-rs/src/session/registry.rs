@@ -0,0 +1,664 @@
+//! Concurrent sessions held by one node.
+//!
+//! A node may serve a primary controller, a backup, and a monitoring tool at once. Each
+//! session keeps its own keys and control sequence window; the [`SessionRegistry`] records
+//! which are open, what each may do, and lets the node enumerate and force-close them.
+//!
+//! A node at its limit refuses further controllers, unless it allows takeovers: then a
+//! controller stating a higher priority than the lowest-priority session is admitted
+//! over the limit with [`SessionRegistry::admit`]. The session it preempts keeps
+//! streaming for a grace period and is told so; the newcomer's frames are refused until
+//! the preempted session closes, by itself or when the grace period runs out.
+//!
+//! Handshakes cost the node signature checks and key exchanges before a session exists,
+//! so it may also cap how many run at once with [`SessionRegistry::with_handshake_limit`].
+//! A controller turned away by either limit is told how long to wait before trying again.
+//!
+//! A controller that vanishes right after the handshake leaves a half-open session that
+//! would hold its slot for good. Sessions whose peer sends no keepalive, frame, or
+//! control message within the first-activity deadline are closed when the registry is
+//! next consulted.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use uuid::Uuid;
+
+use super::state::SessionState;
+use super::AlnpSession;
+use crate::handshake::HandshakeError;
+use crate::messages::{ControlOp, SessionRole};
+use crate::sim::SimClock;
+
+/// Sessions a node holds at once unless configured otherwise.
+pub const DEFAULT_MAX_SESSIONS: usize = 4;
+
+/// How long a busy node asks controllers to wait unless configured otherwise.
+pub const DEFAULT_BUSY_RETRY_AFTER: Duration = Duration::from_secs(2);
+
+/// How long a new session may stay silent unless configured otherwise; twice the default
+/// keepalive interval.
+pub const DEFAULT_FIRST_ACTIVITY_DEADLINE: Duration = Duration::from_secs(10);
+
+/// A higher-priority controller taking a full node over from another session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Takeover {
+    /// Session losing its slot.
+    pub preempted: Uuid,
+    /// Session taking it; its frames are refused until `preempted` is gone.
+    pub by: Uuid,
+    /// Priority the new controller stated.
+    pub priority: u8,
+    pub grace: Duration,
+    /// When `preempted` is closed unless it closed itself first.
+    pub deadline: Instant,
+}
+
+/// What a session may do on the node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionAccess {
+    /// Streams frames and sends any control operation.
+    #[default]
+    Full,
+    /// Reads status only: its frames are refused and only read-only operations run.
+    Monitor,
+}
+
+impl SessionAccess {
+    pub fn allows_frames(&self) -> bool {
+        matches!(self, SessionAccess::Full)
+    }
+
+    pub fn allows(&self, op: &ControlOp) -> bool {
+        match self {
+            SessionAccess::Full => true,
+            SessionAccess::Monitor => op.is_read_only(),
+        }
+    }
+}
+
+/// One open session, as reported by [`SessionRegistry::list`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub session_id: Uuid,
+    /// Controller address, once the serving transport reports it.
+    pub peer: Option<SocketAddr>,
+    pub access: SessionAccess,
+    /// Role the controller asked for in its handshake.
+    pub role: SessionRole,
+    pub state: SessionState,
+    pub established_at: Instant,
+    /// When the controller was last heard from; `None` while the session is half-open.
+    pub last_activity: Option<Instant>,
+    /// Priority the controller stated in its handshake.
+    pub priority: u8,
+    /// Set on a session another controller is taking over, and on the session taking it
+    /// until the other is gone.
+    pub takeover: Option<Takeover>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    session: AlnpSession,
+    peer: Option<SocketAddr>,
+    access: SessionAccess,
+    established_at: Instant,
+    priority: u8,
+    takeover: Option<Takeover>,
+}
+
+/// Device-side record of the sessions currently open.
+///
+/// Clones share the same record. Closed and failed sessions are dropped the next time a
+/// session is inserted or the registry is listed.
+#[derive(Debug, Clone)]
+pub struct SessionRegistry {
+    max_sessions: usize,
+    max_handshakes: Option<usize>,
+    retry_after: Duration,
+    first_activity: Option<Duration>,
+    /// Virtual time for simulations; the system clock when absent.
+    clock: Option<SimClock>,
+    entries: Arc<Mutex<HashMap<Uuid, Entry>>>,
+    handshakes: Arc<AtomicUsize>,
+}
+
+impl SessionRegistry {
+    /// Holds at most `max_sessions` open sessions, with no limit on handshakes.
+    pub fn new(max_sessions: usize) -> Self {
+        Self {
+            max_sessions,
+            max_handshakes: None,
+            retry_after: DEFAULT_BUSY_RETRY_AFTER,
+            first_activity: Some(DEFAULT_FIRST_ACTIVITY_DEADLINE),
+            clock: None,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            handshakes: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Runs at most `max_handshakes` handshakes at once.
+    pub fn with_handshake_limit(mut self, max_handshakes: usize) -> Self {
+        self.max_handshakes = Some(max_handshakes);
+        self
+    }
+
+    /// How long controllers turned away by either limit are asked to wait.
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Closes sessions whose controller sends nothing within `deadline` of the handshake;
+    /// `None` keeps them until they close.
+    pub fn with_first_activity_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.first_activity = deadline;
+        self
+    }
+
+    /// Reads time from `clock` instead of the system clock, so grace periods and
+    /// first-activity deadlines run on simulated time.
+    pub fn with_clock(mut self, clock: SimClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn first_activity_deadline(&self) -> Option<Duration> {
+        self.first_activity
+    }
+
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    pub fn max_handshakes(&self) -> Option<usize> {
+        self.max_handshakes
+    }
+
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    /// Handshakes running now.
+    pub fn handshakes(&self) -> usize {
+        self.handshakes.load(Ordering::Acquire)
+    }
+
+    /// Claims a handshake slot for as long as the returned guard lives; `None` once
+    /// [`SessionRegistry::with_handshake_limit`] handshakes are running.
+    pub fn begin_handshake(&self) -> Option<HandshakeSlot> {
+        let max = self.max_handshakes.unwrap_or(usize::MAX);
+        self.handshakes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+                (running < max).then_some(running + 1)
+            })
+            .ok()?;
+        Some(HandshakeSlot(self.handshakes.clone()))
+    }
+
+    /// Whether another session would exceed the limit.
+    pub fn is_full(&self) -> bool {
+        let mut entries = self.entries.lock();
+        prune(&mut entries, self.first_activity, self.now());
+        entries.len() >= self.max_sessions
+    }
+
+    /// Records an established session; refused once the limit is reached.
+    pub fn insert(
+        &self,
+        session: &AlnpSession,
+        access: SessionAccess,
+    ) -> Result<Uuid, HandshakeError> {
+        self.admit(session, access, None)
+            .map(|(session_id, _)| session_id)
+    }
+
+    /// Session a controller stating `priority` would take over: the lowest-priority
+    /// session below it, oldest first, that is not already being taken over. `None` while
+    /// the node has room.
+    pub fn preemptible(&self, priority: u8) -> Option<Uuid> {
+        let mut entries = self.entries.lock();
+        prune(&mut entries, self.first_activity, self.now());
+        if entries.len() < self.max_sessions {
+            return None;
+        }
+        preemptible(&entries, priority)
+    }
+
+    /// Records an established session like [`SessionRegistry::insert`]. With a `grace`
+    /// period, a controller that outranks a session of a full node takes that session's
+    /// slot: both are marked with the returned [`Takeover`], and the preempted session is
+    /// closed once `grace` has passed.
+    pub fn admit(
+        &self,
+        session: &AlnpSession,
+        access: SessionAccess,
+        grace: Option<Duration>,
+    ) -> Result<(Uuid, Option<Takeover>), HandshakeError> {
+        let established = session
+            .established()
+            .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+        let (session_id, priority) = (established.session_id, established.priority());
+        let mut entries = self.entries.lock();
+        prune(&mut entries, self.first_activity, self.now());
+        let mut takeover = None;
+        if entries.len() >= self.max_sessions {
+            let preempted = grace.and_then(|grace| {
+                preemptible(&entries, priority).map(|preempted| (preempted, grace))
+            });
+            let Some((preempted, grace)) = preempted else {
+                return Err(HandshakeError::Capability(format!(
+                    "session limit of {} reached",
+                    self.max_sessions
+                )));
+            };
+            let now = self.now();
+            takeover = Some(Takeover {
+                preempted,
+                by: session_id,
+                priority,
+                grace,
+                deadline: now + grace,
+            });
+            if let Some(entry) = entries.get_mut(&preempted) {
+                entry.takeover = takeover;
+            }
+        }
+        entries.insert(
+            session_id,
+            Entry {
+                session: session.clone(),
+                peer: None,
+                access,
+                established_at: self.now(),
+                priority,
+                takeover,
+            },
+        );
+        Ok((session_id, takeover))
+    }
+
+    /// Records the controller address serving `session_id`.
+    pub fn set_peer(&self, session_id: Uuid, peer: SocketAddr) {
+        if let Some(entry) = self.entries.lock().get_mut(&session_id) {
+            entry.peer = Some(peer);
+        }
+    }
+
+    /// Changes what `session_id` may do; takes effect with its next frame or envelope.
+    ///
+    /// An observer session stays [`SessionAccess::Monitor`]. Returns whether the session
+    /// now has `access`.
+    pub fn set_access(&self, session_id: Uuid, access: SessionAccess) -> bool {
+        match self.entries.lock().get_mut(&session_id) {
+            Some(entry) if access == SessionAccess::Full && is_observer(&entry.session) => false,
+            Some(entry) => {
+                entry.access = access;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether frames of `session_id` are applied: its access allows them and no session
+    /// it is taking over is still open.
+    pub fn may_stream(&self, session_id: Uuid) -> bool {
+        let mut entries = self.entries.lock();
+        prune(&mut entries, self.first_activity, self.now());
+        let Some(entry) = entries.get(&session_id) else {
+            return false;
+        };
+        // A finished takeover is cleared by `prune`.
+        let waiting = entry
+            .takeover
+            .is_some_and(|takeover| takeover.by == session_id);
+        entry.access.allows_frames() && !waiting
+    }
+
+    /// The takeover `session_id` is part of, as either side, until the preempted session
+    /// is gone.
+    pub fn takeover(&self, session_id: Uuid) -> Option<Takeover> {
+        self.entries
+            .lock()
+            .get(&session_id)
+            .and_then(|entry| entry.takeover)
+    }
+
+    /// Access of an open session; `None` once it is closed or was never recorded.
+    pub fn access(&self, session_id: Uuid) -> Option<SessionAccess> {
+        self.entries
+            .lock()
+            .get(&session_id)
+            .filter(|entry| is_open(&entry.session))
+            .map(|entry| entry.access)
+    }
+
+    /// Role an open session asked for in its handshake.
+    pub fn role(&self, session_id: Uuid) -> Option<SessionRole> {
+        self.entries
+            .lock()
+            .get(&session_id)
+            .filter(|entry| is_open(&entry.session))
+            .and_then(|entry| entry.session.established())
+            .map(|established| established.role())
+    }
+
+    pub fn get(&self, session_id: Uuid) -> Option<AlnpSession> {
+        self.entries
+            .lock()
+            .get(&session_id)
+            .map(|entry| entry.session.clone())
+    }
+
+    /// Open sessions, oldest first.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut entries = self.entries.lock();
+        prune(&mut entries, self.first_activity, self.now());
+        let mut sessions: Vec<SessionInfo> = entries
+            .iter()
+            .map(|(session_id, entry)| SessionInfo {
+                session_id: *session_id,
+                peer: entry.peer,
+                access: entry.access,
+                role: entry
+                    .session
+                    .established()
+                    .map(|established| established.role())
+                    .unwrap_or_default(),
+                state: entry.session.state(),
+                established_at: entry.established_at,
+                last_activity: entry.session.last_peer_activity(),
+                priority: entry.priority,
+                takeover: entry.takeover,
+            })
+            .collect();
+        sessions.sort_by_key(|info| info.established_at);
+        sessions
+    }
+
+    /// Closes `session_id` and forgets it; its serving task stops with its next message.
+    pub fn close(&self, session_id: Uuid) -> bool {
+        let removed = self.entries.lock().remove(&session_id);
+        match removed {
+            Some(entry) => {
+                entry.session.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let mut entries = self.entries.lock();
+        prune(&mut entries, self.first_activity, self.now());
+        entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn now(&self) -> Instant {
+        self.clock.as_ref().map_or_else(Instant::now, SimClock::now)
+    }
+}
+
+/// A running handshake counted against [`SessionRegistry::with_handshake_limit`]; the
+/// slot is released on drop.
+#[derive(Debug)]
+pub struct HandshakeSlot(Arc<AtomicUsize>);
+
+impl Drop for HandshakeSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Default for SessionRegistry {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SESSIONS)
+    }
+}
+
+fn is_open(session: &AlnpSession) -> bool {
+    !matches!(
+        session.state(),
+        SessionState::Closed | SessionState::Failed(_)
+    )
+}
+
+/// Drops closed and failed sessions, first closing preempted ones past their grace.
+fn prune(entries: &mut HashMap<Uuid, Entry>, first_activity: Option<Duration>, now: Instant) {
+    for (session_id, entry) in entries.iter() {
+        let preempted = entry
+            .takeover
+            .is_some_and(|takeover| takeover.preempted == *session_id && takeover.deadline <= now);
+        if preempted || is_half_open(entry, first_activity, now) {
+            entry.session.close();
+        }
+    }
+    entries.retain(|_, entry| is_open(&entry.session));
+    let finished: Vec<Uuid> = entries
+        .values()
+        .filter_map(|entry| entry.takeover)
+        .filter(|takeover| !entries.contains_key(&takeover.preempted))
+        .map(|takeover| takeover.by)
+        .collect();
+    for session_id in finished {
+        if let Some(entry) = entries.get_mut(&session_id) {
+            entry.takeover = None;
+        }
+    }
+}
+
+fn is_observer(session: &AlnpSession) -> bool {
+    session
+        .established()
+        .is_some_and(|established| established.role() == SessionRole::Observer)
+}
+
+/// Whether `entry` has gone `first_activity` without hearing from its controller.
+fn is_half_open(entry: &Entry, first_activity: Option<Duration>, now: Instant) -> bool {
+    first_activity.is_some_and(|deadline| {
+        entry.session.last_peer_activity().is_none()
+            && now.saturating_duration_since(entry.established_at) >= deadline
+    })
+}
+
+fn preemptible(entries: &HashMap<Uuid, Entry>, priority: u8) -> Option<Uuid> {
+    entries
+        .iter()
+        .filter(|(_, entry)| entry.priority < priority && entry.takeover.is_none())
+        .min_by_key(|(_, entry)| (entry.priority, entry.established_at))
+        .map(|(session_id, _)| *session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{SessionKeys, SuiteId};
+    use crate::handshake::HandshakeOutcome;
+    use crate::messages::{CapabilitySet, DeviceIdentity, SessionEstablished};
+    use crate::session::{AlnpRole, TimingConfig};
+
+    fn established() -> AlnpSession {
+        established_at(None)
+    }
+
+    fn established_at(controller_priority: Option<u8>) -> AlnpSession {
+        let session =
+            AlnpSession::start_handshake(AlnpRole::Node, TimingConfig::default()).unwrap();
+        session
+            .finish_handshake(HandshakeOutcome {
+                established: SessionEstablished {
+                    session_id: Uuid::new_v4(),
+                    controller_nonce: vec![1; 32],
+                    device_nonce: vec![2; 32],
+                    capabilities: CapabilitySet::default(),
+                    device_identity: DeviceIdentity {
+                        device_id: "node".into(),
+                        manufacturer_id: "manu".into(),
+                        model_id: "model".into(),
+                        hardware_rev: "rev1".into(),
+                        firmware_rev: "1.0.0".into(),
+                    },
+                    extensions: None,
+                    controller_priority,
+                    role: None,
+                },
+                keys: SessionKeys {
+                    shared_secret: vec![0x11; 32],
+                    control_key: [0x11; 32],
+                    stream_key: [0x22; 32],
+                    suite: SuiteId::default(),
+                    channel_binding: [0; 32],
+                },
+            })
+            .unwrap();
+        session
+    }
+
+    #[test]
+    fn holds_sessions_up_to_the_limit_with_their_own_access() {
+        let registry = SessionRegistry::new(2);
+        let primary = registry
+            .insert(&established(), SessionAccess::Full)
+            .unwrap();
+        let monitor = registry
+            .insert(&established(), SessionAccess::Monitor)
+            .unwrap();
+        assert!(registry.is_full());
+        assert!(matches!(
+            registry.insert(&established(), SessionAccess::Full),
+            Err(HandshakeError::Capability(_))
+        ));
+
+        assert_eq!(registry.access(primary), Some(SessionAccess::Full));
+        let access = registry.access(monitor).unwrap();
+        assert!(!access.allows_frames());
+        assert!(access.allows(&ControlOp::GetStatus));
+        assert!(!access.allows(&ControlOp::SetConfig));
+        assert!(registry.set_access(monitor, SessionAccess::Full));
+        assert_eq!(registry.access(monitor), Some(SessionAccess::Full));
+
+        registry.set_peer(primary, "10.0.0.2:5555".parse().unwrap());
+        let listed = registry.list();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].session_id, primary);
+        assert_eq!(listed[0].peer, Some("10.0.0.2:5555".parse().unwrap()));
+    }
+
+    #[test]
+    fn handshake_slots_are_capped_and_released_on_drop() {
+        let registry = SessionRegistry::new(4).with_handshake_limit(2);
+        let first = registry.begin_handshake().unwrap();
+        let _second = registry.clone().begin_handshake().unwrap();
+        assert!(registry.begin_handshake().is_none());
+        assert_eq!(registry.handshakes(), 2);
+        drop(first);
+        assert!(registry.begin_handshake().is_some());
+        assert_eq!(
+            SessionRegistry::new(0).begin_handshake().map(|_| ()),
+            Some(())
+        );
+    }
+
+    #[test]
+    fn silent_sessions_are_reaped_after_the_first_activity_deadline() {
+        let registry = SessionRegistry::new(2).with_first_activity_deadline(Some(Duration::ZERO));
+        let vanished = established();
+        registry.insert(&vanished, SessionAccess::Full).unwrap();
+        let live = established();
+        live.update_keepalive();
+        let live_id = registry.insert(&live, SessionAccess::Full).unwrap();
+
+        assert!(!registry.is_full());
+        assert_eq!(vanished.state(), SessionState::Closed);
+        let listed = registry.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].session_id, live_id);
+        assert!(listed[0].last_activity.is_some());
+
+        let patient = SessionRegistry::new(1).with_first_activity_deadline(None);
+        patient.insert(&established(), SessionAccess::Full).unwrap();
+        assert!(patient.is_full());
+    }
+
+    #[test]
+    fn closing_one_session_leaves_the_others_and_frees_its_slot() {
+        let registry = SessionRegistry::new(2);
+        let primary = established();
+        let primary_id = registry.insert(&primary, SessionAccess::Full).unwrap();
+        let backup = established();
+        let backup_id = registry.insert(&backup, SessionAccess::Full).unwrap();
+
+        assert!(registry.close(primary_id));
+        assert_eq!(primary.state(), SessionState::Closed);
+        assert_eq!(registry.access(primary_id), None);
+        assert!(backup.ensure_streaming_ready().is_ok());
+        assert_eq!(registry.access(backup_id), Some(SessionAccess::Full));
+
+        // A session that fails on its own gives its slot back too.
+        backup.fail("keepalive lost".into());
+        assert!(registry.is_empty());
+        registry
+            .insert(&established(), SessionAccess::Full)
+            .unwrap();
+        registry
+            .insert(&established(), SessionAccess::Full)
+            .unwrap();
+        assert!(!registry.close(backup_id));
+    }
+
+    #[test]
+    fn higher_priority_controllers_take_over_after_the_grace_period() {
+        let registry = SessionRegistry::new(2);
+        let low_id = registry
+            .insert(&established_at(Some(50)), SessionAccess::Full)
+            .unwrap();
+        registry
+            .insert(&established_at(Some(150)), SessionAccess::Full)
+            .unwrap();
+
+        // Without a grace period nobody is preempted, and never by an equal priority.
+        assert!(registry
+            .admit(&established_at(Some(200)), SessionAccess::Full, None)
+            .is_err());
+        assert_eq!(registry.preemptible(50), None);
+        assert_eq!(registry.preemptible(100), Some(low_id));
+
+        let grace = Duration::from_secs(60);
+        let (by, takeover) = registry
+            .admit(&established(), SessionAccess::Full, Some(grace))
+            .unwrap();
+        let takeover = takeover.unwrap();
+        assert_eq!((takeover.preempted, takeover.by), (low_id, by));
+        assert_eq!(takeover.priority, 100);
+        assert_eq!(registry.takeover(low_id), Some(takeover));
+        // The newcomer waits for the preempted session, which streams on meanwhile.
+        assert!(!registry.may_stream(by));
+        assert!(registry.may_stream(low_id));
+        assert_eq!(registry.preemptible(120), None);
+
+        registry.close(low_id);
+        assert!(registry.may_stream(by));
+        assert_eq!(registry.takeover(by), None);
+    }
+
+    #[test]
+    fn preempted_sessions_close_when_the_grace_period_ends() {
+        let registry = SessionRegistry::new(1);
+        let low = established_at(Some(10));
+        registry.insert(&low, SessionAccess::Full).unwrap();
+        let (by, _) = registry
+            .admit(&established(), SessionAccess::Full, Some(Duration::ZERO))
+            .unwrap();
+        assert!(registry.may_stream(by));
+        assert_eq!(low.state(), SessionState::Closed);
+        assert_eq!(registry.list().len(), 1);
+    }
+}
@@ -0,0 +1,361 @@
+//! Session resumption across controller restarts.
+//!
+//! After each handshake the controller captures a [`ResumptionTicket`] and saves it in a
+//! [`SessionStore`]. When the controller process restarts it loads the ticket and calls
+//! [`AlnpSession::resume`], which runs the abbreviated `session_resume` exchange instead of
+//! discovery plus a full signed handshake. Devices keep the matching secrets in a
+//! [`ResumptionCache`]; each secret resumes at most once, and every resumed session issues
+//! a new one.
+//!
+//! Tickets contain the resumption secret, so stores must be as protected as the
+//! controller's signing key.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{AlnpRole, AlnpSession, TimingConfig};
+use crate::codec;
+use crate::control::ControlClient;
+use crate::crypto::{resumption_secret, KeyExchange};
+use crate::handshake::resume::ClientResumption;
+use crate::handshake::{
+    HandshakeContext, HandshakeError, HandshakeParticipant, HandshakeTransport, ResumableSession,
+    ResumptionLookup,
+};
+use crate::messages::{CapabilitySet, DeviceIdentity};
+
+/// How long a device keeps a session resumable unless configured otherwise.
+pub const DEFAULT_RESUMPTION_TTL: Duration = Duration::from_secs(3600);
+
+/// Controller-side material needed to resume a session after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResumptionTicket {
+    pub session_id: Uuid,
+    pub secret: [u8; 32],
+    pub device_identity: DeviceIdentity,
+    pub capabilities: CapabilitySet,
+    /// Stream profile bound when the ticket was captured, so the same profile can be
+    /// re-applied after resuming.
+    pub config_id: Option<String>,
+    pub issued_at_ms: u64,
+}
+
+impl ResumptionTicket {
+    /// Captures the resumable state of an established session.
+    pub fn capture(session: &AlnpSession) -> Result<Self, HandshakeError> {
+        let established = session
+            .established()
+            .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+        let keys = session
+            .keys()
+            .ok_or_else(|| HandshakeError::Protocol("session keys missing".into()))?;
+        let secret =
+            resumption_secret(&keys).map_err(|e| HandshakeError::Authentication(e.to_string()))?;
+        Ok(Self {
+            session_id: established.session_id,
+            secret,
+            device_identity: established.device_identity,
+            capabilities: established.capabilities,
+            config_id: session.profile_config_id(),
+            issued_at_ms: ControlClient::now_ms(),
+        })
+    }
+}
+
+/// Failures reading or writing persisted tickets.
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("ticket storage failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("ticket encoding failed: {0}")]
+    Codec(String),
+}
+
+/// Persistence for resumption tickets, keyed by an application-chosen peer name such as
+/// the node's address.
+pub trait SessionStore: Send + Sync + std::fmt::Debug {
+    fn save(&self, peer: &str, ticket: &ResumptionTicket) -> Result<(), StoreError>;
+    fn load(&self, peer: &str) -> Result<Option<ResumptionTicket>, StoreError>;
+    fn remove(&self, peer: &str) -> Result<(), StoreError>;
+}
+
+/// Process-local store; tickets do not survive a restart.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySessionStore {
+    tickets: Arc<Mutex<HashMap<String, ResumptionTicket>>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn save(&self, peer: &str, ticket: &ResumptionTicket) -> Result<(), StoreError> {
+        self.tickets.lock().insert(peer.to_string(), ticket.clone());
+        Ok(())
+    }
+
+    fn load(&self, peer: &str) -> Result<Option<ResumptionTicket>, StoreError> {
+        Ok(self.tickets.lock().get(peer).cloned())
+    }
+
+    fn remove(&self, peer: &str) -> Result<(), StoreError> {
+        self.tickets.lock().remove(peer);
+        Ok(())
+    }
+}
+
+/// Stores one CBOR-encoded ticket file per peer under a directory.
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    /// Uses `dir`, creating it on first save.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, peer: &str) -> PathBuf {
+        // Peer names are usually socket addresses; keep only filename-safe characters.
+        let name: String = peer
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{}.ticket", name))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn save(&self, peer: &str, ticket: &ResumptionTicket) -> Result<(), StoreError> {
+        fs::create_dir_all(&self.dir)?;
+        let bytes = codec::to_vec(ticket).map_err(|e| StoreError::Codec(e.to_string()))?;
+        // Write then rename so a crash mid-save never leaves a truncated ticket behind.
+        let path = self.path(peer);
+        let staging = path.with_extension("tmp");
+        fs::write(&staging, bytes)?;
+        fs::rename(staging, path)?;
+        Ok(())
+    }
+
+    fn load(&self, peer: &str) -> Result<Option<ResumptionTicket>, StoreError> {
+        match fs::read(self.path(peer)) {
+            Ok(bytes) => codec::decode_untrusted(&bytes)
+                .map(Some)
+                .map_err(|e| StoreError::Codec(e.to_string())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn remove(&self, peer: &str) -> Result<(), StoreError> {
+        match fs::remove_file(self.path(peer)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Device-side record of sessions a restarted controller may resume.
+#[derive(Debug, Clone)]
+pub struct ResumptionCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<Uuid, (Instant, ResumableSession)>>>,
+}
+
+impl ResumptionCache {
+    /// Keeps each session resumable for `ttl` after it was established.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Makes an established session resumable.
+    pub fn insert(&self, session: &AlnpSession) -> Result<(), HandshakeError> {
+        let established = session
+            .established()
+            .ok_or_else(|| HandshakeError::Protocol("session not established".into()))?;
+        let keys = session
+            .keys()
+            .ok_or_else(|| HandshakeError::Protocol("session keys missing".into()))?;
+        let secret =
+            resumption_secret(&keys).map_err(|e| HandshakeError::Authentication(e.to_string()))?;
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        entries.retain(|_, (since, _)| now.duration_since(*since) < self.ttl);
+        entries.insert(
+            established.session_id,
+            (
+                now,
+                ResumableSession {
+                    secret,
+                    capabilities: established.capabilities,
+                },
+            ),
+        );
+        Ok(())
+    }
+
+    /// Number of sessions currently resumable.
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ResumptionCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_RESUMPTION_TTL)
+    }
+}
+
+impl ResumptionLookup for ResumptionCache {
+    fn take(&self, session_id: &Uuid) -> Option<ResumableSession> {
+        let (since, entry) = self.entries.lock().remove(session_id)?;
+        (since.elapsed() < self.ttl).then_some(entry)
+    }
+}
+
+impl AlnpSession {
+    /// Resumes the session described by `ticket` with the abbreviated handshake.
+    ///
+    /// Fails with [`HandshakeError::Remote`] carrying `SESSION_EXPIRED` when the device no
+    /// longer holds the session; callers then fall back to [`AlnpSession::connect`] on the
+    /// same transport.
+    pub async fn resume<T, K>(
+        ticket: &ResumptionTicket,
+        key_exchange: K,
+        context: HandshakeContext,
+        timing: TimingConfig,
+        transport: &mut T,
+    ) -> Result<Self, HandshakeError>
+    where
+        T: HandshakeTransport + Send,
+        K: KeyExchange + Send + Sync,
+    {
+        let session = Self::start_handshake(AlnpRole::Controller, timing)?;
+        let driver = ClientResumption {
+            resumed_session: ticket.session_id,
+            secret: ticket.secret,
+            device_identity: ticket.device_identity.clone(),
+            capabilities: ticket.capabilities.clone(),
+            key_exchange,
+            context,
+        };
+        let outcome = driver.run(transport).await?;
+        session.finish_handshake(outcome)?;
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::X25519KeyExchange;
+    use crate::messages::ErrorCode;
+    use crate::session::Ed25519Authenticator;
+    use crate::testing::{SimulatedDevice, SimulatorConfig};
+
+    fn identity(name: &str) -> DeviceIdentity {
+        DeviceIdentity {
+            device_id: name.into(),
+            manufacturer_id: "manu".into(),
+            model_id: "model".into(),
+            hardware_rev: "rev1".into(),
+            firmware_rev: "1.0.0".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn restarted_controller_resumes_from_file_store() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let store = FileSessionStore::new(
+            std::env::temp_dir().join(format!("alpine-resume-{}", Uuid::new_v4())),
+        );
+
+        let mut link = device.connect();
+        let original = AlnpSession::connect(
+            identity("controller"),
+            CapabilitySet::default(),
+            Ed25519Authenticator::new(device.credentials().clone()),
+            X25519KeyExchange::new(),
+            HandshakeContext::default(),
+            &mut link,
+        )
+        .await
+        .unwrap();
+        store
+            .save("node", &ResumptionTicket::capture(&original).unwrap())
+            .unwrap();
+        drop(original);
+
+        // A new process only has the store.
+        let ticket = store.load("node").unwrap().unwrap();
+        let mut link = device.connect();
+        let resumed = AlnpSession::resume(
+            &ticket,
+            X25519KeyExchange::new(),
+            HandshakeContext::default(),
+            TimingConfig::default(),
+            &mut link,
+        )
+        .await
+        .unwrap();
+        let established = resumed.established().unwrap();
+        assert_ne!(established.session_id, ticket.session_id);
+        assert_eq!(established.device_identity, ticket.device_identity);
+        assert_eq!(
+            device.session().unwrap().keys().unwrap().control_key,
+            resumed.keys().unwrap().control_key
+        );
+
+        // The ticket was consumed; replaying it is refused and the same exchange falls
+        // back to a full handshake.
+        let mut link = device.connect();
+        let replay = AlnpSession::resume(
+            &ticket,
+            X25519KeyExchange::new(),
+            HandshakeContext::default(),
+            TimingConfig::default(),
+            &mut link,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(replay.code(), Some(ErrorCode::SessionExpired));
+        AlnpSession::connect(
+            identity("controller"),
+            CapabilitySet::default(),
+            Ed25519Authenticator::new(device.credentials().clone()),
+            X25519KeyExchange::new(),
+            HandshakeContext::default(),
+            &mut link,
+        )
+        .await
+        .unwrap();
+        store.remove("node").unwrap();
+        assert!(store.load("node").unwrap().is_none());
+    }
+}
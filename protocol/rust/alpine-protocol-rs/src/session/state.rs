@@ -37,6 +37,19 @@ impl SessionState {
         }
     }
 
+    /// Short lowercase name used in logs and metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionState::Init => "init",
+            SessionState::Handshake => "handshake",
+            SessionState::Authenticated { .. } => "authenticated",
+            SessionState::Ready { .. } => "ready",
+            SessionState::Streaming { .. } => "streaming",
+            SessionState::Failed(_) => "failed",
+            SessionState::Closed => "closed",
+        }
+    }
+
     pub fn is_failed(&self) -> bool {
         matches!(self, SessionState::Failed(_))
     }
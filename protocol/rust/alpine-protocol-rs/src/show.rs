@@ -0,0 +1,451 @@
+//! Recorded shows and standalone fallback playback on nodes.
+//!
+//! A [`ShowRecorder`] captures timed looks from a stream into a [`Show`]. Controllers
+//! upload a show to a node in chunks with the `upload_show` control op and arm it with
+//! `set_fallback`; once no authenticated controller has streamed for the configured idle
+//! time the node loops the show on its own, and streaming takes over again as soon as
+//! frames resume.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::codec;
+use crate::control::ControlHandlers;
+use crate::messages::{ControlOp, UniverseId};
+use crate::scene::Look;
+
+/// Bytes of encoded show carried by each `upload_show` chunk, sized so an envelope stays
+/// well inside one datagram.
+pub const SHOW_CHUNK_BYTES: usize = 1024;
+
+/// Largest encoded show a node accepts.
+pub const MAX_SHOW_BYTES: u64 = 4 * 1024 * 1024;
+
+/// One recorded look for a universe, `at_ms` after the show starts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShowFrame {
+    pub at_ms: u64,
+    pub universe: UniverseId,
+    pub channels: Vec<u16>,
+}
+
+/// Timed looks played in a loop of `length_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Show {
+    pub name: Option<String>,
+    pub length_ms: u64,
+    /// Ordered by `at_ms`.
+    pub frames: Vec<ShowFrame>,
+}
+
+impl Show {
+    /// Look `offset_ms` into the show: the latest frame at or before the offset for each
+    /// universe.
+    pub fn look_at(&self, offset_ms: u64) -> Look {
+        let mut look = Look::new();
+        for frame in self.frames.iter().take_while(|f| f.at_ms <= offset_ms) {
+            look.insert(frame.universe, frame.channels.clone());
+        }
+        look
+    }
+
+    /// Look `elapsed` into looped playback.
+    pub fn looped_at(&self, elapsed: Duration) -> Look {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        match self.length_ms {
+            0 => self.look_at(0),
+            length => self.look_at(elapsed_ms % length),
+        }
+    }
+}
+
+/// Captures streamed looks into a [`Show`].
+#[derive(Debug, Clone)]
+pub struct ShowRecorder {
+    started: Instant,
+    frames: Vec<ShowFrame>,
+}
+
+impl ShowRecorder {
+    pub fn new(started: Instant) -> Self {
+        Self {
+            started,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Records `channels` for `universe` as seen at `now`.
+    pub fn record(&mut self, universe: UniverseId, channels: &[u16], now: Instant) {
+        self.frames.push(ShowFrame {
+            at_ms: now.saturating_duration_since(self.started).as_millis() as u64,
+            universe,
+            channels: channels.to_vec(),
+        });
+    }
+
+    /// Ends the recording at `now`; playback loops back to the start at that point.
+    pub fn finish(mut self, name: Option<String>, now: Instant) -> Show {
+        self.frames.sort_by_key(|frame| frame.at_ms);
+        let recorded = now.saturating_duration_since(self.started).as_millis() as u64;
+        let last = self.frames.last().map(|frame| frame.at_ms + 1).unwrap_or(0);
+        Show {
+            name,
+            length_ms: recorded.max(last),
+            frames: self.frames,
+        }
+    }
+}
+
+/// Payload of `upload_show`: a slice of the CBOR-encoded [`Show`].
+///
+/// Chunks are sent in order; offset zero starts a new transfer and the chunk that reaches
+/// `total` installs the show.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShowChunk {
+    pub offset: u64,
+    pub total: u64,
+    pub data: Vec<u8>,
+}
+
+impl ShowChunk {
+    /// Splits `show` into the chunks that upload it.
+    pub fn split(show: &Show) -> Result<Vec<ShowChunk>, ShowError> {
+        let bytes = codec::to_vec(show).map_err(|e| ShowError::Codec(e.to_string()))?;
+        let total = bytes.len() as u64;
+        Ok(bytes
+            .chunks(SHOW_CHUNK_BYTES)
+            .enumerate()
+            .map(|(i, data)| ShowChunk {
+                offset: (i * SHOW_CHUNK_BYTES) as u64,
+                total,
+                data: data.to_vec(),
+            })
+            .collect())
+    }
+}
+
+/// Payload of `set_fallback`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct FallbackConfig {
+    /// Streaming silence before the stored show takes over; zero or absent disables
+    /// fallback.
+    #[serde(default)]
+    pub idle_ms: u64,
+}
+
+/// Show and fallback settings a node persists.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct StoredShow {
+    pub show: Option<Show>,
+    pub fallback: FallbackConfig,
+}
+
+/// Failures receiving, storing, or decoding shows.
+#[derive(Debug, Error)]
+pub enum ShowError {
+    #[error("chunk at offset {got} does not continue the transfer at {expected}")]
+    OutOfOrder { expected: u64, got: u64 },
+    #[error("show of {0} bytes exceeds the node's limit")]
+    TooLarge(u64),
+    #[error("show storage failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("show encoding failed: {0}")]
+    Codec(String),
+}
+
+/// Persistence for the node's show and fallback settings.
+pub trait ShowStore: Send + Sync + std::fmt::Debug {
+    fn save(&self, stored: &StoredShow) -> Result<(), ShowError>;
+    fn load(&self) -> Result<StoredShow, ShowError>;
+}
+
+/// Process-local store; the show does not survive a restart.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryShowStore {
+    stored: Arc<Mutex<StoredShow>>,
+}
+
+impl MemoryShowStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ShowStore for MemoryShowStore {
+    fn save(&self, stored: &StoredShow) -> Result<(), ShowError> {
+        self.stored.lock().clone_from(stored);
+        Ok(())
+    }
+
+    fn load(&self) -> Result<StoredShow, ShowError> {
+        Ok(self.stored.lock().clone())
+    }
+}
+
+/// Stores the show as one CBOR file, so fallback survives power loss.
+#[derive(Debug, Clone)]
+pub struct FileShowStore {
+    path: PathBuf,
+}
+
+impl FileShowStore {
+    /// Uses the file at `path`, creating its directory on first save.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ShowStore for FileShowStore {
+    fn save(&self, stored: &StoredShow) -> Result<(), ShowError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let bytes = codec::to_vec(stored).map_err(|e| ShowError::Codec(e.to_string()))?;
+        // Write then rename so a power cut mid-save keeps the previous show.
+        let staging = self.path.with_extension("tmp");
+        fs::write(&staging, bytes)?;
+        fs::rename(staging, &self.path)?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<StoredShow, ShowError> {
+        match fs::read(&self.path) {
+            // The file holds settings alongside the show, so allow some headroom.
+            Ok(bytes) => codec::decode_untrusted_within(&bytes, 2 * MAX_SHOW_BYTES as usize)
+                .map_err(|e| ShowError::Codec(e.to_string())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(StoredShow::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Transfer in progress.
+#[derive(Debug)]
+struct Upload {
+    total: u64,
+    bytes: Vec<u8>,
+}
+
+/// Node-side fallback player: receives uploaded shows and loops the stored show while
+/// no controller is streaming.
+#[derive(Debug)]
+pub struct FallbackPlayer {
+    store: Arc<dyn ShowStore>,
+    stored: Mutex<StoredShow>,
+    upload: Mutex<Option<Upload>>,
+    last_stream: Mutex<Instant>,
+    paused: Mutex<bool>,
+}
+
+impl FallbackPlayer {
+    /// Loads the persisted show; the idle timer starts now, so a node that boots with no
+    /// controller falls back after the configured idle time.
+    pub fn open(store: Arc<dyn ShowStore>) -> Result<Self, ShowError> {
+        let stored = store.load()?;
+        Ok(Self {
+            store,
+            stored: Mutex::new(stored),
+            upload: Mutex::new(None),
+            last_stream: Mutex::new(Instant::now()),
+            paused: Mutex::new(false),
+        })
+    }
+
+    /// Notes an authenticated frame at `now`, ending any fallback playback.
+    pub fn observe(&self, now: Instant) {
+        *self.last_stream.lock() = now;
+    }
+
+    /// Notes that the controller paused (`true`) or resumed (`false`) its stream at
+    /// `now`. A paused controller still owns the output, so the idle timer only starts
+    /// again on resume.
+    pub fn set_paused(&self, paused: bool, now: Instant) {
+        *self.paused.lock() = paused;
+        self.observe(now);
+    }
+
+    /// Accepts the next `upload_show` chunk; returns `true` once the show is installed.
+    pub fn receive(&self, chunk: ShowChunk) -> Result<bool, ShowError> {
+        if chunk.total > MAX_SHOW_BYTES {
+            return Err(ShowError::TooLarge(chunk.total));
+        }
+        let mut upload = self.upload.lock();
+        if chunk.offset == 0 {
+            *upload = Some(Upload {
+                total: chunk.total,
+                bytes: Vec::with_capacity(chunk.total as usize),
+            });
+        }
+        let active = upload.as_mut().ok_or(ShowError::OutOfOrder {
+            expected: 0,
+            got: chunk.offset,
+        })?;
+        let expected = active.bytes.len() as u64;
+        if chunk.offset != expected || chunk.total != active.total {
+            return Err(ShowError::OutOfOrder {
+                expected,
+                got: chunk.offset,
+            });
+        }
+        if expected + chunk.data.len() as u64 > active.total {
+            return Err(ShowError::TooLarge(expected + chunk.data.len() as u64));
+        }
+        active.bytes.extend_from_slice(&chunk.data);
+        if (active.bytes.len() as u64) < active.total {
+            return Ok(false);
+        }
+        let bytes = std::mem::take(&mut active.bytes);
+        upload.take();
+        let show: Show = codec::decode_untrusted_within(&bytes, MAX_SHOW_BYTES as usize)
+            .map_err(|e| ShowError::Codec(e.to_string()))?;
+        self.install(show)?;
+        Ok(true)
+    }
+
+    /// Stores `show` as the fallback show.
+    pub fn install(&self, show: Show) -> Result<(), ShowError> {
+        let mut stored = self.stored.lock();
+        let mut next = stored.clone();
+        next.show = Some(show);
+        self.store.save(&next)?;
+        *stored = next;
+        Ok(())
+    }
+
+    /// Applies `set_fallback` settings.
+    pub fn configure(&self, fallback: FallbackConfig) -> Result<(), ShowError> {
+        let mut stored = self.stored.lock();
+        let mut next = stored.clone();
+        next.fallback = fallback;
+        self.store.save(&next)?;
+        *stored = next;
+        Ok(())
+    }
+
+    /// Look to output at `now` while falling back, or `None` while a controller streams
+    /// or fallback is not armed.
+    ///
+    /// The show starts from its beginning when the idle time runs out.
+    pub fn output(&self, now: Instant) -> Option<Look> {
+        let stored = self.stored.lock();
+        let show = stored.show.as_ref()?;
+        let idle = Duration::from_millis(stored.fallback.idle_ms);
+        if idle.is_zero() || *self.paused.lock() {
+            return None;
+        }
+        let silent = now.saturating_duration_since(*self.last_stream.lock());
+        silent
+            .checked_sub(idle)
+            .map(|playing| show.looped_at(playing))
+    }
+
+    /// Whether the stored show is playing at `now`.
+    pub fn is_playing(&self, now: Instant) -> bool {
+        self.output(now).is_some()
+    }
+
+    /// Registers handlers for `upload_show` and `set_fallback`.
+    ///
+    /// Each `upload_show` ack carries the bytes received so far in its detail.
+    pub fn register(self: &Arc<Self>, handlers: &ControlHandlers) {
+        let player = self.clone();
+        handlers.on(ControlOp::UploadShow, move |chunk: ShowChunk| {
+            let received = chunk.offset + chunk.data.len() as u64;
+            player
+                .receive(chunk)
+                .map(|_| Some(received.to_string()))
+                .map_err(|e| e.to_string())
+        });
+        let player = self.clone();
+        handlers.on(ControlOp::SetFallback, move |config: FallbackConfig| {
+            player
+                .configure(config)
+                .map(|_| None)
+                .map_err(|e| e.to_string())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn recorded(start: Instant) -> Show {
+        let mut recorder = ShowRecorder::new(start);
+        recorder.record(0, &[10], start);
+        recorder.record(0, &[20], start + Duration::from_millis(500));
+        recorder.finish(Some("chase".into()), start + Duration::from_secs(1))
+    }
+
+    #[test]
+    fn show_loops_recorded_looks() {
+        let start = Instant::now();
+        let show = recorded(start);
+        assert_eq!(show.length_ms, 1000);
+        assert_eq!(
+            show.looped_at(Duration::from_millis(700)),
+            Look::from([(0, vec![20])])
+        );
+        assert_eq!(
+            show.looped_at(Duration::from_millis(1200)),
+            Look::from([(0, vec![10])])
+        );
+    }
+
+    #[test]
+    fn uploaded_show_plays_while_streaming_is_idle() {
+        let path = std::env::temp_dir()
+            .join(format!("alpine-show-{}", Uuid::new_v4()))
+            .join("fallback.show");
+        let player = FallbackPlayer::open(Arc::new(FileShowStore::new(&path))).unwrap();
+        let start = Instant::now();
+        let show = recorded(start);
+
+        let chunks = ShowChunk::split(&show).unwrap();
+        let mut late = chunks[0].clone();
+        late.offset = 1;
+        assert!(matches!(
+            player.receive(late),
+            Err(ShowError::OutOfOrder { .. })
+        ));
+        let mut installed = false;
+        for chunk in chunks {
+            installed = player.receive(chunk).unwrap();
+        }
+        assert!(installed);
+        player.observe(start);
+        assert_eq!(player.output(start + Duration::from_secs(10)), None);
+
+        player.configure(FallbackConfig { idle_ms: 2000 }).unwrap();
+        assert_eq!(player.output(start + Duration::from_secs(1)), None);
+        assert_eq!(
+            player.output(start + Duration::from_millis(2600)),
+            Some(Look::from([(0, vec![20])]))
+        );
+
+        // Streaming resumes and playback stops until the next idle period.
+        player.observe(start + Duration::from_secs(3));
+        assert!(!player.is_playing(start + Duration::from_secs(4)));
+
+        // A paused controller keeps the output however long the pause lasts.
+        player.set_paused(true, start + Duration::from_secs(4));
+        assert!(!player.is_playing(start + Duration::from_secs(60)));
+        player.set_paused(false, start + Duration::from_secs(60));
+        assert!(!player.is_playing(start + Duration::from_secs(61)));
+        assert!(player.is_playing(start + Duration::from_secs(63)));
+
+        // The armed show survives a reboot.
+        let player = FallbackPlayer::open(Arc::new(FileShowStore::new(&path))).unwrap();
+        player.observe(start);
+        assert!(player.is_playing(start + Duration::from_secs(2)));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,1142 @@
+//! Deterministic simulation of controllers and nodes.
+//!
+//! Adaptation, failover, and takeover hinge on timing and loss patterns that real sockets
+//! cannot reproduce on demand. A [`SimWorld`] runs any number of controllers and nodes in
+//! one thread against a [`SimClock`] that only moves when the simulation advances it,
+//! over a [`SimNetwork`] whose loss and latency come from per-link [`LinkConditions`],
+//! scripted cuts and drops, and a seeded random source. The same seed and script replay
+//! the same run, datagram for datagram.
+//!
+//! Sessions are set up from seeded keys instead of running the handshake, which
+//! [`crate::testing::SimulatedDevice`] covers. From there, frames and arrival reports
+//! cross the simulated network, nodes admit and preempt sessions through a
+//! [`SessionRegistry`] on simulated time, and controllers adapt through the same
+//! [`AlnpStream`] state a real controller keeps.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::codec;
+use crate::crypto::{SessionKeys, SuiteId};
+use crate::handshake::{HandshakeError, HandshakeOutcome};
+use crate::messages::{
+    CapabilitySet, ChannelBuffer, ChannelFormat, DeviceIdentity, FrameEnvelope, MessageType,
+    SessionEstablished, UniverseId,
+};
+use crate::profile::StreamProfile;
+use crate::session::registry::{SessionAccess, SessionRegistry};
+use crate::session::{AlnpRole, AlnpSession, TimingConfig};
+use crate::stream::{AlnpStream, FrameTransport, StreamEvent, UniverseHealth};
+
+/// Wall clock reading every simulation starts at, in microseconds since the epoch.
+pub const SIM_EPOCH_US: u64 = 1_700_000_000_000_000;
+
+/// Frames per second controllers stream at unless the world is configured otherwise.
+pub const DEFAULT_SIM_FRAME_RATE: u32 = 40;
+
+/// How long after sending a frame is still on time unless configured otherwise.
+pub const DEFAULT_SIM_FRAME_DEADLINE: Duration = Duration::from_millis(20);
+
+/// Time that only moves when the simulation advances it.
+///
+/// Clones share the same time. Readings are [`Instant`]s offset from the moment the clock
+/// was created, so they can be handed to code that takes `now`.
+#[derive(Debug, Clone)]
+pub struct SimClock {
+    origin: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            elapsed: Arc::default(),
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.origin + *self.elapsed.lock()
+    }
+
+    /// Simulated wall clock, in microseconds since the epoch.
+    pub fn now_us(&self) -> u64 {
+        SIM_EPOCH_US + self.elapsed().as_micros() as u64
+    }
+
+    /// Time simulated so far.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock() += by;
+    }
+
+    /// Moves the clock to `at`; never backwards.
+    pub fn advance_to(&self, at: Instant) {
+        let mut elapsed = self.elapsed.lock();
+        *elapsed = (*elapsed).max(at.saturating_duration_since(self.origin));
+    }
+}
+
+/// Address of a controller or node on a [`SimNetwork`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SimAddr(pub u32);
+
+/// How one direction of a link treats the datagrams sent over it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkConditions {
+    /// One-way delay of every datagram.
+    pub latency: Duration,
+    /// Extra delay of up to this much, drawn per datagram; datagrams may overtake each
+    /// other.
+    pub jitter: Duration,
+    /// Probability (0.0 to 1.0) that a datagram is lost.
+    pub loss: f64,
+}
+
+/// Counters of one direction of a link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub sent: u64,
+    pub lost: u64,
+    pub delivered: u64,
+}
+
+/// A datagram as delivered by a [`SimNetwork`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Datagram {
+    pub from: SimAddr,
+    pub to: SimAddr,
+    /// Position among the datagrams sent over the link, from 1, counting lost ones; a
+    /// receiver sees loss as gaps.
+    pub seq: u64,
+    pub sent_at: Instant,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    at: Instant,
+    /// Send order, so datagrams due at the same instant arrive in the order sent.
+    order: u64,
+    datagram: Datagram,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    /// Reversed, so the [`BinaryHeap`] yields the earliest datagram first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.at, other.order).cmp(&(self.at, self.order))
+    }
+}
+
+type Link = (SimAddr, SimAddr);
+
+#[derive(Debug)]
+struct NetworkState {
+    rng: StdRng,
+    default_link: LinkConditions,
+    links: HashMap<Link, LinkConditions>,
+    cut: HashSet<Link>,
+    down: HashSet<SimAddr>,
+    drops: HashMap<Link, u32>,
+    seqs: HashMap<Link, u64>,
+    stats: HashMap<Link, LinkStats>,
+    in_flight: BinaryHeap<InFlight>,
+    sent: u64,
+    inboxes: HashMap<SimAddr, VecDeque<Datagram>>,
+}
+
+impl NetworkState {
+    fn lose(&mut self, link: Link, conditions: &LinkConditions) -> bool {
+        if self.down.contains(&link.0) || self.down.contains(&link.1) || self.cut.contains(&link) {
+            return true;
+        }
+        if let Some(remaining) = self.drops.get_mut(&link) {
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.drops.remove(&link);
+            }
+            return true;
+        }
+        conditions.loss > 0.0 && self.rng.gen_bool(conditions.loss.min(1.0))
+    }
+}
+
+/// Datagram network between simulated endpoints, driven by a [`SimClock`].
+///
+/// Sending never fails, like UDP; lost datagrams only show in [`SimNetwork::stats`] and
+/// as gaps in [`Datagram::seq`]. Cloning yields another handle to the same network.
+#[derive(Debug, Clone)]
+pub struct SimNetwork {
+    clock: SimClock,
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl SimNetwork {
+    /// Lossless, zero-latency network whose loss and jitter are drawn from `seed`.
+    pub fn new(clock: SimClock, seed: u64) -> Self {
+        Self {
+            clock,
+            state: Arc::new(Mutex::new(NetworkState {
+                rng: StdRng::seed_from_u64(seed),
+                default_link: LinkConditions::default(),
+                links: HashMap::new(),
+                cut: HashSet::new(),
+                down: HashSet::new(),
+                drops: HashMap::new(),
+                seqs: HashMap::new(),
+                stats: HashMap::new(),
+                in_flight: BinaryHeap::new(),
+                sent: 0,
+                inboxes: HashMap::new(),
+            })),
+        }
+    }
+
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    /// Conditions of every link not given its own with [`SimNetwork::set_link`].
+    pub fn set_default_link(&self, conditions: LinkConditions) {
+        self.state.lock().default_link = conditions;
+    }
+
+    /// Conditions of datagrams sent from `from` to `to`; the reverse direction keeps its
+    /// own. Datagrams already in flight keep the conditions they were sent under.
+    pub fn set_link(&self, from: SimAddr, to: SimAddr, conditions: LinkConditions) {
+        self.state.lock().links.insert((from, to), conditions);
+    }
+
+    pub fn link(&self, from: SimAddr, to: SimAddr) -> LinkConditions {
+        let state = self.state.lock();
+        state
+            .links
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(state.default_link)
+    }
+
+    /// Loses everything sent between `a` and `b`, both ways, until [`SimNetwork::heal`].
+    pub fn cut(&self, a: SimAddr, b: SimAddr) {
+        let mut state = self.state.lock();
+        state.cut.insert((a, b));
+        state.cut.insert((b, a));
+    }
+
+    pub fn heal(&self, a: SimAddr, b: SimAddr) {
+        let mut state = self.state.lock();
+        state.cut.remove(&(a, b));
+        state.cut.remove(&(b, a));
+    }
+
+    /// Takes `addr` off the network, as if it crashed: what it sends and what reaches it,
+    /// including datagrams already in flight, is lost until [`SimNetwork::restore`].
+    pub fn crash(&self, addr: SimAddr) {
+        let mut state = self.state.lock();
+        state.down.insert(addr);
+        state.inboxes.remove(&addr);
+    }
+
+    pub fn restore(&self, addr: SimAddr) {
+        self.state.lock().down.remove(&addr);
+    }
+
+    /// Loses the next `count` datagrams from `from` to `to` whatever the link's loss.
+    pub fn drop_next(&self, from: SimAddr, to: SimAddr, count: u32) {
+        if count > 0 {
+            *self.state.lock().drops.entry((from, to)).or_default() += count;
+        }
+    }
+
+    /// Sends `bytes` from `from` to `to`; it arrives once the clock reaches its delivery
+    /// time and [`SimNetwork::deliver_due`] runs, unless lost.
+    pub fn send(&self, from: SimAddr, to: SimAddr, bytes: &[u8]) {
+        let now = self.clock.now();
+        let mut state = self.state.lock();
+        let link = (from, to);
+        let conditions = state
+            .links
+            .get(&link)
+            .copied()
+            .unwrap_or(state.default_link);
+        let seq = {
+            let seq = state.seqs.entry(link).or_default();
+            *seq += 1;
+            *seq
+        };
+        state.stats.entry(link).or_default().sent += 1;
+        if state.lose(link, &conditions) {
+            state.stats.entry(link).or_default().lost += 1;
+            return;
+        }
+        let jitter = if conditions.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            let max = conditions.jitter.as_micros() as u64;
+            Duration::from_micros(state.rng.gen_range(0..=max))
+        };
+        state.sent += 1;
+        let order = state.sent;
+        state.in_flight.push(InFlight {
+            at: now + conditions.latency + jitter,
+            order,
+            datagram: Datagram {
+                from,
+                to,
+                seq,
+                sent_at: now,
+                bytes: bytes.to_vec(),
+            },
+        });
+    }
+
+    /// When the next datagram in flight is due, if any.
+    pub fn next_delivery(&self) -> Option<Instant> {
+        self.state.lock().in_flight.peek().map(|next| next.at)
+    }
+
+    /// Moves every datagram due by now into its receiver's inbox; returns how many.
+    pub fn deliver_due(&self) -> usize {
+        let now = self.clock.now();
+        let mut state = self.state.lock();
+        let mut delivered = 0;
+        while state.in_flight.peek().is_some_and(|next| next.at <= now) {
+            let Some(InFlight { datagram, .. }) = state.in_flight.pop() else {
+                break;
+            };
+            let link = (datagram.from, datagram.to);
+            if state.down.contains(&datagram.to) {
+                state.stats.entry(link).or_default().lost += 1;
+                continue;
+            }
+            state.stats.entry(link).or_default().delivered += 1;
+            state
+                .inboxes
+                .entry(datagram.to)
+                .or_default()
+                .push_back(datagram);
+            delivered += 1;
+        }
+        delivered
+    }
+
+    /// Next datagram delivered to `addr`, in arrival order.
+    pub fn recv(&self, addr: SimAddr) -> Option<Datagram> {
+        self.state.lock().inboxes.get_mut(&addr)?.pop_front()
+    }
+
+    pub fn stats(&self, from: SimAddr, to: SimAddr) -> LinkStats {
+        self.state
+            .lock()
+            .stats
+            .get(&(from, to))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Frame transport sending from `from` to `to` over this network.
+    pub fn socket(&self, from: SimAddr, to: SimAddr) -> SimSocket {
+        SimSocket {
+            network: self.clone(),
+            from,
+            to,
+        }
+    }
+}
+
+/// One end of a [`SimNetwork`] link, usable wherever a [`FrameTransport`] is.
+#[derive(Debug, Clone)]
+pub struct SimSocket {
+    network: SimNetwork,
+    from: SimAddr,
+    to: SimAddr,
+}
+
+impl SimSocket {
+    pub fn local(&self) -> SimAddr {
+        self.from
+    }
+
+    pub fn peer(&self) -> SimAddr {
+        self.to
+    }
+}
+
+impl FrameTransport for SimSocket {
+    fn send_frame(&self, bytes: &[u8]) -> Result<(), String> {
+        self.network.send(self.from, self.to, bytes);
+        Ok(())
+    }
+}
+
+/// What a simulated node tells a controller.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum NodeReport {
+    /// A frame arrived; arguments follow [`AlnpStream::record_universe_arrival`].
+    Arrival {
+        universe: UniverseId,
+        sequence: u64,
+        arrival_us: u64,
+        deadline_us: u64,
+    },
+    /// Another controller is taking the node over.
+    Preempted { priority: u8, grace_us: u64 },
+}
+
+/// Look a node applied to one universe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedLook {
+    /// Session whose frame set it.
+    pub session_id: Uuid,
+    pub levels: ChannelBuffer,
+    /// Simulated wall clock when it was applied.
+    pub applied_us: u64,
+}
+
+/// Counters describing the frames a simulated node has seen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimNodeStats {
+    pub frames_applied: u64,
+    /// Frames of open sessions the registry would not let stream, e.g. while taking over.
+    pub frames_refused: u64,
+    /// Frames that did not decode or belonged to no open session.
+    pub frames_rejected: u64,
+    /// Received frames, applied or not, that missed their deadline.
+    pub frames_late: u64,
+}
+
+/// A node in a [`SimWorld`]: admits sessions and applies the frames they may stream.
+#[derive(Debug)]
+pub struct SimNode {
+    addr: SimAddr,
+    identity: DeviceIdentity,
+    sessions: SessionRegistry,
+    grace: Option<Duration>,
+    controllers: HashMap<Uuid, SimAddr>,
+    looks: BTreeMap<UniverseId, AppliedLook>,
+    stats: SimNodeStats,
+}
+
+impl SimNode {
+    pub fn addr(&self) -> SimAddr {
+        self.addr
+    }
+
+    pub fn identity(&self) -> &DeviceIdentity {
+        &self.identity
+    }
+
+    pub fn sessions(&self) -> &SessionRegistry {
+        &self.sessions
+    }
+
+    /// Look last applied to `universe`.
+    pub fn look(&self, universe: UniverseId) -> Option<&AppliedLook> {
+        self.looks.get(&universe)
+    }
+
+    pub fn stats(&self) -> SimNodeStats {
+        self.stats
+    }
+
+    fn receive(&mut self, datagram: Datagram, network: &SimNetwork) {
+        let frame = match codec::decode_untrusted::<FrameEnvelope>(&datagram.bytes) {
+            Ok(frame) if frame.message_type == MessageType::AlpineFrame => frame,
+            _ => {
+                self.stats.frames_rejected += 1;
+                return;
+            }
+        };
+        let Some(session) = self
+            .sessions
+            .access(frame.session_id)
+            .and_then(|_| self.sessions.get(frame.session_id))
+        else {
+            self.stats.frames_rejected += 1;
+            return;
+        };
+        session.record_peer_activity();
+        session.record_frame_received();
+        let arrival_us = network.clock().now_us();
+        if frame.missed_deadline(arrival_us) {
+            self.stats.frames_late += 1;
+            session.record_late_frame();
+        }
+        let report = NodeReport::Arrival {
+            universe: frame.universe,
+            sequence: datagram.seq,
+            arrival_us,
+            deadline_us: frame.deadline_us.unwrap_or(u64::MAX),
+        };
+        if let Ok(bytes) = codec::to_vec(&report) {
+            network.send(self.addr, datagram.from, &bytes);
+        }
+        if !self.sessions.may_stream(frame.session_id) {
+            self.stats.frames_refused += 1;
+            return;
+        }
+        self.stats.frames_applied += 1;
+        self.looks.insert(
+            frame.universe,
+            AppliedLook {
+                session_id: frame.session_id,
+                levels: frame.channels,
+                applied_us: arrival_us,
+            },
+        );
+    }
+}
+
+#[derive(Debug)]
+struct Connection {
+    node: SimAddr,
+    session_id: Uuid,
+    stream: AlnpStream<SimSocket>,
+    events: broadcast::Receiver<StreamEvent>,
+}
+
+/// A controller in a [`SimWorld`]: streams its looks to one node at a time and adapts to
+/// the arrivals that node reports.
+#[derive(Debug)]
+pub struct SimController {
+    addr: SimAddr,
+    priority: u8,
+    connection: Option<Connection>,
+    looks: BTreeMap<UniverseId, Vec<u16>>,
+    events: Vec<StreamEvent>,
+}
+
+impl SimController {
+    pub fn addr(&self) -> SimAddr {
+        self.addr
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Node the controller is connected to.
+    pub fn node(&self) -> Option<SimAddr> {
+        self.connection.as_ref().map(|connection| connection.node)
+    }
+
+    pub fn session_id(&self) -> Option<Uuid> {
+        self.connection
+            .as_ref()
+            .map(|connection| connection.session_id)
+    }
+
+    pub fn stream(&self) -> Option<&AlnpStream<SimSocket>> {
+        self.connection
+            .as_ref()
+            .map(|connection| &connection.stream)
+    }
+
+    /// Network and adaptation state of `universe` on the current connection.
+    pub fn health(&self, universe: UniverseId) -> Option<UniverseHealth> {
+        self.stream()?.universe_health(universe)
+    }
+
+    /// Stream events seen so far, across connections, in order.
+    pub fn events(&self) -> &[StreamEvent] {
+        &self.events
+    }
+
+    fn receive(&mut self, datagram: Datagram) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        if datagram.from != connection.node {
+            return;
+        }
+        match codec::decode_untrusted(&datagram.bytes) {
+            Ok(NodeReport::Arrival {
+                universe,
+                sequence,
+                arrival_us,
+                deadline_us,
+            }) => {
+                connection.stream.record_universe_arrival(
+                    universe,
+                    sequence,
+                    arrival_us,
+                    deadline_us,
+                );
+            }
+            Ok(NodeReport::Preempted { priority, grace_us }) => {
+                self.events.push(StreamEvent::Preempted {
+                    priority,
+                    grace: Duration::from_micros(grace_us),
+                });
+            }
+            Err(_) => {}
+        }
+        self.drain_events();
+    }
+
+    fn drain_events(&mut self) {
+        if let Some(connection) = &mut self.connection {
+            while let Ok(event) = connection.events.try_recv() {
+                self.events.push(event);
+            }
+        }
+    }
+}
+
+/// Controllers and nodes sharing one simulated clock and network.
+#[derive(Debug)]
+pub struct SimWorld {
+    clock: SimClock,
+    network: SimNetwork,
+    rng: StdRng,
+    next_addr: u32,
+    nodes: BTreeMap<SimAddr, SimNode>,
+    controllers: BTreeMap<SimAddr, SimController>,
+    frame_interval: Duration,
+    frame_deadline: Duration,
+    next_frame: Instant,
+}
+
+impl SimWorld {
+    /// Empty world whose network loss, jitter, and session keys all derive from `seed`.
+    pub fn new(seed: u64) -> Self {
+        let clock = SimClock::new();
+        let network = SimNetwork::new(clock.clone(), seed);
+        let next_frame = clock.now();
+        Self {
+            clock,
+            network,
+            rng: StdRng::seed_from_u64(seed.rotate_left(32)),
+            next_addr: 1,
+            nodes: BTreeMap::new(),
+            controllers: BTreeMap::new(),
+            frame_interval: Duration::from_secs(1) / DEFAULT_SIM_FRAME_RATE,
+            frame_deadline: DEFAULT_SIM_FRAME_DEADLINE,
+            next_frame,
+        }
+    }
+
+    /// Frames per second each controller sends per universe; at least one.
+    pub fn with_frame_rate(mut self, fps: u32) -> Self {
+        self.frame_interval = Duration::from_secs(1) / fps.max(1);
+        self
+    }
+
+    /// How long after sending a frame is still on time.
+    pub fn with_frame_deadline(mut self, deadline: Duration) -> Self {
+        self.frame_deadline = deadline;
+        self
+    }
+
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    pub fn network(&self) -> &SimNetwork {
+        &self.network
+    }
+
+    /// Adds a node holding sessions in `sessions`, which is switched to simulated time.
+    /// With a `takeover_grace`, higher-priority controllers may take a full node over.
+    pub fn add_node(
+        &mut self,
+        sessions: SessionRegistry,
+        takeover_grace: Option<Duration>,
+    ) -> SimAddr {
+        let addr = self.next_addr();
+        let node = SimNode {
+            addr,
+            identity: DeviceIdentity {
+                device_id: format!("sim-node-{}", addr.0),
+                manufacturer_id: "alpine".into(),
+                model_id: "sim".into(),
+                hardware_rev: "1".into(),
+                firmware_rev: env!("CARGO_PKG_VERSION").into(),
+            },
+            sessions: sessions.with_clock(self.clock.clone()),
+            grace: takeover_grace,
+            controllers: HashMap::new(),
+            looks: BTreeMap::new(),
+            stats: SimNodeStats::default(),
+        };
+        self.nodes.insert(addr, node);
+        addr
+    }
+
+    /// Adds a controller stating `priority` when it connects.
+    pub fn add_controller(&mut self, priority: u8) -> SimAddr {
+        let addr = self.next_addr();
+        self.controllers.insert(
+            addr,
+            SimController {
+                addr,
+                priority,
+                connection: None,
+                looks: BTreeMap::new(),
+                events: Vec::new(),
+            },
+        );
+        addr
+    }
+
+    /// # Panics
+    ///
+    /// If `addr` is not a node of this world.
+    pub fn node(&self, addr: SimAddr) -> &SimNode {
+        &self.nodes[&addr]
+    }
+
+    /// # Panics
+    ///
+    /// If `addr` is not a controller of this world.
+    pub fn controller(&self, addr: SimAddr) -> &SimController {
+        &self.controllers[&addr]
+    }
+
+    /// Opens a session from `controller` to `node`, closing any it had, and returns its
+    /// id. A node at its limit refuses it unless the controller outranks a session there
+    /// and the node allows takeovers; the preempted controller is then told so over the
+    /// network.
+    ///
+    /// # Panics
+    ///
+    /// If either address is not of the right kind.
+    pub fn connect(&mut self, controller: SimAddr, node: SimAddr) -> Result<Uuid, HandshakeError> {
+        self.disconnect(controller);
+        let priority = self.controllers[&controller].priority;
+        let target = &self.nodes[&node];
+        let established = SessionEstablished {
+            session_id: Uuid::from_bytes(self.rng.gen()),
+            controller_nonce: self.rng.gen::<[u8; 32]>().to_vec(),
+            device_nonce: self.rng.gen::<[u8; 32]>().to_vec(),
+            capabilities: CapabilitySet::default(),
+            device_identity: target.identity.clone(),
+            extensions: None,
+            controller_priority: Some(priority),
+            role: None,
+        };
+        let keys = SessionKeys {
+            shared_secret: self.rng.gen::<[u8; 32]>().to_vec(),
+            control_key: self.rng.gen(),
+            stream_key: self.rng.gen(),
+            suite: SuiteId::default(),
+            channel_binding: self.rng.gen(),
+        };
+        let established_session = |role| {
+            let session = AlnpSession::start_handshake(role, TimingConfig::default())?;
+            session.finish_handshake(HandshakeOutcome {
+                established: established.clone(),
+                keys: keys.clone(),
+            })?;
+            Ok::<_, HandshakeError>(session)
+        };
+        let node_session = established_session(AlnpRole::Node)?;
+        let (session_id, takeover) =
+            target
+                .sessions
+                .admit(&node_session, SessionAccess::Full, target.grace)?;
+        let controller_session = established_session(AlnpRole::Controller)?;
+        let profile = StreamProfile::auto()
+            .compile()
+            .map_err(|e| HandshakeError::Protocol(e.to_string()))?;
+        let stream = AlnpStream::new(
+            controller_session,
+            self.network.socket(controller, node),
+            profile,
+        );
+        let events = stream.subscribe_events();
+
+        let target = self.nodes.get_mut(&node).expect("node was looked up above");
+        target.controllers.insert(session_id, controller);
+        if let Some(takeover) = takeover {
+            let preempted = target.controllers.get(&takeover.preempted).copied();
+            let notice = NodeReport::Preempted {
+                priority: takeover.priority,
+                grace_us: takeover.grace.as_micros() as u64,
+            };
+            if let (Some(preempted), Ok(bytes)) = (preempted, codec::to_vec(&notice)) {
+                self.network.send(node, preempted, &bytes);
+            }
+        }
+        let controller = self
+            .controllers
+            .get_mut(&controller)
+            .expect("controller was looked up above");
+        controller.connection = Some(Connection {
+            node,
+            session_id,
+            stream,
+            events,
+        });
+        Ok(session_id)
+    }
+
+    /// Closes the session of `controller`, on the node too, as a clean shutdown would.
+    pub fn disconnect(&mut self, controller: SimAddr) {
+        let Some(connection) = self
+            .controllers
+            .get_mut(&controller)
+            .and_then(|controller| controller.connection.take())
+        else {
+            return;
+        };
+        if let Some(node) = self.nodes.get_mut(&connection.node) {
+            node.sessions.close(connection.session_id);
+            node.controllers.remove(&connection.session_id);
+        }
+    }
+
+    /// Has `controller` send `levels` to `universe` with every frame from now on.
+    pub fn set_look(&mut self, controller: SimAddr, universe: UniverseId, levels: Vec<u16>) {
+        if let Some(controller) = self.controllers.get_mut(&controller) {
+            controller.looks.insert(universe, levels);
+        }
+    }
+
+    /// Stops `controller` streaming; its session stays open.
+    pub fn clear_looks(&mut self, controller: SimAddr) {
+        if let Some(controller) = self.controllers.get_mut(&controller) {
+            controller.looks.clear();
+        }
+    }
+
+    /// Runs the world for `duration`: controllers send their looks once per frame
+    /// interval, and datagrams are delivered and handled in the order they arrive.
+    pub fn run_for(&mut self, duration: Duration) {
+        let end = self.clock.now() + duration;
+        loop {
+            let delivery = self.network.next_delivery().filter(|at| *at <= end);
+            let frame = Some(self.next_frame).filter(|at| *at <= end);
+            // Deliveries due with a frame go first, so reports sent before the frame
+            // interval elapsed are seen before the next frame goes out.
+            match (delivery, frame) {
+                (Some(delivery), frame) if frame.is_none_or(|frame| delivery <= frame) => {
+                    self.clock.advance_to(delivery);
+                    self.network.deliver_due();
+                    self.dispatch();
+                }
+                (_, Some(frame)) => {
+                    self.clock.advance_to(frame);
+                    self.send_frames();
+                    self.next_frame = frame + self.frame_interval;
+                }
+                _ => break,
+            }
+        }
+        self.clock.advance_to(end);
+    }
+
+    fn next_addr(&mut self) -> SimAddr {
+        let addr = SimAddr(self.next_addr);
+        self.next_addr += 1;
+        addr
+    }
+
+    fn send_frames(&mut self) {
+        let now_us = self.clock.now_us();
+        for controller in self.controllers.values_mut() {
+            let Some(connection) = &controller.connection else {
+                continue;
+            };
+            for (&universe, levels) in &controller.looks {
+                let frame = FrameEnvelope {
+                    message_type: MessageType::AlpineFrame,
+                    session_id: connection.session_id,
+                    universe,
+                    timestamp_us: now_us,
+                    priority: controller.priority,
+                    channel_format: ChannelFormat::U16,
+                    channels: ChannelBuffer::from_slice(levels),
+                    packed_channels: None,
+                    float_channels: None,
+                    compression: None,
+                    compressed_channels: None,
+                    groups: None,
+                    group_refs: None,
+                    config_tag: None,
+                    deadline_us: Some(now_us + self.frame_deadline.as_micros() as u64),
+                    metadata: None,
+                    extensions: None,
+                };
+                if let Ok(bytes) = codec::to_vec(&frame) {
+                    connection.stream.transport().send_frame(&bytes).ok();
+                }
+            }
+        }
+    }
+
+    fn dispatch(&mut self) {
+        let addrs: Vec<SimAddr> = self
+            .nodes
+            .keys()
+            .chain(self.controllers.keys())
+            .copied()
+            .collect();
+        for addr in addrs {
+            while let Some(datagram) = self.network.recv(addr) {
+                if let Some(node) = self.nodes.get_mut(&addr) {
+                    node.receive(datagram, &self.network);
+                } else if let Some(controller) = self.controllers.get_mut(&addr) {
+                    controller.receive(datagram);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{AdaptationEvent, RecoveryEvent};
+
+    const UNIVERSE: UniverseId = 1;
+
+    fn lan() -> LinkConditions {
+        LinkConditions {
+            latency: Duration::from_millis(2),
+            ..LinkConditions::default()
+        }
+    }
+
+    fn preemptions(controller: &SimController) -> Vec<StreamEvent> {
+        controller
+            .events()
+            .iter()
+            .filter(|event| matches!(event, StreamEvent::Preempted { .. }))
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn networks_replay_the_same_run_from_the_same_seed() {
+        let run = |seed| {
+            let network = SimNetwork::new(SimClock::new(), seed);
+            let (a, b) = (SimAddr(1), SimAddr(2));
+            network.set_link(
+                a,
+                b,
+                LinkConditions {
+                    latency: Duration::from_millis(5),
+                    jitter: Duration::from_millis(5),
+                    loss: 0.3,
+                },
+            );
+            for index in 0..100u8 {
+                network.send(a, b, &[index]);
+            }
+            network.clock().advance(Duration::from_millis(10));
+            network.deliver_due();
+            let arrived: Vec<_> = std::iter::from_fn(|| network.recv(b))
+                .map(|datagram| (datagram.seq, datagram.bytes))
+                .collect();
+            (arrived, network.stats(a, b))
+        };
+        let (arrived, stats) = run(7);
+        assert_eq!((arrived.clone(), stats), run(7));
+        assert_ne!(arrived, run(8).0);
+        assert_eq!(stats.sent, 100);
+        assert_eq!(stats.lost + stats.delivered, 100);
+        assert_eq!(arrived.len() as u64, stats.delivered);
+    }
+
+    #[test]
+    fn scripted_faults_lose_exactly_what_they_name() {
+        let network = SimNetwork::new(SimClock::new(), 0);
+        let (a, b) = (SimAddr(1), SimAddr(2));
+        network.set_link(a, b, lan());
+        network.drop_next(a, b, 2);
+        for index in 0..4u8 {
+            network.send(a, b, &[index]);
+        }
+        assert_eq!(network.deliver_due(), 0);
+        network.clock().advance(Duration::from_millis(2));
+        assert_eq!(network.deliver_due(), 2);
+        assert_eq!(network.recv(b).map(|datagram| datagram.seq), Some(3));
+
+        network.cut(a, b);
+        network.send(b, a, &[0]);
+        network.heal(a, b);
+        network.send(b, a, &[1]);
+        network.crash(b);
+        network.send(a, b, &[2]);
+        network.deliver_due();
+        assert_eq!(
+            network.recv(a).map(|datagram| datagram.bytes),
+            Some(vec![1])
+        );
+        assert_eq!(network.recv(b), None);
+        assert_eq!(network.stats(b, a).lost, 1);
+    }
+
+    #[test]
+    fn sustained_loss_drives_adaptation_deterministically() {
+        let run = || {
+            let mut world = SimWorld::new(42);
+            let node = world.add_node(SessionRegistry::default(), None);
+            let controller = world.add_controller(100);
+            world.network().set_link(
+                controller,
+                node,
+                LinkConditions {
+                    latency: Duration::from_millis(3),
+                    jitter: Duration::from_millis(2),
+                    loss: 0.4,
+                },
+            );
+            world.network().set_link(node, controller, lan());
+            world.connect(controller, node).unwrap();
+            world.set_look(controller, UNIVERSE, vec![255; 16]);
+            world.run_for(Duration::from_secs(2));
+            let controller = world.controller(controller);
+            (
+                controller.events().to_vec(),
+                controller
+                    .health(UNIVERSE)
+                    .unwrap()
+                    .adaptation
+                    .keyframe_interval,
+                world.node(node).stats(),
+            )
+        };
+        let (events, keyframe_interval, stats) = run();
+        assert_eq!(run(), (events.clone(), keyframe_interval, stats));
+        assert!(events.contains(&StreamEvent::Recovery {
+            universe: UNIVERSE,
+            event: RecoveryEvent::RecoveryStarted(crate::stream::RecoveryReason::SustainedLoss),
+        }));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            StreamEvent::Adaptation {
+                event: AdaptationEvent::KeyframeCadenceIncreased,
+                ..
+            }
+        )));
+        assert!(stats.frames_applied > 0 && stats.frames_applied < 80);
+    }
+
+    #[test]
+    fn a_clean_link_never_starts_recovery() {
+        let mut world = SimWorld::new(1);
+        let node = world.add_node(SessionRegistry::default(), None);
+        let controller = world.add_controller(100);
+        world.network().set_default_link(lan());
+        world.connect(controller, node).unwrap();
+        world.set_look(controller, UNIVERSE, vec![1, 2, 3]);
+        world.run_for(Duration::from_secs(1));
+
+        let controller = world.controller(controller);
+        assert!(!controller
+            .events()
+            .iter()
+            .any(|event| matches!(event, StreamEvent::Recovery { .. })));
+        let health = controller.health(UNIVERSE).unwrap();
+        assert_eq!((health.metrics.loss_ratio, health.max_loss_gap), (0.0, 0));
+        let stats = world.node(node).stats();
+        assert_eq!(stats.frames_applied, 40);
+        assert_eq!(stats.frames_late, 0);
+        let look = world.node(node).look(UNIVERSE).unwrap();
+        assert_eq!(look.levels[..], [1, 2, 3]);
+    }
+
+    #[test]
+    fn a_takeover_waits_out_the_grace_period_on_simulated_time() {
+        let mut world = SimWorld::new(3);
+        let node = world.add_node(SessionRegistry::new(1), Some(Duration::from_millis(500)));
+        let house = world.add_controller(50);
+        let console = world.add_controller(200);
+        world.network().set_default_link(lan());
+        let house_session = world.connect(house, node).unwrap();
+        world.set_look(house, UNIVERSE, vec![10]);
+        world.run_for(Duration::from_millis(100));
+
+        let console_session = world.connect(console, node).unwrap();
+        world.set_look(console, UNIVERSE, vec![200]);
+        world.run_for(Duration::from_millis(250));
+        assert_eq!(
+            preemptions(world.controller(house)),
+            [StreamEvent::Preempted {
+                priority: 200,
+                grace: Duration::from_millis(500),
+            }]
+        );
+        let look = world.node(node).look(UNIVERSE).unwrap();
+        assert_eq!(look.session_id, house_session);
+        assert!(world.node(node).stats().frames_refused > 0);
+
+        world.run_for(Duration::from_millis(300));
+        let look = world.node(node).look(UNIVERSE).unwrap();
+        assert_eq!(
+            (look.session_id, &look.levels[..]),
+            (console_session, &[200][..])
+        );
+        assert_eq!(world.node(node).sessions().len(), 1);
+        // The preempted controller's frames now belong to no open session.
+        let rejected = world.node(node).stats().frames_rejected;
+        world.run_for(Duration::from_millis(100));
+        assert_eq!(world.node(node).stats().frames_rejected, rejected + 4);
+    }
+
+    #[test]
+    fn a_backup_controller_takes_over_when_the_primary_crashes() {
+        let mut world = SimWorld::new(5);
+        let node = world.add_node(SessionRegistry::new(2), None);
+        let primary = world.add_controller(100);
+        let backup = world.add_controller(100);
+        world.network().set_default_link(lan());
+        let primary_session = world.connect(primary, node).unwrap();
+        let backup_session = world.connect(backup, node).unwrap();
+        world.set_look(primary, UNIVERSE, vec![42; 4]);
+        world.run_for(Duration::from_millis(200));
+        assert_eq!(
+            world.node(node).look(UNIVERSE).unwrap().session_id,
+            primary_session
+        );
+
+        world.network().crash(primary);
+        world.run_for(Duration::from_millis(200));
+        // The node holds the last look it was given while nobody streams.
+        let held = world.node(node).look(UNIVERSE).unwrap().clone();
+        assert_eq!(held.session_id, primary_session);
+
+        world.set_look(backup, UNIVERSE, vec![42; 4]);
+        let failover_at = world.clock().now_us();
+        world.run_for(Duration::from_millis(50));
+        let look = world.node(node).look(UNIVERSE).unwrap();
+        assert_eq!(look.session_id, backup_session);
+        assert_eq!(look.levels, held.levels);
+        // The first backup frame goes out on the next frame tick and lands 2 ms later.
+        assert!(look.applied_us > failover_at);
+    }
+}
@@ -0,0 +1,42 @@
+//! Sources of looks to stream.
+//!
+//! A [`FrameSource`] turns legacy lighting input into levels a controller can send on an
+//! authenticated stream, so a console's DMX or sACN output is authenticated before it
+//! crosses the venue network. [`sacn::SacnSource`] receives E1.31; with the `enttec`
+//! feature the Enttec DMX USB Pro's input is a source too.
+
+use std::io;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::messages::{ChannelFormat, UniverseId};
+
+pub mod sacn;
+
+/// Priority given to input that carries none, such as plain DMX.
+pub const DEFAULT_SOURCE_PRIORITY: u8 = 100;
+
+/// Errors raised by frame sources.
+#[derive(Debug, Error)]
+pub enum SourceError {
+    #[error("frame source i/o failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("frame source is closed")]
+    Closed,
+}
+
+/// Levels received from a source, ready to send on a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFrame {
+    pub universe: UniverseId,
+    pub channel_format: ChannelFormat,
+    pub channels: Vec<u16>,
+    pub priority: u8,
+}
+
+/// Input that yields looks to stream.
+pub trait FrameSource: Send {
+    /// Waits up to `timeout` for the next look; `Ok(None)` when none arrived in time.
+    fn next_frame(&mut self, timeout: Duration) -> Result<Option<SourceFrame>, SourceError>;
+}
@@ -0,0 +1,236 @@
+//! sACN (ANSI E1.31) input.
+//!
+//! Consoles send each universe as UDP datagrams to port 5568, multicast to
+//! `239.255.<hi>.<lo>` or unicast. Only data packets with the null start code become
+//! frames; preview data, other start codes, and packets older than the last one seen from
+//! the same source and universe are dropped, as E1.31 asks of receivers.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use super::{FrameSource, SourceError, SourceFrame};
+use crate::messages::{ChannelFormat, UniverseId};
+
+/// UDP port sACN is sent to.
+pub const SACN_PORT: u16 = 5568;
+
+const ACN_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const OPTION_PREVIEW_DATA: u8 = 0x80;
+const OPTION_STREAM_TERMINATED: u8 = 0x40;
+/// Offset of the DMX start code; slots follow it.
+const START_CODE_OFFSET: usize = 125;
+const MAX_SLOTS: usize = 512;
+
+/// A parsed E1.31 data packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SacnPacket {
+    /// Component identifier of the sending console.
+    pub cid: [u8; 16],
+    pub priority: u8,
+    pub sequence: u8,
+    pub options: u8,
+    pub universe: u16,
+    pub start_code: u8,
+    pub slots: Vec<u8>,
+}
+
+impl SacnPacket {
+    /// Parses an E1.31 data packet; `None` for anything else or a malformed packet.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let u32_at = |at: usize| {
+            u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        let u16_at = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+        if bytes.len() <= START_CODE_OFFSET
+            || u16_at(0) != 0x0010
+            || &bytes[4..16] != ACN_IDENTIFIER
+            || u32_at(18) != VECTOR_ROOT_E131_DATA
+            || u32_at(40) != VECTOR_E131_DATA_PACKET
+            || bytes[117] != VECTOR_DMP_SET_PROPERTY
+        {
+            return None;
+        }
+        // The property value count covers the start code and the slots.
+        let values = usize::from(u16_at(123));
+        if values == 0 || values > MAX_SLOTS + 1 || bytes.len() < START_CODE_OFFSET + values {
+            return None;
+        }
+        let mut cid = [0; 16];
+        cid.copy_from_slice(&bytes[22..38]);
+        Some(Self {
+            cid,
+            priority: bytes[108],
+            sequence: bytes[111],
+            options: bytes[112],
+            universe: u16_at(113),
+            start_code: bytes[START_CODE_OFFSET],
+            slots: bytes[START_CODE_OFFSET + 1..START_CODE_OFFSET + values].to_vec(),
+        })
+    }
+
+    /// Multicast group a console sends `universe` to.
+    pub fn multicast_group(universe: u16) -> Ipv4Addr {
+        let [hi, lo] = universe.to_be_bytes();
+        Ipv4Addr::new(239, 255, hi, lo)
+    }
+}
+
+/// [`FrameSource`] receiving sACN universes and mapping them to ALPINE universes.
+#[derive(Debug)]
+pub struct SacnSource {
+    socket: UdpSocket,
+    /// sACN universe to the ALPINE universe it is streamed as.
+    universes: BTreeMap<u16, UniverseId>,
+    /// Last sequence number per console and universe.
+    sequences: HashMap<([u8; 16], u16), u8>,
+}
+
+impl SacnSource {
+    /// Listens on the sACN port of every IPv4 interface and joins the multicast group of
+    /// each universe in `universes`, a map from sACN universe to ALPINE universe.
+    pub fn bind(universes: BTreeMap<u16, UniverseId>) -> Result<Self, SourceError> {
+        let local = SocketAddr::from((Ipv4Addr::UNSPECIFIED, SACN_PORT));
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        // Other receivers on the host may listen on the same port.
+        socket.set_reuse_address(true)?;
+        socket.bind(&local.into())?;
+        let socket: UdpSocket = socket.into();
+        for universe in universes.keys() {
+            socket.join_multicast_v4(
+                &SacnPacket::multicast_group(*universe),
+                &Ipv4Addr::UNSPECIFIED,
+            )?;
+        }
+        Ok(Self::with_socket(socket, universes))
+    }
+
+    /// Reads sACN from an already bound `socket`, such as one receiving unicast.
+    pub fn with_socket(socket: UdpSocket, universes: BTreeMap<u16, UniverseId>) -> Self {
+        Self {
+            socket,
+            universes,
+            sequences: HashMap::new(),
+        }
+    }
+
+    /// Turns a datagram into a frame, or `None` when it should be dropped.
+    fn accept(&mut self, bytes: &[u8]) -> Option<SourceFrame> {
+        let packet = SacnPacket::parse(bytes)?;
+        let universe = *self.universes.get(&packet.universe)?;
+        if packet.options & (OPTION_PREVIEW_DATA | OPTION_STREAM_TERMINATED) != 0 {
+            return None;
+        }
+        let key = (packet.cid, packet.universe);
+        if let Some(&last) = self.sequences.get(&key) {
+            // E1.31 6.7.2: a packet up to 20 behind the last is out of order.
+            let behind = last.wrapping_sub(packet.sequence) as i8;
+            if (0..20).contains(&behind) {
+                return None;
+            }
+        }
+        self.sequences.insert(key, packet.sequence);
+        (packet.start_code == 0).then(|| SourceFrame {
+            universe,
+            channel_format: ChannelFormat::U8,
+            channels: packet.slots.iter().map(|&slot| u16::from(slot)).collect(),
+            priority: packet.priority,
+        })
+    }
+}
+
+impl FrameSource for SacnSource {
+    fn next_frame(&mut self, timeout: Duration) -> Result<Option<SourceFrame>, SourceError> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0; 1144];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+            let len = match self.socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if let Some(frame) = self.accept(&buffer[..len]) {
+                return Ok(Some(frame));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(universe: u16, sequence: u8, options: u8, start_code: u8, slots: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; START_CODE_OFFSET + 1];
+        bytes[0..2].copy_from_slice(&0x0010u16.to_be_bytes());
+        bytes[4..16].copy_from_slice(ACN_IDENTIFIER);
+        bytes[18..22].copy_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+        bytes[22..38].copy_from_slice(&[7; 16]);
+        bytes[40..44].copy_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+        bytes[108] = 150;
+        bytes[111] = sequence;
+        bytes[112] = options;
+        bytes[113..115].copy_from_slice(&universe.to_be_bytes());
+        bytes[117] = VECTOR_DMP_SET_PROPERTY;
+        bytes[118] = 0xa1;
+        bytes[121..123].copy_from_slice(&1u16.to_be_bytes());
+        bytes[123..125].copy_from_slice(&(slots.len() as u16 + 1).to_be_bytes());
+        bytes[START_CODE_OFFSET] = start_code;
+        bytes.extend_from_slice(slots);
+        bytes
+    }
+
+    #[test]
+    fn maps_universes_and_drops_preview_and_stale_packets() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.connect(receiver.local_addr().unwrap()).unwrap();
+        let mut source = SacnSource::with_socket(receiver, BTreeMap::from([(1, 10)]));
+        for bytes in [
+            packet(2, 0, 0, 0, &[1]),
+            packet(1, 5, OPTION_PREVIEW_DATA, 0, &[2]),
+            packet(1, 5, 0, 0xDD, &[3]),
+            packet(1, 6, 0, 0, &[255, 4]),
+            packet(1, 4, 0, 0, &[5]),
+            packet(1, 7, 0, 0, &[6]),
+        ] {
+            sender.send(&bytes).unwrap();
+        }
+        let timeout = Duration::from_secs(2);
+        let frame = source.next_frame(timeout).unwrap().unwrap();
+        assert_eq!(
+            frame,
+            SourceFrame {
+                universe: 10,
+                channel_format: ChannelFormat::U8,
+                channels: vec![255, 4],
+                priority: 150,
+            }
+        );
+        assert_eq!(source.next_frame(timeout).unwrap().unwrap().channels, [6]);
+        assert_eq!(source.next_frame(Duration::from_millis(20)).unwrap(), None);
+        assert_eq!(
+            SacnPacket::multicast_group(513),
+            Ipv4Addr::new(239, 255, 2, 1)
+        );
+    }
+}
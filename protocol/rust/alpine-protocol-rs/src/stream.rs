@@ -1,14 +1,27 @@
-use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use arc_swap::ArcSwapOption;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::sync::broadcast;
 use tracing::{info, warn};
 
-use crate::messages::{ChannelFormat, FrameEnvelope, MessageType};
+use crate::codec;
+use crate::latency::{LatencyRecorder, LatencySample};
+use crate::messages::channels::normalize;
+use crate::messages::metadata::{is_reserved_key, METADATA_VERSION};
+use crate::messages::{
+    AdaptationInfo, ChannelBuffer, ChannelFormat, EchoFrame, Extensions, FrameEnvelope, GroupId,
+    MessageType, RecoveryInfo, UniverseId, DEFAULT_UNIVERSE,
+};
 use crate::profile::CompiledStreamProfile;
+use crate::session::limits::{LimitWarning, SessionLimit};
 use crate::session::{AlnpSession, JitterStrategy};
-use crate::stream::adaptive::{decide_next_state, AdaptationState};
+
+/// Number of buffered events a lagging subscriber may fall behind before losing some.
+const EVENT_CHANNEL_CAPACITY: usize = 64;
 
 /// Minimal transport for sending serialized ALPINE frames (UDP/QUIC left to the caller).
 pub trait FrameTransport: Send + Sync {
@@ -21,11 +34,227 @@ pub trait FrameTransport: Send + Sync {
 pub struct AlnpStream<T: FrameTransport> {
     session: AlnpSession,
     transport: T,
-    last_frame: parking_lot::Mutex<Option<FrameEnvelope>>,
     profile: CompiledStreamProfile,
-    recovery: parking_lot::Mutex<RecoveryMonitor>,
-    recovery_reason: parking_lot::Mutex<Option<RecoveryReason>>,
-    adaptation: parking_lot::Mutex<AdaptationState>,
+    /// Read-mostly: the write lock is taken only to add a universe.
+    universes: parking_lot::RwLock<HashMap<UniverseId, Arc<UniverseSlot>>>,
+    extensions: parking_lot::Mutex<Option<Extensions>>,
+    budget: parking_lot::Mutex<SendBudget>,
+    latency: LatencyRecorder,
+    echo: parking_lot::Mutex<EchoProbe>,
+    fec: parking_lot::Mutex<Option<FecEncoder>>,
+    /// Cuts datagrams past the path MTU into fragments; `None` sends them whole.
+    fragmenter: parking_lot::Mutex<Option<Fragmenter>>,
+    /// Encrypts frames and parity under per-epoch keys; `None` sends them in the clear.
+    /// Whether datagrams are sealed; the sealing state lives on the session.
+    sealed: bool,
+    liveness: Arc<Liveness>,
+    heartbeat: Option<HeartbeatTask>,
+    underrun_alarm: Option<UnderrunAlarm>,
+    events: broadcast::Sender<StreamEvent>,
+}
+
+/// Levels a universe last sent, in the caller's format; the rest of the frame is not
+/// kept.
+#[derive(Debug, PartialEq)]
+struct HeldLevels {
+    channel_format: ChannelFormat,
+    channels: ChannelBuffer,
+    float_channels: Option<Vec<f32>>,
+}
+
+/// Everything a stream keeps for one universe.
+///
+/// Sends to different universes only share the map's read lock: each universe smooths
+/// its own channels, and the held levels are swapped in whole, so neither jitter
+/// handling nor comparing a frame against the held levels waits on another sender.
+#[derive(Debug)]
+struct UniverseSlot {
+    held: ArcSwapOption<HeldLevels>,
+    /// Held apart from `state` so health and arrival reports never wait on smoothing.
+    smoother: parking_lot::Mutex<UniverseSmoother>,
+    state: parking_lot::Mutex<UniverseState>,
+}
+
+impl UniverseSlot {
+    fn new(profile: &CompiledStreamProfile) -> Self {
+        Self {
+            held: ArcSwapOption::empty(),
+            smoother: parking_lot::Mutex::new(UniverseSmoother::new()),
+            state: parking_lot::Mutex::new(UniverseState::new(profile)),
+        }
+    }
+}
+
+/// Detection, recovery, and adaptation state tracked independently per universe.
+///
+/// Universes can travel different multicast paths, so one impaired path must never
+/// force keyframes or degraded-safe onto the others.
+#[derive(Debug)]
+struct UniverseState {
+    /// Lossy conversion already reported for this universe.
+    lossy_conversion: Option<(ChannelFormat, ChannelFormat)>,
+    last_sent: Option<Instant>,
+    throttled_frames: u64,
+    frames_sent: u64,
+    bytes_sent: u64,
+    last_change: Option<Instant>,
+    rate: RateMeter,
+    conditions: NetworkConditions,
+    recovery: RecoveryMonitor,
+    adaptation: AdaptationState,
+}
+
+impl UniverseState {
+    fn new(profile: &CompiledStreamProfile) -> Self {
+        Self {
+            lossy_conversion: None,
+            last_sent: None,
+            throttled_frames: 0,
+            frames_sent: 0,
+            bytes_sent: 0,
+            last_change: None,
+            rate: RateMeter::default(),
+            conditions: NetworkConditions::new(),
+            recovery: RecoveryMonitor::new(),
+            adaptation: AdaptationState::baseline(profile.intent()),
+        }
+    }
+
+    fn health(&self, universe: UniverseId, target_fps: f64) -> UniverseHealth {
+        UniverseHealth {
+            universe,
+            metrics: self.conditions.metrics(),
+            max_loss_gap: self.conditions.max_loss_gap(),
+            recovery: self.recovery.active_reason(),
+            adaptation: self.adaptation.clone(),
+            throttled_frames: self.throttled_frames,
+            frames_sent: self.frames_sent,
+            bytes_sent: self.bytes_sent,
+            achieved_fps: self.rate.fps(),
+            target_fps,
+            last_change: self.last_change,
+            underrun: self.rate.alarmed(),
+        }
+    }
+}
+
+/// Point-in-time view of a single universe's network and adaptation state.
+#[derive(Debug, Clone)]
+pub struct UniverseHealth {
+    pub universe: UniverseId,
+    pub metrics: NetworkMetrics,
+    pub max_loss_gap: u64,
+    pub recovery: Option<RecoveryReason>,
+    pub adaptation: AdaptationState,
+    /// Frames skipped to stay under the bandwidth estimate or the device's frame rate.
+    pub throttled_frames: u64,
+    /// Frames handed to the transport, and their encoded bytes.
+    pub frames_sent: u64,
+    pub bytes_sent: u64,
+    /// Frames per second delivered over the last [`RATE_WINDOW`], counting unchanged
+    /// frames left to the receiver's hold; `None` until a window has passed.
+    pub achieved_fps: Option<f64>,
+    /// Frame rate the profile asks for, capped at the device's limit.
+    pub target_fps: f64,
+    /// When the universe's levels last differed from the previous frame.
+    pub last_change: Option<Instant>,
+    /// Whether the universe's [`UnderrunAlarm`] is raised.
+    pub underrun: bool,
+}
+
+/// Aggregate view across every universe the stream has touched.
+#[derive(Debug, Clone)]
+pub struct StreamHealth {
+    /// Per-universe entries ordered by universe id.
+    pub universes: Vec<UniverseHealth>,
+    /// Highest loss ratio reported by any universe.
+    pub worst_loss_ratio: f64,
+    /// Number of universes currently in recovery.
+    pub recovering: usize,
+    /// Number of universes currently in degraded-safe mode.
+    pub degraded_safe: usize,
+    /// Estimated path capacity in bytes per second; `None` until a receiver report.
+    pub bandwidth_estimate: Option<u64>,
+    /// Whether aggregate output is pressing against the estimate.
+    pub congested: bool,
+}
+
+/// Operator-facing notifications emitted when the stream changes behavior.
+///
+/// Events are broadcast best-effort: if nobody subscribes they are discarded, and a
+/// subscriber that falls more than a few dozen events behind observes a lag error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// The adaptation engine changed keyframe cadence, delta depth, or deadlines.
+    Adaptation {
+        universe: UniverseId,
+        event: AdaptationEvent,
+    },
+    /// Recovery started or completed after sustained or burst loss.
+    Recovery {
+        universe: UniverseId,
+        event: RecoveryEvent,
+    },
+    /// The session is nearing its maximum age or frames per key and should be replaced
+    /// by a fresh handshake before sends start failing.
+    SessionLimit(LimitWarning),
+    /// Frames for the universe are being converted to a format the device advertised,
+    /// losing precision. Reported once until the formats change.
+    LossyConversion {
+        universe: UniverseId,
+        from: ChannelFormat,
+        to: ChannelFormat,
+    },
+    /// The device pushed new capabilities mid-session; frames follow its new limits.
+    CapabilitiesChanged {
+        max_channels: u32,
+        max_frame_rate: Option<u32>,
+    },
+    /// The connection's composite health score moved it to another level.
+    HealthChanged { score: u8, level: HealthLevel },
+    /// A controller of higher `priority` is taking the device over; the device closes
+    /// this session once `grace` has passed.
+    Preempted { priority: u8, grace: Duration },
+    /// The universe has delivered below its [`UnderrunAlarm`] threshold for the alarm's
+    /// duration (`active`), or is back above it. Rates are in whole frames per second.
+    Underrun {
+        universe: UniverseId,
+        achieved_fps: u32,
+        target_fps: u32,
+        active: bool,
+    },
+}
+
+/// What became of a frame the stream accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameOutcome {
+    /// Handed to the transport.
+    Sent,
+    /// Unchanged since the last frame of the universe and not yet due for a refresh.
+    Unchanged,
+    /// Skipped to stay under the bandwidth estimate or the device's frame rate.
+    Throttled,
+}
+
+/// Path a frame takes through the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SendLane {
+    /// Smoothed, and paced by static refresh, the device's frame rate, and the
+    /// bandwidth estimate.
+    Realtime,
+    /// Sent at once as a keyframe at the given levels, for blackouts and recovery; the
+    /// bandwidth it takes is charged to later realtime frames.
+    Express,
+}
+
+/// Severity of a connection's health, for green, yellow, and red status lights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthLevel {
+    Good,
+    Degraded,
+    Critical,
 }
 
 /// Errors emitted from the streaming helper.
@@ -39,47 +268,456 @@ pub enum StreamError {
     StreamingDisabled,
     #[error("no session available")]
     MissingSession,
+    #[error("session {} limit reached; re-handshake required", .0.as_str())]
+    RekeyRequired(SessionLimit),
+    #[error("device does not support channel format {0:?}")]
+    UnsupportedFormat(ChannelFormat),
+    #[error("metadata key {0} is reserved for the protocol")]
+    ReservedMetadata(String),
+    #[error("device does not support encrypted streams")]
+    EncryptionUnsupported,
+}
+
+/// Channel values handed to [`AlnpStream::send_envelope`].
+// Levels stay inline: boxing them would bring back the allocation per frame.
+#[allow(clippy::large_enum_variant)]
+enum Values {
+    Levels(ChannelBuffer),
+    Normalized(Vec<f32>),
 }
 
 mod network;
 
 pub use network::{NetworkConditions, NetworkMetrics};
 
+mod rate;
+
+use rate::{AlarmChange, RateMeter};
+pub use rate::{UnderrunAlarm, RATE_WINDOW};
+
 mod recovery;
 
 pub use recovery::{RecoveryEvent, RecoveryMonitor, RecoveryReason};
 
 mod adaptive;
 
+pub use adaptive::{
+    decide_next_state, AdaptationDecision, AdaptationEvent, AdaptationState, DegradedReason,
+    RampStage, RecoveryRamp,
+};
+
+mod arbitration;
+
+pub use arbitration::{PriorityArbiter, DEFAULT_PRIORITY_HOLD};
+
+mod bandwidth;
+
+pub use bandwidth::{
+    BandwidthEstimator, ReceiverReport, SendBudget, MAX_STATIC_REFRESH, MIN_BANDWIDTH_BPS,
+    STATIC_REFRESH,
+};
+
+mod echo;
+
+use echo::EchoProbe;
+pub use echo::{EchoSample, MAX_OUTSTANDING_ECHOES};
+
+mod fec;
+
+pub use fec::{FecDecoder, FecEncoder, FEC_HISTORY};
+
+mod fragment;
+
+pub use fragment::{
+    Fragmenter, Reassembler, FRAGMENT_OVERHEAD, MAX_FRAGMENTS, MAX_PENDING_DATAGRAMS, MIN_DATAGRAM,
+    REASSEMBLY_TIMEOUT,
+};
+
+mod jitter;
+
+pub use jitter::JitterBuffer;
+
+mod heartbeat;
+
+pub use heartbeat::STREAM_HEARTBEAT_INTERVAL;
+use heartbeat::{HeartbeatTask, Liveness};
+
+pub(crate) mod sealing;
+
+mod shard;
+
+pub use shard::{ShardConfig, ShardJob, ShardPool, ShardStats};
+
+mod smoothing;
+
+pub use smoothing::{
+    HoldTimeout, Smoother, SmoothingConfig, SmoothingPolicy, UniverseSmoother, DEFAULT_LERP_RAMP,
+};
+
+mod validation;
+
+pub use validation::{
+    FrameRejected, FrameValidator, RejectionReason, RejectionStats, REJECTION_REPORT_INTERVAL,
+};
+
 impl<T: FrameTransport> AlnpStream<T> {
     /// Builds a new streaming helper bound to a compiled profile.
     pub fn new(session: AlnpSession, transport: T, profile: CompiledStreamProfile) -> Self {
-        let intent = profile.intent();
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let fec = profile.wire_behavior().fec_group.map(FecEncoder::new);
         Self {
             session,
             transport,
-            last_frame: parking_lot::Mutex::new(None),
             profile,
-            recovery: parking_lot::Mutex::new(RecoveryMonitor::new()),
-            recovery_reason: parking_lot::Mutex::new(None),
-            adaptation: parking_lot::Mutex::new(AdaptationState::baseline(intent)),
+            universes: parking_lot::RwLock::new(HashMap::new()),
+            extensions: parking_lot::Mutex::new(None),
+            budget: parking_lot::Mutex::new(SendBudget::new()),
+            latency: LatencyRecorder::default(),
+            echo: parking_lot::Mutex::new(EchoProbe::default()),
+            fec: parking_lot::Mutex::new(fec),
+            fragmenter: parking_lot::Mutex::new(None),
+            sealed: false,
+            liveness: Arc::new(Liveness::new()),
+            heartbeat: None,
+            underrun_alarm: None,
+            events,
+        }
+    }
+
+    /// Publishes events on an existing channel instead of the stream's own.
+    ///
+    /// Higher layers use this to keep subscriptions alive across stream restarts.
+    pub fn with_event_sender(mut self, events: broadcast::Sender<StreamEvent>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Raises [`StreamEvent::Underrun`] when a universe delivers below `alarm` of its
+    /// target frame rate; see [`UniverseHealth::achieved_fps`].
+    ///
+    /// Universes are checked as frames are sent, so one the caller stopped sending
+    /// altogether is reported on the next frame of any universe.
+    pub fn with_underrun_alarm(mut self, alarm: UnderrunAlarm) -> Self {
+        self.underrun_alarm = Some(alarm);
+        self
+    }
+
+    /// Fragments frames and parity whose encoding exceeds `max_datagram` bytes, typically
+    /// the path MTU less IP and UDP headers. Without it they are sent whole.
+    pub fn with_max_datagram(self, max_datagram: usize) -> Self {
+        self.set_max_datagram(Some(max_datagram));
+        self
+    }
+
+    /// Changes the datagram limit of a running stream, e.g. after an MTU probe; `None`
+    /// stops fragmenting.
+    pub fn set_max_datagram(&self, max_datagram: Option<usize>) {
+        let mut fragmenter = self.fragmenter.lock();
+        match (fragmenter.as_mut(), max_datagram) {
+            (Some(current), Some(max_datagram)) => current.set_max_datagram(max_datagram),
+            (_, max_datagram) => *fragmenter = max_datagram.map(Fragmenter::new),
+        }
+    }
+
+    /// Encrypts every frame and parity datagram, starting a new key epoch at each
+    /// keyframe interval rollover; see [`crate::crypto::ratchet`].
+    ///
+    /// Fails unless the session is established with a device that supports encryption.
+    pub fn with_encryption(mut self) -> Result<Self, StreamError> {
+        let established = self
+            .session
+            .established()
+            .ok_or(StreamError::NotAuthenticated)?;
+        if !established.capabilities.encryption_supported {
+            return Err(StreamError::EncryptionUnsupported);
+        }
+        self.session.keys().ok_or(StreamError::MissingSession)?;
+        self.sealed = true;
+        Ok(self)
+    }
+
+    /// Key epoch of an encrypted stream; `None` when frames go out in the clear.
+    ///
+    /// Epochs belong to the session and carry on across stream restarts.
+    pub fn epoch(&self) -> Option<u32> {
+        if !self.sealed {
+            return None;
+        }
+        Some(self.session.stream_epoch().unwrap_or(0))
+    }
+
+    /// Datagram limit frames are fragmented to, if any.
+    pub fn max_datagram(&self) -> Option<usize> {
+        self.fragmenter
+            .lock()
+            .as_ref()
+            .map(Fragmenter::max_datagram)
+    }
+
+    /// Sends an encoded frame or parity datagram, sealed when the stream is encrypted and
+    /// in a new epoch when `rekey` is set, and fragmented past the datagram limit.
+    fn transmit(
+        &self,
+        session_id: uuid::Uuid,
+        bytes: &[u8],
+        rekey: bool,
+    ) -> Result<(), StreamError> {
+        let sealed = if self.sealed {
+            let sealed = self.session.seal_stream(bytes, rekey)?;
+            Some(codec::to_vec(&sealed).map_err(|e| StreamError::Transport(e.to_string()))?)
+        } else {
+            None
+        };
+        let bytes = sealed.as_deref().unwrap_or(bytes);
+        let fragments = self
+            .fragmenter
+            .lock()
+            .as_mut()
+            .and_then(|fragmenter| fragmenter.split(session_id, bytes));
+        let Some(fragments) = fragments else {
+            return self
+                .transport
+                .send_frame(bytes)
+                .map_err(StreamError::Transport);
+        };
+        for fragment in fragments {
+            let bytes =
+                codec::to_vec(&fragment).map_err(|e| StreamError::Transport(e.to_string()))?;
+            self.transport
+                .send_frame(&bytes)
+                .map_err(StreamError::Transport)?;
         }
+        Ok(())
+    }
+
+    /// Sends an authenticated heartbeat whenever nothing has gone out for `interval`,
+    /// so receivers can tell a paused or static stream from a dead sender.
+    ///
+    /// The heartbeat runs on the current Tokio runtime until the stream is dropped;
+    /// outside a runtime this does nothing and [`Self::send_heartbeat`] is left to the
+    /// caller.
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self
+    where
+        T: Clone + 'static,
+    {
+        self.heartbeat = HeartbeatTask::spawn(
+            self.session.clone(),
+            self.transport.clone(),
+            self.liveness.clone(),
+            interval,
+        );
+        self
+    }
+
+    /// Sends one stream heartbeat now and returns its sequence number.
+    pub fn send_heartbeat(&self) -> Result<u64, StreamError> {
+        heartbeat::send(&self.session, &self.transport, &self.liveness)
+    }
+
+    /// Sets the vendor extensions attached to every subsequent frame; `None` clears them.
+    pub fn set_frame_extensions(&self, extensions: Option<Extensions>) {
+        *self.extensions.lock() = extensions;
+    }
+
+    /// Vendor extensions currently attached to every frame.
+    pub fn frame_extensions(&self) -> Option<Extensions> {
+        self.extensions.lock().clone()
     }
 
-    /// Sends a streaming frame built from raw channel data.
+    /// Subscribes to adaptation, recovery, and session-limit events produced by this stream.
+    ///
+    /// Only events emitted after the call are delivered.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<StreamEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sends a streaming frame built from raw channel data on the default universe.
     ///
     /// # Guarantees
     /// * Only sends when the session is already authenticated and streaming-enabled.
+    /// * Refuses to send once the session has reached one of its lifetime limits.
     /// * Applies jitter strategy derived from the compiled profile; no branching on
     ///   user-facing preferences happens at this layer.
+    /// * Skips frames, still returning `Ok`, that would push output past the bandwidth
+    ///   estimate or the device's frame-rate limits; unchanged universes yield first. See
+    ///   [`UniverseHealth::throttled_frames`].
+    /// * Drops channels past the device's `max_channels`.
     pub fn send(
         &self,
         channel_format: ChannelFormat,
-        channels: Vec<u16>,
+        channels: &[u16],
+        priority: u8,
+        groups: Option<HashMap<String, Vec<u16>>>,
+        metadata: Option<HashMap<String, serde_json::Value>>,
+    ) -> Result<(), StreamError> {
+        self.send_universe(
+            DEFAULT_UNIVERSE,
+            channel_format,
+            channels,
+            priority,
+            groups,
+            metadata,
+        )
+    }
+
+    /// Sends a streaming frame for a specific universe.
+    ///
+    /// Jitter handling, keyframe cadence, and adaptation metadata all come from that
+    /// universe's own state; other universes are unaffected. With
+    /// [`ChannelFormat::U8Packed`] the levels go out as one byte string; with
+    /// [`ChannelFormat::F32`] they are read as 16-bit levels and sent normalized.
+    pub fn send_universe(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+        groups: Option<HashMap<String, Vec<u16>>>,
+        metadata: Option<HashMap<String, serde_json::Value>>,
+    ) -> Result<(), StreamError> {
+        self.send_universe_outcome(
+            universe,
+            channel_format,
+            channels,
+            priority,
+            groups,
+            metadata,
+        )
+        .map(drop)
+    }
+
+    /// Like [`AlnpStream::send_universe`], reporting whether the frame went out or was
+    /// skipped.
+    pub fn send_universe_outcome(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
         priority: u8,
         groups: Option<HashMap<String, Vec<u16>>>,
         metadata: Option<HashMap<String, serde_json::Value>>,
+    ) -> Result<FrameOutcome, StreamError> {
+        self.send_envelope(
+            universe,
+            channel_format,
+            Values::Levels(ChannelBuffer::from_slice(channels)),
+            priority,
+            groups,
+            None,
+            metadata,
+            SendLane::Realtime,
+        )
+    }
+
+    /// Sends a frame for `universe` on the [`SendLane::Express`] lane: at once, at
+    /// exactly `channels`, and flagged as a keyframe, e.g. a blackout that must not wait
+    /// behind pacing or fade in.
+    ///
+    /// Frame-count limits, format negotiation, and `max_channels` still apply.
+    pub fn send_express(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+        metadata: Option<HashMap<String, serde_json::Value>>,
+    ) -> Result<(), StreamError> {
+        self.send_envelope(
+            universe,
+            channel_format,
+            Values::Levels(ChannelBuffer::from_slice(channels)),
+            priority,
+            None,
+            None,
+            metadata,
+            SendLane::Express,
+        )
+        .map(drop)
+    }
+
+    /// Sends normalized 0.0–1.0 values as a [`ChannelFormat::F32`] frame.
+    pub fn send_normalized(
+        &self,
+        universe: UniverseId,
+        values: Vec<f32>,
+        priority: u8,
+        metadata: Option<HashMap<String, serde_json::Value>>,
+    ) -> Result<(), StreamError> {
+        self.send_envelope(
+            universe,
+            ChannelFormat::F32,
+            Values::Normalized(values),
+            priority,
+            None,
+            None,
+            metadata,
+            SendLane::Realtime,
+        )
+        .map(drop)
+    }
+
+    /// Sends a frame addressing channel groups registered on the node by id, instead of
+    /// listing their channels.
+    pub fn send_grouped(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+        group_refs: Vec<GroupId>,
+        metadata: Option<HashMap<String, serde_json::Value>>,
     ) -> Result<(), StreamError> {
+        self.send_envelope(
+            universe,
+            channel_format,
+            Values::Levels(ChannelBuffer::from_slice(channels)),
+            priority,
+            None,
+            Some(group_refs),
+            metadata,
+            SendLane::Realtime,
+        )
+        .map(drop)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn send_envelope(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        values: Values,
+        priority: u8,
+        groups: Option<HashMap<String, Vec<u16>>>,
+        group_refs: Option<Vec<GroupId>>,
+        metadata: Option<HashMap<String, serde_json::Value>>,
+        lane: SendLane,
+    ) -> Result<FrameOutcome, StreamError> {
+        let outcome = self.deliver_envelope(
+            universe,
+            channel_format,
+            values,
+            priority,
+            groups,
+            group_refs,
+            metadata,
+            lane,
+        )?;
+        self.check_underruns(Instant::now());
+        Ok(outcome)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn deliver_envelope(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        values: Values,
+        priority: u8,
+        groups: Option<HashMap<String, Vec<u16>>>,
+        group_refs: Option<Vec<GroupId>>,
+        metadata: Option<HashMap<String, serde_json::Value>>,
+        lane: SendLane,
+    ) -> Result<FrameOutcome, StreamError> {
         let established = self
             .session
             .ensure_streaming_ready()
@@ -87,132 +725,542 @@ impl<T: FrameTransport> AlnpStream<T> {
         if !self.session.streaming_enabled() {
             return Err(StreamError::StreamingDisabled);
         }
+        // Reserved keys carry the stream's own annotations; callers cannot forge them.
+        if let Some(key) = metadata
+            .iter()
+            .flat_map(HashMap::keys)
+            .find(|key| is_reserved_key(key))
+        {
+            return Err(StreamError::ReservedMetadata(key.clone()));
+        }
+        let wire_format = match established.capabilities.closest_format(channel_format) {
+            Some(format) => format,
+            None if established.capabilities.supports_format(channel_format) => channel_format,
+            None => return Err(StreamError::UnsupportedFormat(channel_format)),
+        };
+        // Channels past what the device drives would be refused; send the ones it can.
+        let max_channels =
+            usize::try_from(established.capabilities.max_channels).unwrap_or(usize::MAX);
+        let values = match values {
+            Values::Levels(mut channels) => {
+                channels.truncate(max_channels);
+                Values::Levels(channels)
+            }
+            Values::Normalized(mut values) => {
+                values.truncate(max_channels);
+                Values::Normalized(values)
+            }
+        };
+        match self.session.record_frame() {
+            Ok(Some(warning)) => {
+                warn!(
+                    target: "alpine::session",
+                    limit = warning.limit().as_str(),
+                    "session approaching its {} limit",
+                    warning.limit().as_str()
+                );
+                let _ = self.events.send(StreamEvent::SessionLimit(warning));
+            }
+            Ok(None) => {}
+            Err(limit) => return Err(StreamError::RekeyRequired(limit)),
+        }
 
-        let adjusted_channels = self.apply_jitter(&channels);
-        let mut adaptation = self.adaptation.lock();
-        let should_force_keyframe = adaptation.should_emit_keyframe();
-        let adaptation_snapshot = adaptation.clone();
-        drop(adaptation);
-        let metadata =
-            self.annotate_metadata(metadata, should_force_keyframe, &adaptation_snapshot);
+        // Smoothing runs before the universe state is locked, so health and arrival
+        // reports for the universe never wait on it.
+        let slot = self.universe(universe);
+        let (adjusted_channels, float_channels) = match values {
+            Values::Levels(channels) if channel_format == ChannelFormat::F32 => (
+                ChannelBuffer::new(),
+                Some(channels.iter().map(|&l| normalize(l, u16::MAX)).collect()),
+            ),
+            Values::Levels(channels) if lane == SendLane::Express => {
+                slot.smoother.lock().snap(&channels, Instant::now());
+                (channels, None)
+            }
+            Values::Levels(channels) => (
+                slot.smoother
+                    .lock()
+                    .apply(
+                        self.jitter_strategy_from_profile(),
+                        &self.session.smoothing(),
+                        &channels,
+                        groups.as_ref(),
+                        Instant::now(),
+                    )
+                    .unwrap_or_default(),
+                None,
+            ),
+            Values::Normalized(values) => (ChannelBuffer::new(), Some(values)),
+        };
+        let mut state = slot.state.lock();
+        let should_force_keyframe = state.adaptation.should_emit_keyframe();
+        // Keyframes forced by recovery must not wait behind pacing either.
+        let express = lane == SendLane::Express
+            || (should_force_keyframe && state.recovery.active_reason().is_some());
+        let lossy = (!channel_format.converts_losslessly_to(&wire_format))
+            .then_some((channel_format, wire_format));
+        if lossy.is_some() && state.lossy_conversion != lossy {
+            warn!(
+                target: "alpine::stream",
+                universe,
+                "device lacks {:?}; converting frames to {:?} loses precision",
+                channel_format,
+                wire_format
+            );
+            let _ = self.events.send(StreamEvent::LossyConversion {
+                universe,
+                from: channel_format,
+                to: wire_format,
+            });
+        }
+        state.lossy_conversion = lossy;
+        let (recovery, adaptation) = Self::annotations(
+            should_force_keyframe || lane == SendLane::Express,
+            state.recovery.active_reason(),
+            &state.adaptation,
+        );
+        drop(state);
 
-        let envelope = FrameEnvelope {
+        let encode_started = Instant::now();
+        let wire = self.profile.wire_behavior();
+        let timestamp_us = Self::now_us();
+        let deadline = wire.frame_deadline(adaptation.deadline_offset_ms);
+        let mut envelope = FrameEnvelope {
             message_type: MessageType::AlpineFrame,
             session_id: established.session_id,
-            timestamp_us: Self::now_us(),
+            universe,
+            timestamp_us,
             priority,
             channel_format,
             channels: adjusted_channels,
+            packed_channels: None,
+            float_channels,
+            compression: None,
+            compressed_channels: None,
             groups,
+            group_refs,
+            config_tag: Some(self.profile.config_tag()),
+            deadline_us: Some(timestamp_us.saturating_add(deadline.as_micros() as u64)),
             metadata,
+            extensions: self.extensions.lock().clone(),
+        };
+        envelope.set_recovery_info(recovery);
+        envelope.set_adaptation_info(Some(adaptation));
+
+        // The universe keeps the levels as the caller gave them so jitter handling reads
+        // them in the caller's format; the envelope itself is converted, packed, and
+        // compressed for the wire, and never copied whole.
+        let compression = established.capabilities.compression.first().copied();
+        let rewritten = wire_format != channel_format
+            || wire_format == ChannelFormat::U8Packed
+            || compression.is_some();
+        let held = rewritten.then(|| HeldLevels {
+            channel_format,
+            channels: envelope.channels.clone(),
+            float_channels: envelope.float_channels.clone(),
+        });
+        if rewritten {
+            envelope.convert(wire_format);
+            envelope.pack();
+            if let Some(scheme) = compression {
+                envelope.compress(scheme);
+            }
+        }
+        let bytes = codec::to_vec(&envelope).map_err(|e| StreamError::Transport(e.to_string()))?;
+        let held = held.unwrap_or_else(|| HeldLevels {
+            channel_format,
+            channels: std::mem::take(&mut envelope.channels),
+            float_channels: envelope.float_channels.take(),
+        });
+        let now = Instant::now();
+        if express {
+            self.budget.lock().charge(bytes.len(), now);
+        } else {
+            let mut state = slot.state.lock();
+            let unchanged = slot.held.load().as_deref() == Some(&held);
+            let static_since = state.last_sent.filter(|_| unchanged);
+            if let (Some(sent), Some(refresh)) = (static_since, wire.static_refresh) {
+                if now.saturating_duration_since(sent) < refresh {
+                    // The receiver holds these levels, so the frame still counts as delivered.
+                    state.rate.record(now);
+                    return Ok(FrameOutcome::Unchanged);
+                }
+            }
+            // A tenth of slack keeps a late timer tick from costing the next frame.
+            let min_interval = established
+                .capabilities
+                .min_frame_interval()
+                .map(|interval| interval - interval / 10);
+            if let (Some(sent), Some(interval)) = (state.last_sent, min_interval) {
+                if now.saturating_duration_since(sent) < interval {
+                    state.throttled_frames += 1;
+                    return Ok(FrameOutcome::Throttled);
+                }
+            }
+            let mut budget = self.budget.lock();
+            // Thinned universes are still refreshed before the receiver's hold fades them.
+            budget.cap_static_refresh(self.session.smoothing().hold.map(|hold| hold.after / 2));
+            if !budget.admit(bytes.len(), static_since, now) {
+                state.throttled_frames += 1;
+                return Ok(FrameOutcome::Throttled);
+            }
+        }
+        self.transmit(established.session_id, &bytes, should_force_keyframe)?;
+        self.latency.record(LatencySample {
+            encode: Some(now.duration_since(encode_started)),
+            socket: Some(now.elapsed()),
+            ..LatencySample::default()
+        });
+        self.session.record_stream_frame_sent(envelope.timestamp_us);
+        self.liveness.sent(now);
+        let parity = self.fec.lock().as_mut().and_then(|fec| {
+            fec.push(
+                established.session_id,
+                universe,
+                envelope.timestamp_us,
+                &bytes,
+            )
+        });
+        let last = slot.held.load();
+        let changed = last.as_deref().is_none_or(|last| {
+            last.channels != held.channels || last.float_channels != held.float_channels
+        });
+        let same = last.as_deref() == Some(&held);
+        drop(last);
+        // A static look keeps the copy already held instead of swapping in another.
+        if !same {
+            slot.held.store(Some(Arc::new(held)));
+        }
+        let mut state = slot.state.lock();
+        if changed {
+            state.last_change = Some(now);
+        }
+        state.last_sent = Some(now);
+        state.frames_sent += 1;
+        state.bytes_sent += bytes.len() as u64;
+        state.rate.record(now);
+        drop(state);
+        if let Some(parity) = parity {
+            let bytes =
+                codec::to_vec(&parity).map_err(|e| StreamError::Transport(e.to_string()))?;
+            if self.budget.lock().admit(bytes.len(), None, now) {
+                self.transmit(established.session_id, &bytes, false)?;
+            }
+        }
+        Ok(FrameOutcome::Sent)
+    }
+
+    /// The slot of `universe`, added on first use.
+    fn universe(&self, universe: UniverseId) -> Arc<UniverseSlot> {
+        if let Some(slot) = self.universes.read().get(&universe) {
+            return slot.clone();
+        }
+        self.universes
+            .write()
+            .entry(universe)
+            .or_insert_with(|| Arc::new(UniverseSlot::new(&self.profile)))
+            .clone()
+    }
+
+    /// Frame rate universes are expected to reach: the profile's, capped at the device's.
+    fn target_fps(&self) -> f64 {
+        let interval = self.profile.wire_behavior().frame_interval;
+        let interval = self
+            .session
+            .established()
+            .and_then(|established| established.capabilities.min_frame_interval())
+            .map_or(interval, |min| interval.max(min));
+        1.0 / interval.as_secs_f64()
+    }
+
+    /// Raises or clears the underrun alarm of every universe whose achieved rate crossed
+    /// its threshold.
+    fn check_underruns(&self, now: Instant) {
+        let Some(alarm) = self.underrun_alarm else {
+            return;
         };
+        let target = self.target_fps();
+        let changes: Vec<(UniverseId, AlarmChange)> = self
+            .universes
+            .read()
+            .iter()
+            .filter_map(|(universe, slot)| {
+                let change = slot.state.lock().rate.check(&alarm, target, now)?;
+                Some((*universe, change))
+            })
+            .collect();
+        for (universe, change) in changes {
+            let (achieved, active) = match change {
+                AlarmChange::Raised(fps) => {
+                    warn!(
+                        target: "alpine::stream",
+                        universe,
+                        "universe running at {fps:.1} fps against a target of {target:.1}"
+                    );
+                    (fps, true)
+                }
+                AlarmChange::Cleared(fps) => {
+                    info!(
+                        target: "alpine::stream",
+                        universe,
+                        "universe back to {fps:.1} fps"
+                    );
+                    (fps, false)
+                }
+            };
+            let _ = self.events.send(StreamEvent::Underrun {
+                universe,
+                achieved_fps: achieved.round() as u32,
+                target_fps: target.round() as u32,
+                active,
+            });
+        }
+    }
+
+    /// Feeds a receiver's delivery report into the bandwidth estimate that caps the
+    /// stream's aggregate output.
+    pub fn record_receiver_report(&self, report: &ReceiverReport) {
+        self.budget.lock().estimator_mut().on_report(report);
+    }
+
+    /// Encode and socket timings of recently sent frames.
+    pub fn latency(&self) -> &LatencyRecorder {
+        &self.latency
+    }
+
+    /// Feeds a round-trip sample into the bandwidth estimate; RTT rising well above its
+    /// minimum backs the estimate off before loss appears.
+    pub fn record_rtt(&self, rtt: Duration) {
+        self.budget.lock().estimator_mut().on_rtt(rtt);
+    }
 
-        let bytes = serde_cbor::to_vec(&envelope)
-            .map_err(|e| StreamError::Transport(format!("encode: {}", e)))?;
+    /// Sends an authenticated echo probe on the streaming path and returns its sequence
+    /// number; pass the receiver's reply to [`Self::on_echo_reply`].
+    ///
+    /// Probes give RTT samples when no receiver reports or control traffic are flowing.
+    pub fn send_echo(&self) -> Result<u64, StreamError> {
+        let established = self
+            .session
+            .ensure_streaming_ready()
+            .map_err(|_| StreamError::NotAuthenticated)?;
+        let keys = self.session.keys().ok_or(StreamError::MissingSession)?;
+        let seq = self.echo.lock().start(Instant::now());
+        let probe = EchoFrame::request(&keys, established.session_id, seq, Self::now_us())
+            .map_err(|e| StreamError::Transport(e.to_string()))?;
+        let bytes = codec::to_vec(&probe).map_err(|e| StreamError::Transport(e.to_string()))?;
         self.transport
             .send_frame(&bytes)
             .map_err(StreamError::Transport)?;
-        *self.last_frame.lock() = Some(envelope);
-        Ok(())
+        Ok(seq)
+    }
+
+    /// Sends an echo probe padded to about `size` encoded bytes and returns its sequence
+    /// number; a reply shows datagrams of that size cross the path in both directions.
+    ///
+    /// Probes are never fragmented, so a lost one points at a smaller path MTU.
+    pub fn send_mtu_probe(&self, size: usize) -> Result<u64, StreamError> {
+        let established = self
+            .session
+            .ensure_streaming_ready()
+            .map_err(|_| StreamError::NotAuthenticated)?;
+        let keys = self.session.keys().ok_or(StreamError::MissingSession)?;
+        let seq = self.echo.lock().start(Instant::now());
+        let sent_us = Self::now_us();
+        let bare = EchoFrame::padded_request(&keys, established.session_id, seq, sent_us, 0)
+            .map_err(|e| StreamError::Transport(e.to_string()))?;
+        let bare_len = codec::to_vec(&bare)
+            .map_err(|e| StreamError::Transport(e.to_string()))?
+            .len();
+        // Byte strings past 255 bytes take two more header bytes than an empty one.
+        let padding = size.saturating_sub(bare_len + 2);
+        let probe = EchoFrame::padded_request(&keys, established.session_id, seq, sent_us, padding)
+            .map_err(|e| StreamError::Transport(e.to_string()))?;
+        let bytes = codec::to_vec(&probe).map_err(|e| StreamError::Transport(e.to_string()))?;
+        self.transport
+            .send_frame(&bytes)
+            .map_err(StreamError::Transport)?;
+        Ok(seq)
+    }
+
+    /// Matches an echo reply to its probe and feeds the round trip into the bandwidth
+    /// estimate.
+    ///
+    /// Returns `None` for replies that fail authentication, belong to another session, or
+    /// answer no outstanding probe.
+    pub fn on_echo_reply(&self, reply: &EchoFrame) -> Option<EchoSample> {
+        let received_us = Self::now_us();
+        let now = Instant::now();
+        let established = self.session.established()?;
+        let keys = self.session.keys()?;
+        if reply.message_type != MessageType::AlpineEchoReply
+            || reply.session_id != established.session_id
+            || !reply.verify(&keys)
+        {
+            return None;
+        }
+        let reflected_us = reply.reflected_us?;
+        let rtt = self.echo.lock().complete(reply.seq, now)?;
+        self.record_rtt(rtt);
+        Some(EchoSample {
+            rtt,
+            forward: reflected_us
+                .checked_sub(reply.sent_us)
+                .map(Duration::from_micros),
+            reverse: received_us
+                .checked_sub(reflected_us)
+                .map(Duration::from_micros),
+        })
+    }
+
+    /// Transport the stream sends on.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Discards the stream state and returns its transport, e.g. to restart the stream
+    /// on a new profile over the same socket.
+    pub fn into_transport(self) -> T {
+        self.transport
     }
 
-    /// Updates recovery state based on observed network conditions.
+    /// Updates recovery state of the default universe from observed network conditions.
     pub fn observe_network_conditions(&self, conditions: &NetworkConditions) {
-        let mut monitor = self.recovery.lock();
-        if let Some(event) = monitor.feed(conditions) {
+        self.observe_universe_conditions(DEFAULT_UNIVERSE, conditions);
+    }
+
+    /// Replaces a universe's network view with externally computed conditions and
+    /// re-evaluates its recovery and adaptation state.
+    pub fn observe_universe_conditions(
+        &self,
+        universe: UniverseId,
+        conditions: &NetworkConditions,
+    ) {
+        let slot = self.universe(universe);
+        let mut state = slot.state.lock();
+        state.conditions = conditions.clone();
+        self.evaluate(universe, &mut state);
+    }
+
+    /// Records a receiver-reported arrival for one universe and re-evaluates it.
+    ///
+    /// Arguments follow [`NetworkConditions::record_frame`].
+    pub fn record_universe_arrival(
+        &self,
+        universe: UniverseId,
+        sequence: u64,
+        arrival_us: u64,
+        deadline_us: u64,
+    ) {
+        let slot = self.universe(universe);
+        let mut state = slot.state.lock();
+        state
+            .conditions
+            .record_frame(sequence, arrival_us, deadline_us);
+        self.evaluate(universe, &mut state);
+    }
+
+    /// Returns the current health of one universe, if it has been used.
+    pub fn universe_health(&self, universe: UniverseId) -> Option<UniverseHealth> {
+        let target = self.target_fps();
+        self.universes
+            .read()
+            .get(&universe)
+            .map(|slot| slot.state.lock().health(universe, target))
+    }
+
+    /// Returns per-universe health plus an aggregate summary.
+    pub fn health(&self) -> StreamHealth {
+        let target = self.target_fps();
+        let universes = self.universes.read();
+        let mut entries: Vec<UniverseHealth> = universes
+            .iter()
+            .map(|(universe, slot)| slot.state.lock().health(*universe, target))
+            .collect();
+        drop(universes);
+        entries.sort_by_key(|entry| entry.universe);
+        let budget = self.budget.lock();
+        StreamHealth {
+            worst_loss_ratio: entries
+                .iter()
+                .map(|entry| entry.metrics.loss_ratio)
+                .fold(0.0, f64::max),
+            recovering: entries
+                .iter()
+                .filter(|entry| entry.recovery.is_some())
+                .count(),
+            degraded_safe: entries
+                .iter()
+                .filter(|entry| entry.adaptation.degraded_safe)
+                .count(),
+            bandwidth_estimate: budget.estimator().estimate(),
+            congested: budget.congested(),
+            universes: entries,
+        }
+    }
+
+    fn evaluate(&self, universe: UniverseId, state: &mut UniverseState) {
+        if let Some(event) = state.recovery.feed(&state.conditions) {
             match event {
                 RecoveryEvent::RecoveryStarted(reason) => warn!(
                     target: "alpine::recovery",
+                    universe,
                     reason = reason.as_str(),
                     "recovery started due to {}",
                     reason.as_str()
                 ),
                 RecoveryEvent::RecoveryComplete(reason) => info!(
                     target: "alpine::recovery",
+                    universe,
                     reason = reason.as_str(),
                     "recovery complete for {}",
                     reason.as_str()
                 ),
             }
+            // Sending only fails when nobody is subscribed, which is fine.
+            let _ = self.events.send(StreamEvent::Recovery { universe, event });
         }
-        let reason = monitor.active_reason();
-        {
-            let mut guard = self.recovery_reason.lock();
-            *guard = reason;
+        let reason = state.recovery.active_reason();
+        let congested = self.budget.lock().congested();
+        let decision = decide_next_state(
+            &state.adaptation,
+            &state.conditions,
+            reason,
+            congested,
+            self.profile.intent(),
+        );
+        state.adaptation = decision.state;
+        if let Some(event) = decision.event {
+            let _ = self
+                .events
+                .send(StreamEvent::Adaptation { universe, event });
         }
-        drop(monitor);
-
-        let mut adaptation = self.adaptation.lock();
-        let decision = decide_next_state(&adaptation, conditions, reason, self.profile.intent());
-        *adaptation = decision.state;
     }
 
-    fn annotate_metadata(
-        &self,
-        metadata: Option<HashMap<String, Value>>,
+    fn annotations(
         force_keyframe: bool,
+        recovery_reason: Option<RecoveryReason>,
         adaptation_snapshot: &AdaptationState,
-    ) -> Option<HashMap<String, Value>> {
-        let mut map = metadata.unwrap_or_default();
-        if let Some(reason) = *self.recovery_reason.lock() {
-            map.insert(
-                "alpine_recovery".to_string(),
-                json!({
-                    "phase": "recovery",
-                    "reason": reason.as_str(),
-                }),
-            );
-        }
-
+    ) -> (Option<RecoveryInfo>, AdaptationInfo) {
+        let recovery = recovery_reason.map(|reason| RecoveryInfo {
+            version: METADATA_VERSION,
+            phase: "recovery".to_string(),
+            reason: reason.as_str().to_string(),
+        });
         let event_name = adaptation_snapshot
             .last_event
             .map(|event| event.as_str())
             .unwrap_or("steady");
-        map.insert(
-            "alpine_adaptation".to_string(),
-            json!({
-                "keyframe_interval": adaptation_snapshot.keyframe_interval,
-                "delta_depth": adaptation_snapshot.delta_depth,
-                "deadline_offset_ms": adaptation_snapshot.deadline_offset_ms,
-                "degraded_safe": adaptation_snapshot.degraded_safe,
-                "frames_since_keyframe": adaptation_snapshot.frames_since_keyframe,
-                "force_keyframe": force_keyframe,
-                "event": event_name,
-            }),
-        );
-        Some(map)
-    }
-
-    fn apply_jitter(&self, channels: &[u16]) -> Vec<u16> {
-        match self.jitter_strategy_from_profile() {
-            JitterStrategy::HoldLast => {
-                if channels.is_empty() {
-                    if let Some(last) = self.last_frame.lock().as_ref() {
-                        return last.channels.clone();
-                    }
-                }
-                channels.to_vec()
-            }
-            JitterStrategy::Drop => {
-                if channels.is_empty() {
-                    Vec::new()
-                } else {
-                    channels.to_vec()
-                }
-            }
-            JitterStrategy::Lerp => {
-                if let Some(last) = self.last_frame.lock().as_ref() {
-                    let mut blended = Vec::with_capacity(channels.len());
-                    for (idx, value) in channels.iter().enumerate() {
-                        let prev = last.channels.get(idx).cloned().unwrap_or(0);
-                        blended.push(((prev as u32 + *value as u32) / 2) as u16);
-                    }
-                    blended
-                } else {
-                    channels.to_vec()
-                }
-            }
-        }
+        let adaptation = AdaptationInfo {
+            version: METADATA_VERSION,
+            keyframe_interval: adaptation_snapshot.keyframe_interval,
+            delta_depth: adaptation_snapshot.delta_depth,
+            deadline_offset_ms: adaptation_snapshot.deadline_offset_ms,
+            degraded_safe: adaptation_snapshot.degraded_safe,
+            frames_since_keyframe: adaptation_snapshot.frames_since_keyframe,
+            force_keyframe,
+            event: event_name.to_string(),
+        };
+        (recovery, adaptation)
     }
 
     fn now_us() -> u64 {
@@ -223,10 +1271,6 @@ impl<T: FrameTransport> AlnpStream<T> {
     }
 
     fn jitter_strategy_from_profile(&self) -> JitterStrategy {
-        if self.profile.latency_weight() >= self.profile.resilience_weight() {
-            JitterStrategy::HoldLast
-        } else {
-            JitterStrategy::Lerp
-        }
+        self.profile.wire_behavior().jitter_strategy
     }
 }
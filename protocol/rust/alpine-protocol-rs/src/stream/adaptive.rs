@@ -8,6 +8,9 @@ use crate::stream::network::NetworkConditions;
 use crate::stream::recovery::RecoveryReason;
 
 const DWELL_FRAMES: u32 = 8;
+/// Consecutive samples that must miss the clear thresholds before a ramp relapses, so
+/// one noisy sample does not undo a recovery.
+const RELAPSE_SAMPLES: u32 = 3;
 
 const LOSS_THRESHOLD_KEYFRAME: f64 = 0.30;
 const LOSS_THRESHOLD_DISABLE: f64 = 0.50;
@@ -21,7 +24,7 @@ const BURST_THRESHOLD_DEGRADE: u64 = 10;
 const LOSS_THRESHOLD_DEGRADE: f64 = 0.60;
 const DEADLINE_STEP_MS: i16 = 10;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AdaptationSnapshot {
     keyframe_interval: u8,
     delta_depth: u8,
@@ -40,6 +43,44 @@ impl AdaptationSnapshot {
     }
 }
 
+/// Step of the staged restoration performed after leaving degraded-safe.
+///
+/// Stages run in declaration order, one per dwell period, so a marginal link is
+/// probed gradually instead of being handed the full pre-degradation settings at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RampStage {
+    /// Re-enable delta encoding at the saved depth.
+    DeltaDepth,
+    /// Restore the saved deadline offset.
+    Deadline,
+    /// Relax keyframe cadence back to the saved interval.
+    KeyframeCadence,
+}
+
+impl RampStage {
+    fn next(self) -> Option<RampStage> {
+        match self {
+            RampStage::DeltaDepth => Some(RampStage::Deadline),
+            RampStage::Deadline => Some(RampStage::KeyframeCadence),
+            RampStage::KeyframeCadence => None,
+        }
+    }
+}
+
+/// Pending restoration of the last safe snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryRamp {
+    target: AdaptationSnapshot,
+    stage: RampStage,
+}
+
+impl RecoveryRamp {
+    /// Stage that will be applied after the next dwell period.
+    pub fn stage(&self) -> RampStage {
+        self.stage
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ProfileBounds {
     pub min_keyframe_interval: u8,
@@ -91,6 +132,9 @@ pub struct AdaptationState {
     pub frames_since_keyframe: u8,
     pub degraded_safe: bool,
     pub last_safe_snapshot: Option<AdaptationSnapshot>,
+    pub ramp: Option<RecoveryRamp>,
+    /// Consecutive samples during the ramp that missed the clear thresholds.
+    pub ramp_bad_samples: u32,
     pub last_event: Option<AdaptationEvent>,
 }
 
@@ -106,6 +150,8 @@ impl AdaptationState {
             frames_since_keyframe: 0,
             degraded_safe: false,
             last_safe_snapshot: None,
+            ramp: None,
+            ramp_bad_samples: 0,
             last_event: None,
         }
     }
@@ -131,46 +177,40 @@ impl AdaptationState {
             false
         }
     }
-
-    fn would_violate_bounds(
-        &self,
-        bounds: &ProfileBounds,
-        next_interval: u8,
-        next_delta: u8,
-        next_deadline: i16,
-    ) -> bool {
-        next_interval < bounds.min_keyframe_interval
-            || next_delta < bounds.min_delta_depth
-            || next_deadline < bounds.min_deadline_offset
-            || next_deadline > bounds.max_deadline_offset
-    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DegradedReason {
     ExceededProfileBounds,
     UnrecoverableBurst,
+    /// Metrics worsened again while the post-degradation ramp was in progress.
+    RelapsedDuringRamp,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AdaptationEvent {
     KeyframeCadenceIncreased,
+    /// Keyframes were spaced back out because output exceeds the bandwidth estimate.
+    KeyframeCadenceRelaxed,
     DeltaDepthReduced,
     DeltaDisabled,
     DeadlineAdjusted,
     EnteredDegradedSafe(DegradedReason),
     ExitedDegradedSafe,
+    RampAdvanced(RampStage),
 }
 
 impl AdaptationEvent {
     pub fn as_str(&self) -> &'static str {
         match self {
             AdaptationEvent::KeyframeCadenceIncreased => "keyframe_cadence_increased",
+            AdaptationEvent::KeyframeCadenceRelaxed => "keyframe_cadence_relaxed",
             AdaptationEvent::DeltaDepthReduced => "delta_depth_reduced",
             AdaptationEvent::DeltaDisabled => "delta_disabled",
             AdaptationEvent::DeadlineAdjusted => "deadline_adjusted",
             AdaptationEvent::EnteredDegradedSafe(_) => "entered_degraded_safe",
             AdaptationEvent::ExitedDegradedSafe => "exited_degraded_safe",
+            AdaptationEvent::RampAdvanced(_) => "ramp_advanced",
         }
     }
 }
@@ -188,10 +228,13 @@ impl AdaptationDecision {
     }
 }
 
+/// One adaptation step: the state to use for the next frame given the stream's current
+/// conditions, and the event to report if anything changed.
 pub fn decide_next_state(
     current: &AdaptationState,
     network: &NetworkConditions,
     recovery: Option<RecoveryReason>,
+    congested: bool,
     intent: StreamIntent,
 ) -> AdaptationDecision {
     let mut next = current.clone();
@@ -201,36 +244,66 @@ pub fn decide_next_state(
     let gap = network.max_loss_gap();
 
     if current.degraded_safe {
-        if metrics.loss_ratio <= LOSS_THRESHOLD_DISABLE
-            && gap <= BURST_THRESHOLD_DISABLE
-            && recovery.is_none()
-        {
-            if let Some(snapshot) = current.last_safe_snapshot.clone() {
-                next.keyframe_interval = snapshot.keyframe_interval;
-                next.delta_depth = snapshot.delta_depth;
-                next.deadline_offset_ms = snapshot.deadline_offset_ms;
-                next.frames_since_keyframe = snapshot.frames_since_keyframe;
-            }
+        if metrics_clear(metrics.loss_ratio, gap, recovery) {
             next.degraded_safe = false;
+            next.ramp = current
+                .last_safe_snapshot
+                .clone()
+                .map(|target| RecoveryRamp {
+                    target,
+                    stage: RampStage::DeltaDepth,
+                });
             next.reset_frames();
             return AdaptationDecision::with_event(next, Some(AdaptationEvent::ExitedDegradedSafe));
         }
         return AdaptationDecision::with_event(next, None);
     }
 
-    if metrics.loss_ratio >= LOSS_THRESHOLD_DEGRADE && gap >= BURST_THRESHOLD_DEGRADE {
-        next.degraded_safe = true;
-        next.last_safe_snapshot = Some(AdaptationSnapshot::from_state(current));
+    if let Some(ramp) = &current.ramp {
+        if metrics.loss_ratio >= LOSS_THRESHOLD_DEGRADE && gap >= BURST_THRESHOLD_DEGRADE {
+            return enter_degraded_safe(next, current, &bounds, DegradedReason::UnrecoverableBurst);
+        }
+        if !metrics_clear(metrics.loss_ratio, gap, recovery) {
+            next.ramp_bad_samples = current.ramp_bad_samples + 1;
+            if next.ramp_bad_samples >= RELAPSE_SAMPLES {
+                return enter_degraded_safe(
+                    next,
+                    current,
+                    &bounds,
+                    DegradedReason::RelapsedDuringRamp,
+                );
+            }
+            // The next stage waits for a full clean dwell.
+            next.reset_frames();
+            return AdaptationDecision::with_event(next, None);
+        }
+        next.ramp_bad_samples = 0;
+        if next.frames_in_state < DWELL_FRAMES {
+            return AdaptationDecision::with_event(next, None);
+        }
+        match ramp.stage {
+            RampStage::DeltaDepth => next.delta_depth = ramp.target.delta_depth,
+            RampStage::Deadline => next.deadline_offset_ms = ramp.target.deadline_offset_ms,
+            RampStage::KeyframeCadence => {
+                next.keyframe_interval = ramp.target.keyframe_interval;
+                next.reset_keyframe_counter();
+            }
+        }
+        next.ramp = ramp.stage.next().map(|stage| RecoveryRamp {
+            target: ramp.target.clone(),
+            stage,
+        });
         next.reset_frames();
-        next.reset_keyframe_counter();
         return AdaptationDecision::with_event(
             next,
-            Some(AdaptationEvent::EnteredDegradedSafe(
-                DegradedReason::UnrecoverableBurst,
-            )),
+            Some(AdaptationEvent::RampAdvanced(ramp.stage)),
         );
     }
 
+    if metrics.loss_ratio >= LOSS_THRESHOLD_DEGRADE && gap >= BURST_THRESHOLD_DEGRADE {
+        return enter_degraded_safe(next, current, &bounds, DegradedReason::UnrecoverableBurst);
+    }
+
     if next.frames_in_state < DWELL_FRAMES {
         return AdaptationDecision::with_event(next, None);
     }
@@ -250,15 +323,11 @@ pub fn decide_next_state(
     if metrics.loss_ratio >= LOSS_THRESHOLD_KEYFRAME || gap >= BURST_THRESHOLD_KEYFRAME {
         let next_interval = current.keyframe_interval.saturating_sub(1);
         if next_interval < bounds.min_keyframe_interval {
-            next.degraded_safe = true;
-            next.last_safe_snapshot = Some(AdaptationSnapshot::from_state(current));
-            next.reset_frames();
-            next.reset_keyframe_counter();
-            return AdaptationDecision::with_event(
+            return enter_degraded_safe(
                 next,
-                Some(AdaptationEvent::EnteredDegradedSafe(
-                    DegradedReason::ExceededProfileBounds,
-                )),
+                current,
+                &bounds,
+                DegradedReason::ExceededProfileBounds,
             );
         }
         next.keyframe_interval = next_interval;
@@ -270,21 +339,26 @@ pub fn decide_next_state(
         );
     }
 
+    // Keyframes are the largest frames, so when output exceeds the bandwidth estimate the
+    // cadence goes back toward the profile's base without waiting for a ramp. Loss
+    // handling above takes precedence.
+    if congested && current.keyframe_interval < bounds.base_keyframe_interval {
+        next.keyframe_interval = current.keyframe_interval + 1;
+        next.reset_frames();
+        return AdaptationDecision::with_event(next, Some(AdaptationEvent::KeyframeCadenceRelaxed));
+    }
+
     if metrics.late_frame_rate >= LATE_THRESHOLD_DELTA
         && jitter_ms > JITTER_THRESHOLD_DELTA
         && current.delta_depth > bounds.min_delta_depth
     {
         let next_delta = current.delta_depth.saturating_sub(1);
         if next_delta < bounds.min_delta_depth {
-            next.degraded_safe = true;
-            next.last_safe_snapshot = Some(AdaptationSnapshot::from_state(current));
-            next.reset_frames();
-            next.reset_keyframe_counter();
-            return AdaptationDecision::with_event(
+            return enter_degraded_safe(
                 next,
-                Some(AdaptationEvent::EnteredDegradedSafe(
-                    DegradedReason::ExceededProfileBounds,
-                )),
+                current,
+                &bounds,
+                DegradedReason::ExceededProfileBounds,
             );
         }
         next.delta_depth = next_delta;
@@ -296,15 +370,11 @@ pub fn decide_next_state(
     if jitter_ms > JITTER_TIGHTEN {
         let next_deadline = current.deadline_offset_ms - DEADLINE_STEP_MS;
         if next_deadline < bounds.min_deadline_offset {
-            next.degraded_safe = true;
-            next.last_safe_snapshot = Some(AdaptationSnapshot::from_state(current));
-            next.reset_frames();
-            next.reset_keyframe_counter();
-            return AdaptationDecision::with_event(
+            return enter_degraded_safe(
                 next,
-                Some(AdaptationEvent::EnteredDegradedSafe(
-                    DegradedReason::ExceededProfileBounds,
-                )),
+                current,
+                &bounds,
+                DegradedReason::ExceededProfileBounds,
             );
         }
         next.deadline_offset_ms = next_deadline;
@@ -315,15 +385,11 @@ pub fn decide_next_state(
     if jitter_ms < JITTER_RELAX {
         let next_deadline = current.deadline_offset_ms + DEADLINE_STEP_MS;
         if next_deadline > bounds.max_deadline_offset {
-            next.degraded_safe = true;
-            next.last_safe_snapshot = Some(AdaptationSnapshot::from_state(current));
-            next.reset_frames();
-            next.reset_keyframe_counter();
-            return AdaptationDecision::with_event(
+            return enter_degraded_safe(
                 next,
-                Some(AdaptationEvent::EnteredDegradedSafe(
-                    DegradedReason::ExceededProfileBounds,
-                )),
+                current,
+                &bounds,
+                DegradedReason::ExceededProfileBounds,
             );
         }
         next.deadline_offset_ms = next_deadline;
@@ -334,6 +400,38 @@ pub fn decide_next_state(
     AdaptationDecision::with_event(next, None)
 }
 
+/// Exit condition shared by degraded-safe and the restoration ramp.
+fn metrics_clear(loss_ratio: f64, gap: u64, recovery: Option<RecoveryReason>) -> bool {
+    loss_ratio <= LOSS_THRESHOLD_DISABLE && gap <= BURST_THRESHOLD_DISABLE && recovery.is_none()
+}
+
+/// Drops to keyframes-only at the profile's fastest cadence, remembering what to restore.
+///
+/// When degradation interrupts a ramp, the ramp's target is kept as the snapshot so a
+/// partially restored state is never mistaken for the last safe configuration.
+fn enter_degraded_safe(
+    mut next: AdaptationState,
+    current: &AdaptationState,
+    bounds: &ProfileBounds,
+    reason: DegradedReason,
+) -> AdaptationDecision {
+    next.degraded_safe = true;
+    next.last_safe_snapshot = Some(
+        current
+            .ramp
+            .as_ref()
+            .map(|ramp| ramp.target.clone())
+            .unwrap_or_else(|| AdaptationSnapshot::from_state(current)),
+    );
+    next.ramp = None;
+    next.ramp_bad_samples = 0;
+    next.delta_depth = 0;
+    next.keyframe_interval = bounds.min_keyframe_interval;
+    next.reset_frames();
+    next.reset_keyframe_counter();
+    AdaptationDecision::with_event(next, Some(AdaptationEvent::EnteredDegradedSafe(reason)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -362,7 +460,7 @@ mod tests {
         let profile = StreamProfile::auto();
         let state = AdaptationState::baseline(profile.intent());
         let network = high_loss_conditions();
-        let decision = decide_next_state(&state, &network, None, profile.intent());
+        let decision = decide_next_state(&state, &network, None, false, profile.intent());
         assert_eq!(
             decision.event,
             Some(AdaptationEvent::KeyframeCadenceIncreased)
@@ -377,7 +475,13 @@ mod tests {
         state.keyframe_interval = ProfileBounds::for_intent(profile.intent()).min_keyframe_interval;
         state.frames_in_state = DWELL_FRAMES;
 
-        let decision = decide_next_state(&state, &high_loss_conditions(), None, profile.intent());
+        let decision = decide_next_state(
+            &state,
+            &high_loss_conditions(),
+            None,
+            false,
+            profile.intent(),
+        );
         assert_eq!(
             decision.event,
             Some(AdaptationEvent::EnteredDegradedSafe(
@@ -395,7 +499,13 @@ mod tests {
         state.last_safe_snapshot = Some(AdaptationSnapshot::from_state(&state));
         state.frames_in_state = DWELL_FRAMES;
 
-        let decision = decide_next_state(&state, &low_loss_conditions(), None, profile.intent());
+        let decision = decide_next_state(
+            &state,
+            &low_loss_conditions(),
+            None,
+            false,
+            profile.intent(),
+        );
         assert_eq!(decision.event, Some(AdaptationEvent::ExitedDegradedSafe));
         assert!(!decision.state.degraded_safe);
     }
@@ -415,6 +525,7 @@ mod tests {
             &state,
             &network,
             Some(RecoveryReason::BurstLoss),
+            false,
             profile.intent(),
         );
         assert_eq!(decision.event, Some(AdaptationEvent::DeltaDisabled));
@@ -426,8 +537,356 @@ mod tests {
         let profile = StreamProfile::auto();
         let mut state = AdaptationState::baseline(profile.intent());
         state.frames_in_state = 1;
-        let decision = decide_next_state(&state, &high_loss_conditions(), None, profile.intent());
+        let decision = decide_next_state(
+            &state,
+            &high_loss_conditions(),
+            None,
+            false,
+            profile.intent(),
+        );
         assert!(decision.event.is_none());
         assert_eq!(decision.state.frames_in_state, 2);
     }
+
+    #[test]
+    fn degraded_safe_forces_keyframes_only() {
+        let profile = StreamProfile::auto();
+        let mut state = AdaptationState::baseline(profile.intent());
+        state.keyframe_interval = ProfileBounds::for_intent(profile.intent()).min_keyframe_interval;
+
+        let decision = decide_next_state(
+            &state,
+            &high_loss_conditions(),
+            None,
+            false,
+            profile.intent(),
+        );
+        assert!(decision.state.degraded_safe);
+        assert_eq!(decision.state.delta_depth, 0);
+        assert_eq!(
+            decision.state.keyframe_interval,
+            ProfileBounds::for_intent(profile.intent()).min_keyframe_interval
+        );
+    }
+
+    #[test]
+    fn congestion_relaxes_keyframe_cadence_up_to_base() {
+        let intent = StreamProfile::auto().intent();
+        let bounds = ProfileBounds::for_intent(intent);
+        let mut state = AdaptationState::baseline(intent);
+        state.keyframe_interval = bounds.base_keyframe_interval - 1;
+
+        let decision = decide_next_state(&state, &low_loss_conditions(), None, true, intent);
+        assert_eq!(
+            decision.event,
+            Some(AdaptationEvent::KeyframeCadenceRelaxed)
+        );
+        assert_eq!(
+            decision.state.keyframe_interval,
+            bounds.base_keyframe_interval
+        );
+
+        let state = dwell(decision.state, intent);
+        let decision = decide_next_state(&state, &low_loss_conditions(), None, true, intent);
+        assert_ne!(
+            decision.event,
+            Some(AdaptationEvent::KeyframeCadenceRelaxed)
+        );
+        assert_eq!(
+            decision.state.keyframe_interval,
+            bounds.base_keyframe_interval
+        );
+    }
+
+    fn dwell(mut state: AdaptationState, intent: StreamIntent) -> AdaptationState {
+        for _ in 1..DWELL_FRAMES {
+            let decision = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+            assert!(decision.event.is_none());
+            state = decision.state;
+        }
+        state
+    }
+
+    #[test]
+    fn exit_ramps_back_one_stage_per_dwell() {
+        let intent = StreamProfile::auto().intent();
+        let bounds = ProfileBounds::for_intent(intent);
+        let mut state = AdaptationState::baseline(intent);
+        state.deadline_offset_ms = -10;
+        let saved = AdaptationSnapshot::from_state(&state);
+        state.degraded_safe = true;
+        state.last_safe_snapshot = Some(saved.clone());
+        state.delta_depth = 0;
+        state.keyframe_interval = bounds.min_keyframe_interval;
+        state.deadline_offset_ms = -15;
+
+        let exited = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+        assert_eq!(exited.event, Some(AdaptationEvent::ExitedDegradedSafe));
+        assert_eq!(exited.state.delta_depth, 0);
+
+        let state = dwell(exited.state, intent);
+        let step = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+        assert_eq!(
+            step.event,
+            Some(AdaptationEvent::RampAdvanced(RampStage::DeltaDepth))
+        );
+        assert_eq!(step.state.delta_depth, saved.delta_depth);
+        assert_eq!(step.state.deadline_offset_ms, -15);
+
+        let state = dwell(step.state, intent);
+        let step = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+        assert_eq!(
+            step.event,
+            Some(AdaptationEvent::RampAdvanced(RampStage::Deadline))
+        );
+        assert_eq!(step.state.deadline_offset_ms, saved.deadline_offset_ms);
+        assert_eq!(step.state.keyframe_interval, bounds.min_keyframe_interval);
+
+        let state = dwell(step.state, intent);
+        let step = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+        assert_eq!(
+            step.event,
+            Some(AdaptationEvent::RampAdvanced(RampStage::KeyframeCadence))
+        );
+        assert_eq!(step.state.keyframe_interval, saved.keyframe_interval);
+        assert!(step.state.ramp.is_none());
+    }
+
+    #[test]
+    fn relapse_during_ramp_keeps_original_snapshot() {
+        let intent = StreamProfile::auto().intent();
+        let mut state = AdaptationState::baseline(intent);
+        let saved = AdaptationSnapshot::from_state(&state);
+        state.degraded_safe = true;
+        state.last_safe_snapshot = Some(saved.clone());
+        let exited = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+
+        let mut state = exited.state;
+        for _ in 1..RELAPSE_SAMPLES {
+            let held = decide_next_state(
+                &state,
+                &low_loss_conditions(),
+                Some(RecoveryReason::SustainedLoss),
+                false,
+                intent,
+            );
+            assert!(held.event.is_none());
+            state = held.state;
+        }
+        let relapse = decide_next_state(
+            &state,
+            &low_loss_conditions(),
+            Some(RecoveryReason::SustainedLoss),
+            false,
+            intent,
+        );
+        assert_eq!(
+            relapse.event,
+            Some(AdaptationEvent::EnteredDegradedSafe(
+                DegradedReason::RelapsedDuringRamp
+            ))
+        );
+        assert_eq!(relapse.state.last_safe_snapshot, Some(saved));
+        assert!(relapse.state.ramp.is_none());
+    }
+
+    #[test]
+    fn one_noisy_sample_does_not_reset_the_ramp() {
+        let intent = StreamProfile::auto().intent();
+        let mut state = AdaptationState::baseline(intent);
+        let saved = AdaptationSnapshot::from_state(&state);
+        state.degraded_safe = true;
+        state.last_safe_snapshot = Some(saved.clone());
+        let exited = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+
+        let noisy = decide_next_state(
+            &exited.state,
+            &high_loss_conditions(),
+            Some(RecoveryReason::SustainedLoss),
+            false,
+            intent,
+        );
+        assert!(noisy.event.is_none());
+        assert!(!noisy.state.degraded_safe);
+        assert_eq!(
+            noisy.state.ramp.as_ref().map(RecoveryRamp::stage),
+            Some(RampStage::DeltaDepth)
+        );
+
+        // Clean samples clear the count, and the ramp carries on after a clean dwell.
+        let state = dwell(noisy.state, intent);
+        assert_eq!(state.ramp_bad_samples, 0);
+        let step = decide_next_state(&state, &low_loss_conditions(), None, false, intent);
+        assert_eq!(
+            step.event,
+            Some(AdaptationEvent::RampAdvanced(RampStage::DeltaDepth))
+        );
+        assert_eq!(step.state.delta_depth, saved.delta_depth);
+    }
+
+    mod invariants {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// One observed frame: sequence gap since the previous frame, inter-arrival time,
+        /// and whether it missed its deadline.
+        type Arrival = (u64, u64, bool);
+
+        /// Conditions produced by a frame timeline.
+        fn conditions(arrivals: &[Arrival], extra_loss: &[u64]) -> NetworkConditions {
+            let mut cond = NetworkConditions::new();
+            let (mut seq, mut at) = (0u64, 0u64);
+            for (i, (gap, interval, late)) in arrivals.iter().enumerate() {
+                seq += gap + extra_loss.get(i).copied().unwrap_or(0);
+                at += interval;
+                let deadline = if *late { at.saturating_sub(1) } else { at };
+                cond.record_frame(seq, at, deadline);
+            }
+            cond
+        }
+
+        fn arrivals() -> impl Strategy<Value = Vec<Arrival>> {
+            prop::collection::vec((1u64..=12, 0u64..=20_000, any::<bool>()), 2..24)
+        }
+
+        fn intent() -> impl Strategy<Value = StreamIntent> {
+            prop_oneof![
+                Just(StreamIntent::Auto),
+                Just(StreamIntent::Realtime),
+                Just(StreamIntent::Install),
+            ]
+        }
+
+        fn recovery() -> impl Strategy<Value = Option<RecoveryReason>> {
+            prop_oneof![
+                2 => Just(None),
+                1 => Just(Some(RecoveryReason::SustainedLoss)),
+                1 => Just(Some(RecoveryReason::BurstLoss)),
+            ]
+        }
+
+        fn steps() -> impl Strategy<Value = Vec<(Vec<Arrival>, Option<RecoveryReason>)>> {
+            prop::collection::vec((arrivals(), recovery()), 0..96)
+        }
+
+        /// Runs `steps` from the baseline, checking `check` on every decision.
+        fn walk(
+            intent: StreamIntent,
+            steps: &[(Vec<Arrival>, Option<RecoveryReason>)],
+            mut check: impl FnMut(&AdaptationState, &AdaptationDecision),
+        ) -> AdaptationState {
+            let mut state = AdaptationState::baseline(intent);
+            for (arrivals, recovery) in steps {
+                let decision =
+                    decide_next_state(&state, &conditions(arrivals, &[]), *recovery, false, intent);
+                check(&state, &decision);
+                state = decision.state;
+            }
+            state
+        }
+
+        /// Relax, hold, or tighten; degraded-safe counts as tightening.
+        fn severity(current: &AdaptationState, decision: &AdaptationDecision) -> u8 {
+            match decision.event {
+                Some(AdaptationEvent::ExitedDegradedSafe)
+                | Some(AdaptationEvent::RampAdvanced(_))
+                | Some(AdaptationEvent::KeyframeCadenceRelaxed) => 0,
+                Some(AdaptationEvent::DeadlineAdjusted)
+                    if decision.state.deadline_offset_ms > current.deadline_offset_ms =>
+                {
+                    0
+                }
+                None => 1,
+                Some(_) => 2,
+            }
+        }
+
+        proptest! {
+            #[test]
+            fn never_exceeds_profile_bounds(intent in intent(), steps in steps()) {
+                let bounds = ProfileBounds::for_intent(intent);
+                walk(intent, &steps, |_, decision| {
+                    let state = &decision.state;
+                    assert!(state.keyframe_interval >= bounds.min_keyframe_interval);
+                    assert!(state.keyframe_interval <= bounds.base_keyframe_interval);
+                    assert!(state.delta_depth <= bounds.base_delta_depth);
+                    assert!(state.deadline_offset_ms >= bounds.min_deadline_offset);
+                    assert!(state.deadline_offset_ms <= bounds.max_deadline_offset);
+                    if state.degraded_safe {
+                        assert_eq!(state.delta_depth, 0);
+                        assert_eq!(state.keyframe_interval, bounds.min_keyframe_interval);
+                    }
+                });
+            }
+
+            #[test]
+            fn tuning_changes_wait_for_dwell(intent in intent(), steps in steps()) {
+                walk(intent, &steps, |current, decision| {
+                    let tuned = matches!(
+                        decision.event,
+                        Some(AdaptationEvent::KeyframeCadenceIncreased)
+                            | Some(AdaptationEvent::KeyframeCadenceRelaxed)
+                            | Some(AdaptationEvent::DeltaDepthReduced)
+                            | Some(AdaptationEvent::DeltaDisabled)
+                            | Some(AdaptationEvent::DeadlineAdjusted)
+                            | Some(AdaptationEvent::RampAdvanced(_))
+                    );
+                    if tuned {
+                        assert!(current.frames_in_state + 1 >= DWELL_FRAMES);
+                    }
+                    if decision.event.is_some() {
+                        assert_eq!(decision.state.frames_in_state, 0);
+                    }
+                });
+            }
+
+            #[test]
+            fn degraded_safe_is_always_recoverable(intent in intent(), steps in steps()) {
+                let clean = conditions(&[(1, 1_000, false); 4], &[]);
+                let mut state = walk(intent, &steps, |_, _| {});
+                if !state.degraded_safe {
+                    // A long burst degrades from any state, ramping or not.
+                    let burst = conditions(&[(1, 1_000, false), (20, 1_000, false)], &[]);
+                    state = decide_next_state(&state, &burst, None, false, intent).state;
+                    prop_assert!(state.degraded_safe);
+                }
+                let exited = decide_next_state(&state, &clean, None, false, intent);
+                prop_assert_eq!(exited.event, Some(AdaptationEvent::ExitedDegradedSafe));
+                let mut state = exited.state;
+                for _ in 0..3 * DWELL_FRAMES {
+                    if state.ramp.is_none() {
+                        break;
+                    }
+                    state = decide_next_state(&state, &clean, None, false, intent).state;
+                }
+                prop_assert!(!state.degraded_safe);
+                prop_assert!(state.ramp.is_none());
+            }
+
+            #[test]
+            fn worse_loss_never_relaxes_the_response(
+                intent in intent(),
+                steps in steps(),
+                arrivals in arrivals(),
+                extra_loss in prop::collection::vec(0u64..=12, 24),
+                recovery in recovery(),
+            ) {
+                let state = walk(intent, &steps, |_, _| {});
+                let base = decide_next_state(&state, &conditions(&arrivals, &[]), recovery, false, intent);
+                let worse = decide_next_state(
+                    &state,
+                    &conditions(&arrivals, &extra_loss),
+                    recovery,
+                    false,
+                    intent,
+                );
+                prop_assert!(
+                    severity(&state, &worse) >= severity(&state, &base),
+                    "base {:?} worse {:?}",
+                    base.event,
+                    worse.event
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,180 @@
+//! Receive-side priority arbitration.
+//!
+//! Each channel is held by the source that last won it. A frame takes a channel when its
+//! `priority` is at least the holder's, or when the holder has been silent for longer than
+//! the hold time, so a higher-priority source that skips a few frames does not flicker
+//! back to a lower one. Frames carrying `groups` claim only the grouped channels, which
+//! lets a high-priority source override part of a universe; frames without groups claim
+//! every channel they carry.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use uuid::Uuid;
+
+use crate::messages::{ChannelBuffer, FrameEnvelope, UniverseId};
+
+/// How long a silent source keeps its channels against lower priorities by default.
+pub const DEFAULT_PRIORITY_HOLD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+struct Claim {
+    source: Uuid,
+    priority: u8,
+    value: u16,
+    seen: Instant,
+}
+
+/// Per-channel priority arbitration across sources, keyed by session id.
+#[derive(Debug)]
+pub struct PriorityArbiter {
+    hold: Duration,
+    universes: Mutex<HashMap<UniverseId, Vec<Option<Claim>>>>,
+}
+
+impl PriorityArbiter {
+    pub fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            universes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn hold(&self) -> Duration {
+        self.hold
+    }
+
+    /// Applies `frame` and returns the universe's arbitrated channels.
+    pub fn arbitrate(&self, frame: &FrameEnvelope, now: Instant) -> ChannelBuffer {
+        let mut universes = self.universes.lock();
+        let claims = universes.entry(frame.universe).or_default();
+        if claims.len() < frame.channels.len() {
+            claims.resize(frame.channels.len(), None);
+        }
+        let mut claim = |index: usize| {
+            let (Some(&value), Some(slot)) = (frame.channels.get(index), claims.get_mut(index))
+            else {
+                return;
+            };
+            let wins = match slot {
+                None => true,
+                Some(held) => {
+                    held.source == frame.session_id
+                        || frame.priority >= held.priority
+                        || now.saturating_duration_since(held.seen) > self.hold
+                }
+            };
+            if wins {
+                *slot = Some(Claim {
+                    source: frame.session_id,
+                    priority: frame.priority,
+                    value,
+                    seen: now,
+                });
+            }
+        };
+        match frame.groups.as_ref().filter(|groups| !groups.is_empty()) {
+            Some(groups) => {
+                for index in groups.values().flatten() {
+                    claim(usize::from(*index));
+                }
+            }
+            None => (0..frame.channels.len()).for_each(claim),
+        }
+        claims
+            .iter()
+            .map(|slot| slot.map(|held| held.value).unwrap_or(0))
+            .collect()
+    }
+
+    /// Releases every channel `source` holds, e.g. when its session closes; the values
+    /// stay until another source claims them.
+    pub fn forget(&self, source: Uuid) {
+        for claims in self.universes.lock().values_mut() {
+            for held in claims.iter_mut().flatten() {
+                if held.source == source {
+                    // Priority zero yields to any frame.
+                    held.priority = 0;
+                }
+            }
+        }
+    }
+}
+
+impl Default for PriorityArbiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRIORITY_HOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ChannelFormat, MessageType};
+
+    fn frame(
+        source: Uuid,
+        priority: u8,
+        channels: Vec<u16>,
+        group: Option<Vec<u16>>,
+    ) -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: source,
+            universe: 0,
+            timestamp_us: 0,
+            priority,
+            channel_format: ChannelFormat::U16,
+            channels: channels.into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: group.map(|g| [("front".to_string(), g)].into_iter().collect()),
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        }
+    }
+
+    #[test]
+    fn higher_priority_preempts_and_holds_through_gaps() {
+        let arbiter = PriorityArbiter::new(Duration::from_millis(500));
+        let (low, high) = (Uuid::new_v4(), Uuid::new_v4());
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(
+            arbiter.arbitrate(&frame(low, 10, vec![1, 1, 1], None), at(0))[..],
+            [1, 1, 1]
+        );
+        // The high-priority source overrides only its "front" group.
+        assert_eq!(
+            arbiter.arbitrate(&frame(high, 200, vec![9, 9, 9], Some(vec![0, 1])), at(10))[..],
+            [9, 9, 1]
+        );
+        // It goes quiet briefly; the low source keeps only its own channel.
+        assert_eq!(
+            arbiter.arbitrate(&frame(low, 10, vec![2, 2, 2], None), at(300))[..],
+            [9, 9, 2]
+        );
+        // Past the hold time the low source takes the group back.
+        assert_eq!(
+            arbiter.arbitrate(&frame(low, 10, vec![3, 3, 3], None), at(600))[..],
+            [3, 3, 3]
+        );
+        // Equal or higher priority wins immediately.
+        assert_eq!(
+            arbiter.arbitrate(&frame(high, 200, vec![8, 8, 8], None), at(610))[..],
+            [8, 8, 8]
+        );
+        arbiter.forget(high);
+        assert_eq!(
+            arbiter.arbitrate(&frame(low, 10, vec![4, 4, 4], None), at(620))[..],
+            [4, 4, 4]
+        );
+    }
+}
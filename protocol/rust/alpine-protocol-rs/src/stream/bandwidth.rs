@@ -0,0 +1,263 @@
+//! Congestion-aware bandwidth estimation.
+//!
+//! Receiver reports give the rate the far end actually received and the loss it saw;
+//! round-trip times show queues building before loss does. The estimate follows a cautious
+//! AIMD: loss or a rising RTT cuts it multiplicatively, clean reports grow it slowly past
+//! the delivered rate. [`SendBudget`] then keeps aggregate output across universes under
+//! the estimate, thinning the refresh of static universes, the more the deeper the
+//! congestion, before dropping frames of changing ones.
+
+use std::time::{Duration, Instant};
+
+/// Floor for the estimate in bytes per second, so one bad report cannot stall a stream.
+pub const MIN_BANDWIDTH_BPS: u64 = 16 * 1024;
+
+/// How often a static universe is still refreshed once the link turns congested.
+pub const STATIC_REFRESH: Duration = Duration::from_secs(1);
+
+/// How often a static universe is still refreshed as the budget runs dry.
+pub const MAX_STATIC_REFRESH: Duration = Duration::from_secs(4);
+
+const LOSS_BACKOFF: f64 = 0.10;
+const LOSS_CLEAN: f64 = 0.02;
+const PROBE_GAIN: f64 = 1.05;
+const QUEUE_RTT_FACTOR: f64 = 1.5;
+const QUEUE_RTT_SLACK: Duration = Duration::from_millis(5);
+const DELAY_BACKOFF: f64 = 0.85;
+/// Burst the budget allows, as a fraction of one second at the estimated rate.
+const BURST_FRACTION: f64 = 0.25;
+
+/// Delivery summary a receiver sends back for one reporting interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReceiverReport {
+    /// Frame bytes received during the interval.
+    pub bytes_received: u64,
+    pub interval: Duration,
+    /// Fraction of frames lost during the interval.
+    pub loss_ratio: f64,
+}
+
+/// Estimates the bytes per second the path to the receiver can carry.
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthEstimator {
+    estimate: Option<f64>,
+    min_rtt: Option<Duration>,
+}
+
+impl BandwidthEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current estimate in bytes per second; `None` (unlimited) until the first report.
+    pub fn estimate(&self) -> Option<u64> {
+        self.estimate.map(|bps| bps as u64)
+    }
+
+    pub fn on_report(&mut self, report: &ReceiverReport) {
+        let secs = report.interval.as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+        let delivered = report.bytes_received as f64 / secs;
+        let next = if report.loss_ratio > LOSS_BACKOFF {
+            delivered * (1.0 - report.loss_ratio / 2.0)
+        } else if report.loss_ratio < LOSS_CLEAN {
+            self.estimate.map_or(delivered, |bps| bps.max(delivered)) * PROBE_GAIN
+        } else {
+            self.estimate.unwrap_or(delivered)
+        };
+        self.estimate = Some(next.max(MIN_BANDWIDTH_BPS as f64));
+    }
+
+    /// Backs off when `rtt` shows a queue building well above the smallest RTT seen.
+    pub fn on_rtt(&mut self, rtt: Duration) {
+        let min_rtt = *self.min_rtt.get_or_insert(rtt);
+        if rtt < min_rtt {
+            self.min_rtt = Some(rtt);
+            return;
+        }
+        let queued = min_rtt.mul_f64(QUEUE_RTT_FACTOR) + QUEUE_RTT_SLACK;
+        if rtt > queued {
+            if let Some(bps) = self.estimate.as_mut() {
+                *bps = (*bps * DELAY_BACKOFF).max(MIN_BANDWIDTH_BPS as f64);
+            }
+        }
+    }
+}
+
+/// Token bucket sharing the estimated bandwidth across every universe of a stream.
+#[derive(Debug, Default)]
+pub struct SendBudget {
+    estimator: BandwidthEstimator,
+    tokens: f64,
+    refilled: Option<Instant>,
+    /// Longest static refresh the receiver tolerates; see [`SendBudget::cap_static_refresh`].
+    refresh_cap: Option<Duration>,
+}
+
+impl SendBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn estimator(&self) -> &BandwidthEstimator {
+        &self.estimator
+    }
+
+    pub fn estimator_mut(&mut self) -> &mut BandwidthEstimator {
+        &mut self.estimator
+    }
+
+    /// Whether less than half the burst allowance is left, i.e. output is pressing
+    /// against the estimate.
+    pub fn congested(&self) -> bool {
+        self.capacity()
+            .is_some_and(|capacity| self.tokens < capacity / 2.0)
+    }
+
+    /// How often static universes are refreshed at the current congestion, if they are
+    /// being thinned at all.
+    ///
+    /// Stretches from [`STATIC_REFRESH`] when the link turns congested to
+    /// [`MAX_STATIC_REFRESH`] once the burst allowance is spent, but never past the cap.
+    pub fn static_refresh(&self) -> Option<Duration> {
+        let capacity = self.capacity().filter(|_| self.congested())?;
+        let depth = (1.0 - self.tokens / (capacity / 2.0)).clamp(0.0, 1.0);
+        let refresh = STATIC_REFRESH + (MAX_STATIC_REFRESH - STATIC_REFRESH).mul_f64(depth);
+        Some(self.refresh_cap.map_or(refresh, |cap| refresh.min(cap)))
+    }
+
+    /// Keeps static refreshes at most `cap` apart however congested the link, e.g. within
+    /// the receiver's hold timeout so thinned universes never fade.
+    pub fn cap_static_refresh(&mut self, cap: Option<Duration>) {
+        self.refresh_cap = cap;
+    }
+
+    /// Decides whether a frame of `bytes` may go out now and charges it if so.
+    ///
+    /// `static_since` is when the universe last sent this same content, if it has not
+    /// changed; such frames yield once the link is congested and are only refreshed
+    /// every [`SendBudget::static_refresh`].
+    pub fn admit(&mut self, bytes: usize, static_since: Option<Instant>, now: Instant) -> bool {
+        if !self.refill(now) {
+            return true;
+        }
+        if let (Some(sent), Some(refresh)) = (static_since, self.static_refresh()) {
+            if now.saturating_duration_since(sent) < refresh {
+                return false;
+            }
+        }
+        if self.tokens < bytes as f64 {
+            return false;
+        }
+        self.tokens -= bytes as f64;
+        true
+    }
+
+    /// Charges a frame of `bytes` that went out regardless of the budget, such as an
+    /// express frame; later frames wait until the overdraft is refilled.
+    pub fn charge(&mut self, bytes: usize, now: Instant) {
+        if self.refill(now) {
+            self.tokens -= bytes as f64;
+        }
+    }
+
+    /// Adds the tokens earned since the last refill; `false` while there is no estimate.
+    fn refill(&mut self, now: Instant) -> bool {
+        let Some(capacity) = self.capacity() else {
+            return false;
+        };
+        let rate = capacity / BURST_FRACTION;
+        let elapsed = self
+            .refilled
+            .map_or(Duration::ZERO, |at| now.saturating_duration_since(at));
+        self.tokens = (self.tokens + rate * elapsed.as_secs_f64()).min(capacity);
+        if self.refilled.is_none() {
+            self.tokens = capacity;
+        }
+        self.refilled = Some(now);
+        true
+    }
+
+    fn capacity(&self) -> Option<f64> {
+        self.estimator.estimate.map(|bps| bps * BURST_FRACTION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(bytes_received: u64, loss_ratio: f64) -> ReceiverReport {
+        ReceiverReport {
+            bytes_received,
+            interval: Duration::from_secs(1),
+            loss_ratio,
+        }
+    }
+
+    #[test]
+    fn estimate_backs_off_on_loss_and_queueing() {
+        let mut estimator = BandwidthEstimator::new();
+        assert_eq!(estimator.estimate(), None);
+        estimator.on_report(&report(100_000, 0.0));
+        assert_eq!(estimator.estimate(), Some(105_000));
+        estimator.on_report(&report(80_000, 0.4));
+        assert_eq!(estimator.estimate(), Some(64_000));
+        estimator.on_report(&report(60_000, 0.05));
+        assert_eq!(estimator.estimate(), Some(64_000));
+
+        estimator.on_rtt(Duration::from_millis(10));
+        estimator.on_rtt(Duration::from_millis(12));
+        assert_eq!(estimator.estimate(), Some(64_000));
+        estimator.on_rtt(Duration::from_millis(40));
+        assert_eq!(estimator.estimate(), Some(54_400));
+
+        estimator.on_report(&report(0, 1.0));
+        assert_eq!(estimator.estimate(), Some(MIN_BANDWIDTH_BPS));
+    }
+
+    #[test]
+    fn static_universes_yield_first() {
+        let mut budget = SendBudget::new();
+        let start = Instant::now();
+        assert!(budget.admit(1_000_000, None, start));
+        // 40 kB/s with a 10 kB burst.
+        budget.estimator_mut().on_report(&report(40_000, 0.05));
+        assert!(budget.admit(4_000, None, start));
+        assert!(!budget.congested());
+        assert!(budget.admit(2_000, Some(start), start));
+        assert!(budget.congested());
+        // Unchanged content waits for its refresh; changing content still fits.
+        assert!(!budget.admit(500, Some(start), start));
+        assert!(budget.admit(3_000, None, start));
+        assert!(!budget.admit(3_000, None, start));
+        // Tokens refill at the estimated rate.
+        let later = start + Duration::from_millis(100);
+        assert!(budget.admit(3_000, None, later));
+        assert!(budget.admit(500, Some(start), start + STATIC_REFRESH));
+    }
+
+    #[test]
+    fn static_refresh_stretches_with_congestion_up_to_the_cap() {
+        let mut budget = SendBudget::new();
+        let start = Instant::now();
+        // 40 kB/s with a 10 kB burst.
+        budget.estimator_mut().on_report(&report(40_000, 0.05));
+        assert!(budget.admit(5_000, None, start));
+        assert_eq!(budget.static_refresh(), None);
+        assert!(budget.admit(2_500, None, start));
+        assert_eq!(
+            budget.static_refresh(),
+            Some(STATIC_REFRESH + (MAX_STATIC_REFRESH - STATIC_REFRESH) / 2)
+        );
+        // An overdraft far past the burst takes seconds to refill.
+        budget.charge(100_000, start);
+        assert_eq!(budget.static_refresh(), Some(MAX_STATIC_REFRESH));
+        assert!(!budget.admit(100, Some(start), start + STATIC_REFRESH * 2));
+
+        budget.cap_static_refresh(Some(Duration::from_millis(1_500)));
+        assert_eq!(budget.static_refresh(), Some(Duration::from_millis(1_500)));
+    }
+}
@@ -0,0 +1,185 @@
+//! Echo probes for active RTT measurement.
+//!
+//! The sender puts an `alpine_echo` on the streaming path; the receiver verifies it and
+//! reflects it straight back as `alpine_echo_reply` with its own clock reading. The round
+//! trip comes from the sender's monotonic clock, so it holds without synchronised clocks;
+//! the one-way split uses the two wall clocks and is only as good as their sync. Both
+//! directions are authenticated with the stream key, requests and replies on separate
+//! nonces.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::crypto::{compute_stream_mac, verify_stream_mac, CryptoError, SessionKeys};
+use crate::messages::{EchoFrame, MessageType, PackedChannels};
+
+/// Probes awaiting a reply; the oldest is forgotten once more are in flight.
+pub const MAX_OUTSTANDING_ECHOES: usize = 16;
+
+/// Delay measured by one echo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EchoSample {
+    pub rtt: Duration,
+    /// Sender to receiver; `None` when the clocks disagree by more than the delay.
+    pub forward: Option<Duration>,
+    /// Receiver back to sender; `None` when the clocks disagree by more than the delay.
+    pub reverse: Option<Duration>,
+}
+
+impl EchoFrame {
+    /// Builds an authenticated probe.
+    pub fn request(
+        keys: &SessionKeys,
+        session_id: Uuid,
+        seq: u64,
+        sent_us: u64,
+    ) -> Result<Self, CryptoError> {
+        let mut frame = Self {
+            message_type: MessageType::AlpineEcho,
+            session_id,
+            seq,
+            sent_us,
+            reflected_us: None,
+            padding: None,
+            mac: Vec::new(),
+        };
+        frame.mac = frame.tag(keys)?;
+        Ok(frame)
+    }
+
+    /// Builds an authenticated probe carrying `padding` filler bytes, for measuring
+    /// whether datagrams of a given size cross the path.
+    pub fn padded_request(
+        keys: &SessionKeys,
+        session_id: Uuid,
+        seq: u64,
+        sent_us: u64,
+        padding: usize,
+    ) -> Result<Self, CryptoError> {
+        let mut frame = Self {
+            message_type: MessageType::AlpineEcho,
+            session_id,
+            seq,
+            sent_us,
+            reflected_us: None,
+            padding: Some(PackedChannels(vec![0; padding])),
+            mac: Vec::new(),
+        };
+        frame.mac = frame.tag(keys)?;
+        Ok(frame)
+    }
+
+    /// Builds the authenticated reply to this probe.
+    pub fn reflect(&self, keys: &SessionKeys, reflected_us: u64) -> Result<Self, CryptoError> {
+        let mut reply = Self {
+            message_type: MessageType::AlpineEchoReply,
+            reflected_us: Some(reflected_us),
+            mac: Vec::new(),
+            ..self.clone()
+        };
+        reply.mac = reply.tag(keys)?;
+        Ok(reply)
+    }
+
+    /// Whether the MAC matches the frame's contents under `keys`.
+    pub fn verify(&self, keys: &SessionKeys) -> bool {
+        let (nonce, payload, aad) = self.signed_parts();
+        verify_stream_mac(keys, nonce, &payload, &aad, &self.mac)
+    }
+
+    fn tag(&self, keys: &SessionKeys) -> Result<Vec<u8>, CryptoError> {
+        let (nonce, payload, aad) = self.signed_parts();
+        compute_stream_mac(keys, nonce, &payload, &aad)
+    }
+
+    fn signed_parts(&self) -> (u64, Vec<u8>, Vec<u8>) {
+        let reply = self.message_type == MessageType::AlpineEchoReply;
+        let mut payload = Vec::with_capacity(24);
+        payload.extend_from_slice(&self.seq.to_be_bytes());
+        payload.extend_from_slice(&self.sent_us.to_be_bytes());
+        payload.extend_from_slice(&self.reflected_us.unwrap_or(0).to_be_bytes());
+        if let Some(padding) = &self.padding {
+            payload.extend_from_slice(&padding.0);
+        }
+        let mut aad = self.session_id.as_bytes().to_vec();
+        aad.push(u8::from(reply));
+        ((self.seq << 1) | u64::from(reply), payload, aad)
+    }
+}
+
+/// Sender-side bookkeeping of probes in flight.
+#[derive(Debug, Default)]
+pub(crate) struct EchoProbe {
+    next_seq: u64,
+    outstanding: VecDeque<(u64, Instant)>,
+}
+
+impl EchoProbe {
+    pub(crate) fn start(&mut self, now: Instant) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if self.outstanding.len() == MAX_OUTSTANDING_ECHOES {
+            self.outstanding.pop_front();
+        }
+        self.outstanding.push_back((seq, now));
+        seq
+    }
+
+    /// Round trip of probe `seq`, consuming it; `None` for unknown or repeated replies.
+    pub(crate) fn complete(&mut self, seq: u64, now: Instant) -> Option<Duration> {
+        let index = self.outstanding.iter().position(|(s, _)| *s == seq)?;
+        let (_, sent) = self.outstanding.remove(index)?;
+        Some(now.saturating_duration_since(sent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SuiteId;
+
+    fn keys(byte: u8) -> SessionKeys {
+        SessionKeys {
+            shared_secret: vec![byte; 32],
+            control_key: [byte; 32],
+            stream_key: [byte.wrapping_add(1); 32],
+            suite: SuiteId::default(),
+            channel_binding: [0; 32],
+        }
+    }
+
+    #[test]
+    fn echoes_are_authenticated_and_matched_once() {
+        let session = Uuid::new_v4();
+        let request = EchoFrame::request(&keys(1), session, 7, 1_000).unwrap();
+        assert!(request.verify(&keys(1)));
+        assert!(!request.verify(&keys(2)));
+
+        let reply = request.reflect(&keys(1), 1_400).unwrap();
+        assert!(reply.verify(&keys(1)));
+        assert_ne!(reply.mac, request.mac);
+        let mut forged = reply.clone();
+        forged.reflected_us = Some(1_500);
+        assert!(!forged.verify(&keys(1)));
+        // A request relabelled as a reply does not carry a valid reply MAC.
+        let mut relabelled = request.clone();
+        relabelled.message_type = MessageType::AlpineEchoReply;
+        assert!(!relabelled.verify(&keys(1)));
+
+        let mut probe = EchoProbe::default();
+        let start = Instant::now();
+        let first = probe.start(start);
+        for _ in 0..MAX_OUTSTANDING_ECHOES {
+            probe.start(start);
+        }
+        assert_eq!(probe.complete(first, start), None);
+        let later = start + Duration::from_millis(3);
+        assert_eq!(
+            probe.complete(first + 1, later),
+            Some(Duration::from_millis(3))
+        );
+        assert_eq!(probe.complete(first + 1, later), None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Forward error correction with XOR parity frames.
+//!
+//! The sender XORs the encodings of every `group` consecutive frames of a universe into
+//! one `alpine_parity` frame. A receiver that got all but one frame of the group XORs the
+//! parity with the ones it has and recovers the missing datagram byte for byte, without
+//! a retransmission round trip.
+
+use std::collections::{HashMap, VecDeque};
+
+use uuid::Uuid;
+
+use crate::messages::{MessageType, PackedChannels, ParityFrame, UniverseId};
+
+/// Received frames a [`FecDecoder`] keeps for rebuilding.
+pub const FEC_HISTORY: usize = 64;
+
+fn xor_into(acc: &mut Vec<u8>, bytes: &[u8]) {
+    if acc.len() < bytes.len() {
+        acc.resize(bytes.len(), 0);
+    }
+    for (a, b) in acc.iter_mut().zip(bytes) {
+        *a ^= b;
+    }
+}
+
+/// Sender side: collects each universe's frames and emits parity per full group.
+#[derive(Debug)]
+pub struct FecEncoder {
+    group: usize,
+    pending: HashMap<UniverseId, Vec<(u64, Vec<u8>)>>,
+}
+
+impl FecEncoder {
+    pub fn new(group: usize) -> Self {
+        Self {
+            group: group.max(2),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Adds a sent frame; returns the parity frame once `universe` completes a group.
+    pub fn push(
+        &mut self,
+        session_id: Uuid,
+        universe: UniverseId,
+        timestamp_us: u64,
+        bytes: &[u8],
+    ) -> Option<ParityFrame> {
+        let frames = self.pending.entry(universe).or_default();
+        frames.push((timestamp_us, bytes.to_vec()));
+        if frames.len() < self.group {
+            return None;
+        }
+        let mut parity = Vec::new();
+        for (_, frame) in frames.iter() {
+            xor_into(&mut parity, frame);
+        }
+        let frame = ParityFrame {
+            message_type: MessageType::AlpineParity,
+            session_id,
+            universe,
+            covers: frames.iter().map(|(ts, _)| *ts).collect(),
+            lengths: frames.iter().map(|(_, f)| f.len() as u32).collect(),
+            parity: PackedChannels(parity),
+        };
+        frames.clear();
+        Some(frame)
+    }
+}
+
+/// Receiver side: remembers recent frame encodings and rebuilds single losses.
+#[derive(Debug, Default)]
+pub struct FecDecoder {
+    recent: VecDeque<((UniverseId, u64), Vec<u8>)>,
+}
+
+impl FecDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the encoding of a received frame.
+    pub fn observe(&mut self, universe: UniverseId, timestamp_us: u64, bytes: &[u8]) {
+        if self.recent.len() == FEC_HISTORY {
+            self.recent.pop_front();
+        }
+        self.recent
+            .push_back(((universe, timestamp_us), bytes.to_vec()));
+    }
+
+    /// Rebuilds the encoding of the one frame `parity` covers that never arrived.
+    ///
+    /// Returns `None` when nothing or more than one frame of the group is missing, or
+    /// when the parity is inconsistent.
+    pub fn recover(&mut self, parity: &ParityFrame) -> Option<Vec<u8>> {
+        if parity.covers.len() != parity.lengths.len() {
+            return None;
+        }
+        let mut acc = parity.parity.0.clone();
+        let mut missing = None;
+        for (index, ts) in parity.covers.iter().enumerate() {
+            let key = (parity.universe, *ts);
+            match self.recent.iter().find(|(k, _)| *k == key) {
+                Some((_, bytes)) => xor_into(&mut acc, bytes),
+                None if missing.is_none() => missing = Some(index),
+                None => return None,
+            }
+        }
+        let index = missing?;
+        let len = parity.lengths[index] as usize;
+        if len > acc.len() {
+            return None;
+        }
+        acc.truncate(len);
+        self.observe(parity.universe, parity.covers[index], &acc);
+        Some(acc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parity_rebuilds_a_single_lost_frame() {
+        let session = Uuid::new_v4();
+        let frames: Vec<Vec<u8>> = vec![vec![1, 2, 3], vec![4, 5], vec![6, 7, 8, 9]];
+        let mut encoder = FecEncoder::new(3);
+        assert!(encoder.push(session, 1, 10, &frames[0]).is_none());
+        assert!(encoder.push(session, 2, 11, &[0xff]).is_none());
+        assert!(encoder.push(session, 1, 20, &frames[1]).is_none());
+        let parity = encoder.push(session, 1, 30, &frames[2]).unwrap();
+        assert_eq!(parity.covers, vec![10, 20, 30]);
+
+        let mut decoder = FecDecoder::new();
+        decoder.observe(1, 10, &frames[0]);
+        decoder.observe(1, 30, &frames[2]);
+        assert_eq!(decoder.recover(&parity), Some(frames[1].clone()));
+        // Nothing is missing any more.
+        assert_eq!(decoder.recover(&parity), None);
+
+        let mut lossy = FecDecoder::new();
+        lossy.observe(1, 30, &frames[2]);
+        assert_eq!(lossy.recover(&parity), None);
+    }
+}
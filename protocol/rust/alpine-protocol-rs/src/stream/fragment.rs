@@ -0,0 +1,220 @@
+//! Fragmentation of stream datagrams larger than the path MTU.
+//!
+//! A large universe in u16 or f32 plus metadata can outgrow a 1500-byte Ethernet MTU, and
+//! IP fragments are often dropped by switches and firewalls without a trace. Senders with
+//! a known datagram limit cut oversized frames and parity into `alpine_frame_fragment`
+//! pieces; receivers put them back together and treat the result like any other
+//! datagram. A datagram whose pieces do not all arrive within the reassembly timeout is
+//! dropped and counted.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::messages::{FrameFragment, MessageType, PackedChannels};
+
+/// Bytes a fragment's envelope adds around its data, rounded up.
+pub const FRAGMENT_OVERHEAD: usize = 160;
+
+/// Smallest datagram limit a [`Fragmenter`] accepts, so each piece carries useful data.
+pub const MIN_DATAGRAM: usize = 512;
+
+/// Most pieces one datagram may be cut into.
+pub const MAX_FRAGMENTS: u16 = 64;
+
+/// How long a receiver waits for the missing pieces of a datagram.
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Partially received datagrams a [`Reassembler`] holds before dropping the oldest.
+pub const MAX_PENDING_DATAGRAMS: usize = 32;
+
+/// Sender side: cuts datagrams that exceed the limit into fragments.
+#[derive(Debug)]
+pub struct Fragmenter {
+    max_datagram: usize,
+    next_id: u64,
+}
+
+impl Fragmenter {
+    /// Keeps every datagram within `max_datagram` bytes, raised to [`MIN_DATAGRAM`].
+    pub fn new(max_datagram: usize) -> Self {
+        Self {
+            max_datagram: max_datagram.max(MIN_DATAGRAM),
+            next_id: 0,
+        }
+    }
+
+    pub fn max_datagram(&self) -> usize {
+        self.max_datagram
+    }
+
+    /// Changes the limit; datagram ids keep counting so receivers never mix old and new
+    /// pieces.
+    pub fn set_max_datagram(&mut self, max_datagram: usize) {
+        self.max_datagram = max_datagram.max(MIN_DATAGRAM);
+    }
+
+    /// Fragments for `bytes`, or `None` when it fits in one datagram or would need more
+    /// than [`MAX_FRAGMENTS`] pieces.
+    pub fn split(&mut self, session_id: Uuid, bytes: &[u8]) -> Option<Vec<FrameFragment>> {
+        if bytes.len() <= self.max_datagram {
+            return None;
+        }
+        let piece = self.max_datagram - FRAGMENT_OVERHEAD;
+        let count = u16::try_from(bytes.len().div_ceil(piece))
+            .ok()
+            .filter(|&count| count <= MAX_FRAGMENTS)?;
+        let datagram_id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        Some(
+            bytes
+                .chunks(piece)
+                .zip(0..count)
+                .map(|(data, index)| FrameFragment {
+                    message_type: MessageType::AlpineFrameFragment,
+                    session_id,
+                    datagram_id,
+                    index,
+                    count,
+                    data: PackedChannels(data.to_vec()),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Partial {
+    started: Instant,
+    pieces: Vec<Option<Vec<u8>>>,
+    missing: usize,
+}
+
+/// Receiver side: joins the fragments of each datagram.
+#[derive(Debug)]
+pub struct Reassembler {
+    timeout: Duration,
+    pending: HashMap<u64, Partial>,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new(REASSEMBLY_TIMEOUT)
+    }
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Adds a fragment; returns the whole datagram once its last piece arrives.
+    ///
+    /// Fragments that contradict earlier pieces of the same datagram, repeat one, or
+    /// claim more than [`MAX_FRAGMENTS`] pieces are ignored.
+    pub fn push(&mut self, fragment: FrameFragment, now: Instant) -> Option<Vec<u8>> {
+        let count = usize::from(fragment.count);
+        let index = usize::from(fragment.index);
+        if fragment.count > MAX_FRAGMENTS || index >= count {
+            return None;
+        }
+        if !self.pending.contains_key(&fragment.datagram_id)
+            && self.pending.len() >= MAX_PENDING_DATAGRAMS
+        {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, partial)| partial.started)
+                .map(|(&id, _)| id)?;
+            self.pending.remove(&oldest);
+        }
+        let partial = self
+            .pending
+            .entry(fragment.datagram_id)
+            .or_insert_with(|| Partial {
+                started: now,
+                pieces: vec![None; count],
+                missing: count,
+            });
+        if partial.pieces.len() != count {
+            return None;
+        }
+        let slot = &mut partial.pieces[index];
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(fragment.data.0);
+        partial.missing -= 1;
+        if partial.missing > 0 {
+            return None;
+        }
+        let partial = self.pending.remove(&fragment.datagram_id)?;
+        Some(partial.pieces.into_iter().flatten().flatten().collect())
+    }
+
+    /// Drops datagrams still incomplete after the timeout and returns how many.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let before = self.pending.len();
+        let timeout = self.timeout;
+        self.pending
+            .retain(|_, partial| now.saturating_duration_since(partial.started) < timeout);
+        before - self.pending.len()
+    }
+
+    /// When the oldest incomplete datagram times out.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|partial| partial.started + self.timeout)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+
+    #[test]
+    fn splits_to_the_limit_and_reassembles_in_any_order() {
+        let session = Uuid::new_v4();
+        let bytes: Vec<u8> = (0..5_000u32).map(|i| i as u8).collect();
+        let mut fragmenter = Fragmenter::new(1_472);
+        assert!(fragmenter.split(session, &bytes[..1_000]).is_none());
+        let mut fragments = fragmenter.split(session, &bytes).unwrap();
+        assert_eq!(fragments.len(), 4);
+        for fragment in &fragments {
+            assert!(codec::to_vec(fragment).unwrap().len() <= 1_472);
+        }
+
+        let now = Instant::now();
+        let mut reassembler = Reassembler::default();
+        fragments.reverse();
+        let last = fragments.pop().unwrap();
+        for fragment in fragments.clone() {
+            assert_eq!(reassembler.push(fragment, now), None);
+        }
+        // A repeated piece neither completes nor corrupts the datagram.
+        assert_eq!(reassembler.push(fragments[0].clone(), now), None);
+        assert_eq!(reassembler.push(last, now), Some(bytes));
+        assert_eq!(reassembler.next_deadline(), None);
+    }
+
+    #[test]
+    fn incomplete_datagrams_time_out() {
+        let session = Uuid::new_v4();
+        let mut fragmenter = Fragmenter::new(MIN_DATAGRAM);
+        let fragments = fragmenter.split(session, &[7; 2_000]).unwrap();
+        let now = Instant::now();
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(fragments[0].clone(), now), None);
+        assert_eq!(reassembler.expire(now), 0);
+        assert_eq!(reassembler.next_deadline(), Some(now + REASSEMBLY_TIMEOUT));
+        assert_eq!(reassembler.expire(now + REASSEMBLY_TIMEOUT), 1);
+        assert_eq!(reassembler.push(fragments[1].clone(), now), None);
+    }
+}
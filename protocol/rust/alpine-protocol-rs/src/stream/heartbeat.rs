@@ -0,0 +1,175 @@
+//! Stream-level heartbeats.
+//!
+//! Control keepalives say the session is up; they say nothing about the streaming path.
+//! When suppression or a pause stops frames, an `alpine_stream_heartbeat` goes out every
+//! interval instead, so a receiver that hears neither frames nor heartbeats knows the
+//! sender is gone rather than static. Heartbeats are authenticated with the stream key
+//! on nonces with the top bit set, which echo probes never reach.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use super::{FrameTransport, StreamError};
+use crate::codec;
+use crate::crypto::{compute_stream_mac, verify_stream_mac, CryptoError, SessionKeys};
+use crate::messages::{MessageType, StreamHeartbeat};
+use crate::session::AlnpSession;
+
+/// Interval at which an idle stream sends heartbeats.
+pub const STREAM_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+const HEARTBEAT_NONCE_LANE: u64 = 1 << 63;
+
+impl StreamHeartbeat {
+    /// Builds an authenticated heartbeat.
+    pub fn new(keys: &SessionKeys, session_id: Uuid, seq: u64) -> Result<Self, CryptoError> {
+        let mut heartbeat = Self {
+            message_type: MessageType::AlpineStreamHeartbeat,
+            session_id,
+            seq,
+            mac: Vec::new(),
+        };
+        heartbeat.mac = compute_stream_mac(
+            keys,
+            HEARTBEAT_NONCE_LANE | seq,
+            &seq.to_be_bytes(),
+            session_id.as_bytes(),
+        )?;
+        Ok(heartbeat)
+    }
+
+    /// Whether the MAC matches the heartbeat under `keys`.
+    pub fn verify(&self, keys: &SessionKeys) -> bool {
+        verify_stream_mac(
+            keys,
+            HEARTBEAT_NONCE_LANE | self.seq,
+            &self.seq.to_be_bytes(),
+            self.session_id.as_bytes(),
+            &self.mac,
+        )
+    }
+}
+
+/// When the stream last sent anything, and the next heartbeat sequence number.
+#[derive(Debug)]
+pub(crate) struct Liveness {
+    last_sent: Mutex<Instant>,
+    next_seq: AtomicU64,
+}
+
+impl Liveness {
+    pub(crate) fn new() -> Self {
+        // Seeding from the wall clock keeps sequence numbers rising across stream
+        // restarts, so receivers need no reset.
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        Self {
+            last_sent: Mutex::new(Instant::now()),
+            next_seq: AtomicU64::new(seed & !HEARTBEAT_NONCE_LANE),
+        }
+    }
+
+    pub(crate) fn sent(&self, at: Instant) {
+        *self.last_sent.lock() = at;
+    }
+
+    fn idle_since(&self) -> Instant {
+        *self.last_sent.lock()
+    }
+}
+
+/// Sends one heartbeat and returns its sequence number.
+pub(crate) fn send<T: FrameTransport>(
+    session: &AlnpSession,
+    transport: &T,
+    liveness: &Liveness,
+) -> Result<u64, StreamError> {
+    let established = session
+        .ensure_streaming_ready()
+        .map_err(|_| StreamError::NotAuthenticated)?;
+    let keys = session.keys().ok_or(StreamError::MissingSession)?;
+    let seq = liveness.next_seq.fetch_add(1, Ordering::Relaxed);
+    let heartbeat = StreamHeartbeat::new(&keys, established.session_id, seq)
+        .map_err(|e| StreamError::Transport(e.to_string()))?;
+    let bytes = codec::to_vec(&heartbeat).map_err(|e| StreamError::Transport(e.to_string()))?;
+    transport
+        .send_frame(&bytes)
+        .map_err(StreamError::Transport)?;
+    liveness.sent(Instant::now());
+    Ok(seq)
+}
+
+/// Background task sending heartbeats while the stream is idle; aborted on drop.
+#[derive(Debug)]
+pub(crate) struct HeartbeatTask(JoinHandle<()>);
+
+impl HeartbeatTask {
+    /// Starts the task on the current Tokio runtime; `None` outside one.
+    pub(crate) fn spawn<T>(
+        session: AlnpSession,
+        transport: T,
+        liveness: Arc<Liveness>,
+        interval: Duration,
+    ) -> Option<Self>
+    where
+        T: FrameTransport + 'static,
+    {
+        let runtime = tokio::runtime::Handle::try_current().ok()?;
+        Some(Self(runtime.spawn(async move {
+            loop {
+                let due = liveness.idle_since() + interval;
+                if Instant::now() < due {
+                    tokio::time::sleep_until(due.into()).await;
+                    continue;
+                }
+                // Best-effort: a failed heartbeat is retried next interval.
+                if send(&session, &transport, &liveness).is_err() {
+                    liveness.sent(Instant::now());
+                }
+            }
+        })))
+    }
+}
+
+impl Drop for HeartbeatTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SuiteId;
+    use crate::messages::EchoFrame;
+
+    #[test]
+    fn heartbeats_are_authenticated_on_their_own_nonces() {
+        let keys = SessionKeys {
+            shared_secret: vec![3; 32],
+            control_key: [3; 32],
+            stream_key: [4; 32],
+            suite: SuiteId::default(),
+            channel_binding: [0; 32],
+        };
+        let session = Uuid::new_v4();
+        let heartbeat = StreamHeartbeat::new(&keys, session, 7).unwrap();
+        assert!(heartbeat.verify(&keys));
+        let mut tampered = heartbeat.clone();
+        tampered.seq = 8;
+        assert!(!tampered.verify(&keys));
+        let mut moved = heartbeat.clone();
+        moved.session_id = Uuid::new_v4();
+        assert!(!moved.verify(&keys));
+        let echo = EchoFrame::request(&keys, session, 7, 0).unwrap();
+        assert_ne!(echo.mac, heartbeat.mac);
+        assert!(Liveness::new().next_seq.load(Ordering::Relaxed) < HEARTBEAT_NONCE_LANE);
+    }
+}
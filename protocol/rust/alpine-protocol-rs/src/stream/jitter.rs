@@ -0,0 +1,174 @@
+//! Receive-side jitter buffer.
+//!
+//! Frames are held for the profile's buffer depth after arrival so that reordered frames
+//! and frames rebuilt from parity can still be applied in timestamp order. Once a frame
+//! is released, older frames of its universe are late and are refused. A frame carrying a
+//! `deadline_us` is never held past it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::messages::{FrameEnvelope, UniverseId};
+
+/// Holds frames for a fixed depth and releases them per universe in timestamp order.
+#[derive(Debug)]
+pub struct JitterBuffer {
+    depth: Duration,
+    /// Frames with their arrival and the time they are due.
+    pending: BTreeMap<(UniverseId, u64), (FrameEnvelope, Instant, Instant)>,
+    released: HashMap<UniverseId, u64>,
+}
+
+impl JitterBuffer {
+    pub fn new(depth: Duration) -> Self {
+        Self {
+            depth,
+            pending: BTreeMap::new(),
+            released: HashMap::new(),
+        }
+    }
+
+    pub fn depth(&self) -> Duration {
+        self.depth
+    }
+
+    /// Holds `frame` from `arrived`; returns `false` for duplicates and for frames older
+    /// than one already released.
+    pub fn push(&mut self, frame: FrameEnvelope, arrived: Instant) -> bool {
+        self.hold(frame, arrived, arrived + self.depth)
+    }
+
+    /// Like [`Self::push`], but releases the frame by its `deadline_us` if that comes
+    /// before the buffer depth; `arrival_us` is the arrival on the same clock.
+    pub fn push_by_deadline(
+        &mut self,
+        frame: FrameEnvelope,
+        arrived: Instant,
+        arrival_us: u64,
+    ) -> bool {
+        let hold = frame
+            .deadline_us
+            .map(|deadline_us| Duration::from_micros(deadline_us.saturating_sub(arrival_us)))
+            .map_or(self.depth, |remaining| remaining.min(self.depth));
+        self.hold(frame, arrived, arrived + hold)
+    }
+
+    fn hold(&mut self, frame: FrameEnvelope, arrived: Instant, due: Instant) -> bool {
+        let key = (frame.universe, frame.timestamp_us);
+        if self
+            .released
+            .get(&frame.universe)
+            .is_some_and(|&released| frame.timestamp_us <= released)
+            || self.pending.contains_key(&key)
+        {
+            return false;
+        }
+        self.pending.insert(key, (frame, arrived, due));
+        true
+    }
+
+    /// When the next held frame is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|(_, _, due)| *due).min()
+    }
+
+    /// Frames whose hold has expired at `now`, together with any earlier frames of the
+    /// same universe, in timestamp order per universe and with their arrival times.
+    pub fn pop_due(&mut self, now: Instant) -> Vec<(FrameEnvelope, Instant)> {
+        let mut release: HashMap<UniverseId, u64> = HashMap::new();
+        for ((universe, ts), (_, _, due)) in &self.pending {
+            if *due <= now {
+                release.insert(*universe, *ts);
+            }
+        }
+        let mut out = Vec::new();
+        for (universe, up_to) in release {
+            let keys: Vec<_> = self
+                .pending
+                .range((universe, 0)..=(universe, up_to))
+                .map(|(key, _)| *key)
+                .collect();
+            for key in keys {
+                out.extend(
+                    self.pending
+                        .remove(&key)
+                        .map(|(frame, arrived, _)| (frame, arrived)),
+                );
+            }
+            self.released.insert(universe, up_to);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ChannelFormat, MessageType};
+    use uuid::Uuid;
+
+    fn frame(universe: UniverseId, timestamp_us: u64) -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::nil(),
+            universe,
+            timestamp_us,
+            priority: 100,
+            channel_format: ChannelFormat::U8,
+            channels: vec![1].into(),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        }
+    }
+
+    #[test]
+    fn reorders_within_depth_and_refuses_late_frames() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut buffer = JitterBuffer::new(ms(20));
+        assert!(buffer.push(frame(1, 200), start));
+        // An older frame arrives late but inside the buffer.
+        assert!(buffer.push(frame(1, 100), start + ms(5)));
+        assert!(!buffer.push(frame(1, 100), start + ms(6)));
+        assert_eq!(buffer.next_due(), Some(start + ms(20)));
+        assert!(buffer.pop_due(start + ms(10)).is_empty());
+        let released: Vec<u64> = buffer
+            .pop_due(start + ms(20))
+            .iter()
+            .map(|(f, _)| f.timestamp_us)
+            .collect();
+        assert_eq!(released, vec![100, 200]);
+        assert!(!buffer.push(frame(1, 150), start + ms(21)));
+        assert!(buffer.push(frame(2, 150), start + ms(21)));
+        assert_eq!(buffer.next_due(), Some(start + ms(41)));
+    }
+
+    #[test]
+    fn deadlines_cut_the_hold_short() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut buffer = JitterBuffer::new(ms(40));
+        let mut due_soon = frame(1, 100);
+        due_soon.deadline_us = Some(10_000);
+        assert!(buffer.push_by_deadline(due_soon, start, 4_000));
+        assert_eq!(buffer.next_due(), Some(start + ms(6)));
+        // Already late: released at once rather than held.
+        let mut late = frame(2, 100);
+        late.deadline_us = Some(3_000);
+        assert!(buffer.push_by_deadline(late, start, 4_000));
+        assert_eq!(buffer.next_due(), Some(start));
+        // No deadline: the full depth.
+        assert!(buffer.push_by_deadline(frame(3, 100), start, 4_000));
+        let released = buffer.pop_due(start + ms(6));
+        assert_eq!(released.len(), 2);
+        assert_eq!(buffer.next_due(), Some(start + ms(40)));
+    }
+}
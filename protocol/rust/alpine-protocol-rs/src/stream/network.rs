@@ -18,6 +18,7 @@ pub struct NetworkMetrics {
 }
 
 /// Determines the network conditions for an ALPINE streaming session.
+#[derive(Debug, Clone)]
 pub struct NetworkConditions {
     last_sequence: Option<u64>,
     total_expected: u64,
@@ -31,6 +32,12 @@ pub struct NetworkConditions {
     max_loss_gap: u64,
 }
 
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NetworkConditions {
     /// Creates a fresh tracker.
     pub fn new() -> Self {
@@ -50,9 +57,9 @@ impl NetworkConditions {
 
     /// Records an observed frame arrival.
     ///
-    /// The stream encodes `sequence`, `arrival_us`, and the caller-supplied
-    /// `deadline_us` so we can independently reason about lateness, loss, and
-    /// jitter. All calculations are deterministic and rely solely on these
+    /// The stream encodes `sequence`, `arrival_us`, and the frame's `deadline_us`
+    /// (see [`crate::messages::FrameEnvelope::deadline_us`]) so we can independently
+    /// reason about lateness, loss, and jitter. All calculations are deterministic and rely solely on these
     /// inputs.
     pub fn record_frame(&mut self, sequence: u64, arrival_us: u64, deadline_us: u64) {
         if let Some(last_seq) = self.last_sequence {
@@ -80,11 +87,7 @@ impl NetworkConditions {
         if let Some(last) = self.last_arrival {
             let interval = arrival_us.saturating_sub(last);
             if let Some(prev_interval) = self.last_interval {
-                let jitter = if interval > prev_interval {
-                    interval - prev_interval
-                } else {
-                    prev_interval - interval
-                };
+                let jitter = interval.abs_diff(prev_interval);
                 self.total_jitter_ns = self.total_jitter_ns.saturating_add(jitter as u128);
                 self.jitter_samples = self.jitter_samples.saturating_add(1);
             }
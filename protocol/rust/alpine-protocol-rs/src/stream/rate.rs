@@ -0,0 +1,140 @@
+//! Achieved frame rate per universe, and alarms when it falls short of the target.
+//!
+//! A universe that silently drops to a fraction of its frame rate looks fine on every
+//! other counter: its frames still arrive, just too few of them for smooth chases. The
+//! [`RateMeter`] measures what each universe actually delivers over a rolling window;
+//! an [`UnderrunAlarm`] turns a sustained shortfall into one event when it starts and
+//! one when it clears, rather than a report per frame.
+
+use std::time::{Duration, Instant};
+
+/// Window over which a universe's achieved frame rate is measured.
+pub const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Raises [`crate::stream::StreamEvent::Underrun`] once a universe has run below `below`
+/// of the target frame rate for `after`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnderrunAlarm {
+    /// Fraction of the target rate, e.g. `0.8`, below which a universe underruns.
+    pub below: f64,
+    pub after: Duration,
+}
+
+impl Default for UnderrunAlarm {
+    fn default() -> Self {
+        Self {
+            below: 0.8,
+            after: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Change in a universe's underrun alarm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AlarmChange {
+    Raised(f64),
+    Cleared(f64),
+}
+
+/// Frames a universe delivered, counted per [`RATE_WINDOW`].
+#[derive(Debug, Default)]
+pub(crate) struct RateMeter {
+    window_start: Option<Instant>,
+    window_frames: u64,
+    fps: Option<f64>,
+    below_since: Option<Instant>,
+    alarmed: bool,
+}
+
+impl RateMeter {
+    /// Counts a frame the universe delivered at `now`.
+    pub(crate) fn record(&mut self, now: Instant) {
+        self.roll(now);
+        self.window_frames += 1;
+    }
+
+    /// Frames per second over the last full window; `None` until one has passed.
+    pub(crate) fn fps(&self) -> Option<f64> {
+        self.fps
+    }
+
+    /// Whether the alarm is currently raised.
+    pub(crate) fn alarmed(&self) -> bool {
+        self.alarmed
+    }
+
+    /// Compares the achieved rate with `target` frames per second and reports when the
+    /// alarm is raised or cleared.
+    pub(crate) fn check(
+        &mut self,
+        alarm: &UnderrunAlarm,
+        target: f64,
+        now: Instant,
+    ) -> Option<AlarmChange> {
+        self.roll(now);
+        let fps = self.fps?;
+        if fps < target * alarm.below {
+            let since = *self.below_since.get_or_insert(now);
+            if !self.alarmed && now.saturating_duration_since(since) >= alarm.after {
+                self.alarmed = true;
+                return Some(AlarmChange::Raised(fps));
+            }
+        } else {
+            self.below_since = None;
+            if self.alarmed {
+                self.alarmed = false;
+                return Some(AlarmChange::Cleared(fps));
+            }
+        }
+        None
+    }
+
+    /// Closes the window once it has run for [`RATE_WINDOW`], so silence counts as zero.
+    fn roll(&mut self, now: Instant) {
+        let start = *self.window_start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed >= RATE_WINDOW {
+            self.fps = Some(self.window_frames as f64 / elapsed.as_secs_f64());
+            self.window_start = Some(now);
+            self.window_frames = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alarm_raises_after_a_sustained_shortfall_and_clears_once() {
+        let alarm = UnderrunAlarm {
+            below: 0.5,
+            after: Duration::from_secs(2),
+        };
+        let mut meter = RateMeter::default();
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        // 40 fps for a second, against a 40 fps target.
+        for frame in 0..40 {
+            meter.record(at(frame * 25));
+        }
+        assert_eq!(meter.check(&alarm, 40.0, at(1_000)), None);
+        assert_eq!(meter.fps(), Some(40.0));
+        // Silence: the rate drops to zero but the alarm waits out `after`.
+        assert_eq!(meter.check(&alarm, 40.0, at(2_000)), None);
+        assert_eq!(meter.check(&alarm, 40.0, at(3_000)), None);
+        assert_eq!(
+            meter.check(&alarm, 40.0, at(4_000)),
+            Some(AlarmChange::Raised(0.0))
+        );
+        assert!(meter.alarmed());
+        assert_eq!(meter.check(&alarm, 40.0, at(4_500)), None);
+        for frame in 0..40 {
+            meter.record(at(4_000 + frame * 25));
+        }
+        assert_eq!(
+            meter.check(&alarm, 40.0, at(5_000)),
+            Some(AlarmChange::Cleared(40.0))
+        );
+    }
+}
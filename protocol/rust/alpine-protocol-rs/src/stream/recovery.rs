@@ -50,6 +50,12 @@ pub struct RecoveryMonitor {
     state: RecoveryState,
 }
 
+impl Default for RecoveryMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RecoveryMonitor {
     /// Creates a fresh monitor in the idle state.
     pub fn new() -> Self {
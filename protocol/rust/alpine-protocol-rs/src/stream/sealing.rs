@@ -0,0 +1,227 @@
+//! Encrypted stream datagrams.
+//!
+//! Frames are authenticated by the session but travel in the clear unless the stream is
+//! sealed. A sealed stream wraps every frame and parity datagram in an
+//! `alpine_sealed_frame` encrypted under the key of the current epoch, which the sender
+//! ratchets at each keyframe interval rollover; see [`crate::crypto::ratchet`]. The
+//! epoch travels in the clear so the receiver can step its own ratchet to match.
+//!
+//! Nonces come from the session's sealed-frame lane (see [`crate::crypto::nonce`]) and
+//! number the datagrams of each epoch from zero. The sender keeps one [`Sealer`] for the
+//! whole session, so a restarted stream carries on with its epochs instead of repeating
+//! nonces under keys already used.
+
+use uuid::Uuid;
+
+use crate::crypto::nonce::{self, NonceLane, NonceSequence};
+use crate::crypto::ratchet::{StreamRatchet, EPOCH_NONCE_LIMIT};
+use crate::crypto::{self, CryptoError, SessionKeys};
+use crate::messages::{MessageType, PackedChannels, SealedFrame};
+
+impl SealedFrame {
+    /// Encrypts the encoded datagram `bytes` under the ratchet's current epoch.
+    pub fn seal(
+        ratchet: &StreamRatchet,
+        session_id: Uuid,
+        seq: u64,
+        bytes: &[u8],
+    ) -> Result<Self, CryptoError> {
+        let key = ratchet.current();
+        let ciphertext = crypto::seal(
+            ratchet.suite(),
+            &key.key,
+            &frame_nonce(session_id, seq),
+            &aad(session_id, key.epoch, ratchet.channel_binding()),
+            bytes,
+        )?;
+        Ok(Self {
+            message_type: MessageType::AlpineSealedFrame,
+            session_id,
+            epoch: key.epoch,
+            seq,
+            ciphertext: PackedChannels(ciphertext),
+        })
+    }
+
+    /// Decrypts the datagram, stepping `ratchet` forward to its epoch if the sender has
+    /// moved on. The ratchet only moves once the datagram has authenticated.
+    pub fn open(&self, ratchet: &mut StreamRatchet) -> Result<Vec<u8>, CryptoError> {
+        let key = ratchet
+            .key_for(self.epoch)
+            .ok_or_else(|| CryptoError::Aead(format!("no key for epoch {}", self.epoch)))?;
+        let bytes = crypto::open(
+            ratchet.suite(),
+            &key.key,
+            &frame_nonce(self.session_id, self.seq),
+            &aad(self.session_id, self.epoch, ratchet.channel_binding()),
+            &self.ciphertext.0,
+        )?;
+        ratchet.catch_up(self.epoch)?;
+        Ok(bytes)
+    }
+}
+
+/// Sender side of a session's sealed stream.
+#[derive(Debug)]
+pub(crate) struct Sealer {
+    session_id: Uuid,
+    ratchet: StreamRatchet,
+    nonces: NonceSequence,
+    epoch_limit: u64,
+}
+
+impl Sealer {
+    pub(crate) fn new(keys: &SessionKeys, session_id: Uuid) -> Result<Self, CryptoError> {
+        Self::with_epoch_limit(keys, session_id, EPOCH_NONCE_LIMIT)
+    }
+
+    fn with_epoch_limit(
+        keys: &SessionKeys,
+        session_id: Uuid,
+        epoch_limit: u64,
+    ) -> Result<Self, CryptoError> {
+        Ok(Self {
+            session_id,
+            ratchet: StreamRatchet::sender(keys)?,
+            nonces: epoch_nonces(session_id, epoch_limit),
+            epoch_limit,
+        })
+    }
+
+    pub(crate) fn epoch(&self) -> u32 {
+        self.ratchet.epoch()
+    }
+
+    /// Seals `bytes`, first starting a new epoch when `rekey` is set or the current one
+    /// has no nonces left. Fails only once the last epoch is used up.
+    pub(crate) fn seal(&mut self, bytes: &[u8], rekey: bool) -> Result<SealedFrame, CryptoError> {
+        if rekey {
+            self.next_epoch()?;
+        }
+        let seq = match self.nonces.next_nonce() {
+            Ok((seq, _)) => seq,
+            Err(CryptoError::NoncesExhausted) => {
+                self.next_epoch()?;
+                self.nonces.next_nonce()?.0
+            }
+            Err(err) => return Err(err),
+        };
+        SealedFrame::seal(&self.ratchet, self.session_id, seq, bytes)
+    }
+
+    fn next_epoch(&mut self) -> Result<(), CryptoError> {
+        self.ratchet.advance()?;
+        self.nonces = epoch_nonces(self.session_id, self.epoch_limit);
+        Ok(())
+    }
+}
+
+fn epoch_nonces(session_id: Uuid, limit: u64) -> NonceSequence {
+    NonceSequence::new(session_id.as_bytes(), NonceLane::SealedFrame).with_limit(limit)
+}
+
+fn frame_nonce(session_id: Uuid, seq: u64) -> [u8; 12] {
+    nonce::nonce(
+        nonce::nonce_salt(session_id.as_bytes(), NonceLane::SealedFrame),
+        seq,
+    )
+}
+
+/// Binds the ciphertext to its session, handshake, and epoch.
+fn aad(session_id: Uuid, epoch: u32, channel_binding: &[u8; 32]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(52);
+    aad.extend_from_slice(session_id.as_bytes());
+    aad.extend_from_slice(&epoch.to_be_bytes());
+    aad.extend_from_slice(channel_binding);
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SuiteId;
+
+    fn keys() -> SessionKeys {
+        SessionKeys {
+            shared_secret: Vec::new(),
+            control_key: [3; 32],
+            stream_key: [4; 32],
+            suite: SuiteId::Aes256Gcm,
+            channel_binding: [0; 32],
+        }
+    }
+
+    #[test]
+    fn sealed_frames_open_across_epochs() {
+        let session_id = Uuid::new_v4();
+        let mut sealer = Sealer::new(&keys(), session_id).unwrap();
+        let mut receiver = StreamRatchet::receiver(&keys()).unwrap();
+
+        let first = sealer.seal(b"frame one", false).unwrap();
+        let second = sealer.seal(b"keyframe", true).unwrap();
+        assert_eq!((first.epoch, second.epoch), (0, 1));
+        // Every epoch numbers its nonces from zero.
+        assert_eq!((first.seq, second.seq), (0, 0));
+        assert_eq!(sealer.epoch(), 1);
+
+        assert_eq!(second.open(&mut receiver).unwrap(), b"keyframe");
+        // A frame of the previous epoch arriving late still opens.
+        assert_eq!(first.open(&mut receiver).unwrap(), b"frame one");
+
+        // Keys from another handshake's transcript cannot open it, even if equal.
+        let mut spliced_keys = keys();
+        spliced_keys.channel_binding = [9; 32];
+        let mut spliced_receiver = StreamRatchet::receiver(&spliced_keys).unwrap();
+        assert!(second.open(&mut spliced_receiver).is_err());
+
+        let mut moved = second.clone();
+        moved.epoch = 0;
+        assert!(moved.open(&mut receiver).is_err());
+        let mut spliced = second;
+        spliced.session_id = Uuid::new_v4();
+        assert!(spliced.open(&mut receiver).is_err());
+    }
+
+    #[test]
+    fn forged_future_epochs_leave_the_receiver_alone() {
+        let session_id = Uuid::new_v4();
+        let mut sealer = Sealer::new(&keys(), session_id).unwrap();
+        let mut receiver = StreamRatchet::receiver(&keys()).unwrap();
+        let real = sealer.seal(b"frame", false).unwrap();
+
+        // Epoch and session travel in the clear, so anyone can name a later epoch.
+        let mut forged = real.clone();
+        forged.epoch = crate::crypto::ratchet::MAX_EPOCH_SKIP;
+        assert!(forged.open(&mut receiver).is_err());
+        assert_eq!(receiver.epoch(), 0);
+        assert_eq!(real.open(&mut receiver).unwrap(), b"frame");
+
+        let next = sealer.seal(b"keyframe", true).unwrap();
+        assert!(forged.open(&mut receiver).is_err());
+        assert_eq!(next.open(&mut receiver).unwrap(), b"keyframe");
+        assert_eq!(receiver.epoch(), 1);
+    }
+
+    #[test]
+    fn exhausted_epochs_roll_over_without_reusing_nonces() {
+        let session_id = Uuid::new_v4();
+        let mut sealer = Sealer::with_epoch_limit(&keys(), session_id, 3).unwrap();
+        let mut receiver = StreamRatchet::receiver(&keys()).unwrap();
+
+        let frames: Vec<_> = (0..7u8)
+            .map(|i| sealer.seal(&[i], false).unwrap())
+            .collect();
+        assert_eq!(
+            frames.iter().map(|f| (f.epoch, f.seq)).collect::<Vec<_>>(),
+            [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (2, 0)]
+        );
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.open(&mut receiver).unwrap(), [i as u8]);
+        }
+
+        // A keyframe mid-epoch starts the next one with fresh nonces.
+        let keyframe = sealer.seal(b"keyframe", true).unwrap();
+        assert_eq!((keyframe.epoch, keyframe.seq), (3, 0));
+        assert_eq!(keyframe.open(&mut receiver).unwrap(), b"keyframe");
+    }
+}
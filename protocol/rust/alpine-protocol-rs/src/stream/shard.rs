@@ -0,0 +1,411 @@
+//! Thread-per-core sending for controllers with hundreds of universes.
+//!
+//! One runtime thread encoding and sending every universe of a large rig becomes the
+//! bottleneck long before the network does. A [`ShardPool`] runs a fixed number of
+//! sender threads, each pinned to its own core and sending from its own UDP socket, so
+//! shards never contend for a socket or a stream lock. Streams are placed on a shard by
+//! key, usually the device id: a key keeps its shard for the life of the pool, so a
+//! session re-opened after a network blip sends from the same thread and socket as before.
+//!
+//! Each shard runs the jobs placed on it at their own interval, skipping ticks it missed
+//! rather than bursting to catch up, as the frame schedulers do.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use super::StreamError;
+use crate::transport::{TransportConfig, UdpTransport};
+
+/// How long an idle shard waits for work before checking again.
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// Sends one tick's frames and returns how many went out.
+///
+/// [`StreamError::StreamingDisabled`] skips the tick; any other error removes the job.
+pub type ShardJob = Box<dyn FnMut() -> Result<u64, StreamError> + Send>;
+
+/// Shape of a [`ShardPool`].
+#[derive(Debug, Clone)]
+pub struct ShardConfig {
+    /// Sender threads; at least one.
+    pub shards: usize,
+    /// Pins shard `n` to core `n` modulo the cores available. Only Linux honours it.
+    pub pin_cores: bool,
+    /// Local address each shard socket binds, usually with port 0.
+    pub bind: SocketAddr,
+    /// Socket options of every shard socket, e.g. a DSCP class for streaming.
+    pub transport: TransportConfig,
+}
+
+impl Default for ShardConfig {
+    /// One shard per available core, pinned, on ephemeral IPv4 ports.
+    fn default() -> Self {
+        Self {
+            shards: thread::available_parallelism().map_or(1, usize::from),
+            pin_cores: true,
+            bind: SocketAddr::from(([0, 0, 0, 0], 0)),
+            transport: TransportConfig::default(),
+        }
+    }
+}
+
+/// Counters of one shard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ShardStats {
+    /// Core the shard is pinned to, if pinning took effect.
+    pub core: Option<usize>,
+    /// Keys placed on the shard.
+    pub keys: usize,
+    /// Jobs running on the shard.
+    pub jobs: usize,
+    pub frames_sent: u64,
+    /// Ticks that started after the next one was already due.
+    pub late_ticks: u64,
+    /// Jobs removed after an error.
+    pub failed_jobs: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    core: Mutex<Option<usize>>,
+    jobs: AtomicUsize,
+    frames_sent: AtomicU64,
+    late_ticks: AtomicU64,
+    failed_jobs: AtomicU64,
+}
+
+enum Command {
+    Spawn {
+        key: String,
+        interval: Duration,
+        job: ShardJob,
+    },
+    Cancel(String),
+}
+
+struct Shard {
+    socket: UdpTransport,
+    commands: Option<mpsc::Sender<Command>>,
+    counters: Arc<Counters>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Sender threads, each pinned to a core and sending from its own UDP socket.
+///
+/// Streams are placed on a shard by key, usually the device id, and keep it for the life
+/// of the pool. Each shard runs its jobs at their own interval, skipping missed ticks
+/// rather than bursting to catch up.
+///
+/// Dropping the pool stops its threads once their current tick is done.
+pub struct ShardPool {
+    shards: Vec<Shard>,
+    /// Shard of every key placed so far.
+    placement: Mutex<HashMap<String, usize>>,
+}
+
+impl ShardPool {
+    /// Binds a socket per shard and starts the shard threads.
+    ///
+    /// Must be called within a Tokio runtime, which the shard sockets register with.
+    pub fn start(config: ShardConfig) -> io::Result<Self> {
+        let cores = thread::available_parallelism().map_or(1, usize::from);
+        let mut shards = Vec::with_capacity(config.shards.max(1));
+        for index in 0..config.shards.max(1) {
+            let socket = config.transport.bind_udp(config.bind)?;
+            socket.set_nonblocking(true)?;
+            let socket =
+                UdpTransport::from_socket(tokio::net::UdpSocket::from_std(socket)?, config.bind);
+            let (commands, inbox) = mpsc::channel();
+            let counters = Arc::new(Counters::default());
+            let core = config.pin_cores.then_some(index % cores);
+            let thread = thread::Builder::new()
+                .name(format!("alpine-shard-{index}"))
+                .spawn({
+                    let counters = counters.clone();
+                    move || run_shard(inbox, counters, core)
+                })?;
+            shards.push(Shard {
+                socket,
+                commands: Some(commands),
+                counters,
+                thread: Some(thread),
+            });
+        }
+        Ok(Self {
+            shards,
+            placement: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Number of shards.
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Shard of `key`: the one it was placed on before, or else the shard with the fewest
+    /// keys.
+    pub fn shard_for(&self, key: &str) -> usize {
+        let mut placement = self.placement.lock();
+        if let Some(&shard) = placement.get(key) {
+            return shard;
+        }
+        let mut load = vec![0usize; self.shards.len()];
+        for &shard in placement.values() {
+            load[shard] += 1;
+        }
+        let shard = (0..load.len())
+            .min_by_key(|&shard| load[shard])
+            .unwrap_or(0);
+        placement.insert(key.to_string(), shard);
+        shard
+    }
+
+    /// Transport sending from the socket of `key`'s shard toward `peer`, for the stream
+    /// of the session with that peer. It keeps its own [`UdpTransport::stats`].
+    pub fn transport(&self, key: &str, peer: SocketAddr) -> UdpTransport {
+        self.shards[self.shard_for(key)].socket.to_peer(peer)
+    }
+
+    /// Local address of `shard`'s socket.
+    pub fn local_addr(&self, shard: usize) -> io::Result<SocketAddr> {
+        self.shards[shard].socket.local_addr()
+    }
+
+    /// Runs `job` on `key`'s shard every `interval`, first at once. A key may run several
+    /// jobs.
+    pub fn spawn(
+        &self,
+        key: &str,
+        interval: Duration,
+        job: impl FnMut() -> Result<u64, StreamError> + Send + 'static,
+    ) {
+        self.send(
+            self.shard_for(key),
+            Command::Spawn {
+                key: key.to_string(),
+                interval,
+                job: Box::new(job),
+            },
+        );
+    }
+
+    /// Stops every job of `key`. The key keeps its shard.
+    pub fn cancel(&self, key: &str) {
+        let shard = self.placement.lock().get(key).copied();
+        if let Some(shard) = shard {
+            self.send(shard, Command::Cancel(key.to_string()));
+        }
+    }
+
+    /// Counters of every shard, by index.
+    pub fn stats(&self) -> Vec<ShardStats> {
+        let mut keys = vec![0usize; self.shards.len()];
+        for &shard in self.placement.lock().values() {
+            keys[shard] += 1;
+        }
+        self.shards
+            .iter()
+            .zip(keys)
+            .map(|(shard, keys)| {
+                let counters = &shard.counters;
+                ShardStats {
+                    core: *counters.core.lock(),
+                    keys,
+                    jobs: counters.jobs.load(Ordering::Relaxed),
+                    frames_sent: counters.frames_sent.load(Ordering::Relaxed),
+                    late_ticks: counters.late_ticks.load(Ordering::Relaxed),
+                    failed_jobs: counters.failed_jobs.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    fn send(&self, shard: usize, command: Command) {
+        if let Some(commands) = &self.shards[shard].commands {
+            // The shard thread only exits once the pool drops its sender.
+            let _ = commands.send(command);
+        }
+    }
+}
+
+impl Drop for ShardPool {
+    fn drop(&mut self) {
+        for shard in &mut self.shards {
+            shard.commands.take();
+        }
+        for shard in &mut self.shards {
+            if let Some(thread) = shard.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for ShardPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShardPool")
+            .field("shards", &self.shards.len())
+            .finish_non_exhaustive()
+    }
+}
+
+struct Scheduled {
+    key: String,
+    interval: Duration,
+    due: Instant,
+    job: ShardJob,
+}
+
+fn run_shard(inbox: mpsc::Receiver<Command>, counters: Arc<Counters>, core: Option<usize>) {
+    if let Some(core) = core {
+        if pin_to_core(core) {
+            *counters.core.lock() = Some(core);
+        }
+    }
+    let mut jobs: Vec<Scheduled> = Vec::new();
+    loop {
+        let wait = jobs
+            .iter()
+            .map(|scheduled| scheduled.due)
+            .min()
+            .map_or(IDLE_WAIT, |due| {
+                due.saturating_duration_since(Instant::now())
+            });
+        match inbox.recv_timeout(wait) {
+            Ok(Command::Spawn { key, interval, job }) => jobs.push(Scheduled {
+                key,
+                interval,
+                due: Instant::now(),
+                job,
+            }),
+            Ok(Command::Cancel(key)) => jobs.retain(|scheduled| scheduled.key != key),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        jobs.retain_mut(|scheduled| {
+            if scheduled.due > now {
+                return true;
+            }
+            scheduled.due += scheduled.interval;
+            if scheduled.due <= now && !scheduled.interval.is_zero() {
+                counters.late_ticks.fetch_add(1, Ordering::Relaxed);
+                scheduled.due = now + scheduled.interval;
+            }
+            match (scheduled.job)() {
+                Ok(sent) => {
+                    counters.frames_sent.fetch_add(sent, Ordering::Relaxed);
+                    true
+                }
+                Err(StreamError::StreamingDisabled) => true,
+                Err(_) => {
+                    counters.failed_jobs.fetch_add(1, Ordering::Relaxed);
+                    false
+                }
+            }
+        });
+        counters.jobs.store(jobs.len(), Ordering::Relaxed);
+    }
+}
+
+/// Pins the calling thread to `core`; whether it took effect.
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) -> bool {
+    // SAFETY: `set` is a plain bitmask owned by this frame, and pid 0 is this thread.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_core: usize) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(shards: usize) -> ShardPool {
+        let config = ShardConfig {
+            shards,
+            pin_cores: false,
+            bind: SocketAddr::from(([127, 0, 0, 1], 0)),
+            transport: TransportConfig::default(),
+        };
+        ShardPool::start(config).unwrap()
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[tokio::test]
+    async fn keys_spread_across_shards_and_keep_their_shard() {
+        let pool = pool(3);
+        let shards: Vec<usize> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|key| pool.shard_for(key))
+            .collect();
+        assert_eq!(shards, vec![0, 1, 2, 0]);
+        assert_eq!(pool.shard_for("b"), 1);
+        assert_eq!(
+            pool.stats().iter().map(|s| s.keys).collect::<Vec<_>>(),
+            vec![2, 1, 1]
+        );
+
+        let peer = SocketAddr::from(([127, 0, 0, 1], 9));
+        let transport = pool.transport("d", peer);
+        assert_eq!(transport.peer(), peer);
+        assert_eq!(transport.local_addr().unwrap(), pool.local_addr(0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn jobs_run_on_their_shard_until_cancelled_or_failed() {
+        let pool = pool(2);
+        let runs = Arc::new(AtomicU64::new(0));
+        let counted = runs.clone();
+        pool.spawn("a", Duration::from_millis(1), move || {
+            counted.fetch_add(1, Ordering::Relaxed);
+            Ok(2)
+        });
+        let mut failing = 0;
+        pool.spawn("b", Duration::from_millis(1), move || {
+            failing += 1;
+            if failing < 3 {
+                Err(StreamError::StreamingDisabled)
+            } else {
+                Err(StreamError::NotAuthenticated)
+            }
+        });
+        wait_for(|| runs.load(Ordering::Relaxed) >= 5 && pool.stats()[1].failed_jobs == 1);
+        let stats = pool.stats();
+        assert!(stats[0].frames_sent >= 10);
+        assert_eq!(stats[1].frames_sent, 0);
+        assert_eq!(stats[1].jobs, 0);
+
+        pool.cancel("a");
+        wait_for(|| pool.stats()[0].jobs == 0);
+        let after = runs.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(runs.load(Ordering::Relaxed), after);
+    }
+}
@@ -0,0 +1,499 @@
+//! Time-based smoothing of channel levels between frames.
+//!
+//! Under [`JitterStrategy::Lerp`] each channel ramps from where it stood when a frame
+//! arrived to the frame's level over the configured ramp duration, independent of the
+//! frame rate. Slew limits cap how fast a channel may move under any strategy, so a
+//! moving head's pan can glide while its intensity still snaps. Channel groups carried
+//! in a frame can override the strategy, ramp, and slew for their channels. A hold
+//! timeout fades a universe that stopped receiving frames to a safe level instead of
+//! freezing it, so a controller crashing mid-chase does not leave a strobe running.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages::{ChannelBuffer, Map, UniverseId};
+use crate::session::JitterStrategy;
+
+/// Ramp used by `Lerp` unless configured otherwise: about two DMX refresh periods.
+pub const DEFAULT_LERP_RAMP: Duration = Duration::from_millis(40);
+
+/// Smoothing applied to the channels of one group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmoothingPolicy {
+    pub strategy: JitterStrategy,
+    /// Time a `Lerp` channel takes to reach a new level.
+    #[serde(with = "duration_ms")]
+    pub ramp: Duration,
+    /// Largest level change per second; `None` leaves the channel unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slew_per_sec: Option<u32>,
+}
+
+/// Fade applied once a universe has received no frame for `after`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldTimeout {
+    #[serde(with = "duration_ms")]
+    pub after: Duration,
+    /// Time the fade to `level` takes; zero cuts at once.
+    #[serde(with = "duration_ms")]
+    pub fade: Duration,
+    /// Level every channel fades to.
+    #[serde(default)]
+    pub level: u16,
+}
+
+/// Smoothing settings of a session, changeable while it streams.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmoothingConfig {
+    /// Ramp for channels outside any overridden group; their strategy is the session's
+    /// jitter strategy.
+    #[serde(with = "duration_ms")]
+    pub ramp: Duration,
+    /// Slew limit for channels outside any overridden group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slew_per_sec: Option<u32>,
+    /// Overrides keyed by the group names frames carry in `groups`.
+    #[serde(default)]
+    pub groups: BTreeMap<String, SmoothingPolicy>,
+    /// Slew limits of individual channels, by index; these win over any group's.
+    #[serde(default)]
+    pub channel_slew: BTreeMap<u16, u32>,
+    /// Fade-out of universes whose frames stop; `None` holds their last look forever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold: Option<HoldTimeout>,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        Self {
+            ramp: DEFAULT_LERP_RAMP,
+            slew_per_sec: None,
+            groups: BTreeMap::new(),
+            channel_slew: BTreeMap::new(),
+            hold: None,
+        }
+    }
+}
+
+impl SmoothingConfig {
+    /// Smooths the channels of group `name` with `policy` instead of the defaults.
+    pub fn with_group(mut self, name: impl Into<String>, policy: SmoothingPolicy) -> Self {
+        self.groups.insert(name.into(), policy);
+        self
+    }
+
+    /// Limits `channel` to `per_sec` levels per second.
+    pub fn with_channel_slew(mut self, channel: u16, per_sec: u32) -> Self {
+        self.channel_slew.insert(channel, per_sec);
+        self
+    }
+
+    /// Fades universes to zero over `fade` once they have gone `after` without a frame.
+    pub fn with_hold_timeout(mut self, after: Duration, fade: Duration) -> Self {
+        self.hold = Some(HoldTimeout {
+            after,
+            fade,
+            level: 0,
+        });
+        self
+    }
+
+    fn policies(
+        &self,
+        strategy: JitterStrategy,
+        len: usize,
+        groups: Option<&Map<String, Vec<u16>>>,
+    ) -> Vec<SmoothingPolicy> {
+        let mut policies = vec![
+            SmoothingPolicy {
+                strategy,
+                ramp: self.ramp,
+                slew_per_sec: self.slew_per_sec,
+            };
+            len
+        ];
+        for (name, channels) in groups.into_iter().flatten() {
+            if let Some(policy) = self.groups.get(name) {
+                for &channel in channels {
+                    if let Some(slot) = policies.get_mut(channel as usize) {
+                        *slot = *policy;
+                    }
+                }
+            }
+        }
+        for (&channel, &per_sec) in &self.channel_slew {
+            if let Some(slot) = policies.get_mut(channel as usize) {
+                slot.slew_per_sec = Some(per_sec);
+            }
+        }
+        policies
+    }
+}
+
+mod duration_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// One channel moving from `from` towards `target`.
+#[derive(Debug, Clone, Copy)]
+struct ChannelRamp {
+    from: u16,
+    target: u16,
+    started: Instant,
+    ramp: Duration,
+    slew_per_sec: Option<u32>,
+}
+
+impl ChannelRamp {
+    fn settled(level: u16, now: Instant) -> Self {
+        Self {
+            from: level,
+            target: level,
+            started: now,
+            ramp: Duration::ZERO,
+            slew_per_sec: None,
+        }
+    }
+
+    fn level(&self, now: Instant) -> u16 {
+        let elapsed = now.saturating_duration_since(self.started);
+        let delta = f64::from(self.target) - f64::from(self.from);
+        let mut step = if self.ramp.is_zero() || elapsed >= self.ramp {
+            delta
+        } else {
+            delta * elapsed.as_secs_f64() / self.ramp.as_secs_f64()
+        };
+        if let Some(per_sec) = self.slew_per_sec {
+            let limit = f64::from(per_sec) * elapsed.as_secs_f64();
+            step = step.clamp(-limit, limit);
+        }
+        (f64::from(self.from) + step).round() as u16
+    }
+}
+
+#[derive(Debug)]
+struct UniverseRamps {
+    ramps: Vec<ChannelRamp>,
+    last_frame: Instant,
+    /// Whether the hold timeout has already started fading the universe.
+    faded: bool,
+}
+
+impl UniverseRamps {
+    fn moving(&self, now: Instant) -> bool {
+        self.ramps.iter().any(|ramp| ramp.level(now) != ramp.target)
+    }
+}
+
+/// Smoothing state of one universe.
+///
+/// Senders keep one per universe so smoothing a universe never waits on another;
+/// [`Smoother`] keeps them for every universe of a receiver.
+#[derive(Debug, Default)]
+pub struct UniverseSmoother {
+    /// `None` until the universe's first frame.
+    state: Option<UniverseRamps>,
+}
+
+impl UniverseSmoother {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retargets the universe to a frame's `channels` and returns the levels at `now`.
+    ///
+    /// `strategy` covers channels outside the overridden groups. An empty frame holds
+    /// the current levels, or is dropped (`None`) under [`JitterStrategy::Drop`].
+    pub fn apply(
+        &mut self,
+        strategy: JitterStrategy,
+        config: &SmoothingConfig,
+        channels: &[u16],
+        groups: Option<&Map<String, Vec<u16>>>,
+        now: Instant,
+    ) -> Option<ChannelBuffer> {
+        if let Some(state) = &mut self.state {
+            state.last_frame = now;
+            state.faded = false;
+        }
+        if channels.is_empty() {
+            return match strategy {
+                JitterStrategy::Drop => None,
+                _ => Some(self.sample(now).unwrap_or_default()),
+            };
+        }
+        let policies = config.policies(strategy, channels.len(), groups);
+        let ramps = &mut self
+            .state
+            .get_or_insert_with(|| UniverseRamps {
+                ramps: Vec::new(),
+                last_frame: now,
+                faded: false,
+            })
+            .ramps;
+        ramps.truncate(channels.len());
+        for (index, (&target, policy)) in channels.iter().zip(&policies).enumerate() {
+            let Some(ramp) = ramps.get_mut(index) else {
+                // Channels without history start at their first level.
+                ramps.push(ChannelRamp::settled(target, now));
+                continue;
+            };
+            *ramp = ChannelRamp {
+                from: ramp.level(now),
+                target,
+                started: now,
+                ramp: if policy.strategy == JitterStrategy::Lerp {
+                    policy.ramp
+                } else {
+                    Duration::ZERO
+                },
+                slew_per_sec: policy.slew_per_sec,
+            };
+        }
+        Some(ramps.iter().map(|ramp| ramp.level(now)).collect())
+    }
+
+    /// Sets the universe straight to `channels` without ramping, e.g. for a blackout.
+    pub fn snap(&mut self, channels: &[u16], now: Instant) {
+        self.state = Some(UniverseRamps {
+            ramps: channels
+                .iter()
+                .map(|&level| ChannelRamp::settled(level, now))
+                .collect(),
+            last_frame: now,
+            faded: false,
+        });
+    }
+
+    /// Levels at `now`, if the universe has received a frame.
+    pub fn sample(&self, now: Instant) -> Option<ChannelBuffer> {
+        self.state
+            .as_ref()
+            .map(|state| state.ramps.iter().map(|ramp| ramp.level(now)).collect())
+    }
+}
+
+/// Per-universe smoothing state of one side of a stream.
+#[derive(Debug, Default)]
+pub struct Smoother {
+    universes: HashMap<UniverseId, UniverseSmoother>,
+}
+
+impl Smoother {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retargets `universe` to a frame's `channels` and returns the levels at `now`; see
+    /// [`UniverseSmoother::apply`].
+    pub fn apply(
+        &mut self,
+        strategy: JitterStrategy,
+        config: &SmoothingConfig,
+        universe: UniverseId,
+        channels: &[u16],
+        groups: Option<&Map<String, Vec<u16>>>,
+        now: Instant,
+    ) -> Option<ChannelBuffer> {
+        self.universes
+            .entry(universe)
+            .or_default()
+            .apply(strategy, config, channels, groups, now)
+    }
+
+    /// Sets `universe` straight to `channels` without ramping, e.g. for a blackout.
+    pub fn snap(&mut self, universe: UniverseId, channels: &[u16], now: Instant) {
+        self.universes
+            .entry(universe)
+            .or_default()
+            .snap(channels, now);
+    }
+
+    /// Levels of `universe` at `now`, if it has received a frame.
+    pub fn sample(&self, universe: UniverseId, now: Instant) -> Option<ChannelBuffer> {
+        self.universes.get(&universe)?.sample(now)
+    }
+
+    fn states(&self) -> impl Iterator<Item = (UniverseId, &UniverseRamps)> {
+        self.universes
+            .iter()
+            .filter_map(|(universe, smoother)| Some((*universe, smoother.state.as_ref()?)))
+    }
+
+    /// Universes with a channel still moving at `now`.
+    pub fn ramping(&self, now: Instant) -> Vec<UniverseId> {
+        self.states()
+            .filter(|(_, state)| state.moving(now))
+            .map(|(universe, _)| universe)
+            .collect()
+    }
+
+    /// When the next universe runs out its hold, if any is still holding.
+    pub fn hold_deadline(&self, hold: &HoldTimeout) -> Option<Instant> {
+        self.states()
+            .filter(|(_, state)| !state.faded)
+            .map(|(_, state)| state.last_frame + hold.after)
+            .min()
+    }
+
+    /// Counts as a frame for every universe's hold timeout without changing a level,
+    /// for a sender that is alive but has nothing new to send.
+    pub fn keep_alive(&mut self, now: Instant) {
+        for state in self.universes.values_mut().filter_map(|s| s.state.as_mut()) {
+            if !state.faded {
+                state.last_frame = now;
+            }
+        }
+    }
+
+    /// Starts fading every universe that has gone `hold.after` without a frame and
+    /// returns them; the next frame of a universe takes it back.
+    pub fn expire(&mut self, hold: &HoldTimeout, now: Instant) -> Vec<UniverseId> {
+        let mut expired = Vec::new();
+        for (universe, smoother) in &mut self.universes {
+            let Some(state) = smoother.state.as_mut() else {
+                continue;
+            };
+            if state.faded || now.saturating_duration_since(state.last_frame) < hold.after {
+                continue;
+            }
+            for ramp in &mut state.ramps {
+                *ramp = ChannelRamp {
+                    from: ramp.level(now),
+                    target: hold.level,
+                    started: now,
+                    ramp: hold.fade,
+                    slew_per_sec: None,
+                };
+            }
+            state.faded = true;
+            expired.push(*universe);
+        }
+        expired
+    }
+
+    /// Forgets all state, so the next frame of every universe applies as is.
+    pub fn clear(&mut self) {
+        self.universes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smallvec::smallvec;
+
+    #[test]
+    fn lerp_ramps_over_time_and_groups_override() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let config = SmoothingConfig {
+            ramp: ms(100),
+            ..SmoothingConfig::default()
+        }
+        .with_group(
+            "intensity",
+            SmoothingPolicy {
+                strategy: JitterStrategy::HoldLast,
+                ramp: Duration::ZERO,
+                slew_per_sec: None,
+            },
+        )
+        .with_channel_slew(2, 1_000);
+        let groups: Map<String, Vec<u16>> = [("intensity".to_string(), vec![0])].into();
+        let mut smoother = Smoother::new();
+        let lerp = JitterStrategy::Lerp;
+
+        let first = smoother.apply(lerp, &config, 1, &[0, 0, 0], Some(&groups), start);
+        assert_eq!(first, Some(smallvec![0, 0, 0]));
+        let second = smoother.apply(lerp, &config, 1, &[200, 200, 200], Some(&groups), start);
+        // Intensity snaps; the rest start their ramps.
+        assert_eq!(second, Some(smallvec![200, 0, 0]));
+        assert_eq!(
+            smoother.sample(1, start + ms(50)),
+            Some(smallvec![200, 100, 50])
+        );
+        assert_eq!(smoother.ramping(start + ms(100)), vec![1]);
+        assert_eq!(
+            smoother.sample(1, start + ms(200)),
+            Some(smallvec![200, 200, 200])
+        );
+        assert!(smoother.ramping(start + ms(200)).is_empty());
+
+        // A new target mid-ramp starts from where the channel stands.
+        let mid = smoother.apply(lerp, &config, 1, &[0, 0, 0], None, start + ms(250));
+        assert_eq!(mid, Some(smallvec![200, 200, 200]));
+        assert_eq!(
+            smoother.sample(1, start + ms(300)),
+            Some(smallvec![100, 100, 150])
+        );
+
+        let empty = smoother.apply(JitterStrategy::Drop, &config, 1, &[], None, start);
+        assert_eq!(empty, None);
+    }
+
+    #[test]
+    fn stale_universes_fade_to_safe_level() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let config = SmoothingConfig::default().with_hold_timeout(ms(500), ms(100));
+        let hold = config.hold.unwrap();
+        let mut smoother = Smoother::new();
+        let hold_last = JitterStrategy::HoldLast;
+        smoother.apply(hold_last, &config, 1, &[255, 100], None, start);
+        smoother.apply(hold_last, &config, 2, &[10], None, start + ms(300));
+        assert_eq!(smoother.hold_deadline(&hold), Some(start + ms(500)));
+        assert!(smoother.expire(&hold, start + ms(499)).is_empty());
+
+        assert_eq!(smoother.expire(&hold, start + ms(500)), vec![1]);
+        assert_eq!(
+            smoother.sample(1, start + ms(550)),
+            Some(smallvec![128, 50])
+        );
+        assert_eq!(smoother.sample(1, start + ms(600)), Some(smallvec![0, 0]));
+        // Fading universes are not expired twice.
+        assert_eq!(smoother.hold_deadline(&hold), Some(start + ms(800)));
+
+        // A frame brings the universe back.
+        let back = smoother.apply(hold_last, &config, 1, &[200, 200], None, start + ms(700));
+        assert_eq!(back, Some(smallvec![200, 200]));
+        assert_eq!(smoother.hold_deadline(&hold), Some(start + ms(800)));
+    }
+
+    #[test]
+    fn universe_smoothers_ramp_on_their_own() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let config = SmoothingConfig {
+            ramp: ms(100),
+            ..SmoothingConfig::default()
+        };
+        let lerp = JitterStrategy::Lerp;
+        let mut one = UniverseSmoother::new();
+        let mut two = UniverseSmoother::new();
+        assert_eq!(one.sample(start), None);
+        // An empty first frame holds nothing rather than inventing levels.
+        assert_eq!(
+            one.apply(lerp, &config, &[], None, start),
+            Some(smallvec![])
+        );
+
+        one.apply(lerp, &config, &[0, 0], None, start);
+        two.snap(&[50], start);
+        one.apply(lerp, &config, &[200, 100], None, start);
+        assert_eq!(one.sample(start + ms(50)), Some(smallvec![100, 50]));
+        assert_eq!(two.sample(start + ms(50)), Some(smallvec![50]));
+    }
+}
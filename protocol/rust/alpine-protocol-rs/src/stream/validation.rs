@@ -0,0 +1,335 @@
+//! Receive-side checks of frames against what the device negotiated.
+//!
+//! A frame with more channels than the device's `max_channels`, in a format it never
+//! advertised, or for a universe it does not serve is refused before it reaches merge,
+//! arbitration, or outputs. Every refusal is counted; a misbehaving controller can send
+//! thousands a second, so reports are limited to one per reason and universe each
+//! [`REJECTION_REPORT_INTERVAL`], carrying how many frames were refused since the last.
+//! A session's [`SessionQuotas`] add a frame-rate budget and a cap on metadata size.
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use crate::control::TokenBucket;
+use crate::messages::{CapabilitySet, FrameEnvelope, UniverseId};
+use crate::session::quota::SessionQuotas;
+
+/// Shortest time between two reports of the same reason and universe.
+pub const REJECTION_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why a received frame was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectionReason {
+    /// More channels than the advertised `max_channels`.
+    TooManyChannels,
+    /// A channel format the device did not advertise.
+    UnsupportedFormat,
+    /// A universe the device does not serve.
+    UnknownUniverse,
+    /// Faster than the session's frame-rate quota.
+    RateExceeded,
+    /// Metadata larger than the session's quota.
+    MetadataTooLarge,
+}
+
+impl RejectionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectionReason::TooManyChannels => "too_many_channels",
+            RejectionReason::UnsupportedFormat => "unsupported_format",
+            RejectionReason::UnknownUniverse => "unknown_universe",
+            RejectionReason::RateExceeded => "rate_exceeded",
+            RejectionReason::MetadataTooLarge => "metadata_too_large",
+        }
+    }
+}
+
+/// Rate-limited report of refused frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRejected {
+    pub reason: RejectionReason,
+    pub universe: UniverseId,
+    /// Frames refused for this reason and universe since the previous report, including
+    /// the one that triggered this report.
+    pub count: u64,
+}
+
+/// Frames refused so far, per reason.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RejectionStats {
+    pub too_many_channels: u64,
+    pub unsupported_format: u64,
+    pub unknown_universe: u64,
+    pub rate_exceeded: u64,
+    pub metadata_too_large: u64,
+}
+
+impl RejectionStats {
+    pub fn total(&self) -> u64 {
+        self.too_many_channels
+            + self.unsupported_format
+            + self.unknown_universe
+            + self.rate_exceeded
+            + self.metadata_too_large
+    }
+}
+
+/// Checks received frames against the device's capabilities and served universes.
+#[derive(Debug)]
+pub struct FrameValidator {
+    capabilities: CapabilitySet,
+    /// `None` serves every universe.
+    universes: Option<BTreeSet<UniverseId>>,
+    /// Frame-rate quota of the session, if it has one.
+    frame_rate: Option<TokenBucket>,
+    max_metadata_bytes: Option<usize>,
+    stats: RejectionStats,
+    /// Last report and frames refused since, per reason and universe.
+    reports: HashMap<(RejectionReason, UniverseId), (Instant, u64)>,
+}
+
+impl FrameValidator {
+    /// Validates against the capabilities the device advertised in its handshake.
+    pub fn new(capabilities: &CapabilitySet) -> Self {
+        Self {
+            capabilities: capabilities.clone(),
+            universes: None,
+            frame_rate: None,
+            max_metadata_bytes: None,
+            stats: RejectionStats::default(),
+            reports: HashMap::new(),
+        }
+    }
+
+    /// Validates against capabilities the device pushed mid-session from now on.
+    pub fn set_capabilities(&mut self, capabilities: &CapabilitySet) {
+        self.capabilities = capabilities.clone();
+    }
+
+    /// Refuses frames for universes outside `universes`.
+    pub fn with_universes(mut self, universes: impl IntoIterator<Item = UniverseId>) -> Self {
+        self.universes = Some(universes.into_iter().collect());
+        self
+    }
+
+    /// Enforces the session's frame-rate and metadata quotas.
+    pub fn with_quotas(mut self, quotas: &SessionQuotas) -> Self {
+        self.frame_rate = quotas
+            .frame_rate()
+            .map(|limit| TokenBucket::new(limit, Instant::now()));
+        self.max_metadata_bytes = quotas.max_metadata_bytes();
+        self
+    }
+
+    /// Spends one frame of the frame-rate quota, or returns how long until the next frame
+    /// will be accepted. Called on arrival, before any work is spent on the frame.
+    pub fn admit(&mut self, now: Instant) -> Result<(), Duration> {
+        match &mut self.frame_rate {
+            Some(bucket) => bucket.try_take(now),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks a decompressed frame that has not been unpacked yet, so its format is the
+    /// one it was sent in.
+    pub fn check(&self, frame: &FrameEnvelope) -> Result<(), RejectionReason> {
+        if let (Some(max), Some(metadata)) = (self.max_metadata_bytes, &frame.metadata) {
+            if serde_json::to_vec(metadata).map_or(true, |bytes| bytes.len() > max) {
+                return Err(RejectionReason::MetadataTooLarge);
+            }
+        }
+        if self
+            .universes
+            .as_ref()
+            .is_some_and(|universes| !universes.contains(&frame.universe))
+        {
+            return Err(RejectionReason::UnknownUniverse);
+        }
+        if !self.capabilities.supports_format(frame.channel_format) {
+            return Err(RejectionReason::UnsupportedFormat);
+        }
+        let channels = match (&frame.float_channels, &frame.packed_channels) {
+            (Some(values), _) => values.len(),
+            (None, Some(packed)) => packed.0.len(),
+            (None, None) => frame.channels.len(),
+        };
+        if u32::try_from(channels)
+            .map_or(true, |channels| channels > self.capabilities.max_channels)
+        {
+            return Err(RejectionReason::TooManyChannels);
+        }
+        Ok(())
+    }
+
+    /// Counts a refused frame; returns a report unless one for the same reason and
+    /// universe went out within [`REJECTION_REPORT_INTERVAL`].
+    pub fn reject(
+        &mut self,
+        reason: RejectionReason,
+        universe: UniverseId,
+        now: Instant,
+    ) -> Option<FrameRejected> {
+        let counter = match reason {
+            RejectionReason::TooManyChannels => &mut self.stats.too_many_channels,
+            RejectionReason::UnsupportedFormat => &mut self.stats.unsupported_format,
+            RejectionReason::UnknownUniverse => &mut self.stats.unknown_universe,
+            RejectionReason::RateExceeded => &mut self.stats.rate_exceeded,
+            RejectionReason::MetadataTooLarge => &mut self.stats.metadata_too_large,
+        };
+        *counter = counter.saturating_add(1);
+        let count = match self.reports.get_mut(&(reason, universe)) {
+            Some((reported, pending))
+                if now.saturating_duration_since(*reported) < REJECTION_REPORT_INTERVAL =>
+            {
+                *pending += 1;
+                return None;
+            }
+            Some((_, pending)) => *pending + 1,
+            None => 1,
+        };
+        self.reports.insert((reason, universe), (now, 0));
+        warn!(
+            target: "alpine::stream",
+            universe,
+            reason = reason.as_str(),
+            count,
+            "refused {} frame(s): {}",
+            count,
+            reason.as_str()
+        );
+        Some(FrameRejected {
+            reason,
+            universe,
+            count,
+        })
+    }
+
+    pub fn stats(&self) -> RejectionStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ChannelBuffer, ChannelFormat, MessageType, PackedChannels};
+    use uuid::Uuid;
+
+    fn frame(
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: usize,
+    ) -> FrameEnvelope {
+        FrameEnvelope {
+            message_type: MessageType::AlpineFrame,
+            session_id: Uuid::nil(),
+            universe,
+            timestamp_us: 0,
+            priority: 100,
+            channel_format,
+            channels: ChannelBuffer::from_elem(0, channels),
+            packed_channels: None,
+            float_channels: None,
+            compression: None,
+            compressed_channels: None,
+            groups: None,
+            group_refs: None,
+            config_tag: None,
+            deadline_us: None,
+            metadata: None,
+            extensions: None,
+        }
+    }
+
+    fn validator() -> FrameValidator {
+        let capabilities = CapabilitySet {
+            channel_formats: vec![ChannelFormat::U8, ChannelFormat::U8Packed],
+            max_channels: 512,
+            ..CapabilitySet::default()
+        };
+        FrameValidator::new(&capabilities).with_universes([0, 1])
+    }
+
+    #[test]
+    fn refuses_frames_outside_the_negotiated_limits() {
+        let validator = validator();
+        assert_eq!(validator.check(&frame(1, ChannelFormat::U8, 512)), Ok(()));
+        assert_eq!(
+            validator.check(&frame(1, ChannelFormat::U8, 513)),
+            Err(RejectionReason::TooManyChannels)
+        );
+        let mut packed = frame(0, ChannelFormat::U8Packed, 0);
+        packed.packed_channels = Some(PackedChannels(vec![0; 600]));
+        assert_eq!(
+            validator.check(&packed),
+            Err(RejectionReason::TooManyChannels)
+        );
+        assert_eq!(
+            validator.check(&frame(0, ChannelFormat::U16, 4)),
+            Err(RejectionReason::UnsupportedFormat)
+        );
+        assert_eq!(
+            validator.check(&frame(7, ChannelFormat::U8, 4)),
+            Err(RejectionReason::UnknownUniverse)
+        );
+    }
+
+    #[test]
+    fn reports_are_rate_limited_but_every_frame_is_counted() {
+        let mut validator = validator();
+        let start = Instant::now();
+        let reason = RejectionReason::UnknownUniverse;
+        assert_eq!(
+            validator
+                .reject(reason, 7, start)
+                .map(|report| report.count),
+            Some(1)
+        );
+        for ms in 1..=10 {
+            assert_eq!(
+                validator.reject(reason, 7, start + Duration::from_millis(ms)),
+                None
+            );
+        }
+        // Another universe is reported on its own.
+        assert!(validator.reject(reason, 8, start).is_some());
+        let later = start + REJECTION_REPORT_INTERVAL;
+        assert_eq!(
+            validator
+                .reject(reason, 7, later)
+                .map(|report| report.count),
+            Some(11)
+        );
+        assert_eq!(validator.stats().unknown_universe, 13);
+        assert_eq!(validator.stats().total(), 13);
+    }
+
+    #[test]
+    fn enforces_the_session_quotas() {
+        use crate::control::RateLimit;
+
+        let quotas = SessionQuotas::unlimited()
+            .with_frame_rate(RateLimit::new(10, 2).unwrap())
+            .with_max_metadata_bytes(32);
+        let mut validator = validator().with_quotas(&quotas);
+        let start = Instant::now();
+        assert_eq!(validator.admit(start), Ok(()));
+        assert_eq!(validator.admit(start), Ok(()));
+        assert!(validator.admit(start).is_err());
+        assert_eq!(validator.admit(start + Duration::from_millis(100)), Ok(()));
+
+        let mut tagged = frame(0, ChannelFormat::U8, 4);
+        let mut metadata = crate::messages::Map::new();
+        metadata.insert("cue".into(), "12".into());
+        tagged.metadata = Some(metadata.clone());
+        assert_eq!(validator.check(&tagged), Ok(()));
+        metadata.insert("notes".into(), "x".repeat(32).into());
+        tagged.metadata = Some(metadata);
+        assert_eq!(
+            validator.check(&tagged),
+            Err(RejectionReason::MetadataTooLarge)
+        );
+    }
+}
@@ -0,0 +1,1004 @@
+//! In-process node simulator for integration tests without hardware.
+//!
+//! [`SimulatedDevice`] answers discovery, accepts handshakes, serves the control plane,
+//! and receives frames exactly like a node on the network would, but over in-memory
+//! links. Latency, loss, and scripted failures let controller code exercise its
+//! retransmission and error paths deterministically in CI.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use ed25519_dalek::SigningKey;
+use parking_lot::Mutex;
+use rand::rngs::{OsRng, StdRng};
+use rand::{Rng, RngCore, SeedableRng};
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::time;
+
+use crate::codec;
+use crate::conformance::ConformanceTarget;
+use crate::control::{ControlCrypto, ControlResponder};
+use crate::crypto::identity::NodeCredentials;
+use crate::crypto::SUPPORTED_SUITES;
+use crate::device::DeviceServer;
+use crate::handshake::transport::{ControlDedup, Delivery};
+use crate::handshake::{HandshakeError, HandshakeMessage, HandshakeTransport};
+use crate::messages::{
+    CapabilitySet, ControlEnvelope, ControlOp, DeviceIdentity, DiscoveryReply, DiscoveryRequest,
+    ErrorCode, ErrorEnvelope, FrameEnvelope, FrameFragment, MessageType,
+};
+use crate::mirror::OutputMirror;
+use crate::session::limits::SessionLimits;
+use crate::session::quota::SessionQuotas;
+use crate::session::registry::SessionRegistry;
+use crate::session::resume::ResumptionCache;
+use crate::session::{AlnpSession, TimingConfig};
+use crate::stream::{FrameTransport, Reassembler};
+
+/// Behaviour of a [`SimulatedDevice`].
+#[derive(Clone)]
+pub struct SimulatorConfig {
+    pub identity: DeviceIdentity,
+    pub capabilities: CapabilitySet,
+    pub mac_address: String,
+    pub credentials: NodeCredentials,
+    pub timing: TimingConfig,
+    /// One-way delay applied to everything the device sends and every frame it receives.
+    pub latency: Duration,
+    /// Probability (0.0 to 1.0) that a control reply or frame is lost once a session is up.
+    /// Handshake messages are never lost, since the handshake does not retransmit.
+    pub loss: f64,
+    /// Seed for the loss pattern, so a failing run can be replayed exactly.
+    pub seed: u64,
+    /// Session and handshake limits, e.g. to make the device answer busy.
+    pub sessions: SessionRegistry,
+}
+
+impl SimulatorConfig {
+    /// Lossless, zero-latency device with freshly generated credentials.
+    pub fn new(identity: DeviceIdentity) -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        let signing = SigningKey::from_bytes(&secret);
+        Self {
+            identity,
+            capabilities: CapabilitySet::default(),
+            mac_address: "02:00:00:00:00:01".into(),
+            credentials: NodeCredentials {
+                verifying: signing.verifying_key(),
+                signing,
+            },
+            timing: TimingConfig::default(),
+            latency: Duration::ZERO,
+            loss: 0.0,
+            seed: 0,
+            sessions: SessionRegistry::default(),
+        }
+    }
+}
+
+/// Failure the device plays back the next time the matching event occurs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptedFailure {
+    /// Reads the next handshake but never answers it.
+    IgnoreHandshake,
+    /// Refuses the next handshake with an error envelope carrying this code.
+    RejectHandshake(ErrorCode),
+    /// Refuses the next authenticated control envelope with this code.
+    RejectControl(ErrorCode),
+    /// Loses the next `n` frames regardless of the configured loss rate.
+    DropFrames(u32),
+}
+
+/// Counters describing what the device has seen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimulatorStats {
+    pub handshakes: u64,
+    pub control_envelopes: u64,
+    pub frames_received: u64,
+    pub frames_lost: u64,
+    /// Frames that did not decode or belonged to a session other than the current one.
+    pub frames_rejected: u64,
+    /// Received frames whose simulated arrival missed their `deadline_us`.
+    pub frames_late: u64,
+}
+
+struct DeviceState {
+    session: Option<AlnpSession>,
+    script: VecDeque<ScriptedFailure>,
+    rng: StdRng,
+    mode: Option<String>,
+    identify_count: u32,
+    frames: Vec<(Instant, FrameEnvelope)>,
+    reassembler: Reassembler,
+    stats: SimulatorStats,
+}
+
+impl DeviceState {
+    fn lose(&mut self, loss: f64) -> bool {
+        loss > 0.0 && self.rng.gen_bool(loss.min(1.0))
+    }
+
+    fn take_handshake_failure(&mut self) -> Option<ScriptedFailure> {
+        let index = self.script.iter().position(|failure| {
+            matches!(
+                failure,
+                ScriptedFailure::IgnoreHandshake | ScriptedFailure::RejectHandshake(_)
+            )
+        })?;
+        self.script.remove(index)
+    }
+
+    fn take_control_failure(&mut self) -> Option<ErrorCode> {
+        let index = self
+            .script
+            .iter()
+            .position(|failure| matches!(failure, ScriptedFailure::RejectControl(_)))?;
+        match self.script.remove(index) {
+            Some(ScriptedFailure::RejectControl(code)) => Some(code),
+            _ => None,
+        }
+    }
+
+    fn take_frame_drop(&mut self) -> bool {
+        let Some(index) = self
+            .script
+            .iter()
+            .position(|failure| matches!(failure, ScriptedFailure::DropFrames(_)))
+        else {
+            return false;
+        };
+        if let Some(ScriptedFailure::DropFrames(remaining)) = self.script.get_mut(index) {
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                self.script.remove(index);
+            }
+        }
+        true
+    }
+}
+
+/// A complete node running in-process.
+///
+/// Cloning yields another handle to the same device.
+#[derive(Clone)]
+pub struct SimulatedDevice {
+    config: Arc<SimulatorConfig>,
+    server: Arc<DeviceServer>,
+    state: Arc<Mutex<DeviceState>>,
+}
+
+impl SimulatedDevice {
+    pub fn new(config: SimulatorConfig) -> Self {
+        let server = DeviceServer {
+            identity: config.identity.clone(),
+            mac_address: config.mac_address.clone(),
+            capabilities: config.capabilities.clone().into(),
+            credentials: config.credentials.clone().into(),
+            timing: config.timing,
+            limits: SessionLimits::unlimited(),
+            resumption: ResumptionCache::default(),
+            revocations: None,
+            cipher_suites: SUPPORTED_SUITES.to_vec(),
+            required_firmware_rev: None,
+            firmware_policy: None,
+            sessions: config.sessions.clone(),
+            quotas: SessionQuotas::unlimited(),
+            preemption_grace: None,
+            mirror: OutputMirror::default(),
+        };
+        let state = DeviceState {
+            session: None,
+            script: VecDeque::new(),
+            rng: StdRng::seed_from_u64(config.seed),
+            mode: None,
+            identify_count: 0,
+            frames: Vec::new(),
+            reassembler: Reassembler::default(),
+            stats: SimulatorStats::default(),
+        };
+        Self {
+            config: Arc::new(config),
+            server: Arc::new(server),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn identity(&self) -> &DeviceIdentity {
+        &self.config.identity
+    }
+
+    /// Credentials a controller needs to authenticate this device.
+    pub fn credentials(&self) -> &NodeCredentials {
+        &self.config.credentials
+    }
+
+    /// Queues a failure to play back; failures of the same kind fire in queue order.
+    pub fn inject(&self, failure: ScriptedFailure) {
+        self.state.lock().script.push_back(failure);
+    }
+
+    /// Answers a discovery request with a signed reply.
+    pub fn discover(&self, request: &DiscoveryRequest) -> DiscoveryReply {
+        self.server.discovery_reply(request)
+    }
+
+    /// Opens a controller-side link to the device.
+    ///
+    /// The device side handshakes and then serves control envelopes on the link until
+    /// the controller drops it. Must be called inside a tokio runtime.
+    pub fn connect(&self) -> SimulatedLink {
+        let (to_device, device_rx) = mpsc::channel(32);
+        let (device_tx, from_device) = mpsc::channel(32);
+        let device_end = SimulatedLink {
+            tx: device_tx,
+            rx: device_rx,
+            delay: self.config.latency,
+        };
+        tokio::spawn(self.clone().serve(device_end));
+        SimulatedLink {
+            tx: to_device,
+            rx: from_device,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Frame transport delivering into this device.
+    pub fn frame_transport(&self) -> SimulatedFrames {
+        SimulatedFrames {
+            device: self.clone(),
+        }
+    }
+
+    /// Session established by the most recent successful handshake.
+    pub fn session(&self) -> Option<AlnpSession> {
+        self.state.lock().session.clone()
+    }
+
+    /// Frames that have arrived once the configured latency is taken into account.
+    pub fn frames(&self) -> Vec<FrameEnvelope> {
+        let now = Instant::now();
+        self.state
+            .lock()
+            .frames
+            .iter()
+            .filter(|(arrival, _)| *arrival <= now)
+            .map(|(_, frame)| frame.clone())
+            .collect()
+    }
+
+    /// Operating mode last set with `ControlOp::SetMode`.
+    pub fn mode(&self) -> Option<String> {
+        self.state.lock().mode.clone()
+    }
+
+    /// Number of `ControlOp::Identify` requests acted on.
+    pub fn identify_count(&self) -> u32 {
+        self.state.lock().identify_count
+    }
+
+    pub fn stats(&self) -> SimulatorStats {
+        self.state.lock().stats.clone()
+    }
+
+    async fn serve(self, mut link: SimulatedLink) {
+        let failure = self.state.lock().take_handshake_failure();
+        match failure {
+            Some(ScriptedFailure::IgnoreHandshake) => {
+                while link.recv().await.is_ok() {}
+                return;
+            }
+            Some(ScriptedFailure::RejectHandshake(code)) => {
+                if let Ok(HandshakeMessage::SessionInit(init)) = link.recv().await {
+                    let error = ErrorEnvelope::new(
+                        Some(init.session_id),
+                        code,
+                        Some("scripted failure".into()),
+                    );
+                    let _ = link.send(HandshakeMessage::Error(error)).await;
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let Ok(session) = self.server.accept(&mut link).await else {
+            return;
+        };
+        let (Some(established), Some(keys)) = (session.established(), session.keys()) else {
+            return;
+        };
+        {
+            let mut state = self.state.lock();
+            if let Some(previous) = state.session.replace(session.clone()) {
+                previous.close();
+            }
+            state.stats.handshakes += 1;
+        }
+
+        let responder = ControlResponder::new(established.session_id, ControlCrypto::new(keys));
+        let mut dedup = ControlDedup::default();
+        while let Ok(msg) = link.recv().await {
+            let env = match msg {
+                HandshakeMessage::Control(env) => env,
+                HandshakeMessage::SessionClose(notice)
+                    if responder.verify_close(&notice).is_ok() =>
+                {
+                    session.close();
+                    return;
+                }
+                _ => continue,
+            };
+            let reply = if responder.verify(&env).is_err() {
+                HandshakeMessage::Error(responder.error(
+                    env.seq,
+                    ErrorCode::SessionMacMismatch,
+                    Some("control MAC validation failed".into()),
+                ))
+            } else {
+                session.record_peer_activity();
+                // Retransmissions are answered from cache rather than acted on twice.
+                match dedup.accept(&env) {
+                    Delivery::New => {
+                        let reply = self.handle_control(&responder, &env);
+                        dedup.complete(&env, &reply);
+                        reply
+                    }
+                    Delivery::Replay(reply) => *reply,
+                    Delivery::InProgress | Delivery::Stale => continue,
+                }
+            };
+            if self.state.lock().lose(self.config.loss) {
+                continue;
+            }
+            if link.send(reply).await.is_err() {
+                break;
+            }
+        }
+        session.close();
+    }
+
+    fn handle_control(
+        &self,
+        responder: &ControlResponder,
+        env: &ControlEnvelope,
+    ) -> HandshakeMessage {
+        let mut state = self.state.lock();
+        state.stats.control_envelopes += 1;
+        if let Some(code) = state.take_control_failure() {
+            return HandshakeMessage::Error(responder.error(
+                env.seq,
+                code,
+                Some("scripted failure".into()),
+            ));
+        }
+        let (ok, detail) = match env.op {
+            ControlOp::Identify => {
+                state.identify_count += 1;
+                (true, None)
+            }
+            ControlOp::SetMode => match env
+                .payload_as::<serde_json::Value>()
+                .ok()
+                .and_then(|payload| payload.get("mode")?.as_str().map(str::to_string))
+            {
+                Some(mode) => {
+                    state.mode = Some(mode);
+                    (true, None)
+                }
+                None => (false, Some("missing mode".to_string())),
+            },
+            ControlOp::GetStatus => {
+                let status = json!({
+                    "mode": state.mode,
+                    "frames_received": state.stats.frames_received,
+                    "frames_lost": state.stats.frames_lost,
+                });
+                (true, Some(status.to_string()))
+            }
+            ControlOp::GetInfo => (true, serde_json::to_string(&self.config.identity).ok()),
+            _ => {
+                return HandshakeMessage::Error(responder.error(
+                    env.seq,
+                    ErrorCode::ControlUnknownOp,
+                    Some(format!("unsupported op {:?}", env.op)),
+                ))
+            }
+        };
+        match responder.ack(env.seq, ok, detail) {
+            Ok(ack) => HandshakeMessage::Ack(ack),
+            Err(err) => HandshakeMessage::Error(responder.error(
+                env.seq,
+                ErrorCode::ControlPayloadInvalid,
+                Some(err.to_string()),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl ConformanceTarget for SimulatedDevice {
+    type Transport = SimulatedLink;
+
+    async fn connect(&mut self) -> Result<SimulatedLink, HandshakeError> {
+        Ok(SimulatedDevice::connect(self))
+    }
+
+    fn frame_transport(&mut self) -> Option<Box<dyn FrameTransport>> {
+        Some(Box::new(SimulatedDevice::frame_transport(self)))
+    }
+}
+
+/// In-memory handshake and control link to a [`SimulatedDevice`].
+#[derive(Debug)]
+pub struct SimulatedLink {
+    tx: mpsc::Sender<HandshakeMessage>,
+    rx: mpsc::Receiver<HandshakeMessage>,
+    delay: Duration,
+}
+
+#[async_trait]
+impl HandshakeTransport for SimulatedLink {
+    async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        if !self.delay.is_zero() {
+            time::sleep(self.delay).await;
+        }
+        self.tx
+            .send(msg)
+            .await
+            .map_err(|e| HandshakeError::Transport(e.to_string()))
+    }
+
+    async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+        self.rx
+            .recv()
+            .await
+            .ok_or_else(|| HandshakeError::Transport("simulated link closed".into()))
+    }
+}
+
+/// Frame transport feeding a [`SimulatedDevice`].
+///
+/// Like UDP, sending never reports loss or rejection; inspect the device instead.
+pub struct SimulatedFrames {
+    device: SimulatedDevice,
+}
+
+impl FrameTransport for SimulatedFrames {
+    fn send_frame(&self, bytes: &[u8]) -> Result<(), String> {
+        let mut state = self.device.state.lock();
+        let current = state
+            .session
+            .as_ref()
+            .and_then(|session| session.established())
+            .map(|established| established.session_id);
+        let reassembled;
+        let bytes = match codec::decode_untrusted::<FrameFragment>(bytes) {
+            Ok(fragment) if fragment.message_type == MessageType::AlpineFrameFragment => {
+                let now = Instant::now();
+                let expired = state.reassembler.expire(now);
+                if let (Some(session), true) = (&state.session, expired > 0) {
+                    session.record_reassembly_timeouts(expired as u64);
+                }
+                match state.reassembler.push(fragment, now) {
+                    Some(bytes) => {
+                        reassembled = bytes;
+                        &reassembled[..]
+                    }
+                    None => return Ok(()),
+                }
+            }
+            _ => bytes,
+        };
+        let frame: FrameEnvelope = match codec::decode_untrusted(bytes) {
+            Ok(frame) => frame,
+            Err(_) => {
+                state.stats.frames_rejected += 1;
+                return Ok(());
+            }
+        };
+        if current != Some(frame.session_id) {
+            state.stats.frames_rejected += 1;
+            return Ok(());
+        }
+        if state.take_frame_drop() || state.lose(self.device.config.loss) {
+            state.stats.frames_lost += 1;
+            return Ok(());
+        }
+        state.stats.frames_received += 1;
+        let arrival_us = wallclock_us() + self.device.config.latency.as_micros() as u64;
+        let late = frame.missed_deadline(arrival_us);
+        if late {
+            state.stats.frames_late += 1;
+        }
+        if let Some(session) = &state.session {
+            session.record_frame_received();
+            if late {
+                session.record_late_frame();
+            }
+        }
+        let arrival = Instant::now() + self.device.config.latency;
+        state.frames.push((arrival, frame));
+        Ok(())
+    }
+}
+
+fn wallclock_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{ConformanceConfig, ConformanceSuite};
+    use crate::crypto::X25519KeyExchange;
+    use crate::handshake::transport::ReliableControlChannel;
+    use crate::handshake::HandshakeContext;
+    use crate::messages::{ChannelFormat, SessionRole};
+    use crate::mirror::MirrorSlice;
+    use crate::profile::StreamProfile;
+    use crate::session::registry::SessionAccess;
+    use crate::session::state::SessionState;
+    use crate::session::Ed25519Authenticator;
+    use crate::stream::{AlnpStream, FrameOutcome, UnderrunAlarm};
+    use crate::ControlClient;
+    use ed25519_dalek::{Signature, Verifier};
+    use uuid::Uuid;
+
+    fn identity(name: &str) -> DeviceIdentity {
+        DeviceIdentity {
+            device_id: format!("{name}-1"),
+            manufacturer_id: "manu".into(),
+            model_id: "model".into(),
+            hardware_rev: "rev1".into(),
+            firmware_rev: "1.0.0".into(),
+        }
+    }
+
+    async fn connect(
+        device: &SimulatedDevice,
+    ) -> Result<(AlnpSession, SimulatedLink), HandshakeError> {
+        let mut link = device.connect();
+        let session = AlnpSession::connect(
+            identity("controller"),
+            CapabilitySet::default(),
+            Ed25519Authenticator::new(device.credentials().clone()),
+            X25519KeyExchange::new(),
+            HandshakeContext::default(),
+            &mut link,
+        )
+        .await?;
+        Ok((session, link))
+    }
+
+    fn control_client(session: &AlnpSession) -> ControlClient {
+        let established = session.established().unwrap();
+        ControlClient::new(
+            Uuid::new_v4(),
+            established.session_id,
+            ControlCrypto::new(session.keys().unwrap()),
+        )
+    }
+
+    #[test]
+    fn discovery_reply_is_signed() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let request = DiscoveryRequest::new(vec![], vec![7; 32]);
+        let reply = device.discover(&request);
+        let mut signed = reply.server_nonce.clone();
+        signed.extend_from_slice(&request.client_nonce);
+        let signature = Signature::from_slice(&reply.signature).unwrap();
+        assert!(device
+            .credentials()
+            .verifying
+            .verify(&signed, &signature)
+            .is_ok());
+        assert_eq!(reply.device_id, "node-1");
+    }
+
+    #[tokio::test]
+    async fn rotated_credentials_sign_discovery_while_open_sessions_keep_serving() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let (session, link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let mut channel = ReliableControlChannel::new(link);
+
+        let rotated = crate::crypto::identity::generate();
+        let previous = device.server.rotate_credentials(rotated.clone());
+        assert_eq!(previous.verifying, device.credentials().verifying);
+
+        let request = DiscoveryRequest::new(vec![], vec![7; 32]);
+        let reply = device.discover(&request);
+        let mut signed = reply.server_nonce.clone();
+        signed.extend_from_slice(&request.client_nonce);
+        let signature = Signature::from_slice(&reply.signature).unwrap();
+        assert!(rotated.verifying.verify(&signed, &signature).is_ok());
+        assert!(previous.verifying.verify(&signed, &signature).is_err());
+
+        client
+            .send(&mut channel, ControlOp::Identify, json!({}))
+            .await
+            .unwrap();
+        assert_eq!(device.identify_count(), 1);
+        assert!(session.ensure_streaming_ready().is_ok());
+    }
+
+    #[tokio::test]
+    async fn pushed_capabilities_limit_the_running_stream() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let (session, _link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let node = device.session().unwrap();
+        let responder =
+            ControlResponder::new(client.session_id, ControlCrypto::new(node.keys().unwrap()));
+        let throttled = CapabilitySet {
+            max_channels: 4,
+            max_frame_rate: Some(1),
+            ..CapabilitySet::default()
+        };
+        let update = responder
+            .capability_update(
+                crate::control::CAPABILITY_UPDATE_SEQ_BASE,
+                throttled.clone(),
+                Some("thermal".into()),
+            )
+            .unwrap();
+        let mut forged = update.clone();
+        forged.capabilities.max_channels = 512;
+        assert!(client.accept_capability_update(&forged, &session).is_err());
+        let previous = client.accept_capability_update(&update, &session).unwrap();
+        assert_eq!(previous.max_channels, 512);
+        assert_eq!(session.established().unwrap().capabilities, throttled);
+        assert!(client.accept_capability_update(&update, &session).is_err());
+
+        let profile = StreamProfile::auto().compile().unwrap();
+        session.set_stream_profile(profile.clone()).unwrap();
+        session.mark_streaming();
+        let stream = AlnpStream::new(session, device.frame_transport(), profile);
+        let outcomes: Vec<_> = (0..2)
+            .map(|_| {
+                stream
+                    .send_universe_outcome(0, ChannelFormat::U8, &[9; 8], 100, None, None)
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(outcomes, [FrameOutcome::Sent, FrameOutcome::Throttled]);
+        assert_eq!(device.frames().len(), 1);
+        assert_eq!(device.frames()[0].channels[..], [9; 4]);
+        assert_eq!(stream.health().universes[0].throttled_frames, 1);
+    }
+
+    #[tokio::test]
+    async fn express_frames_skip_frame_rate_pacing() {
+        let mut config = SimulatorConfig::new(identity("node"));
+        config.capabilities.max_frame_rate = Some(1);
+        let device = SimulatedDevice::new(config);
+        let (session, _link) = connect(&device).await.unwrap();
+        let profile = StreamProfile::auto().compile().unwrap();
+        session.set_stream_profile(profile.clone()).unwrap();
+        session.mark_streaming();
+        let stream = AlnpStream::new(session, device.frame_transport(), profile);
+        stream
+            .send(ChannelFormat::U8, &[255; 4], 100, None, None)
+            .unwrap();
+        let paced = stream
+            .send_universe_outcome(0, ChannelFormat::U8, &[128; 4], 100, None, None)
+            .unwrap();
+        assert_eq!(paced, FrameOutcome::Throttled);
+        stream
+            .send_express(0, ChannelFormat::U8, &[0; 4], 100, None)
+            .unwrap();
+        let frames = device.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].channels[..], [0; 4]);
+        assert!(frames[1].adaptation_info().unwrap().force_keyframe);
+    }
+
+    #[tokio::test]
+    async fn universe_health_counts_delivered_frames_against_the_device_rate() {
+        let mut config = SimulatorConfig::new(identity("node"));
+        config.capabilities.max_frame_rate = Some(10);
+        let device = SimulatedDevice::new(config);
+        let (session, _link) = connect(&device).await.unwrap();
+        let profile = StreamProfile::auto().compile().unwrap();
+        session.set_stream_profile(profile.clone()).unwrap();
+        session.mark_streaming();
+        let stream = AlnpStream::new(session, device.frame_transport(), profile)
+            .with_underrun_alarm(UnderrunAlarm::default());
+        stream
+            .send_universe(3, ChannelFormat::U8, &[255; 4], 100, None, None)
+            .unwrap();
+        stream
+            .send_universe(3, ChannelFormat::U8, &[0; 4], 100, None, None)
+            .unwrap();
+        let health = stream.universe_health(3).unwrap();
+        assert_eq!(health.frames_sent, 1);
+        assert_eq!(health.throttled_frames, 1);
+        assert!(health.bytes_sent > 0);
+        assert_eq!(health.target_fps, 10.0);
+        assert!(health.last_change.is_some());
+        assert_eq!(health.achieved_fps, None);
+        assert!(!health.underrun);
+    }
+
+    #[tokio::test]
+    async fn busy_devices_send_a_signed_retry_after() {
+        let mut config = SimulatorConfig::new(identity("node"));
+        config.sessions = SessionRegistry::new(1)
+            .with_handshake_limit(1)
+            .with_retry_after(Duration::from_millis(750));
+        let device = SimulatedDevice::new(config);
+        let (_session, _link) = connect(&device).await.unwrap();
+
+        let err = connect(&device).await.unwrap_err();
+        assert!(matches!(err, HandshakeError::Busy(wait) if wait == Duration::from_millis(750)));
+        assert_eq!(err.code(), Some(ErrorCode::HandshakeCapacity));
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), Some(Duration::from_millis(750)));
+        // The refused handshake gave its slot back.
+        assert_eq!(device.server.sessions.handshakes(), 0);
+    }
+
+    #[tokio::test]
+    async fn observers_are_held_to_monitoring_access() {
+        let mut config = SimulatorConfig::new(identity("node"));
+        config.sessions = SessionRegistry::new(1);
+        let device = SimulatedDevice::new(config);
+        let mut link = device.connect();
+        let observer = AlnpSession::connect(
+            identity("dashboard"),
+            CapabilitySet::default(),
+            Ed25519Authenticator::new(device.credentials().clone()),
+            X25519KeyExchange::new(),
+            HandshakeContext {
+                role: Some(SessionRole::Observer),
+                ..HandshakeContext::default()
+            },
+            &mut link,
+        )
+        .await
+        .unwrap();
+        let session_id = observer.established().unwrap().session_id;
+        let sessions = &device.server.sessions;
+        assert_eq!(sessions.access(session_id), Some(SessionAccess::Monitor));
+        assert!(!sessions.set_access(session_id, SessionAccess::Full));
+        assert!(!sessions.may_stream(session_id));
+        assert_eq!(sessions.list()[0].role, SessionRole::Observer);
+    }
+
+    #[tokio::test]
+    async fn mirrored_output_is_authenticated_apart_from_updates() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let (session, _link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let node = device.session().unwrap();
+        let responder =
+            ControlResponder::new(client.session_id, ControlCrypto::new(node.keys().unwrap()));
+        let slice = MirrorSlice {
+            universe: 3,
+            applied_us: 1_000,
+            start: 256,
+            levels: vec![0, 128, 255],
+        };
+        let first = responder.mirrored_output(&slice).unwrap();
+        let second = responder.mirrored_output(&slice).unwrap();
+        assert_eq!((first.seq, second.seq), (0, 1));
+
+        let mut forged = second.clone();
+        forged.levels[1] = 0;
+        assert!(client.accept_mirrored_output(&forged).is_err());
+        assert_eq!(client.accept_mirrored_output(&second).unwrap(), slice);
+        // A datagram that arrives late is older than what the visualizer shows.
+        assert!(client.accept_mirrored_output(&first).is_err());
+        // Capability updates keep their own sequence.
+        let update = responder
+            .capability_update(
+                crate::control::CAPABILITY_UPDATE_SEQ_BASE,
+                CapabilitySet::default(),
+                None,
+            )
+            .unwrap();
+        client.accept_capability_update(&update, &session).unwrap();
+    }
+
+    #[tokio::test]
+    async fn preemption_notices_are_authenticated_and_ordered_with_updates() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let (session, _link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let node = device.session().unwrap();
+        let responder =
+            ControlResponder::new(client.session_id, ControlCrypto::new(node.keys().unwrap()));
+        let base = crate::control::CAPABILITY_UPDATE_SEQ_BASE;
+        let update = responder
+            .capability_update(base, CapabilitySet::default(), None)
+            .unwrap();
+        client.accept_capability_update(&update, &session).unwrap();
+
+        let notice = responder
+            .preemption_notice(base + 1, 200, Duration::from_secs(2))
+            .unwrap();
+        let mut forged = notice.clone();
+        forged.grace_ms = 60_000;
+        assert!(client.accept_preemption(&forged).is_err());
+        assert_eq!(
+            client.accept_preemption(&notice).unwrap(),
+            Duration::from_secs(2)
+        );
+        assert!(client.accept_preemption(&notice).is_err());
+        // Updates and notices share one sequence, so an older update is stale now.
+        let late = responder
+            .capability_update(base, CapabilitySet::default(), None)
+            .unwrap();
+        assert!(client.accept_capability_update(&late, &session).is_err());
+    }
+
+    #[tokio::test]
+    async fn verified_session_close_frees_the_device() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let (session, link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let mut channel = ReliableControlChannel::new(link);
+        let mut forged = client.close_notice(1, Some("done".into())).unwrap();
+        forged.reason = None;
+        channel
+            .notify(HandshakeMessage::SessionClose(forged))
+            .await
+            .unwrap();
+        client
+            .send(&mut channel, ControlOp::Identify, json!({}))
+            .await
+            .unwrap();
+        assert_eq!(device.identify_count(), 1);
+
+        client
+            .close(&mut channel, Some("done".into()))
+            .await
+            .unwrap();
+        let node = device.session().unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !matches!(node.state(), SessionState::Closed) {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn fragmented_frames_reassemble_on_the_device() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let (session, _link) = connect(&device).await.unwrap();
+        let profile = StreamProfile::auto().compile().unwrap();
+        session.set_stream_profile(profile.clone()).unwrap();
+        session.mark_streaming();
+        let stream =
+            AlnpStream::new(session, device.frame_transport(), profile).with_max_datagram(600);
+        let values: Vec<u16> = (0..512).map(|i| (i * 97) % 256).collect();
+        stream
+            .send(ChannelFormat::U8, &values, 100, None, None)
+            .unwrap();
+        let frames = device.frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].channels[..], values[..]);
+    }
+
+    #[tokio::test]
+    async fn serves_control_and_frames() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let (session, link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let mut channel = ReliableControlChannel::new(link);
+        client
+            .send(&mut channel, ControlOp::Identify, json!({}))
+            .await
+            .unwrap();
+        client
+            .send(&mut channel, ControlOp::SetMode, json!({"mode": "show"}))
+            .await
+            .unwrap();
+        assert_eq!(device.identify_count(), 1);
+        assert_eq!(device.mode().as_deref(), Some("show"));
+
+        let profile = StreamProfile::auto().compile().unwrap();
+        session.set_stream_profile(profile.clone()).unwrap();
+        session.mark_streaming();
+        let stream = AlnpStream::new(session, device.frame_transport(), profile);
+        stream
+            .send(ChannelFormat::U8, &[1, 2, 3], 100, None, None)
+            .unwrap();
+        assert_eq!(device.frames().len(), 1);
+        assert_eq!(device.frames()[0].channels[..], [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn plays_back_scripted_failures() {
+        let device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        device.inject(ScriptedFailure::RejectHandshake(ErrorCode::HandshakeReplay));
+        let err = connect(&device).await.unwrap_err();
+        assert_eq!(err.code(), Some(ErrorCode::HandshakeReplay));
+
+        device.inject(ScriptedFailure::RejectControl(
+            ErrorCode::ControlUnauthorized,
+        ));
+        device.inject(ScriptedFailure::DropFrames(2));
+        let (session, link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let mut channel = ReliableControlChannel::new(link);
+        let err = client
+            .send(&mut channel, ControlOp::Identify, json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some(ErrorCode::ControlUnauthorized));
+
+        let profile = StreamProfile::auto().compile().unwrap();
+        session.set_stream_profile(profile.clone()).unwrap();
+        session.mark_streaming();
+        let stream = AlnpStream::new(session, device.frame_transport(), profile);
+        for _ in 0..3 {
+            stream
+                .send(ChannelFormat::U8, &[0; 4], 100, None, None)
+                .unwrap();
+        }
+        let stats = device.stats();
+        assert_eq!(stats.frames_lost, 2);
+        assert_eq!(stats.frames_received, 1);
+    }
+
+    #[tokio::test]
+    async fn frames_delayed_past_their_deadline_are_late() {
+        let mut config = SimulatorConfig::new(identity("node"));
+        config.latency = Duration::from_millis(60);
+        let device = SimulatedDevice::new(config);
+        let (session, _link) = connect(&device).await.unwrap();
+        // A realtime frame is due one 44 Hz interval after it is sent.
+        let profile = StreamProfile::realtime().compile().unwrap();
+        session.set_stream_profile(profile.clone()).unwrap();
+        session.mark_streaming();
+        let stream = AlnpStream::new(session, device.frame_transport(), profile);
+        stream
+            .send(ChannelFormat::U8, &[0; 4], 100, None, None)
+            .unwrap();
+        assert_eq!(device.stats().frames_late, 1);
+        assert_eq!(device.session().unwrap().metrics().late_frames, 1);
+    }
+
+    #[tokio::test]
+    async fn lost_acks_are_retransmitted() {
+        let mut config = SimulatorConfig::new(identity("node"));
+        // Seed 7 loses three acks in a row first, within the channel's retransmit limit.
+        config.loss = 0.5;
+        config.seed = 7;
+        let device = SimulatedDevice::new(config);
+        let (session, link) = connect(&device).await.unwrap();
+        let client = control_client(&session);
+        let mut channel = ReliableControlChannel::new(link);
+        for _ in 0..3 {
+            client
+                .send(&mut channel, ControlOp::Identify, json!({}))
+                .await
+                .unwrap();
+        }
+        // Retransmissions of an already-answered sequence are not acted on again.
+        assert_eq!(device.identify_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn simulated_device_conforms() {
+        let mut device = SimulatedDevice::new(SimulatorConfig::new(identity("node")));
+        let credentials = device.credentials().clone();
+        let mut config = ConformanceConfig::new(identity("controller"));
+        config.response_timeout = Duration::from_millis(200);
+        let suite = ConformanceSuite::new(config, move || {
+            Ed25519Authenticator::new(credentials.clone())
+        });
+        let report = suite.run(&mut device).await;
+        assert!(report.passed(), "{}", report);
+    }
+}
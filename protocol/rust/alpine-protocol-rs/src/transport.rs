@@ -0,0 +1,572 @@
+//! UDP transport shared by the handshake, control, and streaming layers.
+//!
+//! Venue networks commonly run managed switches with QoS queues and several lighting
+//! VLANs. `TransportConfig` lets callers pin traffic to an interface, mark it with a
+//! DSCP class, and size socket buffers before any ALPINE datagram is sent.
+//! [`UdpTransport`] binds a socket with those options and carries handshake and control
+//! messages as well as stream frames, counting what it sends and receives.
+//! [`interfaces`] lists the local addresses to choose from, e.g. to discover on every
+//! network of a console that is on both the lighting and the office LAN.
+//! [`UringTransport`] batches a tick's frames into one submission for rigs with many
+//! universes.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::ops::Add;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+
+use crate::codec;
+use crate::handshake::{HandshakeError, HandshakeMessage, HandshakeTransport};
+use crate::stream::FrameTransport;
+
+/// DSCP class recommended for live streaming traffic (Expedited Forwarding).
+pub const DSCP_EXPEDITED_FORWARDING: u8 = 46;
+
+/// Largest handshake or control message a [`UdpTransport`] receives unless configured
+/// otherwise.
+pub const DEFAULT_MAX_MESSAGE: usize = 2048;
+
+/// Optional socket settings applied when a UDP transport is bound.
+///
+/// Every field defaults to `None`, which leaves the operating system default in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportConfig {
+    /// Network device to bind to (e.g. `eth1`). Supported on Linux, Android, and Fuchsia.
+    pub bind_device: Option<String>,
+    /// Outgoing IPv4 interface for multicast traffic.
+    pub multicast_interface_v4: Option<Ipv4Addr>,
+    /// Outgoing IPv6 interface index for multicast traffic.
+    pub multicast_interface_v6: Option<u32>,
+    /// Unicast and multicast TTL (IPv4) or hop limit (IPv6).
+    pub ttl: Option<u32>,
+    /// Differentiated Services code point (0-63) written into the IP header.
+    pub dscp: Option<u8>,
+    /// Kernel send buffer size in bytes.
+    pub send_buffer_size: Option<usize>,
+    /// Kernel receive buffer size in bytes.
+    pub recv_buffer_size: Option<usize>,
+    /// Path MTU toward the peer; stream datagrams larger than it allows are fragmented.
+    pub mtu: Option<usize>,
+}
+
+impl TransportConfig {
+    pub fn with_bind_device(mut self, device: impl Into<String>) -> Self {
+        self.bind_device = Some(device.into());
+        self
+    }
+
+    pub fn with_multicast_interface_v4(mut self, interface: Ipv4Addr) -> Self {
+        self.multicast_interface_v4 = Some(interface);
+        self
+    }
+
+    pub fn with_multicast_interface_v6(mut self, index: u32) -> Self {
+        self.multicast_interface_v6 = Some(index);
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
+    }
+
+    pub fn with_buffer_sizes(mut self, send: usize, recv: usize) -> Self {
+        self.send_buffer_size = Some(send);
+        self.recv_buffer_size = Some(recv);
+        self
+    }
+
+    pub fn with_mtu(mut self, mtu: usize) -> Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Largest UDP payload toward `remote` that fits the configured MTU: 28 bytes of
+    /// IPv4 and UDP headers less, or 48 for IPv6.
+    pub fn max_datagram(&self, remote: SocketAddr) -> Option<usize> {
+        let headers = match remote {
+            SocketAddr::V4(_) => 28,
+            SocketAddr::V6(_) => 48,
+        };
+        self.mtu.map(|mtu| mtu.saturating_sub(headers))
+    }
+
+    /// Creates a blocking UDP socket bound to `local` with every configured option applied.
+    pub fn bind_udp(&self, local: SocketAddr) -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::for_address(local), Type::DGRAM, Some(Protocol::UDP))?;
+        self.apply(&socket, local)?;
+        socket.bind(&local.into())?;
+        Ok(socket.into())
+    }
+
+    fn apply(&self, socket: &Socket, local: SocketAddr) -> io::Result<()> {
+        if let Some(device) = &self.bind_device {
+            bind_device(socket, device)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(dscp) = self.dscp {
+            if dscp > 63 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("dscp {} out of range (0-63)", dscp),
+                ));
+            }
+        }
+        // DSCP occupies the upper six bits of the TOS / traffic class byte.
+        let traffic_class = self.dscp.map(|dscp| u32::from(dscp) << 2);
+        match local {
+            SocketAddr::V4(_) => {
+                if let Some(interface) = &self.multicast_interface_v4 {
+                    socket.set_multicast_if_v4(interface)?;
+                }
+                if let Some(ttl) = self.ttl {
+                    socket.set_ttl_v4(ttl)?;
+                    socket.set_multicast_ttl_v4(ttl)?;
+                }
+                if let Some(tos) = traffic_class {
+                    socket.set_tos_v4(tos)?;
+                }
+            }
+            SocketAddr::V6(_) => {
+                if let Some(index) = self.multicast_interface_v6 {
+                    socket.set_multicast_if_v6(index)?;
+                }
+                if let Some(hops) = self.ttl {
+                    socket.set_unicast_hops_v6(hops)?;
+                    socket.set_multicast_hops_v6(hops)?;
+                }
+                if let Some(tclass) = traffic_class {
+                    set_tclass_v6(socket, tclass)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Datagram counters of a [`UdpTransport`], from [`UdpTransport::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransportStats {
+    pub datagrams_sent: u64,
+    pub bytes_sent: u64,
+    pub datagrams_received: u64,
+    pub bytes_received: u64,
+    /// Sends the socket refused, including frames dropped on a full send buffer.
+    pub send_errors: u64,
+    /// Datagrams that did not decode as a handshake or control message.
+    pub decode_errors: u64,
+}
+
+impl Add for TransportStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            datagrams_sent: self.datagrams_sent + other.datagrams_sent,
+            bytes_sent: self.bytes_sent + other.bytes_sent,
+            datagrams_received: self.datagrams_received + other.datagrams_received,
+            bytes_received: self.bytes_received + other.bytes_received,
+            send_errors: self.send_errors + other.send_errors,
+            decode_errors: self.decode_errors + other.decode_errors,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    datagrams_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    datagrams_received: AtomicU64,
+    bytes_received: AtomicU64,
+    send_errors: AtomicU64,
+    decode_errors: AtomicU64,
+}
+
+impl Counters {
+    fn sent(&self, result: &io::Result<usize>) {
+        match result {
+            Ok(len) => {
+                self.datagrams_sent.fetch_add(1, Ordering::Relaxed);
+                self.bytes_sent.fetch_add(*len as u64, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.send_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn received(&self, len: usize) {
+        self.datagrams_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+/// UDP socket toward one peer, bound with the options of a [`TransportConfig`].
+///
+/// Handshakes and the control plane exchange CBOR messages over it as a
+/// [`HandshakeTransport`]; streams send their frames through it as a [`FrameTransport`].
+/// Clones share the socket and its counters, so a stream's heartbeat task sends from the
+/// same port and one [`UdpTransport::stats`] covers both.
+#[derive(Debug, Clone)]
+pub struct UdpTransport {
+    socket: Arc<tokio::net::UdpSocket>,
+    peer: SocketAddr,
+    max_message: usize,
+    counters: Arc<Counters>,
+}
+
+impl UdpTransport {
+    /// Binds `local` with every option of `config` and connects to `peer`.
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn bind(local: SocketAddr, peer: SocketAddr, config: &TransportConfig) -> io::Result<Self> {
+        let socket = config.bind_udp(local)?;
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self::from_socket(
+            tokio::net::UdpSocket::from_std(socket)?,
+            peer,
+        ))
+    }
+
+    /// Wraps a socket the caller already bound, and usually connected, to `peer`.
+    pub fn from_socket(socket: tokio::net::UdpSocket, peer: SocketAddr) -> Self {
+        Self {
+            socket: Arc::new(socket),
+            peer,
+            max_message: DEFAULT_MAX_MESSAGE,
+            counters: Arc::default(),
+        }
+    }
+
+    /// Transport over the same socket toward `peer`, with counters of its own, so one
+    /// socket can carry the streams of many sessions.
+    pub fn to_peer(&self, peer: SocketAddr) -> Self {
+        Self {
+            socket: self.socket.clone(),
+            peer,
+            max_message: self.max_message,
+            counters: Arc::default(),
+        }
+    }
+
+    /// Sets the largest handshake or control message received; longer datagrams are
+    /// truncated and fail to decode.
+    pub fn with_max_message(mut self, max_message: usize) -> Self {
+        self.max_message = max_message;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Sends one datagram to the peer.
+    pub async fn send_datagram(&self, bytes: &[u8]) -> io::Result<usize> {
+        let sent = self.socket.send_to(bytes, self.peer).await;
+        self.counters.sent(&sent);
+        sent
+    }
+
+    /// Sends one datagram without waiting, for contexts that cannot await such as `Drop`.
+    pub fn try_send_datagram(&self, bytes: &[u8]) -> io::Result<usize> {
+        // Straight to the socket: Tokio's `try_send_to` also refuses while the runtime
+        // has not yet seen the socket become writable.
+        let sent = SockRef::from(&*self.socket).send_to(bytes, &self.peer.into());
+        self.counters.sent(&sent);
+        sent
+    }
+
+    /// Waits for the next datagram, such as an echo reply, and returns its length.
+    pub async fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let (len, _) = self.socket.recv_from(buf).await?;
+        self.counters.received(len);
+        Ok(len)
+    }
+
+    pub fn stats(&self) -> TransportStats {
+        let counters = &self.counters;
+        TransportStats {
+            datagrams_sent: counters.datagrams_sent.load(Ordering::Relaxed),
+            bytes_sent: counters.bytes_sent.load(Ordering::Relaxed),
+            datagrams_received: counters.datagrams_received.load(Ordering::Relaxed),
+            bytes_received: counters.bytes_received.load(Ordering::Relaxed),
+            send_errors: counters.send_errors.load(Ordering::Relaxed),
+            decode_errors: counters.decode_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[async_trait]
+impl HandshakeTransport for UdpTransport {
+    async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        let bytes = codec::to_vec(&msg).map_err(|e| HandshakeError::Transport(e.to_string()))?;
+        self.send_datagram(&bytes)
+            .await
+            .map_err(|e| HandshakeError::Transport(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+        let mut buf = vec![0u8; self.max_message];
+        let len = self
+            .recv_datagram(&mut buf)
+            .await
+            .map_err(|e| HandshakeError::Transport(e.to_string()))?;
+        codec::decode_untrusted(&buf[..len]).map_err(|e| {
+            self.counters.decode_errors.fetch_add(1, Ordering::Relaxed);
+            HandshakeError::Transport(e.to_string())
+        })
+    }
+}
+
+/// Frames never wait for the socket: one that does not fit the send buffer is dropped
+/// and counted in [`TransportStats::send_errors`], as a late frame is of no use.
+impl FrameTransport for UdpTransport {
+    fn send_frame(&self, bytes: &[u8]) -> Result<(), String> {
+        self.try_send_datagram(bytes)
+            .map(|_| ())
+            .map_err(|e| format!("udp stream send: {}", e))
+    }
+}
+
+mod uring;
+
+pub use uring::{UringTransport, URING_ENTRIES};
+
+/// One address of a local network interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddr {
+    /// Interface name, e.g. `eth1`.
+    pub name: String,
+    /// Interface index, as used for IPv6 scope ids and multicast interfaces.
+    pub index: u32,
+    pub addr: IpAddr,
+    pub netmask: Option<IpAddr>,
+    /// Directed broadcast address of IPv4 networks that support broadcast.
+    pub broadcast: Option<Ipv4Addr>,
+    pub up: bool,
+    pub loopback: bool,
+}
+
+/// Addresses of every local network interface, one entry per address, so an interface
+/// with IPv4 and IPv6 addresses appears more than once. Supported on Unix platforms.
+#[cfg(unix)]
+pub fn interfaces() -> io::Result<Vec<InterfaceAddr>> {
+    use std::ffi::CStr;
+
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: `list` is a valid out-pointer; it is freed below once read.
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut found = Vec::new();
+    let mut entry = list;
+    while !entry.is_null() {
+        // SAFETY: getifaddrs returned a linked list that stays valid until freed.
+        let ifa = unsafe { &*entry };
+        entry = ifa.ifa_next;
+        // SAFETY: address pointers are null or point at a sockaddr of their family.
+        let Some(addr) = (unsafe { sockaddr_ip(ifa.ifa_addr) }) else {
+            continue;
+        };
+        // SAFETY: as above.
+        let netmask = unsafe { sockaddr_ip(ifa.ifa_netmask) };
+        let flags = ifa.ifa_flags as libc::c_int;
+        let broadcast = match (addr, netmask) {
+            (IpAddr::V4(addr), Some(IpAddr::V4(mask))) if flags & libc::IFF_BROADCAST != 0 => {
+                Some(Ipv4Addr::from(u32::from(addr) | !u32::from(mask)))
+            }
+            _ => None,
+        };
+        // SAFETY: every entry has a NUL-terminated name.
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) };
+        found.push(InterfaceAddr {
+            name: name.to_string_lossy().into_owned(),
+            // SAFETY: as above.
+            index: unsafe { libc::if_nametoindex(ifa.ifa_name) },
+            addr,
+            netmask,
+            broadcast,
+            up: flags & libc::IFF_UP != 0,
+            loopback: flags & libc::IFF_LOOPBACK != 0,
+        });
+    }
+    // SAFETY: `list` came from getifaddrs and is not used afterwards.
+    unsafe { libc::freeifaddrs(list) };
+    Ok(found)
+}
+
+#[cfg(not(unix))]
+pub fn interfaces() -> io::Result<Vec<InterfaceAddr>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "interface enumeration is not supported on this platform",
+    ))
+}
+
+/// The IP address in `addr`, if it is an IPv4 or IPv6 socket address.
+///
+/// # Safety
+///
+/// `addr` must be null or point at a valid `sockaddr` of the family it names.
+#[cfg(unix)]
+unsafe fn sockaddr_ip(addr: *const libc::sockaddr) -> Option<IpAddr> {
+    if addr.is_null() {
+        return None;
+    }
+    match i32::from((*addr).sa_family) {
+        libc::AF_INET => {
+            let addr = &*(addr as *const libc::sockaddr_in);
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                addr.sin_addr.s_addr,
+            ))))
+        }
+        libc::AF_INET6 => {
+            let addr = &*(addr as *const libc::sockaddr_in6);
+            Some(IpAddr::V6(addr.sin6_addr.s6_addr.into()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: &Socket, device: &str) -> io::Result<()> {
+    socket.bind_device(Some(device.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(_socket: &Socket, device: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "binding to device {} is not supported on this platform",
+            device
+        ),
+    ))
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "macos",
+))]
+fn set_tclass_v6(socket: &Socket, tclass: u32) -> io::Result<()> {
+    socket.set_tclass_v6(tclass)
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "macos",
+)))]
+fn set_tclass_v6(_socket: &Socket, _tclass: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "IPv6 traffic class is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_binds_plain_socket() {
+        let socket = TransportConfig::default()
+            .bind_udp("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        assert!(socket.local_addr().unwrap().port() > 0);
+    }
+
+    #[test]
+    fn applies_dscp_and_ttl() {
+        let config = TransportConfig::default()
+            .with_dscp(DSCP_EXPEDITED_FORWARDING)
+            .with_ttl(4);
+        let socket: Socket = config
+            .bind_udp("127.0.0.1:0".parse().unwrap())
+            .unwrap()
+            .into();
+        assert_eq!(
+            socket.tos_v4().unwrap(),
+            u32::from(DSCP_EXPEDITED_FORWARDING) << 2
+        );
+        assert_eq!(socket.ttl_v4().unwrap(), 4);
+    }
+
+    #[test]
+    fn lists_the_loopback_interface() {
+        let loopback = interfaces()
+            .unwrap()
+            .into_iter()
+            .find(|interface| interface.addr == IpAddr::V4(Ipv4Addr::LOCALHOST))
+            .unwrap();
+        assert!(loopback.loopback && loopback.up);
+        assert!(loopback.index > 0);
+        assert_eq!(
+            loopback.netmask,
+            Some(IpAddr::V4(Ipv4Addr::new(255, 0, 0, 0)))
+        );
+    }
+
+    #[tokio::test]
+    async fn carries_messages_and_frames_and_counts_them() {
+        let local = "127.0.0.1:0".parse().unwrap();
+        let node = tokio::net::UdpSocket::bind(local).await.unwrap();
+        let config = TransportConfig::default().with_dscp(DSCP_EXPEDITED_FORWARDING);
+        let mut transport = UdpTransport::bind(local, node.local_addr().unwrap(), &config).unwrap();
+        let mut node = UdpTransport::from_socket(node, transport.local_addr().unwrap());
+
+        let keepalive = HandshakeMessage::Keepalive(crate::messages::Keepalive {
+            message_type: crate::messages::MessageType::Keepalive,
+            session_id: uuid::Uuid::new_v4(),
+            tick_ms: 7,
+            stats: None,
+        });
+        transport.send(keepalive.clone()).await.unwrap();
+        assert_eq!(node.recv().await.unwrap(), keepalive);
+
+        transport.clone().send_frame(b"frame").unwrap();
+        assert!(node.recv().await.is_err());
+
+        let stats = transport.stats();
+        assert_eq!(stats.datagrams_sent, 2);
+        assert_eq!(stats.send_errors, 0);
+        let received = node.stats();
+        assert_eq!(received.datagrams_received, 2);
+        assert_eq!(received.bytes_received, stats.bytes_sent);
+        assert_eq!(received.decode_errors, 1);
+    }
+
+    #[test]
+    fn rejects_out_of_range_dscp() {
+        let err = TransportConfig::default()
+            .with_dscp(64)
+            .bind_udp("127.0.0.1:0".parse().unwrap())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,198 @@
+//! Batched frame sends for controllers and gateways with many universes.
+//!
+//! A [`UdpTransport`] makes one `sendto` system call per frame, so a tick of a few
+//! hundred universes costs as many kernel crossings. A [`UringTransport`] queues the
+//! frames a stream sends and hands a whole tick to the kernel when the caller flushes:
+//! with the `io-uring` feature on Linux as one io_uring submission, elsewhere, or when
+//! the kernel refuses to set up a ring, as the same per-frame sends the [`UdpTransport`]
+//! makes.
+
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use super::UdpTransport;
+use crate::stream::FrameTransport;
+
+/// Submission entries of a ring; larger batches go out in several submissions.
+pub const URING_ENTRIES: u32 = 256;
+
+/// Stream transport that queues frames until [`UringTransport::flush`].
+///
+/// Clones share the queue and the ring, so a stream's heartbeat goes out with the next
+/// flush. Frames, sent or refused, are counted in the [`UdpTransport::stats`] of the
+/// wrapped transport.
+#[derive(Clone)]
+pub struct UringTransport {
+    udp: UdpTransport,
+    batch: Arc<Mutex<Batch>>,
+}
+
+struct Batch {
+    frames: Vec<Vec<u8>>,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    ring: Option<io_uring::IoUring>,
+}
+
+impl UringTransport {
+    /// Queues frames for `udp`'s socket and peer, submitting them through io_uring when
+    /// the feature is on and the kernel sets up a ring.
+    pub fn new(udp: UdpTransport) -> Self {
+        Self {
+            udp,
+            batch: Arc::new(Mutex::new(Batch {
+                frames: Vec::new(),
+                #[cfg(all(feature = "io-uring", target_os = "linux"))]
+                ring: io_uring::IoUring::new(URING_ENTRIES).ok(),
+            })),
+        }
+    }
+
+    /// Whether flushes submit through io_uring rather than sending frame by frame.
+    pub fn is_uring(&self) -> bool {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            self.batch.lock().ring.is_some()
+        }
+        #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+        {
+            false
+        }
+    }
+
+    /// The wrapped transport, e.g. for its stats or to receive echo replies.
+    pub fn udp(&self) -> &UdpTransport {
+        &self.udp
+    }
+
+    /// Frames queued since the last flush.
+    pub fn queued(&self) -> usize {
+        self.batch.lock().frames.len()
+    }
+
+    /// Sends every queued frame and returns how many the socket took.
+    ///
+    /// Frames the socket refuses, e.g. on a full send buffer, are dropped and counted
+    /// as send errors. Fails only when the ring itself fails, in which case the frames
+    /// of that submission are lost.
+    pub fn flush(&self) -> io::Result<usize> {
+        let mut batch = self.batch.lock();
+        let frames = std::mem::take(&mut batch.frames);
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(ring) = batch.ring.as_mut() {
+            return self.submit(ring, &frames);
+        }
+        Ok(frames
+            .iter()
+            .filter(|frame| self.udp.try_send_datagram(frame).is_ok())
+            .count())
+    }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn submit(&self, ring: &mut io_uring::IoUring, frames: &[Vec<u8>]) -> io::Result<usize> {
+        use io_uring::{opcode, types};
+        use std::os::fd::AsRawFd;
+
+        let fd = types::Fd(self.udp.socket.as_raw_fd());
+        let peer = socket2::SockAddr::from(self.udp.peer);
+        let mut sent = 0;
+        for chunk in frames.chunks(URING_ENTRIES as usize) {
+            // The kernel reads these while the submission is in flight, so they stay put
+            // until every completion of the chunk is reaped.
+            let mut iovecs: Vec<libc::iovec> = chunk
+                .iter()
+                .map(|frame| libc::iovec {
+                    iov_base: frame.as_ptr() as *mut libc::c_void,
+                    iov_len: frame.len(),
+                })
+                .collect();
+            let headers: Vec<libc::msghdr> = iovecs
+                .iter_mut()
+                .map(|iovec| {
+                    // SAFETY: an all-zero msghdr is valid: no control data, no flags.
+                    let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+                    header.msg_name = peer.as_ptr() as *mut libc::c_void;
+                    header.msg_namelen = peer.len();
+                    header.msg_iov = iovec;
+                    header.msg_iovlen = 1;
+                    header
+                })
+                .collect();
+            {
+                let mut submission = ring.submission();
+                for (index, header) in headers.iter().enumerate() {
+                    let entry = opcode::SendMsg::new(fd, header)
+                        .build()
+                        .user_data(index as u64);
+                    // SAFETY: the header, its iovec, the peer address, and the frame
+                    // outlive the submission, as completions are reaped below.
+                    unsafe { submission.push(&entry) }
+                        .map_err(|_| io::Error::other("io_uring submission queue full"))?;
+                }
+            }
+            ring.submit_and_wait(chunk.len())?;
+            for completion in ring.completion() {
+                let result = match completion.result() {
+                    len if len >= 0 => Ok(len as usize),
+                    errno => Err(io::Error::from_raw_os_error(-errno)),
+                };
+                sent += usize::from(result.is_ok());
+                self.udp.counters.sent(&result);
+            }
+        }
+        Ok(sent)
+    }
+}
+
+impl fmt::Debug for UringTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringTransport")
+            .field("udp", &self.udp)
+            .field("uring", &self.is_uring())
+            .field("queued", &self.queued())
+            .finish()
+    }
+}
+
+/// Queues the frame for the next [`UringTransport::flush`]; never fails.
+impl FrameTransport for UringTransport {
+    fn send_frame(&self, bytes: &[u8]) -> Result<(), String> {
+        self.batch.lock().frames.push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::TransportConfig;
+
+    #[tokio::test]
+    async fn flush_sends_queued_frames_in_order() {
+        let receiver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let udp = UdpTransport::bind(
+            "127.0.0.1:0".parse().unwrap(),
+            receiver.local_addr().unwrap(),
+            &TransportConfig::default(),
+        )
+        .unwrap();
+        let transport = UringTransport::new(udp);
+        for index in 0..3u8 {
+            transport.send_frame(&[index; 8]).unwrap();
+        }
+        assert_eq!(transport.queued(), 3);
+        assert_eq!(transport.flush().unwrap(), 3);
+        assert_eq!(transport.queued(), 0);
+
+        let mut buf = [0u8; 16];
+        for index in 0..3u8 {
+            let len = receiver.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], &[index; 8]);
+        }
+        let stats = transport.udp().stats();
+        assert_eq!((stats.datagrams_sent, stats.bytes_sent), (3, 24));
+        assert_eq!(transport.flush().unwrap(), 0);
+    }
+}
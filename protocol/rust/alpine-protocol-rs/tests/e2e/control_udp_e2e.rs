@@ -1,10 +1,10 @@
 use std::error::Error;
 
-use serde_cbor;
 use serde_json::json;
 use tokio::net::UdpSocket;
 
 use alpine::control::{ControlClient, ControlCrypto, ControlResponder};
+use alpine::crypto::nonce::NonceLane;
 use alpine::handshake::HandshakeError;
 use alpine::messages::{Acknowledge, ControlEnvelope, ControlOp};
 use uuid::Uuid;
@@ -13,7 +13,13 @@ use alpine::e2e_common::run_udp_handshake;
 
 fn verify_ack(ack: &Acknowledge, crypto: &ControlCrypto) -> Result<(), HandshakeError> {
     let payload = json!({"ok": ack.ok, "detail": ack.detail});
-    crypto.verify_mac(ack.seq, &ack.session_id, &payload, &ack.mac)
+    crypto.verify_mac(
+        NonceLane::ControlReply,
+        ack.seq,
+        &ack.session_id,
+        &payload,
+        &ack.mac,
+    )
 }
 
 #[tokio::test]
@@ -42,10 +48,10 @@ async fn control_udp_e2e_phase2() -> Result<(), Box<dyn Error>> {
     let node_task = tokio::spawn(async move {
         let mut buf = vec![0u8; 2048];
         let (len, src) = node_socket.recv_from(&mut buf).await?;
-        let envelope: ControlEnvelope = serde_cbor::from_slice(&buf[..len])?;
+        let envelope: ControlEnvelope = alpine::codec::from_slice(&buf[..len])?;
         responder.verify(&envelope)?;
         let ack = responder.ack(envelope.seq, true, Some("ok".into()))?;
-        let ack_bytes = serde_cbor::to_vec(&ack)?;
+        let ack_bytes = alpine::codec::to_vec(&ack)?;
         node_socket.send_to(&ack_bytes, src).await?;
         Ok::<_, Box<dyn Error + Send + Sync>>(())
     });
@@ -53,11 +59,11 @@ async fn control_udp_e2e_phase2() -> Result<(), Box<dyn Error>> {
     let controller_task = tokio::spawn(async move {
         let payload = json!({"action": "lock"});
         let envelope = controller_control.envelope(1, ControlOp::Identify, payload)?;
-        let env_bytes = serde_cbor::to_vec(&envelope)?;
+        let env_bytes = alpine::codec::to_vec(&envelope)?;
         controller_socket.send_to(&env_bytes, node_addr).await?;
         let mut buf = vec![0u8; 2048];
         let (len, _) = controller_socket.recv_from(&mut buf).await?;
-        let ack: Acknowledge = serde_cbor::from_slice(&buf[..len])?;
+        let ack: Acknowledge = alpine::codec::from_slice(&buf[..len])?;
         verify_ack(&ack, &controller_crypto_for_ack)?;
         Ok::<_, Box<dyn Error + Send + Sync>>(())
     });
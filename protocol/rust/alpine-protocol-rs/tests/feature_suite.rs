@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::sync::{Arc, Mutex};
 
@@ -9,16 +10,43 @@ use serde_json::json;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
-use alpine::control::{ControlClient, ControlCrypto, ControlResponder};
-use alpine::crypto::X25519KeyExchange;
-use alpine::discovery::DiscoveryResponder;
-use alpine::handshake::{HandshakeContext, HandshakeError, HandshakeMessage, HandshakeTransport};
+use alpine::codec;
+use alpine::control::RateLimit;
+use alpine::control::{
+    ControlClient, ControlCrypto, ControlDispatcher, ControlHandlers, ControlResponder,
+};
+use alpine::crypto::enrollment::{Authority, AuthorityKind};
+use alpine::crypto::identity::{self, NodeCredentials};
+use alpine::crypto::nonce::NonceLane;
+use alpine::crypto::ratchet::StreamRatchet;
+use alpine::crypto::revocation::{RevocationList, RevocationStore};
+use alpine::crypto::SUPPORTED_SUITES;
+use alpine::crypto::{compute_mac, verify_mac, SuiteId, X25519KeyExchange};
+use alpine::device::DeviceServer;
+use alpine::discovery::{DiscoveryConfig, DiscoveryResponder};
+use alpine::handshake::transport::ReliableControlChannel;
+use alpine::handshake::{
+    compare_firmware, FirmwareMismatch, FirmwarePolicy, HandshakeContext, HandshakeEntropy,
+    HandshakeError, HandshakeMessage, HandshakeTransport,
+};
 use alpine::messages::{
     CapabilitySet, ChannelFormat, ControlOp, DeviceIdentity, ErrorCode, FrameEnvelope, MessageType,
+    SealedFrame,
 };
+use alpine::mirror::OutputMirror;
 use alpine::profile::StreamProfile;
-use alpine::session::{AlnpSession, JitterStrategy, StaticKeyAuthenticator};
-use alpine::stream::{AlnpStream, FrameTransport};
+use alpine::session::limits::{LimitWarning, SessionLimit, SessionLimits};
+use alpine::session::migration::{MigrationTicket, SessionSnapshot};
+use alpine::session::quota::SessionQuotas;
+use alpine::session::registry::SessionRegistry;
+use alpine::session::resume::ResumptionCache;
+use alpine::session::{
+    AlnpSession, Ed25519Authenticator, JitterStrategy, StaticKeyAuthenticator, TimingConfig,
+};
+use alpine::stream::{
+    AdaptationEvent, AlnpStream, FrameTransport, NetworkConditions, RecoveryEvent, RecoveryReason,
+    StreamError, StreamEvent,
+};
 
 /// Simple transport bridge used to run two handshake participants in tests.
 struct PipeTransport {
@@ -60,6 +88,28 @@ impl HandshakeTransport for PipeTransport {
     }
 }
 
+/// Pipe end that also logs every message it sends, in order, to a shared transcript.
+struct RecordingPipe {
+    inner: PipeTransport,
+    role: &'static str,
+    transcript: Arc<Mutex<Vec<(&'static str, HandshakeMessage)>>>,
+}
+
+#[async_trait]
+impl HandshakeTransport for RecordingPipe {
+    async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        self.transcript
+            .lock()
+            .unwrap()
+            .push((self.role, msg.clone()));
+        self.inner.send(msg).await
+    }
+
+    async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+        self.inner.recv().await
+    }
+}
+
 fn make_identity(name: &str) -> DeviceIdentity {
     let uuid = Uuid::new_v4();
     DeviceIdentity {
@@ -72,6 +122,18 @@ fn make_identity(name: &str) -> DeviceIdentity {
 }
 
 async fn create_sessions() -> (AlnpSession, AlnpSession) {
+    let (controller, node) =
+        create_sessions_with(HandshakeContext::default(), HandshakeContext::default()).await;
+    (controller.unwrap(), node.unwrap())
+}
+
+async fn create_sessions_with(
+    controller_context: HandshakeContext,
+    node_context: HandshakeContext,
+) -> (
+    Result<AlnpSession, HandshakeError>,
+    Result<AlnpSession, HandshakeError>,
+) {
     let (mut controller_transport, mut node_transport) = PipeTransport::pair();
     let controller_task = tokio::spawn(async move {
         AlnpSession::connect(
@@ -79,7 +141,7 @@ async fn create_sessions() -> (AlnpSession, AlnpSession) {
             CapabilitySet::default(),
             StaticKeyAuthenticator::default(),
             X25519KeyExchange::new(),
-            HandshakeContext::default(),
+            controller_context,
             &mut controller_transport,
         )
         .await
@@ -90,13 +152,13 @@ async fn create_sessions() -> (AlnpSession, AlnpSession) {
             CapabilitySet::default(),
             StaticKeyAuthenticator::default(),
             X25519KeyExchange::new(),
-            HandshakeContext::default(),
+            node_context,
             &mut node_transport,
         )
         .await
     });
     let (ctrl_res, node_res) = tokio::join!(controller_task, node_task);
-    (ctrl_res.unwrap().unwrap(), node_res.unwrap().unwrap())
+    (ctrl_res.unwrap(), node_res.unwrap())
 }
 
 #[derive(Clone)]
@@ -167,9 +229,49 @@ async fn control_mac_roundtrip() {
     let ack_payload = json!({"ok": true, "detail": "ok"});
     let expected_mac = responder
         .crypto
-        .mac_for_payload(ack.seq, &session_id, &ack_payload)
+        .mac_for_payload(NonceLane::ControlReply, ack.seq, &session_id, &ack_payload)
         .unwrap();
     assert_eq!(expected_mac, ack.mac);
+    // The ack shares the envelope's seq but not its nonce.
+    let request_lane = responder
+        .crypto
+        .mac_for_payload(
+            NonceLane::ControlRequest,
+            ack.seq,
+            &session_id,
+            &ack_payload,
+        )
+        .unwrap();
+    assert_ne!(request_lane, ack.mac);
+}
+
+#[tokio::test]
+async fn control_messages_do_not_verify_in_another_session() {
+    let (controller, node) = create_sessions().await;
+    let (other, _) = create_sessions().await;
+    let keys = controller.keys().unwrap();
+    let node_keys = node.keys().unwrap();
+    let other_binding = other.keys().unwrap().channel_binding;
+    // Both ends derive the binding from the same handshake.
+    assert_eq!(keys.channel_binding, node_keys.channel_binding);
+    assert_ne!(keys.channel_binding, [0; 32]);
+    assert_ne!(keys.channel_binding, other_binding);
+
+    let session_id = controller.established().unwrap().session_id;
+    let client = ControlClient::new(Uuid::new_v4(), session_id, ControlCrypto::new(keys.clone()));
+    let envelope = client.envelope(1, ControlOp::Identify, json!({})).unwrap();
+    ControlResponder::new(session_id, ControlCrypto::new(node_keys))
+        .verify(&envelope)
+        .unwrap();
+
+    // The same keys and session id under another handshake's binding refuse it.
+    let mut spliced = keys;
+    spliced.channel_binding = other_binding;
+    assert!(
+        ControlResponder::new(session_id, ControlCrypto::new(spliced))
+            .verify(&envelope)
+            .is_err()
+    );
 }
 
 #[tokio::test]
@@ -180,20 +282,622 @@ async fn streaming_frames_hold_last_when_requested() {
     let profile = StreamProfile::auto().compile().unwrap();
     let stream = AlnpStream::new(controller.clone(), transport.clone(), profile);
     stream
-        .send(ChannelFormat::U8, vec![10, 20], 5, None, None)
-        .unwrap();
-    stream
-        .send(ChannelFormat::U8, Vec::new(), 5, None, None)
+        .send(ChannelFormat::U8, &[10, 20], 5, None, None)
         .unwrap();
+    stream.send(ChannelFormat::U8, &[], 5, None, None).unwrap();
     let snapshots = transport.snapshots();
     assert_eq!(snapshots.len(), 2);
-    let first: FrameEnvelope = serde_cbor::from_slice(&snapshots[0]).unwrap();
-    let second: FrameEnvelope = serde_cbor::from_slice(&snapshots[1]).unwrap();
-    assert_eq!(first.channels, vec![10, 20]);
+    let first: FrameEnvelope = alpine::codec::from_slice(&snapshots[0]).unwrap();
+    let second: FrameEnvelope = alpine::codec::from_slice(&snapshots[1]).unwrap();
+    assert_eq!(first.channels[..], [10, 20]);
     assert_eq!(second.channels, first.channels);
     assert_eq!(first.message_type, MessageType::AlpineFrame);
 }
 
+#[tokio::test]
+async fn encrypted_streams_rekey_at_each_keyframe() {
+    let (controller, node) = create_sessions().await;
+    let transport = RecordingTransport::new();
+    let profile = StreamProfile::auto().compile().unwrap();
+    let stream = AlnpStream::new(controller.clone(), transport.clone(), profile.clone())
+        .with_encryption()
+        .unwrap();
+    assert_eq!(stream.epoch(), Some(0));
+    for level in 0..30u16 {
+        stream
+            .send(ChannelFormat::U8, &[level], 5, None, None)
+            .unwrap();
+    }
+    let first_epoch = stream.epoch().unwrap();
+    assert!(first_epoch >= 2);
+
+    // A restarted stream carries on with the session's epochs and nonces.
+    let restarted = AlnpStream::new(controller, transport.clone(), profile)
+        .with_encryption()
+        .unwrap();
+    assert_eq!(restarted.epoch(), Some(first_epoch));
+    for level in 0..5u16 {
+        restarted
+            .send(ChannelFormat::U8, &[level], 5, None, None)
+            .unwrap();
+    }
+    assert!(restarted.epoch().unwrap() >= first_epoch);
+
+    let mut ratchet = StreamRatchet::receiver(&node.keys().unwrap()).unwrap();
+    let mut epoch = 0;
+    let mut nonces = HashSet::new();
+    for bytes in transport.snapshots() {
+        let sealed: SealedFrame = codec::from_slice(&bytes).unwrap();
+        assert_eq!(sealed.message_type, MessageType::AlpineSealedFrame);
+        assert!(nonces.insert((sealed.epoch, sealed.seq)));
+        let frame: FrameEnvelope = codec::from_slice(&sealed.open(&mut ratchet).unwrap()).unwrap();
+        // Each new epoch starts with a keyframe.
+        let keyframe = frame.adaptation_info().unwrap().force_keyframe;
+        assert_eq!(sealed.epoch != epoch, keyframe);
+        epoch = sealed.epoch;
+    }
+    assert_eq!(epoch, restarted.epoch().unwrap());
+}
+
+#[tokio::test]
+async fn session_metrics_track_handshake_state_and_frames() {
+    let (controller, _) = create_sessions().await;
+    let stream = AlnpStream::new(
+        controller.clone(),
+        RecordingTransport::new(),
+        StreamProfile::auto().compile().unwrap(),
+    );
+    controller.mark_streaming();
+    for _ in 0..3 {
+        stream.send(ChannelFormat::U8, &[1], 5, None, None).unwrap();
+    }
+    controller.record_keepalive_rtt(std::time::Duration::from_millis(4));
+    controller.fail("session timeout".into());
+
+    let metrics = controller.metrics();
+    assert_eq!(metrics.state, "failed");
+    assert!(metrics.handshake_duration.is_some());
+    assert!(metrics.time_in_state.contains_key("handshake"));
+    assert!(metrics.time_in_state.contains_key("streaming"));
+    assert_eq!(metrics.frames_sent, 3);
+    assert_eq!(metrics.frames_received, 0);
+    assert_eq!(metrics.keepalive_rtt.samples, 1);
+    assert_eq!(metrics.last_error.as_deref(), Some("session timeout"));
+}
+
+#[tokio::test]
+async fn stream_publishes_recovery_and_adaptation_events() {
+    let (controller, _) = create_sessions().await;
+    let profile = StreamProfile::auto().compile().unwrap();
+    let stream = AlnpStream::new(controller, RecordingTransport::new(), profile);
+    let mut events = stream.subscribe_events();
+
+    let mut conditions = NetworkConditions::new();
+    conditions.record_frame(1, 0, 0);
+    conditions.record_frame(5, 1_000, 0);
+    stream.observe_network_conditions(&conditions);
+
+    assert_eq!(
+        events.try_recv().unwrap(),
+        StreamEvent::Recovery {
+            universe: 0,
+            event: RecoveryEvent::RecoveryStarted(RecoveryReason::BurstLoss),
+        }
+    );
+    assert_eq!(
+        events.try_recv().unwrap(),
+        StreamEvent::Adaptation {
+            universe: 0,
+            event: AdaptationEvent::KeyframeCadenceIncreased,
+        }
+    );
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn frame_limit_warns_then_requires_rehandshake() {
+    let (controller, _) = create_sessions().await;
+    controller.set_limits(SessionLimits::new(None, Some(5), 60).unwrap());
+    let profile = StreamProfile::auto().compile().unwrap();
+    let stream = AlnpStream::new(controller.clone(), RecordingTransport::new(), profile);
+    let mut events = stream.subscribe_events();
+
+    for _ in 0..5 {
+        stream.send(ChannelFormat::U8, &[1], 5, None, None).unwrap();
+    }
+    assert_eq!(
+        events.try_recv().unwrap(),
+        StreamEvent::SessionLimit(LimitWarning::Frames { remaining: 2 })
+    );
+    assert!(events.try_recv().is_err());
+    assert!(matches!(
+        stream.send(ChannelFormat::U8, &[1], 5, None, None),
+        Err(StreamError::RekeyRequired(SessionLimit::Frames))
+    ));
+
+    let (fresh, _) = create_sessions().await;
+    assert_eq!(fresh.frames_on_key(), 0);
+}
+
+#[tokio::test]
+async fn impaired_universe_does_not_degrade_others() {
+    let (controller, _) = create_sessions().await;
+    let transport = RecordingTransport::new();
+    let profile = StreamProfile::auto().compile().unwrap();
+    let stream = AlnpStream::new(controller, transport.clone(), profile);
+
+    stream.record_universe_arrival(1, 1, 0, 0);
+    stream.record_universe_arrival(1, 2, 1_000, 0);
+    stream.record_universe_arrival(2, 1, 0, 0);
+    stream.record_universe_arrival(2, 6, 1_000, 0);
+
+    let healthy = stream.universe_health(1).unwrap();
+    assert!(healthy.recovery.is_none());
+    let impaired = stream.universe_health(2).unwrap();
+    assert_eq!(impaired.recovery, Some(RecoveryReason::BurstLoss));
+
+    let health = stream.health();
+    assert_eq!(health.universes.len(), 2);
+    assert_eq!(health.recovering, 1);
+    assert!(health.worst_loss_ratio > 0.0);
+
+    stream
+        .send_universe(1, ChannelFormat::U8, &[1], 5, None, None)
+        .unwrap();
+    stream
+        .send_universe(2, ChannelFormat::U8, &[2], 5, None, None)
+        .unwrap();
+    let snapshots = transport.snapshots();
+    let first: FrameEnvelope = alpine::codec::from_slice(&snapshots[0]).unwrap();
+    let second: FrameEnvelope = alpine::codec::from_slice(&snapshots[1]).unwrap();
+    assert_eq!(first.universe, 1);
+    assert!(first
+        .metadata
+        .is_none_or(|m| !m.contains_key("alpine_recovery")));
+    assert_eq!(second.universe, 2);
+    assert!(second.metadata.unwrap().contains_key("alpine_recovery"));
+}
+
+#[tokio::test]
+async fn reliable_control_send_is_verified_and_acked() {
+    let (controller, node) = create_sessions().await;
+    let session_id = controller.established().unwrap().session_id;
+    let client = ControlClient::new(
+        Uuid::new_v4(),
+        session_id,
+        ControlCrypto::new(controller.keys().unwrap()),
+    );
+    let responder = ControlResponder::new(session_id, ControlCrypto::new(node.keys().unwrap()));
+    let (controller_transport, mut node_transport) = PipeTransport::pair();
+    let node_task = tokio::spawn(async move {
+        for _ in 0..2 {
+            let HandshakeMessage::Control(env) = node_transport.recv().await.unwrap() else {
+                panic!("expected control envelope");
+            };
+            responder.verify(&env).unwrap();
+            let ack = responder.ack(env.seq, true, None).unwrap();
+            node_transport
+                .send(HandshakeMessage::Ack(ack))
+                .await
+                .unwrap();
+        }
+    });
+
+    let mut channel = ReliableControlChannel::new(controller_transport);
+    let first = client
+        .send(&mut channel, ControlOp::Identify, json!({}))
+        .await
+        .unwrap();
+    let second = client
+        .send(&mut channel, ControlOp::GetStatus, json!({}))
+        .await
+        .unwrap();
+    assert_eq!((first.seq, second.seq), (1, 2));
+    node_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn unknown_control_op_fails_with_error_envelope() {
+    let (controller, node) = create_sessions().await;
+    let session_id = controller.established().unwrap().session_id;
+    let client = ControlClient::new(
+        Uuid::new_v4(),
+        session_id,
+        ControlCrypto::new(controller.keys().unwrap()),
+    );
+    let responder = ControlResponder::new(session_id, ControlCrypto::new(node.keys().unwrap()));
+    let (controller_transport, mut node_transport) = PipeTransport::pair();
+    tokio::spawn(async move {
+        let HandshakeMessage::Control(env) = node_transport.recv().await.unwrap() else {
+            panic!("expected control envelope");
+        };
+        let error = responder.error(env.seq, ErrorCode::ControlUnknownOp, None);
+        node_transport
+            .send(HandshakeMessage::Error(error))
+            .await
+            .unwrap();
+    });
+
+    let mut channel = ReliableControlChannel::new(controller_transport);
+    let err = client
+        .send(&mut channel, ControlOp::Vendor, json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::ControlUnknownOp));
+    assert!(!err.is_retryable());
+}
+
+#[tokio::test]
+async fn handshake_rejection_reaches_controller_as_signed_code() {
+    let (mut controller_transport, mut node_transport) = PipeTransport::pair();
+    let node_task = tokio::spawn(async move {
+        AlnpSession::accept(
+            make_identity("node"),
+            CapabilitySet::default(),
+            StaticKeyAuthenticator::default(),
+            X25519KeyExchange::new(),
+            HandshakeContext {
+                expected_controller: Some("another-controller".into()),
+                ..HandshakeContext::default()
+            },
+            &mut node_transport,
+        )
+        .await
+    });
+    let err = AlnpSession::connect(
+        make_identity("controller"),
+        CapabilitySet::default(),
+        StaticKeyAuthenticator::default(),
+        X25519KeyExchange::new(),
+        HandshakeContext::default(),
+        &mut controller_transport,
+    )
+    .await
+    .unwrap_err();
+
+    let HandshakeError::Rejected(code) = &err else {
+        panic!("expected the node's signed rejection, got {err:?}");
+    };
+    assert_eq!(*code, ErrorCode::HandshakeNotAuthorized);
+    assert!(!err.is_retryable());
+    assert!(matches!(
+        node_task.await.unwrap(),
+        Err(HandshakeError::Denied(ErrorCode::HandshakeNotAuthorized, _))
+    ));
+}
+
+#[tokio::test]
+async fn exported_keying_material_matches_on_both_peers() {
+    let (controller, node) = create_sessions().await;
+    let exported = controller
+        .export_keying_material("EXPORTER-alpine-monitor", 48)
+        .unwrap();
+    assert_eq!(exported.len(), 48);
+    assert_eq!(
+        node.export_keying_material("EXPORTER-alpine-monitor", 48)
+            .unwrap(),
+        exported
+    );
+    assert_ne!(
+        controller
+            .export_keying_material("EXPORTER-other", 48)
+            .unwrap(),
+        exported
+    );
+    let keys = controller.keys().unwrap();
+    assert!(!exported
+        .windows(32)
+        .any(|w| w == keys.stream_key || w == keys.control_key));
+    assert!(controller
+        .export_keying_material("alpine-stream", 32)
+        .is_err());
+}
+
+#[tokio::test]
+async fn node_preference_selects_aes_gcm() {
+    let node_context = HandshakeContext {
+        cipher_suites: vec![SuiteId::Aes256Gcm, SuiteId::ChaCha20Poly1305],
+        ..HandshakeContext::default()
+    };
+    let (controller, node) = create_sessions_with(HandshakeContext::default(), node_context).await;
+    let (controller, node) = (controller.unwrap(), node.unwrap());
+    let (controller_keys, node_keys) = (controller.keys().unwrap(), node.keys().unwrap());
+    assert_eq!(controller_keys.suite, SuiteId::Aes256Gcm);
+    assert_eq!(node_keys.suite, SuiteId::Aes256Gcm);
+    assert_eq!(
+        controller.security_report().unwrap().suite.aead,
+        "aes-256-gcm"
+    );
+    let mac = compute_mac(&controller_keys, 1, b"payload", b"aad").unwrap();
+    assert!(verify_mac(&node_keys, 1, b"payload", b"aad", &mac));
+}
+
+#[tokio::test]
+async fn handshake_fails_without_a_common_suite() {
+    let only = |suite| HandshakeContext {
+        cipher_suites: vec![suite],
+        ..HandshakeContext::default()
+    };
+    let (controller, node) =
+        create_sessions_with(only(SuiteId::ChaCha20Poly1305), only(SuiteId::Aes256Gcm)).await;
+    assert!(controller.is_err());
+    assert!(matches!(node, Err(HandshakeError::Capability(_))));
+}
+
+#[derive(Debug)]
+struct AllowDegraded;
+
+impl FirmwarePolicy for AllowDegraded {
+    fn allow_degraded(&self, mismatch: &FirmwareMismatch) -> bool {
+        mismatch.actual.starts_with("1.")
+    }
+}
+
+#[tokio::test]
+async fn firmware_requirements_gate_the_handshake() {
+    assert!(compare_firmware("1.0.11", "1.0.2").is_gt());
+    assert!(compare_firmware("v2.1", "2.1.0").is_eq());
+    assert!(compare_firmware("2.0.0-rc1", "2.0.1").is_lt());
+
+    let requiring = |rev: &str| HandshakeContext {
+        required_firmware_rev: Some(rev.into()),
+        ..HandshakeContext::default()
+    };
+    // Both test identities run 1.0.11.
+    let (controller, node) = create_sessions_with(requiring("1.0.9"), requiring("1.0")).await;
+    assert!(controller.is_ok() && node.is_ok());
+
+    let (controller, node) =
+        create_sessions_with(requiring("2.0.0"), HandshakeContext::default()).await;
+    match controller {
+        Err(HandshakeError::Firmware(mismatch)) => assert_eq!(
+            mismatch,
+            FirmwareMismatch {
+                required: "2.0.0".into(),
+                actual: "1.0.11".into(),
+            }
+        ),
+        other => panic!("expected a firmware error, got {other:?}"),
+    }
+    assert!(matches!(
+        node,
+        Err(HandshakeError::Remote(env)) if env.code == ErrorCode::HandshakeFirmwareIncompatible
+    ));
+
+    let (controller, node) =
+        create_sessions_with(HandshakeContext::default(), requiring("1.1")).await;
+    assert!(matches!(node, Err(HandshakeError::Firmware(_))));
+    assert!(matches!(
+        controller,
+        Err(HandshakeError::Rejected(
+            ErrorCode::HandshakeFirmwareIncompatible
+        ))
+    ));
+
+    let degraded = HandshakeContext {
+        firmware_policy: Some(Arc::new(AllowDegraded)),
+        ..requiring("2.0.0")
+    };
+    let (controller, node) = create_sessions_with(degraded, HandshakeContext::default()).await;
+    assert!(controller.is_ok() && node.is_ok());
+}
+
+#[tokio::test]
+async fn both_peers_report_the_same_security_parameters() {
+    let (controller, node) = create_sessions().await;
+    let report = controller.security_report().unwrap();
+    assert_eq!(node.security_report().unwrap(), report);
+    assert_eq!(report.suite, alpine::crypto::ALPINE_CIPHER_SUITE);
+    assert!(alpine::crypto::self_test().passed());
+}
+
+#[tokio::test]
+async fn controller_refuses_revoked_device() {
+    let node = make_identity("node");
+    let root = Authority::new("Venue root", AuthorityKind::Venue, identity::generate());
+    let list =
+        RevocationList::issue(&root, 1, 0, Vec::new(), vec![node.device_id.clone()]).unwrap();
+    let store = Arc::new(RevocationStore::new(vec![root.credentials.verifying]));
+    store.install(list).unwrap();
+
+    let (mut controller_transport, mut node_transport) = PipeTransport::pair();
+    let node_task = tokio::spawn(async move {
+        AlnpSession::accept(
+            node,
+            CapabilitySet::default(),
+            StaticKeyAuthenticator::default(),
+            X25519KeyExchange::new(),
+            HandshakeContext::default(),
+            &mut node_transport,
+        )
+        .await
+    });
+    let err = AlnpSession::connect(
+        make_identity("controller"),
+        CapabilitySet::default(),
+        StaticKeyAuthenticator::default(),
+        X25519KeyExchange::new(),
+        HandshakeContext {
+            revocations: Some(store),
+            ..HandshakeContext::default()
+        },
+        &mut controller_transport,
+    )
+    .await
+    .unwrap_err();
+
+    assert!(matches!(err, HandshakeError::Authentication(_)));
+    assert!(matches!(
+        node_task.await.unwrap(),
+        Err(HandshakeError::Remote(_))
+    ));
+}
+
+#[tokio::test]
+async fn controller_handoff_moves_session_to_new_controller() {
+    let (console_a, node_a) = create_sessions().await;
+    let (console_b, node_b) = create_sessions().await;
+    console_a
+        .set_stream_profile(StreamProfile::install().compile().unwrap())
+        .unwrap();
+    console_a.set_jitter_strategy(JitterStrategy::Lerp);
+    console_a.mark_streaming();
+
+    let snapshot =
+        SessionSnapshot::capture(&console_a, 17, 90, [(2, 120)].into_iter().collect()).unwrap();
+    let ticket = MigrationTicket::seal(&snapshot, &console_a.keys().unwrap()).unwrap();
+
+    let b_session_id = console_b.established().unwrap().session_id;
+    let client = ControlClient::new(
+        Uuid::new_v4(),
+        b_session_id,
+        ControlCrypto::new(console_b.keys().unwrap()),
+    );
+    let responder = ControlResponder::new(b_session_id, ControlCrypto::new(node_b.keys().unwrap()));
+    let env = client.migration_envelope(1, &ticket).unwrap();
+
+    let restored = responder.accept_migration(&env, &node_a, &node_b).unwrap();
+    assert_eq!(restored.control_seq, 17);
+    assert_eq!(restored.priority, 90);
+    assert_eq!(node_b.next_control_seq(), 17);
+    let priorities = node_b.frame_priorities();
+    assert_eq!(priorities.for_universe(1), Some(90));
+    assert_eq!(priorities.for_universe(2), Some(120));
+    assert_eq!(node_b.profile_config_id(), console_a.profile_config_id());
+    assert_eq!(node_b.jitter_strategy(), JitterStrategy::Lerp);
+    assert!(node_b.ensure_streaming_ready().is_ok());
+    assert!(node_a.ensure_streaming_ready().is_err());
+
+    // Sequences the outgoing controller already used are replays on the new session.
+    let mut dispatcher = ControlDispatcher::new(responder, ControlHandlers::new());
+    dispatcher.seed_sequence(node_b.next_control_seq());
+    let old = client.envelope(16, ControlOp::Identify, json!({})).unwrap();
+    assert!(dispatcher.dispatch(&old).await.is_none());
+    let next = client.envelope(17, ControlOp::Identify, json!({})).unwrap();
+    assert!(dispatcher.dispatch(&next).await.is_some());
+}
+
+#[tokio::test]
+async fn handoff_rejects_ticket_from_unrelated_session() {
+    let (console_a, node_a) = create_sessions().await;
+    let (console_b, node_b) = create_sessions().await;
+    console_b
+        .set_stream_profile(StreamProfile::auto().compile().unwrap())
+        .unwrap();
+    // Console B forges a ticket with its own keys for session A.
+    let mut snapshot = SessionSnapshot::capture(&console_b, 1, 1, Default::default()).unwrap();
+    snapshot.from_session = console_a.established().unwrap().session_id;
+    let forged = MigrationTicket::seal(&snapshot, &console_b.keys().unwrap()).unwrap();
+
+    let b_session_id = console_b.established().unwrap().session_id;
+    let client = ControlClient::new(
+        Uuid::new_v4(),
+        b_session_id,
+        ControlCrypto::new(console_b.keys().unwrap()),
+    );
+    let responder = ControlResponder::new(b_session_id, ControlCrypto::new(node_b.keys().unwrap()));
+    let env = client.migration_envelope(1, &forged).unwrap();
+
+    assert!(matches!(
+        responder.accept_migration(&env, &node_a, &node_b),
+        Err(HandshakeError::Authentication(_))
+    ));
+    assert!(node_a.ensure_streaming_ready().is_ok());
+}
+
+const GOLDEN_TRANSCRIPT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/golden/handshake_transcript.txt"
+);
+
+/// Replays a fully deterministic handshake and compares every wire message with the
+/// golden file. Set `ALPINE_BLESS_GOLDEN=1` to regenerate it after an intended change.
+#[tokio::test]
+async fn handshake_matches_golden_transcript() {
+    let transcript = Arc::new(Mutex::new(Vec::new()));
+    let (controller_pipe, node_pipe) = PipeTransport::pair();
+    let mut controller_transport = RecordingPipe {
+        inner: controller_pipe,
+        role: "controller",
+        transcript: transcript.clone(),
+    };
+    let mut node_transport = RecordingPipe {
+        inner: node_pipe,
+        role: "node",
+        transcript: transcript.clone(),
+    };
+    let identity = |name: &str| DeviceIdentity {
+        device_id: format!("{name}-0001"),
+        manufacturer_id: "golden-manu".into(),
+        model_id: format!("{name}-model"),
+        hardware_rev: "rev1".into(),
+        firmware_rev: "1.0.0".into(),
+    };
+    let authenticator = || {
+        let signing = SigningKey::from_bytes(&[0x07; 32]);
+        Ed25519Authenticator::new(NodeCredentials {
+            verifying: signing.verifying_key(),
+            signing,
+        })
+    };
+    let context = |seed: u8| HandshakeContext {
+        entropy: HandshakeEntropy::Deterministic([seed; 32]),
+        ..HandshakeContext::default()
+    };
+
+    let node = AlnpSession::accept(
+        identity("node"),
+        CapabilitySet::default(),
+        authenticator(),
+        X25519KeyExchange::from_secret([0x22; 32]),
+        context(0x02),
+        &mut node_transport,
+    );
+    let controller = AlnpSession::connect(
+        identity("controller"),
+        CapabilitySet::default(),
+        authenticator(),
+        X25519KeyExchange::from_secret([0x11; 32]),
+        context(0x01),
+        &mut controller_transport,
+    );
+    let (controller, node) = tokio::join!(controller, node);
+    let (controller, node) = (controller.unwrap(), node.unwrap());
+    let keys = controller.keys().unwrap();
+    assert_eq!(keys.control_key, node.keys().unwrap().control_key);
+
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{b:02x}")).collect() };
+    let mut rendered = String::from(
+        "# ALPINE 1.0 handshake golden transcript (see docs/handshake.md#golden-transcripts).\n\
+         # <sender> <message> <CBOR of the HandshakeMessage, hex>\n",
+    );
+    for (role, msg) in transcript.lock().unwrap().iter() {
+        let name = match msg {
+            HandshakeMessage::SessionInit(_) => "session_init",
+            HandshakeMessage::SessionAck(_) => "session_ack",
+            HandshakeMessage::SessionReady(_) => "session_ready",
+            HandshakeMessage::SessionComplete(_) => "session_complete",
+            other => panic!("unexpected handshake message {other:?}"),
+        };
+        rendered.push_str(&format!(
+            "{role} {name} {}\n",
+            hex(&codec::to_vec(msg).unwrap())
+        ));
+    }
+    rendered.push_str(&format!("control_key {}\n", hex(&keys.control_key)));
+    rendered.push_str(&format!("stream_key {}\n", hex(&keys.stream_key)));
+    rendered.push_str(&format!("channel_binding {}\n", hex(&keys.channel_binding)));
+
+    if std::env::var_os("ALPINE_BLESS_GOLDEN").is_some() {
+        std::fs::write(GOLDEN_TRANSCRIPT, &rendered).unwrap();
+    }
+    let golden = std::fs::read_to_string(GOLDEN_TRANSCRIPT).unwrap();
+    assert_eq!(
+        rendered, golden,
+        "handshake transcript drifted from the golden file"
+    );
+}
+
 #[test]
 fn capability_defaults_cover_spec_requirements() {
     let caps = CapabilitySet::default();
@@ -236,3 +940,75 @@ fn discovery_reply_is_signed_and_verifiable() {
     let sig = Signature::from_bytes(&sig_bytes);
     verifier.verify(&data, &sig).unwrap();
 }
+
+#[tokio::test]
+async fn device_server_answers_discovery_on_its_listeners_within_the_reply_rate() {
+    let credentials = identity::generate();
+    let server = Arc::new(DeviceServer {
+        identity: make_identity("device"),
+        mac_address: "AA:BB:CC:DD".into(),
+        capabilities: CapabilitySet::default().into(),
+        credentials: credentials.clone().into(),
+        timing: TimingConfig::default(),
+        limits: SessionLimits::unlimited(),
+        resumption: ResumptionCache::default(),
+        revocations: None,
+        cipher_suites: SUPPORTED_SUITES.to_vec(),
+        required_firmware_rev: None,
+        firmware_policy: None,
+        // No room for a session, so the node advertises itself as busy.
+        sessions: SessionRegistry::new(0),
+        quotas: SessionQuotas::unlimited(),
+        preemption_grace: None,
+        mirror: OutputMirror::default(),
+    });
+    let listen = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = DiscoveryConfig::new([listen]).with_reply_rate(RateLimit::new(1, 1).unwrap());
+    let running = tokio::spawn(server.clone().run(config));
+    server.update_capabilities(CapabilitySet {
+        max_channels: 1024,
+        ..CapabilitySet::default()
+    });
+
+    let controller = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buf = vec![0u8; 2048];
+    let mut replies = Vec::new();
+    for nonce in [1u8, 2] {
+        let request = alpine::messages::DiscoveryRequest::new(vec![], vec![nonce; 32]);
+        // Retry the first request until the listener is up.
+        for _ in 0..50 {
+            controller
+                .send_to(&codec::to_vec(&request).unwrap(), listen)
+                .await
+                .unwrap();
+            let received = tokio::time::timeout(
+                std::time::Duration::from_millis(20),
+                controller.recv_from(&mut buf),
+            )
+            .await;
+            if let Ok(Ok((len, _))) = received {
+                let reply: alpine::messages::DiscoveryReply =
+                    codec::decode_untrusted(&buf[..len]).unwrap();
+                replies.push(reply);
+                break;
+            }
+            if !replies.is_empty() {
+                // The second request stays within one second, past the reply rate.
+                break;
+            }
+        }
+    }
+    running.abort();
+
+    assert_eq!(replies.len(), 1);
+    let reply = &replies[0];
+    assert_eq!(reply.busy, Some(true));
+    assert_eq!(reply.capabilities.max_channels, 1024);
+    let mut data = reply.server_nonce.clone();
+    data.extend_from_slice(&[1u8; 32]);
+    let signature = Signature::from_slice(&reply.signature).unwrap();
+    credentials.verifying.verify(&data, &signature).unwrap();
+}
@@ -0,0 +1,82 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use serde_json::json;
+use uuid::Uuid;
+
+use alpine::budget::EmbeddedReceiver;
+use alpine::codec;
+use alpine::messages::{ChannelFormat, FrameEnvelope, MessageType, PackedChannels};
+
+/// Counts the allocations of the current thread, so tests running alongside do not
+/// disturb the count.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn frame(session_id: Uuid, universe: u16, timestamp_us: u64) -> FrameEnvelope {
+    FrameEnvelope {
+        message_type: MessageType::AlpineFrame,
+        session_id,
+        universe,
+        timestamp_us,
+        priority: 100,
+        channel_format: ChannelFormat::U16,
+        channels: (0..512).map(|i| i * 128).collect(),
+        packed_channels: None,
+        float_channels: None,
+        compression: None,
+        compressed_channels: None,
+        groups: Some([("front".into(), vec![1, 2, 3])].into_iter().collect()),
+        group_refs: Some(vec![4]),
+        config_tag: Some(0xdead_beef),
+        deadline_us: Some(timestamp_us + 20_000),
+        metadata: Some([("cue".into(), json!({"n": 12}))].into_iter().collect()),
+        extensions: None,
+    }
+}
+
+#[test]
+fn budgeted_receive_path_does_not_allocate() {
+    let session_id = Uuid::from_u128(0x7e57);
+    let mut packed = frame(session_id, 2, 1_000);
+    packed.channel_format = ChannelFormat::U8Packed;
+    packed.channels.clear();
+    packed.packed_channels = Some(PackedChannels(vec![0xff; 512]));
+    let datagrams: Vec<Vec<u8>> = (0..64)
+        .map(|i| frame(session_id, (i % 3) as u16 + 1, i))
+        .chain([packed])
+        .map(|frame| codec::to_vec(&frame).unwrap())
+        .collect();
+
+    let mut receiver = Box::new(EmbeddedReceiver::new(session_id));
+    let before = allocations();
+    for datagram in &datagrams {
+        receiver.buffer()[..datagram.len()].copy_from_slice(datagram);
+        receiver.receive(datagram.len()).unwrap();
+        while receiver.apply().is_some() {}
+    }
+    assert_eq!(allocations(), before);
+    assert_eq!(receiver.look(2).unwrap().levels(), &[0xff; 512]);
+    assert_eq!(receiver.look(1).unwrap().levels()[1], 128);
+}
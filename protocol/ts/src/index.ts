@@ -1,5 +1,8 @@
 export type Uuid = string;
 
+/** Cipher suite negotiated in the handshake; see docs/handshake.md. */
+export type SuiteId = "chacha20_poly1305" | "aes_256_gcm";
+
 export const ALPINE_VERSION = "1.0";
 
 export enum MessageType {
@@ -13,11 +16,30 @@ export enum MessageType {
   AlpineControlAck = "alpine_control_ack",
   AlpineFrame = "alpine_frame",
   Keepalive = "keepalive",
+  AlpineError = "alpine_error",
+  SessionResume = "session_resume",
+  SessionResumeAck = "session_resume_ack",
+  AlpineControlProgress = "alpine_control_progress",
+  AlpineEcho = "alpine_echo",
+  AlpineEchoReply = "alpine_echo_reply",
+  AlpineParity = "alpine_parity",
+  AlpineStreamHeartbeat = "alpine_stream_heartbeat",
+  AlpineCapabilityUpdate = "alpine_capability_update",
+  AlpineFrameFragment = "alpine_frame_fragment",
+  AlpineSealedFrame = "alpine_sealed_frame",
+  AlpineSessionPreempted = "alpine_session_preempted",
+  AlpineSessionClose = "alpine_session_close",
+  SessionRejected = "session_rejected",
+  AlpineOutputMirror = "alpine_output_mirror",
 }
 
 export enum ChannelFormat {
   U8 = "u8",
   U16 = "u16",
+  /** Normalized 0.0–1.0 values in `float_channels`. */
+  F32 = "f32",
+  /** One byte per channel in `packed_channels`. */
+  U8Packed = "u8_packed",
 }
 
 export enum ControlOp {
@@ -30,6 +52,31 @@ export enum ControlOp {
   SetMode = "set_mode",
   TimeSync = "time_sync",
   Vendor = "vendor",
+  StoreScene = "store_scene",
+  ListScenes = "list_scenes",
+  RecallScene = "recall_scene",
+  UploadShow = "upload_show",
+  SetFallback = "set_fallback",
+  SetSchedule = "set_schedule",
+  GetSchedule = "get_schedule",
+  SetMergePolicy = "set_merge_policy",
+  DefineGroup = "define_group",
+  RemoveGroup = "remove_group",
+  ListGroups = "list_groups",
+  GetLatency = "get_latency",
+  PauseStream = "pause_stream",
+  ResumeStream = "resume_stream",
+  RestartStream = "restart_stream",
+  StartStream = "start_stream",
+  RdmCommand = "rdm_command",
+  UpdateRevocations = "update_revocations",
+  GetCapture = "get_capture",
+  SetPortMap = "set_port_map",
+  TestPattern = "test_pattern",
+  GetLogs = "get_logs",
+  FactoryReset = "factory_reset",
+  GetTime = "get_time",
+  SubscribeOutput = "subscribe_output",
 }
 
 export enum ErrorCode {
@@ -46,9 +93,16 @@ export enum ErrorCode {
   ControlUnknownOp = "CONTROL_UNKNOWN_OP",
   ControlPayloadInvalid = "CONTROL_PAYLOAD_INVALID",
   ControlUnauthorized = "CONTROL_UNAUTHORIZED",
+  HandshakeProtocolViolation = "HANDSHAKE_PROTOCOL_VIOLATION",
+  HandshakeCapabilityMismatch = "HANDSHAKE_CAPABILITY_MISMATCH",
   StreamBadFormat = "STREAM_BAD_FORMAT",
   StreamTooLarge = "STREAM_TOO_LARGE",
   StreamUnsupportedChannelMode = "STREAM_UNSUPPORTED_CHANNEL_MODE",
+  ControlRateLimited = "CONTROL_RATE_LIMITED",
+  StreamRateLimited = "STREAM_RATE_LIMITED",
+  HandshakeFirmwareIncompatible = "HANDSHAKE_FIRMWARE_INCOMPATIBLE",
+  HandshakeNotAuthorized = "HANDSHAKE_NOT_AUTHORIZED",
+  HandshakeCapacity = "HANDSHAKE_CAPACITY",
 }
 
 export interface CapabilitySet {
@@ -58,6 +112,16 @@ export interface CapabilitySet {
   streaming_supported: boolean;
   encryption_supported: boolean;
   vendor_extensions?: Record<string, unknown>;
+  /** Frame compression schemes the device decodes. */
+  compression?: "rle"[];
+  /** Highest frame rate per universe, when the device is limited. */
+  max_frame_rate?: number;
+  /** Shortest time between frames for one universe, in microseconds. */
+  min_frame_interval_us?: number;
+  /** Jitter strategies the device applies; absent accepts all. */
+  jitter_strategies?: ("hold_last" | "drop" | "lerp")[];
+  /** Physical output ports of multi-port nodes, numbered from 1. */
+  ports?: number;
 }
 
 export interface DeviceIdentity {
@@ -96,14 +160,23 @@ export interface DiscoveryReply {
   server_nonce: Uint8Array;
   capabilities: CapabilitySet;
   signature: Uint8Array;
+  busy?: boolean;
 }
 
+export type SessionRole = "controller" | "observer";
+
 export interface SessionInit {
   type: MessageType.SessionInit;
   controller_nonce: Uint8Array;
   controller_pubkey: Uint8Array;
   requested: CapabilitySet;
   session_id: Uuid;
+  suites?: SuiteId[];
+  controller_firmware_rev?: string;
+  /** 0-255; 100 when absent. */
+  controller_priority?: number;
+  /** "controller" when absent. */
+  role?: SessionRole;
 }
 
 export interface SessionAck {
@@ -114,6 +187,7 @@ export interface SessionAck {
   capabilities: CapabilitySet;
   signature: Uint8Array;
   session_id: Uuid;
+  suite?: SuiteId;
 }
 
 export interface SessionReady {
@@ -129,6 +203,29 @@ export interface SessionComplete {
   error?: ErrorCode;
 }
 
+export interface SessionResume {
+  type: MessageType.SessionResume;
+  session_id: Uuid;
+  resumed_session: Uuid;
+  controller_nonce: Uint8Array;
+  controller_pubkey: Uint8Array;
+  mac: Uint8Array;
+  suites?: SuiteId[];
+  controller_priority?: number;
+  role?: SessionRole;
+}
+
+export interface SessionResumeAck {
+  type: MessageType.SessionResumeAck;
+  session_id: Uuid;
+  ok: boolean;
+  error?: ErrorCode;
+  device_nonce: Uint8Array;
+  device_pubkey: Uint8Array;
+  mac: Uint8Array;
+  suite?: SuiteId;
+}
+
 export interface ControlEnvelope {
   type: MessageType.AlpineControl;
   session_id: Uuid;
@@ -164,6 +261,346 @@ export interface Acknowledge {
   mac: Uint8Array;
 }
 
+/** Interim progress for control envelope `seq` while the device is still working. */
+export interface ControlProgress {
+  type: MessageType.AlpineControlProgress;
+  session_id: Uuid;
+  seq: number;
+  /** Device's count of progress updates in the session; numbers the MAC nonce. */
+  update: number;
+  /** 0-100, when the operation can estimate completion. */
+  percent?: number;
+  detail?: string;
+  mac: Uint8Array;
+}
+
+/** Capabilities the device pushes mid-session; `seq` counts up from 2^63. */
+export interface CapabilityUpdate {
+  type: MessageType.AlpineCapabilityUpdate;
+  session_id: Uuid;
+  seq: number;
+  capabilities: CapabilitySet;
+  reason?: string;
+  mac: Uint8Array;
+}
+
+/** A higher-priority controller takes the session over after `grace_ms`. */
+export interface SessionPreempted {
+  type: MessageType.AlpineSessionPreempted;
+  session_id: Uuid;
+  /** Shares the device's counter with capability updates. */
+  seq: number;
+  priority: number;
+  grace_ms: number;
+  mac: Uint8Array;
+}
+
+/** Payload of `subscribe_output`; `rate_hz: 0` ends the subscription. */
+export interface OutputSubscription {
+  universes?: number[];
+  rate_hz?: number;
+}
+
+/** Applied levels of one universe, mirrored to a subscribed observer session. */
+export interface MirroredOutput {
+  type: MessageType.AlpineOutputMirror;
+  session_id: Uuid;
+  /** Counts from 0 in the output mirror's own nonce lane. */
+  seq: number;
+  universe: number;
+  applied_us: number;
+  /** Index of the first channel in `levels`. */
+  start: number;
+  levels: number[];
+  mac: Uint8Array;
+}
+
+/** Sent once by a controller ending its session; the device does not answer. */
+export interface SessionClose {
+  type: MessageType.AlpineSessionClose;
+  session_id: Uuid;
+  /** Taken from the control envelope counter. */
+  seq: number;
+  reason?: string;
+  mac: Uint8Array;
+}
+
+/** Sent by a device refusing a handshake; signed over the controller nonce. */
+export interface SessionRejected {
+  type: MessageType.SessionRejected;
+  session_id: Uuid;
+  code: ErrorCode;
+  detail?: string;
+  /** Sent with `HANDSHAKE_CAPACITY`: how long to wait before trying again. */
+  retry_after_ms?: number;
+  signature: Uint8Array;
+}
+
+/** Payload of `store_scene`. */
+export interface StoreScenePayload {
+  slot: number;
+  name?: string;
+}
+
+/** Payload of `recall_scene`; `fade_ms` defaults to 0 (snap). */
+export interface RecallScenePayload {
+  slot: number;
+  fade_ms?: number;
+}
+
+/** Element of the JSON array carried in the `list_scenes` ack detail. */
+export interface SceneSummary {
+  slot: number;
+  name?: string;
+  universes: number[];
+  captured_at_ms: number;
+}
+
+/** Payload of `upload_show`: a slice of the CBOR-encoded show. */
+export interface ShowChunkPayload {
+  offset: number;
+  total: number;
+  data: number[];
+}
+
+/** Payload of `set_fallback`; an absent or zero `idle_ms` disables fallback. */
+export interface FallbackPayload {
+  idle_ms?: number;
+}
+
+export type ScheduleTrigger =
+  | { at: "time"; hour: number; minute: number }
+  | { at: "sunrise"; offset_min?: number }
+  | { at: "sunset"; offset_min?: number };
+
+export type ScheduleAction =
+  | { action: "recall_scene"; slot: number; fade_ms?: number }
+  | { action: "master"; percent: number; fade_ms?: number };
+
+export interface ScheduleEntry {
+  trigger: ScheduleTrigger;
+  /** Weekday bitmask, bit 0 = Monday through bit 6 = Sunday; defaults to every day. */
+  days?: number;
+  action: ScheduleAction;
+}
+
+export type MergePolicy = "htp" | "ltp" | "priority_only" | "single_source_lock";
+
+/** Payload of `set_merge_policy`. */
+export interface MergeSetting {
+  universe: number;
+  policy: MergePolicy;
+}
+
+/** Per-universe entry of the `merge` array in `get_status` details. */
+export interface UniverseMergeStatus {
+  universe: number;
+  policy: MergePolicy;
+  sources: number;
+  owner?: string | null;
+}
+
+/** Payload of `define_group` and element of the `list_groups` ack detail. */
+export interface ChannelGroup {
+  id: number;
+  name: string;
+  channels: number[];
+}
+
+/** Payload of `rdm_command`; `dest` is an RDM UID written `MMMM:DDDDDDDD` in hex. */
+export interface RdmRequest {
+  dest: string;
+  sub_device?: number;
+  command_class: "get" | "set";
+  pid: number;
+  data?: number[];
+}
+
+/** Ack detail of `rdm_command`. */
+export interface RdmReply {
+  response_type: "ack" | "ack_timer" | "nack_reason" | "ack_overflow";
+  data: number[];
+}
+
+/** Payload of `update_revocations`; keys and the signature are arrays of octets. */
+export interface RevocationList {
+  issuer: string;
+  issuer_key: number[];
+  serial: number;
+  issued_at_ms: number;
+  revoked_keys: number[][];
+  revoked_devices: string[];
+  signature: number[];
+}
+
+export type LatencyStage = "encode" | "socket" | "network" | "queue" | "apply";
+
+/** Distribution of one latency stage, in microseconds. */
+export interface Percentiles {
+  samples: number;
+  p50_us: number;
+  p95_us: number;
+  p99_us: number;
+  max_us: number;
+}
+
+/** Payload of `start_stream` and `restart_stream`; the device must compile it to the same `config_id`. */
+export interface ProfileAnnouncement {
+  intent: "auto" | "realtime" | "install";
+  latency_weight: number;
+  resilience_weight: number;
+  config_id: string;
+}
+
+/** Ack detail of `get_latency`. */
+export interface LatencyReport {
+  budget_us: number;
+  stages: Partial<Record<LatencyStage, Percentiles>>;
+  total: Percentiles;
+  over_budget: number;
+  dominant?: LatencyStage | null;
+}
+
+/** Payload of `identify`; without `port` the whole node identifies itself. */
+export interface IdentifyRequest {
+  port?: number;
+}
+
+/** Payload of `set_port_map`; a null `port` returns the universe to its default port. */
+export interface PortMapping {
+  universe: number;
+  port: number | null;
+}
+
+/** Entry of the `ports` array in `get_status` details. */
+export interface PortStatus {
+  port: number;
+  universe: number | null;
+  healthy: boolean;
+  outputs: number;
+  failures: number;
+  last_error: string | null;
+  idle_ms: number | null;
+}
+
+/** Payload of `get_capture`; `cursor` is the `next` of the previous page. */
+export interface CaptureQuery {
+  since_us?: number;
+  until_us?: number;
+  universe?: number;
+  cursor?: CaptureCursor;
+}
+
+export interface CaptureCursor {
+  seq: number;
+  channel: number;
+}
+
+/** Consecutive channels of one captured frame. */
+export interface CapturedSlice {
+  seq: number;
+  received_us: number;
+  source: string;
+  universe: number;
+  priority: number;
+  timestamp_us: number;
+  format: ChannelFormat;
+  applied: boolean;
+  first_channel: number;
+  levels: number[];
+}
+
+/** Ack detail of `get_capture`. */
+export interface CapturePage {
+  window_ms: number;
+  slices: CapturedSlice[];
+  next: CaptureCursor | null;
+}
+
+export type LogSeverity = "debug" | "info" | "warn" | "error";
+
+/** Payload of `get_logs`; `cursor` is the `next` of the previous page. */
+export interface LogQuery {
+  min_severity?: LogSeverity;
+  tail?: number;
+  cursor?: number;
+}
+
+export interface LogEntry {
+  seq: number;
+  time_us: number;
+  severity: LogSeverity;
+  target: string;
+  message: string;
+}
+
+/** Ack detail of `get_logs`. */
+export interface LogPage {
+  oldest_seq: number;
+  entries: LogEntry[];
+  next: number;
+  more: boolean;
+}
+
+/** Payload of `restart` and `factory_reset`. */
+export interface MaintenanceRequest {
+  confirm?: string;
+  reason?: string;
+}
+
+/** Ack detail of `restart` and `factory_reset`. */
+export type MaintenanceReply =
+  | { status: "confirm_required"; confirm: string; expires_ms: number }
+  | { status: "scheduled"; action: "reboot" | "factory_reset"; after_ms: number };
+
+export type TimeSource = "system" | "rtc" | "ntp" | "ptp" | "gps" | "controller";
+
+/** Payload of `time_sync`. */
+export interface TimeSet {
+  unix_us: number;
+}
+
+/** Ack detail of `get_time` and `time_sync`. */
+export interface TimeStatus {
+  unix_us: number;
+  source: TimeSource;
+  offset_us: number;
+  set_at_us?: number;
+  set_by?: Uuid;
+}
+
+export type TestPattern =
+  | { kind: "full_on" }
+  | { kind: "chase"; step_ms: number }
+  | { kind: "ramp"; period_ms: number }
+  | { kind: "poke"; channel: number; level: number };
+
+/** Payload of `test_pattern`; without `pattern` it stops the universe's pattern. */
+export interface PatternRequest {
+  universe: number;
+  pattern?: TestPattern | null;
+  channels?: number;
+  duration_ms?: number;
+}
+
+/** Payload of `set_schedule` and the `get_schedule` ack detail. */
+export interface Schedule {
+  utc_offset_min?: number;
+  location?: { latitude: number; longitude: number };
+  entries?: ScheduleEntry[];
+}
+
+export interface ErrorEnvelope {
+  type: MessageType.AlpineError;
+  session_id?: Uuid;
+  seq?: number;
+  code: ErrorCode;
+  retryable: boolean;
+  detail?: string;
+  /** Present with CONTROL_RATE_LIMITED: hold control traffic this long. */
+  retry_after_ms?: number;
+}
+
 export interface FrameEnvelope {
   type: MessageType.AlpineFrame;
   session_id: Uuid;
@@ -171,10 +608,53 @@ export interface FrameEnvelope {
   priority: number;
   channel_format: ChannelFormat;
   channels: number[];
+  /** Levels of a `u8_packed` frame, encoded as a CBOR byte string. */
+  packed_channels?: Uint8Array;
+  /** Values of an `f32` frame. */
+  float_channels?: number[];
+  /** Set when values are run-length encoded into `compressed_channels`. */
+  compression?: "rle";
+  compressed_channels?: Uint8Array;
   groups?: Record<string, number[]>;
+  /** Ids of channel groups registered on the node with `define_group`. */
+  group_refs?: number[];
+  /** Leading 32 bits of the profile's `config_id`, as announced with `start_stream`. */
+  config_tag?: number;
+  /** Sender clock time, in microseconds, after which the frame is late. */
+  deadline_us?: number;
+  /** Keys starting with `alpine_` are reserved; see `FrameMetadata`. */
   metadata?: Record<string, unknown>;
 }
 
+/** Sender recovery state, under the `alpine_recovery` metadata key. */
+export interface RecoveryInfo {
+  /** 1 when absent. */
+  version?: number;
+  phase: string;
+  reason: string;
+}
+
+/** Sender adaptation state, under the `alpine_adaptation` metadata key. */
+export interface AdaptationInfo {
+  /** 1 when absent. */
+  version?: number;
+  keyframe_interval: number;
+  delta_depth: number;
+  deadline_offset_ms: number;
+  degraded_safe: boolean;
+  frames_since_keyframe: number;
+  force_keyframe: boolean;
+  event: string;
+}
+
+/** Reserved frame metadata entries; other keys belong to the application. */
+export interface FrameMetadata {
+  alpine_recovery?: RecoveryInfo;
+  alpine_adaptation?: AdaptationInfo;
+  /** Vendor-defined values keyed by vendor name. */
+  alpine_vendor?: Record<string, unknown>;
+}
+
 export function buildFrameEnvelope(
   sessionId: Uuid,
   timestampUs: number,
@@ -200,6 +680,70 @@ export interface Keepalive {
   type: MessageType.Keepalive;
   session_id: Uuid;
   tick_ms: number;
+  /** Sender's stream counters; a node answers with its own. */
+  stats?: KeepaliveStats;
+}
+
+/** Unauthenticated stream counters piggybacked on keepalives. */
+export interface KeepaliveStats {
+  frames_sent: number;
+  frames_received: number;
+  /** `timestamp_us` of the last frame sent. */
+  last_frame_us?: number;
+  /** Frames the peer reported sending that never arrived. */
+  frames_lost: number;
+}
+
+/** Streaming-path RTT probe and its reflection. */
+export interface EchoFrame {
+  type: MessageType.AlpineEcho | MessageType.AlpineEchoReply;
+  session_id: Uuid;
+  seq: number;
+  sent_us: number;
+  /** Set on replies: the device clock when it reflected the probe. */
+  reflected_us?: number;
+  /** Filler for MTU probes; replies copy it. */
+  padding?: Uint8Array;
+  mac: Uint8Array;
+}
+
+/** Sent on the streaming path while no frames flow; `seq` rises strictly. */
+export interface StreamHeartbeat {
+  type: MessageType.AlpineStreamHeartbeat;
+  session_id: Uuid;
+  seq: number;
+  mac: Uint8Array;
+}
+
+/** XOR parity over a group of encoded frames of one universe. */
+export interface ParityFrame {
+  type: MessageType.AlpineParity;
+  session_id: Uuid;
+  universe: number;
+  /** `timestamp_us` of each covered frame, in send order. */
+  covers: number[];
+  /** Encoded length of each covered frame. */
+  lengths: number[];
+  parity: Uint8Array;
+}
+
+/** One piece of a frame or parity datagram cut to fit the path MTU. */
+export interface FrameFragment {
+  type: MessageType.AlpineFrameFragment;
+  session_id: Uuid;
+  datagram_id: number;
+  index: number;
+  count: number;
+  data: Uint8Array;
+}
+
+/** A frame or parity datagram encrypted under the key of one stream epoch. */
+export interface SealedFrame {
+  type: MessageType.AlpineSealedFrame;
+  session_id: Uuid;
+  epoch: number;
+  seq: number;
+  ciphertext: Uint8Array;
 }
 
 export interface SessionState {
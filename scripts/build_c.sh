@@ -11,7 +11,9 @@ cd "$ROOT_DIR/protocol/rust/alpine-protocol-rs"
 echo "==> Building static library for C consumers (version $VERSION)"
 echo "==> Validating UDP E2E tests (cargo test --tests -- --ignored)"
 cargo test --tests -- --ignored
-cargo build --release
+# The crate builds as an rlib only, so `no_std` targets can link it; ask for the C
+# archive here.
+cargo rustc --release --lib --crate-type staticlib
 
 cp -f target/release/libalpine.a "$DIST/libalpine-$VERSION.a"
 cp -f "$ROOT_DIR/protocol/c/alnp.h" "$DIST/alnp.h"
@@ -0,0 +1,186 @@
+//! Commissioning command line over [`alpine_protocol_sdk::tools`].
+//!
+//! ```bash
+//! alpine-tools keygen ./creds
+//! alpine-tools discover 192.168.1.255:5555 --timeout-ms 2000
+//! alpine-tools probe 192.168.1.20:5555 --credentials ./creds
+//! alpine-tools pattern 192.168.1.20:5555 --credentials ./creds --pattern chase --frames 200
+//! alpine-tools decode capture.bin
+//! ```
+//!
+//! Results are printed as JSON, one object per line, so scripts can parse them.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use alpine::messages::{CapabilitySet, DeviceIdentity};
+use alpine::profile::StreamProfile;
+use alpine_protocol_sdk::tools::{self, TestPattern};
+use alpine_protocol_sdk::AlpineClient;
+use serde_json::json;
+
+const USAGE: &str = "usage: alpine-tools <keygen DIR | discover ADDR | probe ADDR | \
+                     pattern ADDR | decode FILE> [--timeout-ms N] [--credentials DIR] \
+                     [--pattern level:N|chase|ramp] [--channels N] [--frames N] \
+                     [--interval-ms N]";
+
+struct Command {
+    name: String,
+    target: String,
+    options: HashMap<String, String>,
+}
+
+impl Command {
+    fn parse() -> Result<Self, String> {
+        let mut args = std::env::args().skip(1);
+        let name = args.next().ok_or(USAGE)?;
+        let target = args.next().ok_or(USAGE)?;
+        let mut options = HashMap::new();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", flag))?;
+            options.insert(flag, value);
+        }
+        Ok(Self {
+            name,
+            target,
+            options,
+        })
+    }
+
+    fn option<T: std::str::FromStr>(&self, flag: &str, default: T) -> Result<T, String> {
+        self.options.get(flag).map_or(Ok(default), |value| {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value for {}: {}", flag, value))
+        })
+    }
+
+    fn addr(&self) -> Result<SocketAddr, String> {
+        self.target
+            .parse()
+            .map_err(|_| format!("invalid address {}", self.target))
+    }
+
+    fn credentials(&self) -> Result<alpine::crypto::identity::NodeCredentials, String> {
+        let dir = self
+            .options
+            .get("--credentials")
+            .ok_or("--credentials DIR is required")?;
+        tools::load_credentials(dir).map_err(|e| e.to_string())
+    }
+
+    fn pattern(&self) -> Result<TestPattern, String> {
+        match self.options.get("--pattern").map(String::as_str) {
+            None | Some("chase") => Ok(TestPattern::Chase),
+            Some("ramp") => Ok(TestPattern::Ramp),
+            Some(other) => other
+                .strip_prefix("level:")
+                .and_then(|level| level.parse().ok())
+                .map(TestPattern::Level)
+                .ok_or_else(|| format!("unknown pattern {}", other)),
+        }
+    }
+}
+
+fn identity() -> DeviceIdentity {
+    DeviceIdentity {
+        device_id: uuid::Uuid::new_v4().to_string(),
+        manufacturer_id: "ALPINE".into(),
+        model_id: "alpine-tools".into(),
+        hardware_rev: String::new(),
+        firmware_rev: env!("CARGO_PKG_VERSION").into(),
+    }
+}
+
+async fn run(command: Command) -> Result<(), String> {
+    match command.name.as_str() {
+        "keygen" => {
+            let dir = PathBuf::from(&command.target);
+            tools::generate_credentials(&dir).map_err(|e| e.to_string())?;
+            println!("{}", json!({ "credentials": dir }));
+        }
+        "discover" => {
+            let timeout = Duration::from_millis(command.option("--timeout-ms", 2_000)?);
+            let outcomes =
+                tools::discover_devices(command.addr()?, timeout).map_err(|e| e.to_string())?;
+            for outcome in outcomes {
+                println!(
+                    "{}",
+                    json!({ "peer": outcome.peer, "reply": outcome.reply })
+                );
+            }
+        }
+        "probe" => {
+            let report =
+                tools::handshake_probe(command.addr()?, identity(), command.credentials()?)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            println!(
+                "{}",
+                json!({
+                    "remote_addr": report.remote_addr,
+                    "handshake_ms": report.handshake.map(|d| d.as_secs_f64() * 1e3),
+                    "control_rtt_ms": report.control_rtt.as_secs_f64() * 1e3,
+                    "status": report.status,
+                })
+            );
+        }
+        "pattern" => {
+            let pattern = command.pattern()?;
+            let channels = command.option("--channels", 512)?;
+            let frames = command.option("--frames", 250)?;
+            let interval = Duration::from_millis(command.option("--interval-ms", 40)?);
+            let mut client = AlpineClient::builder(
+                command.addr()?,
+                identity(),
+                CapabilitySet::default(),
+                command.credentials()?,
+            )
+            .connect()
+            .await
+            .map_err(|e| e.to_string())?;
+            client
+                .start_stream(StreamProfile::auto())
+                .await
+                .map_err(|e| e.to_string())?;
+            let sent = tools::send_test_pattern(&client, pattern, channels, frames, interval).await;
+            client.close().await;
+            sent.map_err(|e| e.to_string())?;
+            println!("{}", json!({ "frames": frames }));
+        }
+        "decode" => {
+            let bytes = if command.target == "-" {
+                let mut bytes = Vec::new();
+                std::io::Read::read_to_end(&mut std::io::stdin(), &mut bytes)
+                    .map_err(|e| e.to_string())?;
+                bytes
+            } else {
+                std::fs::read(&command.target).map_err(|e| e.to_string())?
+            };
+            let packet = tools::decode_packet(&bytes).map_err(|e| e.to_string())?;
+            println!("{}", packet.body);
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let result = match Command::parse() {
+        Ok(command) => run(command).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Synchronous facade over [`AlpineClient`] for hosts that cannot run tokio themselves.
+//!
+//! Each client owns a small multi-threaded runtime so keep-alive and other background
+//! tasks keep running between calls. Do not call these methods from inside an async
+//! context; use [`AlpineClient`] there instead.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use alpine::crypto::identity::NodeCredentials;
+use alpine::messages::{Acknowledge, CapabilitySet, ChannelFormat, ControlOp, DeviceIdentity};
+use alpine::profile::StreamProfile;
+use alpine::stream::StreamHealth;
+use alpine::transport::TransportConfig;
+use serde_json::Value;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::client::AlpineClient;
+use crate::error::AlpineSdkError;
+use crate::health::ConnectionHealth;
+
+/// Blocking wrapper around [`AlpineClient`].
+#[derive(Debug)]
+pub struct BlockingAlpineClient {
+    runtime: Runtime,
+    inner: AlpineClient,
+}
+
+impl BlockingAlpineClient {
+    /// Blocking counterpart of [`AlpineClient::connect`].
+    pub fn connect(
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+    ) -> Result<Self, AlpineSdkError> {
+        Self::connect_with_config(
+            local_addr,
+            remote_addr,
+            identity,
+            capabilities,
+            credentials,
+            TransportConfig::default(),
+        )
+    }
+
+    /// Blocking counterpart of [`AlpineClient::connect_with_config`].
+    pub fn connect_with_config(
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+        transport_config: TransportConfig,
+    ) -> Result<Self, AlpineSdkError> {
+        let runtime = new_runtime()?;
+        let inner = runtime.block_on(AlpineClient::connect_with_config(
+            local_addr,
+            remote_addr,
+            identity,
+            capabilities,
+            credentials,
+            transport_config,
+        ))?;
+        Ok(Self { runtime, inner })
+    }
+
+    /// Blocking counterpart of [`AlpineClient::connect_host`].
+    pub fn connect_host(
+        host: &str,
+        port: u16,
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+    ) -> Result<Self, AlpineSdkError> {
+        let runtime = new_runtime()?;
+        let inner = runtime.block_on(AlpineClient::connect_host(
+            host,
+            port,
+            identity,
+            capabilities,
+            credentials,
+        ))?;
+        Ok(Self { runtime, inner })
+    }
+
+    /// Returns the device address this client is connected to.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.inner.remote_addr()
+    }
+
+    /// Starts streaming with the supplied profile and returns the generated config id.
+    pub fn start_stream(&mut self, profile: StreamProfile) -> Result<String, AlpineSdkError> {
+        self.runtime.block_on(self.inner.start_stream(profile))
+    }
+
+    /// Sends a streaming frame over the active session.
+    pub fn send_frame(
+        &self,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+        groups: Option<HashMap<String, Vec<u16>>>,
+        metadata: Option<HashMap<String, Value>>,
+    ) -> Result<(), AlpineSdkError> {
+        self.inner
+            .send_frame(channel_format, channels, priority, groups, metadata)
+    }
+
+    /// Returns per-universe and aggregate network health for the active stream.
+    pub fn stream_health(&self) -> Option<StreamHealth> {
+        self.inner.stream_health()
+    }
+
+    /// Composite health score and level of the connection; see [`AlpineClient::health`].
+    pub fn health(&self) -> ConnectionHealth {
+        self.inner.health()
+    }
+
+    /// Sends an authenticated control operation and blocks until the node acks it.
+    pub fn send_control(
+        &self,
+        op: ControlOp,
+        payload: Value,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        self.runtime.block_on(self.inner.send_control(op, payload))
+    }
+
+    /// Asks the node to physically identify itself.
+    pub fn identify(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.runtime.block_on(self.inner.identify())
+    }
+
+    /// Switches the node into the named operating mode.
+    pub fn set_mode(&self, mode: &str) -> Result<Acknowledge, AlpineSdkError> {
+        self.runtime.block_on(self.inner.set_mode(mode))
+    }
+
+    /// Requests the node's status; the summary is carried in the ack `detail`.
+    pub fn get_status(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.runtime.block_on(self.inner.get_status())
+    }
+
+    /// Stops frame output and tells the node to hold the last look until resumed.
+    pub fn pause_stream(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.runtime.block_on(self.inner.pause_stream())
+    }
+
+    /// Tells the node frames are resuming and lifts the pause once it acknowledges.
+    pub fn resume_stream(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.runtime.block_on(self.inner.resume_stream())
+    }
+
+    /// Stops keep-alive, shuts down the session, and tears down the runtime.
+    pub fn close(self) {
+        let Self { runtime, inner } = self;
+        runtime.block_on(inner.close());
+    }
+}
+
+fn new_runtime() -> Result<Runtime, AlpineSdkError> {
+    Ok(Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("alpine-sdk-blocking")
+        .enable_all()
+        .build()?)
+}
@@ -1,36 +1,135 @@
-use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use alpine::control::{ControlClient, ControlCrypto};
+use alpine::capture::{CapturePage, CaptureQuery, CapturedFrame};
+use alpine::clock::{TimeSet, TimeStatus};
+use alpine::codec;
+use alpine::control::{ControlClient, ControlCrypto, RateLimit};
 use alpine::crypto::identity::NodeCredentials;
-use alpine::crypto::X25519KeyExchange;
+use alpine::crypto::revocation::RevocationList;
+use alpine::crypto::{SuiteId, X25519KeyExchange, SUPPORTED_SUITES};
+use alpine::groups::ChannelGroup;
 use alpine::handshake::keepalive;
-use alpine::handshake::transport::{CborUdpTransport, TimeoutTransport};
-use alpine::handshake::{HandshakeContext, HandshakeError};
-use alpine::messages::{CapabilitySet, ChannelFormat, ControlEnvelope, ControlOp, DeviceIdentity};
-use alpine::profile::StreamProfile;
-use alpine::session::{AlnpSession, Ed25519Authenticator};
-use alpine::stream::AlnpStream;
-use serde_json::Value;
-use tokio::sync::Mutex;
-use tokio::task::JoinHandle;
+use alpine::handshake::transport::{
+    ControlStats, ControlUpdate, DeviceNotice, ReliableControlChannel, RetryPolicy,
+    TimeoutTransport,
+};
+use alpine::handshake::{
+    FirmwarePolicy, HandshakeContext, HandshakeError, HandshakeMessage, RevocationCheck,
+};
+use alpine::inventory::{DeviceHealth, DeviceRecord};
+use alpine::latency::LatencyReport;
+use alpine::logs::{LogEntry, LogPage, LogQuery};
+use alpine::maintenance::{MaintenanceReply, MaintenanceRequest};
+use alpine::merge::{MergePolicy, MergeSetting};
+use alpine::messages::{
+    Acknowledge, CapabilitySet, ChannelFormat, ControlEnvelope, ControlOp, ControlProgress,
+    DeviceIdentity, EchoFrame, Extensions, GroupId, IdentifyRequest, PortId, SessionRole,
+    UniverseId,
+};
+use alpine::mirror::{MirrorSlice, OutputSubscription};
+use alpine::output::pattern::{PatternRequest, TestPattern, DEFAULT_PATTERN_DURATION};
+use alpine::ports::{PortMapping, PortStatus};
+use alpine::profile::{CompiledStreamProfile, ProfileAnnouncement, StreamProfile};
+use alpine::rdm::RdmRequest;
+use alpine::schedule::Schedule;
+use alpine::session::limits::SessionLimits;
+use alpine::session::metrics::SessionMetrics;
+use alpine::session::migration::{MigrationTicket, SessionSnapshot};
+use alpine::session::resume::{ResumptionTicket, SessionStore};
+use alpine::session::{AlnpSession, Ed25519Authenticator, SessionSecurityReport, TimingConfig};
+use alpine::show::{FallbackConfig, Show, ShowChunk};
+use alpine::source::{FrameSource, SourceError, SourceFrame};
+use alpine::stream::{
+    AlnpStream, EchoSample, FrameOutcome, ReceiverReport, StreamError, StreamEvent, StreamHealth,
+    UnderrunAlarm, STREAM_HEARTBEAT_INTERVAL,
+};
+use alpine::transport::{TransportConfig, TransportStats, UdpTransport};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc, watch, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
 use uuid::Uuid;
 
 use crate::error::AlpineSdkError;
-use crate::transport::UdpFrameTransport;
+use crate::events::{AlpineEvent, EventBus};
+use crate::health::{ConnectionHealth, HealthInputs, HealthScorer};
+
+/// Delay between staggered connection attempts when a hostname resolves to several
+/// addresses (RFC 8305 recommends 250 ms).
+const CONNECT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// How long a relayed source is polled before checking whether the relay was dropped.
+const RELAY_POLL: Duration = Duration::from_millis(100);
+
+/// Source frames buffered between the reading thread and the relay.
+const RELAY_CAPACITY: usize = 64;
+
+/// Mirrored slices a lagging [`AlpineClient::mirrored_output`] receiver may fall behind by
+/// before it misses some.
+const MIRROR_CAPACITY: usize = 256;
+
+/// How often the control channel is checked for capability updates between calls.
+const CAPABILITY_POLL: Duration = Duration::from_millis(250);
+
+/// How long each check listens on an otherwise idle control channel.
+const CAPABILITY_LISTEN: Duration = Duration::from_millis(20);
+
+/// Datagram sizes [`AlpineClient::probe_mtu`] tries below the configured limit: Ethernet
+/// over IPv4 and IPv6, a PPPoE or tunnel allowance, the IPv6 minimum, and the IPv4 one.
+const MTU_PROBE_SIZES: [usize; 5] = [1_472, 1_452, 1_400, 1_232, 548];
+
+/// Receive buffer for echo replies, large enough for any probe.
+const ECHO_BUFFER: usize = 64 * 1024;
+
+/// How often frame sends and round trips re-score the connection's health.
+const HEALTH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the session state is checked for changes to publish on the event bus.
+const STATE_POLL: Duration = Duration::from_millis(250);
+
+/// How long [`AlpineClient::close`] waits for background tasks and the closing message
+/// before aborting what is left.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Control transport shared between the keep-alive task and control requests.
+type SharedTransport = Arc<Mutex<TimeoutTransport<UdpTransport>>>;
+
+/// Control channel shared between control requests and the capability watcher.
+type SharedControl = Arc<Mutex<ReliableControlChannel<SharedTransport>>>;
 
 /// High-level client that wraps the ALPINE protocol primitives.
 #[derive(Debug)]
 pub struct AlpineClient {
     session: AlnpSession,
-    _transport: Arc<Mutex<TimeoutTransport<CborUdpTransport>>>,
+    control_channel: SharedControl,
     local_addr: SocketAddr,
     remote_addr: SocketAddr,
-    stream: Option<AlnpStream<UdpFrameTransport>>,
-    control: ControlClient,
+    transport_config: TransportConfig,
+    /// Shares its socket and counters with the control channel's transport.
+    control_transport: UdpTransport,
+    stream: Option<AlnpStream<UdpTransport>>,
+    control: Arc<ControlClient>,
     keepalive_handle: Option<JoinHandle<()>>,
+    capability_handle: JoinHandle<()>,
+    /// Set to ask the background tasks to finish; dropping it does the same.
+    shutdown: watch::Sender<bool>,
+    /// Whether [`AlpineClient::close`] already ran, so `Drop` has nothing left to do.
+    closed: bool,
+    events: broadcast::Sender<StreamEvent>,
+    /// Output the node mirrors to this session once it subscribed.
+    mirror: broadcast::Sender<MirrorSlice>,
+    bus: EventBus,
+    forward_handle: JoinHandle<()>,
+    /// Health scorer and its latest sample, refreshed at most once per interval.
+    health: std::sync::Mutex<(HealthScorer, Option<(Instant, ConnectionHealth)>)>,
+    store: Option<Arc<dyn SessionStore>>,
+    resumed: Option<Option<String>>,
+    /// Seal frames under per-epoch keys; see [`AlpineClientBuilder::encrypt_frames`].
+    encrypt_frames: bool,
+    underrun_alarm: Option<UnderrunAlarm>,
 }
 
 impl AlpineClient {
@@ -42,77 +141,329 @@ impl AlpineClient {
         capabilities: CapabilitySet,
         credentials: NodeCredentials,
     ) -> Result<Self, AlpineSdkError> {
-        let key_exchange = X25519KeyExchange::new();
-        let authenticator = Ed25519Authenticator::new(credentials.clone());
-
-        let mut transport = TimeoutTransport::new(
-            CborUdpTransport::bind(local_addr, remote_addr, 2048).await?,
-            Duration::from_secs(3),
-        );
-        let session = AlnpSession::connect(
+        Self::connect_with_config(
+            local_addr,
+            remote_addr,
             identity,
-            capabilities.clone(),
-            authenticator,
-            key_exchange,
-            HandshakeContext::default(),
-            &mut transport,
+            capabilities,
+            credentials,
+            TransportConfig::default(),
         )
-        .await?;
+        .await
+    }
 
-        let transport = Arc::new(Mutex::new(transport));
-        let keepalive_handle = tokio::spawn(keepalive::spawn_keepalive(
-            transport.clone(),
-            Duration::from_secs(5),
-            session
-                .established()
-                .ok_or_else(|| AlpineSdkError::Io("session missing after handshake".into()))?
-                .session_id,
-        ));
+    /// Resolves `host` and opens a session with the first address that completes the
+    /// handshake.
+    ///
+    /// Candidates alternate between IPv6 and IPv4 (IPv6 first) and each attempt starts
+    /// `CONNECT_ATTEMPT_DELAY` after the previous one unless that one already failed. The
+    /// winning address is available from [`AlpineClient::remote_addr`].
+    pub async fn connect_host(
+        host: &str,
+        port: u16,
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+    ) -> Result<Self, AlpineSdkError> {
+        let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|source| AlpineSdkError::Connect {
+                target: host.to_string(),
+                source,
+            })?
+            .collect();
+        let candidates = interleave_families(resolved);
+        if candidates.is_empty() {
+            return Err(AlpineSdkError::Connect {
+                target: host.to_string(),
+                source: io::Error::new(io::ErrorKind::NotFound, "no addresses"),
+            });
+        }
 
-        let established = session
-            .established()
-            .ok_or_else(|| AlpineSdkError::Io("session missing after handshake".into()))?;
-        let device_uuid = Uuid::parse_str(&established.device_identity.device_id)
-            .unwrap_or_else(|_| Uuid::new_v4());
-        let control_crypto = ControlCrypto::new(
-            session
-                .keys()
-                .ok_or_else(|| AlpineSdkError::Io("session keys missing".into()))?,
-        );
-        let control = ControlClient::new(device_uuid, established.session_id, control_crypto);
+        let mut pending = candidates.into_iter();
+        let mut attempts = JoinSet::new();
+        let mut last_error = None;
+        loop {
+            if let Some(remote_addr) = pending.next() {
+                let local_addr = unspecified_for(remote_addr);
+                let (identity, capabilities, credentials) =
+                    (identity.clone(), capabilities.clone(), credentials.clone());
+                attempts.spawn(async move {
+                    Self::connect(local_addr, remote_addr, identity, capabilities, credentials)
+                        .await
+                });
+            } else if attempts.is_empty() {
+                break;
+            }
 
-        Ok(Self {
-            session,
-            _transport: transport,
-            local_addr,
+            let outcome = if pending.len() > 0 {
+                match tokio::time::timeout(CONNECT_ATTEMPT_DELAY, attempts.join_next()).await {
+                    Ok(outcome) => outcome,
+                    Err(_) => continue,
+                }
+            } else {
+                attempts.join_next().await
+            };
+            match outcome {
+                Some(Ok(Ok(client))) => return Ok(client),
+                Some(Ok(Err(err))) => last_error = Some(err),
+                Some(Err(err)) => last_error = Some(AlpineSdkError::Internal(err.to_string())),
+                None => {}
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| AlpineSdkError::Internal(format!("connect {}: no attempts", host))))
+    }
+
+    /// Opens a session using `transport_config` for both the control and streaming sockets.
+    ///
+    /// Use this to pin traffic to a venue VLAN or mark streaming with a DSCP class so
+    /// managed switches can prioritize it.
+    pub async fn connect_with_config(
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+        transport_config: TransportConfig,
+    ) -> Result<Self, AlpineSdkError> {
+        Self::builder(remote_addr, identity, capabilities, credentials)
+            .local_addr(local_addr)
+            .transport_config(transport_config)
+            .connect()
+            .await
+    }
+
+    /// Starts configuring a session with `remote_addr`; see [`AlpineClientBuilder`].
+    pub fn builder(
+        remote_addr: SocketAddr,
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+    ) -> AlpineClientBuilder {
+        AlpineClientBuilder {
+            local_addr: unspecified_for(remote_addr),
             remote_addr,
-            stream: None,
-            control,
-            keepalive_handle: Some(keepalive_handle),
-        })
+            identity,
+            capabilities,
+            credentials,
+            transport_config: TransportConfig::default(),
+            timing: TimingConfig::default(),
+            limits: SessionLimits::unlimited(),
+            store: None,
+            control_retry: RetryPolicy::default(),
+            op_retry: HashMap::new(),
+            control_rate: None,
+            revocations: None,
+            cipher_suites: SUPPORTED_SUITES.to_vec(),
+            min_firmware: None,
+            firmware_policy: None,
+            encrypt_frames: false,
+            underrun_alarm: None,
+            priority: None,
+            role: None,
+            bus: None,
+        }
     }
 
     /// Starts streaming with the supplied profile and returns the generated config id.
-    pub fn start_stream(&mut self, profile: StreamProfile) -> Result<String, AlpineSdkError> {
-        let compiled = profile
-            .compile()
-            .map_err(|err| HandshakeError::Protocol(err.to_string()))?;
+    ///
+    /// The node is told the profile's `config_id` over `start_stream` and must confirm
+    /// it before any frame goes out; it refuses frames tagged with any other profile.
+    /// Profiles sending faster than the node's frame-rate limits, or smoothing with a
+    /// jitter strategy it lacks, fail with [`AlpineSdkError::Profile`] before anything is
+    /// sent.
+    pub async fn start_stream(&mut self, profile: StreamProfile) -> Result<String, AlpineSdkError> {
+        let config = &self.transport_config;
+        let stream_socket =
+            UdpTransport::bind(self.local_addr, self.remote_addr, config).map_err(|source| {
+                AlpineSdkError::Bind {
+                    addr: self.local_addr,
+                    source,
+                }
+            })?;
+        self.start_stream_on(profile, stream_socket).await
+    }
+
+    /// Like [`AlpineClient::start_stream`], but sends frames from `stream_socket` rather
+    /// than a socket of its own, e.g. a [`alpine::stream::ShardPool`] transport.
+    pub async fn start_stream_on(
+        &mut self,
+        profile: StreamProfile,
+        stream_socket: UdpTransport,
+    ) -> Result<String, AlpineSdkError> {
+        let compiled = self.compile_profile(profile)?;
         self.session
             .set_stream_profile(compiled.clone())
             .map_err(AlpineSdkError::Handshake)?;
+        let payload = serde_json::to_value(ProfileAnnouncement::new(&compiled))?;
+        let ack = self.send_control(ControlOp::StartStream, payload).await?;
+        if !ack.ok || ack.detail.as_deref() != Some(compiled.config_id()) {
+            return Err(AlpineSdkError::Refused {
+                op: ControlOp::StartStream,
+                detail: format!(
+                    "config_id {} not confirmed: {}",
+                    compiled.config_id(),
+                    ack.detail.unwrap_or_default()
+                ),
+            });
+        }
         self.session.mark_streaming();
+        self.save_ticket();
 
-        let stream_socket = UdpFrameTransport::new(self.local_addr, self.remote_addr)?;
-        let stream = AlnpStream::new(self.session.clone(), stream_socket, compiled.clone());
+        let stream = AlnpStream::new(self.session.clone(), stream_socket, compiled.clone())
+            .with_event_sender(self.events.clone())
+            .with_heartbeat(STREAM_HEARTBEAT_INTERVAL);
+        let stream = if self.encrypt_frames {
+            stream.with_encryption()?
+        } else {
+            stream
+        };
+        let stream = match self.underrun_alarm {
+            Some(alarm) => stream.with_underrun_alarm(alarm),
+            None => stream,
+        };
+        stream.set_max_datagram(self.transport_config.max_datagram(self.remote_addr));
+        self.stream = Some(stream);
+        Ok(compiled.config_id().to_string())
+    }
+
+    /// Switches the running stream to `profile` without a new handshake and returns the
+    /// new config id.
+    ///
+    /// Frames are held while the node checks and adopts the profile's `config_id` over
+    /// `restart_stream`; the stream then starts over on the new profile, with fresh
+    /// per-universe, adaptation, and bandwidth state, and frame extensions carried over.
+    /// If the node refuses, the old profile stays in effect.
+    pub async fn restart_stream(
+        &mut self,
+        profile: StreamProfile,
+    ) -> Result<String, AlpineSdkError> {
+        let old = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        let extensions = old.frame_extensions();
+        let max_datagram = old.max_datagram();
+        let compiled = self.compile_profile(profile)?;
+        let payload = serde_json::to_value(ProfileAnnouncement::new(&compiled))?;
+        let paused = self.stream_paused();
+        self.session.set_streaming_enabled(false);
+        match self.send_control(ControlOp::RestartStream, payload).await {
+            Ok(ack) if ack.ok && ack.detail.as_deref() == Some(compiled.config_id()) => {}
+            refused => {
+                self.session.set_streaming_enabled(!paused);
+                return Err(match refused {
+                    Ok(ack) => AlpineSdkError::Refused {
+                        op: ControlOp::RestartStream,
+                        detail: ack.detail.unwrap_or_default(),
+                    },
+                    Err(err) => err,
+                });
+            }
+        }
+        self.session
+            .restart_stream_profile(compiled.clone())
+            .map_err(AlpineSdkError::Handshake)?;
+        // The socket carries over; everything built on the old profile is discarded.
+        let stream_socket = self
+            .stream
+            .take()
+            .map(AlnpStream::into_transport)
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        let stream = AlnpStream::new(self.session.clone(), stream_socket, compiled.clone())
+            .with_event_sender(self.events.clone())
+            .with_heartbeat(STREAM_HEARTBEAT_INTERVAL);
+        let stream = if self.encrypt_frames {
+            stream.with_encryption()?
+        } else {
+            stream
+        };
+        let stream = match self.underrun_alarm {
+            Some(alarm) => stream.with_underrun_alarm(alarm),
+            None => stream,
+        };
+        stream.set_frame_extensions(extensions);
+        stream.set_max_datagram(max_datagram);
         self.stream = Some(stream);
+        self.session.set_streaming_enabled(!paused);
+        self.save_ticket();
         Ok(compiled.config_id().to_string())
     }
 
+    /// Compiles `profile` and checks it against the node's current capabilities.
+    fn compile_profile(
+        &self,
+        profile: StreamProfile,
+    ) -> Result<CompiledStreamProfile, AlpineSdkError> {
+        let compiled = profile.compile()?;
+        if let Some(capabilities) = self.capabilities() {
+            compiled.check_capabilities(&capabilities)?;
+        }
+        Ok(compiled)
+    }
+
+    /// Returns true when this session was resumed from a stored ticket.
+    pub fn resumed(&self) -> bool {
+        self.resumed.is_some()
+    }
+
+    /// Config id of the stream profile active before a resumed session's restart.
+    ///
+    /// Pass the same profile to [`AlpineClient::start_stream`] to pick up where the show
+    /// left off.
+    pub fn resumed_config_id(&self) -> Option<&str> {
+        self.resumed.as_ref().and_then(|id| id.as_deref())
+    }
+
+    /// Saves the current session's resumption ticket, if a store is configured.
+    ///
+    /// Best-effort: a failed save only costs the next restart a full handshake.
+    fn save_ticket(&self) {
+        if let Some(store) = &self.store {
+            if let Ok(ticket) = ResumptionTicket::capture(&self.session) {
+                let _ = store.save(&self.remote_addr.to_string(), &ticket);
+            }
+        }
+    }
+
+    /// Subscribes to adaptation, recovery, and session-limit events from the active stream,
+    /// and to capability changes the node pushes.
+    ///
+    /// The subscription survives `start_stream` calls, so it can be taken right after
+    /// `connect` to drive operator notices such as "network degraded, keyframes increased".
+    pub fn subscribe_events(&self) -> broadcast::Receiver<StreamEvent> {
+        self.events.subscribe()
+    }
+
+    /// Receives the output the node mirrors to this session after
+    /// [`AlpineClient::subscribe_output`], authenticated and in order; each slice holds
+    /// consecutive channels of one universe as the node applied them.
+    pub fn mirrored_output(&self) -> broadcast::Receiver<MirrorSlice> {
+        self.mirror.subscribe()
+    }
+
+    /// Subscribes to every [`AlpineEvent`] on the client's bus: session state, stream
+    /// events, and control acks, plus whatever other clients sharing the bus publish.
+    pub fn subscribe(&self) -> broadcast::Receiver<AlpineEvent> {
+        self.bus.subscribe()
+    }
+
+    /// The bus this client publishes on, e.g. to hand to a [`crate::DiscoveryClient`].
+    pub fn event_bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    /// Returns the device address this client is connected to.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
     /// Sends a streaming frame over the active session.
     pub fn send_frame(
         &self,
         channel_format: ChannelFormat,
-        channels: Vec<u16>,
+        channels: &[u16],
         priority: u8,
         groups: Option<HashMap<String, Vec<u16>>>,
         metadata: Option<HashMap<String, Value>>,
@@ -120,18 +471,971 @@ impl AlpineClient {
         let stream = self
             .stream
             .as_ref()
-            .ok_or_else(|| AlpineSdkError::Io("stream not started".into()))?;
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        self.refresh_health();
         stream
             .send(channel_format, channels, priority, groups, metadata)
             .map_err(AlpineSdkError::from)
     }
 
-    /// Stops keep-alive and shuts down the session.
+    /// Sends a frame for `universe` addressing channel groups registered on the node with
+    /// [`AlpineClient::define_group`] by id.
+    pub fn send_grouped_frame(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+        group_refs: Vec<GroupId>,
+        metadata: Option<HashMap<String, Value>>,
+    ) -> Result<(), AlpineSdkError> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        self.refresh_health();
+        stream
+            .send_grouped(
+                universe,
+                channel_format,
+                channels,
+                priority,
+                group_refs,
+                metadata,
+            )
+            .map_err(AlpineSdkError::from)
+    }
+
+    /// Sends a frame for `universe`; [`AlpineClient::send_frame`] sends the default one.
+    pub fn send_universe_frame(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+        metadata: Option<HashMap<String, Value>>,
+    ) -> Result<(), AlpineSdkError> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        self.refresh_health();
+        stream
+            .send_universe(universe, channel_format, channels, priority, None, metadata)
+            .map_err(AlpineSdkError::from)
+    }
+
+    /// Sends a frame for `universe` at once on the express lane, bypassing smoothing and
+    /// pacing, e.g. a blackout; see [`AlnpStream::send_express`].
+    pub fn send_express_frame(
+        &self,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+    ) -> Result<(), AlpineSdkError> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        stream
+            .send_express(universe, channel_format, channels, priority, None)
+            .map_err(AlpineSdkError::from)
+    }
+
+    /// Sends a frame for `universe` and publishes what became of it as an
+    /// [`AlpineEvent::FrameResult`] carrying `token`, e.g. a cue number, so show-control
+    /// software can log exactly which look was throttled or failed.
+    pub fn send_tracked_frame(
+        &self,
+        token: u64,
+        universe: UniverseId,
+        channel_format: ChannelFormat,
+        channels: &[u16],
+        priority: u8,
+        metadata: Option<HashMap<String, Value>>,
+    ) -> Result<FrameOutcome, AlpineSdkError> {
+        let result = match self.stream.as_ref() {
+            Some(stream) => {
+                self.refresh_health();
+                stream
+                    .send_universe_outcome(
+                        universe,
+                        channel_format,
+                        channels,
+                        priority,
+                        None,
+                        metadata,
+                    )
+                    .map_err(AlpineSdkError::from)
+            }
+            None => Err(AlpineSdkError::StreamNotStarted),
+        };
+        self.bus.publish(AlpineEvent::FrameResult {
+            remote_addr: self.remote_addr,
+            token,
+            outcome: result.as_ref().copied().map_err(ToString::to_string),
+        });
+        result
+    }
+
+    /// Sends normalized 0.0–1.0 values for `universe` as an `f32` frame; the device must
+    /// have advertised [`ChannelFormat::F32`].
+    pub fn send_normalized_frame(
+        &self,
+        universe: UniverseId,
+        values: Vec<f32>,
+        priority: u8,
+        metadata: Option<HashMap<String, Value>>,
+    ) -> Result<(), AlpineSdkError> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        self.refresh_health();
+        stream
+            .send_normalized(universe, values, priority, metadata)
+            .map_err(AlpineSdkError::from)
+    }
+
+    /// Streams every look `source` produces until it fails or the returned future is
+    /// dropped, so legacy DMX or sACN is authenticated before it crosses the network.
+    ///
+    /// The source is read on a blocking thread. Looks that arrive while the stream is
+    /// paused are dropped rather than ending the relay.
+    pub async fn relay<S>(&self, mut source: S) -> Result<(), AlpineSdkError>
+    where
+        S: FrameSource + 'static,
+    {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        let (frames, mut received) =
+            mpsc::channel::<Result<SourceFrame, SourceError>>(RELAY_CAPACITY);
+        tokio::task::spawn_blocking(move || loop {
+            match source.next_frame(RELAY_POLL) {
+                Ok(Some(frame)) => {
+                    if frames.blocking_send(Ok(frame)).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) if frames.is_closed() => return,
+                Ok(None) => {}
+                Err(err) => {
+                    let _ = frames.blocking_send(Err(err));
+                    return;
+                }
+            }
+        });
+        while let Some(frame) = received.recv().await {
+            let frame = frame?;
+            self.refresh_health();
+            match stream.send_universe(
+                frame.universe,
+                frame.channel_format,
+                &frame.channels,
+                frame.priority,
+                None,
+                None,
+            ) {
+                Ok(()) | Err(StreamError::StreamingDisabled) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Attaches vendor extensions to every subsequent frame; `None` clears them.
+    pub fn set_frame_extensions(
+        &self,
+        extensions: Option<Extensions>,
+    ) -> Result<(), AlpineSdkError> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        stream.set_frame_extensions(extensions);
+        Ok(())
+    }
+
+    /// The profile the running stream was started with; `None` before `start_stream`.
+    pub fn stream_profile(&self) -> Option<CompiledStreamProfile> {
+        self.stream.as_ref().and(self.session.compiled_profile())
+    }
+
+    /// Returns per-universe and aggregate network health for the active stream.
+    pub fn stream_health(&self) -> Option<StreamHealth> {
+        self.stream.as_ref().map(|stream| stream.health())
+    }
+
+    /// Encode and socket latency of recently sent frames, once streaming has started.
+    pub fn latency_report(&self) -> Option<LatencyReport> {
+        self.stream.as_ref().map(|stream| stream.latency().report())
+    }
+
+    /// Returns uptime, state timings, round trips, and frame counts for this session.
+    pub fn metrics(&self) -> SessionMetrics {
+        self.session.metrics()
+    }
+
+    /// Composite health score and level of the connection, from keep-alive RTT, loss,
+    /// late frames, and adaptation state.
+    ///
+    /// Frame sends and round trips re-score it at most once a second, emitting
+    /// [`StreamEvent::HealthChanged`] when the level changes; this returns the latest
+    /// sample, re-scoring first if it is older than that.
+    pub fn health(&self) -> ConnectionHealth {
+        self.refresh_health()
+    }
+
+    fn refresh_health(&self) -> ConnectionHealth {
+        let mut health = self
+            .health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (scorer, latest) = &mut *health;
+        if let Some((at, sample)) = latest {
+            if at.elapsed() < HEALTH_INTERVAL {
+                return *sample;
+            }
+        }
+        let inputs = HealthInputs::collect(&self.session.metrics(), self.stream_health().as_ref());
+        let (sample, changed) = scorer.update(inputs);
+        *latest = Some((Instant::now(), sample));
+        if changed {
+            let _ = self.events.send(StreamEvent::HealthChanged {
+                score: sample.score,
+                level: sample.level,
+            });
+        }
+        sample
+    }
+
+    /// Keying material for `label` (prefixed `EXPORTER-`) that the node can derive too,
+    /// for provisioning a monitoring appliance without sharing the session keys.
+    pub fn export_keying_material(
+        &self,
+        label: &str,
+        len: usize,
+    ) -> Result<Vec<u8>, AlpineSdkError> {
+        Ok(self.session.export_keying_material(label, len)?)
+    }
+
+    /// Cipher suite and negotiated options of the session, for audit logs.
+    pub fn security_report(&self) -> Option<SessionSecurityReport> {
+        self.session.security_report()
+    }
+
+    /// Capabilities the node currently offers: those of the handshake, or the latest the
+    /// node pushed since.
+    pub fn capabilities(&self) -> Option<CapabilitySet> {
+        self.session
+            .established()
+            .map(|established| established.capabilities)
+    }
+
+    /// Identity the node presented in the handshake.
+    pub fn device_identity(&self) -> Option<DeviceIdentity> {
+        self.session
+            .established()
+            .map(|established| established.device_identity)
+    }
+
+    /// Inventory record of the connected node: its identity, address, current
+    /// capabilities, and health. The patch is left for the application to fill in.
+    pub fn inventory_record(&self) -> Option<DeviceRecord> {
+        let established = self.session.established()?;
+        let health = self.health();
+        Some(DeviceRecord {
+            address: Some(self.remote_addr),
+            capabilities: Some(established.capabilities),
+            health: Some(DeviceHealth {
+                score: health.score,
+                level: health.level,
+            }),
+            ..DeviceRecord::from_identity(&established.device_identity)
+        })
+    }
+
+    /// Stops the background tasks, tells the node the session is over, and closes it.
+    ///
+    /// Tasks finish what they are doing rather than being cut off mid-send, so the
+    /// control channel is free to send the node a `SessionClose`; events already raised are
+    /// still published. Whatever is still running after [`SHUTDOWN_TIMEOUT`] is aborted.
     pub async fn close(mut self) {
+        self.closed = true;
+        let deadline = tokio::time::Instant::now() + SHUTDOWN_TIMEOUT;
+        let _ = self.shutdown.send(true);
+        if let Some(mut handle) = self.keepalive_handle.take() {
+            join_by(&mut handle, deadline).await;
+        }
+        join_by(&mut self.capability_handle, deadline).await;
+        if let Ok(mut channel) =
+            tokio::time::timeout_at(deadline, self.control_channel.lock()).await
+        {
+            // Best-effort: a node that misses it times the session out instead.
+            let _ = tokio::time::timeout_at(deadline, self.control.close(&mut channel, None)).await;
+        }
         self.session.close();
-        if let Some(handle) = self.keepalive_handle.take() {
-            handle.abort();
+        join_by(&mut self.forward_handle, deadline).await;
+        self.bus.publish(AlpineEvent::SessionState {
+            remote_addr: self.remote_addr,
+            state: self.session.state().name(),
+            reason: None,
+        });
+    }
+
+    /// Sends an authenticated control operation and waits for the node's ack.
+    ///
+    /// Retransmits with exponential backoff through the session's reliable control channel.
+    pub async fn send_control(
+        &self,
+        op: ControlOp,
+        payload: Value,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let mut channel = self.control_channel.lock().await;
+        // ALPINE keepalives are one-way, so acknowledged control round trips stand in as
+        // the session's liveness RTT samples.
+        let sent = Instant::now();
+        let result = self.control.send(&mut channel, op.clone(), payload).await;
+        self.publish_control(op, sent, result.map_err(Into::into))
+            .inspect(|_| self.record_rtt(sent.elapsed()))
+    }
+
+    /// Sends a long-running control operation, passing each interim progress update from
+    /// the node to `on_progress` until the final ack arrives.
+    pub async fn send_control_with_progress<F>(
+        &self,
+        op: ControlOp,
+        payload: Value,
+        mut on_progress: F,
+    ) -> Result<Acknowledge, AlpineSdkError>
+    where
+        F: FnMut(&ControlProgress),
+    {
+        let mut channel = self.control_channel.lock().await;
+        let sent = Instant::now();
+        let result: Result<Acknowledge, AlpineSdkError> = async {
+            let mut call = self.control.call(&mut channel, op.clone(), payload).await?;
+            while let Some(update) = call.next().await {
+                match update? {
+                    ControlUpdate::Progress(progress) => on_progress(&progress),
+                    ControlUpdate::Acked(ack) => return Ok(ack),
+                }
+            }
+            Err(HandshakeError::Protocol("control call ended without an ack".into()).into())
+        }
+        .await;
+        self.publish_control(op, sent, result)
+    }
+
+    /// Sends a control operation under `policy` instead of the configured retry policy.
+    pub async fn send_control_with_policy(
+        &self,
+        op: ControlOp,
+        payload: Value,
+        policy: RetryPolicy,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let mut channel = self.control_channel.lock().await;
+        let sent = Instant::now();
+        let result = self
+            .control
+            .send_with(&mut channel, op.clone(), payload, policy)
+            .await;
+        self.publish_control(op, sent, result.map_err(Into::into))
+            .inspect(|_| self.record_rtt(sent.elapsed()))
+    }
+
+    /// Publishes the outcome of a control round trip started at `sent`, and passes it on.
+    fn publish_control(
+        &self,
+        op: ControlOp,
+        sent: Instant,
+        result: Result<Acknowledge, AlpineSdkError>,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let remote_addr = self.remote_addr;
+        self.bus.publish(match &result {
+            Ok(ack) => AlpineEvent::ControlAcked {
+                remote_addr,
+                op,
+                ok: ack.ok,
+                rtt: sent.elapsed(),
+            },
+            Err(err) => AlpineEvent::ControlFailed {
+                remote_addr,
+                op,
+                error: err.to_string(),
+            },
+        });
+        result
+    }
+
+    fn record_rtt(&self, rtt: Duration) {
+        self.session.record_keepalive_rtt(rtt);
+        if let Some(stream) = &self.stream {
+            stream.record_rtt(rtt);
         }
+        self.refresh_health();
+    }
+
+    /// Measures the round trip to the node with an authenticated echo on the streaming
+    /// path and feeds it into the session metrics and the stream's bandwidth estimate.
+    ///
+    /// Gives RTT samples while no receiver reports or control calls are flowing. Waits up
+    /// to `timeout` for the reply.
+    pub async fn probe_rtt(&self, timeout: Duration) -> Result<EchoSample, AlpineSdkError> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        let seq = stream.send_echo()?;
+        self.await_echo(stream, seq, Instant::now() + timeout).await
+    }
+
+    /// Finds the largest datagram that crosses the path to the node and back, and
+    /// fragments stream frames to it from then on.
+    ///
+    /// Tries padded echo probes from the configured MTU, or 1472 bytes, down to 548,
+    /// waiting up to `timeout` for each, and returns the first size answered.
+    pub async fn probe_mtu(&self, timeout: Duration) -> Result<usize, AlpineSdkError> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or(AlpineSdkError::StreamNotStarted)?;
+        let largest = self
+            .transport_config
+            .max_datagram(self.remote_addr)
+            .unwrap_or(MTU_PROBE_SIZES[0]);
+        let smaller = MTU_PROBE_SIZES.into_iter().filter(|&size| size < largest);
+        for size in std::iter::once(largest).chain(smaller) {
+            // A local refusal to send this much is as good as a lost probe.
+            let seq = match stream.send_mtu_probe(size) {
+                Ok(seq) => seq,
+                Err(StreamError::Transport(_)) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            match self.await_echo(stream, seq, Instant::now() + timeout).await {
+                Ok(_) => {
+                    stream.set_max_datagram(Some(size));
+                    return Ok(size);
+                }
+                Err(AlpineSdkError::Timeout { .. }) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Err(AlpineSdkError::Timeout {
+            operation: "mtu probe",
+        })
+    }
+
+    /// Waits for the reply to echo `seq` and records its round trip.
+    async fn await_echo(
+        &self,
+        stream: &AlnpStream<UdpTransport>,
+        seq: u64,
+        deadline: Instant,
+    ) -> Result<EchoSample, AlpineSdkError> {
+        let mut buf = vec![0u8; ECHO_BUFFER];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(AlpineSdkError::Timeout {
+                    operation: "echo reply",
+                });
+            }
+            let received =
+                tokio::time::timeout(remaining, stream.transport().recv_datagram(&mut buf)).await;
+            let len = match received {
+                Ok(received) => received?,
+                Err(_) => {
+                    return Err(AlpineSdkError::Timeout {
+                        operation: "echo reply",
+                    })
+                }
+            };
+            // Stale or foreign datagrams are skipped until the reply arrives.
+            let Ok(reply) = codec::decode_untrusted::<EchoFrame>(&buf[..len]) else {
+                continue;
+            };
+            if reply.seq != seq {
+                // Answers an earlier probe that outlived its wait.
+                stream.on_echo_reply(&reply);
+                continue;
+            }
+            if let Some(sample) = stream.on_echo_reply(&reply) {
+                self.session.record_keepalive_rtt(sample.rtt);
+                return Ok(sample);
+            }
+        }
+    }
+
+    /// Feeds a receiver's delivery report into the stream's bandwidth estimate, which
+    /// caps aggregate frame output across universes.
+    pub fn record_receiver_report(&self, report: &ReceiverReport) {
+        if let Some(stream) = &self.stream {
+            stream.record_receiver_report(report);
+        }
+    }
+
+    /// Acked and failed control calls, retransmissions, and ack latency.
+    pub async fn control_stats(&self) -> ControlStats {
+        self.control_channel.lock().await.stats()
+    }
+
+    /// Datagrams and bytes sent and received on the control socket and, while streaming,
+    /// the stream socket, with send and decode failures.
+    pub fn transport_stats(&self) -> TransportStats {
+        let control = self.control_transport.stats();
+        match &self.stream {
+            Some(stream) => control + stream.transport().stats(),
+            None => control,
+        }
+    }
+
+    /// Asks the node to physically identify itself (e.g. flash or strobe).
+    pub async fn identify(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.send_control(ControlOp::Identify, json!({})).await
+    }
+
+    /// Asks a multi-port node to identify only the fixtures on `port`.
+    pub async fn identify_port(&self, port: PortId) -> Result<Acknowledge, AlpineSdkError> {
+        let request = IdentifyRequest { port: Some(port) };
+        self.send_control(ControlOp::Identify, json!(request)).await
+    }
+
+    /// Switches the node into the named operating mode.
+    pub async fn set_mode(&self, mode: &str) -> Result<Acknowledge, AlpineSdkError> {
+        self.send_control(ControlOp::SetMode, json!({ "mode": mode }))
+            .await
+    }
+
+    /// Requests the node's status; the summary is carried in the ack `detail`.
+    pub async fn get_status(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.send_control(ControlOp::GetStatus, json!({})).await
+    }
+
+    /// Stops frame output and tells the node the silence is deliberate, so it holds the
+    /// last look instead of treating the gap as loss, e.g. during page changes or file
+    /// loads.
+    ///
+    /// Frames are refused with [`alpine::stream::StreamError::StreamingDisabled`] from this call until
+    /// [`AlpineClient::resume_stream`] is acknowledged.
+    pub async fn pause_stream(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.session.set_streaming_enabled(false);
+        self.send_control(ControlOp::PauseStream, json!({})).await
+    }
+
+    /// Tells the node frames are resuming and lifts the gate set by
+    /// [`AlpineClient::pause_stream`] once it acknowledges.
+    pub async fn resume_stream(&self) -> Result<Acknowledge, AlpineSdkError> {
+        let ack = self
+            .send_control(ControlOp::ResumeStream, json!({}))
+            .await?;
+        if ack.ok {
+            self.session.set_streaming_enabled(true);
+        }
+        Ok(ack)
+    }
+
+    /// Whether the stream is paused with [`AlpineClient::pause_stream`].
+    pub fn stream_paused(&self) -> bool {
+        !self.session.streaming_enabled()
+    }
+
+    /// Requests the node's latency percentiles per stage; the report is carried as JSON
+    /// in the ack `detail`.
+    pub async fn get_latency(&self) -> Result<Acknowledge, AlpineSdkError> {
+        self.send_control(ControlOp::GetLatency, json!({})).await
+    }
+
+    /// Pulls the frames the node captured that match `query`, page by page; the node
+    /// must have been started with a capture.
+    ///
+    /// Fails with [`AlpineSdkError::Refused`] when the node refuses a page, e.g. because
+    /// it keeps no capture.
+    pub async fn get_capture(
+        &self,
+        query: CaptureQuery,
+    ) -> Result<Vec<CapturedFrame>, AlpineSdkError> {
+        let mut query = CaptureQuery {
+            cursor: None,
+            ..query
+        };
+        let mut slices = Vec::new();
+        loop {
+            let payload = serde_json::to_value(&query)?;
+            let ack = self.send_control(ControlOp::GetCapture, payload).await?;
+            let detail = ack.detail.unwrap_or_default();
+            if !ack.ok {
+                return Err(AlpineSdkError::Refused {
+                    op: ControlOp::GetCapture,
+                    detail,
+                });
+            }
+            let page: CapturePage = serde_json::from_str(&detail)?;
+            slices.extend(page.slices);
+            match page.next {
+                Some(next) => query.cursor = Some(next),
+                None => return Ok(CapturedFrame::join(slices)),
+            }
+        }
+    }
+
+    /// Has the node drive `universe` of its outputs with `pattern` for `duration`, in
+    /// place of streamed frames, to check wiring during commissioning.
+    pub async fn test_pattern(
+        &self,
+        universe: UniverseId,
+        pattern: TestPattern,
+        duration: Duration,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let request = PatternRequest {
+            universe,
+            pattern: Some(pattern),
+            channels: None,
+            duration_ms: Some(duration.as_millis() as u64),
+        };
+        self.send_control(ControlOp::TestPattern, json!(request))
+            .await
+    }
+
+    /// Sets a single channel, counted from zero, to `level` with every other channel of
+    /// the universe at zero, for a minute or until stopped.
+    pub async fn poke_channel(
+        &self,
+        universe: UniverseId,
+        channel: u16,
+        level: u8,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        self.test_pattern(
+            universe,
+            TestPattern::Poke { channel, level },
+            DEFAULT_PATTERN_DURATION,
+        )
+        .await
+    }
+
+    /// Stops the test pattern on `universe`; its outputs follow the stream again.
+    pub async fn stop_test_pattern(
+        &self,
+        universe: UniverseId,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let request = PatternRequest {
+            universe,
+            pattern: None,
+            channels: None,
+            duration_ms: None,
+        };
+        self.send_control(ControlOp::TestPattern, json!(request))
+            .await
+    }
+
+    /// Reads the node's log entries that match `query`, page by page; the node must have
+    /// been started with a log.
+    ///
+    /// Fails with [`AlpineSdkError::Refused`] when the node refuses a page, e.g. because
+    /// it keeps no log.
+    pub async fn get_logs(&self, query: LogQuery) -> Result<Vec<LogEntry>, AlpineSdkError> {
+        let mut query = query;
+        let mut entries = Vec::new();
+        loop {
+            let page = self.logs_page(&query).await?;
+            entries.extend(page.entries);
+            if !page.more {
+                return Ok(entries);
+            }
+            query.cursor = Some(page.next);
+        }
+    }
+
+    /// Follows the node's log: passes every entry matching `query` to `on_entry` as it
+    /// is logged, polling every `interval` once caught up, until `on_entry` returns
+    /// `false`.
+    ///
+    /// Entries the node overwrote between polls are skipped.
+    pub async fn follow_logs<F>(
+        &self,
+        query: LogQuery,
+        interval: Duration,
+        mut on_entry: F,
+    ) -> Result<(), AlpineSdkError>
+    where
+        F: FnMut(LogEntry) -> bool,
+    {
+        let mut query = query;
+        loop {
+            let page = self.logs_page(&query).await?;
+            for entry in page.entries {
+                if !on_entry(entry) {
+                    return Ok(());
+                }
+            }
+            query.cursor = Some(page.next);
+            if !page.more {
+                tokio::time::sleep(interval).await;
+            }
+        }
+    }
+
+    async fn logs_page(&self, query: &LogQuery) -> Result<LogPage, AlpineSdkError> {
+        let payload = serde_json::to_value(query)?;
+        let ack = self.send_control(ControlOp::GetLogs, payload).await?;
+        let detail = ack.detail.unwrap_or_default();
+        if !ack.ok {
+            return Err(AlpineSdkError::Refused {
+                op: ControlOp::GetLogs,
+                detail,
+            });
+        }
+        Ok(serde_json::from_str(&detail)?)
+    }
+
+    /// Sets the node's clock to this host's, allowing half the mean keepalive round
+    /// trip for the request to arrive; returns the node's clock afterwards.
+    ///
+    /// Fails with [`AlpineSdkError::Refused`] when the node keeps its time from NTP,
+    /// PTP, or GPS.
+    pub async fn set_node_time(&self) -> Result<TimeStatus, AlpineSdkError> {
+        let one_way = self
+            .session
+            .metrics()
+            .keepalive_rtt
+            .mean
+            .map_or(Duration::ZERO, |rtt| rtt / 2);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let request = TimeSet {
+            unix_us: (now + one_way).as_micros() as u64,
+        };
+        self.time(ControlOp::TimeSync, serde_json::to_value(request)?)
+            .await
+    }
+
+    /// Reads the node's clock, where its time comes from, and how far a controller
+    /// moved it.
+    pub async fn get_time(&self) -> Result<TimeStatus, AlpineSdkError> {
+        self.time(ControlOp::GetTime, json!({})).await
+    }
+
+    /// Asks the node to mirror the output it applies, after merging or arbitration, for
+    /// remote visualizers; returns the looks per second granted, which the node caps.
+    ///
+    /// Only observer sessions, see [`AlpineClientBuilder::observer`], may subscribe.
+    /// Read the output from [`AlpineClient::mirrored_output`].
+    pub async fn subscribe_output(
+        &self,
+        subscription: OutputSubscription,
+    ) -> Result<u32, AlpineSdkError> {
+        let payload = serde_json::to_value(&subscription)?;
+        let ack = self
+            .send_control(ControlOp::SubscribeOutput, payload)
+            .await?;
+        let detail = ack.detail.unwrap_or_default();
+        if !ack.ok {
+            return Err(AlpineSdkError::Refused {
+                op: ControlOp::SubscribeOutput,
+                detail,
+            });
+        }
+        detail.parse().map_err(|_| {
+            AlpineSdkError::Internal(format!("node granted an invalid mirror rate: {detail}"))
+        })
+    }
+
+    /// Stops the node mirroring its output to this session.
+    pub async fn unsubscribe_output(&self) -> Result<(), AlpineSdkError> {
+        let stop = OutputSubscription {
+            universes: None,
+            rate_hz: Some(0),
+        };
+        self.subscribe_output(stop).await.map(|_| ())
+    }
+
+    async fn time(&self, op: ControlOp, payload: Value) -> Result<TimeStatus, AlpineSdkError> {
+        let ack = self.send_control(op.clone(), payload).await?;
+        let detail = ack.detail.unwrap_or_default();
+        if !ack.ok {
+            return Err(AlpineSdkError::Refused { op, detail });
+        }
+        Ok(serde_json::from_str(&detail)?)
+    }
+
+    /// Asks the node to reboot; returns how long until it does, unless it vetoes the
+    /// reboot by then.
+    ///
+    /// Fails with [`AlpineSdkError::Refused`] when the node vetoes it, e.g. mid-show.
+    pub async fn reboot(&self) -> Result<Duration, AlpineSdkError> {
+        match self
+            .maintenance(ControlOp::Restart, MaintenanceRequest::default())
+            .await?
+        {
+            MaintenanceReply::Scheduled { after_ms, .. } => Ok(Duration::from_millis(after_ms)),
+            MaintenanceReply::ConfirmRequired { .. } => Err(AlpineSdkError::Internal(
+                "node asked to confirm a reboot".into(),
+            )),
+        }
+    }
+
+    /// First step of a factory reset: returns the nonce to pass to
+    /// [`AlpineClient::confirm_factory_reset`] within the node's confirmation window.
+    /// Nothing is reset yet.
+    pub async fn request_factory_reset(&self) -> Result<String, AlpineSdkError> {
+        match self
+            .maintenance(ControlOp::FactoryReset, MaintenanceRequest::default())
+            .await?
+        {
+            MaintenanceReply::ConfirmRequired { confirm, .. } => Ok(confirm),
+            MaintenanceReply::Scheduled { .. } => Err(AlpineSdkError::Internal(
+                "node scheduled a factory reset without confirmation".into(),
+            )),
+        }
+    }
+
+    /// Confirms a factory reset with the nonce from
+    /// [`AlpineClient::request_factory_reset`]; returns how long until the node resets.
+    ///
+    /// Fails with [`AlpineSdkError::Refused`] when the nonce is unknown or expired, or the
+    /// node vetoes the reset.
+    pub async fn confirm_factory_reset(&self, confirm: &str) -> Result<Duration, AlpineSdkError> {
+        let request = MaintenanceRequest {
+            confirm: Some(confirm.into()),
+            reason: None,
+        };
+        match self.maintenance(ControlOp::FactoryReset, request).await? {
+            MaintenanceReply::Scheduled { after_ms, .. } => Ok(Duration::from_millis(after_ms)),
+            MaintenanceReply::ConfirmRequired { .. } => Err(AlpineSdkError::Refused {
+                op: ControlOp::FactoryReset,
+                detail: "confirmation not accepted".into(),
+            }),
+        }
+    }
+
+    async fn maintenance(
+        &self,
+        op: ControlOp,
+        request: MaintenanceRequest,
+    ) -> Result<MaintenanceReply, AlpineSdkError> {
+        let ack = self.send_control(op.clone(), json!(request)).await?;
+        let detail = ack.detail.unwrap_or_default();
+        if !ack.ok {
+            return Err(AlpineSdkError::Refused { op, detail });
+        }
+        Ok(serde_json::from_str(&detail)?)
+    }
+
+    /// Sends an RDM GET or SET to a responder at or behind the node; the `RdmReply` is
+    /// carried as JSON in the ack `detail`.
+    pub async fn rdm_command(&self, request: &RdmRequest) -> Result<Acknowledge, AlpineSdkError> {
+        let payload = serde_json::to_value(request)?;
+        self.send_control(ControlOp::RdmCommand, payload).await
+    }
+
+    /// Pushes a newer signed revocation list to the node; the ack `detail` is the serial
+    /// the node installed.
+    pub async fn update_revocations(
+        &self,
+        list: &RevocationList,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let payload = serde_json::to_value(list)?;
+        self.send_control(ControlOp::UpdateRevocations, payload)
+            .await
+    }
+
+    /// Uploads `show` to the node in `upload_show` chunks for standalone playback.
+    ///
+    /// Stops at the first refused chunk and returns its ack; otherwise returns the ack of
+    /// the final chunk, by which point the node has stored the show.
+    pub async fn upload_show(&self, show: &Show) -> Result<Acknowledge, AlpineSdkError> {
+        let chunks = ShowChunk::split(show)?;
+        let mut last = None;
+        for chunk in chunks {
+            let payload = serde_json::to_value(&chunk)?;
+            let ack = self.send_control(ControlOp::UploadShow, payload).await?;
+            if !ack.ok {
+                return Ok(ack);
+            }
+            last = Some(ack);
+        }
+        last.ok_or_else(|| AlpineSdkError::Internal("show encoded to no chunks".into()))
+    }
+
+    /// Has the node loop its uploaded show once no controller has streamed for `idle`;
+    /// `Duration::ZERO` disables fallback.
+    pub async fn set_fallback(&self, idle: Duration) -> Result<Acknowledge, AlpineSdkError> {
+        let config = FallbackConfig {
+            idle_ms: idle.as_millis() as u64,
+        };
+        self.send_control(ControlOp::SetFallback, json!(config))
+            .await
+    }
+
+    /// Replaces the node's time-of-day schedule; the node runs it without a controller.
+    pub async fn set_schedule(&self, schedule: &Schedule) -> Result<Acknowledge, AlpineSdkError> {
+        let payload = serde_json::to_value(schedule)?;
+        self.send_control(ControlOp::SetSchedule, payload).await
+    }
+
+    /// Sets how the node merges `universe` when several controllers stream it.
+    pub async fn set_merge_policy(
+        &self,
+        universe: UniverseId,
+        policy: MergePolicy,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let setting = MergeSetting { universe, policy };
+        self.send_control(ControlOp::SetMergePolicy, json!(setting))
+            .await
+    }
+
+    /// Moves `universe` to output `port` of a multi-port node, or back to its default
+    /// port with `None`; the node's capabilities give its port count.
+    pub async fn set_port_map(
+        &self,
+        universe: UniverseId,
+        port: Option<PortId>,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let mapping = PortMapping { universe, port };
+        self.send_control(ControlOp::SetPortMap, json!(mapping))
+            .await
+    }
+
+    /// Universe and output health of each port of a multi-port node, from its status;
+    /// empty when the status is not JSON or reports no ports.
+    pub async fn port_status(&self) -> Result<Vec<PortStatus>, AlpineSdkError> {
+        let ack = self.get_status().await?;
+        let detail = ack.detail.unwrap_or_default();
+        if !ack.ok {
+            return Err(AlpineSdkError::Refused {
+                op: ControlOp::GetStatus,
+                detail,
+            });
+        }
+        // Applications may answer get_status with their own summary.
+        let ports = serde_json::from_str::<serde_json::Value>(&detail)
+            .ok()
+            .and_then(|mut status| status.get_mut("ports").map(serde_json::Value::take));
+        match ports {
+            Some(ports) => Ok(serde_json::from_value(ports)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Registers (or replaces) a named channel group on the node, so frames can refer to
+    /// it by `id`.
+    pub async fn define_group(
+        &self,
+        id: GroupId,
+        name: &str,
+        channels: Vec<u16>,
+    ) -> Result<Acknowledge, AlpineSdkError> {
+        let group = ChannelGroup {
+            id,
+            name: name.to_string(),
+            channels,
+        };
+        self.send_control(ControlOp::DefineGroup, json!(group))
+            .await
     }
 
     /// Builds a signed control envelope for the active session.
@@ -143,4 +1447,503 @@ impl AlpineClient {
     ) -> Result<ControlEnvelope, HandshakeError> {
         self.control.envelope(seq, op, payload)
     }
+
+    /// Seals this session's state into a ticket that another controller can present to
+    /// the node to take over the show.
+    pub fn export_migration_ticket(
+        &self,
+        next_control_seq: u64,
+        priority: u8,
+        universe_priorities: BTreeMap<UniverseId, u8>,
+    ) -> Result<MigrationTicket, AlpineSdkError> {
+        let snapshot = SessionSnapshot::capture(
+            &self.session,
+            next_control_seq,
+            priority,
+            universe_priorities,
+        )?;
+        let keys = self
+            .session
+            .keys()
+            .ok_or_else(|| AlpineSdkError::Internal("session keys missing".into()))?;
+        Ok(MigrationTicket::seal(&snapshot, &keys)?)
+    }
+
+    /// Builds the `Migrate` envelope that presents another controller's ticket to the node.
+    pub fn migration_envelope(
+        &self,
+        seq: u64,
+        ticket: &MigrationTicket,
+    ) -> Result<ControlEnvelope, HandshakeError> {
+        self.control.migration_envelope(seq, ticket)
+    }
+}
+
+/// A client dropped without [`AlpineClient::close`] still tells the node, if the control
+/// channel is idle, and lets its background tasks wind down on their own.
+impl Drop for AlpineClient {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let _ = self.shutdown.send(true);
+        if let Ok(mut channel) = self.control_channel.try_lock() {
+            let notice = channel
+                .next_seq()
+                .and_then(|seq| self.control.close_notice(seq, None));
+            if let Ok(bytes) = notice.and_then(|notice| {
+                codec::to_vec(&HandshakeMessage::SessionClose(notice))
+                    .map_err(|e| HandshakeError::Transport(e.to_string()))
+            }) {
+                let _ = self.control_transport.try_send_datagram(&bytes);
+            }
+        }
+        self.session.close();
+    }
+}
+
+/// Optional settings for [`AlpineClient::builder`].
+#[derive(Clone)]
+pub struct AlpineClientBuilder {
+    local_addr: SocketAddr,
+    remote_addr: SocketAddr,
+    identity: DeviceIdentity,
+    capabilities: CapabilitySet,
+    credentials: NodeCredentials,
+    transport_config: TransportConfig,
+    timing: TimingConfig,
+    limits: SessionLimits,
+    store: Option<Arc<dyn SessionStore>>,
+    control_retry: RetryPolicy,
+    op_retry: HashMap<ControlOp, RetryPolicy>,
+    control_rate: Option<RateLimit>,
+    revocations: Option<Arc<dyn RevocationCheck>>,
+    cipher_suites: Vec<SuiteId>,
+    min_firmware: Option<String>,
+    firmware_policy: Option<Arc<dyn FirmwarePolicy>>,
+    encrypt_frames: bool,
+    underrun_alarm: Option<UnderrunAlarm>,
+    priority: Option<u8>,
+    role: Option<SessionRole>,
+    bus: Option<EventBus>,
+}
+
+impl AlpineClientBuilder {
+    /// Local address to bind; defaults to the unspecified address of the remote's family.
+    pub fn local_addr(mut self, local_addr: SocketAddr) -> Self {
+        self.local_addr = local_addr;
+        self
+    }
+
+    /// Socket options for both the control and streaming sockets.
+    pub fn transport_config(mut self, transport_config: TransportConfig) -> Self {
+        self.transport_config = transport_config;
+        self
+    }
+
+    /// Receive timeout, session timeout, and keep-alive interval.
+    pub fn timing(mut self, timing: TimingConfig) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Maximum session age and frames per key; unlimited by default.
+    ///
+    /// Approaching a limit is reported through [`AlpineClient::subscribe_events`]; once
+    /// reached, frames fail with `RekeyRequired` until a new client connects.
+    pub fn limits(mut self, limits: SessionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Persists resumption tickets keyed by the remote address.
+    ///
+    /// When the store already holds a ticket for the remote, `connect` resumes that session
+    /// instead of running the full handshake, falling back to it if the node refuses.
+    pub fn session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Retry policy for control operations without their own; 5 attempts from 200 ms by
+    /// default.
+    pub fn control_retry(mut self, policy: RetryPolicy) -> Self {
+        self.control_retry = policy;
+        self
+    }
+
+    /// Retry policy for `op`, e.g. a single quick attempt for blackout or patient retries
+    /// for firmware chunks.
+    pub fn control_op_retry(mut self, op: ControlOp, policy: RetryPolicy) -> Self {
+        self.op_retry.insert(op, policy);
+        self
+    }
+
+    /// Paces control operations to `limit`. Nodes asking the client to slow down are
+    /// honoured either way.
+    pub fn control_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.control_rate = Some(limit);
+        self
+    }
+
+    /// Refuses devices whose id or signing key `revocations` lists, such as a shared
+    /// `RevocationStore`.
+    pub fn revocations(mut self, revocations: Arc<dyn RevocationCheck>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// Cipher suites to offer, most preferred first; every supported suite by default.
+    /// The node picks the session's suite from this list.
+    pub fn cipher_suites(mut self, suites: Vec<SuiteId>) -> Self {
+        self.cipher_suites = suites;
+        self
+    }
+
+    /// Refuses nodes whose `firmware_rev` is older than `rev`, failing `connect` with
+    /// [`HandshakeError::Firmware`] carrying both revisions.
+    pub fn min_node_firmware(mut self, rev: impl Into<String>) -> Self {
+        self.min_firmware = Some(rev.into());
+        self
+    }
+
+    /// Asks `policy` whether to connect anyway, in degraded operation, when the node is
+    /// older than [`AlpineClientBuilder::min_node_firmware`].
+    pub fn firmware_policy(mut self, policy: Arc<dyn FirmwarePolicy>) -> Self {
+        self.firmware_policy = Some(policy);
+        self
+    }
+
+    /// Encrypts frames and parity, ratcheting to a fresh key at every keyframe interval
+    /// rollover so a leaked key exposes a few hundred milliseconds of show at most.
+    /// `start_stream` fails with [`StreamError::EncryptionUnsupported`] on nodes without
+    /// `encryption_supported`.
+    pub fn encrypt_frames(mut self) -> Self {
+        self.encrypt_frames = true;
+        self
+    }
+
+    /// Publishes [`StreamEvent::Underrun`] when a universe's achieved frame rate stays
+    /// below `alarm` of its target; per-universe rates and counters are in
+    /// [`AlpineClient::stream_health`] either way.
+    pub fn underrun_alarm(mut self, alarm: UnderrunAlarm) -> Self {
+        self.underrun_alarm = Some(alarm);
+        self
+    }
+
+    /// Priority stated in the handshake; 100 when unset. A node configured for takeovers
+    /// admits a controller over its session limit when it outranks one of the sessions
+    /// there, which then gets [`StreamEvent::Preempted`] and is closed after a grace
+    /// period.
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Connects as a read-only observer, for dashboards and visualizers that watch a
+    /// node without driving it. The node holds the session to monitoring access: its
+    /// frames and state-changing control operations are refused, it never takes over
+    /// another controller, and the role is bound into the session so it cannot be
+    /// altered in transit.
+    pub fn observer(mut self) -> Self {
+        self.role = Some(SessionRole::Observer);
+        self
+    }
+
+    /// Publishes the client's events on `bus`, so one subscription covers several
+    /// clients; each client has a bus of its own by default.
+    pub fn event_bus(mut self, bus: EventBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    /// Performs the handshake and starts the keep-alive task.
+    pub async fn connect(self) -> Result<AlpineClient, AlpineSdkError> {
+        let Self {
+            local_addr,
+            remote_addr,
+            identity,
+            capabilities,
+            credentials,
+            transport_config,
+            timing,
+            limits,
+            store,
+            control_retry,
+            op_retry,
+            control_rate,
+            revocations,
+            cipher_suites,
+            min_firmware,
+            firmware_policy,
+            encrypt_frames,
+            underrun_alarm,
+            priority,
+            role,
+            bus,
+        } = self;
+        let context = HandshakeContext {
+            revocations,
+            cipher_suites,
+            required_firmware_rev: min_firmware,
+            firmware_policy,
+            controller_priority: priority,
+            role,
+            ..HandshakeContext::default()
+        };
+        let key_exchange = X25519KeyExchange::new();
+        let authenticator = Ed25519Authenticator::new(credentials);
+
+        let control_transport = UdpTransport::bind(local_addr, remote_addr, &transport_config)
+            .map_err(|source| AlpineSdkError::Bind {
+                addr: local_addr,
+                source,
+            })?;
+        let mut transport = TimeoutTransport::new(control_transport.clone(), timing.recv_timeout());
+        let ticket = store
+            .as_ref()
+            .and_then(|store| store.load(&remote_addr.to_string()).ok().flatten());
+        let mut resumed = None;
+        if let Some(ticket) = ticket {
+            if let Ok(session) = AlnpSession::resume(
+                &ticket,
+                X25519KeyExchange::new(),
+                context.clone(),
+                timing,
+                &mut transport,
+            )
+            .await
+            {
+                resumed = Some((session, ticket.config_id));
+            }
+        }
+        let (session, resumed) = match resumed {
+            Some((session, config_id)) => (session, Some(config_id)),
+            None => {
+                let session = AlnpSession::connect_with_timing(
+                    identity,
+                    capabilities,
+                    authenticator,
+                    key_exchange,
+                    context,
+                    timing,
+                    &mut transport,
+                )
+                .await?;
+                (session, None)
+            }
+        };
+        session.set_limits(limits);
+
+        let transport = Arc::new(Mutex::new(transport));
+        let (shutdown, _) = watch::channel(false);
+        let keepalive_handle = tokio::spawn(keepalive::keepalive_until(
+            transport.clone(),
+            timing.keepalive_interval(),
+            session
+                .established()
+                .ok_or_else(|| AlpineSdkError::Internal("session missing after handshake".into()))?
+                .session_id,
+            session.clone(),
+            shutdown.subscribe(),
+        ));
+
+        let established = session
+            .established()
+            .ok_or_else(|| AlpineSdkError::Internal("session missing after handshake".into()))?;
+        let device_uuid = Uuid::parse_str(&established.device_identity.device_id)
+            .unwrap_or_else(|_| Uuid::new_v4());
+        let control_crypto = ControlCrypto::new(
+            session
+                .keys()
+                .ok_or_else(|| AlpineSdkError::Internal("session keys missing".into()))?,
+        );
+        let mut control = ControlClient::new(device_uuid, established.session_id, control_crypto);
+        if let Some(limit) = control_rate {
+            control = control.with_rate_limit(limit);
+        }
+        let control = Arc::new(control);
+        let (events, _) = broadcast::channel(64);
+        let (mirror, _) = broadcast::channel(MIRROR_CAPACITY);
+        let mut control_channel =
+            ReliableControlChannel::new(transport).with_retry_policy(control_retry);
+        for (op, policy) in op_retry {
+            control_channel.set_op_policy(op, policy);
+        }
+        let control_channel = Arc::new(Mutex::new(control_channel));
+        let capability_handle = tokio::spawn(watch_capabilities(
+            Arc::downgrade(&control_channel),
+            control.clone(),
+            session.clone(),
+            events.clone(),
+            mirror.clone(),
+            shutdown.subscribe(),
+        ));
+        let bus = bus.unwrap_or_default();
+        bus.publish(AlpineEvent::Connected {
+            remote_addr,
+            session_id: established.session_id,
+            resumed: resumed.is_some(),
+        });
+        let forward_handle = tokio::spawn(forward_events(
+            events.subscribe(),
+            session.clone(),
+            remote_addr,
+            bus.clone(),
+            shutdown.subscribe(),
+        ));
+
+        let client = AlpineClient {
+            session,
+            control_channel,
+            local_addr,
+            remote_addr,
+            transport_config,
+            control_transport,
+            stream: None,
+            control,
+            keepalive_handle: Some(keepalive_handle),
+            capability_handle,
+            shutdown,
+            closed: false,
+            events,
+            mirror,
+            bus,
+            forward_handle,
+            health: Default::default(),
+            store,
+            resumed,
+            encrypt_frames,
+            underrun_alarm,
+        };
+        client.save_ticket();
+        Ok(client)
+    }
+}
+
+/// Applies capability updates, reports preemption notices, passes on mirrored output, and
+/// records keepalive stats the node pushes, until the client shuts down.
+///
+/// Control calls hold the channel while they run and keep updates that arrive meanwhile,
+/// so each check first takes those and then listens briefly on the idle channel.
+async fn watch_capabilities(
+    channel: Weak<Mutex<ReliableControlChannel<SharedTransport>>>,
+    control: Arc<ControlClient>,
+    session: AlnpSession,
+    events: broadcast::Sender<StreamEvent>,
+    mirror: broadcast::Sender<MirrorSlice>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut poll = tokio::time::interval(CAPABILITY_POLL);
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {}
+            _ = shutdown.wait_for(|stop| *stop) => return,
+        }
+        let Some(shared) = channel.upgrade() else {
+            return;
+        };
+        let Ok(mut channel) = shared.try_lock() else {
+            continue;
+        };
+        while let Ok(Some(notice)) = channel.next_notice(CAPABILITY_LISTEN).await {
+            // Forged, replayed, and out-of-order notices are ignored.
+            match notice {
+                DeviceNotice::Capabilities(update) => {
+                    if control.accept_capability_update(&update, &session).is_ok() {
+                        let _ = events.send(StreamEvent::CapabilitiesChanged {
+                            max_channels: update.capabilities.max_channels,
+                            max_frame_rate: update.capabilities.max_frame_rate,
+                        });
+                    }
+                }
+                DeviceNotice::Preempted(notice) => {
+                    if let Ok(grace) = control.accept_preemption(&notice) {
+                        let _ = events.send(StreamEvent::Preempted {
+                            priority: notice.priority,
+                            grace,
+                        });
+                    }
+                }
+                DeviceNotice::Output(output) => {
+                    if let Ok(slice) = control.accept_mirrored_output(&output) {
+                        let _ = mirror.send(slice);
+                    }
+                }
+                DeviceNotice::Stats(stats) => session.record_peer_stats(stats),
+            }
+        }
+    }
+}
+
+/// Republishes stream events and session state changes on `bus` until every stream
+/// event sender is gone, or the client shuts down and the events already raised are out.
+async fn forward_events(
+    mut events: broadcast::Receiver<StreamEvent>,
+    session: AlnpSession,
+    remote_addr: SocketAddr,
+    bus: EventBus,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut poll = tokio::time::interval(STATE_POLL);
+    let mut last_state = session.state().name();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => bus.publish(AlpineEvent::Stream { remote_addr, event }),
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = shutdown.wait_for(|stop| *stop) => break,
+            _ = poll.tick() => {
+                let state = session.state().name();
+                if state != last_state {
+                    last_state = state;
+                    bus.publish(AlpineEvent::SessionState {
+                        remote_addr,
+                        state,
+                        reason: session.metrics().last_error,
+                    });
+                }
+            }
+        }
+    }
+    loop {
+        match events.try_recv() {
+            Ok(event) => bus.publish(AlpineEvent::Stream { remote_addr, event }),
+            Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+/// Waits for `handle` until `deadline`, aborting the task if it is still running then.
+async fn join_by(handle: &mut JoinHandle<()>, deadline: tokio::time::Instant) {
+    if tokio::time::timeout_at(deadline, &mut *handle)
+        .await
+        .is_err()
+    {
+        handle.abort();
+    }
+}
+
+/// Orders resolved addresses IPv6-first, alternating address families.
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut ordered = Vec::new();
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+fn unspecified_for(remote: SocketAddr) -> SocketAddr {
+    match remote {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    }
 }
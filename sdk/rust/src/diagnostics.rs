@@ -0,0 +1,277 @@
+//! Pre-show network readiness probe.
+//!
+//! [`ReadinessProbe::probe`] opens a session with one node and measures what the venue
+//! network between here and there actually delivers: how long the handshake takes, the
+//! spread of control round trips, the path MTU, and, by streaming at rising frame rates,
+//! the fastest rate the node receives without loss. Installers get numbers to sign off
+//! on instead of a guess.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use alpine::crypto::identity::NodeCredentials;
+use alpine::latency::Percentiles;
+use alpine::messages::{CapabilitySet, ChannelFormat, DeviceIdentity, UniverseId};
+use alpine::profile::StreamProfile;
+use alpine::session::TimingConfig;
+use tokio::time::MissedTickBehavior;
+
+use crate::client::AlpineClient;
+use crate::error::AlpineSdkError;
+
+/// Frame rates the load test steps through unless told otherwise, capped at the node's.
+pub const DEFAULT_LOAD_RATES: [u32; 4] = [30, 44, 60, 100];
+
+/// Keepalive interval during the probe, so the node's received-frame counters come back
+/// shortly after each load step.
+const PROBE_KEEPALIVE: Duration = Duration::from_millis(250);
+
+/// Longest wait for each padded echo of the MTU probe.
+const MTU_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// One frame rate of the load test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadStep {
+    pub target_fps: u32,
+    /// Frames per second per universe that actually went out.
+    pub achieved_fps: f64,
+    /// Frames sent across every universe.
+    pub frames_sent: u64,
+    /// Frames the node's keepalive stats say arrived; `None` if it reports none.
+    pub frames_received: Option<u64>,
+    /// Fraction of sent frames that did not arrive.
+    pub loss_ratio: Option<f64>,
+}
+
+impl LoadStep {
+    fn sustained(&self, tolerance: f64) -> bool {
+        self.achieved_fps >= f64::from(self.target_fps) * 0.95
+            && self.loss_ratio.is_some_and(|loss| loss <= tolerance)
+    }
+}
+
+/// Outcome of [`ReadinessProbe::probe`].
+#[derive(Debug, Clone)]
+pub struct ReadinessReport {
+    pub remote: SocketAddr,
+    /// Time from the first handshake message to a ready session.
+    pub handshake: Duration,
+    /// Round trips of acknowledged `get_status` requests, in microseconds.
+    pub control_rtt: Percentiles,
+    /// Control requests that got no acknowledgement.
+    pub control_failures: usize,
+    /// Largest datagram that crossed the path both ways; `None` if no echo came back.
+    pub mtu: Option<usize>,
+    /// Load test results in rising frame rate.
+    pub load: Vec<LoadStep>,
+    /// Fastest load step sent in full and received within the loss tolerance.
+    pub sustainable_fps: Option<u32>,
+}
+
+/// Measures the network path to a node before the show.
+pub struct ReadinessProbe {
+    identity: DeviceIdentity,
+    credentials: NodeCredentials,
+    control_samples: usize,
+    load_rates: Vec<u32>,
+    load_step: Duration,
+    universes: u16,
+    loss_tolerance: f64,
+}
+
+impl ReadinessProbe {
+    /// Opens sessions as `identity`, timing 20 control round trips and stepping a load
+    /// of four 512-channel universes through [`DEFAULT_LOAD_RATES`] for two seconds each.
+    pub fn new(identity: DeviceIdentity, credentials: NodeCredentials) -> Self {
+        Self {
+            identity,
+            credentials,
+            control_samples: 20,
+            load_rates: DEFAULT_LOAD_RATES.to_vec(),
+            load_step: Duration::from_secs(2),
+            universes: 4,
+            loss_tolerance: 0.01,
+        }
+    }
+
+    /// Number of `get_status` round trips to time.
+    pub fn control_samples(mut self, samples: usize) -> Self {
+        self.control_samples = samples;
+        self
+    }
+
+    /// Frame rates to step through, and how long to hold each; rates past the node's
+    /// `max_frame_rate` are left out.
+    pub fn load_steps(mut self, rates: impl IntoIterator<Item = u32>, step: Duration) -> Self {
+        self.load_rates = rates.into_iter().collect();
+        self.load_rates.sort_unstable();
+        self.load_step = step;
+        self
+    }
+
+    /// Universes streamed at once during the load test, numbered from 1.
+    pub fn universes(mut self, universes: u16) -> Self {
+        self.universes = universes.max(1);
+        self
+    }
+
+    /// Loss a load step may show and still count as sustainable; 1% by default.
+    pub fn loss_tolerance(mut self, tolerance: f64) -> Self {
+        self.loss_tolerance = tolerance;
+        self
+    }
+
+    /// Connects to the node at `remote`, runs every measurement, and closes the session.
+    ///
+    /// Frames stream at full level changes on the probed universes, so run it before
+    /// fixtures are patched or with the node's outputs disabled.
+    pub async fn probe(&self, remote: SocketAddr) -> Result<ReadinessReport, AlpineSdkError> {
+        let timing = TimingConfig::new(
+            TimingConfig::default().recv_timeout(),
+            TimingConfig::default().session_timeout(),
+            PROBE_KEEPALIVE,
+        )
+        .map_err(|err| AlpineSdkError::Internal(err.to_string()))?;
+        let started = Instant::now();
+        let mut client = AlpineClient::builder(
+            remote,
+            self.identity.clone(),
+            CapabilitySet::default(),
+            self.credentials.clone(),
+        )
+        .timing(timing)
+        .connect()
+        .await?;
+        let handshake = client
+            .metrics()
+            .handshake_duration
+            .unwrap_or_else(|| started.elapsed());
+        let result = self.measure(&mut client, remote, handshake).await;
+        client.close().await;
+        result
+    }
+
+    async fn measure(
+        &self,
+        client: &mut AlpineClient,
+        remote: SocketAddr,
+        handshake: Duration,
+    ) -> Result<ReadinessReport, AlpineSdkError> {
+        let mut rtts = Vec::with_capacity(self.control_samples);
+        let mut control_failures = 0;
+        for _ in 0..self.control_samples {
+            let sent = Instant::now();
+            match client.get_status().await {
+                Ok(_) => rtts.push(sent.elapsed().as_micros() as u64),
+                Err(_) => control_failures += 1,
+            }
+        }
+
+        // Nodes too slow for the auto profile's 40 Hz still take the install one.
+        match client.start_stream(StreamProfile::auto()).await {
+            Err(AlpineSdkError::Profile(_)) => {
+                client.start_stream(StreamProfile::install()).await?;
+            }
+            started => {
+                started?;
+            }
+        }
+        let mtu = client.probe_mtu(MTU_PROBE_TIMEOUT).await.ok();
+
+        let max_rate = client
+            .capabilities()
+            .and_then(|capabilities| capabilities.max_frame_rate)
+            .filter(|&rate| rate > 0);
+        let channels = client
+            .capabilities()
+            .map_or(512, |capabilities| capabilities.max_channels.min(512))
+            as usize;
+        let mut load = Vec::new();
+        for &rate in &self.load_rates {
+            if max_rate.is_some_and(|max| rate > max) {
+                break;
+            }
+            load.push(self.load(client, rate, channels).await?);
+        }
+        let sustainable_fps = load
+            .iter()
+            .filter(|step| step.sustained(self.loss_tolerance))
+            .map(|step| step.target_fps)
+            .max();
+        Ok(ReadinessReport {
+            remote,
+            handshake,
+            control_rtt: Percentiles::of(rtts),
+            control_failures,
+            mtu,
+            load,
+            sustainable_fps,
+        })
+    }
+
+    /// Streams every probed universe at `rate` for one step, then waits for the node's
+    /// counters to catch up.
+    async fn load(
+        &self,
+        client: &AlpineClient,
+        rate: u32,
+        channels: usize,
+    ) -> Result<LoadStep, AlpineSdkError> {
+        let universes: Vec<UniverseId> = (1..=self.universes).collect();
+        let sent_before = frames_sent(client, &universes);
+        let received_before = frames_received(client);
+        let mut ticks = tokio::time::interval(Duration::from_secs(1) / rate.max(1));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let started = Instant::now();
+        let mut level = 0u16;
+        while started.elapsed() < self.load_step {
+            ticks.tick().await;
+            // Every frame changes, so none is held back as a static refresh.
+            level = level.wrapping_add(1);
+            for &universe in &universes {
+                client.send_universe_frame(
+                    universe,
+                    ChannelFormat::U8,
+                    &vec![level % 256; channels],
+                    100,
+                    None,
+                )?;
+            }
+        }
+        let elapsed = started.elapsed();
+        let frames_sent = frames_sent(client, &universes) - sent_before;
+        tokio::time::sleep(PROBE_KEEPALIVE * 4).await;
+        // No stats before the first step means the node had received nothing yet.
+        let frames_received =
+            frames_received(client).map(|after| after.saturating_sub(received_before.unwrap_or(0)));
+        Ok(LoadStep {
+            target_fps: rate,
+            achieved_fps: frames_sent as f64 / f64::from(self.universes) / elapsed.as_secs_f64(),
+            frames_sent,
+            frames_received,
+            loss_ratio: frames_received
+                .filter(|_| frames_sent > 0)
+                .map(|received| 1.0 - (received as f64 / frames_sent as f64).min(1.0)),
+        })
+    }
+}
+
+/// Frames the stream has sent on `universes`.
+fn frames_sent(client: &AlpineClient, universes: &[UniverseId]) -> u64 {
+    client.stream_health().map_or(0, |health| {
+        health
+            .universes
+            .iter()
+            .filter(|entry| universes.contains(&entry.universe))
+            .map(|entry| entry.frames_sent)
+            .sum()
+    })
+}
+
+/// Frames the node last reported receiving.
+fn frames_received(client: &AlpineClient) -> Option<u64> {
+    client
+        .metrics()
+        .peer_stats
+        .map(|stats| stats.frames_received)
+}
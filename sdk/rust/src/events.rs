@@ -0,0 +1,136 @@
+//! One event stream for everything the SDK observes.
+//!
+//! Session state, stream adaptation and recovery, control round trips, and discovery
+//! replies each had their own mechanism. Every [`AlpineClient`](crate::AlpineClient)
+//! publishes all of them as [`AlpineEvent`]s on an [`EventBus`]; hand one bus to several
+//! clients with [`AlpineClientBuilder::event_bus`](crate::AlpineClientBuilder::event_bus)
+//! and to a [`DiscoveryClient`](crate::DiscoveryClient), and subscribe once. A
+//! [`SessionManager`](crate::SessionManager) puts its sessions on its own bus and adds
+//! events for the devices it manages.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use alpine::messages::ControlOp;
+use alpine::stream::{FrameOutcome, StreamEvent};
+use tokio::sync::broadcast;
+
+use crate::manager::ConnectTier;
+
+/// Events a subscriber can fall behind by before it observes a lag error.
+pub const EVENT_BUS_CAPACITY: usize = 256;
+
+/// Something a client or discovery helper observed. Events name the remote they concern.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum AlpineEvent {
+    /// A session finished its handshake, or resumed a stored one.
+    Connected {
+        remote_addr: SocketAddr,
+        session_id: uuid::Uuid,
+        resumed: bool,
+    },
+    /// The session moved to another state, e.g. `streaming`, `failed`, or `closed`; see
+    /// `SessionState::name`. `reason` is the session's last error once it failed.
+    SessionState {
+        remote_addr: SocketAddr,
+        state: &'static str,
+        reason: Option<String>,
+    },
+    /// An adaptation, recovery, session-limit, capability, or health event of the stream.
+    Stream {
+        remote_addr: SocketAddr,
+        event: StreamEvent,
+    },
+    /// What became of a frame sent with
+    /// [`AlpineClient::send_tracked_frame`](crate::AlpineClient::send_tracked_frame):
+    /// sent, skipped by pacing, or failed with the given error.
+    FrameResult {
+        remote_addr: SocketAddr,
+        token: u64,
+        outcome: Result<FrameOutcome, String>,
+    },
+    /// The node acknowledged a control operation.
+    ControlAcked {
+        remote_addr: SocketAddr,
+        op: ControlOp,
+        ok: bool,
+        rtt: Duration,
+    },
+    /// A control operation failed without an ack, e.g. after its retries ran out.
+    ControlFailed {
+        remote_addr: SocketAddr,
+        op: ControlOp,
+        error: String,
+    },
+    /// A discovery reply arrived; it has not been verified yet.
+    Discovered {
+        peer: SocketAddr,
+        device_id: String,
+        interface: Option<String>,
+    },
+    /// A datagram received during discovery was oversized or not a reply, and was set
+    /// aside.
+    DiscoveryQuarantined {
+        peer: SocketAddr,
+        interface: Option<String>,
+        reason: String,
+    },
+    /// A [`SessionManager`](crate::SessionManager) opened a session with a device it
+    /// found.
+    DeviceConnected {
+        device_id: String,
+        peer: SocketAddr,
+        tier: ConnectTier,
+    },
+    /// A managed session failed or closed; the device waits for
+    /// [`SessionManager::reconnect`](crate::SessionManager::reconnect).
+    DeviceLost {
+        device_id: String,
+        peer: SocketAddr,
+        tier: ConnectTier,
+        state: &'static str,
+    },
+    /// A lost device has a fresh session, and its scheduled universes moved over to it.
+    DeviceFailedOver {
+        device_id: String,
+        peer: SocketAddr,
+        tier: ConnectTier,
+    },
+    /// A managed handshake failed, after any busy retries.
+    DeviceConnectFailed {
+        device_id: String,
+        peer: SocketAddr,
+        tier: ConnectTier,
+        reason: String,
+    },
+}
+
+/// Broadcast channel of [`AlpineEvent`]s; clones publish to the same subscribers.
+///
+/// Publishing never blocks and is a no-op without subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<AlpineEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_BUS_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AlpineEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: AlpineEvent) {
+        let _ = self.sender.send(event);
+    }
+}
@@ -0,0 +1,169 @@
+//! Composite connection health per session.
+//!
+//! Operator UIs want one green, yellow, or red light per node rather than four numbers.
+//! [`HealthScorer`] folds keep-alive RTT, the worst loss and late-frame rates, and the
+//! adaptation state into a 0–100 score and a [`HealthLevel`]. Levels worsen as soon as
+//! the score drops below their floor but only improve after the score has stayed clear
+//! of the better level's floor for several samples, so a light does not flicker on a
+//! connection hovering at a boundary.
+
+use std::time::Duration;
+
+use alpine::session::metrics::SessionMetrics;
+use alpine::stream::{HealthLevel, StreamHealth};
+
+/// Lowest score that counts as [`HealthLevel::Good`].
+pub const GOOD_FLOOR: u8 = 80;
+
+/// Lowest score that counts as [`HealthLevel::Degraded`]; anything below is critical.
+pub const DEGRADED_FLOOR: u8 = 50;
+
+/// Points above a better level's floor the score must reach before the level improves.
+pub const HYSTERESIS_MARGIN: u8 = 5;
+
+/// Consecutive samples above the margin needed to improve by one level.
+pub const RECOVERY_SAMPLES: u32 = 3;
+
+/// Signals a score is computed from.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HealthInputs {
+    /// Latest keep-alive or control round trip.
+    pub rtt: Option<Duration>,
+    /// Highest loss ratio of any universe, in `[0, 1]`.
+    pub loss_ratio: f64,
+    /// Highest late-frame rate of any universe, in `[0, 1]`.
+    pub late_rate: f64,
+    /// Universes in recovery after sustained or burst loss.
+    pub recovering: usize,
+    /// Universes held in degraded-safe mode.
+    pub degraded_safe: usize,
+    /// Whether output is pressing against the bandwidth estimate.
+    pub congested: bool,
+}
+
+impl HealthInputs {
+    /// Inputs from a session's metrics and, once streaming, its stream health.
+    pub fn collect(metrics: &SessionMetrics, stream: Option<&StreamHealth>) -> Self {
+        let mut inputs = Self {
+            rtt: metrics.keepalive_rtt.last,
+            ..Self::default()
+        };
+        if let Some(stream) = stream {
+            inputs.loss_ratio = stream.worst_loss_ratio;
+            inputs.late_rate = stream
+                .universes
+                .iter()
+                .map(|universe| universe.metrics.late_frame_rate)
+                .fold(0.0, f64::max);
+            inputs.recovering = stream.recovering;
+            inputs.degraded_safe = stream.degraded_safe;
+            inputs.congested = stream.congested;
+        }
+        inputs
+    }
+
+    /// 100 minus penalties: up to 30 for RTT past 10 ms (full at 250 ms), 40 for loss
+    /// (full at 8%), 20 for late frames (full at 10%), and 20, 10, and 10 for
+    /// degraded-safe universes, recovery, and congestion.
+    pub fn score(&self) -> u8 {
+        let rtt_ms = self.rtt.map_or(0.0, |rtt| rtt.as_secs_f64() * 1_000.0);
+        let mut penalty = ((rtt_ms - 10.0) / 240.0 * 30.0).clamp(0.0, 30.0);
+        penalty += (self.loss_ratio * 500.0).clamp(0.0, 40.0);
+        penalty += (self.late_rate * 200.0).clamp(0.0, 20.0);
+        if self.degraded_safe > 0 {
+            penalty += 20.0;
+        }
+        if self.recovering > 0 {
+            penalty += 10.0;
+        }
+        if self.congested {
+            penalty += 10.0;
+        }
+        (100.0 - penalty).clamp(0.0, 100.0).round() as u8
+    }
+}
+
+/// A scored sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionHealth {
+    pub score: u8,
+    /// Level after hysteresis, which can lag the raw score.
+    pub level: HealthLevel,
+    pub inputs: HealthInputs,
+}
+
+/// Turns successive samples into a level with hysteresis.
+#[derive(Debug, Clone)]
+pub struct HealthScorer {
+    level: HealthLevel,
+    /// Consecutive samples that qualified for the next better level.
+    improving: u32,
+}
+
+impl Default for HealthScorer {
+    fn default() -> Self {
+        Self {
+            level: HealthLevel::Good,
+            improving: 0,
+        }
+    }
+}
+
+impl HealthScorer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn level(&self) -> HealthLevel {
+        self.level
+    }
+
+    /// Scores `inputs` and returns the sample, plus whether the level changed.
+    pub fn update(&mut self, inputs: HealthInputs) -> (ConnectionHealth, bool) {
+        let score = inputs.score();
+        let raw = level_of(score);
+        let previous = self.level;
+        if raw > self.level {
+            self.level = raw;
+            self.improving = 0;
+        } else if raw < self.level && score >= better_floor(self.level) + HYSTERESIS_MARGIN {
+            self.improving += 1;
+            if self.improving >= RECOVERY_SAMPLES {
+                self.level = better(self.level);
+                self.improving = 0;
+            }
+        } else {
+            self.improving = 0;
+        }
+        let health = ConnectionHealth {
+            score,
+            level: self.level,
+            inputs,
+        };
+        (health, self.level != previous)
+    }
+}
+
+fn level_of(score: u8) -> HealthLevel {
+    match score {
+        s if s >= GOOD_FLOOR => HealthLevel::Good,
+        s if s >= DEGRADED_FLOOR => HealthLevel::Degraded,
+        _ => HealthLevel::Critical,
+    }
+}
+
+/// The level one step better than `level`.
+fn better(level: HealthLevel) -> HealthLevel {
+    match level {
+        HealthLevel::Critical => HealthLevel::Degraded,
+        _ => HealthLevel::Good,
+    }
+}
+
+/// Floor of the level one step better than `level`.
+fn better_floor(level: HealthLevel) -> u8 {
+    match better(level) {
+        HealthLevel::Good => GOOD_FLOOR,
+        _ => DEGRADED_FLOOR,
+    }
+}
@@ -0,0 +1,27 @@
+use std::net::SocketAddr;
+
+use alpine::crypto::identity::NodeCredentials;
+use alpine::messages::{CapabilitySet, DeviceIdentity};
+
+use crate::client::AlpineClient;
+use crate::error::AlpineSdkError;
+
+/// Opens a session with `address`, either `ip:port` or a `host:port` resolved and raced
+/// by [`AlpineClient::connect_host`].
+pub(crate) async fn connect_address(
+    address: &str,
+    identity: DeviceIdentity,
+    capabilities: CapabilitySet,
+    credentials: NodeCredentials,
+) -> Result<AlpineClient, AlpineSdkError> {
+    if let Ok(remote_addr) = address.parse::<SocketAddr>() {
+        return AlpineClient::builder(remote_addr, identity, capabilities, credentials)
+            .connect()
+            .await;
+    }
+    let (host, port) = address
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+        .ok_or_else(|| AlpineSdkError::InvalidInput(format!("bad device address {address}")))?;
+    AlpineClient::connect_host(host, port, identity, capabilities, credentials).await
+}
@@ -0,0 +1,242 @@
+//! gRPC control surface.
+//!
+//! Backends written in other languages generate a client from
+//! `proto/alpine_control.proto` and drive devices through a [`GrpcControl`] server. It
+//! serves the sessions of a [`SessionManager`], which holds the controller identity and
+//! credentials: `Connect` opens a managed session, sessions are named by device id, and
+//! status, control ops, and stream events are proxied for any session the manager holds,
+//! including those it opened by discovery. Like the REST
+//! gateway it does not authenticate its own callers, so serve it on a trusted network or
+//! behind TLS and auth middleware, and it shares the REST gateway's [`ControlPolicy`]:
+//! `SendControl` refuses ops the policy does not allow with `PERMISSION_DENIED`.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use alpine::messages::ControlOp;
+use alpine::stream::StreamEvent;
+use serde_json::Value;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::client::AlpineClient;
+use crate::error::AlpineSdkError;
+use crate::interop::ControlPolicy;
+use crate::manager::{ConnectTier, SessionManager};
+
+pub mod proto;
+
+pub use proto::alpine_control_server::{AlpineControl, AlpineControlServer};
+
+/// Events of one session, as sent to `SubscribeEvents` callers.
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<proto::StreamEvent, Status>> + Send>>;
+
+/// [`AlpineControl`] implementation over the sessions of a [`SessionManager`].
+pub struct GrpcControl {
+    manager: Arc<SessionManager>,
+    policy: ControlPolicy,
+}
+
+impl GrpcControl {
+    /// Server for the sessions of `manager`, shared with the rest of the application.
+    /// `SendControl` runs read-only ops and `identify` only.
+    pub fn new(manager: Arc<SessionManager>) -> Self {
+        Self {
+            manager,
+            policy: ControlPolicy::read_only(),
+        }
+    }
+
+    /// Replaces the control ops `SendControl` may run.
+    pub fn with_control_policy(mut self, policy: ControlPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Wraps the server for `tonic::transport::Server::add_service` or a tonic router.
+    pub fn into_service(self) -> AlpineControlServer<Self> {
+        AlpineControlServer::new(self)
+    }
+
+    async fn session(&self, session_id: &str) -> Result<Arc<AlpineClient>, Status> {
+        self.manager
+            .session(session_id)
+            .await
+            .ok_or_else(|| Status::not_found(format!("no session {session_id}")))
+    }
+
+    /// Connects the manager to `address`, either `ip:port` or a `host:port` whose
+    /// addresses are tried in turn, and returns the device id.
+    async fn open(&self, address: &str) -> Result<String, AlpineSdkError> {
+        if let Ok(peer) = address.parse::<SocketAddr>() {
+            return self.manager.connect_to(peer, ConnectTier::Normal).await;
+        }
+        let peers = tokio::net::lookup_host(address).await.map_err(|err| {
+            AlpineSdkError::InvalidInput(format!("bad device address {address}: {err}"))
+        })?;
+        let mut failure = None;
+        for peer in peers {
+            match self.manager.connect_to(peer, ConnectTier::Normal).await {
+                Ok(device_id) => return Ok(device_id),
+                Err(err) => failure = Some(err),
+            }
+        }
+        Err(failure.unwrap_or_else(|| {
+            AlpineSdkError::InvalidInput(format!("{address} resolved to no address"))
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl AlpineControl for GrpcControl {
+    async fn connect(
+        &self,
+        request: Request<proto::ConnectRequest>,
+    ) -> Result<Response<proto::ConnectReply>, Status> {
+        let session_id = self
+            .open(&request.into_inner().address)
+            .await
+            .map_err(|err| Status::unavailable(err.to_string()))?;
+        let remote_addr = self.session(&session_id).await?.remote_addr().to_string();
+        Ok(Response::new(proto::ConnectReply {
+            session_id,
+            remote_addr,
+        }))
+    }
+
+    async fn get_stream_status(
+        &self,
+        request: Request<proto::SessionRef>,
+    ) -> Result<Response<proto::StreamStatus>, Status> {
+        let client = self.session(&request.into_inner().session_id).await?;
+        let metrics = client.metrics();
+        let health = client.stream_health().map(|health| proto::StreamHealth {
+            universes: health.universes.len() as u32,
+            worst_loss_ratio: health.worst_loss_ratio,
+            recovering: health.recovering as u32,
+            degraded_safe: health.degraded_safe as u32,
+            bandwidth_estimate: health.bandwidth_estimate,
+            congested: health.congested,
+        });
+        Ok(Response::new(proto::StreamStatus {
+            state: metrics.state.to_owned(),
+            uptime_ms: metrics.uptime.as_millis() as u64,
+            frames_sent: metrics.frames_sent,
+            frames_received: metrics.frames_received,
+            keepalive_rtt_ms: metrics.keepalive_rtt.last.map(|rtt| rtt.as_millis() as u64),
+            paused: client.stream_paused(),
+            last_error: metrics.last_error,
+            health,
+        }))
+    }
+
+    async fn send_control(
+        &self,
+        request: Request<proto::ControlRequest>,
+    ) -> Result<Response<proto::ControlReply>, Status> {
+        let request = request.into_inner();
+        let client = self.session(&request.session_id).await?;
+        let op: ControlOp = serde_json::from_value(Value::String(request.op.clone()))
+            .map_err(|_| Status::invalid_argument(format!("unknown control op {}", request.op)))?;
+        if !self.policy.permits(&op) {
+            return Err(Status::permission_denied(format!(
+                "control op {} is not allowed through this gateway",
+                request.op
+            )));
+        }
+        let payload = if request.payload_json.is_empty() {
+            Value::Object(Default::default())
+        } else {
+            serde_json::from_str(&request.payload_json)
+                .map_err(|err| Status::invalid_argument(format!("payload_json: {err}")))?
+        };
+        let ack = client
+            .send_control(op, payload)
+            .await
+            .map_err(|err| Status::unavailable(err.to_string()))?;
+        Ok(Response::new(proto::ControlReply {
+            ok: ack.ok,
+            detail: ack.detail,
+        }))
+    }
+
+    type SubscribeEventsStream = EventStream;
+
+    async fn subscribe_events(
+        &self,
+        request: Request<proto::SessionRef>,
+    ) -> Result<Response<EventStream>, Status> {
+        let client = self.session(&request.into_inner().session_id).await?;
+        // A subscriber that falls behind skips the events it missed.
+        let events = BroadcastStream::new(client.subscribe_events())
+            .filter_map(|event| event.ok().map(|event| Ok(event_message(event))));
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    async fn disconnect(
+        &self,
+        request: Request<proto::SessionRef>,
+    ) -> Result<Response<proto::DisconnectReply>, Status> {
+        let session_id = request.into_inner().session_id;
+        let client = self
+            .manager
+            .remove(&session_id)
+            .await
+            .ok_or_else(|| Status::not_found(format!("no session {session_id}")))?;
+        // Calls still running on the session keep it open until they finish.
+        if let Ok(client) = Arc::try_unwrap(client) {
+            client.close().await;
+        }
+        Ok(Response::new(proto::DisconnectReply {}))
+    }
+}
+
+fn event_message(event: StreamEvent) -> proto::StreamEvent {
+    let (kind, universe, description) = match event {
+        StreamEvent::Adaptation { universe, event } => {
+            ("adaptation", Some(universe), format!("{event:?}"))
+        }
+        StreamEvent::Recovery { universe, event } => {
+            ("recovery", Some(universe), format!("{event:?}"))
+        }
+        StreamEvent::SessionLimit(warning) => ("session_limit", None, format!("{warning:?}")),
+        StreamEvent::LossyConversion { universe, from, to } => (
+            "lossy_conversion",
+            Some(universe),
+            format!("{from:?} to {to:?}"),
+        ),
+        StreamEvent::CapabilitiesChanged {
+            max_channels,
+            max_frame_rate,
+        } => (
+            "capabilities_changed",
+            None,
+            format!("max_channels {max_channels}, max_frame_rate {max_frame_rate:?}"),
+        ),
+        StreamEvent::HealthChanged { score, level } => {
+            ("health_changed", None, format!("score {score}, {level:?}"))
+        }
+        StreamEvent::Preempted { priority, grace } => (
+            "preempted",
+            None,
+            format!("priority {priority}, grace {grace:?}"),
+        ),
+        StreamEvent::Underrun {
+            universe,
+            achieved_fps,
+            target_fps,
+            active,
+        } => (
+            "underrun",
+            Some(universe),
+            format!("{achieved_fps} of {target_fps} fps, active {active}"),
+        ),
+    };
+    proto::StreamEvent {
+        kind: kind.to_owned(),
+        universe: universe.map(u32::from),
+        description,
+    }
+}
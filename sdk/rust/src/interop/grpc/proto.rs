@@ -0,0 +1,272 @@
+//! Messages and service of `proto/alpine_control.proto`, in the form tonic-prost-build
+//! emits for it. Checked in so building the SDK does not need `protoc`; keep it in step
+//! with the `.proto` file.
+
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct ConnectRequest {
+    #[prost(string, tag = "1")]
+    pub address: ::prost::alloc::string::String,
+}
+
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct ConnectReply {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub remote_addr: ::prost::alloc::string::String,
+}
+
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct SessionRef {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StreamStatus {
+    #[prost(string, tag = "1")]
+    pub state: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub uptime_ms: u64,
+    #[prost(uint64, tag = "3")]
+    pub frames_sent: u64,
+    #[prost(uint64, tag = "4")]
+    pub frames_received: u64,
+    #[prost(uint64, optional, tag = "5")]
+    pub keepalive_rtt_ms: ::core::option::Option<u64>,
+    #[prost(bool, tag = "6")]
+    pub paused: bool,
+    #[prost(string, optional, tag = "7")]
+    pub last_error: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(message, optional, tag = "8")]
+    pub health: ::core::option::Option<StreamHealth>,
+}
+
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct StreamHealth {
+    #[prost(uint32, tag = "1")]
+    pub universes: u32,
+    #[prost(double, tag = "2")]
+    pub worst_loss_ratio: f64,
+    #[prost(uint32, tag = "3")]
+    pub recovering: u32,
+    #[prost(uint32, tag = "4")]
+    pub degraded_safe: u32,
+    #[prost(uint64, optional, tag = "5")]
+    pub bandwidth_estimate: ::core::option::Option<u64>,
+    #[prost(bool, tag = "6")]
+    pub congested: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct ControlRequest {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub op: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub payload_json: ::prost::alloc::string::String,
+}
+
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct ControlReply {
+    #[prost(bool, tag = "1")]
+    pub ok: bool,
+    #[prost(string, optional, tag = "2")]
+    pub detail: ::core::option::Option<::prost::alloc::string::String>,
+}
+
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct StreamEvent {
+    #[prost(string, tag = "1")]
+    pub kind: ::prost::alloc::string::String,
+    #[prost(uint32, optional, tag = "2")]
+    pub universe: ::core::option::Option<u32>,
+    #[prost(string, tag = "3")]
+    pub description: ::prost::alloc::string::String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ::prost::Message)]
+pub struct DisconnectReply {}
+
+/// Server side of the `AlpineControl` service.
+pub mod alpine_control_server {
+    #![allow(clippy::wildcard_imports)]
+    use tonic::codegen::*;
+
+    /// gRPC methods of `AlpineControl`; serve an implementation with
+    /// [`AlpineControlServer`].
+    #[async_trait]
+    pub trait AlpineControl: std::marker::Send + std::marker::Sync + 'static {
+        async fn connect(
+            &self,
+            request: tonic::Request<super::ConnectRequest>,
+        ) -> std::result::Result<tonic::Response<super::ConnectReply>, tonic::Status>;
+        async fn get_stream_status(
+            &self,
+            request: tonic::Request<super::SessionRef>,
+        ) -> std::result::Result<tonic::Response<super::StreamStatus>, tonic::Status>;
+        async fn send_control(
+            &self,
+            request: tonic::Request<super::ControlRequest>,
+        ) -> std::result::Result<tonic::Response<super::ControlReply>, tonic::Status>;
+        /// Server streaming response type for the SubscribeEvents method.
+        type SubscribeEventsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::StreamEvent, tonic::Status>,
+            > + std::marker::Send
+            + 'static;
+        async fn subscribe_events(
+            &self,
+            request: tonic::Request<super::SessionRef>,
+        ) -> std::result::Result<tonic::Response<Self::SubscribeEventsStream>, tonic::Status>;
+        async fn disconnect(
+            &self,
+            request: tonic::Request<super::SessionRef>,
+        ) -> std::result::Result<tonic::Response<super::DisconnectReply>, tonic::Status>;
+    }
+
+    #[derive(Debug)]
+    pub struct AlpineControlServer<T> {
+        inner: Arc<T>,
+    }
+
+    impl<T> AlpineControlServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self { inner }
+        }
+    }
+
+    impl<T> Clone for AlpineControlServer<T> {
+        fn clone(&self) -> Self {
+            Self {
+                inner: self.inner.clone(),
+            }
+        }
+    }
+
+    /// Unary method of `T` as a tonic service.
+    struct Unary<T, F>(Arc<T>, F);
+
+    impl<T, Req, Res, F, Fut> tonic::server::UnaryService<Req> for Unary<T, F>
+    where
+        T: AlpineControl,
+        F: Fn(Arc<T>, tonic::Request<Req>) -> Fut,
+        Fut: std::future::Future<Output = std::result::Result<tonic::Response<Res>, tonic::Status>>
+            + std::marker::Send
+            + 'static,
+    {
+        type Response = Res;
+        type Future = BoxFuture<tonic::Response<Res>, tonic::Status>;
+
+        fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+            Box::pin((self.1)(Arc::clone(&self.0), request))
+        }
+    }
+
+    struct SubscribeEventsSvc<T>(Arc<T>);
+
+    impl<T: AlpineControl> tonic::server::ServerStreamingService<super::SessionRef>
+        for SubscribeEventsSvc<T>
+    {
+        type Response = super::StreamEvent;
+        type ResponseStream = T::SubscribeEventsStream;
+        type Future = BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+
+        fn call(&mut self, request: tonic::Request<super::SessionRef>) -> Self::Future {
+            let inner = Arc::clone(&self.0);
+            Box::pin(async move { inner.subscribe_events(request).await })
+        }
+    }
+
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for AlpineControlServer<T>
+    where
+        T: AlpineControl,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::Body>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/alpine.control.v1.AlpineControl/Connect" => Box::pin(async move {
+                    let mut grpc = tonic::server::Grpc::new(tonic_prost::ProstCodec::default());
+                    let method = Unary(
+                        inner,
+                        |inner: Arc<T>, request: tonic::Request<super::ConnectRequest>| async move {
+                            inner.connect(request).await
+                        },
+                    );
+                    Ok(grpc.unary(method, req).await)
+                }),
+                "/alpine.control.v1.AlpineControl/GetStreamStatus" => Box::pin(async move {
+                    let mut grpc = tonic::server::Grpc::new(tonic_prost::ProstCodec::default());
+                    let method = Unary(
+                        inner,
+                        |inner: Arc<T>, request: tonic::Request<super::SessionRef>| async move {
+                            inner.get_stream_status(request).await
+                        },
+                    );
+                    Ok(grpc.unary(method, req).await)
+                }),
+                "/alpine.control.v1.AlpineControl/SendControl" => Box::pin(async move {
+                    let mut grpc = tonic::server::Grpc::new(tonic_prost::ProstCodec::default());
+                    let method = Unary(
+                        inner,
+                        |inner: Arc<T>, request: tonic::Request<super::ControlRequest>| async move {
+                            inner.send_control(request).await
+                        },
+                    );
+                    Ok(grpc.unary(method, req).await)
+                }),
+                "/alpine.control.v1.AlpineControl/SubscribeEvents" => Box::pin(async move {
+                    let mut grpc = tonic::server::Grpc::new(tonic_prost::ProstCodec::default());
+                    Ok(grpc.server_streaming(SubscribeEventsSvc(inner), req).await)
+                }),
+                "/alpine.control.v1.AlpineControl/Disconnect" => Box::pin(async move {
+                    let mut grpc = tonic::server::Grpc::new(tonic_prost::ProstCodec::default());
+                    let method = Unary(
+                        inner,
+                        |inner: Arc<T>, request: tonic::Request<super::SessionRef>| async move {
+                            inner.disconnect(request).await
+                        },
+                    );
+                    Ok(grpc.unary(method, req).await)
+                }),
+                _ => Box::pin(async move {
+                    let mut response = http::Response::new(tonic::body::Body::default());
+                    let headers = response.headers_mut();
+                    headers.insert(
+                        tonic::Status::GRPC_STATUS,
+                        (tonic::Code::Unimplemented as i32).into(),
+                    );
+                    headers.insert(
+                        http::header::CONTENT_TYPE,
+                        tonic::metadata::GRPC_CONTENT_TYPE,
+                    );
+                    Ok(response)
+                }),
+            }
+        }
+    }
+
+    /// Fully qualified name of the service.
+    pub const SERVICE_NAME: &str = "alpine.control.v1.AlpineControl";
+
+    impl<T> tonic::server::NamedService for AlpineControlServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
@@ -0,0 +1,224 @@
+//! REST management gateway.
+//!
+//! Dashboards and building-management systems speak HTTP and JSON, not ALPINE. An
+//! [`HttpGateway`] fronts the controller's live [`AlpineClient`] sessions with a small
+//! REST API, so they can list devices and sessions and run control ops without linking
+//! Rust:
+//!
+//! - `GET /discovery`: devices recorded with [`HttpGateway::record_discovery`].
+//! - `GET /sessions` and `GET /sessions/{name}`: session state, uptime, and counters.
+//! - `GET /sessions/{name}/status`: the device's own status, via `get_status`.
+//! - `POST /sessions/{name}/control/{op}`: runs control op `op` (its snake_case name)
+//!   with the request body as payload and answers with the ack.
+//!
+//! Acks answer `{"ok", "detail"}`, with `detail` parsed as JSON when it is JSON. Unknown
+//! sessions are 404, unknown ops or bodies 400, ops the gateway's [`ControlPolicy`] does
+//! not allow 403, and failed round trips 502. The gateway adds no authentication of its
+//! own, so by default it only runs read-only ops and `identify`; bind it to a management
+//! network or put it behind a proxy that does before allowing more.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use alpine::messages::{Acknowledge, ControlOp};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+
+use crate::client::AlpineClient;
+use crate::discovery::DiscoveryOutcome;
+use crate::error::AlpineSdkError;
+use crate::interop::ControlPolicy;
+
+#[derive(Default)]
+struct GatewayState {
+    sessions: RwLock<BTreeMap<String, Arc<AlpineClient>>>,
+    policy: RwLock<ControlPolicy>,
+    /// Latest discovery reply per device id.
+    discovered: RwLock<BTreeMap<String, DiscoveryOutcome>>,
+}
+
+/// REST API over named client sessions; clones share the same sessions.
+#[derive(Clone, Default)]
+pub struct HttpGateway {
+    state: Arc<GatewayState>,
+}
+
+impl HttpGateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exposes `client` under `/sessions/{name}`, replacing any session of that name.
+    pub async fn add_session(&self, name: impl Into<String>, client: Arc<AlpineClient>) {
+        self.state
+            .sessions
+            .write()
+            .await
+            .insert(name.into(), client);
+    }
+
+    /// Replaces the control ops `POST /sessions/{name}/control/{op}` may run.
+    pub async fn set_control_policy(&self, policy: ControlPolicy) {
+        *self.state.policy.write().await = policy;
+    }
+
+    pub async fn remove_session(&self, name: &str) -> Option<Arc<AlpineClient>> {
+        self.state.sessions.write().await.remove(name)
+    }
+
+    /// Lists a discovered device under `/discovery`, replacing its earlier reply.
+    pub async fn record_discovery(&self, outcome: DiscoveryOutcome) {
+        self.state
+            .discovered
+            .write()
+            .await
+            .insert(outcome.reply.device_id.clone(), outcome);
+    }
+
+    /// Routes of the API, for mounting into a larger server.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/discovery", get(discovery))
+            .route("/sessions", get(sessions))
+            .route("/sessions/{name}", get(session))
+            .route("/sessions/{name}/status", get(device_status))
+            .route("/sessions/{name}/control/{op}", post(control))
+            .with_state(self.state.clone())
+    }
+
+    /// Serves the API on `listener` until the listener fails.
+    pub async fn serve(self, listener: TcpListener) -> Result<(), AlpineSdkError> {
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+}
+
+type Shared = State<Arc<GatewayState>>;
+
+/// Error answer: a status and `{"error": message}`.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<AlpineSdkError> for ApiError {
+    fn from(err: AlpineSdkError) -> Self {
+        let status = match err {
+            AlpineSdkError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            AlpineSdkError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+async fn discovery(State(state): Shared) -> Json<Value> {
+    let devices: Vec<Value> = state
+        .discovered
+        .read()
+        .await
+        .values()
+        .map(|outcome| {
+            json!({
+                "peer": outcome.peer,
+                "interface": outcome.interface,
+                "reply": outcome.reply,
+            })
+        })
+        .collect();
+    Json(Value::Array(devices))
+}
+
+async fn sessions(State(state): Shared) -> Json<Value> {
+    let sessions: Vec<Value> = state
+        .sessions
+        .read()
+        .await
+        .iter()
+        .map(|(name, client)| session_summary(name, client))
+        .collect();
+    Json(Value::Array(sessions))
+}
+
+async fn session(State(state): Shared, Path(name): Path<String>) -> Result<Json<Value>, ApiError> {
+    let client = lookup(&state, &name).await?;
+    let mut summary = session_summary(&name, &client);
+    if let Some(health) = client.stream_health() {
+        summary["stream"] = json!({
+            "universes": health.universes.len(),
+            "worst_loss_ratio": health.worst_loss_ratio,
+            "recovering": health.recovering,
+            "degraded_safe": health.degraded_safe,
+            "bandwidth_estimate": health.bandwidth_estimate,
+            "congested": health.congested,
+        });
+    }
+    Ok(Json(summary))
+}
+
+async fn device_status(
+    State(state): Shared,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let client = lookup(&state, &name).await?;
+    Ok(Json(ack_json(&client.get_status().await?)))
+}
+
+async fn control(
+    State(state): Shared,
+    Path((name, op)): Path<(String, String)>,
+    payload: Option<Json<Value>>,
+) -> Result<Json<Value>, ApiError> {
+    let client = lookup(&state, &name).await?;
+    let op: ControlOp = serde_json::from_value(Value::String(op.clone()))
+        .map_err(|_| ApiError(StatusCode::BAD_REQUEST, format!("unknown control op {op}")))?;
+    if !state.policy.read().await.permits(&op) {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            format!("control op {op:?} is not allowed through this gateway"),
+        ));
+    }
+    let payload = payload.map_or_else(|| json!({}), |Json(payload)| payload);
+    Ok(Json(ack_json(&client.send_control(op, payload).await?)))
+}
+
+async fn lookup(state: &GatewayState, name: &str) -> Result<Arc<AlpineClient>, ApiError> {
+    state
+        .sessions
+        .read()
+        .await
+        .get(name)
+        .cloned()
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("no session {name}")))
+}
+
+fn session_summary(name: &str, client: &AlpineClient) -> Value {
+    let metrics = client.metrics();
+    json!({
+        "name": name,
+        "remote_addr": client.remote_addr(),
+        "state": metrics.state,
+        "uptime_ms": metrics.uptime.as_millis() as u64,
+        "frames_sent": metrics.frames_sent,
+        "frames_received": metrics.frames_received,
+        "keepalive_rtt_ms": metrics.keepalive_rtt.last.map(|rtt| rtt.as_millis() as u64),
+        "stream_paused": client.stream_paused(),
+        "last_error": metrics.last_error,
+    })
+}
+
+fn ack_json(ack: &Acknowledge) -> Value {
+    let detail = ack.detail.as_deref().map(|detail| {
+        serde_json::from_str(detail).unwrap_or_else(|_| Value::String(detail.to_owned()))
+    });
+    json!({ "ok": ack.ok, "detail": detail })
+}
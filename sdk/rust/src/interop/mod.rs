@@ -0,0 +1,26 @@
+//! Bridges from ALPINE sessions to systems that do not link the SDK.
+//!
+//! With the `http` feature, `http::HttpGateway` serves discovery results, session and
+//! device status, and control ops as a small REST API for facility dashboards. With the
+//! `grpc` feature, `grpc::GrpcControl` offers the sessions of a `SessionManager` as a
+//! typed gRPC service.
+//! Both forward only the control ops their `ControlPolicy` allows, read-only ones and
+//! `identify` unless configured otherwise.
+//! With the `webtransport` feature, `webtransport::WebTransportGateway` bridges browser
+//! WebTransport sessions to ALPINE sessions for tablet tools.
+
+#[cfg(feature = "webtransport")]
+mod connect;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(any(feature = "http", feature = "grpc"))]
+mod policy;
+#[cfg(feature = "webtransport")]
+pub mod webtransport;
+
+#[cfg(feature = "webtransport")]
+use connect::connect_address;
+#[cfg(any(feature = "http", feature = "grpc"))]
+pub use policy::ControlPolicy;
@@ -0,0 +1,42 @@
+//! Which control ops a gateway forwards for its callers.
+//!
+//! Gateways speak for callers ALPINE never authenticated, so by default they forward
+//! only ops that read node state, plus `identify`. Ops that change or reset a node, such
+//! as `restart` and `factory_reset`, have to be allowed one by one, or wholesale with
+//! [`ControlPolicy::allow_any`] for a gateway whose callers are authenticated in front of
+//! it.
+
+use std::collections::HashSet;
+
+use alpine::messages::ControlOp;
+
+/// Control ops a gateway may send on behalf of its callers.
+#[derive(Debug, Clone, Default)]
+pub struct ControlPolicy {
+    allowed: HashSet<ControlOp>,
+    any: bool,
+}
+
+impl ControlPolicy {
+    /// Read-only ops and `identify`; what gateways use unless configured otherwise.
+    pub fn read_only() -> Self {
+        Self::default()
+    }
+
+    /// Also forwards `op`.
+    pub fn allow(mut self, op: ControlOp) -> Self {
+        self.allowed.insert(op);
+        self
+    }
+
+    /// Forwards every op, `factory_reset` included.
+    pub fn allow_any(mut self) -> Self {
+        self.any = true;
+        self
+    }
+
+    /// Whether a caller may send `op`.
+    pub fn permits(&self, op: &ControlOp) -> bool {
+        self.any || op.is_read_only() || *op == ControlOp::Identify || self.allowed.contains(op)
+    }
+}
@@ -0,0 +1,242 @@
+//! WebTransport gateway for browser tools.
+//!
+//! Remote focus tools on a tablet run in a browser, which can neither open raw UDP nor
+//! hold controller credentials. A [`WebTransportGateway`] sits behind the HTTP/3 server
+//! that terminates WebTransport. For each accepted session it maps the browser user the
+//! server authenticated to a [`ControllerIdentity`], opens an ALPINE session with the
+//! requested device as that controller, starts its stream, and bridges the two:
+//!
+//! - Messages on the session's reliable stream are JSON control requests,
+//!   `{"id", "op", "payload"}`, with `op` the snake_case op name. Each is answered with
+//!   `{"id", "ok", "detail"}`, or `{"id", "error"}` when the round trip fails, in the
+//!   order the acks arrive.
+//! - Datagrams are JSON frames, `{"universe", "format", "channels", "priority"}`, sent on
+//!   the ALPINE stream. Malformed or refused frames are dropped like a lost datagram.
+//!
+//! The HTTP/3 stack is the embedder's choice: implement [`BrowserSession`] over it. The
+//! gateway trusts the user it reports, so authenticate the CONNECT request there (bearer
+//! token, client certificate). The device also checks the mapped controller identity, so
+//! its session permissions apply to every user mapped to it.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use alpine::crypto::identity::NodeCredentials;
+use alpine::messages::{Acknowledge, CapabilitySet, ChannelFormat, ControlOp, DeviceIdentity};
+use alpine::profile::StreamProfile;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+
+use super::connect_address;
+use crate::client::AlpineClient;
+use crate::error::AlpineSdkError;
+
+/// Priority of browser frames that do not name one.
+const DEFAULT_PRIORITY: u8 = 100;
+
+/// One WebTransport session accepted by the embedder's HTTP/3 server.
+#[async_trait]
+pub trait BrowserSession: Send + Sync {
+    /// Browser user the server authenticated for the session.
+    fn user(&self) -> &str;
+
+    /// Device the session asks for, `ip:port` or `host:port`, e.g. from the CONNECT path.
+    fn device(&self) -> &str;
+
+    /// Next message on the reliable stream; `None` once the browser closed it.
+    async fn recv_message(&self) -> Option<Vec<u8>>;
+
+    /// Next datagram; `None` once the session is gone.
+    async fn recv_datagram(&self) -> Option<Vec<u8>>;
+
+    async fn send_message(&self, message: Vec<u8>) -> Result<(), AlpineSdkError>;
+}
+
+/// Controller a browser user acts as toward devices.
+#[derive(Clone)]
+pub struct ControllerIdentity {
+    pub identity: DeviceIdentity,
+    pub capabilities: CapabilitySet,
+    pub credentials: NodeCredentials,
+    /// Profile of the stream opened for the user's frames.
+    pub profile: StreamProfile,
+    /// Device addresses the user may reach; empty allows any.
+    pub devices: Vec<String>,
+}
+
+impl ControllerIdentity {
+    /// Streams with the `realtime` profile to any device.
+    pub fn new(
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+    ) -> Self {
+        Self {
+            identity,
+            capabilities,
+            credentials,
+            profile: StreamProfile::realtime(),
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn with_profile(mut self, profile: StreamProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Limits the user to `devices`, given as they appear in [`BrowserSession::device`].
+    pub fn with_devices(mut self, devices: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.devices = devices.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn may_reach(&self, device: &str) -> bool {
+        self.devices.is_empty() || self.devices.iter().any(|allowed| allowed == device)
+    }
+}
+
+/// Bridges browser WebTransport sessions to ALPINE sessions; clones share the user map.
+#[derive(Clone, Default)]
+pub struct WebTransportGateway {
+    users: Arc<RwLock<HashMap<String, Arc<ControllerIdentity>>>>,
+}
+
+impl WebTransportGateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets browser `user` act as `controller`, replacing any earlier mapping. Sessions
+    /// already open keep the identity they started with.
+    pub async fn map_user(&self, user: impl Into<String>, controller: ControllerIdentity) {
+        self.users
+            .write()
+            .await
+            .insert(user.into(), Arc::new(controller));
+    }
+
+    pub async fn unmap_user(&self, user: &str) -> Option<Arc<ControllerIdentity>> {
+        self.users.write().await.remove(user)
+    }
+
+    /// Serves `session` until the browser goes away, then closes its ALPINE session.
+    ///
+    /// Fails with [`AlpineSdkError::Forbidden`] when the user is not mapped or may not
+    /// reach the device, and with the connect or `start_stream` error when the device
+    /// cannot be reached as the mapped controller.
+    pub async fn serve<S: BrowserSession>(&self, session: S) -> Result<(), AlpineSdkError> {
+        let controller = self.authorize(session.user(), session.device()).await?;
+        let mut client = connect_address(
+            session.device(),
+            controller.identity.clone(),
+            controller.capabilities.clone(),
+            controller.credentials.clone(),
+        )
+        .await?;
+        if let Err(err) = client.start_stream(controller.profile.clone()).await {
+            client.close().await;
+            return Err(err);
+        }
+        let client = Arc::new(client);
+        let mut pending = JoinSet::new();
+        let result = bridge(&session, &client, &mut pending).await;
+        pending.shutdown().await;
+        if let Ok(client) = Arc::try_unwrap(client) {
+            client.close().await;
+        }
+        result
+    }
+
+    async fn authorize(
+        &self,
+        user: &str,
+        device: &str,
+    ) -> Result<Arc<ControllerIdentity>, AlpineSdkError> {
+        self.users
+            .read()
+            .await
+            .get(user)
+            .filter(|controller| controller.may_reach(device))
+            .cloned()
+            .ok_or_else(|| AlpineSdkError::Forbidden {
+                user: user.to_owned(),
+                target: device.to_owned(),
+            })
+    }
+}
+
+/// Moves control requests and frames from the browser until either side closes.
+async fn bridge<S: BrowserSession>(
+    session: &S,
+    client: &Arc<AlpineClient>,
+    pending: &mut JoinSet<Vec<u8>>,
+) -> Result<(), AlpineSdkError> {
+    loop {
+        tokio::select! {
+            message = session.recv_message() => {
+                let Some(message) = message else { return Ok(()) };
+                // Control round trips run alongside, so frames keep flowing meanwhile.
+                let client = client.clone();
+                pending.spawn(async move { control_reply(&client, &message).await });
+            }
+            datagram = session.recv_datagram() => {
+                let Some(datagram) = datagram else { return Ok(()) };
+                let _ = send_frame(client, &datagram);
+            }
+            Some(done) = pending.join_next() => {
+                if let Ok(reply) = done {
+                    session.send_message(reply).await?;
+                }
+            }
+        }
+    }
+}
+
+async fn control_reply(client: &AlpineClient, message: &[u8]) -> Vec<u8> {
+    let request: Value = serde_json::from_slice(message).unwrap_or(Value::Null);
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let reply = match run_control(client, &request).await {
+        Ok(ack) => ack_json(id, &ack),
+        Err(err) => json!({ "id": id, "error": err.to_string() }),
+    };
+    reply.to_string().into_bytes()
+}
+
+async fn run_control(
+    client: &AlpineClient,
+    request: &Value,
+) -> Result<Acknowledge, AlpineSdkError> {
+    let op = request
+        .get("op")
+        .ok_or_else(|| AlpineSdkError::InvalidInput("control request without op".into()))?;
+    let op: ControlOp = serde_json::from_value(op.clone())
+        .map_err(|_| AlpineSdkError::InvalidInput(format!("unknown control op {op}")))?;
+    let payload = request.get("payload").cloned().unwrap_or_else(|| json!({}));
+    client.send_control(op, payload).await
+}
+
+fn send_frame(client: &AlpineClient, datagram: &[u8]) -> Result<(), AlpineSdkError> {
+    let frame: Value = serde_json::from_slice(datagram)?;
+    let universe = serde_json::from_value(frame.get("universe").cloned().unwrap_or(json!(0)))?;
+    let format = match frame.get("format") {
+        Some(format) => serde_json::from_value(format.clone())?,
+        None => ChannelFormat::U8,
+    };
+    let channels: Vec<u16> =
+        serde_json::from_value(frame.get("channels").cloned().unwrap_or(json!([])))?;
+    let priority = match frame.get("priority") {
+        Some(priority) => serde_json::from_value(priority.clone())?,
+        None => DEFAULT_PRIORITY,
+    };
+    client.send_universe_frame(universe, format, &channels, priority, None)
+}
+
+fn ack_json(id: Value, ack: &Acknowledge) -> Value {
+    let detail = ack.detail.as_deref().map(|detail| {
+        serde_json::from_str(detail).unwrap_or_else(|_| Value::String(detail.to_owned()))
+    });
+    json!({ "id": id, "ok": ack.ok, "detail": detail })
+}
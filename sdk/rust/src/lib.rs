@@ -1,12 +1,37 @@
 //! High-level ALPINE SDK built on top of the published protocol bindings.
 //! The crate keeps discovery, connection, and streaming lifecycles explicit
 //! while favoring a minimal public façade.
+pub mod blocking;
 pub mod client;
+pub mod diagnostics;
 pub mod discovery;
 pub mod error;
+pub mod events;
+pub mod health;
+pub mod interop;
+pub mod manager;
+pub mod node;
+pub mod rig;
+pub mod sender;
+pub mod testing;
+pub mod tools;
 pub mod transport;
+pub mod triggers;
 
-pub use client::AlpineClient;
-pub use discovery::{DiscoveryClient, DiscoveryClientOptions, DiscoveryError, DiscoveryOutcome};
+pub use blocking::BlockingAlpineClient;
+pub use client::{AlpineClient, AlpineClientBuilder};
+pub use diagnostics::{ReadinessProbe, ReadinessReport};
+pub use discovery::{
+    DiscoveryClient, DiscoveryClientOptions, DiscoveryError, DiscoveryOutcome, DiscoverySweep,
+    QuarantineReason, QuarantinedReply, MAX_DISCOVERY_REPLY,
+};
 pub use error::AlpineSdkError;
-pub use transport::{quic::QuicFrameTransport, udp::UdpFrameTransport};
+pub use events::{AlpineEvent, EventBus};
+pub use health::{ConnectionHealth, HealthScorer};
+pub use manager::{ConnectOutcome, ConnectReport, ConnectTier, SessionManager};
+pub use node::{AlpineNodeSdk, ControlHandler, NodeConnection};
+pub use rig::{RigChecker, RigReport};
+pub use sender::{FrameScheduler, Universe};
+pub use transport::{quic::QuicFrameTransport, UdpTransport};
+#[allow(deprecated)]
+pub use transport::UdpFrameTransport;
@@ -0,0 +1,768 @@
+//! Sessions with a whole rig.
+//!
+//! Bringing up a rig one handshake at a time takes minutes once it has a few hundred
+//! nodes. A [`SessionManager`] discovers the rig with one broadcast, verifies the replies
+//! in a batch, and opens sessions with the devices that pass a filter concurrently, a
+//! bounded number at a time so the console's socket buffers and the nodes' handshake
+//! rate limits are not swamped. It keeps the sessions it opened by device id.
+//!
+//! Devices are classified into [`ConnectTier`]s. Every tier has its own handshake budget,
+//! and a tier is only admitted once no device of a more urgent one is still waiting, so
+//! after a network blip [`SessionManager::reconnect`] brings back the fixtures a show
+//! cannot do without before the house lights and the haze machines.
+//!
+//! A node at its session or handshake limit answers busy with a retry-after hint. The
+//! manager waits that long and tries the device again, a few times, before reporting it
+//! failed, so a rig coming back all at once does not give up on nodes still shedding
+//! their old sessions.
+//!
+//! With [`SessionManager::sharded`] the streams of a large rig send from a
+//! [`ShardPool`]: each device's frames go out from the socket and pinned thread of its
+//! shard, so hundreds of universes are not encoded and sent on one runtime thread.
+//!
+//! Sessions the manager opens publish on its [`EventBus`], which also carries the
+//! manager's own events: devices connecting, lost, failed over to a fresh session, or
+//! failing their handshake. [`SessionManager::subscribe`] covers the whole rig.
+//!
+//! [`SessionManager::inventory`] snapshots every device the manager knows of: devices
+//! pre-seeded with [`SessionManager::expect`], those discovery found, and the live state
+//! of its sessions. [`SessionManager::rig_check`] diffs what was found against the
+//! expected devices.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use alpine::crypto::identity::NodeCredentials;
+use alpine::inventory::{Inventory, PatchEntry, RigCheck};
+use alpine::messages::{CapabilitySet, ChannelFormat, DeviceIdentity};
+use alpine::profile::StreamProfile;
+use alpine::stream::{ShardConfig, ShardPool, ShardStats, StreamError};
+use alpine::transport::UdpTransport;
+use ed25519_dalek::VerifyingKey;
+use tokio::sync::{broadcast, OnceCell, RwLock};
+use tokio::task::JoinSet;
+
+use crate::client::{AlpineClient, AlpineClientBuilder};
+use crate::discovery::{DiscoveryClient, DiscoveryOutcome};
+use crate::error::AlpineSdkError;
+use crate::events::{AlpineEvent, EventBus};
+use crate::sender::{frame_interval, send_tick, Universe};
+
+/// Handshakes of one tier that run at once unless configured otherwise.
+pub const DEFAULT_CONNECT_PARALLELISM: usize = 32;
+
+/// Handshakes retried after a busy answer unless configured otherwise.
+pub const DEFAULT_BUSY_RETRIES: u32 = 3;
+
+/// Longest retry-after hint the manager honours; longer ones are waited out this long.
+const MAX_BUSY_WAIT: Duration = Duration::from_secs(30);
+
+type Configure = Arc<dyn Fn(AlpineClientBuilder) -> AlpineClientBuilder + Send + Sync>;
+type Classify = Arc<dyn Fn(&DiscoveryOutcome) -> ConnectTier + Send + Sync>;
+
+/// How urgently a device needs its session, most urgent first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConnectTier {
+    /// Fixtures the show cannot run without, e.g. key lights and followspots.
+    Critical,
+    #[default]
+    Normal,
+    /// Devices that can wait until everything else is up, e.g. effects and work lights.
+    Deferred,
+}
+
+/// What became of one device in [`SessionManager::connect_all`] or
+/// [`SessionManager::reconnect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectOutcome {
+    Connected {
+        peer: SocketAddr,
+    },
+    /// The manager already held a session with the device; it was left alone.
+    AlreadyConnected,
+    Failed {
+        peer: SocketAddr,
+        reason: String,
+    },
+}
+
+/// Outcome of [`SessionManager::connect_all`] and [`SessionManager::reconnect`].
+#[derive(Debug, Clone, Default)]
+pub struct ConnectReport {
+    /// Every verified device that passed the filter, by device id.
+    pub devices: BTreeMap<String, ConnectOutcome>,
+    /// Device ids of replies without a registered key or with a bad signature; no
+    /// session was attempted with them.
+    pub unverified: Vec<String>,
+    /// Datagrams discovery set aside as oversized or malformed.
+    pub quarantined: usize,
+}
+
+impl ConnectReport {
+    /// Device ids now connected, including those that already were.
+    pub fn connected(&self) -> impl Iterator<Item = &str> {
+        self.devices
+            .iter()
+            .filter(|(_, outcome)| !matches!(outcome, ConnectOutcome::Failed { .. }))
+            .map(|(device_id, _)| device_id.as_str())
+    }
+
+    /// Devices whose handshake failed, and why.
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.devices
+            .iter()
+            .filter_map(|(device_id, outcome)| match outcome {
+                ConnectOutcome::Failed { reason, .. } => {
+                    Some((device_id.as_str(), reason.as_str()))
+                }
+                _ => None,
+            })
+    }
+}
+
+/// Controller-side sessions with many devices, keyed by device id.
+pub struct SessionManager {
+    identity: DeviceIdentity,
+    capabilities: CapabilitySet,
+    credentials: NodeCredentials,
+    keys: HashMap<String, VerifyingKey>,
+    /// Handshake budget of each tier, indexed by tier.
+    parallelism: [usize; 3],
+    busy_retries: u32,
+    configure: Option<Configure>,
+    classify: Option<Classify>,
+    /// Profile every session starts streaming with once connected.
+    profile: Option<StreamProfile>,
+    shards: Option<ShardConfig>,
+    /// Started on first use, within the runtime the manager runs on.
+    pool: OnceCell<ShardPool>,
+    sessions: RwLock<BTreeMap<String, Managed>>,
+    /// Devices expected on the rig, e.g. loaded from the last show's inventory.
+    expected: Inventory,
+    /// Verified devices seen by discovery, whether or not they passed the filter.
+    discovered: RwLock<Inventory>,
+    events: EventBus,
+}
+
+struct Managed {
+    client: Arc<AlpineClient>,
+    tier: ConnectTier,
+    /// Universes sent on the device's shard, carried over to a reconnected session.
+    schedules: Vec<Schedule>,
+}
+
+#[derive(Clone)]
+struct Schedule {
+    universes: Vec<Universe>,
+    channel_format: ChannelFormat,
+    priority: u8,
+}
+
+/// A device waiting for a handshake, with the schedules its session picks up.
+struct Queued {
+    device_id: String,
+    peer: SocketAddr,
+    schedules: Vec<Schedule>,
+    /// The device lost a managed session, so a new one is a failover.
+    lost: bool,
+}
+
+/// Devices waiting for a handshake, per tier.
+type Queues = BTreeMap<ConnectTier, VecDeque<Queued>>;
+
+impl SessionManager {
+    /// Verifies discovery replies against `keys`, indexed by device id, and opens
+    /// sessions as `identity` offering `capabilities`.
+    pub fn new(
+        identity: DeviceIdentity,
+        capabilities: CapabilitySet,
+        credentials: NodeCredentials,
+        keys: HashMap<String, VerifyingKey>,
+    ) -> Self {
+        Self {
+            identity,
+            capabilities,
+            credentials,
+            keys,
+            parallelism: [DEFAULT_CONNECT_PARALLELISM; 3],
+            busy_retries: DEFAULT_BUSY_RETRIES,
+            configure: None,
+            classify: None,
+            profile: None,
+            shards: None,
+            pool: OnceCell::new(),
+            sessions: RwLock::new(BTreeMap::new()),
+            expected: Inventory::new(),
+            discovered: RwLock::new(Inventory::new()),
+            events: EventBus::default(),
+        }
+    }
+
+    /// Publishes the events of the manager and its sessions on `bus`, e.g. one shared
+    /// with a [`DiscoveryClient`]. Sessions use the manager's bus even if
+    /// [`configure`](SessionManager::configure) hands their builders another.
+    pub fn event_bus(mut self, bus: EventBus) -> Self {
+        self.events = bus;
+        self
+    }
+
+    /// Pre-seeds the devices expected on the rig, e.g. from
+    /// [`Inventory::load`](alpine::inventory::Inventory::load). They appear in
+    /// [`SessionManager::inventory`] before discovery finds them, and
+    /// [`SessionManager::rig_check`] diffs against them.
+    pub fn expect(mut self, expected: Inventory) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    /// Runs at most `parallelism` handshakes of each tier at once; at least one.
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = [parallelism.max(1); 3];
+        self
+    }
+
+    /// Runs at most `parallelism` handshakes of `tier` at once; at least one.
+    pub fn tier_parallelism(mut self, tier: ConnectTier, parallelism: usize) -> Self {
+        self.parallelism[tier as usize] = parallelism.max(1);
+        self
+    }
+
+    /// Tries a device up to `retries` more times when it answers busy, each after the
+    /// wait it asked for; `0` reports the first busy answer as a failure.
+    pub fn busy_retries(mut self, retries: u32) -> Self {
+        self.busy_retries = retries;
+        self
+    }
+
+    /// Sorts discovered devices into tiers with `classify`, e.g. by device id or model;
+    /// every device is [`ConnectTier::Normal`] otherwise. A device keeps its tier for
+    /// reconnects.
+    pub fn classify(
+        mut self,
+        classify: impl Fn(&DiscoveryOutcome) -> ConnectTier + Send + Sync + 'static,
+    ) -> Self {
+        self.classify = Some(Arc::new(classify));
+        self
+    }
+
+    /// Applies `configure` to the builder of every session, e.g. to share an event bus
+    /// or a session store.
+    pub fn configure(
+        mut self,
+        configure: impl Fn(AlpineClientBuilder) -> AlpineClientBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.configure = Some(Arc::new(configure));
+        self
+    }
+
+    /// Starts streaming with `profile` on every session once it is connected, so the
+    /// manager can [`schedule`](SessionManager::schedule) universes on it.
+    pub fn stream(mut self, profile: StreamProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Sends the frames of every session from a [`ShardPool`] shaped by `config`, placing
+    /// devices on shards by device id. The pool starts with the first handshake.
+    ///
+    /// Only streams the manager starts, see [`SessionManager::stream`], use the pool.
+    pub fn sharded(mut self, config: ShardConfig) -> Self {
+        self.shards = Some(config);
+        self
+    }
+
+    /// Discovers the rig with `discovery` and opens a session with every verified device
+    /// `filter` accepts that has none yet, most urgent tier first.
+    ///
+    /// A device that fails its handshake is reported and does not hold up the others;
+    /// only a discovery that cannot send or receive at all fails the call.
+    pub async fn connect_all<F>(
+        &self,
+        discovery: DiscoveryClient,
+        filter: F,
+    ) -> Result<ConnectReport, AlpineSdkError>
+    where
+        F: Fn(&DiscoveryOutcome) -> bool,
+    {
+        let keys = self.keys.clone();
+        let sweep = tokio::task::spawn_blocking(move || discovery.discover_all(&[], &keys))
+            .await
+            .map_err(|err| AlpineSdkError::Internal(err.to_string()))??;
+
+        let mut report = ConnectReport {
+            unverified: sweep
+                .rejected
+                .iter()
+                .map(|outcome| outcome.reply.device_id.clone())
+                .collect(),
+            quarantined: sweep.quarantined_count,
+            ..ConnectReport::default()
+        };
+        {
+            let mut discovered = self.discovered.write().await;
+            for outcome in &sweep.verified {
+                discovered.observe(outcome.record());
+            }
+        }
+        let mut queues = Queues::new();
+        let mut queued = HashSet::new();
+        {
+            let sessions = self.sessions.read().await;
+            for outcome in sweep.verified.iter().filter(|outcome| filter(outcome)) {
+                let device_id = &outcome.reply.device_id;
+                if sessions.contains_key(device_id) {
+                    report
+                        .devices
+                        .insert(device_id.clone(), ConnectOutcome::AlreadyConnected);
+                } else if queued.insert(device_id.clone()) {
+                    // A device answering on several interfaces is connected once.
+                    let tier = self.tier_of(outcome);
+                    queues.entry(tier).or_default().push_back(Queued {
+                        device_id: device_id.clone(),
+                        peer: outcome.peer,
+                        schedules: Vec::new(),
+                        lost: false,
+                    });
+                }
+            }
+        }
+        self.handshake(queues, &mut report).await?;
+        Ok(report)
+    }
+
+    /// Opens a fresh session, at the address it last had, with every managed device
+    /// whose session failed or closed; most urgent tier first, within each tier's
+    /// budget. Healthy sessions are left alone, and scheduled universes move over to the
+    /// new sessions.
+    pub async fn reconnect(&self) -> Result<ConnectReport, AlpineSdkError> {
+        let mut queues = Queues::new();
+        {
+            let mut sessions = self.sessions.write().await;
+            let lost: Vec<String> = sessions
+                .iter()
+                .filter(|(_, managed)| {
+                    matches!(managed.client.metrics().state, "failed" | "closed")
+                })
+                .map(|(device_id, _)| device_id.clone())
+                .collect();
+            for device_id in lost {
+                if let Some(managed) = sessions.remove(&device_id) {
+                    if let Some(pool) = self.pool.get() {
+                        pool.cancel(&device_id);
+                    }
+                    let peer = managed.client.remote_addr();
+                    self.events.publish(AlpineEvent::DeviceLost {
+                        device_id: device_id.clone(),
+                        peer,
+                        tier: managed.tier,
+                        state: managed.client.metrics().state,
+                    });
+                    queues.entry(managed.tier).or_default().push_back(Queued {
+                        peer,
+                        device_id,
+                        schedules: managed.schedules,
+                        lost: true,
+                    });
+                    if let Ok(client) = Arc::try_unwrap(managed.client) {
+                        client.close().await;
+                    }
+                }
+            }
+        }
+        let mut report = ConnectReport::default();
+        self.handshake(queues, &mut report).await?;
+        Ok(report)
+    }
+
+    /// Opens a session with the device at `peer`, e.g. one known by address rather than
+    /// found by discovery, and manages it in `tier` under the device id it presents,
+    /// which is returned. A device the manager already holds a session with keeps that
+    /// session; the new one is closed.
+    pub async fn connect_to(
+        &self,
+        peer: SocketAddr,
+        tier: ConnectTier,
+    ) -> Result<String, AlpineSdkError> {
+        let pool = self.pool().await?;
+        let mut client = self.open(peer).await?;
+        let Some(identity) = client.device_identity() else {
+            client.close().await;
+            return Err(AlpineSdkError::Internal(format!(
+                "session with {peer} not established"
+            )));
+        };
+        let device_id = identity.device_id;
+        if self.sessions.read().await.contains_key(&device_id) {
+            client.close().await;
+            return Ok(device_id);
+        }
+        let stream_socket = pool.map(|pool| pool.transport(&device_id, peer));
+        if let Err(err) = start_stream(&mut client, self.profile.clone(), stream_socket).await {
+            client.close().await;
+            self.events.publish(AlpineEvent::DeviceConnectFailed {
+                device_id,
+                peer,
+                tier,
+                reason: err.to_string(),
+            });
+            return Err(err);
+        }
+        let mut sessions = self.sessions.write().await;
+        // Another call may have connected the device meanwhile.
+        if sessions.contains_key(&device_id) {
+            drop(sessions);
+            client.close().await;
+            return Ok(device_id);
+        }
+        let managed = Managed {
+            client: Arc::new(client),
+            tier,
+            schedules: Vec::new(),
+        };
+        sessions.insert(device_id.clone(), managed);
+        drop(sessions);
+        self.events.publish(AlpineEvent::DeviceConnected {
+            device_id: device_id.clone(),
+            peer,
+            tier,
+        });
+        Ok(device_id)
+    }
+
+    /// The session with `device_id`, if the manager holds one.
+    pub async fn session(&self, device_id: &str) -> Option<Arc<AlpineClient>> {
+        self.sessions
+            .read()
+            .await
+            .get(device_id)
+            .map(|managed| managed.client.clone())
+    }
+
+    /// Every session the manager holds, by device id.
+    pub async fn sessions(&self) -> BTreeMap<String, Arc<AlpineClient>> {
+        self.sessions
+            .read()
+            .await
+            .iter()
+            .map(|(device_id, managed)| (device_id.clone(), managed.client.clone()))
+            .collect()
+    }
+
+    /// Every device the manager knows of: the expected devices, overlaid with what
+    /// discovery found and then with the identity, capabilities, health, and scheduled
+    /// universes of each session. Save it with
+    /// [`Inventory::save`](alpine::inventory::Inventory::save) as the next expected rig.
+    pub async fn inventory(&self) -> Inventory {
+        let mut inventory = self.expected.clone();
+        for record in self.found().await.devices() {
+            inventory.observe(record.clone());
+        }
+        inventory
+    }
+
+    /// Diffs the devices discovery found and the sessions report against the expected
+    /// devices.
+    pub async fn rig_check(&self) -> RigCheck {
+        self.expected.check(&self.found().await)
+    }
+
+    /// Events of the manager and of every session it opens.
+    pub fn subscribe(&self) -> broadcast::Receiver<AlpineEvent> {
+        self.events.subscribe()
+    }
+
+    /// Tier the managed device `device_id` was classified into.
+    pub async fn tier(&self, device_id: &str) -> Option<ConnectTier> {
+        self.sessions
+            .read()
+            .await
+            .get(device_id)
+            .map(|managed| managed.tier)
+    }
+
+    /// Stops managing the session with `device_id` and hands it back, e.g. to close it.
+    /// Its scheduled universes stop.
+    pub async fn remove(&self, device_id: &str) -> Option<Arc<AlpineClient>> {
+        let managed = self.sessions.write().await.remove(device_id)?;
+        if let Some(pool) = self.pool.get() {
+            pool.cancel(device_id);
+        }
+        Some(managed.client)
+    }
+
+    /// Sends `universes` to `device_id` from its shard at the frame rate of its stream,
+    /// like a [`FrameScheduler`](crate::FrameScheduler), until the device is removed.
+    /// A device may run several schedules.
+    ///
+    /// Needs a [`sharded`](SessionManager::sharded) manager that started the device's
+    /// stream. A tick finding the stream paused is skipped; any other send failure
+    /// stops the schedule.
+    pub async fn schedule(
+        &self,
+        device_id: &str,
+        universes: Vec<Universe>,
+        channel_format: ChannelFormat,
+        priority: u8,
+    ) -> Result<(), AlpineSdkError> {
+        let pool = self.pool.get().ok_or_else(|| {
+            AlpineSdkError::InvalidInput("session manager streams without shards".into())
+        })?;
+        let mut sessions = self.sessions.write().await;
+        let managed = sessions
+            .get_mut(device_id)
+            .ok_or_else(|| AlpineSdkError::InvalidInput(format!("no session with {device_id}")))?;
+        let schedule = Schedule {
+            universes,
+            channel_format,
+            priority,
+        };
+        spawn_schedule(pool, device_id, &managed.client, schedule.clone())?;
+        managed.schedules.push(schedule);
+        Ok(())
+    }
+
+    /// Counters of every shard, once the pool has started.
+    pub fn shard_stats(&self) -> Option<Vec<ShardStats>> {
+        self.pool.get().map(ShardPool::stats)
+    }
+
+    /// The shard pool, started on first use; `None` unless the manager is sharded.
+    async fn pool(&self) -> Result<Option<&ShardPool>, AlpineSdkError> {
+        let Some(config) = &self.shards else {
+            return Ok(None);
+        };
+        let pool = self
+            .pool
+            .get_or_try_init(|| async { ShardPool::start(config.clone()) })
+            .await?;
+        Ok(Some(pool))
+    }
+
+    /// Runs the handshakes of `queues` and records their outcomes in `report`.
+    ///
+    /// Each tier runs up to its budget at once. A tier is admitted only while no device
+    /// of a more urgent tier is still queued, so a lower tier never takes a slot a more
+    /// urgent device is waiting for, yet uses its own budget alongside handshakes of more
+    /// urgent tiers still in flight. A device waiting out a busy answer keeps its slot.
+    async fn handshake(
+        &self,
+        mut queues: Queues,
+        report: &mut ConnectReport,
+    ) -> Result<(), AlpineSdkError> {
+        let pool = self.pool().await?;
+        let mut in_flight = [0usize; 3];
+        let mut handshakes = JoinSet::new();
+        loop {
+            for (tier, queue) in queues.iter_mut() {
+                let tier = *tier;
+                while in_flight[tier as usize] < self.parallelism[tier as usize] {
+                    let Some(queued) = queue.pop_front() else {
+                        break;
+                    };
+                    in_flight[tier as usize] += 1;
+                    let stream_socket =
+                        pool.map(|pool| pool.transport(&queued.device_id, queued.peer));
+                    let connect = self.connect(queued.peer, stream_socket);
+                    handshakes.spawn(async move { (queued, tier, connect.await) });
+                }
+                if !queue.is_empty() {
+                    break;
+                }
+            }
+            let Some(joined) = handshakes.join_next().await else {
+                break;
+            };
+            let (queued, tier, result) =
+                joined.map_err(|err| AlpineSdkError::Internal(err.to_string()))?;
+            in_flight[tier as usize] -= 1;
+            let Queued {
+                device_id,
+                peer,
+                schedules,
+                lost,
+            } = queued;
+            let result = result.and_then(|client| {
+                let client = Arc::new(client);
+                if let Some(pool) = pool {
+                    for schedule in &schedules {
+                        spawn_schedule(pool, &device_id, &client, schedule.clone())?;
+                    }
+                }
+                Ok(client)
+            });
+            let outcome = match result {
+                Ok(client) => {
+                    let managed = Managed {
+                        client,
+                        tier,
+                        schedules,
+                    };
+                    self.sessions
+                        .write()
+                        .await
+                        .insert(device_id.clone(), managed);
+                    let device_id = device_id.clone();
+                    self.events.publish(if lost {
+                        AlpineEvent::DeviceFailedOver {
+                            device_id,
+                            peer,
+                            tier,
+                        }
+                    } else {
+                        AlpineEvent::DeviceConnected {
+                            device_id,
+                            peer,
+                            tier,
+                        }
+                    });
+                    ConnectOutcome::Connected { peer }
+                }
+                Err(err) => {
+                    let reason = err.to_string();
+                    self.events.publish(AlpineEvent::DeviceConnectFailed {
+                        device_id: device_id.clone(),
+                        peer,
+                        tier,
+                        reason: reason.clone(),
+                    });
+                    ConnectOutcome::Failed { peer, reason }
+                }
+            };
+            report.devices.insert(device_id, outcome);
+        }
+        Ok(())
+    }
+
+    /// Connects to `peer`, waiting out and retrying busy answers within the budget, and
+    /// starts the stream, from `stream_socket` if given.
+    fn connect(
+        &self,
+        peer: SocketAddr,
+        stream_socket: Option<UdpTransport>,
+    ) -> impl std::future::Future<Output = Result<AlpineClient, AlpineSdkError>> + Send + 'static
+    {
+        let open = self.open(peer);
+        let profile = self.profile.clone();
+        async move {
+            let mut client = open.await?;
+            start_stream(&mut client, profile, stream_socket).await?;
+            Ok(client)
+        }
+    }
+
+    /// Performs the handshake with `peer`, waiting out and retrying busy answers within
+    /// the budget.
+    fn open(
+        &self,
+        peer: SocketAddr,
+    ) -> impl std::future::Future<Output = Result<AlpineClient, AlpineSdkError>> + Send + 'static
+    {
+        let builder = self.builder(peer);
+        let mut retries = self.busy_retries;
+        async move {
+            loop {
+                match builder.clone().connect().await {
+                    Err(err) if retries > 0 => {
+                        let Some(wait) = err.retry_after() else {
+                            return Err(err);
+                        };
+                        retries -= 1;
+                        tokio::time::sleep(wait.min(MAX_BUSY_WAIT)).await;
+                    }
+                    result => return result,
+                }
+            }
+        }
+    }
+
+    /// What discovery and the sessions report, without the expected devices.
+    async fn found(&self) -> Inventory {
+        let mut found = self.discovered.read().await.clone();
+        for managed in self.sessions.read().await.values() {
+            let Some(mut record) = managed.client.inventory_record() else {
+                continue;
+            };
+            record.patch = managed
+                .schedules
+                .iter()
+                .flat_map(|schedule| &schedule.universes)
+                .map(|universe| PatchEntry {
+                    universe: universe.id(),
+                    port: None,
+                })
+                .collect();
+            record.patch.sort();
+            record.patch.dedup();
+            found.observe(record);
+        }
+        found
+    }
+
+    fn tier_of(&self, outcome: &DiscoveryOutcome) -> ConnectTier {
+        self.classify
+            .as_ref()
+            .map(|classify| classify(outcome))
+            .unwrap_or_default()
+    }
+
+    fn builder(&self, peer: SocketAddr) -> AlpineClientBuilder {
+        let builder = AlpineClient::builder(
+            peer,
+            self.identity.clone(),
+            self.capabilities.clone(),
+            self.credentials.clone(),
+        );
+        let builder = match &self.configure {
+            Some(configure) => configure(builder),
+            None => builder,
+        };
+        builder.event_bus(self.events.clone())
+    }
+}
+
+/// Starts streaming with `profile`, if any, from `stream_socket` if given.
+async fn start_stream(
+    client: &mut AlpineClient,
+    profile: Option<StreamProfile>,
+    stream_socket: Option<UdpTransport>,
+) -> Result<(), AlpineSdkError> {
+    match (profile, stream_socket) {
+        (Some(profile), Some(stream_socket)) => {
+            client.start_stream_on(profile, stream_socket).await?;
+        }
+        (Some(profile), None) => {
+            client.start_stream(profile).await?;
+        }
+        (None, _) => {}
+    }
+    Ok(())
+}
+
+/// Runs `schedule` for `device_id` on its shard, over `client`'s stream.
+fn spawn_schedule(
+    pool: &ShardPool,
+    device_id: &str,
+    client: &Arc<AlpineClient>,
+    schedule: Schedule,
+) -> Result<(), AlpineSdkError> {
+    let interval = frame_interval(client)?;
+    let client = client.clone();
+    let mut seen = vec![None; schedule.universes.len()];
+    pool.spawn(device_id, interval, move || {
+        match send_tick(
+            &client,
+            &schedule.universes,
+            &mut seen,
+            schedule.channel_format,
+            schedule.priority,
+        ) {
+            Ok((frames, _)) => Ok(frames),
+            Err(AlpineSdkError::Stream(err)) => Err(err),
+            Err(AlpineSdkError::StreamNotStarted) => Err(StreamError::MissingSession),
+            Err(err) => Err(StreamError::Transport(err.to_string())),
+        }
+    });
+    Ok(())
+}
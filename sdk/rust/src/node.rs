@@ -0,0 +1,1945 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alpine::capture::{FrameArrival, FrameCapture};
+use alpine::clock::NodeClock;
+use alpine::codec;
+use alpine::config::{ConfigStore, SharedConfig};
+use alpine::control::{
+    ControlCrypto, ControlDispatcher, ControlHandlers, ControlResponder, RateLimit,
+    CAPABILITY_UPDATE_SEQ_BASE,
+};
+use alpine::crypto::identity::NodeCredentials;
+use alpine::crypto::ratchet::StreamRatchet;
+use alpine::crypto::revocation::RevocationStore;
+use alpine::device::DeviceServer;
+use alpine::groups::GroupRegistry;
+use alpine::handshake::{FirmwarePolicy, HandshakeError, HandshakeMessage, HandshakeTransport};
+use alpine::latency::{LatencyRecorder, LatencySample};
+use alpine::logs::{LogSeverity, NodeLog};
+use alpine::maintenance::{Maintenance, MaintenanceHook};
+use alpine::merge::MergeEngine;
+use alpine::messages::{
+    CapabilitySet, ControlEnvelope, ControlOp, DiscoveryReply, DiscoveryRequest, EchoFrame,
+    ErrorCode, ErrorEnvelope, FrameEnvelope, FrameFragment, Keepalive, MessageType, ParityFrame,
+    PortId, SealedFrame, SessionClose, StreamHeartbeat, UniverseId,
+};
+use alpine::mirror::OutputMirror;
+use alpine::output::pattern::PatternGenerator;
+use alpine::output::OutputDriver;
+use alpine::ports::PortMap;
+use alpine::profile::ProfileAnnouncement;
+use alpine::rdm::RdmGateway;
+use alpine::scene::{SceneEngine, SceneStore};
+use alpine::schedule::{ScheduleStore, Scheduler};
+use alpine::session::registry::{SessionAccess, SessionInfo, SessionRegistry, Takeover};
+use alpine::session::AlnpSession;
+use alpine::show::{FallbackPlayer, ShowStore};
+use alpine::stream::{
+    FecDecoder, FrameRejected, FrameValidator, JitterBuffer, PriorityArbiter, Reassembler,
+    RejectionReason, RejectionStats, Smoother,
+};
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::error::AlpineSdkError;
+
+/// Largest datagram the node accepts on its socket.
+const MAX_DATAGRAM: usize = 4096;
+
+/// How often the node's scheduler checks the wall clock.
+const SCHEDULE_TICK: Duration = Duration::from_secs(1);
+
+/// How often running test patterns are rendered to the outputs.
+const PATTERN_TICK: Duration = Duration::from_millis(25);
+
+/// How often looks are republished while channels are still ramping.
+const SMOOTHING_TICK: Duration = Duration::from_millis(20);
+
+/// Received looks buffered before the receive loop waits on the application.
+const LOOK_CHANNEL_CAPACITY: usize = 256;
+
+/// Rejection reports a lagging subscriber may fall behind before losing some.
+const REJECTION_CHANNEL_CAPACITY: usize = 64;
+
+/// Datagrams queued for one session before the receive loop drops its traffic.
+const SESSION_INBOX_CAPACITY: usize = 256;
+
+/// Handshake messages queued while no [`AlpineNodeSdk::accept`] is running.
+const HANDSHAKE_QUEUE_CAPACITY: usize = 64;
+
+/// Takeovers announced to workers before a slow one misses some.
+const TAKEOVER_CHANNEL_CAPACITY: usize = 16;
+
+/// Capabilities pushed to every open session, with the reason given for the change.
+#[derive(Debug, Clone)]
+struct CapabilityNotice {
+    capabilities: CapabilitySet,
+    reason: Option<String>,
+}
+
+/// Decides what a controller connecting from an address may do.
+pub type AccessPolicy = Arc<dyn Fn(SocketAddr) -> SessionAccess + Send + Sync>;
+
+/// Handler invoked for an inbound control operation.
+///
+/// Returning `Ok(detail)` acknowledges the envelope; `Err(detail)` sends a negative ack.
+pub type ControlHandler =
+    Arc<dyn Fn(&ControlEnvelope) -> Result<Option<String>, String> + Send + Sync>;
+
+/// High-level node (device) counterpart to [`crate::AlpineClient`].
+///
+/// A single UDP socket answers discovery, accepts handshakes, receives frames, and serves
+/// control requests, mirroring how the controller addresses a node. Several controllers
+/// may hold sessions at once, up to the limit of the server's
+/// [`SessionRegistry`](alpine::session::registry::SessionRegistry); each is served on its
+/// own with its own keys, control sequence window, and [`SessionAccess`].
+pub struct AlpineNodeSdk {
+    socket: Arc<UdpSocket>,
+    server: Arc<DeviceServer>,
+    /// Serving task of each accepted session, keyed by session id.
+    routes: Routes,
+    handshakes: Arc<tokio::sync::Mutex<HandshakeQueue>>,
+    access_policy: Option<AccessPolicy>,
+    /// Latest pushed capabilities; each session's worker tells its controller.
+    capabilities: watch::Sender<CapabilityNotice>,
+    /// Takeovers of full nodes; the preempted session's worker tells its controller.
+    takeovers: broadcast::Sender<Takeover>,
+    receive: JoinHandle<()>,
+    handlers: ControlHandlers,
+    control_rate: Option<RateLimit>,
+    config: SharedConfig,
+    groups: Arc<GroupRegistry>,
+    latency: Arc<LatencyRecorder>,
+    clock: Arc<NodeClock>,
+    capture: Option<Arc<FrameCapture>>,
+    log: Option<Arc<NodeLog>>,
+    scenes: Option<Arc<SceneEngine>>,
+    fallback: Option<Arc<FallbackPlayer>>,
+    scheduler: Option<(Arc<Scheduler>, JoinHandle<()>)>,
+    merge: Option<Arc<MergeEngine>>,
+    arbiter: Option<Arc<PriorityArbiter>>,
+    outputs: Vec<Arc<dyn OutputDriver>>,
+    ports: Option<Arc<PortMap>>,
+    /// Drivers that carry only the universe mapped to their port.
+    port_outputs: Vec<(PortId, Arc<dyn OutputDriver>)>,
+    patterns: Option<(Arc<PatternGenerator>, JoinHandle<()>)>,
+    universes: Option<Vec<UniverseId>>,
+    /// Whether the `get_status` handler is the node's own, which reports merge and port
+    /// state, rather than the application's.
+    own_status: bool,
+}
+
+impl AlpineNodeSdk {
+    /// Binds the node socket on `local_addr` using the identity and credentials in `server`.
+    pub async fn bind(
+        local_addr: SocketAddr,
+        server: DeviceServer,
+    ) -> Result<Self, AlpineSdkError> {
+        let socket = UdpSocket::bind(local_addr)
+            .await
+            .map_err(|source| AlpineSdkError::Bind {
+                addr: local_addr,
+                source,
+            })?;
+        let socket = Arc::new(socket);
+        let server = Arc::new(server);
+        let routes = Routes::default();
+        let (queue, inbound) = mpsc::channel(HANDSHAKE_QUEUE_CAPACITY);
+        let clock = Arc::new(NodeClock::default());
+        let receive = tokio::spawn(receive_loop(
+            socket.clone(),
+            server.clone(),
+            routes.clone(),
+            queue,
+            clock.clone(),
+        ));
+        let (capabilities, _) = watch::channel(CapabilityNotice {
+            capabilities: server.capabilities.current(),
+            reason: None,
+        });
+        let handlers = ControlHandlers::new();
+        let config = SharedConfig::memory();
+        let groups = Arc::new(GroupRegistry::new(config.clone()));
+        groups.register(&handlers);
+        let latency = Arc::new(LatencyRecorder::default());
+        latency.register(&handlers);
+        clock.register(&handlers);
+        server.mirror.register(&handlers, &server.sessions);
+        // Each connection applies pauses itself once the envelope is authenticated.
+        handlers.on(ControlOp::PauseStream, |_: serde_json::Value| Ok(None));
+        handlers.on(ControlOp::ResumeStream, |_: serde_json::Value| Ok(None));
+        for op in [ControlOp::StartStream, ControlOp::RestartStream] {
+            let server = server.clone();
+            handlers.on(op, move |offer: ProfileAnnouncement| {
+                let profile = offer.compile().map_err(|e| e.to_string())?;
+                // Profiles faster or smoother than this node can apply are refused up front.
+                profile
+                    .check_capabilities(&server.capabilities.current())
+                    .map_err(|e| e.to_string())?;
+                Ok(Some(profile.config_id().to_string()))
+            });
+        }
+        Ok(Self {
+            socket,
+            server,
+            routes,
+            handshakes: Arc::new(tokio::sync::Mutex::new(HandshakeQueue {
+                inbound,
+                deferred: VecDeque::new(),
+            })),
+            access_policy: None,
+            capabilities,
+            takeovers: broadcast::channel(TAKEOVER_CHANNEL_CAPACITY).0,
+            receive,
+            handlers,
+            control_rate: None,
+            config,
+            groups,
+            latency,
+            clock,
+            capture: None,
+            log: None,
+            scenes: None,
+            fallback: None,
+            scheduler: None,
+            merge: None,
+            arbiter: None,
+            outputs: Vec::new(),
+            ports: None,
+            port_outputs: Vec::new(),
+            patterns: None,
+            universes: None,
+            own_status: false,
+        })
+    }
+
+    /// Persists device settings such as merge policies and channel groups in `store`
+    /// instead of memory.
+    ///
+    /// Call before enabling subsystems that keep settings in the device config, such as
+    /// [`AlpineNodeSdk::with_merge`].
+    pub fn with_config(mut self, store: Arc<dyn ConfigStore>) -> Result<Self, AlpineSdkError> {
+        self.config = SharedConfig::open(store)?;
+        self.groups = Arc::new(GroupRegistry::new(self.config.clone()));
+        self.groups.register(&self.handlers);
+        Ok(self)
+    }
+
+    /// Channel groups controllers registered with `define_group`.
+    pub fn groups(&self) -> &Arc<GroupRegistry> {
+        &self.groups
+    }
+
+    /// Network, queue, and apply timings of received frames, also served to controllers
+    /// through `get_latency`.
+    pub fn latency(&self) -> &Arc<LatencyRecorder> {
+        &self.latency
+    }
+
+    /// The node's wall clock, which controllers set with `time_sync` and read with
+    /// `get_time`. Received frames, log entries, and schedules use it; call
+    /// `set_source` once the platform's NTP, PTP, or GPS time is known to be good.
+    pub fn clock(&self) -> &Arc<NodeClock> {
+        &self.clock
+    }
+
+    /// Keeps the last `window` of received frames, applied or refused, with their arrival
+    /// time and controller, and serves them to controllers through `get_capture`.
+    pub fn with_capture(mut self, window: Duration) -> Self {
+        let capture = Arc::new(FrameCapture::new(window));
+        capture.register(&self.handlers);
+        self.capture = Some(capture);
+        self
+    }
+
+    pub fn capture(&self) -> Option<&Arc<FrameCapture>> {
+        self.capture.as_ref()
+    }
+
+    /// Logs handshakes, session changes, refused control operations and frames, and
+    /// stream changes in a ring buffer of `capacity` entries, served to controllers
+    /// through `get_logs`.
+    pub fn with_logs(mut self, capacity: usize) -> Self {
+        let log = Arc::new(NodeLog::new(capacity).with_clock(self.clock.clone()));
+        log.register(&self.handlers);
+        self.log = Some(log);
+        self
+    }
+
+    /// The node's event log; applications may add their own entries.
+    pub fn logs(&self) -> Option<&Arc<NodeLog>> {
+        self.log.as_ref()
+    }
+
+    /// Enables the scene control ops backed by `store`.
+    ///
+    /// Received looks become the live look that `store_scene` captures. Recalls are not
+    /// streamed to the application; render [`SceneEngine::output`] from
+    /// [`AlpineNodeSdk::scenes`], which local inputs can also drive with no controller.
+    pub fn with_scenes(mut self, store: Arc<dyn SceneStore>) -> Self {
+        let engine = Arc::new(SceneEngine::new(store));
+        engine.register(&self.handlers);
+        self.scenes = Some(engine);
+        self
+    }
+
+    pub fn scenes(&self) -> Option<&Arc<SceneEngine>> {
+        self.scenes.as_ref()
+    }
+
+    /// Enables the `upload_show` and `set_fallback` control ops, keeping the show in `store`.
+    ///
+    /// Like scenes, fallback playback is not streamed to the application; render
+    /// [`FallbackPlayer::output`] from [`AlpineNodeSdk::fallback`] whenever it returns a
+    /// look. Authenticated frames end playback.
+    pub fn with_fallback_show(mut self, store: Arc<dyn ShowStore>) -> Result<Self, AlpineSdkError> {
+        let player = Arc::new(FallbackPlayer::open(store)?);
+        player.register(&self.handlers);
+        self.fallback = Some(player);
+        Ok(self)
+    }
+
+    pub fn fallback(&self) -> Option<&Arc<FallbackPlayer>> {
+        self.fallback.as_ref()
+    }
+
+    /// Enables the `set_schedule` and `get_schedule` control ops and runs the schedule
+    /// kept in `store` on the system clock, with or without a connected controller.
+    ///
+    /// Call after [`AlpineNodeSdk::with_scenes`] so scheduled entries can recall scenes.
+    /// Apply [`Scheduler::apply_master`] from [`AlpineNodeSdk::scheduler`] to rendered
+    /// looks to honour scheduled intensity master fades.
+    pub fn with_schedule(mut self, store: Arc<dyn ScheduleStore>) -> Result<Self, AlpineSdkError> {
+        let scheduler = Arc::new(Scheduler::open(store, self.scenes.clone())?);
+        scheduler.register(&self.handlers);
+        let task = tokio::spawn(scheduler.clone().run_on(self.clock.clone(), SCHEDULE_TICK));
+        if let Some((_, previous)) = self.scheduler.replace((scheduler, task)) {
+            previous.abort();
+        }
+        Ok(self)
+    }
+
+    pub fn scheduler(&self) -> Option<&Arc<Scheduler>> {
+        self.scheduler.as_ref().map(|(scheduler, _)| scheduler)
+    }
+
+    /// Merges frames from concurrent sessions per universe under the policies in the
+    /// device config, configurable with `set_merge_policy`.
+    ///
+    /// Unless a `get_status` handler is already registered, one is installed that reports
+    /// `{"merge": [...]}`; custom status handlers can include [`MergeEngine::status`].
+    pub fn with_merge(mut self) -> Self {
+        let engine = Arc::new(MergeEngine::new(self.config.clone()));
+        engine.register(&self.handlers);
+        self.merge = Some(engine);
+        self.install_status();
+        self
+    }
+
+    /// Advertises `count` output ports, numbered from 1, and enables `set_port_map`.
+    /// Port `n` carries universe `n - 1` until a controller maps another universe to it;
+    /// mappings persist in the device config, so call after
+    /// [`AlpineNodeSdk::with_config`].
+    ///
+    /// Unless the application registered its own `get_status` handler, status reports
+    /// `{"ports": [...]}` with each port's universe and output health, alongside merge
+    /// state. `identify` payloads may name a port; decode them as
+    /// [`IdentifyRequest`](alpine::messages::IdentifyRequest) in the identify handler.
+    pub fn with_ports(mut self, count: PortId) -> Self {
+        let ports = Arc::new(PortMap::new(count, self.config.clone()));
+        ports.register(&self.handlers);
+        self.update_capabilities(CapabilitySet {
+            ports: Some(count),
+            ..self.server.capabilities.current()
+        });
+        self.ports = Some(ports);
+        self.install_status();
+        self
+    }
+
+    pub fn ports(&self) -> Option<&Arc<PortMap>> {
+        self.ports.as_ref()
+    }
+
+    /// Sends the looks of the universe mapped to `port` to `driver`, recording each
+    /// write in the port's health. Call after [`AlpineNodeSdk::with_ports`].
+    pub fn with_port_output(
+        mut self,
+        port: PortId,
+        driver: Arc<dyn OutputDriver>,
+    ) -> Result<Self, AlpineSdkError> {
+        if !self
+            .ports
+            .as_ref()
+            .is_some_and(|ports| ports.contains(port))
+        {
+            return Err(AlpineSdkError::InvalidInput(format!(
+                "port {port} is not one of the node's ports"
+            )));
+        }
+        self.port_outputs.push((port, driver));
+        Ok(self)
+    }
+
+    /// Installs the node's `get_status` handler over the subsystems enabled so far,
+    /// unless the application registered its own.
+    fn install_status(&mut self) {
+        if self.handlers.contains(&ControlOp::GetStatus) && !self.own_status {
+            return;
+        }
+        let (merge, ports) = (self.merge.clone(), self.ports.clone());
+        self.handlers
+            .on(ControlOp::GetStatus, move |_: serde_json::Value| {
+                let now = Instant::now();
+                let mut status = serde_json::Map::new();
+                if let Some(merge) = &merge {
+                    status.insert("merge".into(), serde_json::json!(merge.status(now)));
+                }
+                if let Some(ports) = &ports {
+                    status.insert("ports".into(), serde_json::json!(ports.status(now)));
+                }
+                Ok(Some(serde_json::Value::Object(status).to_string()))
+            });
+        self.own_status = true;
+    }
+
+    pub fn merge(&self) -> Option<&Arc<MergeEngine>> {
+        self.merge.as_ref()
+    }
+
+    /// Arbitrates frames from concurrent sessions by `priority`, per channel group, with
+    /// silent sources keeping their channels for `hold`.
+    ///
+    /// Arbitration takes the place of merge policies: when it is enabled, frames are not
+    /// passed to the merge engine.
+    pub fn with_priority_arbitration(mut self, hold: Duration) -> Self {
+        self.arbiter = Some(Arc::new(PriorityArbiter::new(hold)));
+        self
+    }
+
+    /// Answers `rdm_command` envelopes through `gateway`, so controllers can GET and SET
+    /// RDM parameters of the node and the gear behind it. Run an
+    /// [`LlrpTarget`](alpine::rdm::llrp::LlrpTarget) on the same gateway to be found by
+    /// RDMnet managers too.
+    pub fn with_rdm(self, gateway: Arc<RdmGateway>) -> Self {
+        gateway.register(&self.handlers);
+        self
+    }
+
+    /// Refuses controllers whose keys `store` revokes and answers `update_revocations`
+    /// with it, so controllers can push newer lists.
+    pub fn with_revocations(mut self, store: Arc<RevocationStore>) -> Self {
+        store.register(&self.handlers);
+        // Only `accept` borrows the server, so the node still holds the sole reference.
+        Arc::get_mut(&mut self.server)
+            .expect("device server is not shared before accept")
+            .revocations = Some(store);
+        self
+    }
+
+    /// Refuses controllers whose firmware is older than `rev`, or that do not report
+    /// theirs, unless a [`FirmwarePolicy`] set with
+    /// [`AlpineNodeSdk::with_firmware_policy`] lets them in.
+    pub fn with_min_controller_firmware(mut self, rev: impl Into<String>) -> Self {
+        Arc::get_mut(&mut self.server)
+            .expect("device server is not shared before accept")
+            .required_firmware_rev = Some(rev.into());
+        self
+    }
+
+    /// Asks `policy` whether a controller below the required firmware may connect for
+    /// degraded operation.
+    pub fn with_firmware_policy(mut self, policy: Arc<dyn FirmwarePolicy>) -> Self {
+        Arc::get_mut(&mut self.server)
+            .expect("device server is not shared before accept")
+            .firmware_policy = Some(policy);
+        self
+    }
+
+    /// Answers `restart` and `factory_reset` through `hook`, which may veto or delay
+    /// them based on show state and performs them once the controller has its ack.
+    /// Factory resets must be confirmed with a nonce from the same session.
+    pub fn with_maintenance(self, hook: Arc<dyn MaintenanceHook>) -> Self {
+        Arc::new(Maintenance::new(hook)).register(&self.handlers);
+        self
+    }
+
+    /// Sends every published look to `driver`, such as the `DmxSerialDriver` of the
+    /// `dmx-serial` feature. Call once per output port.
+    pub fn with_output(mut self, driver: Arc<dyn OutputDriver>) -> Self {
+        self.outputs.push(driver);
+        self
+    }
+
+    /// Answers `test_pattern` by driving the outputs with full-on, chase, ramp, or
+    /// single-channel patterns, so wiring can be checked without a console. Universes
+    /// under a pattern ignore streamed looks until it stops.
+    ///
+    /// Call after every [`AlpineNodeSdk::with_output`]; patterns reach the drivers
+    /// added so far.
+    pub fn with_test_patterns(mut self) -> Self {
+        let generator = Arc::new(PatternGenerator::new());
+        generator.register(&self.handlers);
+        let task = tokio::spawn(generator.clone().run(self.outputs.clone(), PATTERN_TICK));
+        if let Some((_, previous)) = self.patterns.replace((generator, task)) {
+            previous.abort();
+        }
+        self
+    }
+
+    pub fn patterns(&self) -> Option<&Arc<PatternGenerator>> {
+        self.patterns.as_ref().map(|(generator, _)| generator)
+    }
+
+    /// Refuses frames for universes outside `universes`; by default every universe is
+    /// accepted. Frames over the advertised `max_channels` or in a format the node did not
+    /// advertise are always refused; see [`NodeConnection::subscribe_rejections`].
+    pub fn with_universes(mut self, universes: impl IntoIterator<Item = UniverseId>) -> Self {
+        self.universes = Some(universes.into_iter().collect());
+        self
+    }
+
+    /// Refuses control envelopes beyond `limit` per session with `CONTROL_RATE_LIMITED`,
+    /// so a misbehaving controller cannot starve frame handling. Overrides the control
+    /// quota in [`DeviceServer::quotas`].
+    pub fn with_control_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.control_rate = Some(limit);
+        self
+    }
+
+    /// Grants each accepted session the access `policy` returns for its controller's
+    /// address, for example [`SessionAccess::Monitor`] for a monitoring host; without one
+    /// every session gets [`SessionAccess::Full`].
+    pub fn with_access_policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(SocketAddr) -> SessionAccess + Send + Sync + 'static,
+    {
+        self.access_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Installs a new identity key on the running node and returns the old one.
+    ///
+    /// Discovery replies and new handshakes use it at once; open sessions keep serving
+    /// until their controllers re-handshake. See [`DeviceServer::rotate_credentials`].
+    pub fn rotate_credentials(&self, credentials: NodeCredentials) -> NodeCredentials {
+        self.server.rotate_credentials(credentials)
+    }
+
+    /// Replaces the capabilities advertised in discovery and offered to new sessions, and
+    /// pushes them to the controller of every open session. Returns the previous ones.
+    ///
+    /// See [`AlpineNodeSdk::push_capabilities`] to tell controllers why.
+    pub fn update_capabilities(&self, capabilities: CapabilitySet) -> CapabilitySet {
+        self.push_capabilities(capabilities, None)
+    }
+
+    /// Like [`AlpineNodeSdk::update_capabilities`], with a `reason` such as
+    /// `"output port 2 failed"` for the controllers' logs.
+    ///
+    /// Each open session sends its controller a signed capability update and checks
+    /// further frames against the new limits; controllers adjust their streams on receipt.
+    pub fn push_capabilities(
+        &self,
+        capabilities: CapabilitySet,
+        reason: Option<String>,
+    ) -> CapabilitySet {
+        let previous = self.server.update_capabilities(capabilities.clone());
+        self.capabilities.send_replace(CapabilityNotice {
+            capabilities,
+            reason,
+        });
+        previous
+    }
+
+    /// Open sessions, oldest first.
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        self.server.sessions.list()
+    }
+
+    /// Changes what an open session may do; `false` if it is not open, or if it is an
+    /// observer session being given full access.
+    pub fn set_session_access(&self, session_id: Uuid, access: SessionAccess) -> bool {
+        self.server.sessions.set_access(session_id, access)
+    }
+
+    /// Closes a session and stops serving it, leaving the others untouched; `false` if it
+    /// is not open.
+    pub fn close_session(&self, session_id: Uuid) -> bool {
+        self.routes.remove(session_id);
+        let closed = self.server.sessions.close(session_id);
+        if let Some(log) = self.log.as_ref().filter(|_| closed) {
+            log.info("session", format!("session {} closed", session_id));
+        }
+        closed
+    }
+
+    /// Has the preempted session's worker warn its controller and closes the session
+    /// once the grace period is over; the new session streams from then on.
+    fn preempt(&self, takeover: Takeover) {
+        if let Some(log) = &self.log {
+            log.warn(
+                "session",
+                format!(
+                    "session {} takes over from session {} at priority {}; closing it in {:?}",
+                    takeover.by, takeover.preempted, takeover.priority, takeover.grace
+                ),
+            );
+        }
+        let _ = self.takeovers.send(takeover);
+        let routes = self.routes.clone();
+        let sessions = self.server.sessions.clone();
+        let log = self.log.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(takeover.grace).await;
+            routes.remove(takeover.preempted);
+            let closed = sessions.close(takeover.preempted);
+            if let Some(log) = log.filter(|_| closed) {
+                log.info("session", format!("session {} closed", takeover.preempted));
+            }
+        });
+    }
+
+    /// Returns the address controllers should discover and connect to.
+    pub fn local_addr(&self) -> Result<SocketAddr, AlpineSdkError> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Registers the handler for `op`, replacing any previous one.
+    ///
+    /// Operations without a handler are answered with a `CONTROL_UNKNOWN_OP` error envelope.
+    pub fn on_control<F>(&self, op: ControlOp, handler: F)
+    where
+        F: Fn(&ControlEnvelope) -> Result<Option<String>, String> + Send + Sync + 'static,
+    {
+        self.handlers.on_envelope(op, handler);
+    }
+
+    /// Handler table shared with every accepted session, for registering typed or async
+    /// handlers.
+    pub fn control_handlers(&self) -> &ControlHandlers {
+        &self.handlers
+    }
+
+    /// Waits until a controller completes a handshake, then serves that session.
+    ///
+    /// Call it again to admit further controllers while earlier sessions stay served;
+    /// concurrent calls take turns. Discovery is answered throughout. The returned
+    /// connection serves control requests in the background and yields received looks
+    /// through [`NodeConnection::next_look`]; it stops when the node is dropped.
+    pub async fn accept(&self) -> Result<NodeConnection, AlpineSdkError> {
+        let mut queue = self.handshakes.lock().await;
+        let mut transport = NodeTransport {
+            socket: self.socket.clone(),
+            queue: &mut queue,
+            peer: None,
+        };
+        let session = match self.server.accept(&mut transport).await {
+            Ok(session) => session,
+            Err(err) => {
+                if let Some(log) = &self.log {
+                    let peer = transport
+                        .peer
+                        .map_or_else(|| "unknown peer".to_string(), |peer| peer.to_string());
+                    log.warn(
+                        "handshake",
+                        format!("handshake with {} failed: {}", peer, err),
+                    );
+                }
+                return Err(err.into());
+            }
+        };
+        let controller = transport
+            .peer
+            .ok_or_else(|| AlpineSdkError::Internal("handshake completed without a peer".into()))?;
+        drop(queue);
+        let established = session
+            .established()
+            .ok_or_else(|| AlpineSdkError::Internal("session missing after handshake".into()))?;
+        let keys = session
+            .keys()
+            .ok_or_else(|| AlpineSdkError::Internal("session keys missing".into()))?;
+        let session_id = established.session_id;
+
+        // A controller handshaking again from the same address has lost its old session.
+        let sessions = self.server.sessions.clone();
+        for stale in sessions.list() {
+            if stale.session_id != session_id && stale.peer == Some(controller) {
+                self.close_session(stale.session_id);
+            }
+        }
+        sessions.set_peer(session_id, controller);
+        if let Some(policy) = &self.access_policy {
+            sessions.set_access(session_id, policy(controller));
+        }
+        if let Some(log) = &self.log {
+            log.info(
+                "session",
+                format!("controller {} opened session {}", controller, session_id),
+            );
+        }
+        if let Some(takeover) = sessions
+            .takeover(session_id)
+            .filter(|takeover| takeover.by == session_id)
+        {
+            self.preempt(takeover);
+        }
+        let (inbox_sender, inbox) = mpsc::channel(SESSION_INBOX_CAPACITY);
+        self.routes.insert(session_id, inbox_sender);
+
+        let (looks, receiver) = mpsc::channel(LOOK_CHANNEL_CAPACITY);
+        let stream_activity = Arc::new(Mutex::new(None));
+        let mut validator = self.server.frame_validator();
+        if let Some(universes) = &self.universes {
+            validator = validator.with_universes(universes.iter().copied());
+        }
+        let validator = Arc::new(Mutex::new(validator));
+        let (rejections, _) = broadcast::channel(REJECTION_CHANNEL_CAPACITY);
+        let mut control = ControlDispatcher::new(
+            ControlResponder::new(established.session_id, ControlCrypto::new(keys)),
+            self.handlers.clone(),
+        );
+        let (progress, updates) = mpsc::unbounded_channel();
+        control.set_progress_sink(progress.clone());
+        if let Some(limit) = self.control_rate.or(self.server.quotas.control_rate()) {
+            control.set_rate_limit(limit);
+        }
+        let registry = sessions.clone();
+        control.set_permissions(Arc::new(move |op: &ControlOp| {
+            registry
+                .access(session_id)
+                .is_some_and(|access| access.allows(op))
+        }));
+        tokio::spawn(forward_progress(self.socket.clone(), controller, updates));
+        let worker = NodeWorker {
+            socket: self.socket.clone(),
+            inbox,
+            sessions,
+            session: session.clone(),
+            control,
+            groups: self.groups.clone(),
+            latency: self.latency.clone(),
+            clock: self.clock.clone(),
+            capture: self.capture.clone(),
+            log: self.log.clone(),
+            scenes: self.scenes.clone(),
+            fallback: self.fallback.clone(),
+            merge: self.merge.clone(),
+            arbiter: self.arbiter.clone(),
+            mirror: self.server.mirror.clone(),
+            outputs: self.outputs.clone(),
+            ports: self.ports.clone(),
+            port_outputs: self.port_outputs.clone(),
+            patterns: self
+                .patterns
+                .as_ref()
+                .map(|(generator, _)| generator.clone()),
+            controller,
+            last_frames: HashMap::new(),
+            config_tag: None,
+            jitter: None,
+            fec: None,
+            reassembler: Reassembler::default(),
+            ratchet: None,
+            sealed: false,
+            smoother: Smoother::new(),
+            ramping: Vec::new(),
+            stream_activity: stream_activity.clone(),
+            validator: validator.clone(),
+            rejections: rejections.clone(),
+            heartbeat_seq: None,
+            looks,
+            notices: progress,
+            capabilities: self.capabilities.subscribe(),
+            capability_seq: CAPABILITY_UPDATE_SEQ_BASE,
+            takeovers: self.takeovers.subscribe(),
+        };
+        let task = tokio::spawn(worker.run());
+
+        Ok(NodeConnection {
+            session,
+            controller,
+            stream_activity,
+            validator,
+            rejections,
+            looks: receiver,
+            task,
+        })
+    }
+}
+
+impl Drop for AlpineNodeSdk {
+    fn drop(&mut self) {
+        if let Some((_, task)) = &self.scheduler {
+            task.abort();
+        }
+        if let Some((_, task)) = &self.patterns {
+            task.abort();
+        }
+        self.receive.abort();
+    }
+}
+
+/// Session accepted by [`AlpineNodeSdk::accept`].
+pub struct NodeConnection {
+    session: AlnpSession,
+    controller: SocketAddr,
+    stream_activity: Arc<Mutex<Option<Instant>>>,
+    validator: Arc<Mutex<FrameValidator>>,
+    rejections: broadcast::Sender<FrameRejected>,
+    looks: mpsc::Receiver<FrameEnvelope>,
+    task: JoinHandle<()>,
+}
+
+impl NodeConnection {
+    /// Returns the established node-side session.
+    pub fn session(&self) -> &AlnpSession {
+        &self.session
+    }
+
+    /// Returns the controller's control-plane address.
+    pub fn controller_addr(&self) -> SocketAddr {
+        self.controller
+    }
+
+    /// Waits for the next look after jitter handling; `None` once the session ends.
+    pub async fn next_look(&mut self) -> Option<FrameEnvelope> {
+        self.looks.recv().await
+    }
+
+    /// Whether the controller has paused its stream with `pause_stream`; the last look
+    /// stays in place until it resumes.
+    pub fn stream_paused(&self) -> bool {
+        !self.session.streaming_enabled()
+    }
+
+    /// When the controller last sent a frame or stream heartbeat; `None` before either.
+    ///
+    /// Heartbeats keep coming while the stream is paused or static, so a gap of several
+    /// heartbeat intervals means the controller is gone.
+    pub fn last_stream_activity(&self) -> Option<Instant> {
+        *self
+            .stream_activity
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Subscribes to reports of refused frames: at most one per reason and universe each
+    /// second, counting the frames refused since the previous one.
+    pub fn subscribe_rejections(&self) -> broadcast::Receiver<FrameRejected> {
+        self.rejections.subscribe()
+    }
+
+    /// Frames refused so far for exceeding `max_channels`, using a format the node did not
+    /// advertise, or addressing a universe it does not serve.
+    pub fn rejection_stats(&self) -> RejectionStats {
+        self.validator
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .stats()
+    }
+
+    /// Stops serving the session and closes it.
+    pub fn close(self) {
+        self.task.abort();
+        self.session.close();
+    }
+}
+
+/// Datagram categories multiplexed on the node socket.
+// Frames hold their levels inline; boxing them would allocate for every datagram.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+enum Inbound {
+    Discovery(DiscoveryRequest),
+    Frame(FrameEnvelope),
+    Echo(EchoFrame),
+    Parity(ParityFrame),
+    Fragment(FrameFragment),
+    Sealed(SealedFrame),
+    Heartbeat(StreamHeartbeat),
+    Handshake(HandshakeMessage),
+}
+
+fn classify(bytes: &[u8]) -> Option<Inbound> {
+    if let Ok(frame) = codec::decode_untrusted::<FrameEnvelope>(bytes) {
+        if frame.message_type == MessageType::AlpineFrame {
+            return Some(Inbound::Frame(frame));
+        }
+    }
+    if let Ok(fragment) = codec::decode_untrusted::<FrameFragment>(bytes) {
+        if fragment.message_type == MessageType::AlpineFrameFragment {
+            return Some(Inbound::Fragment(fragment));
+        }
+    }
+    if let Ok(sealed) = codec::decode_untrusted::<SealedFrame>(bytes) {
+        if sealed.message_type == MessageType::AlpineSealedFrame {
+            return Some(Inbound::Sealed(sealed));
+        }
+    }
+    if let Ok(echo) = codec::decode_untrusted::<EchoFrame>(bytes) {
+        if echo.message_type == MessageType::AlpineEcho {
+            return Some(Inbound::Echo(echo));
+        }
+    }
+    if let Ok(heartbeat) = codec::decode_untrusted::<StreamHeartbeat>(bytes) {
+        if heartbeat.message_type == MessageType::AlpineStreamHeartbeat {
+            return Some(Inbound::Heartbeat(heartbeat));
+        }
+    }
+    if let Ok(parity) = codec::decode_untrusted::<ParityFrame>(bytes) {
+        if parity.message_type == MessageType::AlpineParity {
+            return Some(Inbound::Parity(parity));
+        }
+    }
+    if let Ok(request) = codec::decode_untrusted::<DiscoveryRequest>(bytes) {
+        if request.message_type == MessageType::AlpineDiscover {
+            return Some(Inbound::Discovery(request));
+        }
+    }
+    codec::decode_untrusted::<HandshakeMessage>(bytes)
+        .ok()
+        .map(Inbound::Handshake)
+}
+
+impl Inbound {
+    /// Session a message belongs to; `None` for discovery and handshake steps.
+    fn session_id(&self) -> Option<Uuid> {
+        match self {
+            Inbound::Frame(frame) => Some(frame.session_id),
+            Inbound::Echo(probe) => Some(probe.session_id),
+            Inbound::Parity(parity) => Some(parity.session_id),
+            Inbound::Fragment(fragment) => Some(fragment.session_id),
+            Inbound::Sealed(sealed) => Some(sealed.session_id),
+            Inbound::Heartbeat(heartbeat) => Some(heartbeat.session_id),
+            Inbound::Handshake(HandshakeMessage::Control(env)) => Some(env.session_id),
+            Inbound::Handshake(HandshakeMessage::Keepalive(keepalive)) => {
+                Some(keepalive.session_id)
+            }
+            Inbound::Handshake(HandshakeMessage::SessionClose(close)) => Some(close.session_id),
+            Inbound::Handshake(_) | Inbound::Discovery(_) => None,
+        }
+    }
+}
+
+/// A datagram routed to the session it belongs to.
+#[derive(Debug)]
+struct Datagram {
+    inbound: Inbound,
+    bytes: Vec<u8>,
+    from: SocketAddr,
+    read_at: Instant,
+    read_us: u64,
+}
+
+/// Inbox of each session being served, keyed by session id.
+#[derive(Clone, Default)]
+struct Routes(Arc<Mutex<HashMap<Uuid, mpsc::Sender<Datagram>>>>);
+
+impl Routes {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, mpsc::Sender<Datagram>>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn insert(&self, session_id: Uuid, inbox: mpsc::Sender<Datagram>) {
+        self.lock().insert(session_id, inbox);
+    }
+
+    fn remove(&self, session_id: Uuid) {
+        self.lock().remove(&session_id);
+    }
+
+    fn get(&self, session_id: Uuid) -> Option<mpsc::Sender<Datagram>> {
+        self.lock().get(&session_id).cloned()
+    }
+}
+
+/// Handshake messages not yet taken by an [`AlpineNodeSdk::accept`].
+struct HandshakeQueue {
+    inbound: mpsc::Receiver<(HandshakeMessage, SocketAddr)>,
+    /// Messages from other controllers, received while one handshake was in progress.
+    deferred: VecDeque<(HandshakeMessage, SocketAddr)>,
+}
+
+/// Reads the node socket, answers discovery, and hands every other datagram to the
+/// session it names or, for handshake steps, to the next [`AlpineNodeSdk::accept`].
+///
+/// Routing by session id rather than address lets a controller stream from another port
+/// than it handshakes on. A session whose inbox is full loses the datagram, like the
+/// network would, instead of stalling every other session.
+async fn receive_loop(
+    socket: Arc<UdpSocket>,
+    server: Arc<DeviceServer>,
+    routes: Routes,
+    handshakes: mpsc::Sender<(HandshakeMessage, SocketAddr)>,
+    clock: Arc<NodeClock>,
+) {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    loop {
+        let Ok((len, from)) = socket.recv_from(&mut buf).await else {
+            break;
+        };
+        let read_at = Instant::now();
+        let read_us = clock.now_us();
+        let Some(inbound) = classify(&buf[..len]) else {
+            continue;
+        };
+        if let Inbound::Discovery(request) = &inbound {
+            // Built per request so replies follow rotations, capability updates, and
+            // whether the node has room for another session.
+            reply_discovery(&socket, &server.discovery_reply(request), from).await;
+            continue;
+        }
+        let route = inbound
+            .session_id()
+            .and_then(|id| routes.get(id).map(|r| (id, r)));
+        match (route, inbound) {
+            (Some((session_id, inbox)), inbound) => {
+                let datagram = Datagram {
+                    inbound,
+                    bytes: buf[..len].to_vec(),
+                    from,
+                    read_at,
+                    read_us,
+                };
+                if let Err(mpsc::error::TrySendError::Closed(_)) = inbox.try_send(datagram) {
+                    routes.remove(session_id);
+                }
+            }
+            // Control and keepalives for a session no longer served are dropped; the
+            // controller notices on its own timeout.
+            (None, Inbound::Handshake(HandshakeMessage::Control(_)))
+            | (None, Inbound::Handshake(HandshakeMessage::Keepalive(_)))
+            | (None, Inbound::Handshake(HandshakeMessage::SessionClose(_))) => {}
+            (None, Inbound::Handshake(msg)) => {
+                let _ = handshakes.try_send((msg, from));
+            }
+            // Frames before a session exists are meaningless; drop them.
+            (None, _) => {}
+        }
+    }
+}
+
+async fn reply_discovery(socket: &UdpSocket, reply: &DiscoveryReply, peer: SocketAddr) {
+    if let Ok(bytes) = codec::to_vec(reply) {
+        // Best-effort: the controller retries discovery on its own timeout.
+        let _ = socket.send_to(&bytes, peer).await;
+    }
+}
+
+/// Handshake transport that learns the controller address from the first handshake message.
+struct NodeTransport<'a> {
+    socket: Arc<UdpSocket>,
+    queue: &'a mut HandshakeQueue,
+    peer: Option<SocketAddr>,
+}
+
+#[async_trait]
+impl HandshakeTransport for NodeTransport<'_> {
+    async fn send(&mut self, msg: HandshakeMessage) -> Result<(), HandshakeError> {
+        let peer = self
+            .peer
+            .ok_or_else(|| HandshakeError::Transport("no controller to reply to".into()))?;
+        let bytes = codec::to_vec(&msg).map_err(|e| HandshakeError::Transport(e.to_string()))?;
+        self.socket
+            .send_to(&bytes, peer)
+            .await
+            .map_err(|e| HandshakeError::Transport(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+        let deferred = &mut self.queue.deferred;
+        let waiting = match self.peer {
+            None => deferred.pop_front(),
+            Some(peer) => deferred
+                .iter()
+                .position(|(_, from)| *from == peer)
+                .and_then(|index| deferred.remove(index)),
+        };
+        if let Some((msg, from)) = waiting {
+            self.peer = Some(from);
+            return Ok(msg);
+        }
+        loop {
+            let (msg, from) = self
+                .queue
+                .inbound
+                .recv()
+                .await
+                .ok_or_else(|| HandshakeError::Transport("node socket closed".into()))?;
+            if self.peer.is_none() || self.peer == Some(from) {
+                self.peer = Some(from);
+                return Ok(msg);
+            }
+            // Another controller's handshake waits for the next accept.
+            if self.queue.deferred.len() >= HANDSHAKE_QUEUE_CAPACITY {
+                self.queue.deferred.pop_front();
+            }
+            self.queue.deferred.push_back((msg, from));
+        }
+    }
+}
+
+/// Background loop serving an established node session.
+struct NodeWorker {
+    socket: Arc<UdpSocket>,
+    /// Datagrams the receive loop routed to this session.
+    inbox: mpsc::Receiver<Datagram>,
+    /// Holds this session's access, which the node may change while it runs.
+    sessions: SessionRegistry,
+    session: AlnpSession,
+    control: ControlDispatcher,
+    groups: Arc<GroupRegistry>,
+    latency: Arc<LatencyRecorder>,
+    clock: Arc<NodeClock>,
+    capture: Option<Arc<FrameCapture>>,
+    log: Option<Arc<NodeLog>>,
+    scenes: Option<Arc<SceneEngine>>,
+    fallback: Option<Arc<FallbackPlayer>>,
+    merge: Option<Arc<MergeEngine>>,
+    arbiter: Option<Arc<PriorityArbiter>>,
+    /// Applied looks, and the observers subscribed to them.
+    mirror: OutputMirror,
+    outputs: Vec<Arc<dyn OutputDriver>>,
+    ports: Option<Arc<PortMap>>,
+    port_outputs: Vec<(PortId, Arc<dyn OutputDriver>)>,
+    /// Test patterns that hold universes of the outputs.
+    patterns: Option<Arc<PatternGenerator>>,
+    controller: SocketAddr,
+    last_frames: HashMap<UniverseId, FrameEnvelope>,
+    /// Tag of the profile the controller announced with `start_stream`.
+    config_tag: Option<u32>,
+    /// Holds frames for the announced profile's buffer depth; `None` applies on arrival.
+    jitter: Option<JitterBuffer>,
+    /// Rebuilds lost frames from parity when the announced profile sends it.
+    fec: Option<FecDecoder>,
+    /// Joins frames and parity the controller cut to fit its path MTU.
+    reassembler: Reassembler,
+    /// Epoch keys of the controller's encrypted stream, set up on its first sealed frame.
+    ratchet: Option<StreamRatchet>,
+    /// Whether the controller has sealed its frames; frames in the clear are then dropped.
+    sealed: bool,
+    smoother: Smoother,
+    /// Universes still ramping when the next smoothing tick was scheduled.
+    ramping: Vec<UniverseId>,
+    /// Last frame or verified stream heartbeat, shared with the connection.
+    stream_activity: Arc<Mutex<Option<Instant>>>,
+    /// Refuses frames outside the negotiated limits, shared with the connection.
+    validator: Arc<Mutex<FrameValidator>>,
+    rejections: broadcast::Sender<FrameRejected>,
+    /// Highest heartbeat sequence accepted, so replays are ignored.
+    heartbeat_seq: Option<u64>,
+    looks: mpsc::Sender<FrameEnvelope>,
+    /// Unsolicited messages to the controller, such as throttling errors.
+    notices: mpsc::UnboundedSender<HandshakeMessage>,
+    /// Capabilities the node pushes while the session is open.
+    capabilities: watch::Receiver<CapabilityNotice>,
+    /// Sequence of the next capability update or preemption notice sent to the
+    /// controller.
+    capability_seq: u64,
+    takeovers: broadcast::Receiver<Takeover>,
+}
+
+/// What woke the receive loop.
+enum Wake {
+    Datagram(Option<Box<Datagram>>),
+    Release,
+    Tick,
+    HoldExpired,
+    ReassemblyExpired,
+    /// `false` once the node is gone.
+    Capabilities(bool),
+    Takeover(Result<Takeover, broadcast::error::RecvError>),
+    /// The controller has said nothing since the handshake, past the registry's
+    /// first-activity deadline.
+    Silent,
+    /// An observer is due the looks applied since it was last sent one.
+    Mirror,
+}
+
+impl NodeWorker {
+    async fn run(mut self) {
+        self.serve().await;
+        self.release();
+    }
+
+    async fn serve(&mut self) {
+        let mut tick: Option<Instant> = None;
+        let half_open = self
+            .sessions
+            .first_activity_deadline()
+            .map(|deadline| Instant::now() + deadline);
+        let session_id = self
+            .session
+            .established()
+            .map(|established| established.session_id);
+        loop {
+            if tick.is_none() {
+                let now = Instant::now();
+                self.ramping = self.smoother.ramping(now);
+                tick = (!self.ramping.is_empty()).then(|| now + SMOOTHING_TICK);
+            }
+            let due = self.jitter.as_ref().and_then(JitterBuffer::next_due);
+            let hold = self.session.smoothing().hold;
+            let stale = hold.and_then(|hold| self.smoother.hold_deadline(&hold));
+            let partial = self.reassembler.next_deadline();
+            let silent = half_open.filter(|_| self.session.last_peer_activity().is_none());
+            let mirrored = session_id.and_then(|session_id| self.mirror.next_due(session_id));
+            let wake = tokio::select! {
+                received = self.inbox.recv() => Wake::Datagram(received.map(Box::new)),
+                _ = tokio::time::sleep_until(due.unwrap_or_else(Instant::now).into()),
+                    if due.is_some() => Wake::Release,
+                _ = tokio::time::sleep_until(tick.unwrap_or_else(Instant::now).into()),
+                    if tick.is_some() => Wake::Tick,
+                _ = tokio::time::sleep_until(stale.unwrap_or_else(Instant::now).into()),
+                    if stale.is_some() => Wake::HoldExpired,
+                _ = tokio::time::sleep_until(partial.unwrap_or_else(Instant::now).into()),
+                    if partial.is_some() => Wake::ReassemblyExpired,
+                changed = self.capabilities.changed() => Wake::Capabilities(changed.is_ok()),
+                taken = self.takeovers.recv() => Wake::Takeover(taken),
+                _ = tokio::time::sleep_until(silent.unwrap_or_else(Instant::now).into()),
+                    if silent.is_some() => Wake::Silent,
+                _ = tokio::time::sleep_until(mirrored.unwrap_or_else(Instant::now).into()),
+                    if mirrored.is_some() => Wake::Mirror,
+            };
+            let received = match wake {
+                Wake::Datagram(received) => received,
+                Wake::Release => {
+                    if !self.release_due().await {
+                        break;
+                    }
+                    continue;
+                }
+                Wake::HoldExpired => {
+                    let Some(hold) = hold else { continue };
+                    // Publish the fade's start at once; later ticks carry it down.
+                    for universe in self.smoother.expire(&hold, Instant::now()) {
+                        if !self.ramping.contains(&universe) {
+                            self.ramping.push(universe);
+                        }
+                    }
+                    tick = Some(Instant::now());
+                    continue;
+                }
+                Wake::ReassemblyExpired => {
+                    self.expire_fragments(Instant::now());
+                    continue;
+                }
+                Wake::Capabilities(false) => break,
+                Wake::Capabilities(true) => {
+                    self.push_capabilities();
+                    continue;
+                }
+                Wake::Takeover(Err(broadcast::error::RecvError::Closed)) => break,
+                Wake::Takeover(taken) => {
+                    if let Ok(takeover) = taken {
+                        self.announce_preemption(takeover);
+                    }
+                    continue;
+                }
+                Wake::Tick => {
+                    tick = None;
+                    if !self.publish_ramps().await {
+                        break;
+                    }
+                    continue;
+                }
+                Wake::Mirror => {
+                    self.push_mirror(Instant::now());
+                    continue;
+                }
+                Wake::Silent => {
+                    self.log(
+                        LogSeverity::Warn,
+                        "session",
+                        format!(
+                            "controller {} went silent after its handshake; closing the session",
+                            self.controller
+                        ),
+                    );
+                    break;
+                }
+            };
+            // The node closed the session or stopped routing to it.
+            let Some(Datagram {
+                inbound,
+                bytes,
+                from,
+                read_at,
+                read_us,
+            }) = received.map(|datagram| *datagram)
+            else {
+                break;
+            };
+            let (inbound, bytes) = match inbound {
+                Inbound::Fragment(fragment) => match self.reassemble(fragment, read_at) {
+                    Some(reassembled) => reassembled,
+                    None => continue,
+                },
+                inbound => (inbound, bytes),
+            };
+            let (inbound, bytes) = match inbound {
+                Inbound::Sealed(sealed) => match self.unseal(&sealed) {
+                    Some(opened) => opened,
+                    None => continue,
+                },
+                // A controller that seals its stream never sends frames in the clear.
+                Inbound::Frame(_) | Inbound::Parity(_) if self.sealed => continue,
+                inbound => (inbound, bytes),
+            };
+            let open = match inbound {
+                Inbound::Frame(frame) if !self.within_quota(&frame, read_at) => true,
+                Inbound::Frame(frame) => {
+                    self.mark_stream_activity(read_at);
+                    if let Some(fec) = &mut self.fec {
+                        fec.observe(frame.universe, frame.timestamp_us, &bytes);
+                    }
+                    self.receive_frame(frame, read_at, read_us).await
+                }
+                Inbound::Parity(parity) => self.recover_frame(&parity, read_at, read_us).await,
+                Inbound::Echo(probe) => {
+                    self.reflect_echo(&probe, read_us, from).await;
+                    true
+                }
+                Inbound::Heartbeat(heartbeat) => {
+                    self.accept_heartbeat(&heartbeat, read_at);
+                    true
+                }
+                Inbound::Handshake(HandshakeMessage::Control(env)) => {
+                    self.handle_control(env).await;
+                    true
+                }
+                Inbound::Handshake(HandshakeMessage::Keepalive(keepalive)) => {
+                    self.session.update_keepalive();
+                    self.answer_keepalive(keepalive);
+                    true
+                }
+                Inbound::Handshake(HandshakeMessage::SessionClose(close)) => {
+                    !self.accept_close(&close)
+                }
+                Inbound::Fragment(_)
+                | Inbound::Sealed(_)
+                | Inbound::Handshake(_)
+                | Inbound::Discovery(_) => true,
+            };
+            if !open {
+                break;
+            }
+        }
+    }
+
+    /// Gives up what the session held once it is no longer served: its registry slot, its
+    /// merge and arbitration sources, so the universes it drove fall to the others, and
+    /// any output mirror subscription.
+    fn release(&self) {
+        let Some(established) = self.session.established() else {
+            return;
+        };
+        let session_id = established.session_id;
+        self.sessions.close(session_id);
+        if let Some(merge) = &self.merge {
+            merge.forget(session_id);
+        }
+        if let Some(arbiter) = &self.arbiter {
+            arbiter.forget(session_id);
+        }
+        self.mirror.unsubscribe(session_id);
+    }
+
+    /// Buffers a frame or applies it straight away; `false` once looks have no receiver.
+    async fn receive_frame(
+        &mut self,
+        frame: FrameEnvelope,
+        read_at: Instant,
+        read_us: u64,
+    ) -> bool {
+        match &mut self.jitter {
+            Some(jitter) => {
+                jitter.push_by_deadline(frame, read_at, read_us);
+                true
+            }
+            None => self.apply_frame(frame, read_at, read_us).await,
+        }
+    }
+
+    /// Adds a fragment; once its datagram is whole, returns the frame or parity it carried.
+    fn reassemble(&mut self, fragment: FrameFragment, now: Instant) -> Option<(Inbound, Vec<u8>)> {
+        self.expire_fragments(now);
+        let session_id = fragment.session_id;
+        let bytes = self.reassembler.push(fragment, now)?;
+        match classify(&bytes)? {
+            inbound @ (Inbound::Frame(_) | Inbound::Parity(_) | Inbound::Sealed(_))
+                if inbound.session_id() == Some(session_id) =>
+            {
+                Some((inbound, bytes))
+            }
+            _ => None,
+        }
+    }
+
+    /// Decrypts a sealed datagram; returns the frame or parity it carried, or `None` when
+    /// it does not open under this session's epoch keys.
+    fn unseal(&mut self, sealed: &SealedFrame) -> Option<(Inbound, Vec<u8>)> {
+        if self.ratchet.is_none() {
+            self.ratchet = StreamRatchet::receiver(&self.session.keys()?).ok();
+        }
+        let bytes = sealed.open(self.ratchet.as_mut()?).ok()?;
+        self.sealed = true;
+        match classify(&bytes)? {
+            inbound @ (Inbound::Frame(_) | Inbound::Parity(_))
+                if inbound.session_id() == Some(sealed.session_id) =>
+            {
+                Some((inbound, bytes))
+            }
+            _ => None,
+        }
+    }
+
+    /// Drops datagrams whose fragments stopped arriving and counts them on the session.
+    fn expire_fragments(&mut self, now: Instant) {
+        let expired = self.reassembler.expire(now);
+        if expired > 0 {
+            self.session.record_reassembly_timeouts(expired as u64);
+        }
+    }
+
+    /// Rebuilds the frame a parity frame says went missing, if it is the only one.
+    async fn recover_frame(
+        &mut self,
+        parity: &ParityFrame,
+        read_at: Instant,
+        read_us: u64,
+    ) -> bool {
+        let Some(established) = self.session.established() else {
+            return true;
+        };
+        if parity.session_id != established.session_id {
+            return true;
+        }
+        let Some(bytes) = self.fec.as_mut().and_then(|fec| fec.recover(parity)) else {
+            return true;
+        };
+        match classify(&bytes) {
+            Some(Inbound::Frame(frame)) => self.receive_frame(frame, read_at, read_us).await,
+            _ => true,
+        }
+    }
+
+    /// Applies the frames whose buffer hold has expired.
+    async fn release_due(&mut self) -> bool {
+        let Some(jitter) = &mut self.jitter else {
+            return true;
+        };
+        for (frame, arrived) in jitter.pop_due(Instant::now()) {
+            let read_us = self
+                .clock
+                .now_us()
+                .saturating_sub(arrived.elapsed().as_micros() as u64);
+            if !self.apply_frame(frame, arrived, read_us).await {
+                return false;
+            }
+        }
+        true
+    }
+
+    async fn apply_frame(&mut self, frame: FrameEnvelope, read_at: Instant, read_us: u64) -> bool {
+        // Needs synchronised clocks; a sender clock ahead of ours gives no sample.
+        let network = read_us
+            .checked_sub(frame.timestamp_us)
+            .map(Duration::from_micros);
+        let late = frame.missed_deadline(read_us);
+        let captured = self.capture.is_some().then(|| frame.clone());
+        let applying = Instant::now();
+        let look = self.accept_frame(frame);
+        if let Some(received) = captured {
+            self.capture_frame(received, read_us, look.is_some());
+        }
+        if let Some(look) = look {
+            if late {
+                self.session.record_late_frame();
+            }
+            if self.looks.send(look).await.is_err() {
+                return false;
+            }
+            self.latency.record(LatencySample {
+                network,
+                queue: Some(applying.duration_since(read_at)),
+                apply: Some(applying.elapsed()),
+                ..LatencySample::default()
+            });
+        }
+        true
+    }
+
+    /// Records the levels of a received frame as decoded, before smoothing.
+    fn capture_frame(&self, mut frame: FrameEnvelope, read_us: u64, applied: bool) {
+        let Some(capture) = &self.capture else {
+            return;
+        };
+        // Payloads too malformed to decompress are kept with whatever levels they carry.
+        let _ = frame.decompress();
+        let format = frame.channel_format;
+        frame.unpack();
+        let arrival = FrameArrival {
+            received_us: read_us,
+            source: self.controller.to_string(),
+            universe: frame.universe,
+            priority: frame.priority,
+            timestamp_us: frame.timestamp_us,
+            format,
+            applied,
+        };
+        capture.record(arrival, frame.channels.into_vec());
+    }
+
+    /// Applies ordering and jitter handling; returns the look to publish, if any.
+    fn accept_frame(&mut self, mut frame: FrameEnvelope) -> Option<FrameEnvelope> {
+        let established = self.session.ensure_streaming_ready().ok()?;
+        if frame.session_id != established.session_id {
+            return None;
+        }
+        // Monitoring sessions read status but never drive the outputs, and a session
+        // taking over waits for the one it preempts to go.
+        if !self.sessions.may_stream(established.session_id) {
+            return None;
+        }
+        // A tag other than the announced profile's means the controller changed profile
+        // without telling the node; untagged frames come from controllers that predate
+        // announcements.
+        if frame.config_tag.is_some() && frame.config_tag != self.config_tag {
+            return None;
+        }
+        // Frames naming groups this node does not know cannot be applied as intended.
+        self.groups.resolve(&mut frame).ok()?;
+        // Malformed or oversized compressed payloads are dropped like any bad frame.
+        frame.decompress().ok()?;
+        // Checked before unpacking so the format is the one the controller sent.
+        let mut validator = self
+            .validator
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(reason) = validator.check(&frame) {
+            if let Some(report) = validator.reject(reason, frame.universe, Instant::now()) {
+                self.notify_refusal(&report, None);
+                let _ = self.rejections.send(report);
+            }
+            return None;
+        }
+        drop(validator);
+        // Merge, arbitration, and scenes work on integer levels; float frames keep 16 bits.
+        frame.unpack();
+        // Frames past the session's per-key limit are refused until the controller
+        // re-handshakes.
+        self.session.record_frame().ok()?;
+        self.session.record_frame_received();
+        let last = self.last_frames.get(&frame.universe);
+        if let Some(last) = last {
+            // Late arrivals would rewind the look; drop them.
+            if frame.timestamp_us < last.timestamp_us {
+                return None;
+            }
+        }
+        let now = Instant::now();
+        frame.channels = self.smoother.apply(
+            self.session.jitter_strategy(),
+            &self.session.smoothing(),
+            frame.universe,
+            &frame.channels,
+            frame.groups.as_ref(),
+            now,
+        )?;
+        self.last_frames.insert(frame.universe, frame.clone());
+        // Only live frames count as activity; ramps and fades republished later do not.
+        if let Some(fallback) = &self.fallback {
+            fallback.observe(now);
+        }
+        Some(self.publish(frame, now))
+    }
+
+    /// Spends the session's frame-rate quota on an arriving frame; `false` refuses it.
+    fn within_quota(&self, frame: &FrameEnvelope, now: Instant) -> bool {
+        let mut validator = self
+            .validator
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Err(wait) = validator.admit(now) else {
+            return true;
+        };
+        self.session.record_throttled_frame();
+        if let Some(report) = validator.reject(RejectionReason::RateExceeded, frame.universe, now) {
+            self.notify_refusal(&report, Some(wait));
+            let _ = self.rejections.send(report);
+        }
+        false
+    }
+
+    /// Tells the controller its frames break a quota, so it can back off or trim them.
+    /// Other refusals are only reported locally, as before quotas existed.
+    fn notify_refusal(&self, report: &FrameRejected, retry_after: Option<Duration>) {
+        self.log(
+            LogSeverity::Warn,
+            "stream",
+            format!(
+                "{} frame(s) from {} on universe {} refused: {}",
+                report.count,
+                self.controller,
+                report.universe,
+                report.reason.as_str()
+            ),
+        );
+        let code = match report.reason {
+            RejectionReason::RateExceeded => ErrorCode::StreamRateLimited,
+            RejectionReason::MetadataTooLarge => ErrorCode::StreamTooLarge,
+            _ => return,
+        };
+        let Some(established) = self.session.established() else {
+            return;
+        };
+        let mut error = ErrorEnvelope::new(
+            Some(established.session_id),
+            code,
+            Some(format!(
+                "{} frame(s) on universe {} refused: {}",
+                report.count,
+                report.universe,
+                report.reason.as_str()
+            )),
+        );
+        error.retry_after_ms = retry_after.map(|wait| wait.as_millis().max(1) as u64);
+        let _ = self.notices.send(HandshakeMessage::Error(error));
+    }
+
+    /// Checks further frames against the node's new capabilities and tells the controller.
+    fn push_capabilities(&mut self) {
+        let notice = self.capabilities.borrow_and_update().clone();
+        self.validator
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .set_capabilities(&notice.capabilities);
+        let seq = self.capability_seq;
+        self.capability_seq += 1;
+        if let Ok(update) =
+            self.control
+                .responder()
+                .capability_update(seq, notice.capabilities, notice.reason)
+        {
+            let _ = self
+                .notices
+                .send(HandshakeMessage::CapabilityUpdate(update));
+        }
+    }
+
+    /// Sends an observer the looks applied since it was last sent one.
+    fn push_mirror(&self, now: Instant) {
+        let Some(established) = self.session.established() else {
+            return;
+        };
+        for slice in self.mirror.take_due(established.session_id, now) {
+            match self.control.responder().mirrored_output(&slice) {
+                Ok(output) => {
+                    let _ = self.notices.send(HandshakeMessage::MirroredOutput(output));
+                }
+                Err(_) => {
+                    self.mirror.unsubscribe(established.session_id);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Records the stream counters a controller piggybacked on `keepalive` and answers
+    /// with the node's own; controllers that send none get no answer.
+    fn answer_keepalive(&self, keepalive: Keepalive) {
+        let Some(stats) = keepalive.stats else {
+            return;
+        };
+        self.session.record_peer_stats(stats);
+        let _ = self.notices.send(HandshakeMessage::Keepalive(Keepalive {
+            stats: Some(self.session.keepalive_stats()),
+            ..keepalive
+        }));
+    }
+
+    /// Warns the controller when `takeover` preempts this session.
+    fn announce_preemption(&mut self, takeover: Takeover) {
+        if self
+            .session
+            .established()
+            .map(|established| established.session_id)
+            != Some(takeover.preempted)
+        {
+            return;
+        }
+        let seq = self.capability_seq;
+        self.capability_seq += 1;
+        if let Ok(notice) =
+            self.control
+                .responder()
+                .preemption_notice(seq, takeover.priority, takeover.grace)
+        {
+            let _ = self
+                .notices
+                .send(HandshakeMessage::SessionPreempted(notice));
+        }
+    }
+
+    /// Closes the session when its controller says it is done; `false` for a close that
+    /// does not verify.
+    fn accept_close(&self, close: &SessionClose) -> bool {
+        if self.control.responder().verify_close(close).is_err() {
+            return false;
+        }
+        self.sessions.close(close.session_id);
+        self.session.close();
+        self.log(
+            LogSeverity::Info,
+            "session",
+            format!(
+                "session {} closed by {}: {}",
+                close.session_id,
+                self.controller,
+                close.reason.as_deref().unwrap_or("done")
+            ),
+        );
+        true
+    }
+
+    /// Republishes the current levels of universes whose channels were still ramping.
+    async fn publish_ramps(&mut self) -> bool {
+        let now = Instant::now();
+        for universe in std::mem::take(&mut self.ramping) {
+            let (Some(last), Some(channels)) = (
+                self.last_frames.get(&universe),
+                self.smoother.sample(universe, now),
+            ) else {
+                continue;
+            };
+            let mut frame = last.clone();
+            frame.channels = channels;
+            let look = self.publish(frame, now);
+            if self.looks.send(look).await.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Runs merge or arbitration, scene capture, and output on a smoothed look.
+    fn publish(&self, mut frame: FrameEnvelope, now: Instant) -> FrameEnvelope {
+        if let Some(arbiter) = &self.arbiter {
+            frame.channels = arbiter.arbitrate(&frame, now);
+        } else if let Some(merge) = &self.merge {
+            frame.channels = merge.submit(
+                frame.session_id,
+                frame.universe,
+                frame.priority,
+                &frame.channels,
+                now,
+            );
+        }
+        if let Some(scenes) = &self.scenes {
+            scenes.observe(frame.universe, &frame.channels);
+        }
+        let testing = self
+            .patterns
+            .as_ref()
+            .is_some_and(|patterns| patterns.is_active(frame.universe, now));
+        if !testing {
+            self.mirror
+                .record(frame.universe, &frame.channels, self.clock.now_us());
+            for output in &self.outputs {
+                // A failed port must not stall the stream or the other outputs.
+                let _ = output.output(&frame);
+            }
+            if let Some((ports, port)) = self
+                .ports
+                .as_ref()
+                .and_then(|ports| Some((ports, ports.port_of(frame.universe)?)))
+            {
+                for (_, output) in self.port_outputs.iter().filter(|(p, _)| *p == port) {
+                    ports.record_output(port, output.output(&frame), now);
+                }
+            }
+        }
+        frame
+    }
+
+    /// Binds the profile a `start_stream` or `restart_stream` envelope announced and
+    /// forgets per-universe state built on any earlier one.
+    fn adopt_profile(&mut self, env: &ControlEnvelope) {
+        let Some(profile) = env
+            .payload_as::<ProfileAnnouncement>()
+            .ok()
+            .and_then(|offer| offer.compile().ok())
+        else {
+            return;
+        };
+        self.config_tag = Some(profile.config_tag());
+        let wire = profile.wire_behavior();
+        self.session.set_jitter_strategy(wire.jitter_strategy);
+        self.jitter =
+            (!wire.jitter_buffer.is_zero()).then(|| JitterBuffer::new(wire.jitter_buffer));
+        self.fec = wire.fec_group.map(|_| FecDecoder::new());
+        // The node never streams itself, so its session profile stays unlocked unless a
+        // resumed or migrated session already carried one.
+        let _ = self
+            .session
+            .set_stream_profile(profile.clone())
+            .or_else(|_| self.session.restart_stream_profile(profile));
+        self.last_frames.clear();
+        self.smoother.clear();
+    }
+
+    /// Takes a verified heartbeat as proof the controller is alive, holding back the
+    /// hold-timeout fade of universes it is keeping static.
+    fn accept_heartbeat(&mut self, heartbeat: &StreamHeartbeat, now: Instant) {
+        let (Some(established), Some(keys)) = (self.session.established(), self.session.keys())
+        else {
+            return;
+        };
+        if heartbeat.session_id != established.session_id
+            || self.heartbeat_seq.is_some_and(|seq| heartbeat.seq <= seq)
+            || !heartbeat.verify(&keys)
+        {
+            return;
+        }
+        self.heartbeat_seq = Some(heartbeat.seq);
+        self.session.record_peer_activity();
+        self.smoother.keep_alive(now);
+        self.mark_stream_activity(now);
+    }
+
+    fn mark_stream_activity(&self, now: Instant) {
+        *self
+            .stream_activity
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(now);
+    }
+
+    /// Answers an echo probe straight away so the controller's RTT excludes frame work.
+    async fn reflect_echo(&self, probe: &EchoFrame, read_us: u64, from: SocketAddr) {
+        let (Some(established), Some(keys)) = (self.session.established(), self.session.keys())
+        else {
+            return;
+        };
+        if probe.session_id != established.session_id || !probe.verify(&keys) {
+            return;
+        }
+        let Ok(reply) = probe.reflect(&keys, read_us) else {
+            return;
+        };
+        if let Ok(bytes) = codec::to_vec(&reply) {
+            let _ = self.socket.send_to(&bytes, from).await;
+        }
+    }
+
+    fn log(&self, severity: LogSeverity, target: &str, message: String) {
+        if let Some(log) = &self.log {
+            log.record(severity, target, message);
+        }
+    }
+
+    async fn handle_control(&mut self, env: ControlEnvelope) {
+        let Some(msg) = self.control.dispatch(&env).await else {
+            return;
+        };
+        if !matches!(&msg, HandshakeMessage::Error(error) if error.code == ErrorCode::SessionMacMismatch)
+        {
+            self.session.record_peer_activity();
+        }
+        if matches!(&msg, HandshakeMessage::Error(error) if error.code == ErrorCode::ControlRateLimited)
+        {
+            self.session.record_throttled_control();
+        }
+        match &msg {
+            HandshakeMessage::Ack(ack) if !ack.ok => self.log(
+                LogSeverity::Warn,
+                "control",
+                format!(
+                    "{:?} from {} refused: {}",
+                    env.op,
+                    self.controller,
+                    ack.detail.as_deref().unwrap_or_default()
+                ),
+            ),
+            HandshakeMessage::Error(error) => self.log(
+                LogSeverity::Warn,
+                "control",
+                format!(
+                    "{:?} from {} failed with {:?}",
+                    env.op, self.controller, error.code
+                ),
+            ),
+            _ => {}
+        }
+        if let HandshakeMessage::Ack(ack) = &msg {
+            if ack.ok && matches!(env.op, ControlOp::Restart | ControlOp::FactoryReset) {
+                self.log(
+                    LogSeverity::Warn,
+                    "maintenance",
+                    format!(
+                        "{:?} from {}: {}",
+                        env.op,
+                        self.controller,
+                        ack.detail.as_deref().unwrap_or_default()
+                    ),
+                );
+            }
+            if ack.ok && matches!(env.op, ControlOp::StartStream | ControlOp::RestartStream) {
+                self.adopt_profile(&env);
+                self.log(
+                    LogSeverity::Info,
+                    "stream",
+                    format!(
+                        "{} bound stream profile {}",
+                        self.controller,
+                        ack.detail.as_deref().unwrap_or_default()
+                    ),
+                );
+            }
+            let paused = match env.op {
+                ControlOp::PauseStream => Some(true),
+                ControlOp::ResumeStream => Some(false),
+                _ => None,
+            };
+            if let Some(paused) = paused.filter(|_| ack.ok) {
+                let change = if paused { "paused" } else { "resumed" };
+                self.log(
+                    LogSeverity::Info,
+                    "stream",
+                    format!("{} {} its stream", self.controller, change),
+                );
+                self.session.set_streaming_enabled(!paused);
+                if let Some(fallback) = &self.fallback {
+                    fallback.set_paused(paused, Instant::now());
+                }
+            }
+        }
+        if let Ok(bytes) = codec::to_vec(&msg) {
+            let _ = self.socket.send_to(&bytes, self.controller).await;
+        }
+    }
+}
+
+/// Sends progress from long-running control handlers to the controller; ends with the
+/// session's dispatcher.
+async fn forward_progress(
+    socket: Arc<UdpSocket>,
+    controller: SocketAddr,
+    mut updates: mpsc::UnboundedReceiver<HandshakeMessage>,
+) {
+    while let Some(update) = updates.recv().await {
+        if let Ok(bytes) = codec::to_vec(&update) {
+            let _ = socket.send_to(&bytes, controller).await;
+        }
+    }
+}
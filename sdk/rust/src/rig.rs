@@ -0,0 +1,159 @@
+//! Pre-show rig checks.
+//!
+//! A [`RigChecker`] takes the expected devices of a rig as an
+//! [`Inventory`](alpine::inventory::Inventory), discovers what answers, opens a session
+//! with each expected device that did to read its ports and health, and diffs the
+//! result: devices missing or unexpected, and expected devices with other firmware,
+//! model, address, or patch.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use alpine::crypto::identity::NodeCredentials;
+use alpine::inventory::{DeviceRecord, Inventory, PatchEntry, RigCheck};
+use alpine::messages::{CapabilitySet, DeviceIdentity};
+use ed25519_dalek::VerifyingKey;
+use tokio::task::JoinSet;
+
+use crate::client::AlpineClient;
+use crate::discovery::DiscoveryClient;
+use crate::error::AlpineSdkError;
+
+/// Outcome of [`RigChecker::run`].
+#[derive(Debug, Clone, Default)]
+pub struct RigReport {
+    /// Expected devices against what was found.
+    pub check: RigCheck,
+    /// Everything found, for saving as the next expected inventory.
+    pub found: Inventory,
+    /// Device ids of replies without a registered key or with a bad signature; they are
+    /// left out of `found`.
+    pub unverified: Vec<String>,
+    /// Expected devices that answered discovery but refused or failed a session, and
+    /// why; their patch and health are unknown.
+    pub unreachable: Vec<(String, String)>,
+    /// Datagrams discovery set aside as oversized or malformed; see
+    /// [`DiscoverySweep::quarantined`](crate::DiscoverySweep::quarantined).
+    pub quarantined: usize,
+}
+
+impl RigReport {
+    pub fn is_clean(&self) -> bool {
+        self.check.is_clean() && self.unverified.is_empty() && self.unreachable.is_empty()
+    }
+}
+
+/// Checks a rig against its expected inventory.
+pub struct RigChecker {
+    identity: DeviceIdentity,
+    credentials: NodeCredentials,
+    keys: HashMap<String, VerifyingKey>,
+    sessions: bool,
+}
+
+impl RigChecker {
+    /// Verifies discovery replies against `keys`, indexed by device id, and opens
+    /// sessions as `identity`.
+    pub fn new(
+        identity: DeviceIdentity,
+        credentials: NodeCredentials,
+        keys: HashMap<String, VerifyingKey>,
+    ) -> Self {
+        Self {
+            identity,
+            credentials,
+            keys,
+            sessions: true,
+        }
+    }
+
+    /// Relies on discovery replies alone: faster, but patch and health are not read, so
+    /// expected patches cannot match.
+    pub fn discovery_only(mut self) -> Self {
+        self.sessions = false;
+        self
+    }
+
+    /// Discovers the rig with `discovery` and diffs it against `expected`.
+    pub async fn run(
+        &self,
+        expected: &Inventory,
+        discovery: DiscoveryClient,
+    ) -> Result<RigReport, AlpineSdkError> {
+        let keys = self.keys.clone();
+        let sweep = tokio::task::spawn_blocking(move || discovery.discover_all(&[], &keys))
+            .await
+            .map_err(|err| AlpineSdkError::Internal(err.to_string()))??;
+
+        let mut report = RigReport {
+            found: sweep.inventory(),
+            unverified: sweep
+                .rejected
+                .iter()
+                .map(|outcome| outcome.reply.device_id.clone())
+                .collect(),
+            quarantined: sweep.quarantined_count,
+            ..RigReport::default()
+        };
+        if self.sessions {
+            let mut sessions = JoinSet::new();
+            for outcome in &sweep.verified {
+                let device_id = outcome.reply.device_id.clone();
+                if expected.get(&device_id).is_none() {
+                    continue;
+                }
+                let (peer, identity, credentials) = (
+                    outcome.peer,
+                    self.identity.clone(),
+                    self.credentials.clone(),
+                );
+                sessions.spawn(async move {
+                    let record = inspect(peer, identity, credentials).await;
+                    (device_id, record)
+                });
+            }
+            while let Some(joined) = sessions.join_next().await {
+                match joined {
+                    Ok((_, Ok(record))) => report.found.observe(record),
+                    Ok((device_id, Err(err))) => {
+                        report.unreachable.push((device_id, err.to_string()))
+                    }
+                    Err(err) => return Err(AlpineSdkError::Internal(err.to_string())),
+                }
+            }
+            report.unreachable.sort();
+        }
+        report.check = expected.check(&report.found);
+        Ok(report)
+    }
+}
+
+/// Opens a session with the node at `peer` and records its capabilities, health, and
+/// patch.
+async fn inspect(
+    peer: SocketAddr,
+    identity: DeviceIdentity,
+    credentials: NodeCredentials,
+) -> Result<DeviceRecord, AlpineSdkError> {
+    let client = AlpineClient::builder(peer, identity, CapabilitySet::default(), credentials)
+        .connect()
+        .await?;
+    let ports = client.port_status().await;
+    let record = client.inventory_record();
+    client.close().await;
+    let mut record = record
+        .ok_or_else(|| AlpineSdkError::Internal(format!("session with {peer} not established")))?;
+    // A node refusing get_status still passed the handshake; it just has no patch to
+    // report.
+    record.patch = ports
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|status| {
+            status.universe.map(|universe| PatchEntry {
+                universe,
+                port: Some(status.port),
+            })
+        })
+        .collect();
+    Ok(record)
+}
@@ -0,0 +1,269 @@
+//! Rate-controlled sending from latest-value universe buffers.
+//!
+//! Consoles change levels whenever a fader moves or an effect steps, which rarely lines up
+//! with the frame rate the node expects. A [`FrameScheduler`] decouples the two: callers
+//! write into [`Universe`] buffers at any rate, and the scheduler sends a snapshot of each
+//! one on every tick of the stream profile's frame interval, so writes between ticks merge
+//! into the next frame instead of queueing up. Universes written since the last tick go
+//! first, so on a congested link the static ones are the ones that yield.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use alpine::messages::{ChannelFormat, UniverseId};
+use alpine::stream::StreamError;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::client::AlpineClient;
+use crate::error::AlpineSdkError;
+
+/// Latest levels for one universe, shared between the caller and a [`FrameScheduler`].
+///
+/// Clones share the same buffer.
+#[derive(Debug, Clone)]
+pub struct Universe {
+    id: UniverseId,
+    levels: Arc<Mutex<Vec<u16>>>,
+    /// Counts edits, so the scheduler can tell changed universes from static ones.
+    edits: Arc<AtomicU64>,
+}
+
+impl Universe {
+    /// A universe of `channels` channels, all at zero.
+    pub fn new(id: UniverseId, channels: usize) -> Self {
+        Self {
+            id,
+            levels: Arc::new(Mutex::new(vec![0; channels])),
+            edits: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn id(&self) -> UniverseId {
+        self.id
+    }
+
+    /// Sets one channel; channels past the end of the universe are ignored.
+    pub fn set(&self, channel: usize, level: u16) {
+        self.update(|levels| {
+            if let Some(slot) = levels.get_mut(channel) {
+                *slot = level;
+            }
+        });
+    }
+
+    /// Sets consecutive channels starting at `start`, truncated to the universe.
+    pub fn set_range(&self, start: usize, values: &[u16]) {
+        self.update(|levels| {
+            let start = start.min(levels.len());
+            let end = (start + values.len()).min(levels.len());
+            levels[start..end].copy_from_slice(&values[..end - start]);
+        });
+    }
+
+    /// Edits the levels in place; the next frame sees every change made by `edit` at once.
+    pub fn update<F>(&self, edit: F)
+    where
+        F: FnOnce(&mut [u16]),
+    {
+        edit(&mut self.lock());
+        self.edits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A copy of the current levels.
+    pub fn snapshot(&self) -> Vec<u16> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<u16>> {
+        self.levels
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Sends every [`Universe`] at the running stream profile's frame rate, slowed to the
+/// node's minimum frame interval when it declares one.
+///
+/// The task stops when the scheduler is dropped. Frames are dropped while the stream is
+/// paused; any other send failure ends the task and is returned by
+/// [`FrameScheduler::stop`].
+#[derive(Debug)]
+pub struct FrameScheduler {
+    client: Arc<AlpineClient>,
+    universes: Vec<Universe>,
+    channel_format: ChannelFormat,
+    priority: u8,
+    frames_sent: Arc<AtomicU64>,
+    /// Ticks begun so far; a tick snapshots every universe after it begins.
+    ticks_started: Arc<AtomicU64>,
+    /// Last tick finished, and whether it sent every universe rather than pausing.
+    ticks_done: watch::Receiver<(u64, bool)>,
+    interval: Duration,
+    task: JoinHandle<Result<(), AlpineSdkError>>,
+}
+
+impl FrameScheduler {
+    /// Starts sending `universes` over `client`'s stream, which must already be started.
+    pub fn start(
+        client: Arc<AlpineClient>,
+        universes: Vec<Universe>,
+        channel_format: ChannelFormat,
+        priority: u8,
+    ) -> Result<Self, AlpineSdkError> {
+        let interval = frame_interval(&client)?;
+        let frames_sent = Arc::new(AtomicU64::new(0));
+        let counter = frames_sent.clone();
+        let ticks_started = Arc::new(AtomicU64::new(0));
+        let started = ticks_started.clone();
+        let (done, ticks_done) = watch::channel((0, true));
+        let sender = client.clone();
+        let scheduled = universes.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // A late tick sends the latest levels once rather than a burst of catch-up
+            // frames.
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut seen = vec![None; scheduled.len()];
+            loop {
+                ticker.tick().await;
+                let tick = started.fetch_add(1, Ordering::AcqRel) + 1;
+                let (frames, sent) =
+                    send_tick(&sender, &scheduled, &mut seen, channel_format, priority)?;
+                counter.fetch_add(frames, Ordering::Relaxed);
+                done.send_replace((tick, sent));
+            }
+        });
+        Ok(Self {
+            client,
+            universes,
+            channel_format,
+            priority,
+            frames_sent,
+            ticks_started,
+            ticks_done,
+            interval,
+            task,
+        })
+    }
+
+    /// The interval between frames, from the stream profile and the node's limits.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Frames sent so far, across all universes.
+    pub fn frames_sent(&self) -> u64 {
+        self.frames_sent.load(Ordering::Relaxed)
+    }
+
+    /// Zeroes every universe and sends them at once on the express lane, ahead of the
+    /// next tick and without fading; ticks after it keep the universes dark until they
+    /// are written again.
+    pub fn blackout(&self) -> Result<(), AlpineSdkError> {
+        for universe in &self.universes {
+            universe.update(|levels| levels.fill(0));
+            self.client.send_express_frame(
+                universe.id(),
+                self.channel_format,
+                &universe.snapshot(),
+                self.priority,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Waits until every universe has gone out to the socket with the levels written
+    /// before the call, e.g. before firing a cue-synchronized effect.
+    ///
+    /// Resolves once the next full tick is sent. Fails with
+    /// [`StreamError::StreamingDisabled`] if that tick found the stream paused, and with
+    /// [`AlpineSdkError::SchedulerStopped`] once sending has ended, in which case
+    /// [`FrameScheduler::stop`] returns why.
+    pub async fn flush(&self) -> Result<(), AlpineSdkError> {
+        let target = self.ticks_started.load(Ordering::Acquire) + 1;
+        let mut ticks_done = self.ticks_done.clone();
+        let done = ticks_done
+            .wait_for(|(tick, _)| *tick >= target)
+            .await
+            .map_err(|_| AlpineSdkError::SchedulerStopped)?;
+        match *done {
+            (_, true) => Ok(()),
+            (_, false) => Err(StreamError::StreamingDisabled.into()),
+        }
+    }
+
+    /// Stops sending; returns the error that ended the task early, if any.
+    pub async fn stop(mut self) -> Result<(), AlpineSdkError> {
+        self.task.abort();
+        match (&mut self.task).await {
+            Ok(result) => result,
+            Err(err) if err.is_cancelled() => Ok(()),
+            Err(err) => Err(AlpineSdkError::Internal(err.to_string())),
+        }
+    }
+}
+
+/// Interval between frames for `client`'s running stream: the profile's frame interval,
+/// slowed to the node's minimum frame interval when it declares one.
+pub(crate) fn frame_interval(client: &AlpineClient) -> Result<Duration, AlpineSdkError> {
+    let profile_interval = client
+        .stream_profile()
+        .ok_or(AlpineSdkError::StreamNotStarted)?
+        .wire_behavior()
+        .frame_interval;
+    Ok(client
+        .capabilities()
+        .and_then(|capabilities| capabilities.min_frame_interval())
+        .map_or(profile_interval, |min| profile_interval.max(min)))
+}
+
+/// Sends a snapshot of every universe once, changed ones first; `seen` holds the edit
+/// count of each universe at its last tick.
+///
+/// Returns the frames sent, and whether every universe went out rather than finding the
+/// stream paused.
+pub(crate) fn send_tick(
+    client: &AlpineClient,
+    universes: &[Universe],
+    seen: &mut [Option<u64>],
+    channel_format: ChannelFormat,
+    priority: u8,
+) -> Result<(u64, bool), AlpineSdkError> {
+    let mut frames = 0;
+    let mut sent = true;
+    // Changed universes claim the bandwidth budget before static ones.
+    let mut order: Vec<(bool, &Universe)> = universes
+        .iter()
+        .zip(seen)
+        .map(|(universe, seen)| {
+            let edits = universe.edits.load(Ordering::Relaxed);
+            (seen.replace(edits) == Some(edits), universe)
+        })
+        .collect();
+    order.sort_by_key(|&(unchanged, _)| unchanged);
+    for (_, universe) in order {
+        // Sent straight from the shared levels so a tick makes no copy; writers wait out
+        // the send.
+        match client.send_universe_frame(
+            universe.id(),
+            channel_format,
+            &universe.lock(),
+            priority,
+            None,
+        ) {
+            Ok(()) => frames += 1,
+            Err(AlpineSdkError::Stream(StreamError::StreamingDisabled)) => sent = false,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok((frames, sent))
+}
+
+impl Drop for FrameScheduler {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,169 @@
+//! In-memory stand-ins for tests and visualizers.
+//!
+//! A [`VisualizerSink`] takes the place of a node's output hardware, or sits behind an
+//! observer's mirrored output, and keeps the resulting look of every universe in memory.
+//! GUI visualizers redraw from its change notifications; tests assert on the look itself
+//! instead of decoding packets.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use alpine::messages::{FrameEnvelope, UniverseId};
+use alpine::mirror::MirrorSlice;
+use alpine::output::{OutputDriver, OutputError};
+use tokio::sync::broadcast;
+
+/// Changes a lagging subscriber may fall behind by before it misses some.
+const CHANGE_CAPACITY: usize = 256;
+
+/// Channels of one universe whose levels changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookChange {
+    pub universe: UniverseId,
+    /// Index and new level of each changed channel, in channel order. A universe seen
+    /// for the first time lists every channel it arrived with.
+    pub channels: Vec<(usize, u16)>,
+}
+
+/// Model of every universe's look, fed by a node's output path or an observer's mirror.
+///
+/// Levels are kept as the node applied them: 0-255 for 8-bit streams, 0-65535 for 16-bit
+/// ones.
+#[derive(Debug)]
+pub struct VisualizerSink {
+    universes: Mutex<BTreeMap<UniverseId, Vec<u16>>>,
+    changes: broadcast::Sender<LookChange>,
+}
+
+impl Default for VisualizerSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VisualizerSink {
+    pub fn new() -> Self {
+        Self {
+            universes: Mutex::default(),
+            changes: broadcast::channel(CHANGE_CAPACITY).0,
+        }
+    }
+
+    /// Notifies each change to the look, in the order they were applied.
+    pub fn subscribe(&self) -> broadcast::Receiver<LookChange> {
+        self.changes.subscribe()
+    }
+
+    /// Replaces the look of `universe`; channels past the end of `levels` are dropped and
+    /// reported as falling to 0. Returns what changed, if anything.
+    pub fn apply_look(&self, universe: UniverseId, levels: &[u16]) -> Option<LookChange> {
+        self.write(universe, 0, levels, true)
+    }
+
+    /// Sets consecutive channels of `universe` from `start`, leaving the others as they
+    /// are. Returns what changed, if anything.
+    pub fn apply_levels(
+        &self,
+        universe: UniverseId,
+        start: usize,
+        levels: &[u16],
+    ) -> Option<LookChange> {
+        self.write(universe, start, levels, false)
+    }
+
+    /// Applies output a node mirrored to an observer session, as read from
+    /// [`crate::AlpineClient::mirrored_output`].
+    pub fn apply_mirror(&self, slice: &MirrorSlice) -> Option<LookChange> {
+        self.apply_levels(slice.universe, usize::from(slice.start), &slice.levels)
+    }
+
+    /// Applies mirrored output from `mirror` until its client is gone; slices missed by
+    /// lagging behind are skipped and caught up by later looks.
+    pub async fn follow(&self, mut mirror: broadcast::Receiver<MirrorSlice>) {
+        loop {
+            match mirror.recv().await {
+                Ok(slice) => {
+                    self.apply_mirror(&slice);
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Current levels of `universe`, if it has been seen.
+    pub fn look(&self, universe: UniverseId) -> Option<Vec<u16>> {
+        self.lock().get(&universe).cloned()
+    }
+
+    /// Level of one channel; `None` for a universe not seen or a channel past its end.
+    pub fn level(&self, universe: UniverseId, channel: usize) -> Option<u16> {
+        self.lock()
+            .get(&universe)
+            .and_then(|levels| levels.get(channel).copied())
+    }
+
+    /// Universes seen so far, in ascending order.
+    pub fn universes(&self) -> Vec<UniverseId> {
+        self.lock().keys().copied().collect()
+    }
+
+    /// Forgets every universe, e.g. between test cases; subscribers are not notified.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn write(
+        &self,
+        universe: UniverseId,
+        start: usize,
+        levels: &[u16],
+        whole: bool,
+    ) -> Option<LookChange> {
+        let mut universes = self.lock();
+        let seen = universes.contains_key(&universe);
+        let look = universes.entry(universe).or_default();
+        let end = start + levels.len();
+        if look.len() < end {
+            look.resize(end, 0);
+        }
+        let mut channels = Vec::new();
+        for (index, &level) in levels.iter().enumerate() {
+            let channel = start + index;
+            if !seen || look[channel] != level {
+                look[channel] = level;
+                channels.push((channel, level));
+            }
+        }
+        if whole && look.len() > end {
+            channels.extend(
+                look[end..]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, level)| **level != 0)
+                    .map(|(index, _)| (end + index, 0)),
+            );
+            look.truncate(end);
+        }
+        if seen && channels.is_empty() {
+            return None;
+        }
+        let change = LookChange { universe, channels };
+        // Sent under the lock, so subscribers see changes in the order they were applied.
+        let _ = self.changes.send(change.clone());
+        Some(change)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<UniverseId, Vec<u16>>> {
+        self.universes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl OutputDriver for VisualizerSink {
+    fn output(&self, look: &FrameEnvelope) -> Result<(), OutputError> {
+        self.apply_look(look.universe, &look.channels);
+        Ok(())
+    }
+}
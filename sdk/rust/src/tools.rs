@@ -0,0 +1,223 @@
+//! Commissioning tools.
+//!
+//! The building blocks of the `alpine-tools` binary, for scripting commissioning from
+//! Rust: generate credentials, discover devices, probe a handshake, send a test pattern,
+//! and decode a captured packet.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use alpine::codec::{self, CodecError};
+use alpine::crypto::identity::{self, NodeCredentials};
+use alpine::messages::{
+    CapabilitySet, ChannelFormat, DeviceIdentity, DiscoveryReply, DiscoveryRequest, MessageType,
+};
+use alpine::source::DEFAULT_SOURCE_PRIORITY;
+use rand::{rngs::OsRng, RngCore};
+use serde_json::Value;
+
+use crate::client::AlpineClient;
+use crate::discovery::{DiscoveryError, DiscoveryOutcome};
+use crate::error::AlpineSdkError;
+
+/// File name of the private key written by [`generate_credentials`].
+pub const SIGNING_PEM: &str = "signing.pem";
+/// File name of the public key written by [`generate_credentials`].
+pub const VERIFYING_PEM: &str = "verifying.pem";
+
+/// Generates credentials and writes them to `dir` as [`SIGNING_PEM`] and
+/// [`VERIFYING_PEM`], in the format `NodeCredentials::load_signing_pem` reads.
+///
+/// Refuses to replace an existing private key.
+pub fn generate_credentials(dir: impl AsRef<Path>) -> Result<NodeCredentials, AlpineSdkError> {
+    let dir = dir.as_ref();
+    let credentials = identity::generate();
+    let (signing, verifying) = credentials.to_pem()?;
+    fs::create_dir_all(dir)?;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dir.join(SIGNING_PEM))?
+        .write_all(signing.as_bytes())?;
+    fs::write(dir.join(VERIFYING_PEM), verifying)?;
+    Ok(credentials)
+}
+
+/// Loads credentials written by [`generate_credentials`] from `dir`.
+pub fn load_credentials(dir: impl AsRef<Path>) -> Result<NodeCredentials, AlpineSdkError> {
+    let path = |name: &str| dir.as_ref().join(name).to_string_lossy().into_owned();
+    let signing = NodeCredentials::load_signing_pem(&path(SIGNING_PEM))?;
+    let verifying = NodeCredentials::load_verifying_pem(&path(VERIFYING_PEM))?;
+    Ok(NodeCredentials { signing, verifying })
+}
+
+/// Sends a discovery request to `target`, which may be a broadcast address, and collects
+/// every reply that arrives within `timeout`, one per device.
+pub fn discover_devices(
+    target: SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<DiscoveryOutcome>, DiscoveryError> {
+    let local: SocketAddr = if target.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    if target.is_ipv4() {
+        socket.set_broadcast(true)?;
+    }
+    let mut nonce = vec![0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    socket.send_to(
+        &codec::to_vec(&DiscoveryRequest::new(Vec::new(), nonce))?,
+        target,
+    )?;
+
+    let deadline = Instant::now() + timeout;
+    let mut seen = HashSet::new();
+    let mut outcomes = Vec::new();
+    let mut buf = vec![0u8; 2048];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(outcomes);
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(outcomes)
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        // Other traffic on the port is not a reason to stop listening.
+        let Ok(reply) = codec::decode_untrusted::<DiscoveryReply>(&buf[..len]) else {
+            continue;
+        };
+        if seen.insert(reply.device_id.clone()) {
+            outcomes.push(DiscoveryOutcome {
+                reply,
+                peer,
+                interface: None,
+            });
+        }
+    }
+}
+
+/// Result of [`handshake_probe`].
+#[derive(Debug, Clone)]
+pub struct ProbeReport {
+    pub remote_addr: SocketAddr,
+    /// Time from the first handshake message to a ready session.
+    pub handshake: Option<Duration>,
+    /// Round trip of a `get_status` control op over the new session.
+    pub control_rtt: Duration,
+    /// The device's status summary from the `get_status` ack.
+    pub status: Option<String>,
+}
+
+/// Opens a session with `remote_addr` as `identity`, times it and one control round
+/// trip, and closes it again.
+pub async fn handshake_probe(
+    remote_addr: SocketAddr,
+    identity: DeviceIdentity,
+    credentials: NodeCredentials,
+) -> Result<ProbeReport, AlpineSdkError> {
+    let client =
+        AlpineClient::builder(remote_addr, identity, CapabilitySet::default(), credentials)
+            .connect()
+            .await?;
+    let started = Instant::now();
+    let status = client.get_status().await;
+    let control_rtt = started.elapsed();
+    let handshake = client.metrics().handshake_duration;
+    client.close().await;
+    Ok(ProbeReport {
+        remote_addr,
+        handshake,
+        control_rtt,
+        status: status?.detail,
+    })
+}
+
+/// Look generator for checking patching and fixtures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPattern {
+    /// Every channel at one level.
+    Level(u8),
+    /// One channel at full, moving up a channel each frame.
+    Chase,
+    /// Levels rising across the channels, shifting each frame.
+    Ramp,
+}
+
+impl TestPattern {
+    /// 8-bit levels of frame `step` for `channels` channels.
+    pub fn frame(self, channels: usize, step: u64) -> Vec<u16> {
+        (0..channels)
+            .map(|channel| match self {
+                TestPattern::Level(level) => u16::from(level),
+                TestPattern::Chase => {
+                    if (channel as u64) == step % channels as u64 {
+                        255
+                    } else {
+                        0
+                    }
+                }
+                TestPattern::Ramp => ((channel as u64 + step) % 256) as u16,
+            })
+            .collect()
+    }
+}
+
+/// Sends `frames` frames of `pattern` over `client`'s stream, one every `interval`.
+///
+/// The stream must already be started.
+pub async fn send_test_pattern(
+    client: &AlpineClient,
+    pattern: TestPattern,
+    channels: usize,
+    frames: u64,
+    interval: Duration,
+) -> Result<(), AlpineSdkError> {
+    let mut ticker = tokio::time::interval(interval);
+    for step in 0..frames {
+        ticker.tick().await;
+        client.send_frame(
+            ChannelFormat::U8,
+            &pattern.frame(channels, step),
+            DEFAULT_SOURCE_PRIORITY,
+            None,
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// A captured packet in readable form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedPacket {
+    /// The message's `type` field, when it names a known message.
+    pub message_type: Option<MessageType>,
+    /// The whole message as JSON; byte strings are hex.
+    pub body: Value,
+}
+
+/// Decodes one captured ALPINE datagram, such as a UDP payload exported from a capture.
+pub fn decode_packet(bytes: &[u8]) -> Result<DecodedPacket, CodecError> {
+    let body = codec::to_diagnostic_json(bytes)?;
+    let message_type = body
+        .get("type")
+        .and_then(|kind| serde_json::from_value(kind.clone()).ok());
+    Ok(DecodedPacket { message_type, body })
+}
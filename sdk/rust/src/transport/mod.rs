@@ -1,5 +1,7 @@
-pub mod udp;
 pub mod quic;
+pub mod udp;
 
-pub use udp::UdpFrameTransport;
+pub use alpine::transport::UdpTransport;
 pub use quic::QuicFrameTransport;
+#[allow(deprecated)]
+pub use udp::UdpFrameTransport;
@@ -1,25 +1,50 @@
 use std::net::{SocketAddr, UdpSocket as StdUdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
 
 use alpine::stream::FrameTransport;
+use alpine::transport::TransportConfig;
 
 /// UDP-based transport used by the SDK streaming client.
-#[derive(Debug)]
+///
+/// Clones share the socket, so a stream's heartbeat task sends from the same port. Kept
+/// for one release; [`UdpTransport`](alpine::transport::UdpTransport) replaces it and
+/// also carries the handshake and control plane.
+#[deprecated(note = "use `alpine::transport::UdpTransport`")]
+#[derive(Debug, Clone)]
 pub struct UdpFrameTransport {
-    socket: StdUdpSocket,
+    socket: Arc<StdUdpSocket>,
     _peer: SocketAddr,
 }
 
+#[allow(deprecated)]
 impl UdpFrameTransport {
     pub fn new(local: SocketAddr, peer: SocketAddr) -> Result<Self, std::io::Error> {
-        let socket = StdUdpSocket::bind(local)?;
+        Self::with_config(local, peer, &TransportConfig::default())
+    }
+
+    /// Binds with interface, TTL, DSCP, and buffer options from `config`.
+    pub fn with_config(
+        local: SocketAddr,
+        peer: SocketAddr,
+        config: &TransportConfig,
+    ) -> Result<Self, std::io::Error> {
+        let socket: StdUdpSocket = config.bind_udp(local)?;
         socket.connect(peer)?;
         Ok(Self {
-            socket,
+            socket: Arc::new(socket),
             _peer: peer,
         })
     }
+
+    /// Waits up to `timeout` for a datagram from the peer, such as an echo reply.
+    pub fn recv_timeout(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, std::io::Error> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        self.socket.recv(buf)
+    }
 }
 
+#[allow(deprecated)]
 impl FrameTransport for UdpFrameTransport {
     fn send_frame(&self, bytes: &[u8]) -> Result<(), String> {
         self.socket
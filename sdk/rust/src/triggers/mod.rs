@@ -0,0 +1,7 @@
+//! Local triggers that drive node-side engines without a controller.
+//!
+//! Theatres often fire lighting from another department's console; a trigger parses that
+//! console's protocol and acts on the node's own engines, such as [`msc`] recalling scenes
+//! from MIDI Show Control.
+
+pub mod msc;
@@ -0,0 +1,245 @@
+//! MIDI Show Control (MSC) trigger input.
+//!
+//! Sound and stage-management consoles fire cues with MSC, a MIDI system exclusive
+//! message `F0 7F <device> 02 <format> <command> <data> F7`. [`MscParser`] picks GO, STOP,
+//! and RESUME addressed to lighting out of a raw MIDI byte stream, and [`MscTrigger`]
+//! plays them on the node's [`SceneEngine`] as its cue stack: cue `n` recalls the scene in
+//! slot `n`.
+
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use alpine::scene::{SceneEngine, SceneError};
+
+use crate::error::AlpineSdkError;
+
+/// Device id every MSC receiver answers to.
+pub const MSC_ALL_CALL: u8 = 0x7f;
+
+const SYSEX_START: u8 = 0xf0;
+const SYSEX_END: u8 = 0xf7;
+const UNIVERSAL_REAL_TIME: u8 = 0x7f;
+const SUB_ID_MSC: u8 = 0x02;
+const FORMAT_LIGHTING: u8 = 0x01;
+const FORMAT_ALL_TYPES: u8 = 0x7f;
+const COMMAND_GO: u8 = 0x01;
+const COMMAND_STOP: u8 = 0x02;
+const COMMAND_RESUME: u8 = 0x03;
+/// Longest system exclusive body kept; MSC messages are far shorter.
+const MAX_SYSEX: usize = 128;
+
+/// Cue named by an MSC command: an ASCII number such as `12` or `12.5`, optionally in a
+/// cue list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub number: String,
+    pub list: Option<String>,
+}
+
+impl Cue {
+    /// Scene slot the cue recalls; `None` for point cues and numbers above `u16::MAX`.
+    pub fn slot(&self) -> Option<u16> {
+        self.number.parse().ok()
+    }
+}
+
+/// Lighting command received over MSC; `None` means no cue number was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MscCommand {
+    Go(Option<Cue>),
+    Stop(Option<Cue>),
+    Resume(Option<Cue>),
+}
+
+/// Extracts MSC commands from a MIDI byte stream.
+///
+/// Real-time bytes interleaved in a message are skipped; any other status byte abandons
+/// the message, as MIDI requires.
+#[derive(Debug)]
+pub struct MscParser {
+    device_id: u8,
+    sysex: Option<Vec<u8>>,
+}
+
+impl MscParser {
+    /// Parser for commands sent to `device_id` or to [`MSC_ALL_CALL`].
+    pub fn new(device_id: u8) -> Self {
+        Self {
+            device_id,
+            sysex: None,
+        }
+    }
+
+    /// Takes the next byte; returns a command when it completes one.
+    pub fn push(&mut self, byte: u8) -> Option<MscCommand> {
+        match byte {
+            0xf8..=0xff => None,
+            SYSEX_START => {
+                self.sysex = Some(Vec::new());
+                None
+            }
+            SYSEX_END => self.sysex.take().and_then(|body| self.decode(&body)),
+            0x80..=0xff => {
+                self.sysex = None;
+                None
+            }
+            _ => {
+                if self
+                    .sysex
+                    .as_ref()
+                    .is_some_and(|body| body.len() >= MAX_SYSEX)
+                {
+                    self.sysex = None;
+                }
+                if let Some(body) = self.sysex.as_mut() {
+                    body.push(byte);
+                }
+                None
+            }
+        }
+    }
+
+    /// Takes a run of bytes and returns the commands they complete.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<MscCommand> {
+        bytes.iter().filter_map(|&byte| self.push(byte)).collect()
+    }
+
+    fn decode(&self, body: &[u8]) -> Option<MscCommand> {
+        let [UNIVERSAL_REAL_TIME, device, SUB_ID_MSC, format, command, data @ ..] = body else {
+            return None;
+        };
+        if *device != self.device_id && *device != MSC_ALL_CALL {
+            return None;
+        }
+        if !matches!(*format, FORMAT_LIGHTING | FORMAT_ALL_TYPES) {
+            return None;
+        }
+        let cue = parse_cue(data);
+        match *command {
+            COMMAND_GO => Some(MscCommand::Go(cue)),
+            COMMAND_STOP => Some(MscCommand::Stop(cue)),
+            COMMAND_RESUME => Some(MscCommand::Resume(cue)),
+            _ => None,
+        }
+    }
+}
+
+/// Reads `Q_number 00 Q_list 00 Q_path`; fields are ASCII digits and dots.
+fn parse_cue(data: &[u8]) -> Option<Cue> {
+    let mut fields = data.split(|&byte| byte == 0).map(|field| {
+        field
+            .iter()
+            .all(|&byte| byte.is_ascii_digit() || byte == b'.')
+            .then(|| String::from_utf8_lossy(field).into_owned())
+            .filter(|field| !field.is_empty())
+    });
+    let number = fields.next().flatten()?;
+    Some(Cue {
+        number,
+        list: fields.next().flatten(),
+    })
+}
+
+/// Plays MSC commands on a [`SceneEngine`].
+///
+/// GO with a cue recalls that slot; GO alone advances to the next stored slot. STOP halts
+/// the running recall and RESUME continues it; with a cue number they only act when it
+/// names the current cue.
+#[derive(Debug)]
+pub struct MscTrigger {
+    parser: MscParser,
+    engine: Arc<SceneEngine>,
+    fade: Duration,
+    current: Option<u16>,
+}
+
+impl MscTrigger {
+    /// Trigger for MSC device `device_id`; cues snap unless [`MscTrigger::with_fade`] is set.
+    pub fn new(engine: Arc<SceneEngine>, device_id: u8) -> Self {
+        Self {
+            parser: MscParser::new(device_id),
+            engine,
+            fade: Duration::ZERO,
+            current: None,
+        }
+    }
+
+    /// Crossfades each GO over `fade`.
+    pub fn with_fade(mut self, fade: Duration) -> Self {
+        self.fade = fade;
+        self
+    }
+
+    /// Slot of the last cue that was fired.
+    pub fn current(&self) -> Option<u16> {
+        self.current
+    }
+
+    /// Plays one command on the engine.
+    pub fn handle(&mut self, command: &MscCommand) -> Result<(), SceneError> {
+        let now = Instant::now();
+        match command {
+            MscCommand::Go(Some(cue)) => {
+                if let Some(slot) = cue.slot() {
+                    self.go(slot)?;
+                }
+            }
+            MscCommand::Go(None) => {
+                let next = self
+                    .engine
+                    .list()?
+                    .into_iter()
+                    .map(|scene| scene.slot)
+                    .filter(|&slot| self.current.is_none_or(|current| slot > current))
+                    .min();
+                if let Some(slot) = next {
+                    self.go(slot)?;
+                }
+            }
+            MscCommand::Stop(cue) => {
+                if self.is_current(cue) {
+                    self.engine.halt(now);
+                }
+            }
+            MscCommand::Resume(cue) => {
+                if self.is_current(cue) {
+                    self.engine.resume(now);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads MIDI from `reader`, such as a raw MIDI device, until it ends.
+    ///
+    /// Blocks; run it on its own thread. Cues that name an empty slot are skipped.
+    pub fn run<R: Read>(&mut self, mut reader: R) -> Result<(), AlpineSdkError> {
+        let mut buffer = [0; 256];
+        loop {
+            let len = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            for command in self.parser.feed(&buffer[..len]) {
+                match self.handle(&command) {
+                    Ok(()) | Err(SceneError::EmptySlot(_)) => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+    }
+
+    fn go(&mut self, slot: u16) -> Result<(), SceneError> {
+        self.engine.recall(slot, self.fade)?;
+        self.current = Some(slot);
+        Ok(())
+    }
+
+    fn is_current(&self, cue: &Option<Cue>) -> bool {
+        cue.as_ref()
+            .is_none_or(|cue| cue.slot().is_some_and(|slot| self.current == Some(slot)))
+    }
+}